@@ -0,0 +1,18 @@
+// Makes the version of the shader compiler available to the shader cache, see ShaderDirectory::new.
+// shaderc-sys is the crate that builds (and links) the actual compiler library.
+fn main() {
+    println!("cargo:rerun-if-changed=Cargo.lock");
+    let lock_file = std::fs::read_to_string("Cargo.lock").unwrap_or_default();
+    let shaderc_version = lock_file
+        .split("[[package]]")
+        .filter(|package| package.lines().any(|line| line.trim() == "name = \"shaderc-sys\""))
+        .find_map(|package| {
+            package.lines().find_map(|line| {
+                line.trim()
+                    .strip_prefix("version = \"")
+                    .map(|version| version.trim_end_matches('"').to_owned())
+            })
+        })
+        .unwrap_or_else(|| "unknown".to_owned());
+    println!("cargo:rustc-env=SHADERC_VERSION={}", shaderc_version);
+}
@@ -1,14 +1,66 @@
 #include "global_bindings.glsl"
 #include "utilities.glsl"
 
+#define LIGHT_TYPE_DIRECTIONAL 0
+#define LIGHT_TYPE_POINT 1
+#define LIGHT_TYPE_SPOT 2
+#define MAX_NUM_LIGHTS 8
+
+struct Light {
+    vec3 Position;
+    uint Type;
+    vec3 Direction; // Direction the light travels in.
+    float SpotCosOuterAngle;
+    vec3 Radiance;
+    float SpotCosInnerAngle;
+};
+
 layout(set = 1, binding = 0) uniform BackgroundAndLighting {
+    // Main light, the only one with caustics.
     vec3 DirectionalLightDirection;
     vec3 DirectionalLightRadiance;
 
     // Radiance SH without the sun. Bands 0-2
     vec3 IndirectRadianceSH3[9];
+
+    // Lights of the scene in addition to the main light.
+    uint NumLights;
+    Light Lights[MAX_NUM_LIGHTS];
 };
 layout(set = 1, binding = 1) uniform textureCube CubemapRgbe;
+// Light arriving at the floor relative to unobstructed light (rgb), height of the highest fluid surface above (a). See Background in Rust.
+layout(set = 1, binding = 2) uniform texture2D CausticsMap;
+
+// The floor is a square around the origin at y=0, the caustics map covers all of it.
+const float FloorPlaneSize = 10.0;
+
+// Factor for direct light at a given position due to caustics. Positions are projected along the light direction onto the floor.
+float sampleCaustics(vec3 position) {
+    vec2 floorPosition = position.xz - DirectionalLightDirection.xz * (position.y / DirectionalLightDirection.y);
+    vec2 uv = floorPosition / (2.0 * FloorPlaneSize) + vec2(0.5);
+    if (any(lessThan(uv, vec2(0.0))) || any(greaterThan(uv, vec2(1.0))))
+        return 1.0;
+    vec4 caustics = textureLod(sampler2D(CausticsMap, SamplerTrilinearClamp), uv, 0);
+    // No fluid above.
+    if (caustics.a <= position.y)
+        return 1.0;
+    return caustics.r;
+}
+
+// Radiance arriving from a light at the given position, outputs normalized direction to the light.
+vec3 evaluateLight(Light light, vec3 position, out vec3 toLight) {
+    if (light.Type == LIGHT_TYPE_DIRECTIONAL) {
+        toLight = -light.Direction;
+        return light.Radiance;
+    }
+    vec3 lightOffset = light.Position - position;
+    float distanceSq = max(dot(lightOffset, lightOffset), 0.0001);
+    toLight = lightOffset * inversesqrt(distanceSq);
+    vec3 radiance = light.Radiance / distanceSq;
+    if (light.Type == LIGHT_TYPE_SPOT)
+        radiance *= smoothstep(light.SpotCosOuterAngle, light.SpotCosInnerAngle, dot(-toLight, light.Direction));
+    return radiance;
+}
 
 vec3 decodeRGBE(vec4 hdr) { return hdr.rgb * exp2((hdr.a * 255.0) - 128.0); }
 
@@ -35,8 +87,7 @@ vec3 sampleBackground(vec3 position, vec3 dir, out float depth) {
     float d = -(position.y / dir.y);
     if (d > 0.0) {
         vec3 planePos = position + dir * d;
-        const float planeSize = 10.0;
-        if (planePos.x < planeSize && planePos.x > -planeSize && planePos.z < planeSize && planePos.z > -planeSize) {
+        if (planePos.x < FloorPlaneSize && planePos.x > -FloorPlaneSize && planePos.z < FloorPlaneSize && planePos.z > -FloorPlaneSize) {
             vec3 albedo = vec3(0.8);
 
 #if FRAGMENT_SHADER
@@ -52,7 +103,7 @@ vec3 sampleBackground(vec3 position, vec3 dir, out float depth) {
             vec2 projected_zw = (Camera.ViewProjection * vec4(planePos, 1.0)).zw; // (trusting optimizer to pick the right thing ;-))
             depth = projected_zw.x / projected_zw.y;
 
-            return albedo * DirectionalLightRadiance * -DirectionalLightDirection.y;
+            return albedo * DirectionalLightRadiance * -DirectionalLightDirection.y * sampleCaustics(planePos);
         }
     }
 
@@ -32,4 +32,43 @@ float evaluateNormalizedBlinnPhong(float BlinnPhongExponent, vec3 normal, vec3 t
 vec3 evaluateHenyeyGreensteinPhaseFunction(vec3 g, vec3 rayIn, vec3 rayOut) {
     const vec3 gSq = g * g;
     return (1.0f - gSq) * pow(1.0f + gSq - 2.0f * g * dot(rayIn, rayOut), vec3(-3.0f / 2.0f)) * (0.5 * INV_TAU);
-}
\ No newline at end of file
+}
+
+// ----------------------------------------
+// Microfacet BRDF (metallic/roughness)
+// Following the usual realtime conventions, see https://google.github.io/filament/Filament.html#materialsystem/specularbrdf
+// ----------------------------------------
+
+vec3 schlickFresnel(float vDotH, vec3 f0) { return f0 + (vec3(1.0) - f0) * pow(1.0 - vDotH, 5.0); }
+
+// GGX/Trowbridge-Reitz normal distribution
+float distributionGgx(float nDotH, float alpha) {
+    float alphaSq = alpha * alpha;
+    float d = nDotH * nDotH * (alphaSq - 1.0) + 1.0;
+    return alphaSq / (PI * d * d);
+}
+
+// Height correlated Smith visibility term (includes the 1 / (4 * nDotL * nDotV) denominator)
+float visibilitySmithGgxCorrelated(float nDotV, float nDotL, float alpha) {
+    float alphaSq = alpha * alpha;
+    float ggxV = nDotL * sqrt(nDotV * nDotV * (1.0 - alphaSq) + alphaSq);
+    float ggxL = nDotV * sqrt(nDotL * nDotL * (1.0 - alphaSq) + alphaSq);
+    return 0.5 / (ggxV + ggxL + 1e-5);
+}
+
+// Returns BRDF * nDotL for a single light direction.
+vec3 evaluatePbrBrdfCosine(vec3 albedo, float roughness, float metallic, vec3 normal, vec3 toCamera, vec3 toLight) {
+    vec3 halfVector = normalize(toCamera + toLight);
+    float nDotV = abs(dot(normal, toCamera)) + 1e-5;
+    float nDotL = saturate(dot(normal, toLight));
+    float nDotH = saturate(dot(normal, halfVector));
+    float vDotH = saturate(dot(toCamera, halfVector));
+
+    float alpha = max(roughness * roughness, 0.002); // perceptual roughness to alpha, avoid singularity for perfect mirrors
+    vec3 f0 = mix(vec3(0.04), albedo, metallic);
+    vec3 fresnel = schlickFresnel(vDotH, f0);
+
+    vec3 specular = distributionGgx(nDotH, alpha) * visibilitySmithGgxCorrelated(nDotV, nDotL, alpha) * fresnel;
+    vec3 diffuse = (vec3(1.0) - fresnel) * (1.0 - metallic) * albedo / PI;
+    return (diffuse + specular) * nDotL;
+}
@@ -4,6 +4,22 @@
 
 layout(set = 0, binding = 0) uniform texture2D Texture;
 
+layout(PUSH_CONSTANT_LAYOUT) uniform PushConstants { uint ColorSpaceDebugMode; };
+
 layout(location = 0) out vec4 out_Color;
 
-void main() { out_Color = texelFetch(Texture, ivec2(gl_FragCoord.xy), 0); }
\ No newline at end of file
+// Needs to be kept in sync with ColorSpaceDebugMode in screen.rs
+#define COLOR_SPACE_DEBUG_NONE 0
+#define COLOR_SPACE_DEBUG_MISSING_ENCODE 1
+#define COLOR_SPACE_DEBUG_DOUBLE_ENCODE 2
+
+void main() {
+    // Both source & target are *Srgb formats, i.e. the read decodes to linear and the write encodes again.
+    out_Color = texelFetch(Texture, ivec2(gl_FragCoord.xy), 0);
+
+    // Simulate the typical mistakes, for comparing against images that look too dark or washed out.
+    if (ColorSpaceDebugMode == COLOR_SPACE_DEBUG_MISSING_ENCODE)
+        out_Color.rgb = srgbToLinear(out_Color.rgb);
+    else if (ColorSpaceDebugMode == COLOR_SPACE_DEBUG_DOUBLE_ENCODE)
+        out_Color.rgb = linearToSrgb(out_Color.rgb);
+}
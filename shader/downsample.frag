@@ -0,0 +1,29 @@
+// Scales a texture to the size of the render target by averaging all source texels in the footprint of a target pixel.
+// Used for resolving supersampled offscreen renderings (integer factor) and for previewing them at arbitrary window size.
+
+#version 450
+
+#include "utilities.glsl"
+
+layout(set = 0, binding = 0) uniform texture2D Texture;
+
+layout(PUSH_CONSTANT_LAYOUT) uniform PushConstants { uvec2 TargetSize; };
+
+layout(location = 0) out vec4 out_Color;
+
+#define MAX_TAPS_PER_AXIS 8
+
+void main() {
+    ivec2 sourceSize = textureSize(Texture, 0);
+    vec2 ratio = vec2(sourceSize) / vec2(TargetSize);
+    ivec2 sourceMin = ivec2(floor(floor(gl_FragCoord.xy) * ratio));
+    ivec2 numTaps = clamp(ivec2(ceil(ratio)), ivec2(1), ivec2(MAX_TAPS_PER_AXIS));
+
+    vec4 sum = vec4(0.0);
+    for (int y = 0; y < numTaps.y; ++y) {
+        for (int x = 0; x < numTaps.x; ++x) {
+            sum += texelFetch(Texture, min(sourceMin + ivec2(x, y), sourceSize - ivec2(1)), 0);
+        }
+    }
+    out_Color = sum / float(numTaps.x * numTaps.y);
+}
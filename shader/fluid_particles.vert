@@ -7,10 +7,21 @@
 
 out gl_PerVertex { vec4 gl_Position; };
 
-layout(push_constant) uniform PushConstants { uint VisualizationType; };
+layout(PUSH_CONSTANT_LAYOUT) uniform PushConstants {
+    uint VisualizationType;
+    uint MirrorIndex;
+};
+
+// Output of particle_culling.comp
+layout(set = 2, binding = 0) buffer restrict readonly VisibleParticlesBuffer { uint VisibleParticles[]; };
+#define VISIBLE_PARTICLE_LOD_SHIFT 28
+#define VISIBLE_PARTICLE_INDEX_MASK ((1u << VISIBLE_PARTICLE_LOD_SHIFT) - 1u)
 
 #define VISUALIZE_VELOCITY 0
 #define VISUALIZE_INDEX 1
+#define VISUALIZE_PHASE 2
+#define VISUALIZE_AGE 3
+#define VISUALIZE_DYE 4
 
 layout(location = 0) out vec3 out_WorldPosition;
 layout(location = 1) out vec3 out_ParticleWorldPosition;
@@ -18,21 +29,39 @@ layout(location = 2) out vec3 out_Tint;
 layout(location = 3) out float out_Radius;
 
 void main() {
-    out_Radius = Rendering.FluidParticleRadius;
+    uint visibleParticle = VisibleParticles[gl_InstanceIndex];
+    uint particleIndex = visibleParticle & VISIBLE_PARTICLE_INDEX_MASK;
+    uint lodLevel = visibleParticle >> VISIBLE_PARTICLE_LOD_SHIFT;
+    // Each lod level skips every other particle, so the remaining ones grow to keep the overall volume.
+    out_Radius = Rendering.FluidParticleRadius * exp2(float(lodLevel) / 3.0);
 
     switch (VisualizationType) {
     case VISUALIZE_VELOCITY: {
-        vec3 velocity = vec3(ParticleBufferVelocityX[gl_InstanceIndex].w, ParticleBufferVelocityY[gl_InstanceIndex].w,
-                             ParticleBufferVelocityZ[gl_InstanceIndex].w);
+        vec3 velocity = vec3(ParticleBufferVelocityX[particleIndex].w, ParticleBufferVelocityY[particleIndex].w,
+                             ParticleBufferVelocityZ[particleIndex].w);
         out_Tint = colormapHeat(length(velocity) * Rendering.VelocityVisualizationScale);
         break;
     }
     case VISUALIZE_INDEX:
-        out_Tint = vec3(fract(gl_InstanceIndex / 255.0));
+        out_Tint = vec3(fract(particleIndex / 255.0));
+        break;
+    case VISUALIZE_PHASE:
+        // Color of white light after passing through a meter of the particle's fluid.
+        out_Tint = exp(-Rendering.FluidPhaseAbsorption[ParticlePhases[particleIndex]].rgb);
+        break;
+    case VISUALIZE_AGE:
+        out_Tint = colormapHeat(ParticleAges[particleIndex] / Rendering.AgeVisualizationRange);
         break;
+    case VISUALIZE_DYE: {
+        // Undyed fluid is shown in a neutral gray, so that dye stands out.
+        vec4 dye = unpackUnorm4x8(ParticleDyes[particleIndex]);
+        out_Tint = mix(vec3(0.5), srgbToLinear(dye.rgb), dye.a);
+        break;
+    }
     }
 
-    out_ParticleWorldPosition = Particles[gl_InstanceIndex].Position * Rendering.FluidGridToWorldScale + Rendering.FluidWorldMin;
+    out_ParticleWorldPosition =
+        mirrorFluidGridPosition(Particles[particleIndex].Position, MirrorIndex) * Rendering.FluidGridToWorldScale + Rendering.FluidWorldMin;
     out_WorldPosition = spanParticle(out_ParticleWorldPosition, out_Radius);
     gl_Position = Camera.ViewProjection * vec4(out_WorldPosition, 1.0);
 }
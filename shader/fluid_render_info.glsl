@@ -18,6 +18,15 @@ layout(set = 1, binding = 6) uniform texture3D VelocityVolumeZ;
 layout(set = 1, binding = 7) uniform texture3D MarkerVolume;
 layout(set = 1, binding = 8) uniform texture3D PressureVolume_Velocity;
 layout(set = 1, binding = 9) uniform texture3D PressureVolume_Density;
+layout(set = 1, binding = 10) buffer restrict readonly ParticlePhaseBuffer { uint ParticlePhases[]; };
+layout(set = 1, binding = 11) uniform texture3D MarkerVolumePrevious;
+layout(set = 1, binding = 12) uniform texture3D SceneVoxelization;
+// Particle count per cell from the last (counting sort) binning in the lower 16 bit, see particle_binning.glsl
+layout(set = 1, binding = 13) uniform utexture3D ParticleCountVolume;
+// Seconds since each particle was added to the simulation.
+layout(set = 1, binding = 14) buffer restrict readonly ParticleAgeBuffer { float ParticleAges[]; };
+// Passive dye color of each particle, packed rgba8 (sRGB). Alpha is the amount of dye, zero for undyed particles.
+layout(set = 1, binding = 15) buffer restrict readonly ParticleDyeBuffer { uint ParticleDyes[]; };
 #ifdef DEBUG
-layout(set = 1, binding = 10) uniform texture3D DebugVolume;
+layout(set = 1, binding = 16) uniform texture3D DebugVolume;
 #endif
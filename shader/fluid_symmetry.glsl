@@ -0,0 +1,17 @@
+#ifndef INCLUDE_FLUID_SYMMETRY
+#define INCLUDE_FLUID_SYMMETRY
+
+#include "global_bindings.glsl"
+
+// Symmetry mode: The simulated domain is mirrored on its positive x (and z) boundary.
+// mirrorIndex bit 0 mirrors on x, bit 1 on z.
+vec3 mirrorFluidGridPosition(vec3 gridPosition, uint mirrorIndex) {
+    vec3 mirrorPlanes = vec3(Rendering.FluidGridResolution) - vec3(1.0); // Outermost cells are solid.
+    if ((mirrorIndex & 1) != 0)
+        gridPosition.x = 2.0 * mirrorPlanes.x - gridPosition.x;
+    if ((mirrorIndex & 2) != 0)
+        gridPosition.z = 2.0 * mirrorPlanes.z - gridPosition.z;
+    return gridPosition;
+}
+
+#endif // INCLUDE_FLUID_SYMMETRY
@@ -35,6 +35,10 @@ struct GlobalRenderingSettings {
     float VelocityVisualizationScale;
     uvec3 FluidGridResolution; // TODO: This is not a rendering setting
     float FluidParticleRadius; // particle size in world space
+    uint FluidSymmetry;        // See FluidSymmetry in scene/mod.rs
+    float AgeVisualizationRange; // Particle age in seconds that maps to the top of the age colormap
+    // Extinction per meter in rgb for each fluid phase (MAX_NUM_FLUID_PHASES), see FluidPhaseConfig in scene/mod.rs
+    vec4 FluidPhaseAbsorption[2];
 };
 
 struct ScreenData {
@@ -72,13 +76,32 @@ struct MeshData {
     uvec2 VertexBufferRange;
     uvec2 IndexBufferRange;
 
-    int TextureIndex;
-    // ivec3 _Padding1;
+    // Material. Texture indices are -1 if not present.
+    int AlbedoTextureIndex;
+    int NormalTextureIndex;
+    int RoughnessTextureIndex;
+    int MetallicTextureIndex;
+    float Roughness;
+    float Metallic;
+
+    // Boundary material for the fluid, see BoundaryMaterial in models.rs
+    float BoundaryFriction;
+    float BoundaryRestitution;
 };
 layout(set = 0, binding = 3) restrict readonly buffer Meshes_ { MeshData Meshes[]; };
+
+// Voxelized solids store their velocity in xyz and the boundary material in w.
+// w is zero for empty voxels, otherwise 1 + friction & restitution quantized to 4 bits each (exactly representable in rgba16f).
+float encodeSolidBoundary(float friction, float restitution) {
+    uint quantized = uint(round(clamp(friction, 0.0, 1.0) * 15.0)) * 16 + uint(round(clamp(restitution, 0.0, 1.0) * 15.0));
+    return 1.0 + float(quantized) / 256.0;
+}
+// Domain border cells aren't voxelized, they (and anything else with w below 1) act as free-slip walls without restitution.
+float solidBoundaryFriction(float voxelW) { return voxelW < 1.0 ? 0.0 : float(uint((voxelW - 1.0) * 256.0 + 0.5) / 16) / 15.0; }
+float solidBoundaryRestitution(float voxelW) { return voxelW < 1.0 ? 0.0 : float(uint((voxelW - 1.0) * 256.0 + 0.5) % 16) / 15.0; }
 // Not going with dynamic size (UNSIZED_BINDING_ARRAY extension) for convenience in layout setup (which doesn't change per scene).
 // (also this is more widely supported)
-layout(set = 0, binding = 4) uniform texture2D MeshTextures[1];
+layout(set = 0, binding = 4) uniform texture2D MeshTextures[14]; // See GlobalBindings::NUM_MESH_TEXTURES
 
 // Can't do packed layouts in glsl/spirv?
 struct Vertex {
@@ -89,6 +112,8 @@ struct Vertex {
 };
 layout(set = 0, binding = 5) restrict readonly buffer MeshIndices_ { uint MeshIndices[]; };
 layout(set = 0, binding = 6) restrict readonly buffer MeshVertices_ { Vertex MeshVertices[]; };
+// Trilinear + anisotropic filtering, repeat addressing. Meant for mesh textures which all come with mipmaps.
+layout(set = 0, binding = 7) uniform sampler SamplerAnisotropicRepeat;
 
 // ----------------------------------------
 // Other
@@ -106,4 +131,14 @@ vec3 reconstructWorldPositionFromViewSpaceDepth(vec2 screenUv, float depth) {
     return viewSpace.x * Camera.Right + viewSpace.y * Camera.Up + viewSpace.z * Camera.Direction + Camera.Position;
 }
 
+// Converts a depth buffer value back to view space depth (distance along the camera direction).
+// Clip space w is the view space depth and clip space z is a*w + b, so we can get a & b from the view projection matrix.
+float viewSpaceDepthFromDepthbuffer(float depthbufferValue) {
+    vec4 rowZ = vec4(Camera.ViewProjection[0][2], Camera.ViewProjection[1][2], Camera.ViewProjection[2][2], Camera.ViewProjection[3][2]);
+    vec4 rowW = vec4(Camera.ViewProjection[0][3], Camera.ViewProjection[1][3], Camera.ViewProjection[2][3], Camera.ViewProjection[3][3]);
+    float a = dot(rowZ.xyz, Camera.Direction);
+    float b = rowZ.w - a * rowW.w;
+    return b / (depthbufferValue - a);
+}
+
 #endif // INCLUDE_PERFRAMERESOURCES
\ No newline at end of file
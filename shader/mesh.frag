@@ -1,28 +1,119 @@
 #version 460
 
 #include "background.glsl"
+#include "brdf.glsl"
 #include "sh.glsl"
 #include "utilities.glsl"
 
-layout(push_constant) uniform PushConstants_ { uint MeshIndex; };
+layout(PUSH_CONSTANT_LAYOUT) uniform PushConstants_ {
+    uint MeshIndex;
+    uint DebugMode;
+};
+
+// See MeshDebugMode
+#define DEBUG_MODE_NONE 0
+#define DEBUG_MODE_FACE_NORMALS 1
+#define DEBUG_MODE_VERTEX_NORMALS 2
+#define DEBUG_MODE_UV_CHECKER 3
+#define DEBUG_MODE_TEXTURE_INDEX 4
+#define DEBUG_MODE_WIREFRAME 5
 
 layout(location = 0) in vec3 in_Normal;
 layout(location = 1) in vec2 in_Texcoord;
+layout(location = 2) in vec3 in_WorldPosition;
 layout(location = 0) out vec4 out_Color;
 
+vec4 sampleMeshTexture(int textureIndex, vec4 fallback) {
+    if (textureIndex < 0)
+        return fallback;
+    return texture(sampler2D(MeshTextures[textureIndex], SamplerAnisotropicRepeat), in_Texcoord);
+}
+
+// We don't have tangents in our vertex data, so we compute a cotangent frame on the fly.
+// http://www.thetenthplanet.de/archives/1180
+vec3 perturbNormal(vec3 normal, vec3 tangentSpaceNormal) {
+    vec3 dp1 = dFdx(in_WorldPosition);
+    vec3 dp2 = dFdy(in_WorldPosition);
+    vec2 duv1 = dFdx(in_Texcoord);
+    vec2 duv2 = dFdy(in_Texcoord);
+
+    vec3 dp2perp = cross(dp2, normal);
+    vec3 dp1perp = cross(normal, dp1);
+    vec3 tangent = dp2perp * duv1.x + dp1perp * duv2.x;
+    vec3 bitangent = dp2perp * duv1.y + dp1perp * duv2.y;
+
+    float invmax = inversesqrt(max(dot(tangent, tangent), dot(bitangent, bitangent)) + 1e-20);
+    // Texcoord y is flipped on load, so bitangent needs to be flipped as well.
+    return normalize(mat3(tangent * invmax, -bitangent * invmax, normal) * tangentSpaceNormal);
+}
+
+vec3 indexColor(int index) {
+    if (index < 0)
+        return vec3(0.1);
+    uint hash = uint(index + 1) * 2654435761u;
+    return vec3((hash >> 8) & 255u, (hash >> 16) & 255u, (hash >> 24) & 255u) / 255.0;
+}
+
+// Returns true if the debug mode was handled.
+bool debugOutput(MeshData mesh, vec3 normal) {
+    switch (DebugMode) {
+    case DEBUG_MODE_FACE_NORMALS:
+        out_Color = vec4(normalize(cross(dFdx(in_WorldPosition), dFdy(in_WorldPosition))) * 0.5 + 0.5, 1.0);
+        return true;
+    case DEBUG_MODE_VERTEX_NORMALS:
+        out_Color = vec4(normal * 0.5 + 0.5, 1.0);
+        return true;
+    case DEBUG_MODE_UV_CHECKER: {
+        // Out of [0, 1] texcoords are tinted red, so wrapping issues stand out.
+        ivec2 checker = ivec2(floor(in_Texcoord * 16.0));
+        vec3 color = vec3(((checker.x + checker.y) & 1) == 0 ? 0.2 : 0.8);
+        if (any(lessThan(in_Texcoord, vec2(0.0))) || any(greaterThan(in_Texcoord, vec2(1.0))))
+            color *= vec3(1.0, 0.3, 0.3);
+        out_Color = vec4(color, 1.0);
+        return true;
+    }
+    case DEBUG_MODE_TEXTURE_INDEX:
+        // Alternating stripes show albedo and normal map texture index. Dark gray means no texture.
+        out_Color = vec4(fract(in_Texcoord.y * 4.0) < 0.5 ? indexColor(mesh.AlbedoTextureIndex) : indexColor(mesh.NormalTextureIndex), 1.0);
+        return true;
+    case DEBUG_MODE_WIREFRAME:
+        out_Color = vec4(1.0, 0.6, 0.0, 1.0);
+        return true;
+    }
+    return false;
+}
+
 void main() {
-    vec3 normal = normalize(vec4(in_Normal, 0.0) * Meshes[MeshIndex].WorldTransform);
+    MeshData mesh = Meshes[MeshIndex];
+    vec3 normal = normalize(vec4(in_Normal, 0.0) * mesh.WorldTransform);
+    if (debugOutput(mesh, normal))
+        return;
+
+    vec3 albedo = sampleMeshTexture(mesh.AlbedoTextureIndex, vec4(1.0)).rgb;
+    float roughness = sampleMeshTexture(mesh.RoughnessTextureIndex, vec4(mesh.Roughness)).r;
+    float metallic = sampleMeshTexture(mesh.MetallicTextureIndex, vec4(mesh.Metallic)).r;
+    if (mesh.NormalTextureIndex >= 0) {
+        vec3 tangentSpaceNormal = sampleMeshTexture(mesh.NormalTextureIndex, vec4(0.5, 0.5, 1.0, 0.0)).xyz * 2.0 - 1.0;
+        normal = perturbNormal(normal, tangentSpaceNormal);
+    }
+
+    vec3 toCamera = normalize(Camera.Position - in_WorldPosition);
 
-    vec3 albedo = vec3(1.0);
-    int textureIndex = Meshes[MeshIndex].TextureIndex;
-    if (textureIndex >= 0) {
-        albedo = texture(sampler2D(MeshTextures[textureIndex], SamplerTrilinearClamp), in_Texcoord).rgb;
+    vec3 radiance = evaluatePbrBrdfCosine(albedo, roughness, metallic, normal, toCamera, -DirectionalLightDirection) * DirectionalLightRadiance *
+                    sampleCaustics(in_WorldPosition);
+    for (uint i = 0; i < NumLights; ++i) {
+        vec3 toLight;
+        vec3 lightRadiance = evaluateLight(Lights[i], in_WorldPosition, toLight);
+        radiance += evaluatePbrBrdfCosine(albedo, roughness, metallic, normal, toCamera, toLight) * lightRadiance;
     }
 
-    vec3 brdf = albedo / PI;
+    // Indirect light.
+    // Diffuse via SH, exagerated because everyone loves exagerated indirect light (need better tonemap!! TODO)
+    // Specular via unfiltered environment lookup, faded out with roughness since we don't have prefiltered mips. Very rough approximation!
+    vec3 f0 = mix(vec3(0.04), albedo, metallic);
+    vec3 fresnel = schlickFresnel(saturate(dot(normal, toCamera)), f0);
+    radiance += (vec3(1.0) - fresnel) * (1.0 - metallic) * albedo / PI * saturate(sh3EvaluateCosine(normal, IndirectRadianceSH3)) * 4.0;
+    radiance += fresnel * sq(1.0 - roughness) * sampleHdrCubemap(reflect(-toCamera, normal));
 
-    vec3 radiance = brdf * saturate(dot(normal, -DirectionalLightDirection)) * DirectionalLightRadiance;
-    radiance += brdf * saturate(sh3EvaluateCosine(normal, IndirectRadianceSH3)) *
-                4.0; // because everyone loves exagerated indirect light (need better tonemap!! TODO)
     out_Color = vec4(radiance, 1);
-}
\ No newline at end of file
+}
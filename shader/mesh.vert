@@ -5,15 +5,17 @@
 layout(location = 0) in vec3 in_Position;
 layout(location = 1) in vec3 in_Normal;
 layout(location = 2) in vec2 in_Texcoord;
-layout(push_constant) uniform PushConstants_ { uint MeshIndex; };
+layout(PUSH_CONSTANT_LAYOUT) uniform PushConstants_ { uint MeshIndex; };
 
 layout(location = 0) out vec3 out_Normal;
 layout(location = 1) out vec2 out_Texcoord;
+layout(location = 2) out vec3 out_WorldPosition;
 
 out gl_PerVertex { vec4 gl_Position; };
 
 void main() {
     out_Normal = in_Normal;
     out_Texcoord = in_Texcoord;
-    gl_Position = Camera.ViewProjection * vec4(vec4(in_Position, 1.0) * Meshes[MeshIndex].WorldTransform, 1.0);
+    out_WorldPosition = vec4(in_Position, 1.0) * Meshes[MeshIndex].WorldTransform;
+    gl_Position = Camera.ViewProjection * vec4(out_WorldPosition, 1.0);
 }
@@ -0,0 +1,11 @@
+#version 460
+
+// Writes mesh ids for object picking.
+
+layout(PUSH_CONSTANT_LAYOUT) uniform PushConstants_ { uint MeshIndex; };
+
+layout(location = 0) out uint out_MeshId;
+
+void main() {
+    out_MeshId = MeshIndex + 1; // 0 means nothing was hit
+}
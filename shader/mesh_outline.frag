@@ -0,0 +1,7 @@
+#version 460
+
+layout(location = 0) out vec4 out_Color;
+
+void main() {
+    out_Color = vec4(4.0, 1.5, 0.1, 1.0); // bright orange, before tonemapping
+}
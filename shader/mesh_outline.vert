@@ -0,0 +1,24 @@
+#version 460
+
+// Selection outline via inverted hull: back faces of the mesh pushed outwards along the normal.
+
+#include "global_bindings.glsl"
+
+layout(location = 0) in vec3 in_Position;
+layout(location = 1) in vec3 in_Normal;
+layout(location = 2) in vec2 in_Texcoord;
+layout(PUSH_CONSTANT_LAYOUT) uniform PushConstants_ { uint MeshIndex; };
+
+out gl_PerVertex { vec4 gl_Position; };
+
+// Outline width relative to screen height.
+const float OutlineWidth = 0.003;
+
+void main() {
+    vec3 worldPosition = vec4(in_Position, 1.0) * Meshes[MeshIndex].WorldTransform;
+    vec3 worldNormal = normalize(vec4(in_Normal, 0.0) * Meshes[MeshIndex].WorldTransform);
+    // Scale with distance so the outline has the same width everywhere on screen.
+    float distanceToCamera = dot(worldPosition - Camera.Position, Camera.Direction);
+    worldPosition += worldNormal * (distanceToCamera * Camera.TanHalfVerticalFov * 2.0 * OutlineWidth);
+    gl_Position = Camera.ViewProjection * vec4(worldPosition, 1.0);
+}
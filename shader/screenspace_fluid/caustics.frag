@@ -0,0 +1,7 @@
+#version 460
+
+layout(location = 0) in float in_Irradiance;
+layout(location = 1) in float in_SurfaceHeight;
+layout(location = 0) out vec4 out_Caustics;
+
+void main() { out_Caustics = vec4(vec3(in_Irradiance), in_SurfaceHeight); }
@@ -0,0 +1,86 @@
+// Splats light refracted by the visible fluid surface onto the floor plane, one point per pixel of the fluid depth target.
+// Each point carries the light that the surface patch under the pixel receives, in relation to the light an unobstructed patch of the
+// floor gets. Where the refracted light converges, points add up to more than one.
+//
+// Only considers what is visible on screen, so anything hidden or offscreen doesn't cast any caustics.
+
+#version 460
+
+#include "../global_bindings.glsl"
+#include "../utilities.glsl"
+
+layout(set = 1, binding = 0) uniform texture2D FluidViewSpaceDepth;
+// Prefix of the BackgroundAndLighting uniform buffer in background.glsl
+layout(set = 1, binding = 1) uniform BackgroundAndLighting {
+    vec3 DirectionalLightDirection;
+    vec3 DirectionalLightRadiance;
+};
+
+layout(location = 0) out float out_Irradiance;
+layout(location = 1) out float out_SurfaceHeight;
+
+// See background.glsl
+const float FloorPlaneSize = 10.0;
+const float CausticsResolution = 256.0;
+
+const float RefractionAirToWater = 1.00029 / 1.333;
+
+bool isFluidDepth(float depth) { return depth < 9999.0 && depth != 0.0; }
+
+void main() {
+    ivec2 fluidResolution = textureSize(FluidViewSpaceDepth, 0);
+    ivec2 coord = ivec2(gl_VertexIndex % fluidResolution.x, gl_VertexIndex / fluidResolution.x);
+    vec2 pixelSize = vec2(1.0) / fluidResolution;
+
+    // Anything that doesn't hit the floor is moved out of clip space.
+    gl_Position = vec4(-10.0, -10.0, 0.0, 1.0);
+    gl_PointSize = 1.0;
+    out_Irradiance = 0.0;
+    out_SurfaceHeight = 0.0;
+
+    float depth = texelFetch(FluidViewSpaceDepth, coord, 0).r;
+    if (!isFluidDepth(depth))
+        return;
+
+    // Surface normal from neighboring pixels, picking the closer of each horizontal & vertical pair. (like in fluid_render.comp)
+    float depthRight = texelFetch(FluidViewSpaceDepth, coord + ivec2(1, 0), 0).r;
+    float depthLeft = texelFetch(FluidViewSpaceDepth, coord - ivec2(1, 0), 0).r;
+    float depthUp = texelFetch(FluidViewSpaceDepth, coord + ivec2(0, 1), 0).r;
+    float depthDown = texelFetch(FluidViewSpaceDepth, coord - ivec2(0, 1), 0).r;
+    if ((!isFluidDepth(depthRight) && !isFluidDepth(depthLeft)) || (!isFluidDepth(depthUp) && !isFluidDepth(depthDown)))
+        return;
+
+    vec3 position = reconstructWorldPositionFromViewSpaceDepth((coord + vec2(0.5)) * pixelSize, depth);
+    vec3 tangentX, tangentY;
+    if (abs(depthRight - depth) < abs(depthLeft - depth))
+        tangentX = reconstructWorldPositionFromViewSpaceDepth((coord + vec2(1.5, 0.5)) * pixelSize, depthRight) - position;
+    else
+        tangentX = position - reconstructWorldPositionFromViewSpaceDepth((coord + vec2(-0.5, 0.5)) * pixelSize, depthLeft);
+    if (abs(depthUp - depth) < abs(depthDown - depth))
+        tangentY = reconstructWorldPositionFromViewSpaceDepth((coord + vec2(0.5, 1.5)) * pixelSize, depthUp) - position;
+    else
+        tangentY = position - reconstructWorldPositionFromViewSpaceDepth((coord + vec2(0.5, -0.5)) * pixelSize, depthDown);
+    vec3 normal = normalize(cross(tangentY, tangentX));
+    vec3 toCamera = normalize(Camera.Position - position);
+    if (dot(normal, toCamera) < 0.0)
+        normal = -normal;
+
+    // Light hitting the back side doesn't enter through this surface.
+    float nDotL = -dot(normal, DirectionalLightDirection);
+    if (nDotL <= 0.0 || position.y <= 0.0)
+        return;
+    vec3 refracted = refract(DirectionalLightDirection, normal, RefractionAirToWater);
+    if (refracted.y >= 0.0)
+        return;
+    vec3 floorPosition = position - refracted * (position.y / refracted.y);
+
+    // Surface area covered by this pixel and the share of light it catches, distributed over a caustics texel.
+    float pixelWorldSize = 2.0 * Camera.TanHalfVerticalFov * depth * pixelSize.y;
+    float surfaceArea = sq(pixelWorldSize) / max(dot(normal, toCamera), 0.1);
+    float texelArea = sq(2.0 * FloorPlaneSize / CausticsResolution);
+    out_Irradiance = surfaceArea * nDotL / (-DirectionalLightDirection.y * texelArea);
+    out_SurfaceHeight = position.y;
+
+    vec2 floorUv = floorPosition.xz / (2.0 * FloorPlaneSize) + vec2(0.5);
+    gl_Position = vec4(floorUv.x * 2.0 - 1.0, 1.0 - floorUv.y * 2.0, 0.0, 1.0);
+}
@@ -0,0 +1,24 @@
+// Downsamples the scene depthbuffer for rendering fluid particles at reduced resolution.
+// Takes the farthest depth in the footprint of a target pixel, so particles are only discarded if they are hidden in all covered pixels.
+// The exact per pixel test is done in fluid_render.comp when composing.
+
+#version 450
+
+#include "../utilities.glsl"
+
+layout(set = 0, binding = 0) uniform texture2D SceneDepth;
+
+layout(PUSH_CONSTANT_LAYOUT) uniform PushConstants { uint ResolutionDivisor; };
+
+void main() {
+    ivec2 sourceSize = textureSize(SceneDepth, 0);
+    ivec2 sourceMin = ivec2(gl_FragCoord.xy) * int(ResolutionDivisor);
+
+    float depth = 0.0;
+    for (int y = 0; y < int(ResolutionDivisor); ++y) {
+        for (int x = 0; x < int(ResolutionDivisor); ++x) {
+            depth = max(depth, texelFetch(SceneDepth, min(sourceMin + ivec2(x, y), sourceSize - ivec2(1)), 0).r);
+        }
+    }
+    gl_FragDepth = depth;
+}
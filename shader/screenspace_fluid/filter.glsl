@@ -1,4 +1,4 @@
-layout(push_constant) uniform PushConstants { uint FilterDirection; };
+layout(PUSH_CONSTANT_LAYOUT) uniform PushConstants { uint FilterDirection; };
 
 #if defined(FILTER_2D)
 
@@ -6,8 +6,11 @@
 layout(location = 0) in vec3 in_WorldPosition;
 layout(location = 1) in vec3 in_ParticleWorldPosition;
 layout(location = 2) in float in_Radius;
+layout(location = 3) in float in_Foam;
+layout(location = 4) in float in_SecondaryPhase;
 layout(location = 0) out float out_ViewSpaceDepth;
 layout(location = 1) out float out_Thickness;
+layout(location = 2) out vec2 out_FoamAndPhase;
 
 void main() {
     vec3 rayDir = normalize(in_WorldPosition - Camera.Position);
@@ -22,4 +25,6 @@ void main() {
     out_ViewSpaceDepth = dot(Camera.Direction, cameraPosToSpherePos);
     // quadratic splats. Compensate a bit for particle overlap
     out_Thickness = (cameraDistanceFar - cameraDistance) * (0.25 * Rendering.FluidGridToWorldScale / Rendering.FluidParticleRadius);
+    // Weighted by thickness, so that dividing by thickness gives the average foam/phase along the view ray.
+    out_FoamAndPhase = vec2(in_Foam, in_SecondaryPhase) * out_Thickness;
 }
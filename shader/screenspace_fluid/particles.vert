@@ -7,13 +7,31 @@
 
 out gl_PerVertex { vec4 gl_Position; };
 
+layout(PUSH_CONSTANT_LAYOUT) uniform PushConstants {
+    uint MirrorIndex;
+    uint FoamParameters; // Threshold & amount, packed as half floats.
+};
+
 layout(location = 0) out vec3 out_WorldPosition;
 layout(location = 1) out vec3 out_ParticleWorldPosition;
 layout(location = 2) out float out_Radius;
+layout(location = 3) out float out_Foam;
+layout(location = 4) out float out_SecondaryPhase;
 
 void main() {
-    out_Radius = Rendering.FluidParticleRadius;
-    out_ParticleWorldPosition = Particles[gl_InstanceIndex].Position * Rendering.FluidGridToWorldScale + Rendering.FluidWorldMin;
+    // Zero radius gives a degenerated quad for dead particles.
+    out_Radius = isParticleDead(Particles[gl_InstanceIndex].Position) ? 0.0 : Rendering.FluidParticleRadius;
+    out_ParticleWorldPosition =
+        mirrorFluidGridPosition(Particles[gl_InstanceIndex].Position, MirrorIndex) * Rendering.FluidGridToWorldScale + Rendering.FluidWorldMin;
     out_WorldPosition = spanParticle(out_ParticleWorldPosition, out_Radius);
     gl_Position = Camera.ViewProjection * vec4(out_WorldPosition, 1.0);
+
+    // The trace of the APIC matrix is the velocity divergence around the particle.
+    // Strongly diverging or converging flow is where water breaks up and air gets mixed in.
+    float divergence = ParticleBufferVelocityX[gl_InstanceIndex].x + ParticleBufferVelocityY[gl_InstanceIndex].y +
+                       ParticleBufferVelocityZ[gl_InstanceIndex].z;
+    vec2 foamParameters = unpackHalf2x16(FoamParameters);
+    out_Foam = saturate((abs(divergence) - foamParameters.x) * foamParameters.y);
+
+    out_SecondaryPhase = ParticlePhases[gl_InstanceIndex] == 0 ? 0.0 : 1.0;
 }
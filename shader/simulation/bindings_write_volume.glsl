@@ -12,3 +12,14 @@ layout(set = 2, binding = 1, r32f) uniform restrict image3D VelocityVolumeX;
 layout(set = 2, binding = 2, r32f) uniform restrict image3D VelocityVolumeY;
 layout(set = 2, binding = 3, r32f) uniform restrict image3D VelocityVolumeZ;
 layout(set = 2, binding = 4) uniform texture3D PressureVolume;
+// Fluid density relative to the primary phase, see transfer_set_boundary_marker.comp
+layout(set = 2, binding = 5) uniform texture3D DensityVolume;
+
+// Density at the face between two cells, at least one of which is fluid. (see FaceCoefficient in pressure.glsl)
+float faceDensity(ivec3 gridCoordA, float cellTypeA, ivec3 gridCoordB, float cellTypeB) {
+    if (cellTypeA != CELL_FLUID)
+        return texelFetch(DensityVolume, gridCoordB, 0).x;
+    if (cellTypeB != CELL_FLUID)
+        return texelFetch(DensityVolume, gridCoordA, 0).x;
+    return 0.5 * (texelFetch(DensityVolume, gridCoordA, 0).x + texelFetch(DensityVolume, gridCoordB, 0).x);
+}
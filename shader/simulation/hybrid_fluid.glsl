@@ -1,16 +1,96 @@
 // Occupancy calculator: https://xmartlabs.github.io/cuda-calculator/
-#define COMPUTE_PASS_PARTICLES layout(local_size_x = 64, local_size_y = 1, local_size_z = 1) in;
-#define COMPUTE_PASS_VOLUME layout(local_size_x = 8, local_size_y = 8, local_size_z = 8) in;
+#include "simulation/workgroup_sizes.glsl"
+#define COMPUTE_PASS_PARTICLES layout(local_size_x = LOCAL_SIZE_PARTICLES, local_size_y = 1, local_size_z = 1) in;
+#define COMPUTE_PASS_VOLUME layout(local_size_x = LOCAL_SIZE_VOLUME_X, local_size_y = LOCAL_SIZE_VOLUME_Y, local_size_z = LOCAL_SIZE_VOLUME_Z) in;
+
+// Wraps grid coordinates around along all axes that have their bit set in periodicAxes (see FluidConfig::periodic).
+// Only works for coordinates that are less than one domain size outside of the grid.
+ivec3 wrapGridCoord(ivec3 gridCoord, ivec3 gridResolution, uint periodicAxes) {
+    for (int i = 0; i < 3; ++i) {
+        if ((periodicAxes & (1u << i)) != 0)
+            gridCoord[i] = (gridCoord[i] + gridResolution[i]) % gridResolution[i];
+    }
+    return gridCoord;
+}
 
 #ifndef NO_SIMPROPS
 
+// Needs to be kept in sync with MAX_NUM_FORCE_FIELDS in hybrid_fluid.rs
+#define MAX_NUM_FORCE_FIELDS 4
+#define FORCE_FIELD_WIND 1
+#define FORCE_FIELD_VORTEX 2
+#define FORCE_FIELD_ATTRACTOR 3
+
+// Needs to be kept in sync with MAX_NUM_FLUID_PHASES in hybrid_fluid.rs
+#define MAX_NUM_FLUID_PHASES 2
+
+// See ForceFieldUniformBufferContent in hybrid_fluid.rs. All in grid space.
+struct ForceField {
+    vec3 Position;
+    uint Type;
+    vec3 Vector; // acceleration for wind, (normalized) axis for vortex
+    float Strength;
+    float Radius; // zero means no falloff
+    float _Padding0;
+    float _Padding1;
+    float _Padding2;
+};
+
 layout(set = 1, binding = 0) uniform SimulationProperties {
     vec3 GravityGridSpace;
     uint NumParticles;
+
+    // User interaction (see InteractionForce in hybrid_fluid.rs), only valid if the interaction pass is executed.
+    vec3 InteractionRayOrigin;
+    float InteractionRadius;
+    vec3 InteractionRayDirection;
+    uint NumForceFields;
+    vec3 InteractionVelocityChange;
+    float SecondaryPhaseRelativeDensity; // Density of fluid phase 1 divided by the density of phase 0.
+    float VorticityConfinement;          // Strength of the vorticity confinement force, zero means off.
+    float SurfaceTension;                // Surface tension coefficient divided by the primary phase's density, zero means off.
+    uint MaxNumParticles;                // Capacity of the particle buffers.
+    uint ParticlesPerCell;               // Number of particles in a cell at rest density.
+    uint PeriodicAxes;                   // Bit per axis that wraps around, see periodic.glsl
+    float MaxParticleAge;                // Particles older than this (in seconds) are removed, zero means no limit.
+    uint _Padding0;
+    uint _Padding1;
+    vec3 EraseBoxMin;                    // Box in which the interaction pass removes all particles (see remove_fluid_in_box in hybrid_fluid.rs)
+    float _Padding2;
+    vec3 EraseBoxMax;
+    float _Padding3;
+
+    ForceField ForceFields[MAX_NUM_FORCE_FIELDS];
 };
 
 layout(set = 1, binding = 1) uniform texture3D SceneVoxelization;
 
+// Sum of accelerations of all force fields at a given grid position.
+vec3 forceFieldAcceleration(vec3 gridPosition) {
+    vec3 acceleration = vec3(0.0);
+    for (uint i = 0; i < NumForceFields; ++i) {
+        ForceField field = ForceFields[i];
+        if (field.Type == FORCE_FIELD_WIND) {
+            acceleration += field.Vector;
+            continue;
+        }
+
+        vec3 toCenter = field.Position - gridPosition;
+        if (field.Type == FORCE_FIELD_VORTEX)
+            toCenter -= dot(toCenter, field.Vector) * field.Vector; // distance to the axis, not the center.
+        float dist = length(toCenter);
+        if (dist < 1e-4)
+            continue;
+        float falloff = field.Radius > 0.0 ? clamp(1.0 - dist / field.Radius, 0.0, 1.0) : 1.0;
+
+        if (field.Type == FORCE_FIELD_VORTEX)
+            acceleration += cross(field.Vector, -toCenter / dist) * (field.Strength * falloff);
+        else if (field.Type == FORCE_FIELD_ATTRACTOR)
+            acceleration += toCenter / dist * (field.Strength * falloff);
+    }
+    return acceleration;
+}
+
 #ifdef DEBUG
 layout(set = 1, binding = 2, r32f) uniform restrict image3D DebugVolume;
 #endif
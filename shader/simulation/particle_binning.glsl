@@ -3,4 +3,26 @@
 layout(set = 2, binding = 0) buffer restrict Old_ParticlePositionLlBuffer { ParticlePositionLl Old_Particles[]; };
 layout(set = 2, binding = 1) buffer restrict New_ParticlePositionLlBuffer { ParticlePositionLl New_Particles[]; };
 layout(set = 2, binding = 2, r32ui) uniform restrict uimage3D ParticleBinningVolume;
-layout(set = 2, binding = 3) buffer restrict ParticleBinningAtomicCounter_ { uint ParticleBinningAtomicCounter; };
+layout(set = 2, binding = 3) buffer restrict ParticleBinningAtomicCounter_ {
+    uint ParticleBinningAtomicCounter;
+    uint ParticleResamplingAddedCounter; // Number of particles added by resampling, to stay within MaxNumParticles.
+};
+layout(set = 2, binding = 4) buffer restrict readonly Old_ParticlePhaseBuffer { uint Old_ParticlePhases[]; };
+layout(set = 2, binding = 5) buffer restrict New_ParticlePhaseBuffer { uint New_ParticlePhases[]; };
+// Number of particles per cell before resampling in the lower 16 bit, after resampling in the upper 16 bit. See particle_resample_plan.comp
+// Written by every counting sort binning, also read by the volume visualization.
+layout(set = 2, binding = 6, r32ui) uniform restrict uimage3D ParticleResamplingVolume;
+// Key/value buffers of the radix sort used by ParticleBinningMethod::RadixSort (see radix_sort.rs)
+layout(set = 2, binding = 7) buffer restrict SortKeys_ { uint SortKeys[]; };
+layout(set = 2, binding = 8) buffer restrict SortValues_ { uint SortValues[]; };
+layout(set = 2, binding = 9) buffer restrict readonly Old_ParticleAgeBuffer { float Old_ParticleAges[]; };
+layout(set = 2, binding = 10) buffer restrict New_ParticleAgeBuffer { float New_ParticleAges[]; };
+layout(set = 2, binding = 11) buffer restrict readonly Old_ParticleDyeBuffer { uint Old_ParticleDyes[]; };
+layout(set = 2, binding = 12) buffer restrict New_ParticleDyeBuffer { uint New_ParticleDyes[]; };
+
+layout(PUSH_CONSTANT_LAYOUT) uniform PushConstants { uint ResamplingEnabled; };
+
+// Resampling keeps particles per cell within these bounds.
+#define TARGET_PARTICLES_PER_CELL ParticlesPerCell
+#define MIN_PARTICLES_PER_CELL (TARGET_PARTICLES_PER_CELL / 2)
+#define MAX_PARTICLES_PER_CELL (TARGET_PARTICLES_PER_CELL * 2)
@@ -2,6 +2,11 @@
 // Otherwise this is the value for an invalid linked list ptr.
 #define INVALID_LINKED_LIST_PTR 0xFFFFFFFF
 
+// Dead particles (e.g. erased by fluid interaction) are skipped by all passes and compacted away by the next particle binning.
+// Live particles are never at negative grid positions.
+#define DEAD_PARTICLE_POSITION vec3(-1.0)
+bool isParticleDead(vec3 position) { return position.x < 0.0; }
+
 struct ParticlePositionLl {
     // Particle positions are in grid space to simplify shader computation
     // (no scaling/translation needed until we're rendering or interacting with other objects!)
@@ -0,0 +1,28 @@
+#ifndef INCLUDE_PERIODIC
+#define INCLUDE_PERIODIC
+
+// Periodic domain (see FluidConfig::periodic in scene/mod.rs)
+// Along periodic axes the outermost cells aren't solid, instead everything leaving the domain on one side enters it on the other.
+// Expects global_bindings.glsl and simulation/hybrid_fluid.glsl to be included already.
+
+bvec3 periodicAxesMask() { return bvec3((PeriodicAxes & 1) != 0, (PeriodicAxes & 2) != 0, (PeriodicAxes & 4) != 0); }
+
+ivec3 wrapGridCoord(ivec3 gridCoord) { return wrapGridCoord(gridCoord, ivec3(Rendering.FluidGridResolution), PeriodicAxes); }
+
+vec3 wrapGridPosition(vec3 gridPosition) {
+    return mix(gridPosition, mod(gridPosition, vec3(Rendering.FluidGridResolution)), periodicAxesMask());
+}
+
+// Periodic image of a position that is closest to a reference position.
+// Used where particles of a wrapped cell are compared with positions on the other side of the domain.
+vec3 periodicImageNear(vec3 gridPosition, vec3 referencePosition) {
+    vec3 resolution = vec3(Rendering.FluidGridResolution);
+    vec3 shifted = gridPosition + resolution * round((referencePosition - gridPosition) / resolution);
+    return mix(gridPosition, shifted, periodicAxesMask());
+}
+
+// Range particles are kept in. Away from the (solid) outermost cells on regular axes, unrestricted on periodic ones (they are wrapped instead).
+vec3 particlePositionMin() { return mix(vec3(1.001), vec3(-1e20), periodicAxesMask()); }
+vec3 particlePositionMax() { return mix(vec3(Rendering.FluidGridResolution) - vec3(1.001), vec3(1e20), periodicAxesMask()); }
+
+#endif // INCLUDE_PERIODIC
@@ -3,7 +3,7 @@
 #include "simulation/hybrid_fluid.glsl"
 #include "utilities.glsl"
 
-#define COMPUTE_PASS_PRESSURE layout(local_size_x = 8, local_size_y = 8, local_size_z = 1) in;
+#define COMPUTE_PASS_PRESSURE layout(local_size_x = LOCAL_SIZE_PRESSURE_X, local_size_y = LOCAL_SIZE_PRESSURE_Y, local_size_z = 1) in;
 
 // Properties fo the reduce pass.
 #define LOCAL_SIZE_REDUCE 1024
@@ -11,10 +11,13 @@
 #define REDUCE_READS_PER_THREAD 16
 
 layout(set = 0, binding = 0) uniform texture3D MarkerVolume;
+// Fluid density relative to the primary phase, see transfer_set_boundary_marker.comp
+layout(set = 0, binding = 1) uniform texture3D DensityVolume;
 layout(set = 1, binding = 0, r32f) uniform restrict image3D Pressure;
 layout(set = 1, binding = 1) uniform Config {
     float ErrorTolerance;
     uint MaxNumSolverIterations;
+    uint PeriodicAxes; // See wrapGridCoord in hybrid_fluid.glsl
 };
 
 struct PcgScalars {
@@ -23,53 +26,74 @@ struct PcgScalars {
     vec2 _Dummy;
 };
 
-layout(push_constant) uniform PushConstants_ {
+layout(PUSH_CONSTANT_LAYOUT) uniform PushConstants_ {
     uint Mode;             // Used to make adjustments to the shader invocation (which don't justify another shader instance)
     uint SourceBufferSize; // The size of the source buffer
 }
 PushConstants;
 
+// Coefficient for the face between a fluid cell and one of its neighbors, i.e. the inverse density at the face.
+// Air has no density, but since its pressure is zero only the fluid side matters. Solid faces don't contribute at all.
+// (for a single fluid phase this is equivalent to abs(neighborMarker))
+float FaceCoefficient(float centerDensity, ivec3 neighborGridCoord, float neighborMarker) {
+    if (neighborMarker == CELL_FLUID)
+        return 2.0 / (centerDensity + texelFetch(DensityVolume, neighborGridCoord, 0).x);
+    return abs(neighborMarker) / centerDensity;
+}
+
+// Direct neighbors of a cell in the order -x, +x, -y, +y, -z, +z, wrapped around on periodic axes.
+void NeighborGridCoords(ivec3 gridCoord, out ivec3 neighbors[6]) {
+    ivec3 gridResolution = textureSize(MarkerVolume, 0);
+    neighbors[0] = wrapGridCoord(gridCoord - ivec3(1, 0, 0), gridResolution, PeriodicAxes);
+    neighbors[1] = wrapGridCoord(gridCoord + ivec3(1, 0, 0), gridResolution, PeriodicAxes);
+    neighbors[2] = wrapGridCoord(gridCoord - ivec3(0, 1, 0), gridResolution, PeriodicAxes);
+    neighbors[3] = wrapGridCoord(gridCoord + ivec3(0, 1, 0), gridResolution, PeriodicAxes);
+    neighbors[4] = wrapGridCoord(gridCoord - ivec3(0, 0, 1), gridResolution, PeriodicAxes);
+    neighbors[5] = wrapGridCoord(gridCoord + ivec3(0, 0, 1), gridResolution, PeriodicAxes);
+}
+
 // Result of multiplication with coefficient matrix with a texture at gridCoord.
 // Only call if gridCoord is a fluid position!
 float MultiplyWithCoefficientMatrix(ivec3 gridCoord, texture3D texture, float valueAtGridCoord) {
     float result = 0.0;
-    float markerX0 = texelFetch(MarkerVolume, gridCoord - ivec3(1, 0, 0), 0).x;
-    float markerX1 = texelFetch(MarkerVolume, gridCoord + ivec3(1, 0, 0), 0).x;
-    float markerY0 = texelFetch(MarkerVolume, gridCoord - ivec3(0, 1, 0), 0).x;
-    float markerY1 = texelFetch(MarkerVolume, gridCoord + ivec3(0, 1, 0), 0).x;
-    float markerZ0 = texelFetch(MarkerVolume, gridCoord - ivec3(0, 0, 1), 0).x;
-    float markerZ1 = texelFetch(MarkerVolume, gridCoord + ivec3(0, 0, 1), 0).x;
+    ivec3 neighbors[6];
+    NeighborGridCoords(gridCoord, neighbors);
+    float markerX0 = texelFetch(MarkerVolume, neighbors[0], 0).x;
+    float markerX1 = texelFetch(MarkerVolume, neighbors[1], 0).x;
+    float markerY0 = texelFetch(MarkerVolume, neighbors[2], 0).x;
+    float markerY1 = texelFetch(MarkerVolume, neighbors[3], 0).x;
+    float markerZ0 = texelFetch(MarkerVolume, neighbors[4], 0).x;
+    float markerZ1 = texelFetch(MarkerVolume, neighbors[5], 0).x;
 
-    // This is the diagonal value of matrix A!
-    float numNonSolidNeighbors = 0.0;
-    numNonSolidNeighbors += abs(markerX0); // float(markerX0 != CELL_SOLID);
-    numNonSolidNeighbors += abs(markerX1); // float(markerX1 != CELL_SOLID);
-    numNonSolidNeighbors += abs(markerY0); // float(markerY0 != CELL_SOLID);
-    numNonSolidNeighbors += abs(markerY1); // float(markerY1 != CELL_SOLID);
-    numNonSolidNeighbors += abs(markerZ0); // float(markerZ0 != CELL_SOLID);
-    numNonSolidNeighbors += abs(markerZ1); // float(markerZ1 != CELL_SOLID);
+    float centerDensity = texelFetch(DensityVolume, gridCoord, 0).x;
+    float coeffX0 = FaceCoefficient(centerDensity, neighbors[0], markerX0);
+    float coeffX1 = FaceCoefficient(centerDensity, neighbors[1], markerX1);
+    float coeffY0 = FaceCoefficient(centerDensity, neighbors[2], markerY0);
+    float coeffY1 = FaceCoefficient(centerDensity, neighbors[3], markerY1);
+    float coeffZ0 = FaceCoefficient(centerDensity, neighbors[4], markerZ0);
+    float coeffZ1 = FaceCoefficient(centerDensity, neighbors[5], markerZ1);
 
-    // apply diagonal of A
-    result += numNonSolidNeighbors * valueAtGridCoord;
+    // apply diagonal of A (sum of all face coefficients)
+    result += (coeffX0 + coeffX1 + coeffY0 + coeffY1 + coeffZ0 + coeffZ1) * valueAtGridCoord;
 
     // apply other coefficients of A
     if (markerX0 == CELL_FLUID) {
-        result -= texelFetch(texture, gridCoord - ivec3(1, 0, 0), 0).x;
+        result -= coeffX0 * texelFetch(texture, neighbors[0], 0).x;
     }
     if (markerX1 == CELL_FLUID) {
-        result -= texelFetch(texture, gridCoord + ivec3(1, 0, 0), 0).x;
+        result -= coeffX1 * texelFetch(texture, neighbors[1], 0).x;
     }
     if (markerY0 == CELL_FLUID) {
-        result -= texelFetch(texture, gridCoord - ivec3(0, 1, 0), 0).x;
+        result -= coeffY0 * texelFetch(texture, neighbors[2], 0).x;
     }
     if (markerY1 == CELL_FLUID) {
-        result -= texelFetch(texture, gridCoord + ivec3(0, 1, 0), 0).x;
+        result -= coeffY1 * texelFetch(texture, neighbors[3], 0).x;
     }
     if (markerZ0 == CELL_FLUID) {
-        result -= texelFetch(texture, gridCoord - ivec3(0, 0, 1), 0).x;
+        result -= coeffZ0 * texelFetch(texture, neighbors[4], 0).x;
     }
     if (markerZ1 == CELL_FLUID) {
-        result -= texelFetch(texture, gridCoord + ivec3(0, 0, 1), 0).x;
+        result -= coeffZ1 * texelFetch(texture, neighbors[5], 0).x;
     }
     return result;
 }
@@ -0,0 +1,24 @@
+// Least significant digit radix sort of key/value pairs, see radix_sort.rs
+// Each pass sorts by RADIX_SORT_BITS_PER_PASS bits of the key, starting at Shift:
+// * radix_sort_histogram.comp counts digits per workgroup
+// * radix_sort_scan.comp turns the counts into output offsets per digit & workgroup
+// * radix_sort_scatter.comp sorts each workgroup's keys locally and writes them to their final location of this pass
+
+#define RADIX_SORT_BITS_PER_PASS 4
+#define RADIX_SORT_NUM_DIGITS (1 << RADIX_SORT_BITS_PER_PASS)
+// Needs to be kept in sync with RadixSort::LOCAL_SIZE in radix_sort.rs
+#define LOCAL_SIZE_RADIX_SORT 256
+
+layout(set = 0, binding = 0) buffer restrict readonly KeysIn_ { uint KeysIn[]; };
+layout(set = 0, binding = 1) buffer restrict readonly ValuesIn_ { uint ValuesIn[]; };
+layout(set = 0, binding = 2) buffer restrict writeonly KeysOut_ { uint KeysOut[]; };
+layout(set = 0, binding = 3) buffer restrict writeonly ValuesOut_ { uint ValuesOut[]; };
+// Digit counts of all workgroups, digit major. An exclusive prefix sum over it yields the output offset of each digit in each workgroup.
+layout(set = 0, binding = 4) buffer restrict Histogram_ { uint Histogram[]; };
+
+layout(PUSH_CONSTANT_LAYOUT) uniform PushConstants {
+    uint Shift;
+    uint NumWorkgroups;
+};
+
+uint extractDigit(uint key) { return (key >> Shift) & (RADIX_SORT_NUM_DIGITS - 1); }
@@ -0,0 +1,74 @@
+// Common bindings & utilities of the grid based smoke simulation (see smoke.rs)
+
+#define NO_SIMPROPS
+#include "global_bindings.glsl"
+#include "simulation/hybrid_fluid.glsl"
+#include "utilities.glsl"
+
+// Needs to be kept in sync with MAX_NUM_SMOKE_EMITTERS in smoke.rs
+#define MAX_NUM_SMOKE_EMITTERS 4
+
+// Box that continuously emits smoke. All in grid space.
+struct SmokeEmitter {
+    vec3 Min;
+    float _Padding0;
+    vec3 Max;
+    float _Padding1;
+    vec3 Velocity;
+    float _Padding2;
+};
+
+layout(set = 1, binding = 0) uniform SmokeProperties {
+    vec3 GravityGridSpace;
+    uint NumEmitters;
+
+    float Buoyancy;      // Upward acceleration per degree above ambient temperature, relative to gravity.
+    float DensityWeight; // Downward acceleration per unit of smoke density, relative to gravity.
+    float AmbientTemperature;
+    float VorticityConfinement;
+
+    float SourceDensity;
+    float SourceTemperature;
+    float Dissipation; // Fraction of density & temperature difference lost per second.
+    float _Padding;
+
+    SmokeEmitter Emitters[MAX_NUM_SMOKE_EMITTERS];
+};
+
+layout(set = 1, binding = 1) uniform texture3D SceneVoxelization;
+
+// Trilinear interpolation of a cell centered volume at a grid position (i.e. cell centers are at +0.5).
+// Clamps to the border of the volume.
+vec4 sampleTrilinear(texture3D volume, vec3 gridPosition) {
+    ivec3 maxCoord = textureSize(volume, 0) - ivec3(1);
+    vec3 position = gridPosition - vec3(0.5);
+    ivec3 coord0 = ivec3(floor(position));
+    vec3 interpolants = saturate(position - vec3(coord0));
+    ivec3 coord1 = clamp(coord0 + ivec3(1), ivec3(0), maxCoord);
+    coord0 = clamp(coord0, ivec3(0), maxCoord);
+
+    vec4 v000 = texelFetch(volume, coord0, 0);
+    vec4 v100 = texelFetch(volume, ivec3(coord1.x, coord0.y, coord0.z), 0);
+    vec4 v010 = texelFetch(volume, ivec3(coord0.x, coord1.y, coord0.z), 0);
+    vec4 v110 = texelFetch(volume, ivec3(coord1.x, coord1.y, coord0.z), 0);
+    vec4 v001 = texelFetch(volume, ivec3(coord0.x, coord0.y, coord1.z), 0);
+    vec4 v101 = texelFetch(volume, ivec3(coord1.x, coord0.y, coord1.z), 0);
+    vec4 v011 = texelFetch(volume, ivec3(coord0.x, coord1.y, coord1.z), 0);
+    vec4 v111 = texelFetch(volume, coord1, 0);
+
+    return mix(mix(mix(v000, v100, interpolants.x), mix(v010, v110, interpolants.x), interpolants.y),
+               mix(mix(v001, v101, interpolants.x), mix(v011, v111, interpolants.x), interpolants.y), interpolants.z);
+}
+
+// Samples a staggered velocity grid (velocity components on the positive walls of each cell) at a grid position.
+vec3 sampleStaggeredVelocity(texture3D velocityX, texture3D velocityY, texture3D velocityZ, vec3 gridPosition) {
+    return vec3(sampleTrilinear(velocityX, gridPosition - vec3(0.5, 0.0, 0.0)).x, sampleTrilinear(velocityY, gridPosition - vec3(0.0, 0.5, 0.0)).x,
+                sampleTrilinear(velocityZ, gridPosition - vec3(0.0, 0.0, 0.5)).x);
+}
+
+// Velocity at the center of a cell of a staggered velocity grid.
+vec3 cellCenterVelocity(texture3D velocityX, texture3D velocityY, texture3D velocityZ, ivec3 gridCoord) {
+    return 0.5 * vec3(texelFetch(velocityX, gridCoord, 0).x + texelFetch(velocityX, gridCoord - ivec3(1, 0, 0), 0).x,
+                      texelFetch(velocityY, gridCoord, 0).x + texelFetch(velocityY, gridCoord - ivec3(0, 1, 0), 0).x,
+                      texelFetch(velocityZ, gridCoord, 0).x + texelFetch(velocityZ, gridCoord - ivec3(0, 0, 1), 0).x);
+}
@@ -0,0 +1,7 @@
+#include "hybrid_fluid.glsl"
+
+layout(set = 2, binding = 0) uniform texture3D MarkerVolume;
+// Smoothed fluid indicator, 1 inside the fluid, 0 in air.
+layout(set = 2, binding = 1, r32f) uniform restrict image3D ColorFieldVolume;
+// Mean curvature of the color field's isosurfaces, in 1/cells.
+layout(set = 2, binding = 2, r32f) uniform restrict image3D CurvatureVolume;
@@ -7,5 +7,15 @@ layout(set = 2, binding = 1) buffer restrict readonly ParticleComp { vec4 Partic
 layout(set = 2, binding = 2, r32ui) uniform restrict uimage3D LinkedListDualGrid;
 layout(set = 2, binding = 3, r8_snorm) uniform restrict image3D MarkerVolume;
 layout(set = 2, binding = 4, r32f) uniform restrict image3D VelocityComponentVolume;
+layout(set = 2, binding = 5) buffer restrict readonly ParticlePhaseBuffer { uint ParticlePhases[]; };
+// Number of particles per cell in the lower 16 bit, number of secondary phase particles in the upper 16 bit.
+layout(set = 2, binding = 6, r32ui) uniform restrict uimage3D PhaseCountVolume;
+// Fluid density per cell, relative to the density of the primary phase.
+layout(set = 2, binding = 7, r32f) uniform restrict image3D DensityVolume;
+// Curl of the velocity field at the end of the previous step, see vorticity_compute.comp
+layout(set = 2, binding = 8) uniform texture3D CurlVolume;
+// See surface_tension_bindings.glsl
+layout(set = 2, binding = 9) uniform texture3D ColorFieldVolume;
+layout(set = 2, binding = 10) uniform texture3D CurvatureVolume;
 
-layout(push_constant) uniform PushConstants { uint VelocityTransferComponent; };
+layout(PUSH_CONSTANT_LAYOUT) uniform PushConstants { uint VelocityTransferComponent; };
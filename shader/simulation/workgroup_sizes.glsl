@@ -0,0 +1,14 @@
+// Local sizes of the main simulation passes.
+// Usually injected as defines (see workgroup_sizes.rs) since they may be autotuned per adapter, these are the defaults.
+#ifndef LOCAL_SIZE_PARTICLES
+#define LOCAL_SIZE_PARTICLES 64
+#endif
+#ifndef LOCAL_SIZE_VOLUME_X
+#define LOCAL_SIZE_VOLUME_X 8
+#define LOCAL_SIZE_VOLUME_Y 8
+#define LOCAL_SIZE_VOLUME_Z 8
+#endif
+#ifndef LOCAL_SIZE_PRESSURE_X
+#define LOCAL_SIZE_PRESSURE_X 8
+#define LOCAL_SIZE_PRESSURE_Y 8
+#endif
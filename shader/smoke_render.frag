@@ -0,0 +1,43 @@
+// Raymarches the smoke volume of the smoke simulation (see smoke_renderer.rs)
+// Smoke is lit by a constant ambient light, no shadowing.
+
+#version 450
+
+#include "global_bindings.glsl"
+#include "utilities.glsl"
+
+layout(set = 1, binding = 0) uniform texture3D SmokeVolume;
+layout(set = 1, binding = 1) uniform texture2D SceneDepth;
+
+layout(location = 0) out vec4 out_Color;
+
+#define STEPS_PER_CELL 2.0
+// Extinction per world space unit at smoke density 1.
+#define SMOKE_EXTINCTION 40.0
+#define SMOKE_AMBIENT_LIGHT vec3(0.6)
+
+void main() {
+    vec2 screenUv = gl_FragCoord.xy * Screen.ResolutionInv;
+    vec3 rayDirection = normalize(reconstructWorldPositionFromViewSpaceDepth(screenUv, 1.0) - Camera.Position);
+
+    // Limit to the fluid domain and whatever opaque is in front of it.
+    float sceneDepth = viewSpaceDepthFromDepthbuffer(texelFetch(SceneDepth, ivec2(gl_FragCoord.xy), 0).r);
+    vec3 invRayDirection = 1.0 / rayDirection;
+    vec3 domainMin = (Rendering.FluidWorldMin - Camera.Position) * invRayDirection;
+    vec3 domainMax = (Rendering.FluidWorldMax - Camera.Position) * invRayDirection;
+    float rayStart = max(max3(min(domainMin, domainMax)), 0.0);
+    float rayEnd = min(min3(max(domainMin, domainMax)), sceneDepth / dot(rayDirection, Camera.Direction));
+    if (rayStart >= rayEnd)
+        discard;
+
+    float stepSize = Rendering.FluidGridToWorldScale / STEPS_PER_CELL;
+    float transmittance = 1.0;
+    for (float t = rayStart + stepSize * 0.5; t < rayEnd && transmittance > 0.01; t += stepSize) {
+        vec3 texcoord = (Camera.Position + t * rayDirection - Rendering.FluidWorldMin) / (Rendering.FluidWorldMax - Rendering.FluidWorldMin);
+        float density = texture(sampler3D(SmokeVolume, SamplerTrilinearClamp), texcoord).x;
+        transmittance *= exp(-max(density, 0.0) * SMOKE_EXTINCTION * min(stepSize, rayEnd - t));
+    }
+
+    float opacity = 1.0 - transmittance;
+    out_Color = vec4(SMOKE_AMBIENT_LIGHT * opacity, opacity);
+}
@@ -1,4 +1,5 @@
 #include "global_bindings.glsl"
+#include "fluid_symmetry.glsl"
 #include "utilities.glsl"
 
 const vec2 quadPositions[4] = vec2[4](vec2(-1.0, -1.0), vec2(-1.0, 1.0), vec2(1.0, -1.0), vec2(1.0, 1.0));
@@ -0,0 +1,27 @@
+#version 450
+
+#include "utilities.glsl"
+
+layout(set = 0, binding = 0) uniform texture2D HdrBackbuffer;
+layout(set = 1, binding = 0) uniform texture3D ColorGradingLut;
+layout(set = 1, binding = 1) uniform sampler SamplerLinear;
+
+layout(PUSH_CONSTANT_LAYOUT) uniform PushConstants { uvec2 TargetSize; };
+
+layout(location = 0) out vec4 out_Color;
+
+void main() {
+    // The hdr backbuffer may be rendered at a different resolution (render scale), at equal size this hits texel centers exactly.
+    vec4 hdrColor = textureLod(sampler2D(HdrBackbuffer, SamplerLinear), gl_FragCoord.xy / vec2(TargetSize), 0.0);
+
+    // TODO: Actual tonemapping. For now we just clip.
+    vec3 color = saturate(hdrColor.rgb);
+
+    // Color grading. LUTs operate on display values, so we need to go to srgb and back since our target is srgb.
+    float lutSize = textureSize(ColorGradingLut, 0).x;
+    vec3 lutCoord = linearToSrgb(color) * ((lutSize - 1.0) / lutSize) + 0.5 / lutSize;
+    color = srgbToLinear(texture(sampler3D(ColorGradingLut, SamplerLinear), lutCoord).rgb);
+
+    // Linear output, the target is an *Srgb format that encodes on write.
+    out_Color = vec4(color, hdrColor.a);
+}
@@ -25,6 +25,12 @@ vec2 sq(vec2 a) { return a * a; }
 vec3 sq(vec3 a) { return a * a; }
 vec4 sq(vec4 a) { return a * a; }
 
+// Exact sRGB transfer functions, for when the conversion can't be left to an *Srgb texture format.
+vec3 linearToSrgb(vec3 linear) {
+    return mix(linear * 12.92, 1.055 * pow(linear, vec3(1.0 / 2.4)) - 0.055, step(vec3(0.0031308), linear));
+}
+vec3 srgbToLinear(vec3 srgb) { return mix(srgb / 12.92, pow((srgb + 0.055) / 1.055, vec3(2.4)), step(vec3(0.04045), srgb)); }
+
 // t = [0; 1]
 vec3 colormapHeat(float t) { return saturate(vec3(t * 3, t * 3 - 1, t * 3 - 2)); }
 // t = [-1; 1]
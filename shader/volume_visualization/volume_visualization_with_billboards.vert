@@ -15,13 +15,18 @@ layout(location = 1) out vec3 out_ParticleWorldPosition;
 layout(location = 2) out vec3 out_Tint;
 layout(location = 3) out float out_Radius;
 
-layout(push_constant) uniform PushConstants { uint VisualizationType; };
+layout(PUSH_CONSTANT_LAYOUT) uniform PushConstants {
+    uint VisualizationType;
+    uint TargetParticlesPerCell;
+};
 
 #define VISUALIZE_DIVERGENCE 0
 #define VISUALIZE_PRESSURE_VELOCITY 1
 #define VISUALIZE_PRESSURE_DENSITY 2
 #define VISUALIZE_MARKER 3
 #define VISUALIZE_DEBUG 4
+#define VISUALIZE_MARKER_CHANGES 5
+#define VISUALIZE_PARTICLES_PER_CELL 6
 
 float computeDivergenceForDirection(ivec3 coord, texture3D velocityVolume, float oppositeWallType, const uint component) {
     ivec3 neighborCoord = coord;
@@ -77,6 +82,51 @@ void main() {
             out_Tint = vec3(0.0, 0.0, 1.0);
         break;
 
+    // Distinguishes domain border from solid scene objects and highlights everything that changed its type since the last step.
+    case VISUALIZE_MARKER_CHANGES:
+        float previousMarker = texelFetch(MarkerVolumePrevious, volumeCoordinate, 0).x;
+        bool isSceneSolid = texelFetch(SceneVoxelization, volumeCoordinate, 0).w != 0.0;
+
+        if (marker != previousMarker) {
+            scale = 1.0;
+            if (marker == CELL_SOLID)
+                out_Tint = vec3(1.0, 0.0, 0.0); // became solid
+            else if (previousMarker == CELL_SOLID)
+                out_Tint = vec3(1.0, 1.0, 0.0); // was solid
+            else
+                out_Tint = vec3(0.0, 1.0, 0.0); // fluid <-> air
+        } else {
+            scale = 0.4;
+            if (marker == CELL_SOLID)
+                out_Tint = isSceneSolid ? vec3(0.5) : vec3(0.0); // scene object or domain border
+            else if (marker == CELL_FLUID)
+                out_Tint = vec3(0.0, 0.0, 1.0);
+            else
+                scale = 0.0;
+        }
+        break;
+
+    // Heatmap of particles per cell relative to the resampling bounds (see particle_binning.glsl).
+    // Cells outside of the bounds are drawn at full size, these are where resampling kicks in.
+    case VISUALIZE_PARTICLES_PER_CELL:
+        uint particleCount = texelFetch(ParticleCountVolume, volumeCoordinate, 0).x & 0xFFFF;
+        uint minParticleCount = TargetParticlesPerCell / 2;
+        uint maxParticleCount = TargetParticlesPerCell * 2;
+
+        if (particleCount == 0) {
+            scale = 0.0;
+        } else if (particleCount < minParticleCount) {
+            scale = 1.0;
+            out_Tint = vec3(0.0, 0.5, 1.0); // too sparse
+        } else if (particleCount > maxParticleCount) {
+            scale = 1.0;
+            out_Tint = vec3(1.0, 0.0, 1.0); // over-crowded
+        } else {
+            scale = 0.5;
+            out_Tint = colormapHeat(float(particleCount) / float(maxParticleCount));
+        }
+        break;
+
 #ifdef DEBUG
     case VISUALIZE_DEBUG:
         float debugValue = texelFetch(DebugVolume, volumeCoordinate, 0).x;
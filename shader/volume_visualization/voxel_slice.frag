@@ -0,0 +1,35 @@
+#version 460
+
+#include "../global_bindings.glsl"
+
+layout(set = 1, binding = 0) uniform texture3D SceneVoxelization;
+
+layout(PUSH_CONSTANT_LAYOUT) uniform PushConstants_ {
+    uint SliceAxis; // slice plane is orthogonal to this axis
+    uint SliceIndex;
+};
+
+layout(location = 0) in vec2 in_Texcoord;
+layout(location = 0) out vec4 out_Color;
+
+void main() {
+    ivec3 gridResolution = textureSize(SceneVoxelization, 0);
+    // Remaining two axes in order, e.g. slicing along y shows x/z.
+    ivec2 planeAxes = SliceAxis == 0 ? ivec2(1, 2) : (SliceAxis == 1 ? ivec2(0, 2) : ivec2(0, 1));
+    ivec2 planeResolution = ivec2(gridResolution[planeAxes.x], gridResolution[planeAxes.y]);
+    ivec2 planeCoordinate = min(ivec2(in_Texcoord * planeResolution), planeResolution - 1);
+
+    ivec3 volumeCoordinate;
+    volumeCoordinate[SliceAxis] = int(SliceIndex);
+    volumeCoordinate[planeAxes.x] = planeCoordinate.x;
+    volumeCoordinate[planeAxes.y] = planeCoordinate.y;
+    vec4 voxel = texelFetch(SceneVoxelization, volumeCoordinate, 0);
+
+    // Occupied cells are colored by speed, empty ones checkered so individual cells are distinguishable.
+    vec3 color;
+    if (voxel.w == 0.0)
+        color = vec3(((planeCoordinate.x + planeCoordinate.y) & 1) == 0 ? 0.02 : 0.04);
+    else
+        color = vec3(0.2) + abs(voxel.xyz) * Rendering.VelocityVisualizationScale;
+    out_Color = vec4(color, 1.0);
+}
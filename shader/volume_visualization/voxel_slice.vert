@@ -0,0 +1,13 @@
+#version 460
+
+// Fullscreen triangle with texcoords. Placed on screen via viewport.
+
+out gl_PerVertex { vec4 gl_Position; };
+layout(location = 0) out vec2 out_Texcoord;
+
+const vec2 triPositions[3] = vec2[3](vec2(-1.0, -3.0), vec2(-1.0, 1.0), vec2(3.0, 1.0));
+
+void main() {
+    gl_Position = vec4(triPositions[gl_VertexIndex], 0.0, 1.0);
+    out_Texcoord = triPositions[gl_VertexIndex] * vec2(0.5, -0.5) + 0.5;
+}
@@ -3,7 +3,7 @@
 #include "../global_bindings.glsl"
 #include "../utilities.glsl"
 
-layout(push_constant) uniform PushConstants_ { uint MeshIndex; };
+layout(PUSH_CONSTANT_LAYOUT) uniform PushConstants_ { uint MeshIndex; };
 
 layout(set = 1, binding = 0, rgba16f) uniform restrict writeonly image3D SceneVoxelization;
 
@@ -30,7 +30,8 @@ void main() {
     voxelPosSwizzled.xy = gl_FragCoord.xy;
     voxelPosSwizzled.z = gl_FragCoord.z * viewportSize;
     vec3 voxelPos = UnswizzlePosAndClamp(ivec3(voxelPosSwizzled));
-    imageStore(SceneVoxelization, ivec3(voxelPos), vec4(ComputeVoxelSpeed(voxelPos), 1.0));
+    float boundary = encodeSolidBoundary(Meshes[MeshIndex].BoundaryFriction, Meshes[MeshIndex].BoundaryRestitution);
+    imageStore(SceneVoxelization, ivec3(voxelPos), vec4(ComputeVoxelSpeed(voxelPos), boundary));
 
     // "Depth Conservative"
     // If there is a strong change in depth we need to mark extra more voxels
@@ -43,11 +44,11 @@ void main() {
 
     if (floor(voxelPosSwizzled.z) != floor(voxelPosSwizzled.z - maxChange)) {
         voxelPos = UnswizzlePosAndClamp(voxelPosSwizzled - vec3(0, 0, 1));
-        imageStore(SceneVoxelization, ivec3(voxelPos), vec4(ComputeVoxelSpeed(voxelPos), 1.0));
+        imageStore(SceneVoxelization, ivec3(voxelPos), vec4(ComputeVoxelSpeed(voxelPos), boundary));
     }
     if (floor(voxelPosSwizzled.z) != floor(voxelPosSwizzled.z + maxChange)) {
         voxelPos = UnswizzlePosAndClamp(voxelPosSwizzled + vec3(0, 0, 1));
-        imageStore(SceneVoxelization, ivec3(voxelPos), vec4(ComputeVoxelSpeed(voxelPos), 1.0));
+        imageStore(SceneVoxelization, ivec3(voxelPos), vec4(ComputeVoxelSpeed(voxelPos), boundary));
     }
 
     out_Dummy = 0.0;
@@ -3,7 +3,7 @@
 #include "../global_bindings.glsl"
 #include "../utilities.glsl"
 
-layout(push_constant) uniform PushConstants_ { uint MeshIndex; };
+layout(PUSH_CONSTANT_LAYOUT) uniform PushConstants_ { uint MeshIndex; };
 
 layout(location = 0) out flat uint out_SideIndex;
 
@@ -0,0 +1,262 @@
+use crate::{
+    global_bindings::GlobalBindings,
+    global_ubo::GlobalUBO,
+    scene::Scene,
+    simulation::{SimulationStage, SolverStatisticSample},
+    timer::Timer,
+    wgpu_utils::{fence_profiler::FenceProfiler, pipelines::PipelineManager, profiling::ProfilingDetail},
+};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+use wgpu_profiler::{GpuProfiler, GpuTimerScopeResult};
+
+pub struct BenchmarkConfig {
+    pub scenes: Vec<PathBuf>,
+    pub num_steps: u32,
+    pub simulation_steps_per_second: u64,
+    pub output: PathBuf, // without extension
+}
+
+impl Default for BenchmarkConfig {
+    fn default() -> Self {
+        BenchmarkConfig {
+            scenes: Vec::new(),
+            num_steps: 300,
+            simulation_steps_per_second: 120,
+            output: PathBuf::from("benchmark-report"),
+        }
+    }
+}
+
+#[derive(Serialize, Default)]
+pub struct SolverStatistics {
+    pub mean_iteration_count: f32,
+    pub max_iteration_count: i32,
+    pub mean_error: f32,
+}
+
+impl SolverStatistics {
+    fn from_samples<'a>(samples: impl Iterator<Item = &'a SolverStatisticSample>) -> Self {
+        let mut stats = SolverStatistics::default();
+        let mut num_samples = 0;
+        for sample in samples {
+            stats.mean_iteration_count += sample.iteration_count as f32;
+            stats.max_iteration_count = stats.max_iteration_count.max(sample.iteration_count);
+            stats.mean_error += sample.error;
+            num_samples += 1;
+        }
+        if num_samples > 0 {
+            stats.mean_iteration_count /= num_samples as f32;
+            stats.mean_error /= num_samples as f32;
+        }
+        stats
+    }
+}
+
+#[derive(Serialize)]
+pub struct SceneBenchmarkResult {
+    pub scene: PathBuf,
+    pub num_particles: u32,
+    pub num_steps: u32,
+    pub simulation_delta_ms: f64,
+    // Wall clock time of all steps, including waiting on the gpu after every step.
+    pub cpu_total_ms: f64,
+    pub cpu_mean_step_ms: f64,
+    // Mean gpu time per step of every profiler scope, nested scopes are joined with '/'.
+    pub gpu_mean_scope_ms: BTreeMap<String, f64>,
+    pub solver_velocity: SolverStatistics,
+    pub solver_density: SolverStatistics,
+}
+
+#[derive(Serialize)]
+struct BenchmarkReport<'a> {
+    start_time: String,
+    num_steps: u32,
+    simulation_steps_per_second: u64,
+    scenes: &'a [SceneBenchmarkResult],
+}
+
+fn accumulate_scopes(scopes: &[GpuTimerScopeResult], prefix: &str, sums: &mut BTreeMap<String, f64>) {
+    for scope in scopes {
+        let label = if prefix.is_empty() {
+            scope.label.clone()
+        } else {
+            format!("{}/{}", prefix, scope.label)
+        };
+        *sums.entry(label.clone()).or_default() += (scope.time.end - scope.time.start) * 1000.0;
+        accumulate_scopes(&scope.nested_scopes, &label, sums);
+    }
+}
+
+// Simulates a scene step by step and gathers timings.
+//
+// Waits for the gpu after every step, so cpu timings are stable but don't reflect the (pipelined) realtime mode.
+pub struct SceneBenchmark {
+    timer: Timer,
+    profiler: GpuProfiler,
+    fence_profiler: FenceProfiler,
+    gpu_scope_sums: BTreeMap<String, f64>,
+    num_profiled_steps: u32,
+    num_steps: u32,
+    cpu_total: Duration,
+}
+
+impl SceneBenchmark {
+    pub fn new(simulation_steps_per_second: u64, device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let simulation_delta = Duration::from_nanos(1000 * 1000 * 1000 / simulation_steps_per_second);
+
+        // Without timestamp queries, fall back to coarse per stage timings.
+        let timestamps_supported = device.features().contains(wgpu::Features::TIMESTAMP_QUERY);
+        let mut profiler = GpuProfiler::new(4, queue.get_timestamp_period());
+        profiler.enable_timer = timestamps_supported;
+        profiler.enable_debug_marker = timestamps_supported;
+        let mut fence_profiler = FenceProfiler::new(4);
+        fence_profiler.enabled = !timestamps_supported;
+
+        SceneBenchmark {
+            timer: Timer::new(simulation_delta),
+            profiler,
+            fence_profiler,
+            gpu_scope_sums: BTreeMap::new(),
+            num_profiled_steps: 0,
+            num_steps: 0,
+            cpu_total: Duration::from_secs(0),
+        }
+    }
+
+    pub fn timer(&self) -> &Timer {
+        &self.timer
+    }
+
+    pub fn step(
+        &mut self,
+        scene: &mut Scene,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pipeline_manager: &PipelineManager,
+        global_ubo: &mut GlobalUBO,
+        global_bindings: &GlobalBindings,
+    ) {
+        let start_time = Instant::now();
+        // Simulation shaders take the simulation delta from the global UBO. As in realtime mode, it's written before the step.
+        global_ubo.set_time(self.timer.fill_global_uniform_buffer());
+        global_ubo.write_changed_sections(queue);
+        self.timer.force_simulation_step();
+        scene.step(
+            &self.timer,
+            &SimulationStage::ALL,
+            device,
+            &mut self.profiler,
+            &mut self.fence_profiler,
+            ProfilingDetail::Fine,
+            pipeline_manager,
+            queue,
+            global_bindings.bind_group(global_ubo),
+        );
+        device.poll(wgpu::Maintain::Wait);
+        self.cpu_total += start_time.elapsed();
+        self.num_steps += 1;
+
+        while let Some(scopes) = self.profiler.process_finished_frame() {
+            accumulate_scopes(&scopes, "", &mut self.gpu_scope_sums);
+            self.num_profiled_steps += 1;
+        }
+        while let Some(scopes) = self.fence_profiler.process_finished_frame() {
+            accumulate_scopes(&scopes, "", &mut self.gpu_scope_sums);
+            self.num_profiled_steps += 1;
+        }
+    }
+
+    pub fn finish(mut self, scene: &Scene) -> SceneBenchmarkResult {
+        for sum in self.gpu_scope_sums.values_mut() {
+            *sum /= self.num_profiled_steps.max(1) as f64;
+        }
+
+        SceneBenchmarkResult {
+            scene: scene.path().to_path_buf(),
+            num_particles: scene.fluid().num_particles(),
+            num_steps: self.num_steps,
+            simulation_delta_ms: self.timer.simulation_delta().as_secs_f64() * 1000.0,
+            cpu_total_ms: self.cpu_total.as_secs_f64() * 1000.0,
+            cpu_mean_step_ms: self.cpu_total.as_secs_f64() * 1000.0 / self.num_steps.max(1) as f64,
+            gpu_mean_scope_ms: self.gpu_scope_sums,
+            solver_velocity: SolverStatistics::from_samples(scene.fluid().pressure_solver_stats_velocity().iter()),
+            solver_density: SolverStatistics::from_samples(scene.fluid().pressure_solver_stats_density().iter()),
+        }
+    }
+}
+
+// Simulates a freshly loaded scene for a fixed number of steps and gathers timings.
+pub fn run_scene(
+    config: &BenchmarkConfig,
+    scene: &mut Scene,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    pipeline_manager: &PipelineManager,
+    global_ubo: &mut GlobalUBO,
+    global_bindings: &GlobalBindings,
+) -> SceneBenchmarkResult {
+    info!("Benchmarking {:?} for {} steps", scene.path(), config.num_steps);
+    let mut benchmark = SceneBenchmark::new(config.simulation_steps_per_second, device, queue);
+    for _ in 0..config.num_steps {
+        benchmark.step(scene, device, queue, pipeline_manager, global_ubo, global_bindings);
+    }
+    benchmark.finish(scene)
+}
+
+fn write_csv(path: &Path, results: &[SceneBenchmarkResult]) -> std::io::Result<()> {
+    let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    writeln!(
+        file,
+        "scene,num_particles,num_steps,cpu_total_ms,cpu_mean_step_ms,scope,gpu_mean_scope_ms,velocity_mean_iterations,density_mean_iterations"
+    )?;
+    for result in results {
+        // One row per scope, so the file stays easy to filter & pivot.
+        for (scope, gpu_ms) in result.gpu_mean_scope_ms.iter() {
+            writeln!(
+                file,
+                "{:?},{},{},{:.3},{:.3},{:?},{:.4},{:.2},{:.2}",
+                result.scene.to_string_lossy(),
+                result.num_particles,
+                result.num_steps,
+                result.cpu_total_ms,
+                result.cpu_mean_step_ms,
+                scope,
+                gpu_ms,
+                result.solver_velocity.mean_iteration_count,
+                result.solver_density.mean_iteration_count,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+// Writes <output>.json and <output>.csv
+pub fn write_report(config: &BenchmarkConfig, results: &[SceneBenchmarkResult]) {
+    let report = BenchmarkReport {
+        start_time: chrono::Local::now().to_rfc3339(),
+        num_steps: config.num_steps,
+        simulation_steps_per_second: config.simulation_steps_per_second,
+        scenes: results,
+    };
+
+    let json_path = config.output.with_extension("json");
+    match std::fs::File::create(&json_path) {
+        Ok(file) => match serde_json::to_writer_pretty(file, &report) {
+            Ok(()) => info!("Wrote benchmark report to {:?}", json_path),
+            Err(err) => error!("Failed to write benchmark report {:?}: {}", json_path, err),
+        },
+        Err(err) => error!("Failed to create benchmark report {:?}: {}", json_path, err),
+    }
+
+    let csv_path = config.output.with_extension("csv");
+    match write_csv(&csv_path, results) {
+        Ok(()) => info!("Wrote benchmark report to {:?}", csv_path),
+        Err(err) => error!("Failed to write benchmark report {:?}: {}", csv_path, err),
+    }
+}
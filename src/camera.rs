@@ -2,6 +2,8 @@ use super::timer::Timer;
 use super::wgpu_utils::uniformbuffer::*;
 use cgmath::prelude::*;
 use enumflags2::{bitflags, BitFlags};
+use serde::Deserialize;
+use std::{collections::HashMap, path::Path};
 use winit::event::{DeviceEvent, ElementState, KeyboardInput, VirtualKeyCode, WindowEvent};
 
 #[cfg_attr(rustfmt, rustfmt_skip)]
@@ -14,15 +16,71 @@ const OPENGL_PROJECTION_TO_WGPU_PROJECTION: cgmath::Matrix4<f32> = cgmath::Matri
 
 const VERTICAL_FOV: cgmath::Deg<f32> = cgmath::Deg(80f32);
 
+const KEY_BINDINGS_FILE: &str = "camera_bindings.json";
+const GAMEPAD_DEADZONE: f32 = 0.15;
+
 #[bitflags]
 #[repr(u8)]
-#[derive(Copy, Clone, Debug, PartialEq)]
-enum MoveCommands {
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+pub enum MoveCommands {
     Left = 0b0001,
     Right = 0b0010,
     Forwards = 0b0100,
     Backwards = 0b1000,
     SpeedUp = 0b1_0000,
+    Up = 0b10_0000,
+    Down = 0b100_0000,
+}
+
+// Maps keys to camera movement.
+// Can be overwritten with a json file mapping winit key names to move commands, e.g. { "W": "Forwards", "Q": "Down" }
+#[derive(Deserialize)]
+#[serde(transparent)]
+pub struct KeyBindings(HashMap<VirtualKeyCode, MoveCommands>);
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings(
+            [
+                (VirtualKeyCode::W, MoveCommands::Forwards),
+                (VirtualKeyCode::Up, MoveCommands::Forwards),
+                (VirtualKeyCode::S, MoveCommands::Backwards),
+                (VirtualKeyCode::Down, MoveCommands::Backwards),
+                (VirtualKeyCode::A, MoveCommands::Left),
+                (VirtualKeyCode::Left, MoveCommands::Left),
+                (VirtualKeyCode::D, MoveCommands::Right),
+                (VirtualKeyCode::Right, MoveCommands::Right),
+                (VirtualKeyCode::E, MoveCommands::Up),
+                (VirtualKeyCode::Q, MoveCommands::Down),
+                (VirtualKeyCode::LShift, MoveCommands::SpeedUp),
+            ]
+            .iter()
+            .cloned()
+            .collect(),
+        )
+    }
+}
+
+impl KeyBindings {
+    fn load_or_default() -> Self {
+        let path = Path::new(KEY_BINDINGS_FILE);
+        if !path.exists() {
+            return Default::default();
+        }
+        match std::fs::File::open(path)
+            .map_err(|err| err.to_string())
+            .and_then(|file| serde_json::from_reader(std::io::BufReader::new(file)).map_err(|err| err.to_string()))
+        {
+            Ok(bindings) => {
+                info!("Loaded camera key bindings from {:?}", path);
+                bindings
+            }
+            Err(err) => {
+                error!("Failed to load camera key bindings from {:?}, using defaults: {}", path, err);
+                Default::default()
+            }
+        }
+    }
 }
 
 pub struct Camera {
@@ -33,9 +91,18 @@ pub struct Camera {
     movement_locked: bool,
     active_move_commands: BitFlags<MoveCommands>,
     mouse_delta: (f64, f64),
+    key_bindings: KeyBindings,
+    gilrs: Option<gilrs::Gilrs>,
+
+    // Current (smoothed) velocities. Input only sets a target velocity, which we approach exponentially.
+    velocity: cgmath::Vector3<f32>,
+    angular_velocity: cgmath::Vector2<f32>, // gamepad only, mouse is applied directly.
 
     translation_speed: f32,
     rotation_speed: f32,
+    gamepad_rotation_speed: f32,
+    // How fast we converge to the target velocity. Higher is snappier.
+    acceleration_sharpness: f32,
 }
 
 impl Camera {
@@ -49,9 +116,22 @@ impl Camera {
             movement_locked: true,
             active_move_commands: Default::default(),
             mouse_delta: (0.0, 0.0),
+            key_bindings: KeyBindings::load_or_default(),
+            gilrs: match gilrs::Gilrs::new() {
+                Ok(gilrs) => Some(gilrs),
+                Err(err) => {
+                    warn!("Failed to initialize gamepad input: {}", err);
+                    None
+                }
+            },
+
+            velocity: cgmath::Vector3::zero(),
+            angular_velocity: cgmath::Vector2::zero(),
 
             translation_speed: 0.5,
             rotation_speed: 0.001,
+            gamepad_rotation_speed: 2.0,
+            acceleration_sharpness: 15.0,
         }
     }
 
@@ -68,13 +148,9 @@ impl Camera {
                     },
                 ..
             } => {
-                let direction = match virtual_keycode {
-                    VirtualKeyCode::S | VirtualKeyCode::Down => BitFlags::from(MoveCommands::Backwards),
-                    VirtualKeyCode::A | VirtualKeyCode::Left => BitFlags::from(MoveCommands::Left),
-                    VirtualKeyCode::D | VirtualKeyCode::Right => BitFlags::from(MoveCommands::Right),
-                    VirtualKeyCode::W | VirtualKeyCode::Up => BitFlags::from(MoveCommands::Forwards),
-                    VirtualKeyCode::LShift => BitFlags::from(MoveCommands::SpeedUp),
-                    _ => Default::default(),
+                let direction = match self.key_bindings.0.get(virtual_keycode) {
+                    Some(command) => BitFlags::from(*command),
+                    None => Default::default(),
                 };
                 match state {
                     ElementState::Pressed => self.active_move_commands.insert(direction),
@@ -99,38 +175,112 @@ impl Camera {
         }
     }
 
+    fn command_axis(&self, positive: MoveCommands, negative: MoveCommands) -> f32 {
+        self.active_move_commands.contains(positive) as i32 as f32 - self.active_move_commands.contains(negative) as i32 as f32
+    }
+
+    // Returns (movement in camera space [right, up, forward], rotation [left/right, up/down], speed up)
+    fn gamepad_input(&mut self) -> (cgmath::Vector3<f32>, cgmath::Vector2<f32>, bool) {
+        let gilrs = match self.gilrs.as_mut() {
+            Some(gilrs) => gilrs,
+            None => return (cgmath::Vector3::zero(), cgmath::Vector2::zero(), false),
+        };
+        // Need to drain events in order to update gamepad state.
+        while let Some(_) = gilrs.next_event() {}
+
+        let deadzone = |v: f32| if v.abs() < GAMEPAD_DEADZONE { 0.0 } else { v };
+        match gilrs.gamepads().next() {
+            Some((_, gamepad)) => (
+                cgmath::vec3(
+                    deadzone(gamepad.value(gilrs::Axis::LeftStickX)),
+                    gamepad.is_pressed(gilrs::Button::RightTrigger) as i32 as f32 - gamepad.is_pressed(gilrs::Button::LeftTrigger) as i32 as f32,
+                    deadzone(gamepad.value(gilrs::Axis::LeftStickY)),
+                ),
+                cgmath::vec2(
+                    deadzone(gamepad.value(gilrs::Axis::RightStickX)),
+                    deadzone(gamepad.value(gilrs::Axis::RightStickY)),
+                ),
+                gamepad.is_pressed(gilrs::Button::RightTrigger2),
+            ),
+            None => (cgmath::Vector3::zero(), cgmath::Vector2::zero(), false),
+        }
+    }
+
     pub fn update(&mut self, timer: &Timer) {
+        let frame_delta = timer.frame_delta().as_secs_f32();
+        let right = self.direction.cross(self.rotational_up).normalize();
+        let up = right.cross(self.direction);
+
+        let (gamepad_movement, gamepad_rotation, gamepad_speed_up) = self.gamepad_input();
+
+        let mut target_velocity = gamepad_movement.x * right + gamepad_movement.y * up + gamepad_movement.z * self.direction;
+        let mut speed_up = gamepad_speed_up;
         if self.movement_locked == false {
-            let right = self.direction.cross(self.rotational_up).normalize();
-
-            let mut translation = (self.active_move_commands.contains(MoveCommands::Forwards) as i32 as f32
-                - self.active_move_commands.contains(MoveCommands::Backwards) as i32 as f32)
-                * self.direction;
-            translation += (self.active_move_commands.contains(MoveCommands::Right) as i32 as f32
-                - self.active_move_commands.contains(MoveCommands::Left) as i32 as f32)
-                * right;
-            translation *= timer.frame_delta().as_secs_f32() * self.translation_speed;
-            if self.active_move_commands.contains(MoveCommands::SpeedUp) {
-                translation *= 4.0;
-            }
+            target_velocity += self.command_axis(MoveCommands::Forwards, MoveCommands::Backwards) * self.direction;
+            target_velocity += self.command_axis(MoveCommands::Right, MoveCommands::Left) * right;
+            target_velocity += self.command_axis(MoveCommands::Up, MoveCommands::Down) * up;
+            speed_up |= self.active_move_commands.contains(MoveCommands::SpeedUp);
+        }
+        if target_velocity.magnitude2() > 1.0 {
+            target_velocity = target_velocity.normalize();
+        }
+        target_velocity *= self.translation_speed;
+        if speed_up {
+            target_velocity *= 4.0;
+        }
 
-            let rotation_updown = cgmath::Quaternion::from_axis_angle(right, cgmath::Rad(-self.mouse_delta.1 as f32 * self.rotation_speed));
-            let rotation_leftright =
-                cgmath::Quaternion::from_axis_angle(self.rotational_up, cgmath::Rad(-self.mouse_delta.0 as f32 * self.rotation_speed));
-            self.direction = (rotation_updown + rotation_leftright).rotate_vector(self.direction).normalize();
+        // Exponential smoothing, independent of frame rate.
+        let smoothing = 1.0 - (-self.acceleration_sharpness * frame_delta).exp();
+        self.velocity += (target_velocity - self.velocity) * smoothing;
+        self.angular_velocity += (gamepad_rotation * self.gamepad_rotation_speed - self.angular_velocity) * smoothing;
 
-            self.position += translation;
+        let mut rotation_leftright = -self.angular_velocity.x * frame_delta;
+        let mut rotation_updown = self.angular_velocity.y * frame_delta;
+        if self.movement_locked == false {
+            rotation_leftright -= self.mouse_delta.0 as f32 * self.rotation_speed;
+            rotation_updown -= self.mouse_delta.1 as f32 * self.rotation_speed;
         }
+        let rotation = cgmath::Quaternion::from_axis_angle(self.rotational_up, cgmath::Rad(rotation_leftright))
+            * cgmath::Quaternion::from_axis_angle(right, cgmath::Rad(rotation_updown));
+        self.direction = rotation.rotate_vector(self.direction).normalize();
 
+        self.position += self.velocity * frame_delta;
         self.mouse_delta = (0.0, 0.0);
     }
 
-    pub fn fill_global_uniform_buffer(&self, aspect_ratio: f32) -> CameraUniformBufferContent {
+    // Ray through a pixel, returns origin & normalized direction in world space.
+    pub fn ray_through_pixel(
+        &self,
+        pixel: winit::dpi::PhysicalPosition<f64>,
+        resolution: winit::dpi::PhysicalSize<u32>,
+    ) -> (cgmath::Point3<f32>, cgmath::Vector3<f32>) {
         let right = self.direction.cross(self.rotational_up).normalize();
         let up = right.cross(self.direction).normalize();
+        let tan_half_vertical_fov = (VERTICAL_FOV * 0.5).tan();
+        let aspect_ratio = resolution.width as f32 / resolution.height.max(1) as f32;
 
+        let ndc_x = (pixel.x as f32 / resolution.width.max(1) as f32) * 2.0 - 1.0;
+        let ndc_y = 1.0 - (pixel.y as f32 / resolution.height.max(1) as f32) * 2.0;
+        let direction = self.direction + right * (ndc_x * tan_half_vertical_fov * aspect_ratio) + up * (ndc_y * tan_half_vertical_fov);
+        (self.position, direction.normalize())
+    }
+
+    fn view_and_projection(&self, aspect_ratio: f32) -> (cgmath::Matrix4<f32>, cgmath::Matrix4<f32>) {
         let view = cgmath::Matrix4::look_to_rh(self.position, self.direction, self.rotational_up);
         let projection = OPENGL_PROJECTION_TO_WGPU_PROJECTION * cgmath::perspective(VERTICAL_FOV, aspect_ratio, 0.01, 1000.0);
+        (view, projection)
+    }
+
+    pub fn frustum(&self, aspect_ratio: f32) -> Frustum {
+        let (view, projection) = self.view_and_projection(aspect_ratio);
+        Frustum::from_view_projection(&(projection * view))
+    }
+
+    pub fn fill_global_uniform_buffer(&self, aspect_ratio: f32) -> CameraUniformBufferContent {
+        let right = self.direction.cross(self.rotational_up).normalize();
+        let up = right.cross(self.direction).normalize();
+
+        let (view, projection) = self.view_and_projection(aspect_ratio);
         let view_projection = projection * view;
         let inverse_projection = projection.invert().unwrap();
         //let inverse_view_projection = view_projection.invert().unwrap();
@@ -151,6 +301,43 @@ impl Camera {
     }
 }
 
+// View frustum in world space for culling on the cpu.
+pub struct Frustum {
+    // Plane equations (normal pointing inwards, w is the distance), left, right, bottom, top, near, far
+    planes: [cgmath::Vector4<f32>; 6],
+}
+
+impl Frustum {
+    // Extracts the planes directly from the matrix (Gribb & Hartmann), assuming wgpu's 0-1 depth range.
+    fn from_view_projection(view_projection: &cgmath::Matrix4<f32>) -> Self {
+        let row = |i| view_projection.row(i);
+        let normalize = |plane: cgmath::Vector4<f32>| plane / plane.truncate().magnitude();
+        Frustum {
+            planes: [
+                normalize(row(3) + row(0)),
+                normalize(row(3) - row(0)),
+                normalize(row(3) + row(1)),
+                normalize(row(3) - row(1)),
+                normalize(row(2)),
+                normalize(row(3) - row(2)),
+            ],
+        }
+    }
+
+    // Conservative test, may return true for boxes that are close to but outside of the frustum corners.
+    pub fn intersects_box(&self, min: cgmath::Point3<f32>, max: cgmath::Point3<f32>) -> bool {
+        self.planes.iter().all(|plane| {
+            // Corner furthest along the plane normal.
+            let corner = cgmath::vec3(
+                if plane.x >= 0.0 { max.x } else { min.x },
+                if plane.y >= 0.0 { max.y } else { min.y },
+                if plane.z >= 0.0 { max.z } else { min.z },
+            );
+            plane.truncate().dot(corner) + plane.w >= 0.0
+        })
+    }
+}
+
 #[repr(C)]
 #[derive(Clone, Copy)]
 pub struct CameraUniformBufferContent {
@@ -0,0 +1,108 @@
+use crate::{benchmark::BenchmarkConfig, settings_overrides::SettingsOverrides, sweep::SweepConfig, wgpu_utils::profiling::ProfilingDetail};
+use std::path::PathBuf;
+
+const USAGE: &str = "Usage: blub [options]
+
+Options:
+    --scene <scene.json>            Scene to load on startup. (default: most recently used scene)
+    --set <key>=<value>             Overrides a setting of every loaded scene, can be given several times.
+                                    Keys are paths into the scene json (e.g. fluid.max_num_particles=4000000) or solver settings
+                                    (e.g. solver.error_tolerance=0.05, solver_velocity.max_num_iterations=64).
+    --benchmark <scene.json>...     Runs the given scenes for a fixed number of simulation steps and writes a report, then exits.
+    --benchmark-steps <n>           Number of simulation steps per scene in benchmark mode. (default 300)
+    --benchmark-steps-per-second <n>
+                                    Simulation steps per second in benchmark mode. (default 120)
+    --benchmark-output <path>       Report path without extension, .json and .csv are written. (default benchmark-report)
+    --sweep <sweep.json>            Runs all variants of a parameter sweep and writes screenshots & statistics per variant, then exits.
+    --autotune-workgroups           Re-runs the workgroup size autotuning for the current gpu. (runs automatically for new gpus)
+    --profiling-detail <level>      Profiler scopes recorded in simulation steps: off, coarse or fine. (default fine)
+    --help                          Prints this message.";
+
+#[derive(Default)]
+pub struct CommandLineArgs {
+    pub scene: Option<PathBuf>,
+    pub settings_overrides: SettingsOverrides,
+    pub benchmark: Option<BenchmarkConfig>,
+    pub sweep: Option<SweepConfig>,
+    pub autotune_workgroups: bool,
+    pub profiling_detail: Option<ProfilingDetail>,
+}
+
+fn parse_value<T: std::str::FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("Missing value for {}", flag))?;
+    value.parse().map_err(|_| format!("Invalid value for {}: {}", flag, value))
+}
+
+impl CommandLineArgs {
+    // Parses the process arguments. Prints usage & exits on --help or invalid arguments.
+    pub fn from_env() -> Self {
+        match Self::parse(std::env::args().skip(1)) {
+            Ok(Some(args)) => args,
+            Ok(None) => {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            }
+            Err(err) => {
+                eprintln!("{}\n\n{}", err, USAGE);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    // Returns None if help was requested.
+    fn parse(args: impl Iterator<Item = String>) -> Result<Option<Self>, String> {
+        let mut parsed = CommandLineArgs::default();
+        let mut benchmark_scenes = Vec::new();
+        let mut benchmark_config = BenchmarkConfig::default();
+        let mut settings_assignments = Vec::new();
+
+        let mut args = args.peekable();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--scene" => {
+                    let scene: PathBuf = parse_value(&arg, args.next())?;
+                    if !scene.is_file() {
+                        return Err(format!("Scene file {:?} not found", scene));
+                    }
+                    parsed.scene = Some(scene);
+                }
+                "--set" => settings_assignments.push(parse_value::<String>(&arg, args.next())?),
+                "--benchmark" => {
+                    while let Some(scene) = args.next_if(|arg| !arg.starts_with("--")) {
+                        benchmark_scenes.push(PathBuf::from(scene));
+                    }
+                    if benchmark_scenes.is_empty() {
+                        return Err("--benchmark needs at least one scene".to_string());
+                    }
+                }
+                "--sweep" => {
+                    let path: PathBuf = parse_value(&arg, args.next())?;
+                    let sweep = SweepConfig::from_file(&path).map_err(|err| format!("Failed to read sweep {:?}: {}", path, err))?;
+                    parsed.sweep = Some(sweep);
+                }
+                "--benchmark-steps" => benchmark_config.num_steps = parse_value(&arg, args.next())?,
+                "--benchmark-steps-per-second" => {
+                    benchmark_config.simulation_steps_per_second = parse_value(&arg, args.next())?;
+                    if benchmark_config.simulation_steps_per_second == 0 {
+                        return Err("--benchmark-steps-per-second needs to be greater than zero".to_string());
+                    }
+                }
+                "--benchmark-output" => benchmark_config.output = parse_value(&arg, args.next())?,
+                "--autotune-workgroups" => parsed.autotune_workgroups = true,
+                "--profiling-detail" => parsed.profiling_detail = Some(parse_value(&arg, args.next())?),
+                "--help" | "-h" => return Ok(None),
+                _ => return Err(format!("Unknown argument {}", arg)),
+            }
+        }
+
+        parsed.settings_overrides = SettingsOverrides::from_assignments(&settings_assignments)?;
+        if !benchmark_scenes.is_empty() && parsed.sweep.is_some() {
+            return Err("--benchmark and --sweep can't be combined".to_string());
+        }
+        if !benchmark_scenes.is_empty() {
+            benchmark_config.scenes = benchmark_scenes;
+            parsed.benchmark = Some(benchmark_config);
+        }
+        Ok(Some(parsed))
+    }
+}
@@ -0,0 +1,114 @@
+use crate::{camera::Camera, scene::FluidConfig, simulation::InteractionForce, timer::Timer};
+use cgmath::prelude::*;
+use strum_macros::EnumIter;
+use winit::event::{ElementState, MouseButton, WindowEvent};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter)]
+pub enum InteractionMode {
+    None,
+    // Particles under the cursor follow the cursor movement.
+    Drag,
+    // Particles under the cursor are pushed away from the camera.
+    Push,
+    // Particles under the cursor are removed.
+    Erase,
+    // Particles under the cursor are colored with the dye color, see FluidRenderingMode::ParticlesDye.
+    Dye,
+}
+
+// Lets the user poke the fluid with the mouse: while the left mouse button is held, particles close to the ray through the cursor are accelerated.
+pub struct FluidInteraction {
+    pub mode: InteractionMode,
+    pub radius: f32, // world space
+    // Drag: how quickly particles pick up the cursor velocity (1/s)
+    // Push: acceleration in world units/s²
+    pub strength: f32,
+    pub dye_color: [u8; 3], // sRGB
+
+    cursor_position: winit::dpi::PhysicalPosition<f64>,
+    button_pressed: bool,
+    // Depth of the plane in which the cursor is moved while dragging, fixed when the drag starts.
+    drag_depth: Option<f32>,
+    last_drag_point: Option<cgmath::Point3<f32>>,
+}
+
+impl FluidInteraction {
+    pub fn new() -> Self {
+        FluidInteraction {
+            mode: InteractionMode::None,
+            radius: 0.1,
+            strength: 10.0,
+            dye_color: [230, 40, 40],
+
+            cursor_position: winit::dpi::PhysicalPosition::new(0.0, 0.0),
+            button_pressed: false,
+            drag_depth: None,
+            last_drag_point: None,
+        }
+    }
+
+    pub fn on_window_event(&mut self, event: &WindowEvent, gui_wants_pointer_input: bool) {
+        match event {
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor_position = *position;
+            }
+            WindowEvent::MouseInput {
+                button: MouseButton::Left,
+                state,
+                ..
+            } => {
+                self.button_pressed = *state == ElementState::Pressed && !gui_wants_pointer_input;
+                if !self.button_pressed {
+                    self.drag_depth = None;
+                    self.last_drag_point = None;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Returns the force that should be applied to the fluid during the steps of this frame.
+    pub fn update(
+        &mut self,
+        camera: &Camera,
+        resolution: winit::dpi::PhysicalSize<u32>,
+        timer: &Timer,
+        fluid_config: &FluidConfig,
+    ) -> Option<InteractionForce> {
+        if self.mode == InteractionMode::None || !self.button_pressed {
+            return None;
+        }
+
+        let (ray_origin, ray_direction) = camera.ray_through_pixel(self.cursor_position, resolution);
+        let acceleration = match self.mode {
+            InteractionMode::None => unreachable!(),
+            InteractionMode::Push => ray_direction * self.strength,
+            InteractionMode::Erase | InteractionMode::Dye => cgmath::Vector3::zero(),
+            InteractionMode::Drag => {
+                let fluid_center = fluid_config.world_position
+                    + fluid_config.grid_dimension.cast::<f32>().unwrap().to_vec() * (fluid_config.grid_to_world_scale * 0.5);
+                let drag_depth = *self
+                    .drag_depth
+                    .get_or_insert_with(|| (fluid_center - camera.position).dot(camera.direction).max(0.1));
+                let drag_point = ray_origin + ray_direction * (drag_depth / ray_direction.dot(camera.direction).max(0.01));
+
+                let cursor_velocity = match self.last_drag_point {
+                    Some(last_drag_point) => (drag_point - last_drag_point) / timer.frame_delta().as_secs_f32().max(0.0001),
+                    None => cgmath::Vector3::zero(),
+                };
+                self.last_drag_point = Some(drag_point);
+                cursor_velocity * self.strength
+            }
+        };
+
+        let world_to_grid = 1.0 / fluid_config.grid_to_world_scale;
+        Some(InteractionForce {
+            ray_origin: cgmath::Point3::from_vec((ray_origin - fluid_config.world_position) * world_to_grid),
+            ray_direction,
+            radius: self.radius * world_to_grid,
+            acceleration: acceleration * world_to_grid,
+            erase: self.mode == InteractionMode::Erase,
+            dye: if self.mode == InteractionMode::Dye { Some(self.dye_color) } else { None },
+        })
+    }
+}
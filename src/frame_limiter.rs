@@ -0,0 +1,48 @@
+use std::time::{Duration, Instant};
+
+// Caps the frame rate when the swap chain doesn't (i.e. without vsync), so the event loop doesn't burn a full core.
+//
+// OS sleeps are coarse (up to a few milliseconds late, more on Windows), so we sleep for most of the remaining frame time
+// and spin for the last bit to hit the target frame time precisely.
+pub struct FrameLimiter {
+    next_frame: Instant,
+}
+
+impl FrameLimiter {
+    // Remaining time that is spent spinning instead of sleeping.
+    const SPIN_DURATION: Duration = Duration::from_millis(2);
+
+    pub fn new() -> Self {
+        FrameLimiter { next_frame: Instant::now() }
+    }
+
+    // Blocks until the next frame is due. Without target, returns immediately.
+    pub fn wait_for_next_frame(&mut self, target_fps: Option<u32>) {
+        let target_fps = match target_fps {
+            Some(target_fps) if target_fps > 0 => target_fps,
+            _ => {
+                self.next_frame = Instant::now();
+                return;
+            }
+        };
+        let frame_duration = Duration::from_secs_f64(1.0 / target_fps as f64);
+
+        let now = Instant::now();
+        if self.next_frame > now {
+            let remaining = self.next_frame - now;
+            if remaining > Self::SPIN_DURATION {
+                std::thread::sleep(remaining - Self::SPIN_DURATION);
+            }
+            while Instant::now() < self.next_frame {
+                std::hint::spin_loop();
+            }
+        }
+
+        // If we fell behind by more than a frame (slow frame, target changed), start over instead of rushing to catch up.
+        let now = Instant::now();
+        self.next_frame += frame_duration;
+        if self.next_frame < now {
+            self.next_frame = now + frame_duration;
+        }
+    }
+}
@@ -1,16 +1,20 @@
 use crate::{
     global_ubo::GlobalUBO,
     scene::models::SceneModels,
-    wgpu_utils::{binding_builder::*, binding_glsl},
+    wgpu_utils::{binding_builder::*, binding_glsl, uniformbuffer::NUM_RING_SLOTS},
 };
+use std::sync::Arc;
 
 pub struct GlobalBindings {
     bind_group_layout: BindGroupLayoutWithDesc,
-    bind_group: Option<wgpu::BindGroup>,
+    // One bind group per slot of the global ubo ring buffer, so passes don't need to deal with dynamic offsets.
+    bind_groups: Vec<Arc<wgpu::BindGroup>>,
 }
 
 impl GlobalBindings {
-    pub const NUM_MESH_TEXTURES: u32 = 1;
+    // Mesh rendering binds the lighting & background group as well, which adds another two sampled textures to the fragment stage.
+    // Together they need to stay within the default limit of 16 sampled textures per shader stage.
+    pub const NUM_MESH_TEXTURES: u32 = 14;
 
     pub fn new(device: &wgpu::Device) -> Self {
         let bind_group_layout = BindGroupLayoutBuilder::new()
@@ -32,11 +36,13 @@ impl GlobalBindings {
             })
             .next_binding(wgpu::ShaderStage::COMPUTE | wgpu::ShaderStage::VERTEX, binding_glsl::buffer(true))
             .next_binding(wgpu::ShaderStage::COMPUTE | wgpu::ShaderStage::VERTEX, binding_glsl::buffer(true))
+            // Sampler for mesh textures
+            .next_binding_fragment(binding_glsl::sampler(true))
             .create(device, "BindGroupLayout: GlobalBindings");
 
         GlobalBindings {
             bind_group_layout: bind_group_layout,
-            bind_group: None,
+            bind_groups: Vec::new(),
         }
     }
 
@@ -62,6 +68,18 @@ impl GlobalBindings {
             ..Default::default()
         });
 
+        let anisotropic_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Sampler AnisotropicRepeat (global)"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            address_mode_w: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            anisotropy_clamp: std::num::NonZeroU8::new(16), // silently ignored if not supported by the device
+            ..Default::default()
+        });
+
         let dummy_texture_view = device
             .create_texture(&wgpu::TextureDescriptor {
                 label: Some("Dummy Texture"),
@@ -84,24 +102,38 @@ impl GlobalBindings {
             .chain(std::iter::repeat(&dummy_texture_view).take(Self::NUM_MESH_TEXTURES as usize - meshes.texture_views.len()))
             .collect();
 
-        self.bind_group = Some(
-            BindGroupBuilder::new(&self.bind_group_layout)
-                // Constants
-                .resource(ubo.binding_resource())
-                // Sampler
-                .sampler(&trilinear_sampler)
-                .sampler(&point_sampler)
-                // Meshdata
-                .resource(meshes.mesh_desc_buffer.as_entire_binding())
-                .resource(wgpu::BindingResource::TextureViewArray(&texture_views))
-                .resource(meshes.index_buffer.as_entire_binding())
-                .resource(meshes.vertex_buffer.as_entire_binding())
-                .create(device, "BindGroup: GlobalBindings"),
-        );
+        self.bind_groups = (0..NUM_RING_SLOTS)
+            .map(|slot| {
+                BindGroupBuilder::new(&self.bind_group_layout)
+                    // Constants
+                    .resource(ubo.slot_binding_resource(slot))
+                    // Sampler
+                    .sampler(&trilinear_sampler)
+                    .sampler(&point_sampler)
+                    // Meshdata
+                    .resource(meshes.mesh_desc_buffer.as_entire_binding())
+                    .resource(wgpu::BindingResource::TextureViewArray(&texture_views))
+                    .resource(meshes.index_buffer.as_entire_binding())
+                    .resource(meshes.vertex_buffer.as_entire_binding())
+                    // Sampler for mesh textures
+                    .sampler(&anisotropic_sampler)
+                    .create(device, &format!("BindGroup: GlobalBindings, slot {}", slot))
+            })
+            .map(Arc::new)
+            .collect();
+    }
+
+    // Bind group referring to the latest content of the global ubo.
+    pub fn bind_group(&self, ubo: &GlobalUBO) -> &wgpu::BindGroup {
+        self.bind_groups.get(ubo.current_slot()).expect("Bind group has not been created yet!")
     }
 
-    pub fn bind_group(&self) -> &wgpu::BindGroup {
-        &self.bind_group.as_ref().expect("Bind group has not been created yet!")
+    // Same as bind_group, for handing it to the simulation thread.
+    pub fn shared_bind_group(&self, ubo: &GlobalUBO) -> Arc<wgpu::BindGroup> {
+        self.bind_groups
+            .get(ubo.current_slot())
+            .expect("Bind group has not been created yet!")
+            .clone()
     }
 
     pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
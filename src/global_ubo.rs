@@ -1,7 +1,8 @@
+use crate::renderer;
 use crate::timer;
+use crate::wgpu_utils::uniformbuffer::{UniformBufferRing, NUM_RING_SLOTS};
 use crate::{camera, render_output::screen};
-use crate::{renderer, wgpu_utils::*};
-use uniformbuffer::UniformBuffer;
+use std::ops::Range;
 
 #[repr(C)]
 #[derive(Clone, Copy)]
@@ -14,23 +15,131 @@ pub struct GlobalUBOContent {
 unsafe impl bytemuck::Pod for GlobalUBOContent {}
 unsafe impl bytemuck::Zeroable for GlobalUBOContent {}
 
-pub type GlobalUBO = UniformBuffer<GlobalUBOContent>;
+// Sections of the global UBO in the order they appear in the buffer.
+#[derive(Clone, Copy)]
+enum GlobalUBOSection {
+    Camera,
+    Time,
+    Rendering,
+    Screen,
+}
+const NUM_SECTIONS: usize = 4;
 
-pub fn update_global_ubo(
-    ubo: &mut GlobalUBO,
-    queue: &wgpu::Queue,
-    camera: camera::CameraUniformBufferContent,
-    time: timer::FrameTimeUniformBufferContent,
-    rendering: renderer::GlobalRenderSettingsUniformBufferContent,
-    screen: screen::ScreenUniformBufferContent,
-) {
-    ubo.update_content(
-        queue,
-        GlobalUBOContent {
-            camera,
-            time,
-            rendering,
-            screen,
-        },
-    );
+// Global uniform buffer, split up into independently updated sections.
+//
+// Sections are set during the frame, but only the ones that actually changed are written to the gpu on write_changed_sections.
+// Every write goes to the next slot of a ring buffer, so passes recorded before an update keep seeing the previous content.
+// A slot only needs the sections that changed since it was last written.
+pub struct GlobalUBO {
+    ring: UniformBufferRing<GlobalUBOContent>,
+    content: GlobalUBOContent,
+    stale_sections: [[bool; NUM_SECTIONS]; NUM_RING_SLOTS],
+}
+
+impl GlobalUBO {
+    pub fn new(device: &wgpu::Device) -> Self {
+        GlobalUBO {
+            ring: UniformBufferRing::new(device),
+            content: bytemuck::Zeroable::zeroed(),
+            stale_sections: [[true; NUM_SECTIONS]; NUM_RING_SLOTS],
+        }
+    }
+
+    // There is a bind group per slot, this is the one with the latest content.
+    pub fn current_slot(&self) -> usize {
+        self.ring.current_slot()
+    }
+
+    pub fn slot_binding_resource(&self, slot: usize) -> wgpu::BindingResource {
+        self.ring.slot_binding_resource(slot)
+    }
+
+    pub fn set_camera(&mut self, camera: camera::CameraUniformBufferContent) {
+        let previous_content = self.content;
+        self.content.camera = camera;
+        self.mark_if_changed(&previous_content, GlobalUBOSection::Camera);
+    }
+
+    pub fn set_time(&mut self, time: timer::FrameTimeUniformBufferContent) {
+        let previous_content = self.content;
+        self.content.time = time;
+        self.mark_if_changed(&previous_content, GlobalUBOSection::Time);
+    }
+
+    pub fn set_rendering(&mut self, rendering: renderer::GlobalRenderSettingsUniformBufferContent) {
+        let previous_content = self.content;
+        self.content.rendering = rendering;
+        self.mark_if_changed(&previous_content, GlobalUBOSection::Rendering);
+    }
+
+    pub fn set_screen(&mut self, screen: screen::ScreenUniformBufferContent) {
+        let previous_content = self.content;
+        self.content.screen = screen;
+        self.mark_if_changed(&previous_content, GlobalUBOSection::Screen);
+    }
+
+    // Writes all sections that changed since the last call to the next slot. Neighboring sections are written together.
+    pub fn write_changed_sections(&mut self, queue: &wgpu::Queue) {
+        if !self.stale_sections[self.ring.current_slot()].contains(&true) {
+            return;
+        }
+        self.ring.next_slot();
+        let slot = self.ring.current_slot();
+
+        let content = bytemuck::bytes_of(&self.content);
+        let mut pending_range: Option<Range<usize>> = None;
+        for (index, section) in [
+            GlobalUBOSection::Camera,
+            GlobalUBOSection::Time,
+            GlobalUBOSection::Rendering,
+            GlobalUBOSection::Screen,
+        ]
+        .iter()
+        .enumerate()
+        {
+            if !self.stale_sections[slot][index] {
+                if let Some(range) = pending_range.take() {
+                    self.ring.write_current_slot(queue, range.start as wgpu::BufferAddress, &content[range]);
+                }
+                continue;
+            }
+            let section_range = Self::section_range(*section);
+            pending_range = Some(match pending_range {
+                Some(range) => range.start..section_range.end,
+                None => section_range,
+            });
+        }
+        if let Some(range) = pending_range {
+            self.ring.write_current_slot(queue, range.start as wgpu::BufferAddress, &content[range]);
+        }
+        self.stale_sections[slot] = [false; NUM_SECTIONS];
+    }
+
+    fn mark_if_changed(&mut self, previous_content: &GlobalUBOContent, section: GlobalUBOSection) {
+        let range = Self::section_range(section);
+        if bytemuck::bytes_of(previous_content)[range.clone()] != bytemuck::bytes_of(&self.content)[range] {
+            for stale_sections in self.stale_sections.iter_mut() {
+                stale_sections[section as usize] = true;
+            }
+        }
+    }
+
+    // Byte range of a section within GlobalUBOContent. Sections are sized in multiples of 16 bytes, so this is always a valid copy range.
+    fn section_range(section: GlobalUBOSection) -> Range<usize> {
+        let start = match section {
+            GlobalUBOSection::Camera => 0,
+            GlobalUBOSection::Time => std::mem::size_of::<camera::CameraUniformBufferContent>(),
+            GlobalUBOSection::Rendering => {
+                std::mem::size_of::<camera::CameraUniformBufferContent>() + std::mem::size_of::<timer::FrameTimeUniformBufferContent>()
+            }
+            GlobalUBOSection::Screen => std::mem::size_of::<GlobalUBOContent>() - std::mem::size_of::<screen::ScreenUniformBufferContent>(),
+        };
+        let size = match section {
+            GlobalUBOSection::Camera => std::mem::size_of::<camera::CameraUniformBufferContent>(),
+            GlobalUBOSection::Time => std::mem::size_of::<timer::FrameTimeUniformBufferContent>(),
+            GlobalUBOSection::Rendering => std::mem::size_of::<renderer::GlobalRenderSettingsUniformBufferContent>(),
+            GlobalUBOSection::Screen => std::mem::size_of::<screen::ScreenUniformBufferContent>(),
+        };
+        start..(start + size)
+    }
 }
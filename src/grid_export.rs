@@ -0,0 +1,183 @@
+use crate::{scene::Scene, simulation::GridReadback};
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+// Periodically writes the simulation grid (velocity, pressure, cell types) to disk for analysis in ParaView & co.
+//
+// Every export is a VTK image data file (.vti) with cell centered values in world space,
+// a .pvd collection next to them lists all exports with their simulated time so they can be loaded as a time series.
+// Reading back the grid stalls the gpu, so this is only meant for offline analysis. Exports happen at most once per frame.
+pub struct GridExporter {
+    output_dir: Option<PathBuf>,
+    next_export_time: Duration,
+    // Simulated time & file name of every export so far, for the collection file.
+    exports: Vec<(Duration, String)>,
+}
+
+impl GridExporter {
+    pub fn new() -> Self {
+        GridExporter {
+            output_dir: None,
+            next_export_time: Duration::from_secs(0),
+            exports: Vec::new(),
+        }
+    }
+
+    // Starts exporting into a new folder named after scene & current time within the given output directory.
+    pub fn start(&mut self, output_root: &Path, scene_name: &str) {
+        let export_name = format!("{}_grid_{}", scene_name, chrono::Local::now().format("%Y-%m-%d_%H-%M-%S"));
+        let output_dir = (0..)
+            .map(|i| {
+                if i == 0 {
+                    output_root.join(&export_name)
+                } else {
+                    output_root.join(format!("{}_{}", export_name, i))
+                }
+            })
+            .find(|dir| !dir.exists())
+            .unwrap();
+        if let Err(err) = std::fs::create_dir_all(&output_dir) {
+            error!("Failed to create grid export directory {:?}: {}", output_dir, err);
+            return;
+        }
+
+        info!("Exporting grid to {:?}", output_dir);
+        self.output_dir = Some(output_dir);
+        self.next_export_time = Duration::from_secs(0);
+        self.exports.clear();
+    }
+
+    pub fn stop(&mut self) {
+        self.output_dir = None;
+    }
+
+    pub fn is_exporting(&self) -> bool {
+        self.output_dir.is_some()
+    }
+
+    // Exports the current grid if at least interval simulated time passed since the last export.
+    pub fn update(&mut self, scene: &Scene, device: &wgpu::Device, queue: &wgpu::Queue, simulated_time: Duration, interval: Duration) {
+        let output_dir = match self.output_dir {
+            Some(ref output_dir) => output_dir.clone(),
+            None => return,
+        };
+        if simulated_time < self.next_export_time {
+            // Simulation was restarted, the old time series is done.
+            if self.exports.last().map_or(false, |(time, _)| simulated_time < *time) {
+                let output_root = output_dir.parent().unwrap_or_else(|| Path::new("")).to_path_buf();
+                self.start(&output_root, &scene.path().file_stem().unwrap_or_default().to_string_lossy());
+            }
+            return;
+        }
+
+        let grid = match scene.fluid().read_grid(device, queue) {
+            Some(grid) => grid,
+            None => return,
+        };
+        let file_name = format!("grid{:05}.vti", self.exports.len());
+        let path = output_dir.join(&file_name);
+        let fluid_config = &scene.config().fluid;
+        if let Err(err) = write_vti(
+            &path,
+            &grid,
+            fluid_config.grid_dimension,
+            fluid_config.world_position,
+            fluid_config.grid_to_world_scale,
+        ) {
+            error!("Failed to write grid export {:?}: {}", path, err);
+            self.stop();
+            return;
+        }
+        self.exports.push((simulated_time, file_name));
+
+        let collection_path = output_dir.join("grid.pvd");
+        if let Err(err) = write_pvd(&collection_path, &self.exports) {
+            error!("Failed to write grid export collection {:?}: {}", collection_path, err);
+        }
+
+        // Stay on a regular time grid, unless we fell behind by more than an interval (e.g. fast forward).
+        self.next_export_time += interval;
+        if self.next_export_time <= simulated_time {
+            self.next_export_time = simulated_time + interval;
+        }
+    }
+}
+
+// VTK XML image data with all arrays in a raw appended block.
+// See https://kitware.github.io/vtk-examples/site/VTKFileFormats/
+fn write_vti(
+    path: &Path,
+    grid: &GridReadback,
+    grid_dimension: cgmath::Point3<u32>,
+    world_position: cgmath::Point3<f32>,
+    grid_to_world_scale: f32,
+) -> std::io::Result<()> {
+    // Read back velocity is in grid space (cells/s), exported in world units.
+    let mut velocity = Vec::with_capacity(grid.velocity.len() * 3);
+    for cell_velocity in grid.velocity.iter() {
+        let cell_velocity = cell_velocity * grid_to_world_scale;
+        velocity.extend_from_slice(&[cell_velocity.x, cell_velocity.y, cell_velocity.z]);
+    }
+    let arrays: [(&str, u32, &[f32]); 3] = [("velocity", 3, &velocity), ("pressure", 1, &grid.pressure), ("marker", 1, &grid.marker)];
+
+    let mut file = BufWriter::new(File::create(path)?);
+    // Values are at cell centers.
+    let origin = world_position + cgmath::vec3(0.5, 0.5, 0.5) * grid_to_world_scale;
+    let extent = format!("0 {} 0 {} 0 {}", grid_dimension.x - 1, grid_dimension.y - 1, grid_dimension.z - 1);
+    writeln!(file, r#"<?xml version="1.0"?>"#)?;
+    writeln!(
+        file,
+        r#"<VTKFile type="ImageData" version="1.0" byte_order="LittleEndian" header_type="UInt64">"#
+    )?;
+    writeln!(
+        file,
+        r#"  <ImageData WholeExtent="{}" Origin="{} {} {}" Spacing="{s} {s} {s}">"#,
+        extent,
+        origin.x,
+        origin.y,
+        origin.z,
+        s = grid_to_world_scale
+    )?;
+    writeln!(file, r#"    <Piece Extent="{}">"#, extent)?;
+    writeln!(file, r#"      <PointData Vectors="velocity" Scalars="pressure">"#)?;
+    let mut offset = 0;
+    for (name, num_components, data) in arrays.iter() {
+        writeln!(
+            file,
+            r#"        <DataArray type="Float32" Name="{}" NumberOfComponents="{}" format="appended" offset="{}"/>"#,
+            name, num_components, offset
+        )?;
+        offset += std::mem::size_of::<u64>() + data.len() * std::mem::size_of::<f32>();
+    }
+    writeln!(file, "      </PointData>")?;
+    writeln!(file, "    </Piece>")?;
+    writeln!(file, "  </ImageData>")?;
+    write!(file, r#"  <AppendedData encoding="raw">"#)?;
+    write!(file, "\n_")?;
+    for (_, _, data) in arrays.iter() {
+        file.write_all(&((data.len() * std::mem::size_of::<f32>()) as u64).to_le_bytes())?;
+        for value in data.iter() {
+            file.write_all(&value.to_le_bytes())?;
+        }
+    }
+    writeln!(file, "\n  </AppendedData>")?;
+    writeln!(file, "</VTKFile>")?;
+    file.flush()
+}
+
+fn write_pvd(path: &Path, exports: &[(Duration, String)]) -> std::io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    writeln!(file, r#"<?xml version="1.0"?>"#)?;
+    writeln!(file, r#"<VTKFile type="Collection" version="0.1">"#)?;
+    writeln!(file, "  <Collection>")?;
+    for (time, file_name) in exports {
+        writeln!(file, r#"    <DataSet timestep="{}" file="{}"/>"#, time.as_secs_f64(), file_name)?;
+    }
+    writeln!(file, "  </Collection>")?;
+    writeln!(file, "</VTKFile>")?;
+    file.flush()
+}
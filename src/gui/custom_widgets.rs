@@ -1,3 +1,4 @@
+use super::{formatting, theme};
 use egui::*;
 
 pub fn plot_barchart(
@@ -20,6 +21,7 @@ pub fn plot_barchart(
 
     let rect = rect.shrink(4.0);
     let half_bar_width = rect.width() / values.len() as f32 * 0.5;
+    let palette = theme::plot_palette();
 
     for (i, &value) in values.iter().rev().enumerate() {
         let x = remap(i as f32, values.len() as f32..=0.0, rect.x_range());
@@ -31,16 +33,16 @@ pub fn plot_barchart(
             max: pos2(x_max, rect.bottom()),
         };
 
-        let mut fill_color = ui.visuals().weak_text_color();
+        let mut fill_color = palette.bar();
 
         let tooltip = if let Some(pointer_pos) = ui.input().pointer.interact_pos() {
             if bar.contains(pointer_pos) {
-                fill_color = ui.visuals().text_color();
+                fill_color = palette.bar_highlight();
                 Some(Shape::text(
                     ui.fonts(),
                     pointer_pos,
                     egui::Align2::LEFT_BOTTOM,
-                    format!("{:.*} {}", value_decimals, value, value_unit),
+                    format!("{} {}", formatting::decimal(value as f64, value_decimals), value_unit),
                     TextStyle::Body,
                     ui.visuals().strong_text_color(),
                 ))
@@ -68,3 +70,175 @@ pub fn plot_barchart(
 
     response
 }
+
+fn max_scope_depth(scopes: &[wgpu_profiler::GpuTimerScopeResult]) -> usize {
+    scopes.iter().map(|scope| 1 + max_scope_depth(&scope.nested_scopes)).max().unwrap_or(0)
+}
+
+// Deterministic color per label, so scopes keep their color from frame to frame.
+fn scope_color(palette: &theme::PlotPalette, label: &str) -> Color32 {
+    use std::hash::{Hash, Hasher};
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    label.hash(&mut hasher);
+    palette.category(hasher.finish() as usize)
+}
+
+// Flame graph of gpu scopes, time goes left to right, nesting top to bottom.
+pub fn plot_timeline(ui: &mut egui::Ui, width: f32, scopes: &[wgpu_profiler::GpuTimerScopeResult]) -> egui::Response {
+    const ROW_HEIGHT: f32 = 18.0;
+
+    let num_rows = max_scope_depth(scopes).max(1);
+    let (rect, response) = ui.allocate_at_least(vec2(width, num_rows as f32 * ROW_HEIGHT + 8.0), Sense::hover());
+    let style = ui.style().noninteractive();
+    ui.painter().add(Shape::Rect {
+        rect,
+        corner_radius: style.corner_radius,
+        fill: ui.visuals().extreme_bg_color,
+        stroke: style.bg_stroke,
+    });
+
+    let (start, end) = match (scopes.first(), scopes.last()) {
+        (Some(first), Some(last)) => (first.time.start, last.time.end),
+        _ => return response,
+    };
+    let rect = rect.shrink(4.0);
+    let pointer_pos = ui.input().pointer.interact_pos();
+    let mut tooltip = None;
+    let palette = theme::plot_palette();
+
+    let mut stack: Vec<(&wgpu_profiler::GpuTimerScopeResult, usize)> = scopes.iter().map(|scope| (scope, 0)).collect();
+    while let Some((scope, depth)) = stack.pop() {
+        let x_min = remap(scope.time.start as f32, start as f32..=end as f32, rect.x_range());
+        let x_max = remap(scope.time.end as f32, start as f32..=end as f32, rect.x_range());
+        let y_min = rect.top() + depth as f32 * ROW_HEIGHT;
+        let bar = Rect {
+            min: pos2(ui.painter().round_to_pixel(x_min), y_min),
+            max: pos2(ui.painter().round_to_pixel(x_max).max(x_min + 1.0), y_min + ROW_HEIGHT - 1.0),
+        };
+
+        let hovered = pointer_pos.map_or(false, |pos| bar.contains(pos));
+        ui.painter().add(Shape::Rect {
+            rect: bar,
+            corner_radius: 2.0,
+            fill: scope_color(&palette, &scope.label),
+            stroke: if hovered {
+                Stroke::new(1.0, ui.visuals().strong_text_color())
+            } else {
+                Default::default()
+            },
+        });
+        ui.painter().sub_region(bar).text(
+            bar.left_center() + vec2(2.0, 0.0),
+            Align2::LEFT_CENTER,
+            &scope.label,
+            TextStyle::Small,
+            Color32::BLACK,
+        );
+
+        if hovered {
+            tooltip = Some((
+                pointer_pos.unwrap(),
+                format!("{}\n{}", scope.label, formatting::seconds(scope.time.end - scope.time.start)),
+            ));
+        }
+        stack.extend(scope.nested_scopes.iter().map(|nested| (nested, depth + 1)));
+    }
+
+    // Drawn last so it is on top of all bars.
+    if let Some((pos, text)) = tooltip {
+        ui.painter().add(Shape::text(
+            ui.fonts(),
+            pos,
+            egui::Align2::LEFT_BOTTOM,
+            text,
+            TextStyle::Body,
+            ui.visuals().strong_text_color(),
+        ));
+    }
+
+    response
+}
+
+// Drag to tilt a (mostly) downward pointing direction, like tilting a tank of fluid.
+// Shows the lower hemisphere seen from above: the center is straight down (-y), the rim is horizontal, +x right and +z down.
+// Upward pointing directions can't be picked but are shown on the projected position as well.
+pub fn tilt_gizmo(ui: &mut egui::Ui, radius: f32, direction: &mut cgmath::Vector3<f32>) -> egui::Response {
+    let (rect, mut response) = ui.allocate_exact_size(Vec2::splat(radius * 2.0), Sense::drag());
+    let center = rect.center();
+    if let Some(pointer_pos) = response.interact_pointer_pos() {
+        let mut offset = (pointer_pos - center) / radius;
+        if offset.length() > 1.0 {
+            offset = offset.normalized();
+        }
+        *direction = cgmath::vec3(offset.x, -(1.0 - offset.length_sq()).max(0.0).sqrt(), offset.y);
+        response.mark_changed();
+    }
+
+    let painter = ui.painter();
+    let visuals = ui.style().interact(&response);
+    painter.circle_filled(center, radius, ui.visuals().extreme_bg_color);
+    painter.circle_stroke(center, radius, visuals.bg_stroke);
+    // Guides for 30° and 60° tilt.
+    for tilt_degrees in [30.0_f32, 60.0].iter() {
+        painter.circle_stroke(
+            center,
+            radius * tilt_degrees.to_radians().sin(),
+            ui.visuals().widgets.noninteractive.bg_stroke,
+        );
+    }
+    let tip = center + vec2(direction.x, direction.z) * radius;
+    painter.line_segment([center, tip], visuals.fg_stroke);
+    if direction.y > 0.0 {
+        painter.circle_stroke(tip, 4.0, visuals.fg_stroke);
+    } else {
+        painter.circle_filled(tip, 4.0, visuals.fg_stroke.color);
+    }
+
+    response
+}
+
+fn xyz_drag(ui: &mut egui::Ui, label: &str, x: &mut f32, y: &mut f32, z: &mut f32) {
+    ui.horizontal(|ui| {
+        ui.label(label);
+        ui.add(egui::DragValue::new(x).speed(0.01).prefix("x: "));
+        ui.add(egui::DragValue::new(y).speed(0.01).prefix("y: "));
+        ui.add(egui::DragValue::new(z).speed(0.01).prefix("z: "));
+    });
+}
+
+pub fn vector_drag(ui: &mut egui::Ui, label: &str, v: &mut cgmath::Vector3<f32>) {
+    xyz_drag(ui, label, &mut v.x, &mut v.y, &mut v.z);
+}
+
+pub fn point_drag(ui: &mut egui::Ui, label: &str, p: &mut cgmath::Point3<f32>) {
+    xyz_drag(ui, label, &mut p.x, &mut p.y, &mut p.z);
+}
+
+fn linear_to_srgb(linear: f32) -> u8 {
+    let linear = linear.max(0.0).min(1.0);
+    let srgb = if linear <= 0.0031308 {
+        linear * 12.92
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    };
+    (srgb * 255.0).round() as u8
+}
+
+fn srgb_to_linear(srgb: u8) -> f32 {
+    let srgb = srgb as f32 / 255.0;
+    if srgb <= 0.04045 {
+        srgb / 12.92
+    } else {
+        ((srgb + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+// Color picker for a linear rgb color (egui's picker works in sRGB). Values above one are clamped once the color is edited.
+pub fn color_edit_button_linear(ui: &mut egui::Ui, color: &mut cgmath::Vector3<f32>) -> egui::Response {
+    let mut srgb = [linear_to_srgb(color.x), linear_to_srgb(color.y), linear_to_srgb(color.z)];
+    let response = ui.color_edit_button_srgb(&mut srgb);
+    if response.changed() {
+        *color = cgmath::vec3(srgb_to_linear(srgb[0]), srgb_to_linear(srgb[1]), srgb_to_linear(srgb[2]));
+    }
+    response
+}
@@ -0,0 +1,123 @@
+use crate::render_output::screen::Screen;
+use winit::{
+    event_loop::EventLoopWindowTarget,
+    window::{Window, WindowBuilder, WindowId},
+};
+
+// Additional OS window that hosts parts of the gui, e.g. the profiler, so they don't cover the main viewport.
+//
+// Has its own surface, swap chain and egui context but shares the device with the main window.
+// Unlike the main window there are no intermediate render targets, egui draws directly into the swap chain.
+pub struct DetachedWindow {
+    window: Window,
+    surface: wgpu::Surface,
+    swap_chain: wgpu::SwapChain,
+    swap_chain_resolution: winit::dpi::PhysicalSize<u32>,
+
+    platform: egui_winit_platform::Platform,
+    render_pass: egui_wgpu_backend::RenderPass,
+}
+
+impl DetachedWindow {
+    pub fn new<T>(
+        title: &str,
+        event_loop: &EventLoopWindowTarget<T>,
+        wgpu_instance: &wgpu::Instance,
+        device: &wgpu::Device,
+        style: egui::Style,
+        font_definitions: egui::FontDefinitions,
+    ) -> Result<Self, winit::error::OsError> {
+        let window = WindowBuilder::new()
+            .with_title(title)
+            .with_resizable(true)
+            .with_inner_size(winit::dpi::LogicalSize::new(500, 800))
+            .build(event_loop)?;
+        let surface = unsafe { wgpu_instance.create_surface(&window) };
+        let swap_chain_resolution = window.inner_size();
+        let swap_chain = Self::create_swap_chain(device, &surface, swap_chain_resolution);
+
+        let platform = egui_winit_platform::Platform::new(egui_winit_platform::PlatformDescriptor {
+            physical_width: swap_chain_resolution.width,
+            physical_height: swap_chain_resolution.height,
+            scale_factor: window.scale_factor(),
+            font_definitions,
+            style,
+        });
+        let render_pass = egui_wgpu_backend::RenderPass::new(device, Screen::FORMAT_SWAPCHAIN, 1);
+
+        Ok(DetachedWindow {
+            window,
+            surface,
+            swap_chain,
+            swap_chain_resolution,
+            platform,
+            render_pass,
+        })
+    }
+
+    fn create_swap_chain(device: &wgpu::Device, surface: &wgpu::Surface, resolution: winit::dpi::PhysicalSize<u32>) -> wgpu::SwapChain {
+        device.create_swap_chain(
+            surface,
+            &wgpu::SwapChainDescriptor {
+                usage: wgpu::TextureUsage::RENDER_ATTACHMENT,
+                format: Screen::FORMAT_SWAPCHAIN,
+                width: resolution.width,
+                height: resolution.height,
+                // Never hold up the main window by waiting for this one's vblank.
+                present_mode: wgpu::PresentMode::Mailbox,
+            },
+        )
+    }
+
+    pub fn id(&self) -> WindowId {
+        self.window.id()
+    }
+
+    // Only pass events that belong to this window.
+    pub fn handle_event<T>(&mut self, winit_event: &winit::event::Event<T>) {
+        self.platform.handle_event(winit_event);
+    }
+
+    // Sets up the ui with the given function, then renders and presents it right away.
+    pub fn draw(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, setup_ui: impl FnOnce(&egui::CtxRef)) {
+        let window_size = self.window.inner_size();
+        if window_size.width == 0 || window_size.height == 0 {
+            return;
+        }
+        if window_size != self.swap_chain_resolution {
+            self.swap_chain_resolution = window_size;
+            self.swap_chain = Self::create_swap_chain(device, &self.surface, window_size);
+        }
+
+        self.platform.begin_frame();
+        setup_ui(&self.platform.context());
+        let (_output, paint_commands) = self.platform.end_frame();
+        let paint_jobs = self.platform.context().tessellate(paint_commands);
+
+        let frame = match self.swap_chain.get_current_frame() {
+            Ok(frame) => frame.output,
+            Err(err) => {
+                warn!("Skipping frame of detached window, failed to acquire swap chain frame: {}", err);
+                self.swap_chain = Self::create_swap_chain(device, &self.surface, self.swap_chain_resolution);
+                return;
+            }
+        };
+
+        let screen_descriptor = egui_wgpu_backend::ScreenDescriptor {
+            physical_width: self.swap_chain_resolution.width,
+            physical_height: self.swap_chain_resolution.height,
+            scale_factor: self.window.scale_factor() as f32,
+        };
+        self.render_pass.update_texture(device, queue, &self.platform.context().texture());
+        self.render_pass.update_user_textures(device, queue);
+        self.render_pass.update_buffers(device, queue, &paint_jobs, &screen_descriptor);
+
+        // Separate submit, since the frame needs to be presented independently of the main window's.
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Encoder: Detached window"),
+        });
+        self.render_pass
+            .execute(&mut encoder, &frame.view, &paint_jobs, &screen_descriptor, Some(wgpu::Color::BLACK));
+        queue.submit(Some(encoder.finish()));
+    }
+}
@@ -0,0 +1,64 @@
+// Consistent display of durations, counts & memory sizes.
+//
+// Everything goes through here so that switching to a decimal comma (handy for screenshots in german publications)
+// affects all numbers shown.
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+static DECIMAL_COMMA: AtomicBool = AtomicBool::new(false);
+
+pub fn set_decimal_comma(enabled: bool) {
+    DECIMAL_COMMA.store(enabled, Ordering::Relaxed);
+}
+
+// Formats a number with fixed precision, using the configured decimal separator.
+pub fn decimal(value: f64, decimals: usize) -> String {
+    let text = format!("{:.*}", decimals, value);
+    if DECIMAL_COMMA.load(Ordering::Relaxed) {
+        text.replace('.', ",")
+    } else {
+        text
+    }
+}
+
+// Picks µs/ms/s depending on magnitude.
+pub fn seconds(seconds: f64) -> String {
+    if seconds < 0.001 {
+        format!("{} µs", decimal(seconds * 1000.0 * 1000.0, 1))
+    } else if seconds < 1.0 {
+        format!("{} ms", decimal(seconds * 1000.0, 2))
+    } else {
+        format!("{} s", decimal(seconds, 2))
+    }
+}
+
+pub fn duration(duration: Duration) -> String {
+    seconds(duration.as_secs_f64())
+}
+
+// Large counts (particles, cells, ...) with K/M suffix.
+pub fn count(count: u64) -> String {
+    if count < 1000 {
+        format!("{}", count)
+    } else if count < 1000 * 1000 {
+        format!("{}K", decimal(count as f64 / 1000.0, 1))
+    } else {
+        format!("{}M", decimal(count as f64 / (1000.0 * 1000.0), 2))
+    }
+}
+
+pub fn bytes(num_bytes: u64) -> String {
+    const KIB: f64 = 1024.0;
+    let num_bytes_f = num_bytes as f64;
+    if num_bytes_f < KIB {
+        format!("{} B", num_bytes)
+    } else if num_bytes_f < KIB * KIB {
+        format!("{} KiB", decimal(num_bytes_f / KIB, 1))
+    } else if num_bytes_f < KIB * KIB * KIB {
+        format!("{} MiB", decimal(num_bytes_f / (KIB * KIB), 1))
+    } else {
+        format!("{} GiB", decimal(num_bytes_f / (KIB * KIB * KIB), 2))
+    }
+}
@@ -0,0 +1,90 @@
+use crate::log_buffer;
+
+// Window listing the latest log messages, so users don't need to look at the terminal to see what went wrong.
+pub struct LogConsole {
+    visible: bool,
+    show_levels: [bool; 5], // indexed by log::Level - 1 (Error, Warn, Info, Debug, Trace)
+    num_errors_seen: usize,
+}
+
+impl LogConsole {
+    pub fn new() -> Self {
+        LogConsole {
+            visible: false,
+            show_levels: [true, true, true, false, false],
+            num_errors_seen: 0,
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    fn level_color(level: log::Level, visuals: &egui::Visuals) -> egui::Color32 {
+        match level {
+            log::Level::Error => egui::Color32::from_rgb(255, 80, 80),
+            log::Level::Warn => egui::Color32::from_rgb(255, 200, 60),
+            log::Level::Info => visuals.text_color(),
+            log::Level::Debug | log::Level::Trace => visuals.weak_text_color(),
+        }
+    }
+
+    pub fn setup_ui(&mut self, ctx: &egui::CtxRef) {
+        // Copy out what we need, logging while the lock is held would deadlock.
+        let entries: Vec<(log::Level, String)> = {
+            let entries = log_buffer::entries();
+
+            // Pop up on new errors.
+            let num_errors = entries.iter().filter(|entry| entry.level == log::Level::Error).count();
+            if num_errors > self.num_errors_seen {
+                self.visible = true;
+            }
+            self.num_errors_seen = num_errors;
+
+            if !self.visible {
+                return;
+            }
+            entries
+                .iter()
+                .filter(|entry| self.show_levels[entry.level as usize - 1])
+                .map(|entry| {
+                    (
+                        entry.level,
+                        format!("{} {:5} [{}] {}", entry.timestamp, entry.level, entry.target, entry.message),
+                    )
+                })
+                .collect()
+        };
+
+        let mut visible = self.visible;
+        egui::Window::new("Log")
+            .open(&mut visible)
+            .default_size([600.0, 300.0])
+            .resizable(true)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    for level in [
+                        log::Level::Error,
+                        log::Level::Warn,
+                        log::Level::Info,
+                        log::Level::Debug,
+                        log::Level::Trace,
+                    ]
+                    .iter()
+                    {
+                        ui.checkbox(&mut self.show_levels[*level as usize - 1], level.as_str());
+                    }
+                    if ui.button("Copy").clicked() {
+                        ui.output().copied_text = entries.iter().map(|(_, line)| line.as_str()).collect::<Vec<_>>().join("\n");
+                    }
+                });
+                ui.separator();
+                egui::ScrollArea::auto_sized().show(ui, |ui| {
+                    for (level, line) in entries.iter() {
+                        ui.add(egui::Label::new(line).monospace().text_color(Self::level_color(*level, ui.visuals())));
+                    }
+                });
+            });
+        self.visible = visible;
+    }
+}
@@ -1,25 +1,65 @@
+use crate::fluid_interaction::{FluidInteraction, InteractionMode};
 use crate::simulation_controller::{SimulationController, SimulationControllerStatus};
 use crate::{
-    render_output::screen::Screen,
-    simulation::{HybridFluid, SolverConfig, SolverStatisticSample},
+    render_output::{
+        offscreen_target::OffscreenTargetDesc,
+        recording_overlay::RecordingOverlaySettings,
+        screen::{ColorSpaceDebugMode, Screen},
+        screenshot_capture::ScreenshotFormat,
+    },
+    simulation::{AdvectionScheme, HybridFluid, ParticleBinningMethod, PlaybackCache, SmokeSimulation, SolverConfig, SolverStatisticSample},
+    wgpu_utils::{
+        profiling::ProfilingDetail,
+        shader::{ShaderCacheWarming, ShaderCompileError},
+    },
     ApplicationEvent,
 };
 use crate::{
-    renderer::{FluidRenderingMode, SceneRenderer, VolumeVisualizationMode},
-    scene::Scene,
+    renderer::{
+        FluidRenderingMode, MeshDebugMode, ObjectPicking, ParticleLodMode, SceneRenderer, ScreenSpaceFluidDepthFilter, ScreenSpaceFluidQuality,
+        ScreenSpaceFluidResolution, ScreenSpaceFluidSettings, VolumeVisualizationMode,
+    },
+    scene::{
+        force_fields::{ForceField, ForceFieldConfig},
+        lights::{Light, LightConfig},
+        FluidSymmetry, Scene,
+    },
 };
 use std::{
     collections::VecDeque,
     path::{Path, PathBuf},
-    time::Duration,
+    time::{Duration, Instant},
 };
 use strum::IntoEnumIterator;
 use wgpu_profiler::GpuTimerScopeResult;
-use winit::event_loop::EventLoopProxy;
+use winit::{
+    event_loop::{EventLoopProxy, EventLoopWindowTarget},
+    window::WindowId,
+};
 
 mod custom_widgets;
+mod detached_window;
+pub mod formatting;
+mod log_console;
+mod onboarding;
+mod persistent_settings;
+mod profiler_history;
+mod recent_scenes;
+pub mod scene_thumbnails;
+mod theme;
+
+use detached_window::DetachedWindow;
+use log_console::LogConsole;
+use onboarding::Onboarding;
+use persistent_settings::PersistentSettings;
+use profiler_history::ProfilerHistory;
+use recent_scenes::RecentScenes;
+use scene_thumbnails::SceneThumbnails;
+use theme::Theme;
 
 const SCENE_DIRECTORY: &str = "scenes";
+const PROFILER_HISTORY_LENGTH: usize = 128;
+const HUD_MESSAGE_DURATION: Duration = Duration::from_millis(1500);
 
 fn list_scene_files() -> Vec<PathBuf> {
     let files: Vec<PathBuf> = std::fs::read_dir(SCENE_DIRECTORY)
@@ -37,18 +77,62 @@ fn list_scene_files() -> Vec<PathBuf> {
     files
 }
 
+fn scene_display_name(scene_file: &Path) -> String {
+    let scene_directory = Path::new(SCENE_DIRECTORY)
+        .canonicalize()
+        .unwrap_or_else(|_| PathBuf::from(SCENE_DIRECTORY));
+    let scene_file = scene_file.canonicalize().unwrap_or_else(|_| scene_file.to_path_buf());
+    format!("{:?}", scene_file.strip_prefix(&scene_directory).unwrap_or(&scene_file))
+}
+
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
 pub struct GUIState {
     fast_forward_length_seconds: f32,
     video_fps: i32,
+    video_offscreen: bool,
+    video_resolution: [u32; 2],
+    video_supersampling: u32,
+    screenshot_format: ScreenshotFormat,
+    recording_output_dir: String,
+    // Empty for recording without time scale changes.
+    recording_schedule_path: String,
+    recording_overlay: RecordingOverlaySettings,
     selected_scene_idx: usize,
     known_scene_files: Vec<PathBuf>,
-    wait_for_vblank: bool,
+    scene_thumbnails: SceneThumbnails,
+    present_mode: wgpu::PresentMode,
+    frame_limit_enabled: bool,
+    frame_limit_fps: u32,
+    grid_export_enabled: bool,
+    grid_export_interval: f32, // simulated seconds
+    color_space_debug_mode: ColorSpaceDebugMode,
+    // As detected by Screen, see set_present_modes.
+    supported_present_modes: Vec<wgpu::PresentMode>,
 
-    profiling_data_rendering: Vec<GpuTimerScopeResult>,
-    profiling_data_simulation: Vec<GpuTimerScopeResult>,
+    profiler_history_rendering: ProfilerHistory,
+    profiler_history_simulation: ProfilerHistory,
+    // Last measured gpu time of each ParticleBinningMethod (in iteration order), for comparing them.
+    particle_binning_durations: Vec<Option<f64>>,
 
     show_profiling_data_rendering: bool,
     show_profiling_data_simulation: bool,
+    // Otherwise profiling falls back to FenceProfiler.
+    timestamp_queries_supported: bool,
+
+    hud_message: Option<(String, Instant)>,
+    // Name of the scene that is loading in the background and for how long, see set_scene_loading.
+    scene_loading: Option<(String, Duration)>,
+    shader_compile_errors: Vec<ShaderCompileError>,
+    // Edited by the ui scale slider, only applied once the slider is let go since the slider would jump otherwise.
+    ui_scale: f32,
+    // Particle seed while it is being dragged, applied once let go since every change resets the scene.
+    seed_edit: Option<u64>,
 }
 
 pub struct GUI {
@@ -56,6 +140,15 @@ pub struct GUI {
     render_pass: egui_wgpu_backend::RenderPass,
 
     state: GUIState,
+    settings: PersistentSettings,
+    recent_scenes: RecentScenes,
+    onboarding: Onboarding,
+    log_console: LogConsole,
+
+    // If present, the profiler is shown in this window instead of the main one.
+    profiler_window: Option<DetachedWindow>,
+    // OS scale factor times ui scale, as last passed to the platform.
+    scale_factor: f64,
 }
 
 struct DummyRepaintSignal;
@@ -64,44 +157,463 @@ impl epi::RepaintSignal for DummyRepaintSignal {
 }
 
 impl GUI {
-    pub fn new(device: &wgpu::Device, window: &winit::window::Window) -> Self {
-        let mut style = egui::Style::default();
-        style.visuals.code_bg_color = egui::Color32::from_rgb(64, 64, 100);
+    fn font_definitions(font_size: f32) -> egui::FontDefinitions {
+        let mut font_definitions = egui::FontDefinitions::default();
+        for (_, size) in font_definitions.family_and_size.values_mut() {
+            *size *= font_size / PersistentSettings::DEFAULT_FONT_SIZE;
+        }
+        font_definitions
+    }
 
+    pub fn new(device: &wgpu::Device, window: &winit::window::Window) -> Self {
+        let settings = PersistentSettings::load();
+        let ui_scale = settings.ui_scale;
+        let scale_factor = window.scale_factor() * ui_scale as f64;
         let platform = egui_winit_platform::Platform::new(egui_winit_platform::PlatformDescriptor {
             physical_width: window.inner_size().width as u32,
             physical_height: window.inner_size().height as u32,
-            scale_factor: window.scale_factor(),
-            font_definitions: egui::FontDefinitions::default(),
-            style,
+            scale_factor,
+            font_definitions: Self::font_definitions(settings.font_size),
+            style: settings.theme.style(),
         });
 
         let render_pass = egui_wgpu_backend::RenderPass::new(device, Screen::FORMAT_BACKBUFFER, 1);
 
+        formatting::set_decimal_comma(settings.decimal_comma);
+        theme::set_plot_palette(&settings.plot_palette);
+        let onboarding = Onboarding::new(&settings);
+
         GUI {
             platform,
             render_pass,
+            settings,
+            recent_scenes: RecentScenes::load(),
+            onboarding,
+            log_console: LogConsole::new(),
+            profiler_window: None,
+            scale_factor,
             state: GUIState {
                 fast_forward_length_seconds: 5.0,
                 video_fps: 60,
+                video_offscreen: false,
+                video_resolution: [3840, 2160],
+                video_supersampling: 2,
+                screenshot_format: ScreenshotFormat::Png,
+                recording_output_dir: "recordings".to_string(),
+                recording_schedule_path: String::new(),
+                recording_overlay: Default::default(),
                 selected_scene_idx: 0,
                 known_scene_files: list_scene_files(),
-                wait_for_vblank: Screen::DEFAULT_PRESENT_MODE == wgpu::PresentMode::Fifo,
+                scene_thumbnails: SceneThumbnails::new(),
+                present_mode: Screen::DEFAULT_PRESENT_MODE,
+                frame_limit_enabled: false,
+                frame_limit_fps: 60,
+                grid_export_enabled: false,
+                grid_export_interval: 0.1,
+                color_space_debug_mode: ColorSpaceDebugMode::None,
+                supported_present_modes: vec![Screen::DEFAULT_PRESENT_MODE],
 
-                profiling_data_rendering: Vec::new(),
-                profiling_data_simulation: Vec::new(),
+                profiler_history_rendering: ProfilerHistory::new(PROFILER_HISTORY_LENGTH),
+                profiler_history_simulation: ProfilerHistory::new(PROFILER_HISTORY_LENGTH),
+                particle_binning_durations: vec![None; ParticleBinningMethod::iter().count()],
+                timestamp_queries_supported: device.features().contains(wgpu::Features::TIMESTAMP_QUERY),
                 show_profiling_data_rendering: false,
                 show_profiling_data_simulation: false,
+
+                hud_message: None,
+                scene_loading: None,
+                shader_compile_errors: Vec::new(),
+                ui_scale,
+                seed_edit: None,
             },
         }
     }
 
+    // Gui state survives, only the gpu resources of the egui renderer are recreated.
+    // The profiler window's surface belongs to the old device as well, so it is simply closed.
+    pub fn on_device_recreated(&mut self, device: &wgpu::Device) {
+        self.render_pass = egui_wgpu_backend::RenderPass::new(device, Screen::FORMAT_BACKBUFFER, 1);
+        self.state.scene_thumbnails.clear();
+        self.state.timestamp_queries_supported = device.features().contains(wgpu::Features::TIMESTAMP_QUERY);
+        self.profiler_window = None;
+    }
+
+    // Window events are passed on to the gui of the window they belong to.
     pub fn handle_event<T>(&mut self, winit_event: &winit::event::Event<T>) {
+        if let winit::event::Event::WindowEvent { window_id, .. } = winit_event {
+            if let Some(profiler_window) = self.profiler_window.as_mut() {
+                if profiler_window.id() == *window_id {
+                    profiler_window.handle_event(winit_event);
+                    return;
+                }
+            }
+        }
+        // The platform's scale factor is the OS scale factor with the ui scale applied on top.
+        if let winit::event::Event::WindowEvent {
+            window_id,
+            event: winit::event::WindowEvent::ScaleFactorChanged {
+                scale_factor,
+                new_inner_size,
+            },
+        } = winit_event
+        {
+            self.scale_factor = scale_factor * self.settings.ui_scale as f64;
+            let mut new_inner_size = **new_inner_size;
+            self.platform.handle_event::<T>(&winit::event::Event::WindowEvent {
+                window_id: *window_id,
+                event: winit::event::WindowEvent::ScaleFactorChanged {
+                    scale_factor: self.scale_factor,
+                    new_inner_size: &mut new_inner_size,
+                },
+            });
+            return;
+        }
         self.platform.handle_event(winit_event);
     }
 
-    pub fn selected_scene(&self) -> &PathBuf {
-        &self.state.known_scene_files[self.state.selected_scene_idx]
+    // Passes a scale factor change to the platform if the ui scale changed since the last frame.
+    fn update_scale_factor(&mut self, window: &winit::window::Window) {
+        let scale_factor = window.scale_factor() * self.settings.ui_scale as f64;
+        if scale_factor == self.scale_factor {
+            return;
+        }
+        self.scale_factor = scale_factor;
+        let mut inner_size = window.inner_size();
+        self.platform.handle_event::<()>(&winit::event::Event::WindowEvent {
+            window_id: window.id(),
+            event: winit::event::WindowEvent::ScaleFactorChanged {
+                scale_factor,
+                new_inner_size: &mut inner_size,
+            },
+        });
+    }
+
+    pub fn open_profiler_window<T>(&mut self, event_loop: &EventLoopWindowTarget<T>, wgpu_instance: &wgpu::Instance, device: &wgpu::Device) {
+        if self.profiler_window.is_some() {
+            return;
+        }
+        match DetachedWindow::new(
+            "Blub - Profiler",
+            event_loop,
+            wgpu_instance,
+            device,
+            self.settings.theme.style(),
+            Self::font_definitions(self.settings.font_size),
+        ) {
+            Ok(window) => self.profiler_window = Some(window),
+            Err(err) => error!("Failed to open profiler window: {}", err),
+        }
+    }
+
+    pub fn close_profiler_window(&mut self) {
+        self.profiler_window = None;
+    }
+
+    // Whether the given window is one of the gui's own windows, i.e. not the main window.
+    pub fn owns_window(&self, window_id: WindowId) -> bool {
+        self.profiler_window.as_ref().map_or(false, |window| window.id() == window_id)
+    }
+
+    // Scenes to try in order if none was given on the command line (or it fails to load):
+    // the recently used ones, most recent first, followed by all in the scene directory.
+    pub fn startup_scene_candidates(&self) -> Vec<PathBuf> {
+        let mut candidates: Vec<PathBuf> = Vec::new();
+        for scene in self.recent_scenes.iter().chain(self.state.known_scene_files.iter()) {
+            if !candidates.iter().any(|candidate| is_same_file(candidate, scene)) {
+                candidates.push(scene.clone());
+            }
+        }
+        candidates
+    }
+
+    pub fn on_scene_loaded(&mut self, scene_path: &Path) {
+        self.recent_scenes.add(scene_path);
+
+        // Scenes from outside the scene directory (command line, recent list) are added to the selection.
+        let known_scene_files = &mut self.state.known_scene_files;
+        self.state.selected_scene_idx = match known_scene_files.iter().position(|known| is_same_file(known, scene_path)) {
+            Some(idx) => idx,
+            None => {
+                known_scene_files.push(scene_path.to_path_buf());
+                known_scene_files.len() - 1
+            }
+        };
+    }
+
+    // Thumbnail is written asynchronously, it shows up in the scene selection once it is on disk.
+    pub fn on_scene_thumbnail_captured(&mut self, scene_path: &Path) {
+        let scene_file = match self.state.known_scene_files.iter().find(|known| is_same_file(known, scene_path)) {
+            Some(known) => known.clone(),
+            None => scene_path.to_path_buf(),
+        };
+        self.state.scene_thumbnails.invalidate(&scene_file, &mut self.render_pass);
+    }
+
+    pub fn pause_in_background(&self) -> bool {
+        self.settings.pause_in_background
+    }
+
+    // Target frame rate of the event loop, None if unlimited.
+    pub fn frame_limit(&self) -> Option<u32> {
+        if self.state.frame_limit_enabled {
+            Some(self.state.frame_limit_fps)
+        } else {
+            None
+        }
+    }
+
+    // Simulated time between grid exports, None if exporting is disabled. See GridExporter
+    pub fn grid_export_interval(&self) -> Option<Duration> {
+        if self.state.grid_export_enabled {
+            Some(Duration::from_secs_f32(self.state.grid_export_interval))
+        } else {
+            None
+        }
+    }
+
+    pub fn disable_grid_export(&mut self) {
+        self.state.grid_export_enabled = false;
+    }
+
+    pub fn color_space_debug_mode(&self) -> ColorSpaceDebugMode {
+        self.state.color_space_debug_mode
+    }
+
+    pub fn window_title_stats(&self) -> bool {
+        self.settings.window_title_stats
+    }
+
+    pub fn wants_keyboard_input(&self) -> bool {
+        self.platform.context().wants_keyboard_input()
+    }
+
+    pub fn wants_pointer_input(&self) -> bool {
+        self.platform.context().wants_pointer_input()
+    }
+
+    pub fn fast_forward_length(&self) -> Duration {
+        Duration::from_secs_f32(self.state.fast_forward_length_seconds)
+    }
+
+    pub fn video_fps(&self) -> f64 {
+        self.state.video_fps as f64
+    }
+
+    pub fn recording_output_dir(&self) -> PathBuf {
+        PathBuf::from(&self.state.recording_output_dir)
+    }
+
+    pub fn screenshot_format(&self) -> ScreenshotFormat {
+        self.state.screenshot_format
+    }
+
+    pub fn recording_schedule_path(&self) -> Option<PathBuf> {
+        if self.state.recording_schedule_path.is_empty() {
+            None
+        } else {
+            Some(PathBuf::from(&self.state.recording_schedule_path))
+        }
+    }
+
+    pub fn recording_overlay_settings(&self) -> RecordingOverlaySettings {
+        self.state.recording_overlay
+    }
+
+    pub fn recording_offscreen_target_desc(&self) -> Option<OffscreenTargetDesc> {
+        Self::offscreen_target_desc(&self.state)
+    }
+
+    fn offscreen_target_desc(state: &GUIState) -> Option<OffscreenTargetDesc> {
+        if state.video_offscreen {
+            Some(OffscreenTargetDesc {
+                resolution: winit::dpi::PhysicalSize::new(state.video_resolution[0], state.video_resolution[1]),
+                supersampling: state.video_supersampling,
+            })
+        } else {
+            None
+        }
+    }
+
+    // Present modes offered in the gui and the one that is actually in use, needs to be updated whenever the screen changes its present mode.
+    pub fn set_present_modes(&mut self, supported_present_modes: &[wgpu::PresentMode], present_mode: wgpu::PresentMode) {
+        self.state.supported_present_modes = supported_present_modes.to_vec();
+        self.state.present_mode = present_mode;
+    }
+
+    // Shows a short message on top of the screen that fades out after a moment. Used to confirm hotkey actions.
+    pub fn show_hud_message(&mut self, message: String) {
+        self.state.hud_message = Some((message, Instant::now()));
+    }
+
+    // Needs to be updated every frame while a scene loads in the background.
+    pub fn set_scene_loading(&mut self, scene_loading: Option<(&Path, Duration)>) {
+        self.state.scene_loading =
+            scene_loading.map(|(path, duration)| (path.file_stem().unwrap_or_default().to_string_lossy().to_string(), duration));
+    }
+
+    pub fn set_shader_compile_errors(&mut self, errors: Vec<ShaderCompileError>) {
+        self.state.shader_compile_errors = errors;
+    }
+
+    // Failed shader compiles would otherwise go unnoticed apart from the log, since pipelines silently keep their last working version.
+    fn setup_ui_shader_compile_errors(ctx: &egui::CtxRef, errors: &[ShaderCompileError]) {
+        if errors.is_empty() {
+            return;
+        }
+        egui::Area::new("shader compile errors")
+            .anchor(egui::Align2::CENTER_BOTTOM, [0.0, -20.0])
+            .show(ctx, |ui| {
+                egui::Frame::popup(&ctx.style())
+                    .fill(egui::Color32::from_rgba_unmultiplied(60, 0, 0, 230))
+                    .stroke(egui::Stroke::new(2.0, egui::Color32::from_rgb(255, 80, 80)))
+                    .show(ui, |ui| {
+                        ui.add(
+                            egui::Label::new("Shader compilation failed, using previous pipelines")
+                                .heading()
+                                .text_color(egui::Color32::from_rgb(255, 80, 80)),
+                        );
+                        for error in errors.iter() {
+                            let location = match error.line {
+                                Some(line) => format!("{}:{}", error.file, line),
+                                None => error.file.clone(),
+                            };
+                            ui.add(egui::Label::new(location).strong().text_color(egui::Color32::WHITE));
+                            ui.add(egui::Label::new(&error.message).monospace().text_color(egui::Color32::from_gray(220)));
+                        }
+                    });
+            });
+    }
+
+    // Values of the voxel slice cell under the cursor, shown next to the cursor.
+    fn setup_ui_grid_cell_values(ctx: &egui::CtxRef, scene_renderer: &SceneRenderer) {
+        if !scene_renderer.voxel_slice.enabled || !scene_renderer.voxel_slice.inspect {
+            return;
+        }
+        let (cell_values, pointer_pos) = match (scene_renderer.grid_cell_inspector.values(), ctx.input().pointer.hover_pos()) {
+            (Some(cell_values), Some(pointer_pos)) => (cell_values, pointer_pos),
+            _ => return,
+        };
+        egui::Area::new("grid cell values")
+            .fixed_pos(pointer_pos + egui::vec2(16.0, 16.0))
+            .interactable(false)
+            .show(ctx, |ui| {
+                egui::Frame::popup(&ctx.style()).show(ui, |ui| {
+                    ui.label(format!("cell {} {} {}", cell_values.cell.x, cell_values.cell.y, cell_values.cell.z));
+                    egui::Grid::new("grid cell values grid").show(ui, |ui| {
+                        for (name, value) in cell_values.values.iter() {
+                            ui.label(*name);
+                            match value {
+                                Some(value) => ui.monospace(format!("{:+.6e}", value)),
+                                None => ui.monospace("n/a"),
+                            };
+                            ui.end_row();
+                        }
+                    });
+                });
+            });
+    }
+
+    fn setup_ui_appearance(ui: &mut egui::Ui, state: &mut GUIState, settings: &mut PersistentSettings) {
+        let mut changed = false;
+
+        ui.horizontal(|ui| {
+            for theme in Theme::iter() {
+                if ui.radio_value(&mut settings.theme, theme, format!("{:?}", theme)).changed() {
+                    ui.ctx().set_style(theme.style());
+                    changed = true;
+                }
+            }
+        });
+        let response = ui.add(egui::Slider::new(&mut state.ui_scale, 0.5..=3.0).text("ui scale"));
+        if response.drag_released() || (response.changed() && !response.dragged()) {
+            settings.ui_scale = state.ui_scale;
+            changed = true;
+        }
+        if ui.add(egui::Slider::new(&mut settings.font_size, 8.0..=32.0).text("font size")).changed() {
+            ui.ctx().set_fonts(Self::font_definitions(settings.font_size));
+            changed = true;
+        }
+        // Not persisted, only meant for checking whether an image is off due to a color space mistake.
+        egui::ComboBox::from_label("color space debug")
+            .selected_text(format!("{:?}", state.color_space_debug_mode))
+            .show_ui(ui, |ui| {
+                for mode in ColorSpaceDebugMode::iter() {
+                    ui.selectable_value(&mut state.color_space_debug_mode, mode, format!("{:?}", mode));
+                }
+            });
+
+        ui.separator();
+        ui.label("Plot colors");
+        let palette = &mut settings.plot_palette;
+        let mut palette_changed = false;
+        ui.horizontal(|ui| {
+            palette_changed |= ui.color_edit_button_srgb(&mut palette.bar).changed();
+            ui.label("bars");
+            palette_changed |= ui.color_edit_button_srgb(&mut palette.bar_highlight).changed();
+            ui.label("highlighted bar");
+        });
+        ui.horizontal(|ui| {
+            for color in palette.categories.iter_mut() {
+                palette_changed |= ui.color_edit_button_srgb(color).changed();
+            }
+            ui.label("categories");
+        });
+        if ui
+            .button("Reset to colorblind-safe defaults")
+            .on_hover_text("Okabe-Ito palette")
+            .clicked()
+        {
+            *palette = Default::default();
+            palette_changed = true;
+        }
+        if palette_changed {
+            theme::set_plot_palette(palette);
+            changed = true;
+        }
+
+        if changed {
+            settings.save();
+        }
+    }
+
+    fn setup_ui_hud(ctx: &egui::CtxRef, state: &mut GUIState) {
+        if let Some((scene_name, duration)) = &state.scene_loading {
+            // There is no way of knowing how long loading takes, so instead of a progress bar there's a dot bouncing back and forth.
+            let phase = (duration.as_secs_f32() * 2.0).fract();
+            let phase = if phase < 0.5 { phase * 2.0 } else { 2.0 - phase * 2.0 };
+            egui::Area::new("scene loading")
+                .anchor(egui::Align2::CENTER_BOTTOM, [0.0, -20.0])
+                .interactable(false)
+                .show(ctx, |ui| {
+                    egui::Frame::popup(&ctx.style()).show(ui, |ui| {
+                        ui.add(egui::Label::new(format!("Loading {}...", scene_name)).heading());
+                        ui.add(egui::ProgressBar::new(phase).text(formatting::duration(*duration)));
+                    });
+                });
+        }
+
+        if let Some((message, start_time)) = &state.hud_message {
+            let elapsed = start_time.elapsed();
+            if elapsed > HUD_MESSAGE_DURATION {
+                state.hud_message = None;
+                return;
+            }
+            let alpha = 1.0 - elapsed.as_secs_f32() / HUD_MESSAGE_DURATION.as_secs_f32();
+            egui::Area::new("hud message")
+                .anchor(egui::Align2::CENTER_TOP, [0.0, 20.0])
+                .interactable(false)
+                .show(ctx, |ui| {
+                    egui::Frame::popup(&ctx.style())
+                        .fill(egui::Color32::from_black_alpha((alpha * 180.0) as u8))
+                        .show(ui, |ui| {
+                            ui.add(
+                                egui::Label::new(message)
+                                    .heading()
+                                    .text_color(egui::Color32::from_white_alpha((alpha * 255.0) as u8)),
+                            );
+                        });
+                });
+        }
     }
 
     fn setup_ui_timer(
@@ -112,9 +624,9 @@ impl GUI {
     ) {
         ui.add(
             egui::Label::new(format!(
-                "{:3.2}ms, FPS: {:3.2}",
-                simulation_controller.timer().duration_last_frame().as_secs_f64() * 1000.0,
-                1000.0 / 1000.0 / simulation_controller.timer().duration_last_frame().as_secs_f64()
+                "{}, FPS: {}",
+                formatting::duration(simulation_controller.timer().duration_last_frame()),
+                formatting::decimal(1.0 / simulation_controller.timer().duration_last_frame().as_secs_f64(), 2)
             ))
             .heading(),
         );
@@ -134,13 +646,25 @@ impl GUI {
             1,
         );
 
-        if ui.checkbox(&mut state.wait_for_vblank, "wait for vsync").clicked() {
-            let present_mode = match state.wait_for_vblank {
-                true => wgpu::PresentMode::Fifo,
-                false => wgpu::PresentMode::Mailbox,
-            };
-            event_loop_proxy.send_event(ApplicationEvent::ChangePresentMode(present_mode)).unwrap();
+        let previous_present_mode = state.present_mode;
+        egui::ComboBox::from_label("present mode")
+            .selected_text(format!("{:?}", state.present_mode))
+            .show_ui(ui, |ui| {
+                for present_mode in state.supported_present_modes.iter() {
+                    ui.selectable_value(&mut state.present_mode, *present_mode, format!("{:?}", present_mode));
+                }
+            });
+        if state.present_mode != previous_present_mode {
+            event_loop_proxy
+                .send_event(ApplicationEvent::ChangePresentMode(state.present_mode))
+                .unwrap();
         }
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut state.frame_limit_enabled, "limit fps");
+            if state.frame_limit_enabled {
+                ui.add(egui::Slider::new(&mut state.frame_limit_fps, 10..=240).text(""));
+            }
+        });
         ui.separator();
 
         ui.horizontal(|ui| {
@@ -159,18 +683,18 @@ impl GUI {
                 ui.colored_label(
                     egui::Color32::RED,
                     egui::Label::new(format!(
-                        "OFFLINE RECORDING ({:.2}fps)",
-                        1.0 / simulation_controller.timer().frame_delta().as_secs_f64()
+                        "OFFLINE RECORDING ({}fps)",
+                        formatting::decimal(1.0 / simulation_controller.timer().frame_delta().as_secs_f64(), 2)
                     )),
                 );
             } else {
                 ui.label("rendered time:");
-                ui.add(egui::Label::new(format!("{:.2}", simulation_controller.timer().total_render_time().as_secs_f64())).strong());
+                ui.add(egui::Label::new(formatting::duration(simulation_controller.timer().total_render_time())).strong());
             }
             ui.end_row();
 
             ui.label("simulated time:");
-            ui.label(egui::Label::new(format!("{:.2}", simulation_controller.timer().total_simulated_time().as_secs_f64())).strong());
+            ui.label(egui::Label::new(formatting::duration(simulation_controller.timer().total_simulated_time())).strong());
         });
     }
 
@@ -249,6 +773,66 @@ impl GUI {
         }
     }
 
+    fn setup_ui_solver_smoke(ui: &mut egui::Ui, smoke: &mut SmokeSimulation) {
+        ui.label(format!(
+            "pressure solver, smoke ({}x{}x{})",
+            smoke.grid_dimension().width,
+            smoke.grid_dimension().height,
+            smoke.grid_dimension().depth_or_array_layers
+        ));
+        let max_num_iterations = smoke.pressure_solver_config().max_num_iterations;
+        let error_tolerance = smoke.pressure_solver_config().error_tolerance;
+        Self::setup_ui_solver_stats(ui, smoke.pressure_solver_stats(), max_num_iterations, error_tolerance);
+        ui.separator();
+        Self::setup_ui_solver_config(ui, smoke.pressure_solver_config());
+    }
+
+    fn setup_ui_playback(ui: &mut egui::Ui, simulation_controller: &mut SimulationController, playback_cache: &mut PlaybackCache) {
+        let num_frames = playback_cache.num_frames();
+        if !playback_cache.recording() {
+            ui.label("recording is off");
+        } else if num_frames == 0 {
+            ui.label("no frames cached yet");
+        } else {
+            // Index num_frames stands for the live simulation state.
+            let mut frame = playback_cache.requested_frame().unwrap_or(num_frames);
+            let time_label = if frame == num_frames {
+                "live".to_string()
+            } else {
+                formatting::duration(playback_cache.frame_simulation_time(frame))
+            };
+            if ui.add(egui::Slider::new(&mut frame, 0..=num_frames).text(time_label)).changed() {
+                if frame < num_frames {
+                    if simulation_controller.status() != SimulationControllerStatus::Paused {
+                        simulation_controller.pause_or_resume();
+                    }
+                    playback_cache.request_frame(Some(frame));
+                } else {
+                    playback_cache.request_frame(None);
+                }
+            }
+        }
+
+        ui.horizontal(|ui| {
+            let mut recording = playback_cache.recording();
+            if ui
+                .checkbox(&mut recording, "record")
+                .on_hover_text("keeps a copy of all particle positions per simulated frame")
+                .changed()
+            {
+                playback_cache.set_recording(recording);
+            }
+            let mut max_num_frames = playback_cache.max_num_frames();
+            if ui
+                .add(egui::DragValue::new(&mut max_num_frames).clamp_range(0..=1024).speed(1.0))
+                .changed()
+            {
+                playback_cache.set_max_num_frames(max_num_frames);
+            }
+            ui.label(format!("cached frames ({})", formatting::bytes(playback_cache.memory_usage())));
+        });
+    }
+
     fn setup_ui_simulation_control(
         ui: &mut egui::Ui,
         state: &mut GUIState,
@@ -269,6 +853,18 @@ impl GUI {
             {
                 simulation_controller.pause_or_resume();
             }
+            if ui
+                .add(egui::Button::new("Step once  (N)").enabled(simulation_controller.status() == SimulationControllerStatus::Paused))
+                .clicked()
+            {
+                simulation_controller.step_once();
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut simulation_controller.sub_step_inspection, "step individual stages");
+            if let Some(stage) = simulation_controller.next_sub_step_stage() {
+                ui.label(format!("(next: {:?})", stage));
+            }
         });
 
         ui.horizontal(|ui| {
@@ -291,7 +887,7 @@ impl GUI {
             simulation_controller.set_simulation_steps_per_second(simulation_steps_per_second.max(20).min(60 * 20) as u64);
             ui.end_row();
 
-            ui.label("time scale");
+            ui.label("time scale  (+/-)");
             ui.add(
                 egui::DragValue::new(&mut simulation_controller.time_scale)
                     .speed(0.05)
@@ -310,26 +906,50 @@ impl GUI {
                     .speed(0.01)
                     .clamp_range(min_jump..=120.0),
             );
-            if ui.button("Fast Forward").clicked() {
+            if ui.button("Fast Forward  (F)").clicked() {
                 event_loop_proxy
                     .send_event(ApplicationEvent::FastForwardSimulation(Duration::from_secs_f32(
                         state.fast_forward_length_seconds,
                     )))
                     .unwrap();
             }
-            ui.label(format!("last jump took {:?}", simulation_controller.computation_time_last_fast_forward()));
+            ui.label(format!(
+                "last jump took {}",
+                formatting::duration(simulation_controller.computation_time_last_fast_forward())
+            ));
         });
+        if let Some(progress) = simulation_controller.fast_forward_progress() {
+            ui.horizontal(|ui| {
+                ui.label(format!(
+                    "fast forwarding: {}/{} steps ({}%), ETA {}",
+                    progress.num_steps_finished,
+                    progress.num_steps_total,
+                    formatting::decimal(progress.num_steps_finished as f64 / progress.num_steps_total as f64 * 100.0, 0),
+                    progress.eta.map_or("-".to_string(), formatting::duration),
+                ));
+                if ui.button("Cancel").clicked() {
+                    simulation_controller.cancel_fast_forward();
+                }
+            });
+        }
 
         if let SimulationControllerStatus::RecordingWithFixedFrameLength { .. } = simulation_controller.status() {
-            if ui.button("End Recording").clicked() {
+            if ui.button("End Recording  (R)").clicked() {
                 simulation_controller.pause_or_resume();
             }
+            if simulation_controller.recording_schedule().is_some() {
+                ui.label(format!(
+                    "scheduled time scale {}",
+                    formatting::decimal(simulation_controller.current_time_scale() as f64, 2)
+                ));
+            }
         } else {
             ui.horizontal(|ui| {
-                if ui.button("Reset & Record Video").clicked() {
+                if ui.button("Reset & Record Video  (R)").clicked() {
                     event_loop_proxy
                         .send_event(ApplicationEvent::ResetAndStartRecording {
                             recording_fps: state.video_fps as f64,
+                            offscreen: Self::offscreen_target_desc(state),
                         })
                         .unwrap();
                 }
@@ -339,48 +959,406 @@ impl GUI {
                     ui.label("video fps")
                 });
             });
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(&mut state.recording_output_dir);
+                ui.label("output directory");
+            });
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(&mut state.recording_schedule_path);
+                ui.label("time scale schedule (json)");
+            });
+            ui.checkbox(&mut state.video_offscreen, "record at fixed resolution");
+            if state.video_offscreen {
+                ui.horizontal(|ui| {
+                    ui.add(egui::DragValue::new(&mut state.video_resolution[0]).clamp_range(16..=8192));
+                    ui.label("x");
+                    ui.add(egui::DragValue::new(&mut state.video_resolution[1]).clamp_range(16..=8192));
+                    ui.label("resolution");
+                });
+                ui.add(egui::Slider::new(&mut state.video_supersampling, 1..=4).text("supersampling"));
+            }
+        }
+        // Can be changed while recording, takes effect with the next frame.
+        ui.checkbox(&mut state.recording_overlay.enabled, "text overlay in recordings");
+        if state.recording_overlay.enabled {
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut state.recording_overlay.scene_name, "scene");
+                ui.checkbox(&mut state.recording_overlay.simulation_time, "time");
+                ui.checkbox(&mut state.recording_overlay.solver_settings, "solver");
+                ui.checkbox(&mut state.recording_overlay.frame_number, "frame");
+            });
+        }
+
+        // Applies to both recordings and regular screenshots. Exr captures the hdr image before tonemapping & color grading.
+        egui::ComboBox::from_label("screenshot format")
+            .selected_text(format!("{:?}", state.screenshot_format))
+            .show_ui(ui, |ui| {
+                for format in ScreenshotFormat::iter() {
+                    ui.selectable_value(&mut state.screenshot_format, format, format!("{:?}", format));
+                }
+            });
+
+        ui.separator();
+        ui.checkbox(&mut state.grid_export_enabled, "export grid (VTK)").on_hover_text(
+            "Periodically writes velocity, pressure & cell types as .vti files (plus a .pvd time series) into the output directory.\n\
+             Stalls the gpu for every export!",
+        );
+        if state.grid_export_enabled {
+            ui.add(
+                egui::Slider::new(&mut state.grid_export_interval, 0.01..=2.0)
+                    .logarithmic(true)
+                    .text("interval (simulated s)"),
+            );
         }
     }
 
-    fn setup_ui_scene_settings(ui: &mut egui::Ui, state: &mut GUIState, scene: &mut Scene, event_loop_proxy: &EventLoopProxy<ApplicationEvent>) {
+    fn setup_ui_scene_settings(
+        ui: &mut egui::Ui,
+        state: &mut GUIState,
+        recent_scenes: &RecentScenes,
+        scene: &mut Scene,
+        event_loop_proxy: &EventLoopProxy<ApplicationEvent>,
+    ) {
         ui.spacing_mut().slider_width = 250.0;
         ui.horizontal(|ui| {
             ui.label("volume resolution:");
             let grid_dim = scene.config().fluid.grid_dimension;
             ui.add(egui::Label::new(format!("{}x{}x{}", grid_dim.x, grid_dim.y, grid_dim.z)).strong());
+            if ui
+                .button("half")
+                .on_hover_text("resample running simulation to half resolution")
+                .clicked()
+            {
+                event_loop_proxy.send_event(ApplicationEvent::ResampleGrid(0.5)).unwrap();
+            }
+            if ui
+                .button("double")
+                .on_hover_text("resample running simulation to double resolution")
+                .clicked()
+            {
+                event_loop_proxy.send_event(ApplicationEvent::ResampleGrid(2.0)).unwrap();
+            }
         });
         ui.horizontal(|ui| {
             ui.label("num particles:");
-            ui.add(egui::Label::new(format!("{}", scene.num_active_particles())).strong());
+            ui.add(egui::Label::new(formatting::count(scene.num_active_particles() as u64)).strong());
         });
+        let mut particles_per_cell = scene.config().fluid.particles_per_cell;
+        egui::ComboBox::from_label("particles per cell (resets scene)")
+            .selected_text(format!("{}", particles_per_cell))
+            .show_ui(ui, |ui| {
+                for &count in [4, 6, 8, 12, 16].iter() {
+                    ui.selectable_value(&mut particles_per_cell, count, format!("{}", count));
+                }
+            });
+        if particles_per_cell != scene.config().fluid.particles_per_cell {
+            scene.set_particles_per_cell(particles_per_cell);
+            event_loop_proxy.send_event(ApplicationEvent::ResetScene).unwrap();
+        }
+        let mut seed = state.seed_edit.unwrap_or(scene.config().fluid.seed);
+        let response = ui
+            .add(egui::DragValue::new(&mut seed).speed(1.0).prefix("seed (resets scene): "))
+            .on_hover_text("jitter of the initial particle positions, keep it fixed to compare runs");
+        if response.drag_released() || (response.changed() && !response.dragged()) {
+            state.seed_edit = None;
+            if seed != scene.config().fluid.seed {
+                scene.set_seed(seed);
+                event_loop_proxy.send_event(ApplicationEvent::ResetScene).unwrap();
+            }
+        } else if response.dragged() {
+            state.seed_edit = Some(seed);
+        }
+        if scene.config().fluid.symmetry != FluidSymmetry::None {
+            ui.horizontal(|ui| {
+                ui.label("symmetry:");
+                ui.add(egui::Label::new(format!("{:?}", scene.config().fluid.symmetry)).strong());
+            });
+        }
         ui.separator();
         egui::ComboBox::from_label("Scene Selection")
-            .selected_text(format!(
-                "{:?}",
-                state.known_scene_files[state.selected_scene_idx].strip_prefix(SCENE_DIRECTORY).unwrap()
-            ))
+            .selected_text(scene_display_name(&state.known_scene_files[state.selected_scene_idx]))
             .show_ui(ui, |ui| {
+                ui.label("Recent");
+                for scene_file in recent_scenes.iter() {
+                    if ui.selectable_label(false, scene_display_name(scene_file)).clicked() {
+                        event_loop_proxy.send_event(ApplicationEvent::LoadScene(scene_file.clone())).unwrap();
+                    }
+                }
+                ui.separator();
+                const THUMBNAIL_HEIGHT: f32 = 36.0;
                 for (i, scene_file) in state.known_scene_files.iter().enumerate() {
-                    if ui
-                        .selectable_value(
-                            &mut state.selected_scene_idx,
-                            i,
-                            format!("{:?}", scene_file.strip_prefix(SCENE_DIRECTORY).unwrap()),
-                        )
-                        .clicked()
-                    {
-                        event_loop_proxy
-                            .send_event(ApplicationEvent::LoadScene(state.known_scene_files[state.selected_scene_idx].clone()))
-                            .unwrap();
+                    ui.horizontal(|ui| {
+                        match state.scene_thumbnails.get(scene_file) {
+                            Some((texture, size)) => {
+                                ui.image(texture, size * (THUMBNAIL_HEIGHT / size.y));
+                            }
+                            None => {
+                                ui.allocate_space(egui::vec2(THUMBNAIL_HEIGHT * 16.0 / 9.0, THUMBNAIL_HEIGHT));
+                            }
+                        }
+                        if ui
+                            .selectable_label(i == state.selected_scene_idx, scene_display_name(scene_file))
+                            .clicked()
+                        {
+                            event_loop_proxy.send_event(ApplicationEvent::LoadScene(scene_file.clone())).unwrap();
+                        }
+                    });
+                }
+            });
+        if ui
+            .button("Update Thumbnail")
+            .on_hover_text("Replaces the thumbnail of this scene in the scene selection with the current view")
+            .clicked()
+        {
+            event_loop_proxy.send_event(ApplicationEvent::CaptureSceneThumbnail).unwrap();
+        }
+    }
+
+    fn setup_ui_selected_object(ui: &mut egui::Ui, object_picking: &mut ObjectPicking, scene: &Scene) {
+        let config = match object_picking
+            .selected_object()
+            .and_then(|object_index| scene.config().static_objects.get(object_index))
+        {
+            Some(config) => config,
+            None => {
+                ui.label("click on an object to select it");
+                return;
+            }
+        };
+
+        egui::Grid::new("selected object").show(ui, |ui| {
+            match &config.heightfield {
+                Some(heightfield) => {
+                    ui.label("heightfield:");
+                    ui.add(egui::Label::new(format!("{:?}", heightfield.image)).strong());
+                }
+                None => {
+                    ui.label("model:");
+                    ui.add(egui::Label::new(format!("{:?}", config.model)).strong());
+                }
+            }
+            ui.end_row();
+            ui.label("position:");
+            ui.label(format!(
+                "{} {} {}",
+                formatting::decimal(config.world_position.x as f64, 2),
+                formatting::decimal(config.world_position.y as f64, 2),
+                formatting::decimal(config.world_position.z as f64, 2)
+            ));
+            ui.end_row();
+            ui.label("rotation (deg):");
+            ui.label(format!(
+                "{} {} {}",
+                formatting::decimal(config.rotation_angles.x.0 as f64, 1),
+                formatting::decimal(config.rotation_angles.y.0 as f64, 1),
+                formatting::decimal(config.rotation_angles.z.0 as f64, 1)
+            ));
+            ui.end_row();
+            ui.label("scale:");
+            ui.label(formatting::decimal(config.scale as f64, 2));
+            ui.end_row();
+            ui.label("animation:");
+            ui.label(match (&config.animation, &config.vertex_animation) {
+                (None, None) => "none".to_string(),
+                (Some(_), None) => "rigid".to_string(),
+                (None, Some(vertex_animation)) => format!("{} vertex frames", vertex_animation.frames.len()),
+                (Some(_), Some(vertex_animation)) => format!("rigid & {} vertex frames", vertex_animation.frames.len()),
+            });
+            ui.end_row();
+        });
+        if ui.button("Deselect").clicked() {
+            object_picking.clear_selection();
+        }
+    }
+
+    fn setup_ui_interaction(ui: &mut egui::Ui, fluid_interaction: &mut FluidInteraction) {
+        egui::ComboBox::from_label("Mouse Interaction (left click)")
+            .selected_text(format!("{:?}", fluid_interaction.mode))
+            .show_ui(ui, |ui| {
+                for mode in InteractionMode::iter() {
+                    ui.selectable_value(&mut fluid_interaction.mode, mode, format!("{:?}", mode));
+                }
+            });
+        ui.add(
+            egui::Slider::new(&mut fluid_interaction.radius, 0.01..=1.0)
+                .logarithmic(true)
+                .text("radius"),
+        );
+        if fluid_interaction.mode == InteractionMode::Dye {
+            ui.horizontal(|ui| {
+                ui.color_edit_button_srgb(&mut fluid_interaction.dye_color);
+                ui.label("dye color");
+            });
+        } else {
+            ui.add(egui::Slider::new(&mut fluid_interaction.strength, 0.0..=100.0).text("strength"));
+        }
+    }
+
+    fn setup_ui_force_fields(ui: &mut egui::Ui, force_fields: &mut Vec<ForceFieldConfig>) {
+        if force_fields.is_empty() {
+            ui.label("scene has no force fields");
+        }
+
+        for (i, config) in force_fields.iter_mut().enumerate() {
+            ui.checkbox(&mut config.enabled, format!("#{} {}", i, config.field.name()));
+            ui.indent(i, |ui| match &mut config.field {
+                ForceField::Wind { acceleration } => {
+                    custom_widgets::vector_drag(ui, "acceleration", acceleration);
+                }
+                ForceField::Vortex {
+                    center,
+                    axis,
+                    strength,
+                    radius,
+                } => {
+                    custom_widgets::point_drag(ui, "center", center);
+                    custom_widgets::vector_drag(ui, "axis", axis);
+                    ui.add(egui::Slider::new(strength, -50.0..=50.0).text("strength"));
+                    ui.add(egui::Slider::new(radius, 0.0..=2.0).text("radius (0 = infinite)"));
+                }
+                ForceField::Attractor { position, strength, radius } => {
+                    custom_widgets::point_drag(ui, "position", position);
+                    ui.add(egui::Slider::new(strength, -50.0..=50.0).text("strength"));
+                    ui.add(egui::Slider::new(radius, 0.0..=2.0).text("radius (0 = infinite)"));
+                }
+            });
+        }
+    }
+
+    fn setup_ui_lights(ui: &mut egui::Ui, lights: &mut Vec<LightConfig>, default_sun: LightConfig) {
+        if lights.is_empty() {
+            ui.label("scene has no lights, using the sun of the background");
+        }
+
+        let mut removed_light = None;
+        for (i, config) in lights.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut config.enabled, format!("#{} {}", i, config.light.name()));
+                if ui.small_button("remove").clicked() {
+                    removed_light = Some(i);
+                }
+            });
+            ui.indent(i, |ui| {
+                ui.horizontal(|ui| {
+                    custom_widgets::color_edit_button_linear(ui, &mut config.color);
+                    ui.add(
+                        egui::DragValue::new(&mut config.intensity)
+                            .speed(0.01)
+                            .clamp_range(0.0..=1000.0)
+                            .prefix("intensity: "),
+                    );
+                });
+                match &mut config.light {
+                    Light::Directional { direction } => {
+                        custom_widgets::vector_drag(ui, "direction", direction);
+                    }
+                    Light::Point { position } => {
+                        custom_widgets::point_drag(ui, "position", position);
+                    }
+                    Light::Spot {
+                        position,
+                        direction,
+                        outer_angle,
+                        inner_angle,
+                    } => {
+                        custom_widgets::point_drag(ui, "position", position);
+                        custom_widgets::vector_drag(ui, "direction", direction);
+                        ui.add(egui::Slider::new(outer_angle, 1.0..=89.0).text("outer angle (deg)"));
+                        ui.add(egui::Slider::new(inner_angle, 0.0..=89.0).text("inner angle (deg)"));
+                    }
+                }
+            });
+        }
+        if let Some(i) = removed_light {
+            lights.remove(i);
+        }
+
+        let mut added_light = None;
+        ui.horizontal(|ui| {
+            if ui.button("add directional").clicked() {
+                added_light = Some(Light::Directional {
+                    direction: cgmath::vec3(0.0, -1.0, 0.0),
+                });
+            }
+            if ui.button("add point").clicked() {
+                added_light = Some(Light::Point {
+                    position: cgmath::point3(0.0, 1.0, 0.0),
+                });
+            }
+            if ui.button("add spot").clicked() {
+                added_light = Some(Light::Spot {
+                    position: cgmath::point3(0.0, 2.0, 0.0),
+                    direction: cgmath::vec3(0.0, -1.0, 0.0),
+                    outer_angle: 30.0,
+                    inner_angle: 20.0,
+                });
+            }
+        });
+        if let Some(light) = added_light {
+            // Scene lights replace the sun, keep it unless the scene already has lights.
+            if lights.is_empty() {
+                lights.push(default_sun);
+            }
+            lights.push(LightConfig {
+                enabled: true,
+                color: cgmath::vec3(1.0, 1.0, 1.0),
+                intensity: 1.0,
+                light,
+            });
+        }
+    }
+
+    fn setup_ui_gravity(ui: &mut egui::Ui, scene: &mut Scene) {
+        use cgmath::InnerSpace;
+
+        let mut gravity = scene.gravity();
+        let mut magnitude = gravity.magnitude();
+        let mut direction = if magnitude > 0.0 {
+            gravity / magnitude
+        } else {
+            -cgmath::Vector3::unit_y()
+        };
+        let mut changed = false;
+
+        ui.horizontal(|ui| {
+            changed |= custom_widgets::tilt_gizmo(ui, 50.0, &mut direction)
+                .on_hover_text("drag to tilt gravity (view from above), I/J/K/L tilt in steps")
+                .changed();
+            ui.vertical(|ui| {
+                changed |= ui.add(egui::Slider::new(&mut magnitude, 0.0..=30.0).text("strength (m/s²)")).changed();
+                ui.horizontal(|ui| {
+                    let x = ui.add(egui::DragValue::new(&mut gravity.x).speed(0.01).prefix("x: ")).changed();
+                    let y = ui.add(egui::DragValue::new(&mut gravity.y).speed(0.01).prefix("y: ")).changed();
+                    let z = ui.add(egui::DragValue::new(&mut gravity.z).speed(0.01).prefix("z: ")).changed();
+                    if x || y || z {
+                        scene.set_gravity(gravity);
                     }
+                });
+                if ui.button("reset to scene gravity  (G)").clicked() {
+                    scene.set_gravity(scene.config().gravity);
                 }
             });
+        });
+        if changed {
+            scene.set_gravity(direction * magnitude);
+        }
     }
 
-    fn setup_ui_render_settings(ui: &mut egui::Ui, scene_renderer: &mut SceneRenderer) {
+    fn setup_ui_render_settings(ui: &mut egui::Ui, scene_renderer: &mut SceneRenderer, grid_dimension: cgmath::Point3<u32>, num_particles: u32) {
         egui::Grid::new("render settings").show(ui, |ui| {
             ui.spacing_mut().slider_width = 170.0;
 
+            ui.label("Render Scale");
+            ui.add(
+                egui::Slider::new(
+                    &mut scene_renderer.render_scale,
+                    SceneRenderer::MIN_RENDER_SCALE..=SceneRenderer::MAX_RENDER_SCALE,
+                )
+                .text(""),
+            )
+            .on_hover_text("Resolution of the scene rendering relative to the window, scaled with bilinear filtering while tonemapping");
+            ui.end_row();
+
             ui.label("Fluid Rendering");
             egui::ComboBox::from_label("Fluid Rendering")
                 .selected_text(format!("{:?}", scene_renderer.fluid_rendering_mode))
@@ -395,6 +1373,113 @@ impl GUI {
             ui.add(egui::Slider::new(&mut scene_renderer.particle_radius_factor, 0.0..=1.0).text(""));
             ui.end_row();
 
+            if let FluidRenderingMode::ScreenSpaceFluid = scene_renderer.fluid_rendering_mode {
+                let settings = &mut scene_renderer.screenspace_fluid.settings;
+
+                ui.label("Fluid Quality");
+                let quality_text = match settings.quality() {
+                    Some(quality) => format!("{:?}", quality),
+                    None => "Custom".to_owned(),
+                };
+                egui::ComboBox::from_label("Fluid Quality").selected_text(quality_text).show_ui(ui, |ui| {
+                    for quality in ScreenSpaceFluidQuality::iter() {
+                        if ui
+                            .selectable_label(settings.quality() == Some(quality), format!("{:?}", quality))
+                            .clicked()
+                        {
+                            *settings = ScreenSpaceFluidSettings::from_quality(quality);
+                        }
+                    }
+                });
+                ui.end_row();
+
+                ui.label("Fluid Resolution");
+                egui::ComboBox::from_label("Fluid Resolution")
+                    .selected_text(format!("{:?}", settings.resolution))
+                    .show_ui(ui, |ui| {
+                        for resolution in ScreenSpaceFluidResolution::iter() {
+                            ui.selectable_value(&mut settings.resolution, resolution, format!("{:?}", resolution));
+                        }
+                    });
+                ui.end_row();
+
+                ui.label("Fluid Depth Filter");
+                egui::ComboBox::from_label("Fluid Depth Filter")
+                    .selected_text(format!("{:?}", settings.depth_filter))
+                    .show_ui(ui, |ui| {
+                        for filter in ScreenSpaceFluidDepthFilter::iter() {
+                            ui.selectable_value(&mut settings.depth_filter, filter, format!("{:?}", filter));
+                        }
+                    });
+                ui.end_row();
+
+                match settings.depth_filter {
+                    ScreenSpaceFluidDepthFilter::NarrowRange => {
+                        ui.checkbox(&mut settings.depth_filter_2d, "2D Depth Filter");
+                        ui.end_row();
+                    }
+                    ScreenSpaceFluidDepthFilter::CurvatureFlow => {
+                        ui.label("Curvature Flow Iterations");
+                        ui.add(egui::Slider::new(&mut settings.curvature_flow_iterations, 0..=200).text(""));
+                        ui.end_row();
+                    }
+                }
+
+                ui.checkbox(&mut settings.caustics, "Caustics");
+                ui.end_row();
+
+                ui.label("Reflection Intensity");
+                ui.add(egui::Slider::new(&mut settings.reflection_intensity, 0.0..=1.0).text(""));
+                ui.end_row();
+
+                ui.label("Reflection Roughness");
+                ui.add(egui::Slider::new(&mut settings.reflection_roughness, 0.0..=1.0).text(""));
+                ui.end_row();
+
+                ui.label("Foam Threshold");
+                ui.add(egui::Slider::new(&mut settings.foam_threshold, 0.0..=10.0).text(""));
+                ui.end_row();
+
+                ui.label("Foam Amount");
+                ui.add(egui::Slider::new(&mut settings.foam_amount, 0.0..=5.0).text(""));
+                ui.end_row();
+            }
+
+            if let FluidRenderingMode::ParticlesIndex
+            | FluidRenderingMode::ParticlesVelocity
+            | FluidRenderingMode::ParticlesPhase
+            | FluidRenderingMode::ParticlesAge
+            | FluidRenderingMode::ParticlesDye = scene_renderer.fluid_rendering_mode
+            {
+                let particle_renderer = &mut scene_renderer.particle_renderer;
+                let lod_active = particle_renderer.is_lod_active(num_particles);
+                let lod_settings = &mut particle_renderer.lod_settings;
+
+                ui.checkbox(&mut lod_settings.frustum_culling, "Particle Frustum Culling");
+                ui.end_row();
+
+                ui.label("Particle LOD");
+                egui::ComboBox::from_label("Particle LOD")
+                    .selected_text(format!("{:?} ({})", lod_settings.mode, if lod_active { "active" } else { "inactive" }))
+                    .show_ui(ui, |ui| {
+                        for mode in ParticleLodMode::iter() {
+                            ui.selectable_value(&mut lod_settings.mode, mode, format!("{:?}", mode));
+                        }
+                    });
+                ui.end_row();
+
+                ui.label("Particle LOD Distance");
+                ui.add(egui::Slider::new(&mut lod_settings.lod_distance, 0.1..=20.0).logarithmic(true).text(""));
+                ui.end_row();
+
+                ui.label("Rendered Particles");
+                match particle_renderer.rendered_fraction() {
+                    Some(fraction) => ui.label(format!("{:.1}%", fraction * 100.0)),
+                    None => ui.label("-"),
+                };
+                ui.end_row();
+            }
+
             ui.label("Volume Visualization");
             egui::ComboBox::from_label("Volume Visualization")
                 .selected_text(format!("{:?}", scene_renderer.volume_visualization))
@@ -408,50 +1493,83 @@ impl GUI {
             ui.checkbox(&mut scene_renderer.enable_voxel_visualization, "Voxel Visualization");
             ui.end_row();
 
+            let voxel_slice = &mut scene_renderer.voxel_slice;
+            ui.checkbox(&mut voxel_slice.enabled, "Voxel Slice");
+            ui.horizontal(|ui| {
+                for (axis, name) in ["x", "y", "z"].iter().enumerate() {
+                    ui.radio_value(&mut voxel_slice.axis, axis, *name);
+                }
+            });
+            ui.end_row();
+            if voxel_slice.enabled {
+                let num_slices = [grid_dimension.x, grid_dimension.y, grid_dimension.z][voxel_slice.axis];
+                voxel_slice.index = voxel_slice.index.min(num_slices.max(1) - 1);
+                ui.label("Voxel Slice Index");
+                ui.add(egui::Slider::new(&mut voxel_slice.index, 0..=num_slices.max(1) - 1).text(""));
+                ui.end_row();
+                ui.label("");
+                if ui
+                    .checkbox(&mut voxel_slice.inspect, "inspect values on hover")
+                    .on_hover_text("Reads back the raw simulation values of the cell under the cursor")
+                    .changed()
+                {
+                    scene_renderer.grid_cell_inspector.clear();
+                }
+                ui.end_row();
+            }
+
             ui.label("Velocity Visualization Scale");
             ui.add(
                 egui::Slider::new(&mut scene_renderer.velocity_visualization_scale, 0.001..=5.0)
                     .logarithmic(true)
                     .text(""),
             );
+            ui.end_row();
+
+            ui.label("Age Visualization Range (s)");
+            ui.add(
+                egui::Slider::new(&mut scene_renderer.age_visualization_range, 0.1..=120.0)
+                    .logarithmic(true)
+                    .text(""),
+            );
         });
         ui.checkbox(&mut scene_renderer.enable_mesh_rendering, "Render meshes");
-        ui.checkbox(&mut scene_renderer.enable_box_lines, "Show Fluid Domain Bounds");
-    }
-
-    fn setup_ui_profiler(ui: &mut egui::Ui, profiling_data: &Vec<GpuTimerScopeResult>, levels_default_open: i32) {
-        for scope in profiling_data.iter() {
-            let time = format!("{:.3}ms", (scope.time.end - scope.time.start) * 1000.0);
-            if scope.nested_scopes.is_empty() {
-                ui.horizontal(|ui| {
-                    ui.label(&scope.label);
-                    ui.with_layout(egui::Layout::default().with_cross_align(egui::Align::Max), |ui| {
-                        ui.label(time);
-                    });
+        ui.horizontal(|ui| {
+            let mesh_renderer = &mut scene_renderer.mesh_renderer;
+            egui::ComboBox::from_label("Mesh Debug")
+                .selected_text(format!("{:?}", mesh_renderer.debug_mode))
+                .show_ui(ui, |ui| {
+                    for mode in MeshDebugMode::iter() {
+                        ui.selectable_value(&mut mesh_renderer.debug_mode, mode, format!("{:?}", mode));
+                    }
                 });
-            } else {
-                egui::CollapsingHeader::new(format!("{}  -  {}", scope.label, time))
-                    .id_source(&scope.label)
-                    .default_open(levels_default_open > 0)
-                    .show(ui, |ui| Self::setup_ui_profiler(ui, &scope.nested_scopes, levels_default_open - 1));
+            if mesh_renderer.wireframe_supported() {
+                ui.checkbox(&mut mesh_renderer.wireframe_overlay, "Wireframe");
             }
-            ui.end_row();
-        }
+        });
+        ui.checkbox(&mut scene_renderer.enable_box_lines, "Show Fluid Domain Bounds");
+        ui.checkbox(&mut scene_renderer.gizmos.enabled, "Show Gizmos")
+            .on_hover_text("drag the handles to change gravity (blue), light direction (yellow) and the voxel slice (green)");
+        ui.checkbox(&mut scene_renderer.enable_frustum_culling, "Frustum Culling (meshes & voxels)");
     }
 
     pub fn draw(
         &mut self,
-        device: &mut wgpu::Device,
+        device: &wgpu::Device,
         window: &winit::window::Window,
         encoder: &mut wgpu::CommandEncoder,
-        queue: &mut wgpu::Queue,
+        queue: &wgpu::Queue,
         view: &wgpu::TextureView,
+        view_resolution: winit::dpi::PhysicalSize<u32>,
         simulation_controller: &mut SimulationController,
         scene_renderer: &mut SceneRenderer,
         scene: &mut Scene,
+        fluid_interaction: &mut FluidInteraction,
         event_loop_proxy: &EventLoopProxy<ApplicationEvent>,
     ) {
+        self.update_scale_factor(window);
         self.platform.begin_frame();
+        self.state.scene_thumbnails.update(&mut self.render_pass);
 
         // Draw gui
         egui::Window::new("Blub")
@@ -463,69 +1581,200 @@ impl GUI {
                 Self::setup_ui_timer(ui, &mut self.state, simulation_controller, event_loop_proxy);
 
                 egui::CollapsingHeader::new("Solver").show(ui, |ui| {
+                    if let Some(smoke) = scene.smoke_mut() {
+                        Self::setup_ui_solver_smoke(ui, smoke);
+                        return;
+                    }
                     Self::setup_ui_solver(ui, scene.fluid_mut());
                     ui.separator();
                     ui.add(
                         egui::Slider::new(&mut scene.fluid_mut().dynamic_settings().particle_rebinning_step_frequency, 0..=300)
                             .text("particle binning frequency"),
                     );
+                    ui.add(
+                        egui::Slider::new(&mut scene.fluid_mut().dynamic_settings().vorticity_confinement, 0.0..=2.0).text("vorticity confinement"),
+                    );
+                    ui.checkbox(
+                        &mut scene.fluid_mut().dynamic_settings().particle_resampling,
+                        "particle resampling (on binning)",
+                    );
+                    egui::ComboBox::from_label("particle binning")
+                        .selected_text(format!("{:?}", scene.fluid_mut().dynamic_settings().particle_binning_method))
+                        .show_ui(ui, |ui| {
+                            for method in ParticleBinningMethod::iter() {
+                                ui.selectable_value(
+                                    &mut scene.fluid_mut().dynamic_settings().particle_binning_method,
+                                    method,
+                                    format!("{:?}", method),
+                                );
+                            }
+                        });
+                    if scene.fluid_mut().dynamic_settings().particle_resampling {
+                        ui.label("  binning steps with resampling always use CountingSort");
+                    }
+                    // Measured only while the simulation profiler is shown.
+                    for (method, duration) in ParticleBinningMethod::iter().zip(self.state.particle_binning_durations.iter()) {
+                        if let Some(duration) = duration {
+                            ui.label(format!("  last {:?}: {}", method, formatting::seconds(*duration)));
+                        }
+                    }
+                    egui::ComboBox::from_label("particle advection")
+                        .selected_text(format!("{:?}", scene.fluid_mut().dynamic_settings().advection_scheme))
+                        .show_ui(ui, |ui| {
+                            for scheme in AdvectionScheme::iter() {
+                                ui.selectable_value(
+                                    &mut scene.fluid_mut().dynamic_settings().advection_scheme,
+                                    scheme,
+                                    format!("{:?}", scheme),
+                                );
+                            }
+                        });
+                    ui.checkbox(&mut scene.fluid_mut().dynamic_settings().solid_push_out, "push particles out of solids")
+                        .on_hover_text("projects particles that tunneled into (moving) solids back out after advection");
                 });
                 egui::CollapsingHeader::new("Simulation Controller & Recording")
                     .default_open(true)
                     .show(ui, |ui| {
                         Self::setup_ui_simulation_control(ui, &mut self.state, simulation_controller, event_loop_proxy);
+                        if ui.checkbox(&mut self.settings.pause_in_background, "pause when in background").changed() {
+                            self.settings.save();
+                        }
+                        if ui.checkbox(&mut self.settings.decimal_comma, "decimal comma in gui").changed() {
+                            formatting::set_decimal_comma(self.settings.decimal_comma);
+                            self.settings.save();
+                        }
+                        if ui
+                            .checkbox(&mut self.settings.window_title_stats, "stats in window title")
+                            .on_hover_text("Fps, simulated time & scene name, updated every second")
+                            .changed()
+                        {
+                            self.settings.save();
+                        }
                     });
+                egui::CollapsingHeader::new("Playback").show(ui, |ui| {
+                    Self::setup_ui_playback(ui, simulation_controller, &mut scene.playback_cache);
+                });
                 egui::CollapsingHeader::new("Scene Settings").default_open(true).show(ui, |ui| {
-                    Self::setup_ui_scene_settings(ui, &mut self.state, scene, event_loop_proxy);
+                    Self::setup_ui_scene_settings(ui, &mut self.state, &self.recent_scenes, scene, event_loop_proxy);
+                    ui.separator();
+                    Self::setup_ui_selected_object(ui, &mut scene_renderer.object_picking, scene);
+                });
+                egui::CollapsingHeader::new("Interaction").show(ui, |ui| {
+                    Self::setup_ui_interaction(ui, fluid_interaction);
+                });
+                egui::CollapsingHeader::new("Gravity").show(ui, |ui| {
+                    Self::setup_ui_gravity(ui, scene);
+                });
+                egui::CollapsingHeader::new("Force Fields").show(ui, |ui| {
+                    Self::setup_ui_force_fields(ui, &mut scene.force_fields);
+                });
+                egui::CollapsingHeader::new("Lights").show(ui, |ui| {
+                    Self::setup_ui_lights(ui, &mut scene.lights, scene_renderer.default_sun());
                 });
                 egui::CollapsingHeader::new("Rendering Settings").default_open(true).show(ui, |ui| {
-                    Self::setup_ui_render_settings(ui, scene_renderer);
+                    Self::setup_ui_render_settings(ui, scene_renderer, scene.config().fluid.grid_dimension, scene.fluid().num_particles());
                 });
-                if let Some(_) = egui::CollapsingHeader::new("Profiler - Single Simulation Frame")
-                    .default_open(false)
-                    .show(ui, |ui| {
-                        if ui.button("Write Chrometrace").clicked() {
-                            let filename = Path::new("simulation-trace.json");
-                            info!("Writing chrome trace file to {:?}", filename);
-                            wgpu_profiler::chrometrace::write_chrometrace(filename, &self.state.profiling_data_simulation)
-                                .expect("Failed to write chrometrace");
-                        }
-                        Self::setup_ui_profiler(ui, &self.state.profiling_data_simulation, 2);
-                    })
-                    .body_returned
-                {
-                    self.state.show_profiling_data_simulation = true;
-                } else {
-                    self.state.show_profiling_data_simulation = false;
+                egui::CollapsingHeader::new("Appearance").show(ui, |ui| {
+                    Self::setup_ui_appearance(ui, &mut self.state, &mut self.settings);
+                });
+                if self.profiler_window.is_none() {
+                    Self::setup_ui_profilers(ui, &mut self.state, simulation_controller);
                 }
-                if let Some(_) = egui::CollapsingHeader::new("Profiler - Rendering")
-                    .default_open(false)
-                    .show(ui, |ui| {
-                        if ui.button("Write Chrometrace").clicked() {
-                            let filename = Path::new("rendering-trace.json");
-                            info!("Writing chrome trace file to {:?}", filename);
-                            wgpu_profiler::chrometrace::write_chrometrace(filename, &self.state.profiling_data_rendering)
-                                .expect("Failed to write chrometrace");
+                ui.separator();
+                ui.horizontal(|ui| {
+                    if ui.button("Show Getting Started Guide").clicked() {
+                        self.onboarding.show();
+                    }
+                    if ui.button("Toggle Log").clicked() {
+                        self.log_console.toggle();
+                    }
+                    if self.profiler_window.is_none() {
+                        if ui
+                            .button("Detach Profiler")
+                            .on_hover_text("Shows the profiler in a separate window")
+                            .clicked()
+                        {
+                            event_loop_proxy.send_event(ApplicationEvent::OpenProfilerWindow).unwrap();
                         }
-                        Self::setup_ui_profiler(ui, &self.state.profiling_data_rendering, 4);
-                    })
-                    .body_returned
-                {
-                    self.state.show_profiling_data_rendering = true;
-                } else {
-                    self.state.show_profiling_data_rendering = false;
-                }
+                    } else if ui.button("Attach Profiler").clicked() {
+                        self.profiler_window = None;
+                    }
+                    if ui
+                        .button("Clear Shader Cache")
+                        .on_hover_text("Removes all cached shader binaries and recompiles all pipelines")
+                        .clicked()
+                    {
+                        event_loop_proxy.send_event(ApplicationEvent::ClearShaderCache).unwrap();
+                    }
+                });
+            });
+
+        self.onboarding.setup_ui(&self.platform.context(), &mut self.settings);
+        self.log_console.setup_ui(&self.platform.context());
+        Self::setup_ui_hud(&self.platform.context(), &mut self.state);
+        Self::setup_ui_shader_compile_errors(&self.platform.context(), &self.state.shader_compile_errors);
+        Self::setup_ui_grid_cell_values(&self.platform.context(), scene_renderer);
+
+        self.end_frame_and_render(device, window, encoder, queue, view, view_resolution);
+
+        if let Some(profiler_window) = self.profiler_window.as_mut() {
+            let state = &mut self.state;
+            profiler_window.draw(device, queue, |ctx| {
+                egui::CentralPanel::default().show(ctx, |ui| {
+                    egui::ScrollArea::auto_sized().show(ui, |ui| Self::setup_ui_profilers(ui, state, simulation_controller));
+                });
+            });
+        }
+    }
+
+    // Minimal ui shown while shaders are compiled at startup.
+    pub fn draw_startup_progress(
+        &mut self,
+        device: &wgpu::Device,
+        window: &winit::window::Window,
+        encoder: &mut wgpu::CommandEncoder,
+        queue: &wgpu::Queue,
+        view: &wgpu::TextureView,
+        view_resolution: winit::dpi::PhysicalSize<u32>,
+        shader_cache_warming: &ShaderCacheWarming,
+    ) {
+        self.update_scale_factor(window);
+        self.platform.begin_frame();
+        egui::Area::new("startup progress")
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(&self.platform.context(), |ui| {
+                egui::Frame::popup(&ui.ctx().style()).show(ui, |ui| {
+                    ui.heading("Compiling shaders...");
+                    ui.add(egui::ProgressBar::new(shader_cache_warming.progress()).show_percentage());
+                    ui.label(format!(
+                        "{} / {}",
+                        shader_cache_warming.num_finished(),
+                        shader_cache_warming.num_shaders()
+                    ));
+                });
             });
+        self.end_frame_and_render(device, window, encoder, queue, view, view_resolution);
+    }
 
+    fn end_frame_and_render(
+        &mut self,
+        device: &wgpu::Device,
+        window: &winit::window::Window,
+        encoder: &mut wgpu::CommandEncoder,
+        queue: &wgpu::Queue,
+        view: &wgpu::TextureView,
+        view_resolution: winit::dpi::PhysicalSize<u32>,
+    ) {
         // End the UI frame.
         let (_output, paint_commands) = self.platform.end_frame();
         let paint_jobs = self.platform.context().tessellate(paint_commands);
 
         // Upload all resources for the GPU.
         let screen_descriptor = egui_wgpu_backend::ScreenDescriptor {
-            physical_width: window.inner_size().width,
-            physical_height: window.inner_size().height,
-            scale_factor: window.scale_factor() as f32,
+            // Not necessarily the window size, render targets lag behind while resizing.
+            physical_width: view_resolution.width,
+            physical_height: view_resolution.height,
+            scale_factor: self.scale_factor as f32,
         };
         self.render_pass.update_texture(device, queue, &self.platform.context().texture());
         self.render_pass.update_user_textures(device, queue);
@@ -535,11 +1784,53 @@ impl GUI {
         self.render_pass.execute(encoder, view, &paint_jobs, &screen_descriptor, None);
     }
 
+    // Profilers only record while their section is open.
+    fn setup_ui_profilers(ui: &mut egui::Ui, state: &mut GUIState, simulation_controller: &mut SimulationController) {
+        state.show_profiling_data_simulation = egui::CollapsingHeader::new("Profiler - Single Simulation Frame")
+            .default_open(false)
+            .show(ui, |ui| {
+                Self::setup_ui_profiler_fallback_note(ui, state.timestamp_queries_supported);
+                Self::setup_ui_profiling_detail(ui, &mut simulation_controller.profiling_detail);
+                state.profiler_history_simulation.setup_ui(ui, 2, Path::new("simulation-trace.json"));
+            })
+            .body_returned
+            .is_some();
+        state.show_profiling_data_rendering = egui::CollapsingHeader::new("Profiler - Rendering")
+            .default_open(false)
+            .show(ui, |ui| {
+                Self::setup_ui_profiler_fallback_note(ui, state.timestamp_queries_supported);
+                state.profiler_history_rendering.setup_ui(ui, 4, Path::new("rendering-trace.json"));
+            })
+            .body_returned
+            .is_some();
+    }
+
+    fn setup_ui_profiling_detail(ui: &mut egui::Ui, profiling_detail: &mut ProfilingDetail) {
+        egui::ComboBox::from_label("Profiling Detail")
+            .selected_text(format!("{:?}", profiling_detail))
+            .show_ui(ui, |ui| {
+                for detail in ProfilingDetail::iter() {
+                    ui.selectable_value(profiling_detail, detail, format!("{:?}", detail));
+                }
+            });
+    }
+
+    fn setup_ui_profiler_fallback_note(ui: &mut egui::Ui, timestamp_queries_supported: bool) {
+        if !timestamp_queries_supported {
+            ui.label("No timestamp query support, showing coarse timings from waiting on every submission. Stalls the cpu while open!");
+        }
+    }
+
     pub fn report_profiling_data_rendering(&mut self, profiling_data_rendering: Vec<GpuTimerScopeResult>) {
-        self.state.profiling_data_rendering = profiling_data_rendering;
+        self.state.profiler_history_rendering.push(profiling_data_rendering);
     }
     pub fn report_profiling_data_simulation(&mut self, profiling_data_simulation: Vec<GpuTimerScopeResult>) {
-        self.state.profiling_data_simulation = profiling_data_simulation;
+        for (method, duration) in ParticleBinningMethod::iter().zip(self.state.particle_binning_durations.iter_mut()) {
+            if let Some(scope) = find_profiler_scope(&profiling_data_simulation, method.profiling_scope_label()) {
+                *duration = Some(scope.time.end - scope.time.start);
+            }
+        }
+        self.state.profiler_history_simulation.push(profiling_data_simulation);
     }
     pub fn show_profiling_data_simulation(&self) -> bool {
         self.state.show_profiling_data_simulation
@@ -548,3 +1839,14 @@ impl GUI {
         self.state.show_profiling_data_rendering
     }
 }
+
+// Depth first search for a scope by label.
+fn find_profiler_scope<'a>(scopes: &'a [GpuTimerScopeResult], label: &str) -> Option<&'a GpuTimerScopeResult> {
+    scopes.iter().find_map(|scope| {
+        if scope.label == label {
+            Some(scope)
+        } else {
+            find_profiler_scope(&scope.nested_scopes, label)
+        }
+    })
+}
@@ -0,0 +1,99 @@
+use super::persistent_settings::PersistentSettings;
+
+// (title, description) of every page shown by the first-run overlay.
+const ONBOARDING_PAGES: [(&str, &str); 4] = [
+    (
+        "Welcome to Blub!",
+        "Blub is an interactive GPU fluid simulation.\n\
+         This short tour walks you through the sections of the control window on the left.\n\n\
+         Move the camera with WASD/arrow keys while holding the right mouse button.\n\
+         F11 toggles fullscreen.\n\
+         Pick a mouse interaction mode in the Interaction section to poke the fluid with the left mouse button.",
+    ),
+    (
+        "Simulation Controller & Recording",
+        "Pause/continue the simulation with the button or the Space key, reset it or fast forward a given amount of simulated time.\n\n\
+         \"Reset & Record Video\" restarts the scene and writes a screenshot for every frame at a fixed frame rate.\n\
+         Press \"End Recording\" to stop. Single screenshots can be taken at any time with the Print Screen key.",
+    ),
+    (
+        "Scene Settings",
+        "Pick one of the scenes from the \"scenes\" directory in the Scene Selection dropdown.\n\
+         Scenes are plain json files, so it's easy to create your own!\n\n\
+         Tilt gravity with the gizmo in the Gravity section or with I/J/K/L, G resets it.",
+    ),
+    (
+        "Rendering Settings & Profiler",
+        "Switch between different fluid visualizations, show the simulation grid or the voxelized obstacles.\n\n\
+         The profiler sections show GPU timings for the simulation and rendering.",
+    ),
+];
+
+pub struct Onboarding {
+    visible: bool,
+    current_page: usize,
+    never_show_again: bool,
+}
+
+impl Onboarding {
+    pub fn new(settings: &PersistentSettings) -> Self {
+        Onboarding {
+            visible: !settings.onboarding_dismissed,
+            current_page: 0,
+            never_show_again: true,
+        }
+    }
+
+    pub fn show(&mut self) {
+        self.visible = true;
+        self.current_page = 0;
+    }
+
+    pub fn setup_ui(&mut self, ctx: &egui::CtxRef, settings: &mut PersistentSettings) {
+        if !self.visible {
+            return;
+        }
+
+        let (title, description) = ONBOARDING_PAGES[self.current_page];
+        let mut close = false;
+
+        egui::Window::new("Getting Started")
+            .id(egui::Id::new("onboarding"))
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                ui.set_max_width(400.0);
+                ui.heading(title);
+                ui.separator();
+                ui.label(description);
+                ui.separator();
+
+                ui.horizontal(|ui| {
+                    ui.label(format!("{}/{}", self.current_page + 1, ONBOARDING_PAGES.len()));
+                    if ui.add(egui::Button::new("Back").enabled(self.current_page > 0)).clicked() {
+                        self.current_page -= 1;
+                    }
+                    if self.current_page + 1 < ONBOARDING_PAGES.len() {
+                        if ui.button("Next").clicked() {
+                            self.current_page += 1;
+                        }
+                        if ui.button("Skip").clicked() {
+                            close = true;
+                        }
+                    } else if ui.button("Done").clicked() {
+                        close = true;
+                    }
+                });
+                ui.checkbox(&mut self.never_show_again, "don't show this again");
+            });
+
+        if close {
+            self.visible = false;
+            if self.never_show_again && !settings.onboarding_dismissed {
+                settings.onboarding_dismissed = true;
+                settings.save();
+            }
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use super::theme::{PlotPalette, Theme};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+const SETTINGS_FILE: &str = "gui_settings.json";
+
+// GUI settings that survive application restarts.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct PersistentSettings {
+    pub onboarding_dismissed: bool,
+    // Stop rendering & simulating while the window is unfocused or minimized (unless recording or fast forwarding).
+    pub pause_in_background: bool,
+    // Numbers in the gui use ',' instead of '.' as decimal separator.
+    pub decimal_comma: bool,
+    // Fps, simulated time & scene name in the window title, readable while the gui is out of sight (e.g. on a projector).
+    pub window_title_stats: bool,
+    // Applied on top of the OS scale factor.
+    pub ui_scale: f32,
+    // Size of regular text in points, all other text styles are scaled accordingly.
+    pub font_size: f32,
+    pub theme: Theme,
+    pub plot_palette: PlotPalette,
+}
+
+impl Default for PersistentSettings {
+    fn default() -> Self {
+        PersistentSettings {
+            onboarding_dismissed: false,
+            pause_in_background: false,
+            decimal_comma: false,
+            window_title_stats: false,
+            ui_scale: 1.0,
+            font_size: PersistentSettings::DEFAULT_FONT_SIZE,
+            theme: Theme::Dark,
+            plot_palette: Default::default(),
+        }
+    }
+}
+
+impl PersistentSettings {
+    // egui's default size for TextStyle::Body.
+    pub const DEFAULT_FONT_SIZE: f32 = 14.0;
+
+    pub fn load() -> Self {
+        let path = Path::new(SETTINGS_FILE);
+        if !path.exists() {
+            return Default::default();
+        }
+        match std::fs::File::open(path)
+            .map_err(|err| err.to_string())
+            .and_then(|file| serde_json::from_reader(std::io::BufReader::new(file)).map_err(|err| err.to_string()))
+        {
+            Ok(settings) => settings,
+            Err(err) => {
+                warn!("Failed to read gui settings from {:?}: {}", path, err);
+                Default::default()
+            }
+        }
+    }
+
+    pub fn save(&self) {
+        let path = Path::new(SETTINGS_FILE);
+        let result = std::fs::File::create(path)
+            .map_err(|err| err.to_string())
+            .and_then(|file| serde_json::to_writer_pretty(file, self).map_err(|err| err.to_string()));
+        if let Err(err) = result {
+            error!("Failed to write gui settings to {:?}: {}", path, err);
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use super::{custom_widgets, formatting};
+use std::{collections::VecDeque, path::Path};
+use wgpu_profiler::GpuTimerScopeResult;
+
+// Profiling results of the last few frames, with a timeline of the selected frame.
+pub struct ProfilerHistory {
+    frames: VecDeque<Vec<GpuTimerScopeResult>>, // oldest first
+    selected_frame: Option<usize>,              // None means always showing the latest frame.
+    max_num_frames: usize,
+}
+
+fn frame_duration_ms(scopes: &[GpuTimerScopeResult]) -> f32 {
+    match (scopes.first(), scopes.last()) {
+        (Some(first), Some(last)) => ((last.time.end - first.time.start) * 1000.0) as f32,
+        _ => 0.0,
+    }
+}
+
+fn setup_ui_scope_list(ui: &mut egui::Ui, profiling_data: &[GpuTimerScopeResult], levels_default_open: i32) {
+    for scope in profiling_data.iter() {
+        let time = formatting::seconds(scope.time.end - scope.time.start);
+        if scope.nested_scopes.is_empty() {
+            ui.horizontal(|ui| {
+                ui.label(&scope.label);
+                ui.with_layout(egui::Layout::default().with_cross_align(egui::Align::Max), |ui| {
+                    ui.label(time);
+                });
+            });
+        } else {
+            egui::CollapsingHeader::new(format!("{}  -  {}", scope.label, time))
+                .id_source(&scope.label)
+                .default_open(levels_default_open > 0)
+                .show(ui, |ui| setup_ui_scope_list(ui, &scope.nested_scopes, levels_default_open - 1));
+        }
+        ui.end_row();
+    }
+}
+
+impl ProfilerHistory {
+    pub fn new(max_num_frames: usize) -> Self {
+        ProfilerHistory {
+            frames: VecDeque::with_capacity(max_num_frames),
+            selected_frame: None,
+            max_num_frames,
+        }
+    }
+
+    pub fn push(&mut self, frame: Vec<GpuTimerScopeResult>) {
+        if self.frames.len() == self.max_num_frames {
+            self.frames.pop_front();
+            // Keep looking at the same frame while scrubbing, until it drops out of the history.
+            self.selected_frame = self.selected_frame.and_then(|selected| selected.checked_sub(1));
+        }
+        self.frames.push_back(frame);
+    }
+
+    fn displayed_frame(&self) -> Option<&Vec<GpuTimerScopeResult>> {
+        match self.selected_frame {
+            Some(selected) => self.frames.get(selected),
+            None => self.frames.back(),
+        }
+    }
+
+    pub fn setup_ui(&mut self, ui: &mut egui::Ui, levels_default_open: i32, chrometrace_filename: &Path) {
+        if self.frames.is_empty() {
+            ui.label("no data yet");
+            return;
+        }
+
+        let frame_durations: Vec<f32> = self.frames.iter().map(|frame| frame_duration_ms(frame)).collect();
+        custom_widgets::plot_barchart(
+            ui,
+            egui::vec2(ui.available_size_before_wrap_finite().x, 40.0),
+            &frame_durations,
+            frame_durations.iter().cloned().fold(0.0, f32::max),
+            "ms",
+            3,
+        );
+
+        // Last slider position means "latest", same as for the playback cache.
+        let num_frames = self.frames.len();
+        let mut slider_position = self.selected_frame.unwrap_or(num_frames);
+        ui.horizontal(|ui| {
+            if ui.add(egui::Slider::new(&mut slider_position, 0..=num_frames).text("history")).changed() {
+                self.selected_frame = if slider_position >= num_frames { None } else { Some(slider_position) };
+            }
+            if self.selected_frame.is_some() && ui.button("Latest").clicked() {
+                self.selected_frame = None;
+            }
+        });
+
+        let frame = match self.displayed_frame() {
+            Some(frame) => frame,
+            None => return,
+        };
+        if ui.button("Write Chrometrace").clicked() {
+            info!("Writing chrome trace file to {:?}", chrometrace_filename);
+            if let Err(err) = wgpu_profiler::chrometrace::write_chrometrace(chrometrace_filename, frame) {
+                error!("Failed to write chrometrace {:?}: {}", chrometrace_filename, err);
+            }
+        }
+        custom_widgets::plot_timeline(ui, ui.available_size_before_wrap_finite().x, frame);
+        ui.separator();
+        setup_ui_scope_list(ui, frame, levels_default_open);
+    }
+}
@@ -0,0 +1,66 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+const MAX_NUM_RECENT_SCENES: usize = 8;
+
+// Recently loaded scenes, most recent first.
+// Lives in the user's config directory (unlike the gui settings) since paths are stored absolute and are useful across checkouts.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct RecentScenes {
+    scenes: Vec<PathBuf>,
+}
+
+fn recent_scenes_file() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("blub").join("recent_scenes.json"))
+}
+
+impl RecentScenes {
+    pub fn load() -> Self {
+        let path = match recent_scenes_file() {
+            Some(path) if path.exists() => path,
+            _ => return Default::default(),
+        };
+        match std::fs::File::open(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|file| serde_json::from_reader(std::io::BufReader::new(file)).map_err(|err| err.to_string()))
+        {
+            Ok(recent_scenes) => recent_scenes,
+            Err(err) => {
+                warn!("Failed to read recent scenes from {:?}: {}", path, err);
+                Default::default()
+            }
+        }
+    }
+
+    fn save(&self) {
+        let path = match recent_scenes_file() {
+            Some(path) => path,
+            None => {
+                warn!("No config directory available, can't store recent scenes");
+                return;
+            }
+        };
+        let result = std::fs::create_dir_all(path.parent().unwrap())
+            .and_then(|_| std::fs::File::create(&path))
+            .map_err(|err| err.to_string())
+            .and_then(|file| serde_json::to_writer_pretty(file, self).map_err(|err| err.to_string()));
+        if let Err(err) = result {
+            error!("Failed to write recent scenes to {:?}: {}", path, err);
+        }
+    }
+
+    // Moves the scene to the front of the list and saves it.
+    pub fn add(&mut self, scene: &Path) {
+        let scene = scene.canonicalize().unwrap_or_else(|_| scene.to_path_buf());
+        self.scenes.retain(|recent| *recent != scene);
+        self.scenes.insert(0, scene);
+        self.scenes.truncate(MAX_NUM_RECENT_SCENES);
+        self.save();
+    }
+
+    // Scenes that were deleted or moved since are skipped.
+    pub fn iter(&self) -> impl Iterator<Item = &PathBuf> {
+        self.scenes.iter().filter(|scene| scene.is_file())
+    }
+}
@@ -0,0 +1,117 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, Receiver, Sender},
+    time::SystemTime,
+};
+
+// Largest side of a thumbnail in pixels (as written to disk).
+pub const THUMBNAIL_MAX_SIZE: u32 = 160;
+
+// Png next to the scene file that holds its thumbnail, e.g. scenes/dam.json -> scenes/dam.thumbnail.png
+pub fn thumbnail_path(scene_file: &Path) -> PathBuf {
+    scene_file.with_extension("thumbnail.png")
+}
+
+enum Thumbnail {
+    Loading,
+    // Loaded again if the file was written after this time, i.e. a thumbnail was captured in the meantime.
+    Missing(SystemTime),
+    Loaded { texture: egui::TextureId, size: egui::Vec2 },
+}
+
+struct LoadedThumbnail {
+    scene_file: PathBuf,
+    image: Option<image::RgbaImage>,
+}
+
+// Preview images for the scene selection.
+// Thumbnails are cached as png next to the scene files and are decoded on a background thread the first time a scene is listed.
+// Capturing missing ones is up to the application (it happens a few seconds into a scene, see Application::capture_scene_thumbnail).
+pub struct SceneThumbnails {
+    thumbnails: HashMap<PathBuf, Thumbnail>,
+    loaded_sender: Sender<LoadedThumbnail>,
+    loaded_receiver: Receiver<LoadedThumbnail>,
+}
+
+impl SceneThumbnails {
+    pub fn new() -> Self {
+        let (loaded_sender, loaded_receiver) = channel();
+        SceneThumbnails {
+            thumbnails: HashMap::new(),
+            loaded_sender,
+            loaded_receiver,
+        }
+    }
+
+    // Texture & size of the thumbnail if it is ready. Starts loading it otherwise.
+    pub fn get(&mut self, scene_file: &Path) -> Option<(egui::TextureId, egui::Vec2)> {
+        match self.thumbnails.get(scene_file) {
+            Some(Thumbnail::Loaded { texture, size }) => return Some((*texture, *size)),
+            Some(Thumbnail::Loading) => return None,
+            Some(Thumbnail::Missing(since)) => {
+                let modified = std::fs::metadata(thumbnail_path(scene_file)).and_then(|metadata| metadata.modified());
+                if !modified.map_or(false, |modified| modified > *since) {
+                    return None;
+                }
+            }
+            None => {}
+        }
+
+        self.thumbnails.insert(scene_file.to_path_buf(), Thumbnail::Loading);
+        let scene_file = scene_file.to_path_buf();
+        let loaded_sender = self.loaded_sender.clone();
+        std::thread::spawn(move || {
+            let path = thumbnail_path(&scene_file);
+            let image = if path.exists() {
+                match image::io::Reader::open(&path)
+                    .map_err(image::ImageError::from)
+                    .and_then(|reader| reader.decode())
+                {
+                    Ok(image) => Some(image.to_rgba8()),
+                    Err(err) => {
+                        warn!("Failed to load scene thumbnail {:?}: {}", path, err);
+                        None
+                    }
+                }
+            } else {
+                None
+            };
+            // Receiver is gone if the gui shut down in the meantime.
+            let _ = loaded_sender.send(LoadedThumbnail { scene_file, image });
+        });
+        None
+    }
+
+    // Uploads all thumbnails that finished loading since the last call.
+    pub fn update(&mut self, texture_allocator: &mut dyn epi::TextureAllocator) {
+        while let Ok(loaded) = self.loaded_receiver.try_recv() {
+            let thumbnail = match loaded.image {
+                Some(image) => {
+                    let size = (image.width() as usize, image.height() as usize);
+                    let pixels: Vec<egui::Color32> = image.pixels().map(|p| egui::Color32::from_rgb(p[0], p[1], p[2])).collect();
+                    Thumbnail::Loaded {
+                        texture: texture_allocator.alloc_srgba_premultiplied(size, &pixels),
+                        size: egui::vec2(size.0 as f32, size.1 as f32),
+                    }
+                }
+                None => Thumbnail::Missing(SystemTime::now()),
+            };
+            if let Some(Thumbnail::Loaded { texture, .. }) = self.thumbnails.insert(loaded.scene_file, thumbnail) {
+                texture_allocator.free(texture);
+            }
+        }
+    }
+
+    // Thumbnail changed on disk, it is reloaded once it is asked for again.
+    pub fn invalidate(&mut self, scene_file: &Path, texture_allocator: &mut dyn epi::TextureAllocator) {
+        if let Some(Thumbnail::Loaded { texture, .. }) = self.thumbnails.insert(scene_file.to_path_buf(), Thumbnail::Missing(SystemTime::now())) {
+            texture_allocator.free(texture);
+        }
+    }
+
+    // Textures are gone, e.g. because the gui renderer was recreated.
+    pub fn clear(&mut self) {
+        self.thumbnails.clear();
+    }
+}
@@ -0,0 +1,97 @@
+// GUI color theme & colors used by plots.
+//
+// Like the decimal separator in formatting, the plot palette is global so that plot widgets don't need to have it passed through.
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::sync::RwLock;
+use strum_macros::EnumIter;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, EnumIter)]
+pub enum Theme {
+    Dark,
+    Light,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::Dark
+    }
+}
+
+impl Theme {
+    pub fn style(self) -> egui::Style {
+        let mut style = egui::Style::default();
+        match self {
+            Theme::Dark => {
+                style.visuals = egui::Visuals::dark();
+                style.visuals.code_bg_color = egui::Color32::from_rgb(64, 64, 100);
+            }
+            Theme::Light => {
+                style.visuals = egui::Visuals::light();
+            }
+        }
+        style
+    }
+}
+
+// Default colors are from the Okabe-Ito palette, which stays distinguishable for the common kinds of color blindness
+// and survives being printed in grayscale reasonably well.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct PlotPalette {
+    pub bar: [u8; 3],
+    pub bar_highlight: [u8; 3],
+    // Colors for categories, e.g. profiler scopes.
+    pub categories: Vec<[u8; 3]>,
+}
+
+impl Default for PlotPalette {
+    fn default() -> Self {
+        PlotPalette {
+            bar: [0x56, 0xB4, 0xE9],           // sky blue
+            bar_highlight: [0xE6, 0x9F, 0x00], // orange
+            categories: vec![
+                [0xE6, 0x9F, 0x00], // orange
+                [0x56, 0xB4, 0xE9], // sky blue
+                [0x00, 0x9E, 0x73], // bluish green
+                [0xF0, 0xE4, 0x42], // yellow
+                [0x00, 0x72, 0xB2], // blue
+                [0xD5, 0x5E, 0x00], // vermillion
+                [0xCC, 0x79, 0xA7], // reddish purple
+            ],
+        }
+    }
+}
+
+impl PlotPalette {
+    pub fn bar(&self) -> egui::Color32 {
+        color(self.bar)
+    }
+
+    pub fn bar_highlight(&self) -> egui::Color32 {
+        color(self.bar_highlight)
+    }
+
+    pub fn category(&self, index: usize) -> egui::Color32 {
+        match self.categories.len() {
+            0 => color(self.bar),
+            num_categories => color(self.categories[index % num_categories]),
+        }
+    }
+}
+
+fn color(rgb: [u8; 3]) -> egui::Color32 {
+    egui::Color32::from_rgb(rgb[0], rgb[1], rgb[2])
+}
+
+lazy_static! {
+    static ref PLOT_PALETTE: RwLock<PlotPalette> = RwLock::new(Default::default());
+}
+
+pub fn set_plot_palette(palette: &PlotPalette) {
+    *PLOT_PALETTE.write().unwrap() = palette.clone();
+}
+
+pub fn plot_palette() -> PlotPalette {
+    PLOT_PALETTE.read().unwrap().clone()
+}
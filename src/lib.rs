@@ -0,0 +1,30 @@
+// Library target, only used for the python bindings (enable with the "python" feature).
+// Shares the simulation relevant modules with the application, everything window/gui related stays in main.rs.
+#![cfg(feature = "python")]
+// Large parts of the renderer are only used by the application.
+#![allow(dead_code)]
+
+#[macro_use]
+extern crate more_asserts;
+#[macro_use]
+extern crate log;
+#[macro_use]
+extern crate strum_macros;
+#[macro_use]
+mod wgpu_utils;
+
+mod camera;
+mod global_bindings;
+mod global_ubo;
+mod log_buffer;
+mod python;
+mod recording_schedule;
+mod render_output;
+mod renderer;
+mod scene;
+mod settings_overrides;
+mod simulation;
+mod timer;
+mod utils;
+
+use wgpu_utils::shader;
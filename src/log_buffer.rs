@@ -0,0 +1,88 @@
+use lazy_static::lazy_static;
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex, MutexGuard,
+    },
+};
+
+pub struct LogEntry {
+    pub level: log::Level,
+    pub target: String,
+    pub message: String,
+    pub timestamp: String,
+}
+
+const MAX_NUM_LOG_ENTRIES: usize = 1000;
+
+lazy_static! {
+    static ref LOG_ENTRIES: Mutex<VecDeque<LogEntry>> = Mutex::new(VecDeque::with_capacity(MAX_NUM_LOG_ENTRIES));
+}
+// Total number of entries ever logged, including the ones that were already dropped.
+static NUM_ENTRIES_LOGGED: AtomicUsize = AtomicUsize::new(0);
+
+// Warnings & errors are always buffered, no matter what the env_logger filter says.
+// The application inspects them in some places (see Screen::try_create_swap_chain), which mustn't depend on RUST_LOG.
+const MIN_BUFFERED_LEVEL: log::LevelFilter = log::LevelFilter::Warn;
+
+// Logger that passes everything on to env_logger but also keeps the latest records in memory, so we can show them in the GUI.
+struct BufferedLogger {
+    env_logger: env_logger::Logger,
+}
+
+impl log::Log for BufferedLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= MIN_BUFFERED_LEVEL || self.env_logger.enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.env_logger.matches(record) {
+            self.env_logger.log(record);
+        } else if record.level() > MIN_BUFFERED_LEVEL {
+            return;
+        }
+
+        let mut entries = LOG_ENTRIES.lock().unwrap();
+        if entries.len() == MAX_NUM_LOG_ENTRIES {
+            entries.pop_front();
+        }
+        entries.push_back(LogEntry {
+            level: record.level(),
+            target: record.target().to_string(),
+            message: record.args().to_string(),
+            timestamp: chrono::Local::now().format("%H:%M:%S%.3f").to_string(),
+        });
+        NUM_ENTRIES_LOGGED.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn flush(&self) {
+        self.env_logger.flush();
+    }
+}
+
+pub fn init(env: env_logger::Env) {
+    let env_logger = env_logger::Builder::from_env(env).build();
+    log::set_max_level(env_logger.filter().max(MIN_BUFFERED_LEVEL));
+    log::set_boxed_logger(Box::new(BufferedLogger { env_logger })).unwrap();
+}
+
+pub fn entries() -> MutexGuard<'static, VecDeque<LogEntry>> {
+    LOG_ENTRIES.lock().unwrap()
+}
+
+pub fn num_entries_logged() -> usize {
+    NUM_ENTRIES_LOGGED.load(Ordering::SeqCst)
+}
+
+// Messages of all entries logged after num_entries_logged() returned the given value, as far as they are still buffered.
+pub fn messages_since(num_logged_before: usize, target_prefix: &str) -> Vec<String> {
+    let entries = entries();
+    let num_new = num_entries_logged().saturating_sub(num_logged_before).min(entries.len());
+    entries
+        .iter()
+        .skip(entries.len() - num_new)
+        .filter(|entry| entry.target.starts_with(target_prefix))
+        .map(|entry| entry.message.clone())
+        .collect()
+}
@@ -7,115 +7,258 @@ extern crate strum_macros;
 #[macro_use]
 mod wgpu_utils;
 
+mod benchmark;
 mod camera;
+mod command_line;
+mod fluid_interaction;
+mod frame_limiter;
 mod global_bindings;
 mod global_ubo;
+mod grid_export;
 mod gui;
+mod log_buffer;
+mod recording_schedule;
 mod render_output;
+mod renderdoc_capture;
 mod renderer;
 mod scene;
+mod scene_loader;
+mod settings_overrides;
 mod simulation;
 mod simulation_controller;
+mod simulation_thread;
+mod sweep;
 mod timer;
 mod utils;
 use wgpu_profiler::{wgpu_profiler, GpuProfiler};
 
 use global_bindings::*;
 use global_ubo::*;
-use render_output::{hdr_backbuffer::HdrBackbuffer, screen::Screen, screenshot_recorder::ScreenshotRecorder};
+use recording_schedule::RecordingSchedule;
+use render_output::{
+    color_grading::ColorGrading,
+    hdr_backbuffer::HdrBackbuffer,
+    offscreen_target::{OffscreenTarget, OffscreenTargetDesc},
+    recording_overlay::RecordingOverlay,
+    screen::{ColorSpaceDebugMode, Screen, ScreenUniformBufferContent},
+    screenshot_recorder::{RecordingMetadata, ScreenshotRecorder},
+};
+use renderdoc_capture::RenderDocCapture;
 use renderer::SceneRenderer;
 use simulation_controller::SimulationControllerStatus;
+use simulation_thread::{FastForwardJob, Lendable, SimulationThread};
 use std::{
     path::{Path, PathBuf},
-    time::Duration,
+    sync::Arc,
+    time::{Duration, Instant},
 };
-use wgpu_utils::{pipelines, shader};
+use wgpu_utils::{error_handling::GpuErrorTracker, fence_profiler::FenceProfiler, pipelines, shader, texture_pool::TexturePool};
 use winit::{
     event::{Event, KeyboardInput, VirtualKeyCode, WindowEvent},
-    event_loop::{ControlFlow, EventLoop, EventLoopProxy},
+    event_loop::{ControlFlow, EventLoop, EventLoopProxy, EventLoopWindowTarget},
     window::Window,
     window::WindowBuilder,
 };
 
+// Workgroup size autotuning results, per adapter.
+const WORKGROUP_SIZES_PATH: &str = "workgroup_sizes.json";
+
 #[derive(Debug, Clone)]
 pub enum ApplicationEvent {
     LoadScene(PathBuf),
     ResetScene,
+    // Changes the fluid grid resolution by the given factor, keeping the simulation running.
+    ResampleGrid(f32),
     FastForwardSimulation(Duration),
-    ResetAndStartRecording { recording_fps: f64 }, // to stop recording, pause the simulation controller.
+    // To stop recording, pause the simulation controller.
+    // If an offscreen target is given, recording happens at its resolution instead of the window's.
+    ResetAndStartRecording {
+        recording_fps: f64,
+        offscreen: Option<OffscreenTargetDesc>,
+    },
     ChangePresentMode(wgpu::PresentMode),
+    ClearShaderCache,
+    OpenProfilerWindow,
+    // Replaces the thumbnail of the current scene with the next frame.
+    CaptureSceneThumbnail,
+}
+
+impl ApplicationEvent {
+    // Whether this event makes an earlier, not yet processed event pointless.
+    fn supersedes(&self, earlier: &ApplicationEvent) -> bool {
+        match (self, earlier) {
+            (ApplicationEvent::LoadScene(..), ApplicationEvent::LoadScene(..))
+            | (ApplicationEvent::LoadScene(..), ApplicationEvent::ResetScene)
+            | (ApplicationEvent::LoadScene(..), ApplicationEvent::ResampleGrid(..))
+            | (ApplicationEvent::ResetScene, ApplicationEvent::ResetScene)
+            | (ApplicationEvent::ChangePresentMode(..), ApplicationEvent::ChangePresentMode(..))
+            | (ApplicationEvent::ClearShaderCache, ApplicationEvent::ClearShaderCache)
+            | (ApplicationEvent::OpenProfilerWindow, ApplicationEvent::OpenProfilerWindow)
+            | (ApplicationEvent::CaptureSceneThumbnail, ApplicationEvent::CaptureSceneThumbnail) => true,
+            _ => false,
+        }
+    }
 }
 
 struct Application {
+    wgpu_instance: wgpu::Instance,
     window: Window,
     window_surface: wgpu::Surface,
     screen: Screen,
     hdr_backbuffer: HdrBackbuffer,
+    color_grading: ColorGrading,
     screenshot_recorder: ScreenshotRecorder,
+    recording_overlay: RecordingOverlay,
+    texture_pool: TexturePool,
+    last_window_resize: Instant,
+    // Render resolution that the render targets are (or will be after the resize delay) resized to.
+    requested_render_resolution: winit::dpi::PhysicalSize<u32>,
 
-    device: wgpu::Device,
-    command_queue: wgpu::Queue,
+    // Shared with the simulation thread.
+    device: Arc<wgpu::Device>,
+    command_queue: Arc<wgpu::Queue>,
+    gpu_errors: GpuErrorTracker,
+    renderdoc: RenderDocCapture,
 
     profiler_rendering: GpuProfiler,
     profiler_simulation: GpuProfiler,
+    fence_profiler_rendering: FenceProfiler,
+    fence_profiler_simulation: FenceProfiler,
 
     shader_dir: shader::ShaderDirectory,
-    pipeline_manager: pipelines::PipelineManager,
-    scene: scene::Scene,
+    pipeline_manager: Lendable<pipelines::PipelineManager>,
+    scene: Lendable<scene::Scene>,
     scene_renderer: SceneRenderer,
-    simulation_controller: simulation_controller::SimulationController,
+    simulation_controller: Lendable<simulation_controller::SimulationController>,
+    simulation_thread: SimulationThread,
     gui: gui::GUI,
 
     camera: camera::Camera,
+    fluid_interaction: fluid_interaction::FluidInteraction,
+    cursor_position: winit::dpi::PhysicalPosition<f64>,
     global_ubo: GlobalUBO,
     global_bindings: GlobalBindings,
+
+    // From the command line, applied to every scene that is loaded or reset.
+    settings_overrides: settings_overrides::SettingsOverrides,
+    scene_loader: scene_loader::SceneLoader,
+    // See queue_application_event
+    pending_application_events: Vec<ApplicationEvent>,
+
+    window_focused: bool,
+    suspended: bool,
+    frame_limiter: frame_limiter::FrameLimiter,
+    grid_exporter: grid_export::GridExporter,
+    // Simulated time at which the current scene's thumbnail is captured, None if it already has one.
+    scene_thumbnail_capture_time: Option<Duration>,
+    window_title: String,
+    window_title_update: Instant,
+    frames_since_window_title_update: u32,
 }
 
 impl Application {
-    async fn new(event_loop: &EventLoop<ApplicationEvent>) -> Application {
+    // Creates a device for the given window. Returns None (with an error logged) if there is no suitable adapter.
+    // The instance is kept around for creating surfaces of additional windows.
+    async fn create_device(window: &Window) -> Option<(wgpu::Instance, wgpu::Surface, wgpu::AdapterInfo, Arc<wgpu::Device>, Arc<wgpu::Queue>)> {
         let wgpu_instance = wgpu::Instance::new(wgpu::BackendBit::VULKAN); //wgpu::BackendBit::DX12);
-        let window = WindowBuilder::new()
-            .with_title("Blub")
-            .with_resizable(true)
-            .with_inner_size(winit::dpi::LogicalSize::new(1980, 1080))
-            .build(&event_loop)
-            .unwrap();
-
-        let window_surface = unsafe { wgpu_instance.create_surface(&window) };
+        let window_surface = unsafe { wgpu_instance.create_surface(window) };
         let adapter = wgpu_instance
             .request_adapter(&wgpu::RequestAdapterOptions {
                 power_preference: wgpu::PowerPreference::HighPerformance,
                 compatible_surface: Some(&window_surface),
             })
-            .await
-            .unwrap();
+            .await;
+        let adapter = match adapter {
+            Some(adapter) => adapter,
+            None => {
+                error!("No gpu adapter found that can present to the window");
+                return None;
+            }
+        };
+        // Without push constants, PipelineManager emulates them.
+        let (push_constant_features, max_push_constant_size) = pipelines::push_constant_requirements(&adapter);
+        if adapter.limits().max_storage_buffers_per_shader_stage < simulation::MAX_STORAGE_BUFFERS_PER_SHADER_STAGE {
+            error!(
+                "Adapter supports only {} storage buffers per shader stage, the simulation needs {}",
+                adapter.limits().max_storage_buffers_per_shader_stage,
+                simulation::MAX_STORAGE_BUFFERS_PER_SHADER_STAGE
+            );
+            return None;
+        }
 
-        let (device, command_queue) = adapter
+        let device = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
                     label: Some("main device"),
-                    features: wgpu::Features::PUSH_CONSTANTS
+                    // Wireframe rendering is only for debugging, so don't require it.
+                    // Without timestamp queries, profiling falls back to FenceProfiler.
+                    features: (adapter.features() & (wgpu::Features::NON_FILL_POLYGON_MODE | wgpu::Features::TIMESTAMP_QUERY))
+                        | push_constant_features
                         | wgpu::Features::SAMPLED_TEXTURE_BINDING_ARRAY
                         | wgpu::Features::SAMPLED_TEXTURE_ARRAY_NON_UNIFORM_INDEXING
                         | wgpu::Features::SAMPLED_TEXTURE_ARRAY_DYNAMIC_INDEXING
                         | wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
                         | wgpu::Features::CONSERVATIVE_RASTERIZATION
-                        | wgpu::Features::TIMESTAMP_QUERY
                         | wgpu::Features::CLEAR_COMMANDS,
                     limits: wgpu::Limits {
-                        max_push_constant_size: 8,
+                        max_push_constant_size,
+                        // Particle binning alone binds more storage buffers than the default limit of 8 allows.
+                        max_storage_buffers_per_shader_stage: adapter.limits().max_storage_buffers_per_shader_stage,
                         ..Default::default()
                     },
                 },
                 None, //Some(Path::new("C:/dev/blub/trace")),
             )
-            .await
+            .await;
+        let (device, command_queue) = match device {
+            Ok(device) => device,
+            Err(err) => {
+                error!("Failed to create device: {}", err);
+                return None;
+            }
+        };
+        Some((
+            wgpu_instance,
+            window_surface,
+            adapter.get_info(),
+            Arc::new(device),
+            Arc::new(command_queue),
+        ))
+    }
+
+    async fn new(
+        event_loop: &EventLoop<ApplicationEvent>,
+        startup_scene: Option<PathBuf>,
+        settings_overrides: settings_overrides::SettingsOverrides,
+        force_workgroup_autotune: bool,
+    ) -> Application {
+        let window = WindowBuilder::new()
+            .with_title("Blub")
+            .with_resizable(true)
+            .with_inner_size(winit::dpi::LogicalSize::new(1980, 1080))
+            .build(&event_loop)
             .unwrap();
+        // RenderDoc needs to be hooked up before the device is created.
+        let renderdoc = RenderDocCapture::new();
+        let (wgpu_instance, window_surface, adapter_info, device, command_queue) =
+            Self::create_device(&window).await.unwrap_or_else(|| std::process::exit(1));
+        let gpu_errors = GpuErrorTracker::new();
+        gpu_errors.install_uncaptured_error_handler(&device);
 
-        let shader_dir = shader::ShaderDirectory::new(Path::new("shader"), Path::new(".shadercache"));
-        let mut pipeline_manager = pipelines::PipelineManager::new();
+        let mut shader_dir = shader::ShaderDirectory::new(Path::new("shader"), Path::new(".shadercache"));
+        // Needs to happen before any simulation shader is compiled.
+        simulation::WorkgroupSizes::load_or_autotune(
+            &device,
+            &command_queue,
+            &shader_dir,
+            &adapter_info,
+            Path::new(WORKGROUP_SIZES_PATH),
+            force_workgroup_autotune,
+        )
+        .apply_to(&mut shader_dir);
+        let mut pipeline_manager = pipelines::PipelineManager::new(&device);
 
-        let screen = Screen::new(
+        let mut screen = Screen::new(
             &device,
             &window_surface,
             Screen::DEFAULT_PRESENT_MODE,
@@ -123,7 +266,37 @@ impl Application {
             &shader_dir,
             &mut pipeline_manager,
         );
-        let hdr_backbuffer = HdrBackbuffer::new(&device, screen.resolution(), &shader_dir, &mut pipeline_manager);
+        let mut gui = gui::GUI::new(&device, &window);
+        gui.set_present_modes(screen.supported_present_modes(), screen.present_mode());
+
+        // Compile all shaders upfront with a progress bar, instead of stalling on each pipeline one by one.
+        {
+            let shader_cache_warming = shader_dir.warm_cache();
+            while !shader_cache_warming.is_finished() {
+                if let Ok(frame) = screen.start_frame(&device, &window_surface) {
+                    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                        label: Some("Encoder: Startup progress"),
+                    });
+                    gui.draw_startup_progress(
+                        &device,
+                        &window,
+                        &mut encoder,
+                        &command_queue,
+                        screen.backbuffer(),
+                        screen.resolution(),
+                        &shader_cache_warming,
+                    );
+                    screen.copy_to_swapchain(&frame, &mut encoder, &pipeline_manager, ColorSpaceDebugMode::None);
+                    pipeline_manager.flush_push_constants(&device, &command_queue);
+                    command_queue.submit(Some(encoder.finish()));
+                    screen.end_frame(frame);
+                }
+                std::thread::sleep(std::time::Duration::from_millis(16));
+            }
+            shader_cache_warming.join();
+        }
+        let mut color_grading = ColorGrading::new(&device, &command_queue);
+        let hdr_backbuffer = HdrBackbuffer::new(&device, screen.resolution(), &shader_dir, &mut pipeline_manager, &color_grading);
         let global_ubo = GlobalUBO::new(&device);
         let mut global_bindings = GlobalBindings::new(&device);
         let simulation_controller = simulation_controller::SimulationController::new();
@@ -133,137 +306,342 @@ impl Application {
             &shader_dir,
             &mut pipeline_manager,
             global_bindings.bind_group_layout(),
-            &hdr_backbuffer,
         );
-        let gui = gui::GUI::new(&device, &window);
 
         let profiler_rendering = GpuProfiler::new(4, command_queue.get_timestamp_period());
         let profiler_simulation = GpuProfiler::new(16, command_queue.get_timestamp_period());
 
-        // Load initial scene. Gui already needs to list all scenes, so we go there to grab the default selected.
-        let scene = scene::Scene::new(
-            gui.selected_scene(),
-            &device,
-            &command_queue,
-            &shader_dir,
-            &mut pipeline_manager,
-            global_bindings.bind_group_layout(),
-        )
-        .unwrap();
+        // Load initial scene. Gui already needs to list all scenes (and knows the recent ones), so we go there to grab the candidates.
+        // Scenes may have been moved or broken since they were last used, so we go on until one loads.
+        if !settings_overrides.is_empty() {
+            info!("Settings overrides from the command line are applied to all scenes");
+        }
+        let (startup_scene, scene) = startup_scene
+            .into_iter()
+            .chain(gui.startup_scene_candidates())
+            .find_map(|scene_path| {
+                match scene::Scene::load(
+                    &scene_path,
+                    &settings_overrides,
+                    &device,
+                    &command_queue,
+                    &shader_dir,
+                    &mut pipeline_manager,
+                    global_bindings.bind_group_layout(),
+                ) {
+                    Ok(scene) => Some((scene_path, scene)),
+                    Err(error) => {
+                        error!("Failed to load scene from {:?}: {:?}", scene_path, error);
+                        None
+                    }
+                }
+            })
+            .unwrap_or_else(|| {
+                error!("None of the scenes could be loaded");
+                std::process::exit(1)
+            });
+        gui.on_scene_loaded(&startup_scene);
         scene_renderer.on_new_scene(&device, &command_queue, &scene);
         global_bindings.create_bind_group(&device, &global_ubo, &scene.models);
+        color_grading.set_lut(&device, &command_queue, scene.config().color_grading_lut.as_deref());
+        let requested_render_resolution = screen.resolution();
 
         Application {
+            wgpu_instance,
             window,
             window_surface,
             screen,
             hdr_backbuffer,
+            color_grading,
             screenshot_recorder: ScreenshotRecorder::new(),
+            recording_overlay: RecordingOverlay::new(&device),
+            texture_pool: TexturePool::new(),
+            last_window_resize: Instant::now(),
+            requested_render_resolution,
 
             device,
             command_queue,
+            gpu_errors,
+            renderdoc,
 
             profiler_rendering,
             profiler_simulation,
+            fence_profiler_rendering: FenceProfiler::new(4),
+            fence_profiler_simulation: FenceProfiler::new(16),
 
             shader_dir,
-            pipeline_manager,
-            scene,
+            pipeline_manager: Lendable::new(pipeline_manager),
+            scene: Lendable::new(scene),
             scene_renderer,
-            simulation_controller,
+            simulation_controller: Lendable::new(simulation_controller),
+            simulation_thread: SimulationThread::new(),
             gui,
 
             camera: camera::Camera::new(),
+            fluid_interaction: fluid_interaction::FluidInteraction::new(),
+            cursor_position: winit::dpi::PhysicalPosition::new(0.0, 0.0),
             global_ubo,
             global_bindings,
+
+            settings_overrides,
+            scene_loader: scene_loader::SceneLoader::new(),
+            pending_application_events: Vec::new(),
+
+            window_focused: true,
+            suspended: false,
+            frame_limiter: frame_limiter::FrameLimiter::new(),
+            grid_exporter: grid_export::GridExporter::new(),
+            scene_thumbnail_capture_time: Self::scene_thumbnail_capture_time(&startup_scene),
+            window_title: "Blub".to_string(),
+            window_title_update: Instant::now(),
+            frames_since_window_title_update: 0,
         }
     }
 
-    pub fn load_scene(&mut self, scene_path: &Path) {
-        let new_scene = scene::Scene::new(
+    // Loads a scene synchronously, see SceneLoader for loading in the background.
+    pub fn load_scene(&mut self, scene_path: &Path) -> bool {
+        let new_scene = scene::Scene::load(
             scene_path,
+            &self.settings_overrides,
             &self.device,
             &self.command_queue,
             &self.shader_dir,
             &mut self.pipeline_manager,
             self.global_bindings.bind_group_layout(),
         );
+        self.switch_to_scene(scene_path, new_scene)
+    }
+
+    // Picks up a scene that finished loading in the background.
+    fn finish_scene_loading(&mut self) {
+        let (scene_path, scene_data) = match self.scene_loader.poll_finished() {
+            Some(finished) => finished,
+            None => return,
+        };
+        let new_scene = match scene_data {
+            Ok(scene_data) => {
+                let mut scene = scene::Scene::from_data(
+                    scene_data,
+                    &self.device,
+                    &self.command_queue,
+                    &self.shader_dir,
+                    &mut self.pipeline_manager,
+                    self.global_bindings.bind_group_layout(),
+                );
+                self.settings_overrides.apply(&mut scene);
+                Ok(scene)
+            }
+            Err(error) => Err(error.into()),
+        };
+        if self.switch_to_scene(&scene_path, new_scene) {
+            self.gui.on_scene_loaded(&scene_path);
+        }
+        self.simulation_controller.restart();
+    }
+
+    // Scenes without a thumbnail get one a bit into the simulation, when there is some movement to see.
+    fn scene_thumbnail_capture_time(scene_path: &Path) -> Option<Duration> {
+        if gui::scene_thumbnails::thumbnail_path(scene_path).exists() {
+            None
+        } else {
+            Some(Duration::from_secs(2))
+        }
+    }
+
+    // Writes the tonemapped frame (without gui) as thumbnail of the current scene once its capture time is reached.
+    fn capture_scene_thumbnail(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        let capture_time = match self.scene_thumbnail_capture_time {
+            Some(capture_time) => capture_time,
+            None => return,
+        };
+        // With an offscreen target, the screen only shows a preview.
+        if self.simulation_controller.timer().total_simulated_time() < capture_time || self.screenshot_recorder.offscreen_target().is_some() {
+            return;
+        }
+        let scene_path = self.scene.path().to_path_buf();
+        self.screen.capture_thumbnail(
+            &gui::scene_thumbnails::thumbnail_path(&scene_path),
+            gui::scene_thumbnails::THUMBNAIL_MAX_SIZE,
+            &self.device,
+            encoder,
+        );
+        self.gui.on_scene_thumbnail_captured(&scene_path);
+        self.scene_thumbnail_capture_time = None;
+    }
 
+    // Keeps the previous scene if the new one failed to load.
+    fn switch_to_scene(&mut self, scene_path: &Path, new_scene: Result<scene::Scene, Box<dyn std::error::Error>>) -> bool {
         match new_scene {
             Ok(scene) => {
-                self.scene = scene;
+                self.scene = Lendable::new(scene);
+                // A new export (named after the new scene) starts with the next frame if still enabled.
+                self.grid_exporter.stop();
+                self.scene_thumbnail_capture_time = Self::scene_thumbnail_capture_time(scene_path);
                 self.scene_renderer.on_new_scene(&self.device, &self.command_queue, &self.scene);
                 self.global_bindings.create_bind_group(&self.device, &self.global_ubo, &self.scene.models);
+                self.color_grading
+                    .set_lut(&self.device, &self.command_queue, self.scene.config().color_grading_lut.as_deref());
+                true
             }
             Err(error) => {
                 error!("Failed to load scene from {:?}: {:?}", scene_path, error);
+                false
             }
         }
     }
 
+    // Runs all benchmark scenes one after another without rendering and writes out a report.
+    fn run_benchmark(mut self, config: benchmark::BenchmarkConfig) {
+        let mut results = Vec::new();
+        for scene_path in config.scenes.iter() {
+            if !self.load_scene(scene_path) {
+                continue;
+            }
+            results.push(benchmark::run_scene(
+                &config,
+                &mut self.scene,
+                &self.device,
+                &self.command_queue,
+                &self.pipeline_manager,
+                &mut self.global_ubo,
+                &self.global_bindings,
+            ));
+        }
+        benchmark::write_report(&config, &results);
+    }
+
+    // Simulates every variant of a parameter sweep, taking screenshots along the way and writing out statistics.
+    fn run_sweep(mut self, config: sweep::SweepConfig) {
+        self.profiler_rendering.enable_timer = false;
+        for variant in config.variants.iter() {
+            let scene_path = match config.write_variant_scene(variant) {
+                Ok(scene_path) => scene_path,
+                Err(err) => {
+                    error!("Failed to write scene for sweep variant {:?}: {}", variant.name, err);
+                    continue;
+                }
+            };
+            if !self.load_scene(&scene_path) {
+                continue;
+            }
+            variant.overrides.apply(&mut self.scene);
+
+            info!("Sweep variant {:?}, simulating {} steps", variant.name, config.num_steps());
+            let directory = config.variant_directory(variant);
+            let mut benchmark = benchmark::SceneBenchmark::new(config.simulation_steps_per_second, &self.device, &self.command_queue);
+            for step in 1..=config.num_steps() {
+                benchmark.step(
+                    &mut self.scene,
+                    &self.device,
+                    &self.command_queue,
+                    &self.pipeline_manager,
+                    &mut self.global_ubo,
+                    &self.global_bindings,
+                );
+                if config.is_screenshot_step(step) {
+                    let screenshot_path = directory.join(format!("{:05}.png", step));
+                    self.render_screenshot(benchmark.timer(), &screenshot_path);
+                }
+            }
+            sweep::write_variant_statistics(&directory, &benchmark.finish(&self.scene));
+        }
+    }
+
+    // Renders the scene (without gui) and writes it synchronously to a png file.
+    fn render_screenshot(&mut self, timer: &timer::Timer, path: &Path) {
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Encoder: Screenshot"),
+        });
+        self.update_global_ubo(timer.fill_global_uniform_buffer());
+        self.scene_renderer.draw(
+            &self.scene,
+            &mut self.profiler_rendering,
+            &self.device,
+            &self.command_queue,
+            &mut encoder,
+            &self.pipeline_manager,
+            &self.hdr_backbuffer,
+            self.screen.depthbuffer(),
+            self.global_bindings.bind_group(&self.global_ubo),
+            &self.camera.frustum(self.screen.aspect_ratio()),
+        );
+        self.hdr_backbuffer.tonemap(
+            &self.screen.backbuffer(),
+            self.screen.resolution(),
+            &mut encoder,
+            &self.pipeline_manager,
+            &self.color_grading,
+        );
+        self.screen.capture_screenshot(path, &self.device, &mut encoder);
+        self.profiler_rendering.resolve_queries(&mut encoder);
+        self.pipeline_manager.flush_push_constants(&self.device, &self.command_queue);
+        self.command_queue.submit(Some(encoder.finish()));
+        self.profiler_rendering.end_frame().unwrap();
+        self.screen.wait_for_pending_screenshots(&self.device);
+    }
+
     fn run(mut self, event_loop: EventLoop<ApplicationEvent>) {
         let event_loop_proxy = event_loop.create_proxy();
 
-        event_loop.run(move |event, _, control_flow| {
+        event_loop.run(move |event, event_loop_target, control_flow| {
             // ControlFlow::Poll continuously runs the event loop, even if the OS hasn't
             // dispatched any events. This is ideal for games and similar applications.
-            *control_flow = ControlFlow::Poll;
+            // While suspended in the background however, we only want to wake up for new events.
+            *control_flow = if self.suspended { ControlFlow::Wait } else { ControlFlow::Poll };
+
+            if self.simulation_thread.is_busy() {
+                self.handle_event_during_simulation_job(&event, control_flow);
+                self.gui.handle_event(&event);
+                return;
+            }
 
             match &event {
-                Event::UserEvent(event) => match event {
-                    ApplicationEvent::LoadScene(scene_path) => {
-                        self.load_scene(scene_path);
-                        self.simulation_controller.restart();
-                    }
-                    ApplicationEvent::ResetScene => {
-                        self.scene.reset(
-                            &self.device,
-                            &self.command_queue,
-                            &self.shader_dir,
-                            &mut self.pipeline_manager,
-                            self.global_bindings.bind_group_layout(),
-                        );
-                        self.simulation_controller.restart();
-                    }
-                    ApplicationEvent::FastForwardSimulation(simulation_jump_length) => {
-                        self.simulation_controller.fast_forward_steps(
-                            *simulation_jump_length,
-                            &self.device,
-                            &self.command_queue,
-                            &mut self.scene,
-                            &self.pipeline_manager,
-                            self.global_bindings.bind_group(), // values from last draw are good enough.
-                        );
-                    }
-                    ApplicationEvent::ResetAndStartRecording { recording_fps } => {
-                        self.scene.reset(
-                            &self.device,
-                            &self.command_queue,
-                            &self.shader_dir,
-                            &mut self.pipeline_manager,
-                            self.global_bindings.bind_group_layout(),
-                        );
-                        self.simulation_controller.restart();
-                        self.simulation_controller.start_recording_with_fixed_frame_length(*recording_fps);
-                        self.screenshot_recorder.start_next_recording();
-                    }
-                    ApplicationEvent::ChangePresentMode(present_mode) => {
-                        self.screen = Screen::new(
-                            &self.device,
-                            &self.window_surface,
-                            *present_mode,
-                            self.screen.resolution(),
-                            &self.shader_dir,
-                            &mut self.pipeline_manager,
-                        );
-                    }
+                Event::UserEvent(event) => self.queue_application_event(event.clone()),
+                // Additional gui windows are handled by the gui below, apart from closing and focus.
+                // Focusing them shouldn't count as being in the background.
+                Event::WindowEvent { event, window_id } if self.gui.owns_window(*window_id) => match event {
+                    WindowEvent::CloseRequested => self.gui.close_profiler_window(),
+                    WindowEvent::Focused(focused) => self.window_focused = *focused,
+                    _ => {}
                 },
                 Event::WindowEvent { event, .. } => {
                     self.camera.on_window_event(&event);
+                    // Dragging a gizmo takes the mouse away from fluid interaction & object picking.
+                    self.scene_renderer.gizmos.on_window_event(&event, self.gui.wants_pointer_input());
+                    let pointer_captured = self.gui.wants_pointer_input() || self.scene_renderer.gizmos.is_dragging();
+                    self.fluid_interaction.on_window_event(&event, pointer_captured);
                     match event {
                         WindowEvent::CloseRequested => {
                             *control_flow = ControlFlow::Exit;
                         }
+                        WindowEvent::Focused(focused) => {
+                            self.window_focused = *focused;
+                        }
+                        WindowEvent::CursorMoved { position, .. } => {
+                            self.cursor_position = *position;
+                            let voxel_slice = self.scene_renderer.voxel_slice;
+                            if voxel_slice.enabled && voxel_slice.inspect && !self.gui.wants_pointer_input() {
+                                let window_size = self.window.inner_size();
+                                self.scene_renderer.grid_cell_inspector.request(cgmath::point2(
+                                    position.x as f32 / window_size.width.max(1) as f32,
+                                    position.y as f32 / window_size.height.max(1) as f32,
+                                ));
+                            }
+                        }
+                        // Left click selects objects, unless it is used for fluid interaction or a gizmo.
+                        WindowEvent::MouseInput {
+                            button: winit::event::MouseButton::Left,
+                            state: winit::event::ElementState::Pressed,
+                            ..
+                        } => {
+                            if self.fluid_interaction.mode == fluid_interaction::InteractionMode::None && !pointer_captured {
+                                let window_size = self.window.inner_size();
+                                self.scene_renderer.object_picking.request_pick(cgmath::point2(
+                                    self.cursor_position.x as f32 / window_size.width.max(1) as f32,
+                                    self.cursor_position.y as f32 / window_size.height.max(1) as f32,
+                                ));
+                            }
+                        }
                         // Instead of handling WindowEvent::Resized and WindowEvent::ScaleFactorChanged here, we periodically check in draw.
                         // Has the advantage of not doing more resizes than necessary, also need to check size already for 0 size!
                         WindowEvent::KeyboardInput {
@@ -276,13 +654,22 @@ impl Application {
                             ..
                         } => match virtual_keycode {
                             VirtualKeyCode::Escape => *control_flow = ControlFlow::Exit,
-                            VirtualKeyCode::Snapshot => self.screenshot_recorder.schedule_next_screenshot(), // Bug? doesn't seem to receive a winit::event::ElementState::Pressed event.
+                            VirtualKeyCode::F11 => {
+                                if let winit::event::ElementState::Pressed = state {
+                                    self.toggle_fullscreen();
+                                }
+                            }
+                            VirtualKeyCode::Snapshot => self.screenshot_recorder.schedule_next_screenshot(self.gui.screenshot_format()), // Bug? doesn't seem to receive a winit::event::ElementState::Pressed event.
                             VirtualKeyCode::Space => {
                                 if let winit::event::ElementState::Pressed = state {
                                     self.simulation_controller.pause_or_resume();
                                 }
                             }
-                            _ => {}
+                            _ => {
+                                if *state == winit::event::ElementState::Pressed && !self.gui.wants_keyboard_input() {
+                                    self.handle_simulation_hotkey(*virtual_keycode, &event_loop_proxy);
+                                }
+                            }
                         },
                         _ => {}
                     }
@@ -291,15 +678,41 @@ impl Application {
                     self.camera.on_device_event(&event);
                 }
                 Event::MainEventsCleared => {
-                    self.window.request_redraw();
+                    if self.gpu_errors.take_device_lost() && !self.recreate_device() {
+                        *control_flow = ControlFlow::Exit;
+                        return;
+                    }
+                    self.process_application_events(event_loop_target);
+                    self.finish_scene_loading();
+                    self.update_suspended_state();
+                    if !self.suspended {
+                        self.frame_limiter.wait_for_next_frame(self.gui.frame_limit());
+                        self.window.request_redraw();
+                    }
                 }
                 Event::RedrawRequested(_) => {
-                    self.update();
-                    self.draw(&event_loop_proxy);
+                    if !self.suspended {
+                        self.update();
+                        self.draw(&event_loop_proxy);
+                        self.update_window_title();
+                        // Fast forward draws a frame in between chunks to show its progress and to give a chance to cancel.
+                        if let SimulationControllerStatus::FastForward(..) = self.simulation_controller.status() {
+                            self.simulation_thread.start(FastForwardJob::new(
+                                &mut self.simulation_controller,
+                                &mut self.scene,
+                                &mut self.pipeline_manager,
+                                self.device.clone(),
+                                self.command_queue.clone(),
+                                self.global_bindings.shared_bind_group(&self.global_ubo),
+                            ));
+                        }
+                    }
                 }
                 Event::LoopDestroyed => {
                     // workaround for errors on shutdown while recording screenshots
                     self.screen.wait_for_pending_screenshots(&self.device);
+                    self.hdr_backbuffer.wait_for_pending_screenshots(&self.device);
+                    self.screenshot_recorder.stop_recording(&self.device);
                 }
                 _ => (),
             }
@@ -308,17 +721,482 @@ impl Application {
         });
     }
 
-    fn window_resize(&mut self, size: winit::dpi::PhysicalSize<u32>) {
+    // While the simulation thread has the scene, simulation controller & pipelines, the window is only kept alive: no drawing and no input that would need any of them.
+    fn handle_event_during_simulation_job(&mut self, event: &Event<ApplicationEvent>, control_flow: &mut ControlFlow) {
+        match event {
+            Event::UserEvent(event) => self.queue_application_event(event.clone()),
+            Event::WindowEvent { event, window_id } if !self.gui.owns_window(*window_id) => match event {
+                WindowEvent::CloseRequested
+                | WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::Escape),
+                            ..
+                        },
+                    ..
+                } => *control_flow = ControlFlow::Exit,
+                WindowEvent::Focused(focused) => self.window_focused = *focused,
+                _ => {}
+            },
+            Event::MainEventsCleared => {
+                // Nothing is drawn in the meantime, so wait for the job instead of spinning the event loop.
+                if let Some(job) = self.simulation_thread.wait_for_finished_job(Duration::from_millis(16)) {
+                    job.give_back(&mut self.simulation_controller, &mut self.scene, &mut self.pipeline_manager);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Application events are collected over an event loop iteration and processed together before drawing.
+    // Redundant events (e.g. several scene loads in a row from spamming a button) are dropped, only the latest one is kept.
+    fn queue_application_event(&mut self, event: ApplicationEvent) {
+        self.pending_application_events.retain(|earlier| !event.supersedes(earlier));
+        self.pending_application_events.push(event);
+    }
+
+    fn process_application_events(&mut self, event_loop_target: &EventLoopWindowTarget<ApplicationEvent>) {
+        for event in std::mem::take(&mut self.pending_application_events) {
+            self.handle_application_event(event, event_loop_target);
+        }
+    }
+
+    fn handle_application_event(&mut self, event: ApplicationEvent, event_loop_target: &EventLoopWindowTarget<ApplicationEvent>) {
+        match event {
+            ApplicationEvent::LoadScene(scene_path) => {
+                self.scene_loader.start(&scene_path, &self.settings_overrides);
+            }
+            ApplicationEvent::ResetScene => {
+                self.scene.reset(
+                    &self.device,
+                    &self.command_queue,
+                    &self.shader_dir,
+                    &mut self.pipeline_manager,
+                    self.global_bindings.bind_group_layout(),
+                );
+                self.settings_overrides.apply(&mut self.scene);
+                self.simulation_controller.restart();
+            }
+            ApplicationEvent::ResampleGrid(factor) => {
+                self.scene.resample_grid(
+                    factor,
+                    &self.device,
+                    &self.command_queue,
+                    &self.shader_dir,
+                    &mut self.pipeline_manager,
+                    self.global_bindings.bind_group_layout(),
+                    self.global_bindings.bind_group(&self.global_ubo),
+                );
+                self.scene_renderer.on_new_scene(&self.device, &self.command_queue, &self.scene);
+            }
+            ApplicationEvent::FastForwardSimulation(simulation_jump_length) => {
+                self.simulation_controller.start_fast_forward(simulation_jump_length);
+            }
+            ApplicationEvent::ResetAndStartRecording { recording_fps, offscreen } => {
+                self.scene.reset(
+                    &self.device,
+                    &self.command_queue,
+                    &self.shader_dir,
+                    &mut self.pipeline_manager,
+                    self.global_bindings.bind_group_layout(),
+                );
+                self.settings_overrides.apply(&mut self.scene);
+                self.simulation_controller.restart();
+                let schedule = self.gui.recording_schedule_path().and_then(|path| {
+                    RecordingSchedule::from_file(&path)
+                        .map_err(|err| {
+                            error!("Failed to read recording schedule {:?}: {}", path, err);
+                            self.gui.show_hud_message("Invalid recording schedule, recording without".to_string());
+                        })
+                        .ok()
+                });
+                self.simulation_controller
+                    .start_recording_with_fixed_frame_length(recording_fps, schedule);
+                self.screenshot_recorder.stop_recording(&self.device);
+                let offscreen_target =
+                    offscreen.map(|desc| OffscreenTarget::new(&self.device, desc, &self.shader_dir, &mut self.pipeline_manager, &self.color_grading));
+                self.screenshot_recorder.start_next_recording(
+                    offscreen_target,
+                    self.gui.screenshot_format(),
+                    &self.gui.recording_output_dir(),
+                    &self.scene.path().file_stem().unwrap_or_default().to_string_lossy(),
+                );
+                self.write_recording_metadata(recording_fps);
+            }
+            ApplicationEvent::ClearShaderCache => {
+                self.shader_dir.clear_cache();
+                let timer = std::time::Instant::now();
+                self.pipeline_manager.reload_all(&self.device, &self.shader_dir);
+                info!("recompiling all pipelines took {:?}", std::time::Instant::now() - timer);
+                self.gui.set_shader_compile_errors(self.shader_dir.compile_errors());
+            }
+            ApplicationEvent::ChangePresentMode(present_mode) => {
+                if let Err(err) = self.screen.set_present_mode(&self.device, &self.window_surface, present_mode) {
+                    error!("Failed to switch to present mode {:?}: {}", present_mode, err);
+                    self.gui.show_hud_message(format!(
+                        "Present mode {:?} not supported, keeping {:?}",
+                        present_mode,
+                        self.screen.present_mode()
+                    ));
+                }
+                self.gui
+                    .set_present_modes(self.screen.supported_present_modes(), self.screen.present_mode());
+            }
+            ApplicationEvent::OpenProfilerWindow => {
+                self.gui.open_profiler_window(event_loop_target, &self.wgpu_instance, &self.device);
+            }
+            ApplicationEvent::CaptureSceneThumbnail => {
+                self.scene_thumbnail_capture_time = Some(Duration::from_secs(0));
+            }
+        }
+    }
+
+    // Throws away everything that lives on the gpu and starts over with a fresh device & the current scene.
+    // Gui state, camera and settings survive.
+    // Returns false if the scene couldn't be restored, in which case there is nothing left to continue with.
+    fn recreate_device(&mut self) -> bool {
+        error!("GPU device lost, recreating device and all gpu resources");
+
+        // Screenshots in flight are lost along with the old device.
+        if let SimulationControllerStatus::RecordingWithFixedFrameLength(..) = self.simulation_controller.status() {
+            self.simulation_controller.pause_or_resume();
+        }
+        self.screenshot_recorder = ScreenshotRecorder::new();
+        self.texture_pool = TexturePool::new();
+
+        let (wgpu_instance, window_surface, _, device, command_queue) = match futures::executor::block_on(Self::create_device(&self.window)) {
+            Some(device) => device,
+            None => {
+                error!("Failed to recreate the device, exiting");
+                return false;
+            }
+        };
+        self.gpu_errors.install_uncaptured_error_handler(&device);
+        self.gpu_errors.on_device_recreated();
+        self.wgpu_instance = wgpu_instance;
+        self.window_surface = window_surface;
+        self.device = device;
+        self.command_queue = command_queue;
+
+        self.pipeline_manager = Lendable::new(pipelines::PipelineManager::new(&self.device));
         self.screen = Screen::new(
             &self.device,
             &self.window_surface,
             self.screen.present_mode(),
-            size,
+            self.window.inner_size(),
+            &self.shader_dir,
+            &mut self.pipeline_manager,
+        );
+        self.gui
+            .set_present_modes(self.screen.supported_present_modes(), self.screen.present_mode());
+        self.color_grading = ColorGrading::new(&self.device, &self.command_queue);
+        self.recording_overlay = RecordingOverlay::new(&self.device);
+        self.hdr_backbuffer = HdrBackbuffer::new(
+            &self.device,
+            self.screen.resolution(),
+            &self.shader_dir,
+            &mut self.pipeline_manager,
+            &self.color_grading,
+        );
+        self.global_ubo = GlobalUBO::new(&self.device);
+        self.global_bindings = GlobalBindings::new(&self.device);
+        self.scene_renderer = SceneRenderer::new(
+            &self.device,
+            &self.command_queue,
             &self.shader_dir,
             &mut self.pipeline_manager,
+            self.global_bindings.bind_group_layout(),
         );
-        self.hdr_backbuffer = HdrBackbuffer::new(&self.device, self.screen.resolution(), &self.shader_dir, &mut self.pipeline_manager);
-        self.scene_renderer.on_window_resize(&self.device, &self.hdr_backbuffer);
+        self.profiler_rendering = GpuProfiler::new(4, self.command_queue.get_timestamp_period());
+        self.profiler_simulation = GpuProfiler::new(16, self.command_queue.get_timestamp_period());
+        self.gui.on_device_recreated(&self.device);
+
+        let scene_path = self.scene.path().to_path_buf();
+        if !self.load_scene(&scene_path) {
+            // The old scene's gpu resources belong to the lost device.
+            error!("Failed to reload scene {:?} on the new device, exiting", scene_path);
+            return false;
+        }
+        self.simulation_controller.restart();
+        true
+    }
+
+    // Suspend rendering & simulation while in background if the user asked for it.
+    // Recordings and fast forward are never interrupted since they don't depend on the window.
+    fn update_suspended_state(&mut self) {
+        let window_size = self.window.inner_size();
+        let in_background = !self.window_focused || window_size.width == 0 || window_size.height == 0;
+        let suspend = self.gui.pause_in_background()
+            && in_background
+            && match self.simulation_controller.status() {
+                SimulationControllerStatus::Realtime | SimulationControllerStatus::Paused => true,
+                SimulationControllerStatus::RecordingWithFixedFrameLength(..) | SimulationControllerStatus::FastForward(..) => false,
+            };
+
+        if suspend != self.suspended {
+            self.suspended = suspend;
+            if suspend {
+                info!("Window in background, suspending simulation & rendering");
+            } else {
+                info!("Resuming simulation & rendering");
+                self.simulation_controller.on_resume_from_suspend();
+            }
+        }
+    }
+
+    fn write_recording_metadata(&mut self, recording_fps: f64) {
+        // Snapshot of the scene file as is, the config struct only knows how to read.
+        let scene_config = std::fs::File::open(self.scene.path())
+            .map_err(serde_json::Error::io)
+            .and_then(|file| serde_json::from_reader(std::io::BufReader::new(file)))
+            .unwrap_or_else(|err| {
+                warn!("Failed to read scene config {:?} for recording metadata: {}", self.scene.path(), err);
+                serde_json::Value::Null
+            });
+        let (resolution, supersampling) = match self.screenshot_recorder.offscreen_target() {
+            Some(offscreen_target) => (offscreen_target.desc().resolution, offscreen_target.desc().supersampling),
+            None => (self.screen.resolution(), 1),
+        };
+        let scene_path = self.scene.path().to_path_buf();
+        let fluid = self.scene.fluid_mut();
+        let metadata = RecordingMetadata {
+            start_time: chrono::Local::now().to_rfc3339(),
+            scene_path,
+            scene_config,
+            recording_fps,
+            simulation_steps_per_second: self.simulation_controller.simulation_steps_per_second(),
+            resolution: [resolution.width, resolution.height],
+            supersampling,
+            format: format!("{:?}", self.gui.screenshot_format()),
+            solver_velocity: *fluid.pressure_solver_config_velocity(),
+            solver_density: *fluid.pressure_solver_config_density(),
+            particle_rebinning_step_frequency: fluid.dynamic_settings().particle_rebinning_step_frequency,
+            vorticity_confinement: fluid.dynamic_settings().vorticity_confinement,
+            schedule: self.simulation_controller.recording_schedule().cloned(),
+        };
+        self.screenshot_recorder.write_recording_metadata(&metadata);
+    }
+
+    // Text burned into the current recording frame, empty if there is nothing to show.
+    fn recording_overlay_lines(&mut self) -> Vec<String> {
+        let settings = self.gui.recording_overlay_settings();
+        if !settings.enabled || !self.screenshot_recorder.is_recording() {
+            return Vec::new();
+        }
+
+        let mut lines = Vec::new();
+        if settings.scene_name {
+            if let Some(scene_name) = self.scene.path().file_stem() {
+                lines.push(scene_name.to_string_lossy().into_owned());
+            }
+        }
+        if settings.simulation_time {
+            lines.push(format!(
+                "t = {}",
+                gui::formatting::duration(self.simulation_controller.timer().total_simulated_time())
+            ));
+        }
+        if settings.solver_settings {
+            let solver_config = match self.scene.smoke_mut() {
+                Some(smoke) => *smoke.pressure_solver_config(),
+                None => *self.scene.fluid_mut().pressure_solver_config_velocity(),
+            };
+            lines.push(format!(
+                "solver: tolerance {}, max {} iterations",
+                gui::formatting::decimal(solver_config.error_tolerance as f64, 4),
+                solver_config.max_num_iterations
+            ));
+        }
+        if settings.frame_number {
+            lines.push(format!("frame {}", self.screenshot_recorder.recording_frame_index()));
+        }
+        lines
+    }
+
+    // Borderless fullscreen on the monitor the window is currently on.
+    fn toggle_fullscreen(&mut self) {
+        if self.window.fullscreen().is_some() {
+            self.window.set_fullscreen(None);
+        } else {
+            self.window
+                .set_fullscreen(Some(winit::window::Fullscreen::Borderless(self.window.current_monitor())));
+        }
+    }
+
+    fn update_window_title(&mut self) {
+        const UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+
+        self.frames_since_window_title_update += 1;
+        let elapsed = self.window_title_update.elapsed();
+        if elapsed < UPDATE_INTERVAL {
+            return;
+        }
+        let fps = self.frames_since_window_title_update as f64 / elapsed.as_secs_f64();
+        self.window_title_update = Instant::now();
+        self.frames_since_window_title_update = 0;
+
+        let title = if self.gui.window_title_stats() {
+            format!(
+                "Blub - {} - {} fps - t = {}",
+                self.scene.path().file_stem().unwrap_or_default().to_string_lossy(),
+                gui::formatting::decimal(fps, 1),
+                gui::formatting::duration(self.simulation_controller.timer().total_simulated_time()),
+            )
+        } else {
+            "Blub".to_string()
+        };
+        if title != self.window_title {
+            self.window.set_title(&title);
+            self.window_title = title;
+        }
+    }
+
+    // Simulation control shortcuts (supplementing Space for pause), confirmed by a short hud message.
+    fn handle_simulation_hotkey(&mut self, key: VirtualKeyCode, event_loop_proxy: &EventLoopProxy<ApplicationEvent>) {
+        match key {
+            VirtualKeyCode::F9 | VirtualKeyCode::F10 => {
+                if !self.renderdoc.is_available() {
+                    self.gui.show_hud_message("RenderDoc not available".to_string());
+                } else if key == VirtualKeyCode::F9 {
+                    self.renderdoc.capture_next_frame();
+                    self.gui.show_hud_message("RenderDoc: capturing frame".to_string());
+                } else {
+                    self.renderdoc.capture_next_simulation_step();
+                    self.gui.show_hud_message("RenderDoc: capturing next simulation step".to_string());
+                }
+            }
+            VirtualKeyCode::F => {
+                let length = self.gui.fast_forward_length();
+                event_loop_proxy.send_event(ApplicationEvent::FastForwardSimulation(length)).unwrap();
+                self.gui.show_hud_message(format!("Fast forward {}", gui::formatting::duration(length)));
+            }
+            VirtualKeyCode::N => {
+                if self.simulation_controller.status() == SimulationControllerStatus::Paused {
+                    self.simulation_controller.step_once();
+                    if self.simulation_controller.sub_step_inspection {
+                        let stage = self
+                            .simulation_controller
+                            .next_sub_step_stage()
+                            .unwrap_or(simulation::SimulationStage::TransferToGrid);
+                        self.gui.show_hud_message(format!("Sub step: {:?}", stage));
+                    } else {
+                        self.gui.show_hud_message("Single step".to_string());
+                    }
+                } else {
+                    self.gui.show_hud_message("Single step only possible while paused".to_string());
+                }
+            }
+            VirtualKeyCode::R => {
+                if let SimulationControllerStatus::RecordingWithFixedFrameLength(..) = self.simulation_controller.status() {
+                    self.simulation_controller.pause_or_resume();
+                    self.gui.show_hud_message("Recording stopped".to_string());
+                } else {
+                    let recording_fps = self.gui.video_fps();
+                    let offscreen = self.gui.recording_offscreen_target_desc();
+                    event_loop_proxy
+                        .send_event(ApplicationEvent::ResetAndStartRecording { recording_fps, offscreen })
+                        .unwrap();
+                    self.gui.show_hud_message(format!("Reset & recording at {}fps", recording_fps));
+                }
+            }
+            VirtualKeyCode::I | VirtualKeyCode::K | VirtualKeyCode::J | VirtualKeyCode::L => {
+                use cgmath::{InnerSpace, Rotation, Rotation3};
+                const TILT_STEP: cgmath::Deg<f32> = cgmath::Deg(5.0);
+                let rotation = match key {
+                    VirtualKeyCode::I => cgmath::Quaternion::from_angle_x(-TILT_STEP),
+                    VirtualKeyCode::K => cgmath::Quaternion::from_angle_x(TILT_STEP),
+                    VirtualKeyCode::J => cgmath::Quaternion::from_angle_z(-TILT_STEP),
+                    _ => cgmath::Quaternion::from_angle_z(TILT_STEP),
+                };
+                let gravity = rotation.rotate_vector(self.scene.gravity());
+                self.scene.set_gravity(gravity);
+                let tilt = cgmath::Deg::from(gravity.angle(-cgmath::Vector3::unit_y()));
+                self.gui
+                    .show_hud_message(format!("Gravity tilted by {}°", gui::formatting::decimal(tilt.0 as f64, 0)));
+            }
+            VirtualKeyCode::G => {
+                let gravity = self.scene.config().gravity;
+                self.scene.set_gravity(gravity);
+                self.gui.show_hud_message("Gravity reset".to_string());
+            }
+            VirtualKeyCode::Plus | VirtualKeyCode::Equals | VirtualKeyCode::NumpadAdd => {
+                self.simulation_controller.time_scale = (self.simulation_controller.time_scale * 2.0).min(100.0);
+                self.gui.show_hud_message(format!(
+                    "Time scale {}",
+                    gui::formatting::decimal(self.simulation_controller.time_scale as f64, 2)
+                ));
+            }
+            VirtualKeyCode::Minus | VirtualKeyCode::NumpadSubtract => {
+                self.simulation_controller.time_scale = (self.simulation_controller.time_scale * 0.5).max(0.01);
+                self.gui.show_hud_message(format!(
+                    "Time scale {}",
+                    gui::formatting::decimal(self.simulation_controller.time_scale as f64, 2)
+                ));
+            }
+            _ => {}
+        }
+    }
+
+    // Resizing all render targets (and everything that depends on them) is expensive, so while the window size keeps changing
+    // we only resize the swap chain and wait until the size settled before resizing everything else.
+    fn handle_window_resize(&mut self, window_size: winit::dpi::PhysicalSize<u32>) {
+        const RENDER_TARGET_RESIZE_DELAY: Duration = Duration::from_millis(100);
+
+        if window_size != self.screen.swap_chain_resolution() {
+            self.screen.resize_swap_chain(&self.device, &self.window_surface, window_size);
+            self.last_window_resize = Instant::now();
+        }
+        // Render scale changes are debounced like window resizes, so dragging the slider doesn't allocate new targets every frame.
+        let render_resolution = self.scaled_render_resolution(window_size);
+        if render_resolution != self.requested_render_resolution {
+            self.requested_render_resolution = render_resolution;
+            self.last_window_resize = Instant::now();
+        }
+        if (window_size != self.screen.resolution() || render_resolution != self.screen.render_resolution())
+            && self.last_window_resize.elapsed() > RENDER_TARGET_RESIZE_DELAY
+        {
+            self.resize_render_targets(window_size, render_resolution);
+        }
+    }
+
+    // Resolution of the hdr backbuffer for a given window size, see SceneRenderer::render_scale.
+    fn scaled_render_resolution(&self, window_size: winit::dpi::PhysicalSize<u32>) -> winit::dpi::PhysicalSize<u32> {
+        let render_scale = self
+            .scene_renderer
+            .render_scale
+            .max(SceneRenderer::MIN_RENDER_SCALE)
+            .min(SceneRenderer::MAX_RENDER_SCALE);
+        let max_texture_dimension = self.device.limits().max_texture_dimension_2d;
+        let scale = |size: u32| ((size as f32 * render_scale).round() as u32).max(1).min(max_texture_dimension);
+        winit::dpi::PhysicalSize::new(scale(window_size.width), scale(window_size.height))
+    }
+
+    fn resize_render_targets(&mut self, size: winit::dpi::PhysicalSize<u32>, render_resolution: winit::dpi::PhysicalSize<u32>) {
+        self.screen
+            .resize_render_targets(&self.device, &mut self.texture_pool, size, render_resolution);
+        self.hdr_backbuffer.resize(&self.device, &mut self.texture_pool, render_resolution);
+    }
+
+    fn render_target_aspect_ratio(&self) -> f32 {
+        match self.screenshot_recorder.offscreen_target() {
+            Some(offscreen_target) => offscreen_target.aspect_ratio(),
+            None => self.screen.aspect_ratio(),
+        }
+    }
+
+    // Sets all global UBO sections for the current render target. Only sections that changed are uploaded.
+    fn update_global_ubo(&mut self, time: timer::FrameTimeUniformBufferContent) {
+        let screen_uniform_buffer_content = match self.screenshot_recorder.offscreen_target() {
+            Some(offscreen_target) => ScreenUniformBufferContent::new(offscreen_target.hdr_backbuffer().resolution()),
+            None => self.screen.fill_global_uniform_buffer(),
+        };
+        let camera_uniform_buffer_content = self.camera.fill_global_uniform_buffer(self.render_target_aspect_ratio());
+        let rendering_uniform_buffer_content = self.scene_renderer.fill_global_uniform_buffer(&self.scene);
+        self.global_ubo.set_camera(camera_uniform_buffer_content);
+        self.global_ubo.set_time(time);
+        self.global_ubo.set_rendering(rendering_uniform_buffer_content);
+        self.global_ubo.set_screen(screen_uniform_buffer_content);
+        self.global_ubo.write_changed_sections(&self.command_queue);
     }
 
     fn update(&mut self) {
@@ -330,37 +1208,81 @@ impl Application {
                 let timer = std::time::Instant::now();
                 self.pipeline_manager.reload_changed(&self.device, &self.shader_dir, &changed_files);
                 info!("shader reload took {:?}", std::time::Instant::now() - timer);
+                self.gui.set_shader_compile_errors(self.shader_dir.compile_errors());
             }
         }
 
         self.camera.update(self.simulation_controller.timer());
 
-        update_global_ubo(
-            &mut self.global_ubo,
-            &self.command_queue,
-            self.camera.fill_global_uniform_buffer(self.screen.aspect_ratio()),
-            self.simulation_controller.timer().fill_global_uniform_buffer(),
-            self.scene_renderer.fill_global_uniform_buffer(&self.scene),
-            self.screen.fill_global_uniform_buffer(),
+        self.update_global_ubo(self.simulation_controller.timer().fill_global_uniform_buffer());
+        let interaction_force = self.fluid_interaction.update(
+            &self.camera,
+            self.window.inner_size(),
+            self.simulation_controller.timer(),
+            &self.scene.config().fluid,
         );
+        self.scene.fluid_mut().set_interaction_force(interaction_force);
+        self.scene_renderer
+            .update_gizmos(&self.camera, self.window.inner_size(), &mut self.scene, &self.command_queue);
+
+        self.gpu_errors.push_scope(&self.device);
         self.simulation_controller.frame_steps(
             &mut self.scene,
             &self.device,
             &self.command_queue,
             &self.pipeline_manager,
             &mut self.profiler_simulation,
-            self.global_bindings.bind_group(),
+            &mut self.fence_profiler_simulation,
+            self.global_bindings.bind_group(&self.global_ubo),
+            &mut self.renderdoc,
         );
+        self.scene
+            .update_playback_cache(&self.device, &self.command_queue, self.simulation_controller.timer());
+        self.gpu_errors.pop_scope(&self.device, "simulation");
+
+        match self.gui.grid_export_interval() {
+            Some(interval) => {
+                if !self.grid_exporter.is_exporting() {
+                    self.grid_exporter.start(
+                        &self.gui.recording_output_dir(),
+                        &self.scene.path().file_stem().unwrap_or_default().to_string_lossy(),
+                    );
+                }
+                self.grid_exporter.update(
+                    &self.scene,
+                    &self.device,
+                    &self.command_queue,
+                    self.simulation_controller.timer().total_simulated_time(),
+                    interval,
+                );
+                // Don't retry every frame if the output directory isn't writable.
+                if !self.grid_exporter.is_exporting() {
+                    self.gui.disable_grid_export();
+                    self.gui.show_hud_message("Grid export failed, see log".to_string());
+                }
+            }
+            None => self.grid_exporter.stop(),
+        }
 
         if self.simulation_controller.status() == SimulationControllerStatus::Paused {
-            self.screenshot_recorder.stop_recording();
+            self.screenshot_recorder.stop_recording(&self.device);
         }
 
-        self.profiler_simulation.enable_timer = self.gui.show_profiling_data_simulation();
-        self.profiler_rendering.enable_timer = self.gui.show_profiling_data_rendering();
+        let timestamps_supported = self.device.features().contains(wgpu::Features::TIMESTAMP_QUERY);
+        self.profiler_simulation.enable_timer = timestamps_supported && self.gui.show_profiling_data_simulation();
+        self.profiler_rendering.enable_timer = timestamps_supported && self.gui.show_profiling_data_rendering();
+        self.fence_profiler_simulation.enabled = !timestamps_supported && self.gui.show_profiling_data_simulation();
+        self.fence_profiler_rendering.enabled = !timestamps_supported && self.gui.show_profiling_data_rendering();
+        // Fence profiling splits up submissions, which doesn't work with debug groups spanning several of them.
+        self.profiler_simulation.enable_debug_marker = !self.fence_profiler_simulation.enabled;
+        self.profiler_rendering.enable_debug_marker = !self.fence_profiler_rendering.enabled;
+
         if let Some(profiling_data_rendering) = self.profiler_rendering.process_finished_frame() {
             self.gui.report_profiling_data_rendering(profiling_data_rendering);
         }
+        if let Some(profiling_data_rendering) = self.fence_profiler_rendering.process_finished_frame() {
+            self.gui.report_profiling_data_rendering(profiling_data_rendering);
+        }
         loop {
             if let Some(simulation_profiling_data) = self.profiler_simulation.process_finished_frame() {
                 self.gui.report_profiling_data_simulation(simulation_profiling_data);
@@ -368,80 +1290,182 @@ impl Application {
                 break;
             }
         }
+        while let Some(simulation_profiling_data) = self.fence_profiler_simulation.process_finished_frame() {
+            self.gui.report_profiling_data_simulation(simulation_profiling_data);
+        }
     }
 
     fn draw(&mut self, event_loop_proxy: &EventLoopProxy<ApplicationEvent>) {
         let window_size = self.window.inner_size();
         if window_size.width == 0 || window_size.height == 0 {
             return;
-        } else if window_size != self.screen.resolution() {
-            self.window_resize(window_size);
         }
+        self.handle_window_resize(window_size);
 
-        let frame = self.screen.start_frame(&self.device, &self.window_surface);
+        let frame = match self.screen.start_frame(&self.device, &self.window_surface) {
+            Ok(frame) => frame,
+            Err(wgpu::SwapChainError::Lost) => {
+                error!("Swap chain lost even after recreating it, assuming the device is lost");
+                self.gpu_errors.report_device_lost();
+                return;
+            }
+            Err(err) => {
+                warn!("Skipping frame, failed to acquire swap chain frame: {}", err);
+                return;
+            }
+        };
+        self.gpu_errors.push_scope(&self.device);
 
         let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
             label: Some("Encoder: Frame Main"),
         });
 
-        update_global_ubo(
-            &mut self.global_ubo,
-            &self.command_queue,
-            self.camera.fill_global_uniform_buffer(self.screen.aspect_ratio()),
-            self.simulation_controller.timer().fill_global_uniform_buffer(),
-            self.scene_renderer.fill_global_uniform_buffer(&self.scene),
-            self.screen.fill_global_uniform_buffer(),
-        );
+        // Usually a no-op, unless the simulation advanced or an offscreen recording started/stopped since update.
+        self.update_global_ubo(self.simulation_controller.timer().fill_global_uniform_buffer());
 
+        let camera_frustum = self.camera.frustum(self.render_target_aspect_ratio());
+        let (hdr_backbuffer, depthbuffer) = match self.screenshot_recorder.offscreen_target() {
+            Some(offscreen_target) => (offscreen_target.hdr_backbuffer(), offscreen_target.depthbuffer()),
+            None => (&self.hdr_backbuffer, self.screen.depthbuffer()),
+        };
+        wgpu_profiler!("object picking", self.profiler_rendering, &mut encoder, &self.device, {
+            self.scene_renderer.object_picking.draw(
+                &self.device,
+                &mut encoder,
+                &self.pipeline_manager,
+                self.global_bindings.bind_group(&self.global_ubo),
+                &self.scene.models,
+                hdr_backbuffer.resolution(),
+            );
+        });
+        self.fence_profiler_rendering
+            .submit_scope("object picking", &mut encoder, &self.device, &self.command_queue, &self.pipeline_manager);
         wgpu_profiler!("scene", self.profiler_rendering, &mut encoder, &self.device, {
             self.scene_renderer.draw(
                 &self.scene,
                 &mut self.profiler_rendering,
                 &self.device,
+                &self.command_queue,
                 &mut encoder,
                 &self.pipeline_manager,
-                &self.hdr_backbuffer,
-                self.screen.depthbuffer(),
-                self.global_bindings.bind_group(),
+                hdr_backbuffer,
+                depthbuffer,
+                self.global_bindings.bind_group(&self.global_ubo),
+                &camera_frustum,
             );
         });
+        self.fence_profiler_rendering
+            .submit_scope("scene", &mut encoder, &self.device, &self.command_queue, &self.pipeline_manager);
 
+        let recording_overlay_lines = self.recording_overlay_lines();
         wgpu_profiler!("tonemap", self.profiler_rendering, &mut encoder, &self.device, {
-            self.hdr_backbuffer
-                .tonemap(&self.screen.backbuffer(), &mut encoder, &self.pipeline_manager);
+            match self.screenshot_recorder.offscreen_target() {
+                Some(offscreen_target) => {
+                    offscreen_target.resolve(&mut encoder, &self.pipeline_manager, &self.color_grading);
+                    self.recording_overlay.draw(
+                        &self.device,
+                        &self.command_queue,
+                        &mut encoder,
+                        offscreen_target.output(),
+                        offscreen_target.desc().resolution,
+                        &recording_overlay_lines,
+                    );
+                    offscreen_target.draw_preview(&mut encoder, &self.pipeline_manager, self.screen.backbuffer(), self.screen.resolution());
+                }
+                None => {
+                    self.hdr_backbuffer.tonemap(
+                        &self.screen.backbuffer(),
+                        self.screen.resolution(),
+                        &mut encoder,
+                        &self.pipeline_manager,
+                        &self.color_grading,
+                    );
+                    self.recording_overlay.draw(
+                        &self.device,
+                        &self.command_queue,
+                        &mut encoder,
+                        self.screen.backbuffer(),
+                        self.screen.resolution(),
+                        &recording_overlay_lines,
+                    );
+                }
+            }
         });
+        self.fence_profiler_rendering
+            .submit_scope("tonemap", &mut encoder, &self.device, &self.command_queue, &self.pipeline_manager);
 
-        self.screenshot_recorder.capture_screenshot(&mut self.screen, &self.device, &mut encoder);
+        self.capture_scene_thumbnail(&mut encoder);
+        self.screenshot_recorder.capture_screenshot(
+            &mut self.screen,
+            &mut self.hdr_backbuffer,
+            &self.device,
+            &mut encoder,
+            &self.pipeline_manager,
+        );
 
+        self.gui.set_scene_loading(self.scene_loader.loading_scene());
         wgpu_profiler!("gui", self.profiler_rendering, &mut encoder, &self.device, {
             self.gui.draw(
-                &mut self.device,
+                &self.device,
                 &self.window,
                 &mut encoder,
-                &mut self.command_queue,
+                &self.command_queue,
                 &self.screen.backbuffer(),
+                self.screen.resolution(),
                 &mut self.simulation_controller,
                 &mut self.scene_renderer,
                 &mut self.scene,
+                &mut self.fluid_interaction,
                 event_loop_proxy,
             );
         });
+        self.fence_profiler_rendering
+            .submit_scope("gui", &mut encoder, &self.device, &self.command_queue, &self.pipeline_manager);
 
         wgpu_profiler!("copy to swapchain", self.profiler_rendering, &mut encoder, &self.device, {
-            self.screen.copy_to_swapchain(&frame, &mut encoder, &self.pipeline_manager);
+            self.screen
+                .copy_to_swapchain(&frame, &mut encoder, &self.pipeline_manager, self.gui.color_space_debug_mode());
         });
+        self.fence_profiler_rendering.submit_scope(
+            "copy to swapchain",
+            &mut encoder,
+            &self.device,
+            &self.command_queue,
+            &self.pipeline_manager,
+        );
         self.profiler_rendering.resolve_queries(&mut encoder);
+        self.pipeline_manager.flush_push_constants(&self.device, &self.command_queue);
         self.command_queue.submit(Some(encoder.finish()));
+        self.gpu_errors.pop_scope(&self.device, "frame");
         self.screen.end_frame(frame);
+        self.hdr_backbuffer.end_frame();
+        self.screenshot_recorder.end_frame();
         self.simulation_controller.on_frame_submitted();
+        self.scene_renderer.update_readback(&self.device, &self.scene);
 
         self.profiler_rendering.end_frame().unwrap();
+        self.fence_profiler_rendering.end_frame();
     }
 }
 
 fn main() {
-    env_logger::init_from_env(env_logger::Env::default().filter_or(env_logger::DEFAULT_FILTER_ENV, "warn,blub=info"));
+    log_buffer::init(env_logger::Env::default().filter_or(env_logger::DEFAULT_FILTER_ENV, "warn,blub=info"));
+    let args = command_line::CommandLineArgs::from_env();
     let event_loop = EventLoop::<ApplicationEvent>::with_user_event();
-    let application = futures::executor::block_on(Application::new(&event_loop));
-    application.run(event_loop);
+    let mut application = futures::executor::block_on(Application::new(
+        &event_loop,
+        args.scene,
+        args.settings_overrides,
+        args.autotune_workgroups,
+    ));
+    if let Some(profiling_detail) = args.profiling_detail {
+        application.simulation_controller.profiling_detail = profiling_detail;
+    }
+    if let Some(benchmark_config) = args.benchmark {
+        application.run_benchmark(benchmark_config);
+    } else if let Some(sweep_config) = args.sweep {
+        application.run_sweep(sweep_config);
+    } else {
+        application.run(event_loop);
+    }
 }
@@ -0,0 +1,257 @@
+// Python bindings for setting up & running simulations without the application, e.g. from notebooks.
+//
+// Paths to models & shaders are relative, so the python process needs to run with the blub directory as working directory.
+//
+// Usage:
+//   import blub
+//   sim = blub.Simulation("scenes/dam_halfhalf.json", scene_overrides='{"gravity": {"x": 0, "y": -3.7, "z": 0}}')
+//   sim.set_solver("velocity", error_tolerance=0.01)
+//   sim.step(120)
+//   positions = sim.particle_positions()
+
+use crate::{
+    camera::Camera,
+    global_bindings::GlobalBindings,
+    global_ubo::GlobalUBO,
+    render_output::screen::ScreenUniformBufferContent,
+    renderer::GlobalRenderSettingsUniformBufferContent,
+    scene::{load_scene_json, Scene, SceneConfig},
+    simulation::{self, SimulationStage},
+    timer::Timer,
+    utils::merge_json,
+    wgpu_utils::{
+        fence_profiler::FenceProfiler,
+        pipelines::{self, PipelineManager},
+        profiling::ProfilingDetail,
+        shader::ShaderDirectory,
+    },
+};
+use pyo3::{exceptions::PyRuntimeError, exceptions::PyValueError, prelude::*};
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use wgpu_profiler::GpuProfiler;
+
+fn to_py_err(err: impl std::fmt::Display) -> PyErr {
+    PyRuntimeError::new_err(err.to_string())
+}
+
+async fn create_headless_device() -> Option<(wgpu::Device, wgpu::Queue)> {
+    let wgpu_instance = wgpu::Instance::new(wgpu::BackendBit::VULKAN);
+    let adapter = wgpu_instance
+        .request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            compatible_surface: None,
+        })
+        .await?;
+    let (push_constant_features, max_push_constant_size) = pipelines::push_constant_requirements(&adapter);
+    if adapter.limits().max_storage_buffers_per_shader_stage < simulation::MAX_STORAGE_BUFFERS_PER_SHADER_STAGE {
+        error!(
+            "Adapter supports only {} storage buffers per shader stage, the simulation needs {}",
+            adapter.limits().max_storage_buffers_per_shader_stage,
+            simulation::MAX_STORAGE_BUFFERS_PER_SHADER_STAGE
+        );
+        return None;
+    }
+    // Same as the application, minus the debug rendering features.
+    adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                label: Some("python device"),
+                features: (adapter.features() & wgpu::Features::TIMESTAMP_QUERY)
+                    | push_constant_features
+                    | wgpu::Features::SAMPLED_TEXTURE_BINDING_ARRAY
+                    | wgpu::Features::SAMPLED_TEXTURE_ARRAY_NON_UNIFORM_INDEXING
+                    | wgpu::Features::SAMPLED_TEXTURE_ARRAY_DYNAMIC_INDEXING
+                    | wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
+                    | wgpu::Features::CONSERVATIVE_RASTERIZATION
+                    | wgpu::Features::CLEAR_COMMANDS,
+                limits: wgpu::Limits {
+                    max_push_constant_size,
+                    max_storage_buffers_per_shader_stage: adapter.limits().max_storage_buffers_per_shader_stage,
+                    ..Default::default()
+                },
+            },
+            None,
+        )
+        .await
+        .ok()
+}
+
+// A single scene with its simulation, owning its own (headless) device.
+#[pyclass(unsendable)]
+pub struct Simulation {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    shader_dir: ShaderDirectory,
+    pipeline_manager: PipelineManager,
+    global_ubo: GlobalUBO,
+    global_bindings: GlobalBindings,
+    camera: Camera,
+    profiler: GpuProfiler,
+    timer: Timer,
+    scene: Scene,
+}
+
+#[pymethods]
+impl Simulation {
+    // scene_overrides is a json string that is merged into the scene file (json merge patch).
+    #[new]
+    #[args(scene_overrides = "None", simulation_steps_per_second = "120")]
+    fn new(scene: PathBuf, scene_overrides: Option<&str>, simulation_steps_per_second: u64) -> PyResult<Self> {
+        if simulation_steps_per_second == 0 {
+            return Err(PyValueError::new_err("simulation_steps_per_second needs to be positive"));
+        }
+
+        let mut scene_json = load_scene_json(&scene).map_err(to_py_err)?;
+        if let Some(overrides) = scene_overrides {
+            let overrides: serde_json::Value = serde_json::from_str(overrides).map_err(|err| PyValueError::new_err(err.to_string()))?;
+            merge_json(&mut scene_json, &overrides);
+        }
+        let config: SceneConfig = serde_json::from_value(scene_json).map_err(|err| PyValueError::new_err(err.to_string()))?;
+
+        let (device, queue) = futures::executor::block_on(create_headless_device()).ok_or_else(|| to_py_err("No suitable gpu found"))?;
+        let shader_dir = ShaderDirectory::new(Path::new("shader"), Path::new(".shadercache"));
+        let mut pipeline_manager = PipelineManager::new(&device);
+        let global_ubo = GlobalUBO::new(&device);
+        let mut global_bindings = GlobalBindings::new(&device);
+
+        let scene = Scene::from_config(
+            &scene,
+            config,
+            &device,
+            &queue,
+            &shader_dir,
+            &mut pipeline_manager,
+            global_bindings.bind_group_layout(),
+        )
+        .map_err(to_py_err)?;
+        global_bindings.create_bind_group(&device, &global_ubo, &scene.models);
+
+        let mut profiler = GpuProfiler::new(4, queue.get_timestamp_period());
+        profiler.enable_timer = device.features().contains(wgpu::Features::TIMESTAMP_QUERY);
+        Ok(Simulation {
+            device,
+            queue,
+            shader_dir,
+            pipeline_manager,
+            global_ubo,
+            global_bindings,
+            camera: Camera::new(),
+            profiler,
+            timer: Timer::new(Duration::from_nanos(1000 * 1000 * 1000 / simulation_steps_per_second)),
+            scene,
+        })
+    }
+
+    // Performs the given number of simulation steps. Blocks until the gpu is done.
+    #[args(num_steps = "1")]
+    fn step(&mut self, num_steps: u32) {
+        for _ in 0..num_steps {
+            self.timer.force_simulation_step();
+            self.global_ubo.set_camera(self.camera.fill_global_uniform_buffer(1.0));
+            self.global_ubo.set_time(self.timer.fill_global_uniform_buffer());
+            self.global_ubo
+                .set_rendering(GlobalRenderSettingsUniformBufferContent::new(&self.scene.config().fluid, 1.0, 1.0, 1.0));
+            self.global_ubo
+                .set_screen(ScreenUniformBufferContent::new(winit::dpi::PhysicalSize::new(1, 1)));
+            self.global_ubo.write_changed_sections(&self.queue);
+            self.scene.step(
+                &self.timer,
+                &SimulationStage::ALL,
+                &self.device,
+                &mut self.profiler,
+                &mut FenceProfiler::new(1),
+                ProfilingDetail::Off,
+                &self.pipeline_manager,
+                &self.queue,
+                self.global_bindings.bind_group(&self.global_ubo),
+            );
+            self.device.poll(wgpu::Maintain::Wait);
+            while self.profiler.process_finished_frame().is_some() {}
+        }
+    }
+
+    // Restarts the simulation from the initial scene state.
+    fn reset(&mut self) {
+        self.scene.reset(
+            &self.device,
+            &self.queue,
+            &self.shader_dir,
+            &mut self.pipeline_manager,
+            self.global_bindings.bind_group_layout(),
+        );
+        self.timer = Timer::new(self.timer.simulation_delta());
+    }
+
+    // solver is either "velocity" or "density", unset parameters are left untouched.
+    #[args(error_tolerance = "None", max_num_iterations = "None", error_check_frequency = "None")]
+    fn set_solver(
+        &mut self,
+        solver: &str,
+        error_tolerance: Option<f32>,
+        max_num_iterations: Option<i32>,
+        error_check_frequency: Option<i32>,
+    ) -> PyResult<()> {
+        let config = match solver {
+            "velocity" => self.scene.fluid_mut().pressure_solver_config_velocity(),
+            "density" => self.scene.fluid_mut().pressure_solver_config_density(),
+            _ => {
+                return Err(PyValueError::new_err(format!(
+                    "Unknown solver {:?}, expected \"velocity\" or \"density\"",
+                    solver
+                )))
+            }
+        };
+        if let Some(error_tolerance) = error_tolerance {
+            config.error_tolerance = error_tolerance;
+        }
+        if let Some(max_num_iterations) = max_num_iterations {
+            config.max_num_iterations = max_num_iterations;
+        }
+        if let Some(error_check_frequency) = error_check_frequency {
+            config.error_check_frequency = error_check_frequency;
+        }
+        Ok(())
+    }
+
+    // Gravity in world space. Reset to the scene's gravity on reset.
+    fn set_gravity(&mut self, x: f32, y: f32, z: f32) {
+        self.scene.set_gravity(cgmath::vec3(x, y, z));
+    }
+
+    #[getter]
+    fn num_particles(&self) -> u32 {
+        self.scene.fluid().num_particles()
+    }
+
+    // Total simulated time in seconds.
+    #[getter]
+    fn simulated_time(&self) -> f64 {
+        self.timer.total_simulated_time().as_secs_f64()
+    }
+
+    // World space positions of all particles as list of (x, y, z) tuples.
+    fn particle_positions(&self) -> Vec<(f32, f32, f32)> {
+        let fluid_config = &self.scene.config().fluid;
+        self.scene
+            .fluid()
+            .read_particle_positions(&self.device, &self.queue)
+            .iter()
+            .map(|p| {
+                (
+                    fluid_config.world_position.x + p.x * fluid_config.grid_to_world_scale,
+                    fluid_config.world_position.y + p.y * fluid_config.grid_to_world_scale,
+                    fluid_config.world_position.z + p.z * fluid_config.grid_to_world_scale,
+                )
+            })
+            .collect()
+    }
+}
+
+#[pymodule]
+fn blub(_py: Python, module: &PyModule) -> PyResult<()> {
+    module.add_class::<Simulation>()?;
+    Ok(())
+}
@@ -0,0 +1,49 @@
+use serde::{Deserialize, Serialize};
+use std::{error::Error, fs::File, io::BufReader, path::Path, time::Duration};
+
+// Changes of time scale over the course of a recording, e.g. for slow motion sections.
+//
+// Keyframes are placed on the simulation timeline and the time scale is linearly interpolated in between,
+// before the first and after the last keyframe their time scale is held.
+// A slow motion section that eases in & out looks like this:
+//   { "time_scale": [ { "time": 1.8, "time_scale": 1.0 }, { "time": 2.0, "time_scale": 0.25 },
+//                     { "time": 2.5, "time_scale": 0.25 }, { "time": 2.7, "time_scale": 1.0 } ] }
+#[derive(Deserialize, Serialize, Clone)]
+pub struct RecordingSchedule {
+    pub time_scale: Vec<TimeScaleKeyframe>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy)]
+pub struct TimeScaleKeyframe {
+    // Simulated time in seconds.
+    pub time: f64,
+    pub time_scale: f32,
+}
+
+impl RecordingSchedule {
+    pub fn from_file(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let schedule: RecordingSchedule = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        if schedule.time_scale.windows(2).any(|pair| pair[0].time > pair[1].time) {
+            return Err("time scale keyframes need to be ordered by time".into());
+        }
+        if let Some(keyframe) = schedule.time_scale.iter().find(|keyframe| keyframe.time_scale <= 0.0) {
+            return Err(format!("time scale needs to be positive, got {} at {}s", keyframe.time_scale, keyframe.time).into());
+        }
+        Ok(schedule)
+    }
+
+    pub fn time_scale_at(&self, simulated_time: Duration) -> f32 {
+        let time = simulated_time.as_secs_f64();
+        let next_index = self.time_scale.iter().position(|keyframe| keyframe.time > time);
+        match next_index {
+            None => self.time_scale.last().map_or(1.0, |keyframe| keyframe.time_scale),
+            Some(0) => self.time_scale[0].time_scale,
+            Some(next_index) => {
+                let previous = self.time_scale[next_index - 1];
+                let next = self.time_scale[next_index];
+                let t = ((time - previous.time) / (next.time - previous.time)) as f32;
+                previous.time_scale + (next.time_scale - previous.time_scale) * t
+            }
+        }
+    }
+}
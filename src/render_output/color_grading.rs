@@ -0,0 +1,189 @@
+use crate::wgpu_utils::{
+    binding_builder::{BindGroupBuilder, BindGroupLayoutBuilder, BindGroupLayoutWithDesc},
+    binding_glsl,
+};
+use std::{error::Error, path::Path};
+
+// Color grading via 3D lookup table, applied after tonemapping.
+// LUTs are expected to operate on display (sRGB encoded) values, as pretty much all .cube files out there do.
+pub struct ColorGrading {
+    bind_group_layout: BindGroupLayoutWithDesc,
+    bind_group: wgpu::BindGroup,
+    sampler: wgpu::Sampler,
+}
+
+struct CubeLut {
+    size: u32,
+    data: Vec<[f32; 3]>, // red changes fastest, then green, then blue.
+}
+
+// Parses the Adobe/Resolve .cube format. Only 3D LUTs with default domain are supported.
+fn parse_cube_file(content: &str) -> Result<CubeLut, Box<dyn Error>> {
+    let mut size = 0;
+    let mut data = Vec::new();
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with("TITLE") {
+            continue;
+        }
+
+        let mut tokens = line.split_whitespace();
+        let first = tokens.next().unwrap();
+        match first {
+            "LUT_3D_SIZE" => size = tokens.next().ok_or("LUT_3D_SIZE without value")?.parse()?,
+            "LUT_1D_SIZE" => return Err("1D LUTs are not supported".into()),
+            "DOMAIN_MIN" | "DOMAIN_MAX" => {
+                let expected = if first == "DOMAIN_MIN" { 0.0 } else { 1.0 };
+                for value in tokens {
+                    if value.parse::<f32>()? != expected {
+                        warn!("Color grading LUT has non-default {}, ignoring it", first);
+                        break;
+                    }
+                }
+            }
+            _ => {
+                let r: f32 = first.parse()?;
+                let g: f32 = tokens.next().ok_or("incomplete LUT entry")?.parse()?;
+                let b: f32 = tokens.next().ok_or("incomplete LUT entry")?.parse()?;
+                data.push([r, g, b]);
+            }
+        }
+    }
+
+    if size < 2 {
+        return Err("LUT_3D_SIZE missing or too small".into());
+    }
+    if data.len() != (size * size * size) as usize {
+        return Err(format!("expected {} LUT entries, got {}", size * size * size, data.len()).into());
+    }
+
+    Ok(CubeLut { size, data })
+}
+
+impl CubeLut {
+    fn identity() -> Self {
+        let mut data = Vec::with_capacity(8);
+        for b in 0..2 {
+            for g in 0..2 {
+                for r in 0..2 {
+                    data.push([r as f32, g as f32, b as f32]);
+                }
+            }
+        }
+        CubeLut { size: 2, data }
+    }
+
+    // Rgb10a2Unorm is filterable everywhere and gives a bit more precision than 8bit formats.
+    fn create_texture(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> wgpu::Texture {
+        let size = wgpu::Extent3d {
+            width: self.size,
+            height: self.size,
+            depth_or_array_layers: self.size,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Texture: Color Grading LUT"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D3,
+            format: wgpu::TextureFormat::Rgb10a2Unorm,
+            usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
+        });
+
+        let packed: Vec<u32> = self
+            .data
+            .iter()
+            .map(|rgb| {
+                let quantize = |v: f32| (v.clamp(0.0, 1.0) * 1023.0 + 0.5) as u32;
+                quantize(rgb[0]) | (quantize(rgb[1]) << 10) | (quantize(rgb[2]) << 20) | (3 << 30)
+            })
+            .collect();
+
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            bytemuck::cast_slice(&packed),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: std::num::NonZeroU32::new(4 * self.size),
+                rows_per_image: std::num::NonZeroU32::new(self.size),
+            },
+            size,
+        );
+
+        texture
+    }
+}
+
+impl ColorGrading {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let bind_group_layout = BindGroupLayoutBuilder::new()
+            .next_binding_fragment(binding_glsl::texture3D())
+            .next_binding_fragment(binding_glsl::sampler(true))
+            .create(device, "BindGroupLayout: Color Grading");
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Sampler: Color Grading LUT"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+        let bind_group = Self::create_bind_group(device, queue, &bind_group_layout, &sampler, &CubeLut::identity());
+
+        ColorGrading {
+            bind_group_layout,
+            bind_group,
+            sampler,
+        }
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        bind_group_layout: &BindGroupLayoutWithDesc,
+        sampler: &wgpu::Sampler,
+        lut: &CubeLut,
+    ) -> wgpu::BindGroup {
+        let lut_view = lut.create_texture(device, queue).create_view(&Default::default());
+        BindGroupBuilder::new(bind_group_layout)
+            .texture(&lut_view)
+            .sampler(sampler)
+            .create(device, "BindGroup: Color Grading")
+    }
+
+    // Sets a new LUT or goes back to identity if None is passed or loading fails.
+    pub fn set_lut(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, path: Option<&Path>) {
+        let lut = match path {
+            Some(path) => {
+                info!("Loading color grading LUT {:?}", path);
+                match std::fs::read_to_string(path)
+                    .map_err(|err| Box::<dyn Error>::from(err))
+                    .and_then(|content| parse_cube_file(&content))
+                {
+                    Ok(lut) => lut,
+                    Err(err) => {
+                        error!("Failed to load color grading LUT {:?}: {}", path, err);
+                        CubeLut::identity()
+                    }
+                }
+            }
+            None => CubeLut::identity(),
+        };
+        self.bind_group = Self::create_bind_group(device, queue, &self.bind_group_layout, &self.sampler, &lut);
+    }
+
+    pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layout.layout
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+}
@@ -1,19 +1,27 @@
-use super::screen::Screen;
+use super::{
+    color_grading::ColorGrading,
+    screen::Screen,
+    screenshot_capture::{ScreenshotCapture, ScreenshotFormat},
+};
 use crate::wgpu_utils::{
-    binding_builder::{BindGroupBuilder, BindGroupLayoutBuilder},
+    binding_builder::{BindGroupBuilder, BindGroupLayoutBuilder, BindGroupLayoutWithDesc},
     binding_glsl,
     pipelines::*,
     shader::ShaderDirectory,
+    texture_pool::TexturePool,
 };
-use std::{path::Path, rc::Rc};
+use std::{path::Path, sync::Arc};
 
 pub struct HdrBackbuffer {
     hdr_backbuffer: wgpu::Texture,
     hdr_backbuffer_view: wgpu::TextureView,
     resolution: winit::dpi::PhysicalSize<u32>,
 
+    bind_group_layout: Arc<BindGroupLayoutWithDesc>,
     read_backbuffer_bind_group: wgpu::BindGroup,
     hdr_resolve_pipeline: RenderPipelineHandle,
+
+    screenshot_capture: ScreenshotCapture,
 }
 
 impl HdrBackbuffer {
@@ -24,32 +32,25 @@ impl HdrBackbuffer {
         resolution: winit::dpi::PhysicalSize<u32>,
         shader_dir: &ShaderDirectory,
         pipeline_manager: &mut PipelineManager,
+        color_grading: &ColorGrading,
     ) -> Self {
-        let size = wgpu::Extent3d {
-            width: resolution.width,
-            height: resolution.height,
-            depth_or_array_layers: 1,
-        };
-
-        let hdr_backbuffer = device.create_texture(&wgpu::TextureDescriptor {
-            label: Some("Texture: HdrBackbuffer"),
-            size,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: Self::FORMAT,
-            usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::STORAGE | wgpu::TextureUsage::COPY_SRC,
-        });
+        let hdr_backbuffer = device.create_texture(&Self::texture_desc(resolution));
         let hdr_backbuffer_view = hdr_backbuffer.create_view(&Default::default());
 
         let bind_group_layout = BindGroupLayoutBuilder::new()
             .next_binding_fragment(binding_glsl::texture2D())
-            .create(device, "BindGroupLayout: Screen, Read Texture");
-        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("HdrBackbuffer Pipeline Layout"),
-            bind_group_layouts: &[&bind_group_layout.layout],
-            push_constant_ranges: &[],
-        });
+            .create_cached(device, pipeline_manager.bind_group_layout_cache(), "BindGroupLayout: Read Texture");
+        let pipeline_layout = pipeline_manager.create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("HdrBackbuffer Pipeline Layout"),
+                bind_group_layouts: &[&bind_group_layout.layout, color_grading.bind_group_layout()],
+                push_constant_ranges: &[wgpu::PushConstantRange {
+                    stages: wgpu::ShaderStage::FRAGMENT,
+                    range: 0..8,
+                }],
+            },
+        );
         let read_backbuffer_bind_group = BindGroupBuilder::new(&bind_group_layout)
             .texture(&hdr_backbuffer_view)
             .create(device, "BindGroup: Read HdrBackbuffer");
@@ -58,10 +59,10 @@ impl HdrBackbuffer {
             device,
             shader_dir,
             RenderPipelineCreationDesc::new(
-                "HdrBackbuffer: Copy texture",
-                Rc::new(pipeline_layout),
+                "HdrBackbuffer: Tonemap",
+                pipeline_layout,
                 Path::new("screentri.vert"),
-                Path::new("copy_texture.frag"),
+                Path::new("tonemap.frag"),
                 Screen::FORMAT_BACKBUFFER,
                 None,
             ),
@@ -72,11 +73,47 @@ impl HdrBackbuffer {
             hdr_backbuffer_view: hdr_backbuffer_view,
             resolution,
 
+            bind_group_layout,
             read_backbuffer_bind_group,
             hdr_resolve_pipeline,
+
+            screenshot_capture: ScreenshotCapture::new(resolution, ScreenshotFormat::Exr),
         }
     }
 
+    fn texture_desc(resolution: winit::dpi::PhysicalSize<u32>) -> wgpu::TextureDescriptor<'static> {
+        wgpu::TextureDescriptor {
+            label: Some("Texture: HdrBackbuffer"),
+            size: wgpu::Extent3d {
+                width: resolution.width,
+                height: resolution.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::FORMAT,
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::STORAGE | wgpu::TextureUsage::COPY_SRC,
+        }
+    }
+
+    // Swaps out the texture for one of the new size, keeping the pipeline.
+    pub fn resize(&mut self, device: &wgpu::Device, texture_pool: &mut TexturePool, resolution: winit::dpi::PhysicalSize<u32>) {
+        let hdr_backbuffer = texture_pool.acquire(device, &Self::texture_desc(resolution));
+        texture_pool.release(
+            &Self::texture_desc(self.resolution),
+            std::mem::replace(&mut self.hdr_backbuffer, hdr_backbuffer),
+        );
+        self.hdr_backbuffer_view = self.hdr_backbuffer.create_view(&Default::default());
+        self.read_backbuffer_bind_group = BindGroupBuilder::new(&self.bind_group_layout)
+            .texture(&self.hdr_backbuffer_view)
+            .create(device, "BindGroup: Read HdrBackbuffer");
+
+        self.screenshot_capture.wait_for_pending_screenshots(device);
+        self.screenshot_capture = ScreenshotCapture::new(resolution, ScreenshotFormat::Exr);
+        self.resolution = resolution;
+    }
+
     pub fn resolution(&self) -> winit::dpi::PhysicalSize<u32> {
         self.resolution
     }
@@ -89,8 +126,29 @@ impl HdrBackbuffer {
         &self.hdr_backbuffer_view
     }
 
-    pub fn tonemap(&self, target: &wgpu::TextureView, encoder: &mut wgpu::CommandEncoder, pipeline_manager: &PipelineManager) {
-        // TODO: All this tonemapping does is go from half (linear) to srgb. Do some nice tonemapping here!
+    // Captures the hdr image as is, i.e. before tonemapping & color grading.
+    pub fn capture_screenshot(&mut self, path: &Path, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) {
+        self.screenshot_capture.capture_screenshot(path, &self.hdr_backbuffer, device, encoder);
+    }
+
+    pub fn end_frame(&mut self) {
+        self.screenshot_capture.process_pending_screenshots();
+    }
+
+    pub fn wait_for_pending_screenshots(&mut self, device: &wgpu::Device) {
+        self.screenshot_capture.wait_for_pending_screenshots(device);
+    }
+
+    // Target may have a different resolution than the hdr backbuffer, in which case the image is scaled with bilinear filtering.
+    pub fn tonemap(
+        &self,
+        target: &wgpu::TextureView,
+        target_resolution: winit::dpi::PhysicalSize<u32>,
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline_manager: &PipelineManager,
+        color_grading: &ColorGrading,
+    ) {
+        // TODO: All this tonemapping does is go from half (linear) to srgb (+ color grading). Do some nice tonemapping here!
         // Note that we can't use a compute shader here since that would require STORAGE usage flag on the final output which we can't do since it's srgb!
         let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: Some("tonemap"),
@@ -106,6 +164,14 @@ impl HdrBackbuffer {
         });
         render_pass.set_pipeline(pipeline_manager.get_render(&self.hdr_resolve_pipeline));
         render_pass.set_bind_group(0, &self.read_backbuffer_bind_group, &[]);
+        render_pass.set_bind_group(1, color_grading.bind_group(), &[]);
+        pipeline_manager.set_push_constants_render(
+            &mut render_pass,
+            &self.hdr_resolve_pipeline,
+            wgpu::ShaderStage::FRAGMENT,
+            0,
+            bytemuck::bytes_of(&[target_resolution.width, target_resolution.height]),
+        );
         render_pass.draw(0..3, 0..1);
     }
 }
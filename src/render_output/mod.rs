@@ -1,4 +1,14 @@
+// Color spaces along the way to the window:
+// * HdrBackbuffer: linear, scene-referred (Rgba16Float)
+// * tonemap: linear in, clipped & color graded linear out into the Screen backbuffer, which is Rgba8UnormSrgb and thus sRGB encoded on write
+// * gui & recording overlay: egui gives sRGB colors, egui_wgpu_backend decodes them and relies on the *Srgb target for encoding (blending happens in linear)
+// * copy to swapchain: *Srgb to *Srgb, decode on read & encode on write, i.e. the bytes end up unchanged
+// * png screenshots: raw bytes of the Screen backbuffer, i.e. sRGB encoded as expected by image viewers (without gui)
+// * exr screenshots: raw linear values of the HdrBackbuffer
+pub mod color_grading;
 pub mod hdr_backbuffer;
+pub mod offscreen_target;
+pub mod recording_overlay;
 pub mod screen;
 pub mod screenshot_capture;
 pub mod screenshot_recorder;
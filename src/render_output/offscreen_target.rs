@@ -0,0 +1,313 @@
+use super::{
+    color_grading::ColorGrading,
+    hdr_backbuffer::HdrBackbuffer,
+    screen::Screen,
+    screenshot_capture::{ScreenshotCapture, ScreenshotFormat},
+};
+use crate::wgpu_utils::{
+    binding_builder::{BindGroupBuilder, BindGroupLayoutBuilder},
+    binding_glsl,
+    pipelines::*,
+    shader::ShaderDirectory,
+};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OffscreenTargetDesc {
+    pub resolution: winit::dpi::PhysicalSize<u32>,
+    pub supersampling: u32,
+}
+
+// Render target that is independent of the window, used for recording videos at arbitrary resolution.
+//
+// The scene is rendered & tonemapped at supersampled resolution, then box filtered down to the output resolution.
+// The result can be shown scaled on the screen as a preview.
+// For hdr screenshots, the hdr backbuffer is filtered down separately on demand.
+pub struct OffscreenTarget {
+    desc: OffscreenTargetDesc,
+
+    hdr_backbuffer: HdrBackbuffer,
+    depth_view: wgpu::TextureView,
+    supersampled_view: wgpu::TextureView,
+    output: wgpu::Texture,
+    output_view: wgpu::TextureView,
+    hdr_output: wgpu::Texture,
+    hdr_output_view: wgpu::TextureView,
+
+    read_supersampled_bind_group: wgpu::BindGroup,
+    read_output_bind_group: wgpu::BindGroup,
+    read_hdr_backbuffer_bind_group: wgpu::BindGroup,
+    downsample_pipeline: RenderPipelineHandle,
+    downsample_hdr_pipeline: RenderPipelineHandle,
+
+    screenshot_capture: ScreenshotCapture,
+    hdr_screenshot_capture: ScreenshotCapture,
+}
+
+impl OffscreenTarget {
+    pub fn new(
+        device: &wgpu::Device,
+        desc: OffscreenTargetDesc,
+        shader_dir: &ShaderDirectory,
+        pipeline_manager: &mut PipelineManager,
+        color_grading: &ColorGrading,
+    ) -> Self {
+        let mut desc = desc;
+        let max_texture_dimension = device.limits().max_texture_dimension_2d;
+        desc.resolution.width = desc.resolution.width.max(1).min(max_texture_dimension);
+        desc.resolution.height = desc.resolution.height.max(1).min(max_texture_dimension);
+        desc.supersampling = desc.supersampling.max(1);
+        while desc.supersampling > 1 && desc.resolution.width.max(desc.resolution.height) * desc.supersampling > max_texture_dimension {
+            desc.supersampling -= 1;
+        }
+        info!("creating offscreen target {:?}", desc);
+
+        let supersampled_resolution =
+            winit::dpi::PhysicalSize::new(desc.resolution.width * desc.supersampling, desc.resolution.height * desc.supersampling);
+        let hdr_backbuffer = HdrBackbuffer::new(device, supersampled_resolution, shader_dir, pipeline_manager, color_grading);
+
+        let supersampled_size = wgpu::Extent3d {
+            width: supersampled_resolution.width,
+            height: supersampled_resolution.height,
+            depth_or_array_layers: 1,
+        };
+        let depth_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Texture: Offscreen DepthBuffer"),
+            size: supersampled_size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Screen::FORMAT_DEPTH,
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::SAMPLED,
+        });
+        let supersampled = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Texture: Offscreen supersampled"),
+            size: supersampled_size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Screen::FORMAT_BACKBUFFER,
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::SAMPLED,
+        });
+        let supersampled_view = supersampled.create_view(&Default::default());
+        let output_size = wgpu::Extent3d {
+            width: desc.resolution.width,
+            height: desc.resolution.height,
+            depth_or_array_layers: 1,
+        };
+        let output = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Texture: Offscreen output"),
+            size: output_size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Screen::FORMAT_BACKBUFFER,
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_SRC,
+        });
+        let output_view = output.create_view(&Default::default());
+        let hdr_output = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Texture: Offscreen hdr output"),
+            size: output_size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: HdrBackbuffer::FORMAT,
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::COPY_SRC,
+        });
+        let hdr_output_view = hdr_output.create_view(&Default::default());
+
+        let bind_group_layout = BindGroupLayoutBuilder::new()
+            .next_binding_fragment(binding_glsl::texture2D())
+            .create_cached(device, pipeline_manager.bind_group_layout_cache(), "BindGroupLayout: Read Texture");
+        let pipeline_layout = pipeline_manager.create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Offscreen Downsample Pipeline Layout"),
+                bind_group_layouts: &[&bind_group_layout.layout],
+                push_constant_ranges: &[wgpu::PushConstantRange {
+                    stages: wgpu::ShaderStage::FRAGMENT,
+                    range: 0..8,
+                }],
+            },
+        );
+        let read_supersampled_bind_group = BindGroupBuilder::new(&bind_group_layout)
+            .texture(&supersampled_view)
+            .create(device, "BindGroup: Read Offscreen supersampled");
+        let read_output_bind_group = BindGroupBuilder::new(&bind_group_layout)
+            .texture(&output_view)
+            .create(device, "BindGroup: Read Offscreen output");
+        let read_hdr_backbuffer_bind_group = BindGroupBuilder::new(&bind_group_layout)
+            .texture(hdr_backbuffer.texture_view())
+            .create(device, "BindGroup: Read Offscreen hdr backbuffer");
+
+        let downsample_pipeline = pipeline_manager.create_render_pipeline(
+            device,
+            shader_dir,
+            RenderPipelineCreationDesc::new(
+                "Offscreen: Downsample",
+                pipeline_layout.clone(),
+                Path::new("screentri.vert"),
+                Path::new("downsample.frag"),
+                Screen::FORMAT_BACKBUFFER,
+                None,
+            ),
+        );
+        let downsample_hdr_pipeline = pipeline_manager.create_render_pipeline(
+            device,
+            shader_dir,
+            RenderPipelineCreationDesc::new(
+                "Offscreen: Downsample hdr",
+                pipeline_layout,
+                Path::new("screentri.vert"),
+                Path::new("downsample.frag"),
+                HdrBackbuffer::FORMAT,
+                None,
+            ),
+        );
+
+        OffscreenTarget {
+            desc,
+            hdr_backbuffer,
+            depth_view: depth_texture.create_view(&Default::default()),
+            supersampled_view,
+            output,
+            output_view,
+            hdr_output,
+            hdr_output_view,
+
+            read_supersampled_bind_group,
+            read_output_bind_group,
+            read_hdr_backbuffer_bind_group,
+            downsample_pipeline,
+            downsample_hdr_pipeline,
+
+            screenshot_capture: ScreenshotCapture::new(desc.resolution, ScreenshotFormat::Png),
+            hdr_screenshot_capture: ScreenshotCapture::new(desc.resolution, ScreenshotFormat::Exr),
+        }
+    }
+
+    pub fn desc(&self) -> OffscreenTargetDesc {
+        self.desc
+    }
+
+    pub fn aspect_ratio(&self) -> f32 {
+        self.desc.resolution.width as f32 / self.desc.resolution.height as f32
+    }
+
+    pub fn hdr_backbuffer(&self) -> &HdrBackbuffer {
+        &self.hdr_backbuffer
+    }
+
+    pub fn depthbuffer(&self) -> &wgpu::TextureView {
+        &self.depth_view
+    }
+
+    // Tonemapped image at output resolution, valid after resolve.
+    pub fn output(&self) -> &wgpu::TextureView {
+        &self.output_view
+    }
+
+    // Tonemaps the supersampled hdr image and filters it down to output resolution.
+    pub fn resolve(&self, encoder: &mut wgpu::CommandEncoder, pipeline_manager: &PipelineManager, color_grading: &ColorGrading) {
+        self.hdr_backbuffer.tonemap(
+            &self.supersampled_view,
+            self.hdr_backbuffer.resolution(),
+            encoder,
+            pipeline_manager,
+            color_grading,
+        );
+        self.downsample(
+            encoder,
+            pipeline_manager,
+            &self.downsample_pipeline,
+            &self.read_supersampled_bind_group,
+            &self.output_view,
+            self.desc.resolution,
+        );
+    }
+
+    // Draws the output, scaled to the given target. (no aspect ratio correction)
+    pub fn draw_preview(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline_manager: &PipelineManager,
+        target: &wgpu::TextureView,
+        target_resolution: winit::dpi::PhysicalSize<u32>,
+    ) {
+        self.downsample(
+            encoder,
+            pipeline_manager,
+            &self.downsample_pipeline,
+            &self.read_output_bind_group,
+            target,
+            target_resolution,
+        );
+    }
+
+    fn downsample(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline_manager: &PipelineManager,
+        pipeline: &RenderPipelineHandle,
+        source: &wgpu::BindGroup,
+        target: &wgpu::TextureView,
+        target_resolution: winit::dpi::PhysicalSize<u32>,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("offscreen downsample"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(pipeline_manager.get_render(pipeline));
+        render_pass.set_bind_group(0, source, &[]);
+        pipeline_manager.set_push_constants_render(
+            &mut render_pass,
+            pipeline,
+            wgpu::ShaderStage::FRAGMENT,
+            0,
+            bytemuck::bytes_of(&[target_resolution.width, target_resolution.height]),
+        );
+        render_pass.draw(0..3, 0..1);
+    }
+
+    pub fn capture_screenshot(
+        &mut self,
+        path: &Path,
+        format: ScreenshotFormat,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline_manager: &PipelineManager,
+    ) {
+        match format {
+            ScreenshotFormat::Png => self.screenshot_capture.capture_screenshot(path, &self.output, device, encoder),
+            ScreenshotFormat::Exr => {
+                self.downsample(
+                    encoder,
+                    pipeline_manager,
+                    &self.downsample_hdr_pipeline,
+                    &self.read_hdr_backbuffer_bind_group,
+                    &self.hdr_output_view,
+                    self.desc.resolution,
+                );
+                self.hdr_screenshot_capture.capture_screenshot(path, &self.hdr_output, device, encoder);
+            }
+        }
+    }
+
+    pub fn end_frame(&mut self) {
+        self.screenshot_capture.process_pending_screenshots();
+        self.hdr_screenshot_capture.process_pending_screenshots();
+    }
+
+    pub fn wait_for_pending_screenshots(&mut self, device: &wgpu::Device) {
+        self.screenshot_capture.wait_for_pending_screenshots(device);
+        self.hdr_screenshot_capture.wait_for_pending_screenshots(device);
+    }
+}
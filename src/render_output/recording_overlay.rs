@@ -0,0 +1,88 @@
+use super::screen::Screen;
+
+// What goes into the text overlay of recorded frames.
+#[derive(Clone, Copy)]
+pub struct RecordingOverlaySettings {
+    pub enabled: bool,
+    pub scene_name: bool,
+    pub simulation_time: bool,
+    pub solver_settings: bool,
+    pub frame_number: bool,
+}
+
+impl Default for RecordingOverlaySettings {
+    fn default() -> Self {
+        RecordingOverlaySettings {
+            enabled: false,
+            scene_name: true,
+            simulation_time: true,
+            solver_settings: false,
+            frame_number: true,
+        }
+    }
+}
+
+// Burns a few lines of text into recorded frames so exported videos are self-describing.
+//
+// Uses its own egui context that is independent of the gui and of the window: no input, sized to the target,
+// and scaled with the target's height so the overlay covers the same portion of the image at any resolution.
+// Draws into the tonemapped (ldr) image, hdr screenshots don't get an overlay.
+pub struct RecordingOverlay {
+    context: egui::CtxRef,
+    render_pass: egui_wgpu_backend::RenderPass,
+}
+
+impl RecordingOverlay {
+    // Overlay text size is chosen for this target height.
+    const REFERENCE_HEIGHT: f32 = 720.0;
+
+    pub fn new(device: &wgpu::Device) -> Self {
+        RecordingOverlay {
+            context: egui::CtxRef::default(),
+            render_pass: egui_wgpu_backend::RenderPass::new(device, Screen::FORMAT_BACKBUFFER, 1),
+        }
+    }
+
+    pub fn draw(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &wgpu::TextureView,
+        target_resolution: winit::dpi::PhysicalSize<u32>,
+        lines: &[String],
+    ) {
+        if lines.is_empty() {
+            return;
+        }
+
+        let pixels_per_point = target_resolution.height as f32 / Self::REFERENCE_HEIGHT;
+        self.context.begin_frame(egui::RawInput {
+            screen_rect: Some(egui::Rect::from_min_size(
+                Default::default(),
+                egui::vec2(target_resolution.width as f32, target_resolution.height as f32) / pixels_per_point,
+            )),
+            pixels_per_point: Some(pixels_per_point),
+            ..Default::default()
+        });
+        egui::Area::new("recording overlay")
+            .anchor(egui::Align2::LEFT_BOTTOM, [8.0, -8.0])
+            .show(&self.context, |ui| {
+                for line in lines.iter() {
+                    ui.add(egui::Label::new(line).text_color(egui::Color32::WHITE).monospace());
+                }
+            });
+        let (_output, shapes) = self.context.end_frame();
+        let paint_jobs = self.context.tessellate(shapes);
+
+        let screen_descriptor = egui_wgpu_backend::ScreenDescriptor {
+            physical_width: target_resolution.width,
+            physical_height: target_resolution.height,
+            scale_factor: pixels_per_point,
+        };
+        self.render_pass.update_texture(device, queue, &self.context.texture());
+        self.render_pass.update_user_textures(device, queue);
+        self.render_pass.update_buffers(device, queue, &paint_jobs, &screen_descriptor);
+        self.render_pass.execute(encoder, target, &paint_jobs, &screen_descriptor, None);
+    }
+}
@@ -1,19 +1,37 @@
-use super::screenshot_capture::ScreenshotCapture;
+use super::screenshot_capture::{ScreenshotCapture, ScreenshotFormat};
+use crate::log_buffer;
 use crate::wgpu_utils::binding_builder::*;
 use crate::wgpu_utils::shader::*;
 use crate::wgpu_utils::*;
 use pipelines::*;
-use std::{path::Path, rc::Rc};
+use std::{path::Path, sync::Arc};
+use texture_pool::TexturePool;
+
+// Only affects what is shown in the window, not screenshots. See copy_texture.frag
+#[derive(Clone, Copy, Debug, EnumIter, PartialEq)]
+pub enum ColorSpaceDebugMode {
+    None = 0,
+    // Shows the image as if linear values were written to the display without sRGB encoding (too dark).
+    MissingEncode = 1,
+    // Shows the image as if sRGB encoding was applied twice (washed out).
+    DoubleEncode = 2,
+}
 
 pub struct Screen {
     resolution: winit::dpi::PhysicalSize<u32>,
+    // Resolution of the depth buffer (and the hdr backbuffer it is used with), may differ from the window resolution, see render scale.
+    render_resolution: winit::dpi::PhysicalSize<u32>,
+    swap_chain_resolution: winit::dpi::PhysicalSize<u32>,
     swap_chain: wgpu::SwapChain,
     present_mode: wgpu::PresentMode,
+    supported_present_modes: Vec<wgpu::PresentMode>,
 
     backbuffer: wgpu::Texture,
     backbuffer_view: wgpu::TextureView,
+    depth_texture: wgpu::Texture,
     depth_view: wgpu::TextureView,
 
+    bind_group_layout: Arc<BindGroupLayoutWithDesc>,
     read_backbuffer_bind_group: wgpu::BindGroup,
     copy_to_swapchain_pipeline: RenderPipelineHandle,
 
@@ -27,9 +45,19 @@ pub struct ScreenUniformBufferContent {
     resolution_inv: cgmath::Point2<f32>,
 }
 
+impl ScreenUniformBufferContent {
+    pub fn new(resolution: winit::dpi::PhysicalSize<u32>) -> Self {
+        ScreenUniformBufferContent {
+            resolution: cgmath::point2(resolution.width as f32, resolution.height as f32),
+            resolution_inv: cgmath::point2(1.0 / resolution.width as f32, 1.0 / resolution.height as f32),
+        }
+    }
+}
+
 impl Screen {
     pub const FORMAT_BACKBUFFER: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
-    const FORMAT_SWAPCHAIN: wgpu::TextureFormat = wgpu::TextureFormat::Bgra8UnormSrgb;
+    // SDR only, wgpu can't select an HDR color space for the swap chain yet. See "HDR Display Output" in the readme.
+    pub const FORMAT_SWAPCHAIN: wgpu::TextureFormat = wgpu::TextureFormat::Bgra8UnormSrgb;
     pub const FORMAT_DEPTH: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
     pub const DEFAULT_PRESENT_MODE: wgpu::PresentMode = wgpu::PresentMode::Fifo;
 
@@ -43,52 +71,38 @@ impl Screen {
     ) -> Self {
         info!("creating screen with {:?}", resolution);
 
-        let swap_chain = device.create_swap_chain(
-            window_surface,
-            &wgpu::SwapChainDescriptor {
-                usage: wgpu::TextureUsage::RENDER_ATTACHMENT,
-                format: Screen::FORMAT_SWAPCHAIN,
-                width: resolution.width,
-                height: resolution.height,
+        let supported_present_modes = Self::probe_present_modes(device, window_surface, resolution);
+        info!("supported present modes: {:?}", supported_present_modes);
+        let present_mode = if supported_present_modes.contains(&present_mode) {
+            present_mode
+        } else {
+            warn!(
+                "present mode {:?} is not supported, using {:?} instead",
                 present_mode,
-            },
-        );
-
-        let size = wgpu::Extent3d {
-            width: resolution.width,
-            height: resolution.height,
-            depth_or_array_layers: 1,
+                Self::DEFAULT_PRESENT_MODE
+            );
+            Self::DEFAULT_PRESENT_MODE
         };
-
-        let backbuffer = device.create_texture(&wgpu::TextureDescriptor {
-            label: Some("Texture: Backbuffer"),
-            size,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: Self::FORMAT_BACKBUFFER,
-            usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_SRC,
-        });
+        let swap_chain = Self::create_swap_chain(device, window_surface, resolution, present_mode);
+        let backbuffer = device.create_texture(&Self::backbuffer_desc(resolution));
         let backbuffer_view = backbuffer.create_view(&Default::default());
-
-        let depth_texture = device.create_texture(&wgpu::TextureDescriptor {
-            label: Some("Texture: Screen DepthBuffer"),
-            size,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: Self::FORMAT_DEPTH,
-            usage: wgpu::TextureUsage::RENDER_ATTACHMENT,
-        });
+        let depth_texture = device.create_texture(&Self::depth_desc(resolution));
+        let depth_view = depth_texture.create_view(&Default::default());
 
         let bind_group_layout = BindGroupLayoutBuilder::new()
             .next_binding_fragment(binding_glsl::texture2D())
-            .create(device, "BindGroupLayout: Screen, Read Texture");
-        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("Screen Swapchain Copy Pipeline Layout"),
-            bind_group_layouts: &[&bind_group_layout.layout],
-            push_constant_ranges: &[],
-        });
+            .create_cached(device, pipeline_manager.bind_group_layout_cache(), "BindGroupLayout: Read Texture");
+        let pipeline_layout = pipeline_manager.create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Screen Swapchain Copy Pipeline Layout"),
+                bind_group_layouts: &[&bind_group_layout.layout],
+                push_constant_ranges: &[wgpu::PushConstantRange {
+                    stages: wgpu::ShaderStage::FRAGMENT,
+                    range: 0..4,
+                }],
+            },
+        );
 
         let read_backbuffer_bind_group = BindGroupBuilder::new(&bind_group_layout)
             .texture(&backbuffer_view)
@@ -99,7 +113,7 @@ impl Screen {
             shader_dir,
             RenderPipelineCreationDesc::new(
                 "Screen: Copy texture",
-                Rc::new(pipeline_layout),
+                pipeline_layout,
                 Path::new("screentri.vert"),
                 Path::new("copy_texture.frag"),
                 Self::FORMAT_SWAPCHAIN,
@@ -109,26 +123,198 @@ impl Screen {
 
         Screen {
             resolution,
+            render_resolution: resolution,
+            swap_chain_resolution: resolution,
             swap_chain,
             present_mode,
+            supported_present_modes,
             backbuffer,
             backbuffer_view,
-            depth_view: depth_texture.create_view(&Default::default()),
+            depth_texture,
+            depth_view,
 
+            bind_group_layout,
             read_backbuffer_bind_group,
             copy_to_swapchain_pipeline,
-            screenshot_capture: ScreenshotCapture::new(device, resolution),
+            screenshot_capture: ScreenshotCapture::new(resolution, ScreenshotFormat::Png),
+        }
+    }
+
+    fn create_swap_chain(
+        device: &wgpu::Device,
+        window_surface: &wgpu::Surface,
+        resolution: winit::dpi::PhysicalSize<u32>,
+        present_mode: wgpu::PresentMode,
+    ) -> wgpu::SwapChain {
+        device.create_swap_chain(
+            window_surface,
+            &wgpu::SwapChainDescriptor {
+                usage: wgpu::TextureUsage::RENDER_ATTACHMENT,
+                format: Screen::FORMAT_SWAPCHAIN,
+                width: resolution.width,
+                height: resolution.height,
+                present_mode,
+            },
+        )
+    }
+
+    // Like create_swap_chain, but fails if the present mode can't be used.
+    // Depending on the backend, wgpu either reports an unsupported present mode as validation error or silently falls back to Fifo,
+    // only logging a warning. The latter is detected via the log.
+    fn try_create_swap_chain(
+        device: &wgpu::Device,
+        window_surface: &wgpu::Surface,
+        resolution: winit::dpi::PhysicalSize<u32>,
+        present_mode: wgpu::PresentMode,
+    ) -> Result<wgpu::SwapChain, String> {
+        let num_entries_logged = log_buffer::num_entries_logged();
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let swap_chain = Self::create_swap_chain(device, window_surface, resolution, present_mode);
+        if let Some(error) = futures::executor::block_on(device.pop_error_scope()) {
+            return Err(error.to_string());
+        }
+        let fallback_warning = log_buffer::messages_since(num_entries_logged, "wgpu")
+            .into_iter()
+            .find(|message| message.to_lowercase().contains("present mode"));
+        match fallback_warning {
+            Some(warning) => Err(warning),
+            None => Ok(swap_chain),
+        }
+    }
+
+    // wgpu has no way of querying the present modes a surface supports, so we try them all.
+    // Fifo is the only mode that is guaranteed to be available.
+    fn probe_present_modes(
+        device: &wgpu::Device,
+        window_surface: &wgpu::Surface,
+        resolution: winit::dpi::PhysicalSize<u32>,
+    ) -> Vec<wgpu::PresentMode> {
+        [wgpu::PresentMode::Fifo, wgpu::PresentMode::Mailbox, wgpu::PresentMode::Immediate]
+            .iter()
+            .cloned()
+            .filter(|&present_mode| {
+                present_mode == wgpu::PresentMode::Fifo || Self::try_create_swap_chain(device, window_surface, resolution, present_mode).is_ok()
+            })
+            .collect()
+    }
+
+    fn backbuffer_desc(resolution: winit::dpi::PhysicalSize<u32>) -> wgpu::TextureDescriptor<'static> {
+        wgpu::TextureDescriptor {
+            label: Some("Texture: Backbuffer"),
+            size: wgpu::Extent3d {
+                width: resolution.width,
+                height: resolution.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::FORMAT_BACKBUFFER,
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_SRC,
+        }
+    }
+
+    fn depth_desc(resolution: winit::dpi::PhysicalSize<u32>) -> wgpu::TextureDescriptor<'static> {
+        wgpu::TextureDescriptor {
+            label: Some("Texture: Screen DepthBuffer"),
+            size: wgpu::Extent3d {
+                width: resolution.width,
+                height: resolution.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::FORMAT_DEPTH,
+            // Read by the screen space fluid when rendering at reduced resolution.
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::SAMPLED,
+        }
+    }
+
+    // Only recreates the swap chain. Cheap, so this can be done right away when the window size changes.
+    // Until the render targets are resized as well, the image is cropped/padded. (copy to swapchain is 1:1 per pixel)
+    pub fn resize_swap_chain(&mut self, device: &wgpu::Device, window_surface: &wgpu::Surface, resolution: winit::dpi::PhysicalSize<u32>) {
+        self.swap_chain_resolution = resolution;
+        self.swap_chain = Self::create_swap_chain(device, window_surface, resolution, self.present_mode);
+    }
+
+    // Keeps the previous present mode if the new one can't be used.
+    pub fn set_present_mode(&mut self, device: &wgpu::Device, window_surface: &wgpu::Surface, present_mode: wgpu::PresentMode) -> Result<(), String> {
+        if present_mode == self.present_mode {
+            return Ok(());
+        }
+        match Self::try_create_swap_chain(device, window_surface, self.swap_chain_resolution, present_mode) {
+            Ok(swap_chain) => {
+                self.swap_chain = swap_chain;
+                self.present_mode = present_mode;
+                Ok(())
+            }
+            Err(err) => {
+                self.supported_present_modes.retain(|&supported| supported != present_mode);
+                self.swap_chain = Self::create_swap_chain(device, window_surface, self.swap_chain_resolution, self.present_mode);
+                Err(err)
+            }
         }
     }
 
+    pub fn supported_present_modes(&self) -> &[wgpu::PresentMode] {
+        &self.supported_present_modes
+    }
+
+    // Recreates all resolution dependent render targets, reusing textures from the pool if possible. Old ones go back to the pool.
+    // The depth buffer is sized to the render resolution since it is used together with the hdr backbuffer.
+    pub fn resize_render_targets(
+        &mut self,
+        device: &wgpu::Device,
+        texture_pool: &mut TexturePool,
+        resolution: winit::dpi::PhysicalSize<u32>,
+        render_resolution: winit::dpi::PhysicalSize<u32>,
+    ) {
+        info!(
+            "resizing screen render targets to {:?} (render resolution {:?})",
+            resolution, render_resolution
+        );
+
+        let backbuffer = texture_pool.acquire(device, &Self::backbuffer_desc(resolution));
+        let depth_texture = texture_pool.acquire(device, &Self::depth_desc(render_resolution));
+        texture_pool.release(
+            &Self::backbuffer_desc(self.resolution),
+            std::mem::replace(&mut self.backbuffer, backbuffer),
+        );
+        texture_pool.release(
+            &Self::depth_desc(self.render_resolution),
+            std::mem::replace(&mut self.depth_texture, depth_texture),
+        );
+        self.backbuffer_view = self.backbuffer.create_view(&Default::default());
+        self.depth_view = self.depth_texture.create_view(&Default::default());
+        self.read_backbuffer_bind_group = BindGroupBuilder::new(&self.bind_group_layout)
+            .texture(&self.backbuffer_view)
+            .create(device, "BindGroup: Read Backbuffer");
+
+        self.screenshot_capture.wait_for_pending_screenshots(device);
+        self.screenshot_capture = ScreenshotCapture::new(resolution, ScreenshotFormat::Png);
+        self.resolution = resolution;
+        self.render_resolution = render_resolution;
+    }
+
     pub fn aspect_ratio(&self) -> f32 {
-        self.resolution.width as f32 / self.resolution.height as f32
+        self.render_resolution.width as f32 / self.render_resolution.height as f32
     }
 
+    // Resolution of all render targets. May lag behind the swap chain resolution while resizing.
     pub fn resolution(&self) -> winit::dpi::PhysicalSize<u32> {
         self.resolution
     }
 
+    // Resolution the scene is rendered at before it is scaled to the backbuffer while tonemapping.
+    pub fn render_resolution(&self) -> winit::dpi::PhysicalSize<u32> {
+        self.render_resolution
+    }
+
+    pub fn swap_chain_resolution(&self) -> winit::dpi::PhysicalSize<u32> {
+        self.swap_chain_resolution
+    }
+
     pub fn backbuffer(&self) -> &wgpu::TextureView {
         &self.backbuffer_view
     }
@@ -145,33 +331,35 @@ impl Screen {
         self.screenshot_capture.capture_screenshot(path, &self.backbuffer, device, encoder);
     }
 
-    pub fn start_frame(&mut self, device: &wgpu::Device, window_surface: &wgpu::Surface) -> wgpu::SwapChainTexture {
+    pub fn capture_thumbnail(&mut self, path: &Path, max_size: u32, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) {
+        self.screenshot_capture
+            .capture_thumbnail(path, max_size, &self.backbuffer, device, encoder);
+    }
+
+    pub fn start_frame(&mut self, device: &wgpu::Device, window_surface: &wgpu::Surface) -> Result<wgpu::SwapChainTexture, wgpu::SwapChainError> {
         // We assume here that any resizing has already been handled.
         // In that case it can still sometimes happen that the swap chain doesn't give a valid frame, e.g. after getting back from minimized state.
         // The problem usually goes away after recreating the swap chain.
         match self.swap_chain.get_current_frame() {
-            Ok(frame) => frame.output,
+            Ok(frame) => Ok(frame.output),
             Err(_) => {
                 info!(
                     "Failed to query current frame from swap chain. Recreating swap chain (resolution {:?}, present mode {:?})",
-                    self.resolution, self.present_mode
-                );
-                self.swap_chain = device.create_swap_chain(
-                    window_surface,
-                    &wgpu::SwapChainDescriptor {
-                        usage: wgpu::TextureUsage::RENDER_ATTACHMENT,
-                        format: Screen::FORMAT_SWAPCHAIN,
-                        width: self.resolution.width,
-                        height: self.resolution.height,
-                        present_mode: self.present_mode,
-                    },
+                    self.swap_chain_resolution, self.present_mode
                 );
-                self.swap_chain.get_current_frame().unwrap().output
+                self.swap_chain = Self::create_swap_chain(device, window_surface, self.swap_chain_resolution, self.present_mode);
+                self.swap_chain.get_current_frame().map(|frame| frame.output)
             }
         }
     }
 
-    pub fn copy_to_swapchain(&mut self, output: &wgpu::SwapChainTexture, encoder: &mut wgpu::CommandEncoder, pipeline_manager: &PipelineManager) {
+    pub fn copy_to_swapchain(
+        &mut self,
+        output: &wgpu::SwapChainTexture,
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline_manager: &PipelineManager,
+        color_space_debug_mode: ColorSpaceDebugMode,
+    ) {
         // why this extra copy?
         // Webgpu doesn't allow us to do anything with the swapchain target but read from it!
         // That means that we can never take a screenshot.
@@ -193,6 +381,13 @@ impl Screen {
         });
         render_pass.set_pipeline(pipeline_manager.get_render(&self.copy_to_swapchain_pipeline));
         render_pass.set_bind_group(0, &self.read_backbuffer_bind_group, &[]);
+        pipeline_manager.set_push_constants_render(
+            &mut render_pass,
+            &self.copy_to_swapchain_pipeline,
+            wgpu::ShaderStage::FRAGMENT,
+            0,
+            bytemuck::bytes_of(&(color_space_debug_mode as u32)),
+        );
         render_pass.draw(0..3, 0..1);
     }
 
@@ -205,10 +400,8 @@ impl Screen {
         self.screenshot_capture.wait_for_pending_screenshots(device);
     }
 
+    // Screen space passes of the scene renderer operate at render resolution.
     pub fn fill_global_uniform_buffer(&self) -> ScreenUniformBufferContent {
-        ScreenUniformBufferContent {
-            resolution: cgmath::point2(self.resolution.width as f32, self.resolution.height as f32),
-            resolution_inv: cgmath::point2(1.0 / self.resolution.width as f32, 1.0 / self.resolution.height as f32),
-        }
+        ScreenUniformBufferContent::new(self.render_resolution)
     }
 }
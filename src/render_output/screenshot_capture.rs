@@ -6,16 +6,43 @@ use std::sync::mpsc::{channel, Receiver, Sender};
 
 use crate::utils::round_to_multiple;
 
+// Format of written screenshots.
+// Png is captured from the final (tonemapped) image, Exr from the hdr backbuffer before tonemapping, so it can be graded offline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter)]
+pub enum ScreenshotFormat {
+    Png,
+    Exr,
+}
+
+impl ScreenshotFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ScreenshotFormat::Png => "png",
+            ScreenshotFormat::Exr => "exr",
+        }
+    }
+
+    fn bytes_per_pixel(&self) -> usize {
+        match self {
+            ScreenshotFormat::Png => 4, // Rgba8UnormSrgb
+            ScreenshotFormat::Exr => 8, // Rgba16Float
+        }
+    }
+}
+
 struct PendingScreenshot {
     copy_operation: Option<Pin<Box<dyn Future<Output = std::result::Result<(), wgpu::BufferAsyncError>>>>>,
     buffer: wgpu::Buffer,
     target_path: PathBuf,
+    // Downscales the image to fit into a square of this size before writing it. Png only.
+    max_size: Option<u32>,
 }
 
 impl PendingScreenshot {
     pub fn spawn_write_thread_if_ready(
         mut self,
         resolution: winit::dpi::PhysicalSize<u32>,
+        format: ScreenshotFormat,
         completion_sender: &Sender<wgpu::Buffer>,
     ) -> Option<PendingScreenshot> {
         if self.copy_operation.is_none() {
@@ -27,27 +54,66 @@ impl PendingScreenshot {
         if val.is_some() {
             let buffer = self.buffer;
             let target_path = self.target_path;
+            let max_size = self.max_size;
             let completion_sender_clone = completion_sender.clone();
 
             std::thread::spawn(move || {
                 let start_time = std::time::Instant::now();
 
-                let imgbuf = {
-                    let screenshot_buffer_slice = buffer.slice(..);
-                    let padded_buffer = screenshot_buffer_slice.get_mapped_range();
-                    let padded_row_size = ScreenshotCapture::screenshot_buffer_bytes_per_padded_row(resolution);
-                    let mut imgbuf = image::ImageBuffer::<image::Rgb<u8>, std::vec::Vec<_>>::new(resolution.width, resolution.height);
-                    for (image_row, buffer_chunk) in imgbuf.rows_mut().zip(padded_buffer.chunks(padded_row_size)) {
-                        for (image_pixel, buffer_pixel) in image_row.zip(buffer_chunk.chunks(4)) {
-                            *image_pixel = image::Rgb([buffer_pixel[0], buffer_pixel[1], buffer_pixel[2]]);
-                        }
+                let padded_row_size = ScreenshotCapture::screenshot_buffer_bytes_per_padded_row(resolution, format);
+                match format {
+                    ScreenshotFormat::Png => {
+                        let imgbuf = {
+                            let screenshot_buffer_slice = buffer.slice(..);
+                            let padded_buffer = screenshot_buffer_slice.get_mapped_range();
+                            let mut imgbuf = image::ImageBuffer::<image::Rgb<u8>, std::vec::Vec<_>>::new(resolution.width, resolution.height);
+                            for (image_row, buffer_chunk) in imgbuf.rows_mut().zip(padded_buffer.chunks(padded_row_size)) {
+                                for (image_pixel, buffer_pixel) in image_row.zip(buffer_chunk.chunks(4)) {
+                                    *image_pixel = image::Rgb([buffer_pixel[0], buffer_pixel[1], buffer_pixel[2]]);
+                                }
+                            }
+                            imgbuf
+                        };
+
+                        buffer.unmap();
+                        completion_sender_clone.send(buffer).unwrap();
+                        let imgbuf = match max_size {
+                            Some(max_size) if resolution.width.max(resolution.height) > max_size => {
+                                let scale = max_size as f32 / resolution.width.max(resolution.height) as f32;
+                                image::imageops::thumbnail(
+                                    &imgbuf,
+                                    ((resolution.width as f32 * scale) as u32).max(1),
+                                    ((resolution.height as f32 * scale) as u32).max(1),
+                                )
+                            }
+                            _ => imgbuf,
+                        };
+                        imgbuf.save(target_path.clone()).unwrap();
                     }
-                    imgbuf
-                };
+                    ScreenshotFormat::Exr => {
+                        let pixels: Vec<half::f16> = {
+                            let screenshot_buffer_slice = buffer.slice(..);
+                            let padded_buffer = screenshot_buffer_slice.get_mapped_range();
+                            let row_size = ScreenshotCapture::screenshot_buffer_bytes_per_row(resolution, format);
+                            padded_buffer
+                                .chunks(padded_row_size)
+                                .flat_map(|row| bytemuck::cast_slice::<u8, u16>(&row[..row_size]).iter())
+                                .map(|bits| half::f16::from_bits(*bits))
+                                .collect()
+                        };
 
-                buffer.unmap();
-                completion_sender_clone.send(buffer).unwrap();
-                imgbuf.save(target_path.clone()).unwrap();
+                        buffer.unmap();
+                        completion_sender_clone.send(buffer).unwrap();
+                        let width = resolution.width as usize;
+                        if let Err(err) = exr::prelude::write_rgba_file(&target_path, width, resolution.height as usize, |x, y| {
+                            let pixel = &pixels[(y * width + x) * 4..];
+                            (pixel[0], pixel[1], pixel[2], pixel[3])
+                        }) {
+                            error!("Failed to write exr screenshot to {:?}: {}", target_path, err);
+                            return;
+                        }
+                    }
+                }
 
                 info!("Wrote screenshot to {:?} (took {:?})", target_path, start_time.elapsed());
             });
@@ -57,48 +123,44 @@ impl PendingScreenshot {
     }
 }
 
+// Readback buffers are full resolution, so they are only created once screenshots are actually taken.
 pub struct ScreenshotCapture {
     unused_screenshot_buffers: Vec<wgpu::Buffer>,
+    num_screenshot_buffers: usize,
     pending_screenshots: VecDeque<PendingScreenshot>,
     screenshot_completion_receiver: Receiver<wgpu::Buffer>,
     screenshot_completion_sender: Sender<wgpu::Buffer>,
 
     resolution: winit::dpi::PhysicalSize<u32>,
+    format: ScreenshotFormat,
 }
 
 // This seems like an excessively high number, but it allows us to stream out video even if the picture format is heavier
-const NUM_SCREENSHOT_BUFFERS: usize = 10;
+const MAX_NUM_SCREENSHOT_BUFFERS: usize = 10;
 
 impl ScreenshotCapture {
-    pub fn new(device: &wgpu::Device, resolution: winit::dpi::PhysicalSize<u32>) -> Self {
-        let mut unused_screenshot_buffers = Vec::new();
-        for i in 0..NUM_SCREENSHOT_BUFFERS {
-            unused_screenshot_buffers.push(device.create_buffer(&wgpu::BufferDescriptor {
-                size: ScreenshotCapture::screenshot_buffer_bytes_per_padded_row(resolution) as u64 * resolution.height as u64,
-                usage: wgpu::BufferUsage::MAP_READ | wgpu::BufferUsage::COPY_DST,
-                label: Some(&format!("Buffer: Screenshot readback buffer {}", i)),
-                mapped_at_creation: false,
-            }));
-        }
+    pub fn new(resolution: winit::dpi::PhysicalSize<u32>, format: ScreenshotFormat) -> Self {
         let (screenshot_completion_sender, screenshot_completion_receiver) = channel();
 
         ScreenshotCapture {
-            unused_screenshot_buffers,
+            unused_screenshot_buffers: Vec::new(),
+            num_screenshot_buffers: 0,
             pending_screenshots: VecDeque::new(),
             screenshot_completion_receiver,
             screenshot_completion_sender,
 
             resolution,
+            format,
         }
     }
 
-    fn screenshot_buffer_bytes_per_row(resolution: winit::dpi::PhysicalSize<u32>) -> usize {
-        resolution.width as usize * std::mem::size_of::<u32>()
+    fn screenshot_buffer_bytes_per_row(resolution: winit::dpi::PhysicalSize<u32>, format: ScreenshotFormat) -> usize {
+        resolution.width as usize * format.bytes_per_pixel()
     }
 
-    fn screenshot_buffer_bytes_per_padded_row(resolution: winit::dpi::PhysicalSize<u32>) -> usize {
+    fn screenshot_buffer_bytes_per_padded_row(resolution: winit::dpi::PhysicalSize<u32>, format: ScreenshotFormat) -> usize {
         round_to_multiple(
-            Self::screenshot_buffer_bytes_per_row(resolution),
+            Self::screenshot_buffer_bytes_per_row(resolution, format),
             wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as usize,
         )
     }
@@ -106,7 +168,7 @@ impl ScreenshotCapture {
     pub fn process_pending_screenshots(&mut self) {
         if let Some(pending_screenshot) = self.pending_screenshots.pop_front() {
             if let Some(still_pending_screenshot) =
-                pending_screenshot.spawn_write_thread_if_ready(self.resolution, &self.screenshot_completion_sender)
+                pending_screenshot.spawn_write_thread_if_ready(self.resolution, self.format, &self.screenshot_completion_sender)
             {
                 self.pending_screenshots.push_front(still_pending_screenshot);
             }
@@ -117,7 +179,7 @@ impl ScreenshotCapture {
     }
 
     pub fn wait_for_pending_screenshots(&mut self, device: &wgpu::Device) {
-        while self.unused_screenshot_buffers.len() < NUM_SCREENSHOT_BUFFERS {
+        while self.unused_screenshot_buffers.len() < self.num_screenshot_buffers {
             device.poll(wgpu::Maintain::Poll);
             self.process_pending_screenshots();
             std::thread::yield_now();
@@ -125,17 +187,41 @@ impl ScreenshotCapture {
     }
 
     pub fn capture_screenshot(&mut self, path: &Path, backbuffer: &wgpu::Texture, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) {
+        self.capture(path, None, backbuffer, device, encoder);
+    }
+
+    // Screenshot that is downscaled to fit into a max_size square (keeping the aspect ratio).
+    pub fn capture_thumbnail(
+        &mut self,
+        path: &Path,
+        max_size: u32,
+        backbuffer: &wgpu::Texture,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+    ) {
+        self.capture(path, Some(max_size), backbuffer, device, encoder);
+    }
+
+    fn capture(&mut self, path: &Path, max_size: Option<u32>, backbuffer: &wgpu::Texture, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) {
         if self.unused_screenshot_buffers.len() == 0 {
             device.poll(wgpu::Maintain::Poll);
             self.process_pending_screenshots();
-
-            if self.unused_screenshot_buffers.len() == 0 {
-                warn!("No more unused screenshot buffers available. Waiting for GPU/writer to catch up and draining screenshot queue...");
-                while self.unused_screenshot_buffers.len() == 0 {
-                    std::thread::yield_now();
-                    device.poll(wgpu::Maintain::Poll);
-                    self.process_pending_screenshots();
-                }
+        }
+        if self.unused_screenshot_buffers.len() == 0 && self.num_screenshot_buffers < MAX_NUM_SCREENSHOT_BUFFERS {
+            self.unused_screenshot_buffers.push(device.create_buffer(&wgpu::BufferDescriptor {
+                size: ScreenshotCapture::screenshot_buffer_bytes_per_padded_row(self.resolution, self.format) as u64 * self.resolution.height as u64,
+                usage: wgpu::BufferUsage::MAP_READ | wgpu::BufferUsage::COPY_DST,
+                label: Some(&format!("Buffer: Screenshot readback buffer {}", self.num_screenshot_buffers)),
+                mapped_at_creation: false,
+            }));
+            self.num_screenshot_buffers += 1;
+        }
+        if self.unused_screenshot_buffers.len() == 0 {
+            warn!("No more unused screenshot buffers available. Waiting for GPU/writer to catch up and draining screenshot queue...");
+            while self.unused_screenshot_buffers.len() == 0 {
+                std::thread::yield_now();
+                device.poll(wgpu::Maintain::Poll);
+                self.process_pending_screenshots();
             }
         }
         let buffer = self.unused_screenshot_buffers.pop().unwrap();
@@ -150,7 +236,9 @@ impl ScreenshotCapture {
                 buffer: &buffer,
                 layout: wgpu::ImageDataLayout {
                     offset: 0,
-                    bytes_per_row: std::num::NonZeroU32::new(ScreenshotCapture::screenshot_buffer_bytes_per_padded_row(self.resolution) as u32),
+                    bytes_per_row: std::num::NonZeroU32::new(
+                        ScreenshotCapture::screenshot_buffer_bytes_per_padded_row(self.resolution, self.format) as u32
+                    ),
                     rows_per_image: None,
                 },
             },
@@ -165,6 +253,7 @@ impl ScreenshotCapture {
             copy_operation: None,
             buffer,
             target_path: path.into(),
+            max_size,
         });
     }
 }
@@ -1,19 +1,43 @@
-use super::screen::Screen;
+use super::{hdr_backbuffer::HdrBackbuffer, offscreen_target::OffscreenTarget, screen::Screen, screenshot_capture::ScreenshotFormat};
+use crate::{recording_schedule::RecordingSchedule, simulation::SolverConfig, wgpu_utils::pipelines::PipelineManager};
+use serde::Serialize;
 use std::path::{Path, PathBuf};
+use strum::IntoEnumIterator;
+
+// Written as a sidecar file next to each recording, so it can be reproduced later.
+#[derive(Serialize)]
+pub struct RecordingMetadata {
+    pub start_time: String,
+    pub scene_path: PathBuf,
+    pub scene_config: serde_json::Value,
+    pub recording_fps: f64,
+    pub simulation_steps_per_second: u64,
+    pub resolution: [u32; 2],
+    pub supersampling: u32,
+    pub format: String,
+    pub solver_velocity: SolverConfig,
+    pub solver_density: SolverConfig,
+    pub particle_rebinning_step_frequency: u32,
+    pub vorticity_confinement: f32,
+    pub schedule: Option<RecordingSchedule>,
+}
 
 pub struct ScreenshotRecorder {
     next_regular_screenshot_index: usize,
-    scheduled_screenshot: Option<PathBuf>,
+    scheduled_screenshot: Option<(PathBuf, ScreenshotFormat)>,
 
     next_recording_screenshot_index: usize,
     recording_output_dir: Option<PathBuf>,
+    recording_format: ScreenshotFormat,
+    // If present, recording frames are taken from here instead of the screen.
+    offscreen_target: Option<OffscreenTarget>,
 }
 
 impl ScreenshotRecorder {
     pub fn new() -> Self {
         let mut next_regular_screenshot_index = 0;
         for i in 1..usize::MAX {
-            if !Self::regular_screenshot_path(i).exists() {
+            if ScreenshotFormat::iter().all(|format| !Self::regular_screenshot_path(i, format).exists()) {
                 next_regular_screenshot_index = i;
                 break;
             }
@@ -25,16 +49,33 @@ impl ScreenshotRecorder {
 
             next_recording_screenshot_index: 0,
             recording_output_dir: None,
+            recording_format: ScreenshotFormat::Png,
+            offscreen_target: None,
         }
     }
 
-    fn regular_screenshot_path(index: usize) -> PathBuf {
-        PathBuf::from(format!("screenshot{}.png", index))
+    fn regular_screenshot_path(index: usize, format: ScreenshotFormat) -> PathBuf {
+        PathBuf::from(format!("screenshot{}.{}", index, format.extension()))
     }
 
-    pub fn start_next_recording(&mut self) {
+    // Starts a recording into a new folder named after scene & current time within the given output directory.
+    pub fn start_next_recording(
+        &mut self,
+        offscreen_target: Option<OffscreenTarget>,
+        format: ScreenshotFormat,
+        output_root: &Path,
+        scene_name: &str,
+    ) {
+        self.offscreen_target = offscreen_target;
+        self.recording_format = format;
+
+        let recording_name = format!("{}_{}", scene_name, chrono::Local::now().format("%Y-%m-%d_%H-%M-%S"));
         for i in 0..usize::MAX {
-            let recording_output_dir = PathBuf::from(format!("recording{}", i));
+            let recording_output_dir = if i == 0 {
+                output_root.join(&recording_name)
+            } else {
+                output_root.join(format!("{}_{}", recording_name, i))
+            };
             if !recording_output_dir.exists() {
                 self.start_recording(&recording_output_dir);
                 break;
@@ -43,37 +84,90 @@ impl ScreenshotRecorder {
     }
 
     fn start_recording(&mut self, recording_output_dir: &Path) {
-        std::fs::create_dir(&recording_output_dir).unwrap();
+        if let Err(err) = std::fs::create_dir_all(&recording_output_dir) {
+            error!("Failed to create recording output directory {:?}: {}", recording_output_dir, err);
+            return;
+        }
+        info!("Recording to {:?}", recording_output_dir);
         self.next_recording_screenshot_index = 0;
         self.recording_output_dir = Some(recording_output_dir.into());
     }
 
-    pub fn stop_recording(&mut self) {
+    pub fn write_recording_metadata(&self, metadata: &RecordingMetadata) {
+        if let Some(ref recording_output_dir) = self.recording_output_dir {
+            let path = recording_output_dir.join("recording.json");
+            let result = std::fs::File::create(&path)
+                .map_err(serde_json::Error::io)
+                .and_then(|file| serde_json::to_writer_pretty(file, metadata));
+            if let Err(err) = result {
+                error!("Failed to write recording metadata to {:?}: {}", path, err);
+            }
+        }
+    }
+
+    pub fn stop_recording(&mut self, device: &wgpu::Device) {
         self.recording_output_dir = None;
+        if let Some(mut offscreen_target) = self.offscreen_target.take() {
+            offscreen_target.wait_for_pending_screenshots(device);
+        }
     }
 
-    pub fn schedule_next_screenshot(&mut self) {
-        self.schedule_screenshot(&Self::regular_screenshot_path(self.next_regular_screenshot_index));
+    pub fn is_recording(&self) -> bool {
+        self.recording_output_dir.is_some()
+    }
+
+    // Index of the next recorded frame.
+    pub fn recording_frame_index(&self) -> usize {
+        self.next_recording_screenshot_index
+    }
+
+    pub fn offscreen_target(&self) -> Option<&OffscreenTarget> {
+        self.offscreen_target.as_ref()
+    }
+
+    pub fn schedule_next_screenshot(&mut self, format: ScreenshotFormat) {
+        self.schedule_screenshot(&Self::regular_screenshot_path(self.next_regular_screenshot_index, format), format);
         self.next_regular_screenshot_index += 1;
     }
 
-    fn schedule_screenshot(&mut self, path: &Path) {
-        self.scheduled_screenshot = Some(path.into());
+    fn schedule_screenshot(&mut self, path: &Path, format: ScreenshotFormat) {
+        self.scheduled_screenshot = Some((path.into(), format));
     }
 
-    pub fn capture_screenshot(&mut self, screen: &mut Screen, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) {
-        if let Some(ref scheduled_screenshot) = self.scheduled_screenshot {
-            screen.capture_screenshot(&scheduled_screenshot, device, encoder);
+    pub fn capture_screenshot(
+        &mut self,
+        screen: &mut Screen,
+        hdr_backbuffer: &mut HdrBackbuffer,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline_manager: &PipelineManager,
+    ) {
+        if let Some((ref path, format)) = self.scheduled_screenshot {
+            match format {
+                ScreenshotFormat::Png => screen.capture_screenshot(path, device, encoder),
+                ScreenshotFormat::Exr => hdr_backbuffer.capture_screenshot(path, device, encoder),
+            }
         }
         if let Some(ref recording_output_dir) = self.recording_output_dir {
-            screen.capture_screenshot(
-                &recording_output_dir.join(format!("screenshot{}.png", self.next_recording_screenshot_index)),
-                device,
-                encoder,
-            );
+            let path = recording_output_dir.join(format!(
+                "screenshot{}.{}",
+                self.next_recording_screenshot_index,
+                self.recording_format.extension()
+            ));
+            match (self.offscreen_target.as_mut(), self.recording_format) {
+                (Some(offscreen_target), format) => offscreen_target.capture_screenshot(&path, format, device, encoder, pipeline_manager),
+                (None, ScreenshotFormat::Png) => screen.capture_screenshot(&path, device, encoder),
+                (None, ScreenshotFormat::Exr) => hdr_backbuffer.capture_screenshot(&path, device, encoder),
+            }
             self.next_recording_screenshot_index += 1;
         }
 
         self.scheduled_screenshot = None;
     }
+
+    pub fn end_frame(&mut self) {
+        if let Some(ref mut offscreen_target) = self.offscreen_target {
+            offscreen_target.end_frame();
+        }
+    }
 }
@@ -0,0 +1,76 @@
+// In-application RenderDoc capture triggers, only functional with the renderdoc feature and when started from RenderDoc.
+//
+// Besides capturing whole frames (same as RenderDoc's own hotkey), a capture can span exactly the next simulation step,
+// which makes it a lot easier to find a misbehaving solver iteration than digging through a frame with several steps & rendering.
+pub struct RenderDocCapture {
+    #[cfg(feature = "renderdoc")]
+    api: Option<renderdoc::RenderDoc<renderdoc::V110>>,
+    step_capture_requested: bool,
+    step_capture_active: bool,
+}
+
+impl RenderDocCapture {
+    pub fn new() -> Self {
+        RenderDocCapture {
+            #[cfg(feature = "renderdoc")]
+            api: match renderdoc::RenderDoc::new() {
+                Ok(api) => {
+                    info!("RenderDoc attached, F9 captures a frame, F10 the next simulation step");
+                    Some(api)
+                }
+                Err(err) => {
+                    info!("RenderDoc captures not available: {}", err);
+                    None
+                }
+            },
+            step_capture_requested: false,
+            step_capture_active: false,
+        }
+    }
+
+    pub fn is_available(&self) -> bool {
+        #[cfg(feature = "renderdoc")]
+        return self.api.is_some();
+        #[cfg(not(feature = "renderdoc"))]
+        return false;
+    }
+
+    // Captures the next presented frame.
+    pub fn capture_next_frame(&mut self) {
+        #[cfg(feature = "renderdoc")]
+        if let Some(api) = self.api.as_mut() {
+            api.trigger_capture();
+        }
+    }
+
+    // Captures everything submitted by the next simulation step (or stage, if sub step inspection is active).
+    pub fn capture_next_simulation_step(&mut self) {
+        self.step_capture_requested = self.is_available();
+    }
+
+    // Starts a capture if one was requested, needs to be followed by end_simulation_step.
+    pub fn begin_simulation_step(&mut self) {
+        if !self.step_capture_requested {
+            return;
+        }
+        self.step_capture_requested = false;
+        #[cfg(feature = "renderdoc")]
+        if let Some(api) = self.api.as_mut() {
+            // Null pointers let RenderDoc pick the active device & window.
+            api.start_frame_capture(std::ptr::null(), std::ptr::null());
+            self.step_capture_active = true;
+        }
+    }
+
+    pub fn end_simulation_step(&mut self) {
+        if !self.step_capture_active {
+            return;
+        }
+        self.step_capture_active = false;
+        #[cfg(feature = "renderdoc")]
+        if let Some(api) = self.api.as_mut() {
+            api.end_frame_capture(std::ptr::null(), std::ptr::null());
+            info!("Captured simulation step with RenderDoc");
+        }
+    }
+}
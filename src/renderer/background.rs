@@ -1,11 +1,13 @@
 use crate::{
     render_output::hdr_backbuffer::HdrBackbuffer,
     render_output::screen::Screen,
+    scene::lights::{Light, LightConfig},
     wgpu_utils::uniformbuffer::PaddedVector3,
     wgpu_utils::{binding_builder::*, binding_glsl, pipelines::*, shader::ShaderDirectory, uniformbuffer::UniformBuffer},
 };
+use cgmath::InnerSpace;
 use serde::Deserialize;
-use std::{fs::File, io, io::BufReader, path::Path, rc::Rc};
+use std::{fs::File, io, io::BufReader, path::Path, sync::Arc};
 
 // Data describing a scene.
 #[derive(Deserialize)]
@@ -15,12 +17,77 @@ pub struct BackgroundConfig {
     pub indirect_lighting_sh: [(f32, f32, f32); 9],
 }
 
+// Lights in addition to the main directional light. (see background.glsl)
+pub const MAX_NUM_LIGHTS: usize = 8;
+
+const LIGHT_TYPE_DIRECTIONAL: u32 = 0;
+const LIGHT_TYPE_POINT: u32 = 1;
+const LIGHT_TYPE_SPOT: u32 = 2;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct LightUniformBufferContent {
+    position: cgmath::Point3<f32>,
+    light_type: u32,
+    direction: cgmath::Vector3<f32>,
+    spot_cos_outer_angle: f32,
+    radiance: cgmath::Vector3<f32>,
+    spot_cos_inner_angle: f32,
+}
+unsafe impl bytemuck::Pod for LightUniformBufferContent {}
+unsafe impl bytemuck::Zeroable for LightUniformBufferContent {}
+
+impl LightUniformBufferContent {
+    fn new(config: &LightConfig) -> Self {
+        let (light_type, position, direction, spot_cos_outer_angle, spot_cos_inner_angle) = match config.light {
+            Light::Directional { direction } => (LIGHT_TYPE_DIRECTIONAL, cgmath::point3(0.0, 0.0, 0.0), direction, -1.0, -1.0),
+            Light::Point { position } => (LIGHT_TYPE_POINT, position, cgmath::vec3(0.0, -1.0, 0.0), -1.0, -1.0),
+            Light::Spot {
+                position,
+                direction,
+                outer_angle,
+                inner_angle,
+            } => (
+                LIGHT_TYPE_SPOT,
+                position,
+                direction,
+                outer_angle.to_radians().cos(),
+                // Needs to be strictly larger for the falloff to be well defined.
+                inner_angle
+                    .min(outer_angle)
+                    .to_radians()
+                    .cos()
+                    .max(outer_angle.to_radians().cos() + 0.0001),
+            ),
+        };
+        LightUniformBufferContent {
+            position,
+            light_type,
+            direction: normalized_light_direction(direction),
+            spot_cos_outer_angle,
+            radiance: config.radiance(),
+            spot_cos_inner_angle,
+        }
+    }
+}
+
+fn normalized_light_direction(direction: cgmath::Vector3<f32>) -> cgmath::Vector3<f32> {
+    if direction.magnitude2() > 0.0 {
+        direction.normalize()
+    } else {
+        -cgmath::Vector3::unit_y()
+    }
+}
+
 #[repr(C)]
 #[derive(Clone, Copy)]
 struct LightingAndBackgroundUniformBufferContent {
     pub dir_light_direction: PaddedVector3,
     pub dir_light_radiance: PaddedVector3,
     pub indirect_lighting_sh: [((f32, f32, f32), f32); 9],
+    pub num_lights: u32,
+    pub padding: [u32; 3],
+    pub lights: [LightUniformBufferContent; MAX_NUM_LIGHTS],
 }
 unsafe impl bytemuck::Pod for LightingAndBackgroundUniformBufferContent {}
 unsafe impl bytemuck::Zeroable for LightingAndBackgroundUniformBufferContent {}
@@ -31,6 +98,17 @@ pub struct Background {
     pipeline: RenderPipelineHandle,
     bind_group_layout: wgpu::BindGroupLayout,
     bind_group: wgpu::BindGroup,
+    ubo: LightingAndBackgroundUniformBuffer,
+    ubo_content: LightingAndBackgroundUniformBufferContent,
+    // Sun as described by the background config, used if a scene doesn't have any lights.
+    default_sun: LightConfig,
+    // Lights the uniform buffer was last filled with.
+    uploaded_lights: Option<Vec<LightConfig>>,
+
+    // Light on the floor plane relative to unobstructed light (rgb) and height of the highest fluid surface above it (a).
+    // Alpha zero means there is no fluid and no caustics are applied.
+    caustics_view: wgpu::TextureView,
+    caustics_empty: bool,
 }
 
 mod cubemap_loader {
@@ -182,6 +260,10 @@ mod cubemap_loader {
 }
 
 impl Background {
+    pub const FORMAT_CAUSTICS: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+    // Covers the entire floor plane. (see background.glsl)
+    const CAUSTICS_RESOLUTION: u32 = 256;
+
     pub fn new(
         path: &Path,
         device: &wgpu::Device,
@@ -194,40 +276,66 @@ impl Background {
         let reader = BufReader::new(file);
         let config: BackgroundConfig = serde_json::from_reader(reader)?;
 
-        let ubo = LightingAndBackgroundUniformBuffer::new_with_data(
-            &device,
-            &LightingAndBackgroundUniformBufferContent {
-                dir_light_direction: config.dir_light_direction.into(),
-                dir_light_radiance: config.dir_light_radiance.into(),
-                indirect_lighting_sh: [
-                    (config.indirect_lighting_sh[0], 0.0),
-                    (config.indirect_lighting_sh[1], 0.0),
-                    (config.indirect_lighting_sh[2], 0.0),
-                    (config.indirect_lighting_sh[3], 0.0),
-                    (config.indirect_lighting_sh[4], 0.0),
-                    (config.indirect_lighting_sh[5], 0.0),
-                    (config.indirect_lighting_sh[6], 0.0),
-                    (config.indirect_lighting_sh[7], 0.0),
-                    (config.indirect_lighting_sh[8], 0.0),
-                ],
-            },
-        );
+        let ubo_content = LightingAndBackgroundUniformBufferContent {
+            dir_light_direction: config.dir_light_direction.into(),
+            dir_light_radiance: config.dir_light_radiance.into(),
+            indirect_lighting_sh: [
+                (config.indirect_lighting_sh[0], 0.0),
+                (config.indirect_lighting_sh[1], 0.0),
+                (config.indirect_lighting_sh[2], 0.0),
+                (config.indirect_lighting_sh[3], 0.0),
+                (config.indirect_lighting_sh[4], 0.0),
+                (config.indirect_lighting_sh[5], 0.0),
+                (config.indirect_lighting_sh[6], 0.0),
+                (config.indirect_lighting_sh[7], 0.0),
+                (config.indirect_lighting_sh[8], 0.0),
+            ],
+            num_lights: 0,
+            padding: [0; 3],
+            lights: [bytemuck::Zeroable::zeroed(); MAX_NUM_LIGHTS],
+        };
+        let ubo = LightingAndBackgroundUniformBuffer::new_with_data(&device, &ubo_content);
+        // Keeps the sun color in 0-1 so it can be edited as a color.
+        let max_component = config
+            .dir_light_radiance
+            .x
+            .max(config.dir_light_radiance.y)
+            .max(config.dir_light_radiance.z)
+            .max(0.0001);
 
         let cubemap_view = cubemap_loader::load(path, device, queue)?;
 
+        let caustics_view = device
+            .create_texture(&wgpu::TextureDescriptor {
+                label: Some("Texture: Caustics"),
+                size: wgpu::Extent3d {
+                    width: Self::CAUSTICS_RESOLUTION,
+                    height: Self::CAUSTICS_RESOLUTION,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: Self::FORMAT_CAUSTICS,
+                usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::SAMPLED,
+            })
+            .create_view(&Default::default());
+
         let bind_group_layout = BindGroupLayoutBuilder::new()
             .next_binding(wgpu::ShaderStage::COMPUTE | wgpu::ShaderStage::FRAGMENT, binding_glsl::uniform())
             .next_binding(wgpu::ShaderStage::COMPUTE | wgpu::ShaderStage::FRAGMENT, binding_glsl::textureCube())
+            .next_binding(wgpu::ShaderStage::COMPUTE | wgpu::ShaderStage::FRAGMENT, binding_glsl::texture2D())
             .create(device, "BindGroupLayout: Lighting & Background");
 
         let bind_group = BindGroupBuilder::new(&bind_group_layout)
             .resource(ubo.binding_resource())
             .texture(&cubemap_view)
+            .texture(&caustics_view)
             .create(device, "BindGroup: Lighting & Background");
 
         let mut render_pipeline_desc = RenderPipelineCreationDesc::new(
             "Cubemap Renderer",
-            Rc::new(device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            Arc::new(device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("Cubemap Renderer Pipeline Layout"),
                 bind_group_layouts: &[&global_bind_group_layout, &bind_group_layout.layout],
                 push_constant_ranges: &[],
@@ -249,6 +357,21 @@ impl Background {
             pipeline: pipeline_manager.create_render_pipeline(device, shader_dir, render_pipeline_desc),
             bind_group_layout: bind_group_layout.layout,
             bind_group,
+            ubo,
+            ubo_content,
+            default_sun: LightConfig {
+                enabled: true,
+                color: config.dir_light_radiance / max_component,
+                intensity: max_component,
+                light: Light::Directional {
+                    direction: config.dir_light_direction,
+                },
+            },
+            uploaded_lights: None,
+
+            caustics_view,
+            // Contents of new textures are zero.
+            caustics_empty: true,
         })
     }
 
@@ -265,4 +388,83 @@ impl Background {
     pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
         &self.bind_group_layout
     }
+
+    pub fn uniform_buffer_binding_resource(&self) -> wgpu::BindingResource {
+        self.ubo.binding_resource()
+    }
+
+    pub fn default_sun(&self) -> LightConfig {
+        self.default_sun
+    }
+
+    // Uploads the given lights if they changed since the last call. Without any lights, the sun of the background config is used.
+    //
+    // The first enabled directional light becomes the main light which also drives caustics and in-scattering of the fluid,
+    // all others are evaluated per pixel on meshes & fluid surface.
+    pub fn update_lights(&mut self, queue: &wgpu::Queue, lights: &[LightConfig]) {
+        let default_sun = [self.default_sun];
+        let lights = if lights.is_empty() { &default_sun[..] } else { lights };
+        if self.uploaded_lights.as_deref() == Some(lights) {
+            return;
+        }
+
+        let main_light = lights
+            .iter()
+            .position(|config| config.enabled && matches!(config.light, Light::Directional { .. }));
+        let (direction, radiance) = match main_light {
+            Some(index) => (lights[index].light.direction().unwrap(), lights[index].radiance()),
+            None => (self.default_sun.light.direction().unwrap(), cgmath::vec3(0.0, 0.0, 0.0)),
+        };
+        // Caustics & floor shadows project along the light onto the floor, so the main light always needs to point somewhat downwards.
+        let mut direction = normalized_light_direction(direction);
+        direction.y = direction.y.min(-0.05);
+        self.ubo_content.dir_light_direction = direction.normalize().into();
+        self.ubo_content.dir_light_radiance = radiance.into();
+
+        let mut num_lights = 0;
+        for (index, config) in lights.iter().enumerate() {
+            if !config.enabled || Some(index) == main_light {
+                continue;
+            }
+            if num_lights == MAX_NUM_LIGHTS {
+                warn!(
+                    "Only {} lights in addition to the main directional light are supported, ignoring the rest",
+                    MAX_NUM_LIGHTS
+                );
+                break;
+            }
+            self.ubo_content.lights[num_lights] = LightUniformBufferContent::new(config);
+            num_lights += 1;
+        }
+        self.ubo_content.num_lights = num_lights as u32;
+
+        self.ubo.update_content(queue, self.ubo_content);
+        self.uploaded_lights = Some(lights.to_vec());
+    }
+
+    // Caustics target for the current frame. Expected to be cleared by whoever renders to it.
+    pub fn caustics_render_target(&mut self) -> &wgpu::TextureView {
+        self.caustics_empty = false;
+        &self.caustics_view
+    }
+
+    // Removes all caustics, does nothing if there weren't any.
+    pub fn clear_caustics(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        if self.caustics_empty {
+            return;
+        }
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("clear caustics"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: &self.caustics_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+        self.caustics_empty = true;
+    }
 }
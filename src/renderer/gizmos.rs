@@ -0,0 +1,343 @@
+use super::static_line_renderer::{LineVertex, StaticLineRenderer};
+use crate::{
+    camera::Camera,
+    render_output::hdr_backbuffer::HdrBackbuffer,
+    wgpu_utils::{pipelines::PipelineManager, shader::ShaderDirectory},
+};
+use cgmath::prelude::*;
+use winit::event::{ElementState, MouseButton, WindowEvent};
+
+// What a gizmo manipulates. Identifies the gizmo across frames and tells the owner where to apply edits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GizmoTarget {
+    Gravity,
+    // Index into the lights of the scene.
+    Light(usize),
+    VoxelSlice,
+}
+
+#[derive(Clone, Copy)]
+pub enum GizmoShape {
+    // Arrow starting at origin, its tip can be dragged around the origin to change the (normalized) direction.
+    Direction {
+        origin: cgmath::Point3<f32>,
+        direction: cgmath::Vector3<f32>,
+        length: f32,
+    },
+    // Rectangle spanned by two half extents around center, the handle in its center can be dragged along the normal.
+    Plane {
+        center: cgmath::Point3<f32>,
+        normal: cgmath::Vector3<f32>,
+        half_extent_u: cgmath::Vector3<f32>,
+        half_extent_v: cgmath::Vector3<f32>,
+    },
+}
+
+impl GizmoShape {
+    fn handle_position(&self) -> cgmath::Point3<f32> {
+        match *self {
+            GizmoShape::Direction { origin, direction, length } => origin + direction * length,
+            GizmoShape::Plane { center, .. } => center,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Gizmo {
+    pub target: GizmoTarget,
+    pub shape: GizmoShape,
+    pub color: cgmath::Vector3<f32>,
+}
+
+// Result of dragging a gizmo handle, in world space.
+#[derive(Clone, Copy)]
+pub enum GizmoEdit {
+    Direction(cgmath::Vector3<f32>),
+    PlaneCenter(cgmath::Point3<f32>),
+}
+
+struct GizmoDrag {
+    target: GizmoTarget,
+    // Plane gizmos: distance along the normal between handle & cursor ray when the drag started, so the handle doesn't jump.
+    grab_offset: f32,
+}
+
+// Handles in the viewport to manipulate directional parameters (gravity, light) and planes (voxel slice) with the mouse.
+//
+// The owner passes the current set of gizmos every frame to update, which does hit testing against the cursor and returns edits while a handle is dragged.
+// Gizmos are drawn on top of everything else (no depth test), so they can't get lost inside the fluid.
+pub struct Gizmos {
+    pub enabled: bool,
+    line_renderer: StaticLineRenderer,
+
+    cursor_position: winit::dpi::PhysicalPosition<f64>,
+    hovered: Option<GizmoTarget>,
+    drag: Option<GizmoDrag>,
+    drag_requested: bool,
+}
+
+impl Gizmos {
+    const MAX_NUM_LINES: usize = 256;
+    // Size of handles relative to their distance to the camera, i.e. they have roughly the same size on screen.
+    const HANDLE_SIZE: f32 = 0.025;
+
+    pub fn new(
+        device: &wgpu::Device,
+        shader_dir: &ShaderDirectory,
+        pipeline_manager: &mut PipelineManager,
+        global_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        Gizmos {
+            enabled: false,
+            line_renderer: StaticLineRenderer::new(device, shader_dir, pipeline_manager, global_bind_group_layout, Self::MAX_NUM_LINES, None),
+
+            cursor_position: winit::dpi::PhysicalPosition::new(0.0, 0.0),
+            hovered: None,
+            drag: None,
+            drag_requested: false,
+        }
+    }
+
+    // Returns true if the event started dragging a gizmo, i.e. it shouldn't be used for anything else.
+    pub fn on_window_event(&mut self, event: &WindowEvent, gui_wants_pointer_input: bool) -> bool {
+        match event {
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor_position = *position;
+            }
+            WindowEvent::MouseInput {
+                button: MouseButton::Left,
+                state,
+                ..
+            } => match state {
+                ElementState::Pressed => {
+                    if self.enabled && !gui_wants_pointer_input && self.hovered.is_some() {
+                        self.drag_requested = true;
+                        return true;
+                    }
+                }
+                ElementState::Released => {
+                    self.drag = None;
+                    self.drag_requested = false;
+                }
+            },
+            _ => {}
+        }
+        false
+    }
+
+    pub fn is_dragging(&self) -> bool {
+        self.drag.is_some() || self.drag_requested
+    }
+
+    // Hit tests & updates the lines of all gizmos. Returns an edit if a gizmo is dragged.
+    pub fn update(
+        &mut self,
+        gizmos: &[Gizmo],
+        camera: &Camera,
+        resolution: winit::dpi::PhysicalSize<u32>,
+        queue: &wgpu::Queue,
+    ) -> Option<(GizmoTarget, GizmoEdit)> {
+        self.line_renderer.clear_lines();
+        if !self.enabled {
+            self.hovered = None;
+            self.drag = None;
+            self.drag_requested = false;
+            return None;
+        }
+
+        let (ray_origin, ray_direction) = camera.ray_through_pixel(self.cursor_position, resolution);
+        let handle_radius = |position: cgmath::Point3<f32>| position.distance(camera.position) * Self::HANDLE_SIZE;
+
+        if self.drag.is_none() {
+            // Closest handle along the cursor ray.
+            self.hovered = gizmos
+                .iter()
+                .filter_map(|gizmo| {
+                    let handle = gizmo.shape.handle_position();
+                    let distance_along_ray = (handle - ray_origin).dot(ray_direction);
+                    let distance_to_ray = (handle - (ray_origin + ray_direction * distance_along_ray)).magnitude();
+                    if distance_along_ray > 0.0 && distance_to_ray < handle_radius(handle) * 1.5 {
+                        Some((distance_along_ray, gizmo.target))
+                    } else {
+                        None
+                    }
+                })
+                .min_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap())
+                .map(|(_, target)| target);
+        }
+
+        if self.drag_requested {
+            self.drag_requested = false;
+            if let Some(gizmo) = gizmos.iter().find(|gizmo| Some(gizmo.target) == self.hovered) {
+                let grab_offset = match gizmo.shape {
+                    GizmoShape::Plane { center, normal, .. } => closest_point_on_line(center, normal, ray_origin, ray_direction).unwrap_or(0.0),
+                    GizmoShape::Direction { .. } => 0.0,
+                };
+                self.drag = Some(GizmoDrag {
+                    target: gizmo.target,
+                    grab_offset,
+                });
+            }
+        }
+
+        let mut edit = None;
+        if let Some((target, grab_offset)) = self.drag.as_ref().map(|drag| (drag.target, drag.grab_offset)) {
+            match gizmos.iter().find(|gizmo| gizmo.target == target) {
+                Some(gizmo) => {
+                    edit = match gizmo.shape {
+                        GizmoShape::Direction { origin, length, .. } => {
+                            // Point on the sphere the tip moves on, or closest point to it if the cursor is beside it.
+                            let point = ray_sphere_intersection(ray_origin, ray_direction, origin, length)
+                                .unwrap_or_else(|| ray_origin + ray_direction * (origin - ray_origin).dot(ray_direction));
+                            let direction = point - origin;
+                            if direction.magnitude2() > 0.0 {
+                                Some((target, GizmoEdit::Direction(direction.normalize())))
+                            } else {
+                                None
+                            }
+                        }
+                        GizmoShape::Plane { center, normal, .. } => closest_point_on_line(center, normal, ray_origin, ray_direction)
+                            .map(|offset| (target, GizmoEdit::PlaneCenter(center + normal * (offset - grab_offset)))),
+                    };
+                }
+                // Gizmo went away while dragging it.
+                None => self.drag = None,
+            }
+        }
+
+        let active = self.drag.as_ref().map(|drag| drag.target).or(self.hovered);
+        let mut lines = Vec::new();
+        for gizmo in gizmos.iter() {
+            let color = if Some(gizmo.target) == active {
+                gizmo.color * 0.5 + cgmath::vec3(0.5, 0.5, 0.5)
+            } else {
+                gizmo.color
+            };
+            let mut line = |start: cgmath::Point3<f32>, end: cgmath::Point3<f32>| {
+                lines.push(LineVertex::new(start, color));
+                lines.push(LineVertex::new(end, color));
+            };
+
+            let handle = gizmo.shape.handle_position();
+            let handle_size = handle_radius(handle);
+            match gizmo.shape {
+                GizmoShape::Direction { origin, direction, length } => {
+                    let (tangent, bitangent) = tangent_frame(direction);
+                    let arrow_base = handle - direction * (length * 0.15).min(handle_size * 3.0);
+                    line(origin, handle);
+                    for offset in [tangent, -tangent, bitangent, -bitangent].iter() {
+                        line(handle, arrow_base + offset * handle_size);
+                    }
+                }
+                GizmoShape::Plane {
+                    center,
+                    normal,
+                    half_extent_u,
+                    half_extent_v,
+                } => {
+                    let corners = [
+                        center - half_extent_u - half_extent_v,
+                        center + half_extent_u - half_extent_v,
+                        center + half_extent_u + half_extent_v,
+                        center - half_extent_u + half_extent_v,
+                    ];
+                    for i in 0..4 {
+                        line(corners[i], corners[(i + 1) % 4]);
+                    }
+                    line(center - normal * handle_size * 3.0, center + normal * handle_size * 3.0);
+                }
+            }
+            // Octahedron marking the handle.
+            let axes = [cgmath::Vector3::unit_x(), cgmath::Vector3::unit_y(), cgmath::Vector3::unit_z()];
+            for (i, a) in axes.iter().enumerate() {
+                for b in axes[i + 1..].iter() {
+                    for &(sign_a, sign_b) in [(1.0, 1.0), (1.0, -1.0), (-1.0, 1.0), (-1.0, -1.0)].iter() {
+                        line(handle + a * (sign_a * handle_size), handle + b * (sign_b * handle_size));
+                    }
+                }
+            }
+        }
+        self.line_renderer.add_lines(&lines, queue);
+
+        edit
+    }
+
+    pub fn draw(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline_manager: &PipelineManager,
+        global_bind_group: &wgpu::BindGroup,
+        backbuffer: &HdrBackbuffer,
+    ) {
+        if !self.enabled {
+            return;
+        }
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("gizmos"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: backbuffer.texture_view(),
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+        rpass.set_bind_group(0, global_bind_group, &[]);
+        self.line_renderer.draw(&mut rpass, pipeline_manager);
+    }
+}
+
+// Two vectors orthogonal to a normalized direction and each other.
+fn tangent_frame(direction: cgmath::Vector3<f32>) -> (cgmath::Vector3<f32>, cgmath::Vector3<f32>) {
+    let helper = if direction.y.abs() < 0.9 {
+        cgmath::Vector3::unit_y()
+    } else {
+        cgmath::Vector3::unit_x()
+    };
+    let tangent = direction.cross(helper).normalize();
+    (tangent, direction.cross(tangent))
+}
+
+// Nearest intersection of a ray with a sphere in front of the ray origin.
+fn ray_sphere_intersection(
+    ray_origin: cgmath::Point3<f32>,
+    ray_direction: cgmath::Vector3<f32>,
+    center: cgmath::Point3<f32>,
+    radius: f32,
+) -> Option<cgmath::Point3<f32>> {
+    let to_origin = ray_origin - center;
+    let b = to_origin.dot(ray_direction);
+    let discriminant = b * b - (to_origin.magnitude2() - radius * radius);
+    if discriminant < 0.0 {
+        return None;
+    }
+    let sqrt_discriminant = discriminant.sqrt();
+    let t = if -b - sqrt_discriminant > 0.0 {
+        -b - sqrt_discriminant
+    } else {
+        -b + sqrt_discriminant
+    };
+    if t > 0.0 {
+        Some(ray_origin + ray_direction * t)
+    } else {
+        None
+    }
+}
+
+// Parameter of the point on the line (origin + t * direction) that is closest to the ray. None if they are (almost) parallel.
+fn closest_point_on_line(
+    line_origin: cgmath::Point3<f32>,
+    line_direction: cgmath::Vector3<f32>,
+    ray_origin: cgmath::Point3<f32>,
+    ray_direction: cgmath::Vector3<f32>,
+) -> Option<f32> {
+    let w = line_origin - ray_origin;
+    let b = line_direction.dot(ray_direction);
+    let denominator = 1.0 - b * b;
+    if denominator < 1e-4 {
+        return None;
+    }
+    Some((b * ray_direction.dot(w) - line_direction.dot(w)) / denominator)
+}
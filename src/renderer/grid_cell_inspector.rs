@@ -0,0 +1,181 @@
+use futures::*;
+use std::pin::Pin;
+
+use super::voxel_renderer::VoxelSliceSettings;
+use crate::simulation::HybridFluid;
+
+// Volumes are copied one value after another, 4 bytes apart so that every copy offset is aligned to the texel size.
+const VALUE_STRIDE: u64 = 4;
+const MAX_NUM_VALUES: u64 = 8;
+
+enum InspectState {
+    None,
+    Requested {
+        uv: cgmath::Point2<f32>,
+    },
+    Copied {
+        cell: cgmath::Point3<u32>,
+        volumes: Vec<(&'static str, wgpu::TextureFormat)>,
+    },
+    Mapping {
+        cell: cgmath::Point3<u32>,
+        volumes: Vec<(&'static str, wgpu::TextureFormat)>,
+        mapping: Pin<Box<dyn Future<Output = Result<(), wgpu::BufferAsyncError>>>>,
+    },
+}
+
+pub struct GridCellValues {
+    pub cell: cgmath::Point3<u32>,
+    // None for volume formats that can't be read back.
+    pub values: Vec<(&'static str, Option<f32>)>,
+}
+
+// Reads back the raw simulation values of the grid cell under the cursor in the voxel slice view.
+// Like ObjectPicking, results come in asynchronously a frame or two after the request.
+pub struct GridCellInspector {
+    readback_buffer: wgpu::Buffer,
+    state: InspectState,
+    values: Option<GridCellValues>,
+}
+
+impl GridCellInspector {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Buffer: Grid cell inspector readback"),
+            size: VALUE_STRIDE * MAX_NUM_VALUES,
+            usage: wgpu::BufferUsage::MAP_READ | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        GridCellInspector {
+            readback_buffer,
+            state: InspectState::None,
+            values: None,
+        }
+    }
+
+    // Position in normalized screen coordinates (0-1, top left origin). Replaces any request that wasn't copied yet.
+    pub fn request(&mut self, uv: cgmath::Point2<f32>) {
+        match self.state {
+            InspectState::None | InspectState::Requested { .. } => self.state = InspectState::Requested { uv },
+            _ => {} // readback in flight, ignore.
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.values = None;
+    }
+
+    pub fn values(&self) -> Option<&GridCellValues> {
+        self.values.as_ref()
+    }
+
+    // Copies the values of the requested cell if the request hits the slice, clears the last result otherwise.
+    pub fn copy(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        resolution: winit::dpi::PhysicalSize<u32>,
+        grid_dimension: &cgmath::Point3<u32>,
+        settings: VoxelSliceSettings,
+        fluid: &HybridFluid,
+    ) {
+        let uv = match self.state {
+            InspectState::Requested { uv } => uv,
+            _ => return,
+        };
+        let pixel = cgmath::point2(uv.x * resolution.width as f32, uv.y * resolution.height as f32);
+        let cell = match settings.cell_at(pixel, resolution, grid_dimension) {
+            Some(cell) => cell,
+            None => {
+                self.state = InspectState::None;
+                self.values = None;
+                return;
+            }
+        };
+
+        let volumes = fluid.inspectable_volumes();
+        assert!(volumes.len() as u64 <= MAX_NUM_VALUES);
+        for (i, (_, volume, _)) in volumes.iter().enumerate() {
+            encoder.copy_texture_to_buffer(
+                wgpu::ImageCopyTexture {
+                    texture: volume,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d {
+                        x: cell.x,
+                        y: cell.y,
+                        z: cell.z,
+                    },
+                },
+                wgpu::ImageCopyBuffer {
+                    buffer: &self.readback_buffer,
+                    layout: wgpu::ImageDataLayout {
+                        offset: i as u64 * VALUE_STRIDE,
+                        bytes_per_row: None,
+                        rows_per_image: None,
+                    },
+                },
+                wgpu::Extent3d {
+                    width: 1,
+                    height: 1,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+        self.state = InspectState::Copied {
+            cell,
+            volumes: volumes.iter().map(|(name, _, format)| (*name, *format)).collect(),
+        };
+    }
+
+    // Needs to be called after the command buffer of copy was submitted.
+    pub fn update_readback(&mut self, device: &wgpu::Device) {
+        self.state = match std::mem::replace(&mut self.state, InspectState::None) {
+            InspectState::Copied { cell, volumes } => InspectState::Mapping {
+                cell,
+                volumes,
+                mapping: self.readback_buffer.slice(..).map_async(wgpu::MapMode::Read).boxed(),
+            },
+            state => state,
+        };
+        let mapping_result = match &mut self.state {
+            InspectState::Mapping { mapping, .. } => {
+                device.poll(wgpu::Maintain::Poll);
+                match mapping.now_or_never() {
+                    Some(result) => result,
+                    None => return,
+                }
+            }
+            _ => return,
+        };
+        let (cell, volumes) = match std::mem::replace(&mut self.state, InspectState::None) {
+            InspectState::Mapping { cell, volumes, .. } => (cell, volumes),
+            _ => unreachable!(),
+        };
+
+        if let Err(err) = mapping_result {
+            error!("Failed to read back grid cell values: {}", err);
+            return;
+        }
+        {
+            let mapped = self.readback_buffer.slice(..).get_mapped_range();
+            let values = volumes
+                .into_iter()
+                .enumerate()
+                .map(|(i, (name, format))| {
+                    let offset = i * VALUE_STRIDE as usize;
+                    let value = match format {
+                        wgpu::TextureFormat::R8Snorm => Some((mapped[offset] as i8 as f32 / 127.0).max(-1.0)),
+                        wgpu::TextureFormat::R32Float => Some(*bytemuck::from_bytes::<f32>(&mapped[offset..offset + 4])),
+                        _ => {
+                            warn!("Grid cell inspector can't read {:?} values of {}", format, name);
+                            None
+                        }
+                    };
+                    (name, value)
+                })
+                .collect();
+            self.values = Some(GridCellValues { cell, values });
+        }
+        self.readback_buffer.unmap();
+    }
+}
@@ -1,13 +1,30 @@
-use std::{path::PathBuf, rc::Rc};
+use std::path::PathBuf;
 
 use crate::{
+    camera::Frustum,
     render_output::{hdr_backbuffer::HdrBackbuffer, screen::Screen},
-    scene::models::SceneModels,
+    scene::models::{SceneModels, StaticMeshData},
     wgpu_utils::{pipelines::*, shader::ShaderDirectory},
 };
 
+#[derive(Clone, Copy, Debug, EnumIter, PartialEq)]
+pub enum MeshDebugMode {
+    None = 0,
+    FaceNormals = 1,
+    VertexNormals = 2,
+    UvChecker = 3,
+    TextureIndex = 4,
+}
+// Value of the debug mode push constant for the wireframe overlay, needs to be distinct from all MeshDebugMode values.
+const WIREFRAME_PUSH_CONSTANT: u32 = 5;
+
 pub struct MeshRenderer {
     render_pipeline: RenderPipelineHandle,
+    wireframe_pipeline: Option<RenderPipelineHandle>, // requires NON_FILL_POLYGON_MODE
+    outline_pipeline: RenderPipelineHandle,
+
+    pub debug_mode: MeshDebugMode,
+    pub wireframe_overlay: bool,
 }
 
 impl MeshRenderer {
@@ -18,19 +35,23 @@ impl MeshRenderer {
         global_bind_group_layout: &wgpu::BindGroupLayout,
         background_and_lighting_group_layout: &wgpu::BindGroupLayout,
     ) -> MeshRenderer {
+        let pipeline_layout = pipeline_manager.create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("MeshRenderer Pipeline Layout"),
+                bind_group_layouts: &[global_bind_group_layout, background_and_lighting_group_layout],
+                push_constant_ranges: &[wgpu::PushConstantRange {
+                    stages: wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
+                    range: 0..8,
+                }],
+            },
+        );
         let render_pipeline = pipeline_manager.create_render_pipeline(
             device,
             shader_dir,
             RenderPipelineCreationDesc {
                 label: "MeshRenderer",
-                layout: Rc::new(device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                    label: Some("MeshRenderer Pipeline Layout"),
-                    bind_group_layouts: &[global_bind_group_layout, background_and_lighting_group_layout],
-                    push_constant_ranges: &[wgpu::PushConstantRange {
-                        stages: wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
-                        range: 0..4,
-                    }],
-                })),
+                layout: pipeline_layout.clone(),
                 vertex: VertexStateCreationDesc {
                     shader_relative_path: PathBuf::from("mesh.vert"),
                     buffers: vec![SceneModels::vertex_buffer_layout()],
@@ -47,30 +68,161 @@ impl MeshRenderer {
                 },
             },
         );
-        MeshRenderer { render_pipeline }
+
+        let outline_pipeline = pipeline_manager.create_render_pipeline(
+            device,
+            shader_dir,
+            RenderPipelineCreationDesc {
+                label: "MeshRenderer Selection Outline",
+                layout: pipeline_layout.clone(),
+                vertex: VertexStateCreationDesc {
+                    shader_relative_path: PathBuf::from("mesh_outline.vert"),
+                    buffers: vec![SceneModels::vertex_buffer_layout()],
+                },
+                primitive: wgpu::PrimitiveState {
+                    cull_mode: Some(wgpu::Face::Front),
+                    ..Default::default()
+                },
+                depth_stencil: Some(depth_state::default_read_write(Screen::FORMAT_DEPTH)),
+                multisample: Default::default(),
+                fragment: FragmentStateCreationDesc {
+                    shader_relative_path: PathBuf::from("mesh_outline.frag"),
+                    targets: vec![HdrBackbuffer::FORMAT.into()],
+                },
+            },
+        );
+
+        let wireframe_pipeline = if device.features().contains(wgpu::Features::NON_FILL_POLYGON_MODE) {
+            Some(pipeline_manager.create_render_pipeline(
+                device,
+                shader_dir,
+                RenderPipelineCreationDesc {
+                    label: "MeshRenderer Wireframe",
+                    layout: pipeline_layout,
+                    vertex: VertexStateCreationDesc {
+                        shader_relative_path: PathBuf::from("mesh.vert"),
+                        buffers: vec![SceneModels::vertex_buffer_layout()],
+                    },
+                    primitive: wgpu::PrimitiveState {
+                        polygon_mode: wgpu::PolygonMode::Line,
+                        ..Default::default()
+                    },
+                    // Pulled towards the camera so lines don't fight with the mesh underneath.
+                    depth_stencil: Some(wgpu::DepthStencilState {
+                        depth_write_enabled: false,
+                        bias: wgpu::DepthBiasState {
+                            constant: -2,
+                            slope_scale: -1.0,
+                            clamp: 0.0,
+                        },
+                        ..depth_state::default_read_write(Screen::FORMAT_DEPTH)
+                    }),
+                    multisample: Default::default(),
+                    fragment: FragmentStateCreationDesc {
+                        shader_relative_path: PathBuf::from("mesh.frag"),
+                        targets: vec![HdrBackbuffer::FORMAT.into()],
+                    },
+                },
+            ))
+        } else {
+            warn!("NON_FILL_POLYGON_MODE not supported, mesh wireframe overlay won't be available");
+            None
+        };
+
+        MeshRenderer {
+            render_pipeline,
+            wireframe_pipeline,
+            outline_pipeline,
+            debug_mode: MeshDebugMode::None,
+            wireframe_overlay: false,
+        }
+    }
+
+    pub fn wireframe_supported(&self) -> bool {
+        self.wireframe_pipeline.is_some()
     }
 
     // Render pass is assumed to have the global bindings set
+    // Meshes outside of the frustum are skipped if one is passed.
     pub fn draw<'a>(
         &'a self,
         rpass: &mut wgpu::RenderPass<'a>,
         pipeline_manager: &'a PipelineManager,
         background_and_lighting_bind_group: &'a wgpu::BindGroup,
         scene_models: &'a SceneModels,
+        selected_object: Option<usize>,
+        frustum: Option<&Frustum>,
     ) {
+        let visible_meshes: Vec<usize> = (0..scene_models.meshes.len())
+            .filter(|&i| frustum.map_or(true, |frustum| scene_models.is_mesh_visible(i, frustum)))
+            .collect();
+
         rpass.set_pipeline(pipeline_manager.get_render(&self.render_pipeline));
         rpass.set_bind_group(1, background_and_lighting_bind_group, &[]);
 
         rpass.set_index_buffer(scene_models.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
         rpass.set_vertex_buffer(0, scene_models.vertex_buffer.slice(..));
 
-        for (i, mesh) in scene_models.meshes.iter().enumerate() {
-            rpass.set_push_constants(
-                wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
-                0,
-                bytemuck::cast_slice(&[i as u32]),
-            );
-            rpass.draw_indexed(mesh.index_buffer_range.clone(), mesh.vertex_buffer_range.start as i32, 0..1);
+        Self::draw_meshes(
+            rpass,
+            pipeline_manager,
+            &self.render_pipeline,
+            scene_models,
+            &visible_meshes,
+            self.debug_mode as u32,
+        );
+
+        if self.wireframe_overlay {
+            if let Some(wireframe_pipeline) = &self.wireframe_pipeline {
+                rpass.set_pipeline(pipeline_manager.get_render(wireframe_pipeline));
+                Self::draw_meshes(
+                    rpass,
+                    pipeline_manager,
+                    wireframe_pipeline,
+                    scene_models,
+                    &visible_meshes,
+                    WIREFRAME_PUSH_CONSTANT,
+                );
+            }
         }
+
+        if let Some(selected_object) = selected_object {
+            rpass.set_pipeline(pipeline_manager.get_render(&self.outline_pipeline));
+            for &i in visible_meshes.iter().filter(|&&i| scene_models.meshes[i].object_index == selected_object) {
+                Self::draw_mesh(rpass, pipeline_manager, &self.outline_pipeline, i, &scene_models.meshes[i], 0);
+            }
+        }
+    }
+
+    fn draw_meshes<'a>(
+        rpass: &mut wgpu::RenderPass<'a>,
+        pipeline_manager: &'a PipelineManager,
+        pipeline: &RenderPipelineHandle,
+        scene_models: &SceneModels,
+        mesh_indices: &[usize],
+        debug_mode: u32,
+    ) {
+        for &i in mesh_indices {
+            Self::draw_mesh(rpass, pipeline_manager, pipeline, i, &scene_models.meshes[i], debug_mode);
+        }
+    }
+
+    // Expects index & vertex buffer and the given pipeline to be set. Also used for the object picking pass.
+    pub fn draw_mesh<'a>(
+        rpass: &mut wgpu::RenderPass<'a>,
+        pipeline_manager: &'a PipelineManager,
+        pipeline: &RenderPipelineHandle,
+        mesh_index: usize,
+        mesh: &StaticMeshData,
+        debug_mode: u32,
+    ) {
+        pipeline_manager.set_push_constants_render(
+            rpass,
+            pipeline,
+            wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
+            0,
+            bytemuck::cast_slice(&[mesh_index as u32, debug_mode]),
+        );
+        rpass.draw_indexed(mesh.index_buffer_range.clone(), mesh.vertex_buffer_range.start as i32, 0..1);
     }
 }
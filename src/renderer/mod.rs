@@ -1,13 +1,21 @@
 mod background;
+mod gizmos;
+mod grid_cell_inspector;
 mod mesh_renderer;
+mod object_picking;
 mod particle_renderer;
 mod scene_renderer;
 mod screenspace_fluid;
+mod smoke_renderer;
 mod static_line_renderer;
 mod volume_renderer;
 mod voxel_renderer;
 
+pub use mesh_renderer::MeshDebugMode;
+pub use object_picking::ObjectPicking;
+pub use particle_renderer::ParticleLodMode;
 pub use scene_renderer::FluidRenderingMode;
 pub use scene_renderer::GlobalRenderSettingsUniformBufferContent;
 pub use scene_renderer::SceneRenderer;
+pub use screenspace_fluid::{ScreenSpaceFluidDepthFilter, ScreenSpaceFluidQuality, ScreenSpaceFluidResolution, ScreenSpaceFluidSettings};
 pub use volume_renderer::VolumeVisualizationMode;
@@ -0,0 +1,250 @@
+use futures::*;
+use std::{path::PathBuf, pin::Pin};
+
+use super::mesh_renderer::MeshRenderer;
+use crate::{
+    render_output::screen::Screen,
+    scene::models::SceneModels,
+    wgpu_utils::{pipelines::*, shader::ShaderDirectory},
+};
+
+const FORMAT_ID: wgpu::TextureFormat = wgpu::TextureFormat::R32Uint;
+
+enum PickState {
+    None,
+    Requested { uv: cgmath::Point2<f32> },
+    Copied,
+    Mapping(Pin<Box<dyn Future<Output = Result<(), wgpu::BufferAsyncError>>>>),
+}
+
+struct IdTarget {
+    resolution: winit::dpi::PhysicalSize<u32>,
+    id_view: wgpu::TextureView,
+    id_texture: wgpu::Texture,
+    depth_view: wgpu::TextureView,
+}
+
+// Finds out which static object is under a given pixel by rendering mesh ids into an extra target on demand.
+// Results come in asynchronously, usually a frame or two after the request.
+pub struct ObjectPicking {
+    pipeline: RenderPipelineHandle,
+    target: Option<IdTarget>, // created on first pick
+    readback_buffer: wgpu::Buffer,
+    state: PickState,
+    selected_object: Option<usize>,
+}
+
+impl ObjectPicking {
+    pub fn new(
+        device: &wgpu::Device,
+        shader_dir: &ShaderDirectory,
+        pipeline_manager: &mut PipelineManager,
+        global_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let pipeline_layout = pipeline_manager.create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Object Picking Pipeline Layout"),
+                bind_group_layouts: &[global_bind_group_layout],
+                push_constant_ranges: &[wgpu::PushConstantRange {
+                    stages: wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
+                    range: 0..8,
+                }],
+            },
+        );
+        let pipeline = pipeline_manager.create_render_pipeline(
+            device,
+            shader_dir,
+            RenderPipelineCreationDesc {
+                label: "Object Picking",
+                layout: pipeline_layout,
+                vertex: VertexStateCreationDesc {
+                    shader_relative_path: PathBuf::from("mesh.vert"),
+                    buffers: vec![SceneModels::vertex_buffer_layout()],
+                },
+                primitive: wgpu::PrimitiveState {
+                    cull_mode: Some(wgpu::Face::Back),
+                    ..Default::default()
+                },
+                depth_stencil: Some(depth_state::default_read_write(Screen::FORMAT_DEPTH)),
+                multisample: Default::default(),
+                fragment: FragmentStateCreationDesc {
+                    shader_relative_path: PathBuf::from("mesh_id.frag"),
+                    targets: vec![FORMAT_ID.into()],
+                },
+            },
+        );
+
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Buffer: Object picking readback"),
+            size: std::mem::size_of::<u32>() as u64,
+            usage: wgpu::BufferUsage::MAP_READ | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        ObjectPicking {
+            pipeline,
+            target: None,
+            readback_buffer,
+            state: PickState::None,
+            selected_object: None,
+        }
+    }
+
+    fn create_target(device: &wgpu::Device, resolution: winit::dpi::PhysicalSize<u32>) -> IdTarget {
+        let size = wgpu::Extent3d {
+            width: resolution.width,
+            height: resolution.height,
+            depth_or_array_layers: 1,
+        };
+        let id_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Object Picking Ids"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: FORMAT_ID,
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::COPY_SRC,
+        });
+        let depth_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Object Picking Depth"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Screen::FORMAT_DEPTH,
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT,
+        });
+        IdTarget {
+            resolution,
+            id_view: id_texture.create_view(&Default::default()),
+            id_texture,
+            depth_view: depth_texture.create_view(&Default::default()),
+        }
+    }
+
+    // Position in normalized screen coordinates (0-1, top left origin). Replaces any pick that wasn't rendered yet.
+    pub fn request_pick(&mut self, uv: cgmath::Point2<f32>) {
+        match self.state {
+            PickState::None | PickState::Requested { .. } => self.state = PickState::Requested { uv },
+            _ => {} // readback in flight, ignore.
+        }
+    }
+
+    pub fn selected_object(&self) -> Option<usize> {
+        self.selected_object
+    }
+
+    pub fn clear_selection(&mut self) {
+        self.selected_object = None;
+    }
+
+    // Renders the id pass if a pick was requested. Assumes the global uniform buffer is already updated for this frame.
+    pub fn draw(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline_manager: &PipelineManager,
+        global_bind_group: &wgpu::BindGroup,
+        scene_models: &SceneModels,
+        resolution: winit::dpi::PhysicalSize<u32>,
+    ) {
+        let uv = match self.state {
+            PickState::Requested { uv } => uv,
+            _ => return,
+        };
+        if self.target.as_ref().map_or(true, |target| target.resolution != resolution) {
+            self.target = Some(Self::create_target(device, resolution));
+        }
+        let target = self.target.as_ref().unwrap();
+
+        {
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("object picking"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &target.id_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &target.depth_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: false,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+            rpass.set_pipeline(pipeline_manager.get_render(&self.pipeline));
+            rpass.set_bind_group(0, global_bind_group, &[]);
+            rpass.set_index_buffer(scene_models.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+            rpass.set_vertex_buffer(0, scene_models.vertex_buffer.slice(..));
+            for (i, mesh) in scene_models.meshes.iter().enumerate() {
+                MeshRenderer::draw_mesh(&mut rpass, pipeline_manager, &self.pipeline, i, mesh, 0);
+            }
+        }
+
+        let pixel_x = ((uv.x * resolution.width as f32) as u32).min(resolution.width - 1);
+        let pixel_y = ((uv.y * resolution.height as f32) as u32).min(resolution.height - 1);
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &target.id_texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: pixel_x,
+                    y: pixel_y,
+                    z: 0,
+                },
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &self.readback_buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: None,
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.state = PickState::Copied;
+    }
+
+    // Needs to be called after the command buffer of draw was submitted.
+    // Mesh ids are translated to objects, so the scene models need to be the same as during draw.
+    pub fn update_readback(&mut self, device: &wgpu::Device, scene_models: &SceneModels) {
+        if let PickState::Copied = self.state {
+            self.state = PickState::Mapping(self.readback_buffer.slice(..).map_async(wgpu::MapMode::Read).boxed());
+        }
+        let mapping_result = match &mut self.state {
+            PickState::Mapping(mapping) => {
+                device.poll(wgpu::Maintain::Poll);
+                match mapping.now_or_never() {
+                    Some(result) => result,
+                    None => return,
+                }
+            }
+            _ => return,
+        };
+        self.state = PickState::None;
+
+        if let Err(err) = mapping_result {
+            error!("Failed to read back object picking result: {}", err);
+            return;
+        }
+        let mesh_id = *bytemuck::from_bytes::<u32>(&self.readback_buffer.slice(..).get_mapped_range());
+        self.readback_buffer.unmap();
+
+        // 0 means nothing, otherwise mesh index + 1
+        self.selected_object = match mesh_id {
+            0 => None,
+            mesh_id => scene_models.meshes.get(mesh_id as usize - 1).map(|mesh| mesh.object_index),
+        };
+    }
+}
@@ -1,21 +1,96 @@
-use crate::wgpu_utils::pipelines::*;
+use crate::wgpu_utils::{
+    binding_builder::{BindGroupBuilder, BindGroupLayoutBuilder, BindGroupLayoutWithDesc},
+    binding_glsl,
+    pipelines::*,
+    uniformbuffer::UniformBuffer,
+};
 use crate::{
     render_output::{hdr_backbuffer::HdrBackbuffer, screen::Screen},
+    scene::FluidSymmetry,
     simulation::HybridFluid,
     wgpu_utils::shader::*,
 };
-use std::{path::Path, rc::Rc};
+use futures::*;
+use std::{path::Path, pin::Pin, sync::Arc};
+
+#[derive(Clone, Copy, Debug, EnumIter, PartialEq)]
+pub enum ParticleLodMode {
+    Off,
+    On,
+    // Enabled above AUTOMATIC_LOD_MIN_NUM_PARTICLES
+    Automatic,
+}
+
+#[derive(Clone, Copy)]
+pub struct ParticleLodSettings {
+    pub frustum_culling: bool,
+    pub mode: ParticleLodMode,
+    // Distance to the camera in world space from which on only every second particle is drawn, every fourth from twice the distance and so on.
+    pub lod_distance: f32,
+}
+
+impl Default for ParticleLodSettings {
+    fn default() -> Self {
+        ParticleLodSettings {
+            frustum_culling: true,
+            mode: ParticleLodMode::Automatic,
+            lod_distance: 2.0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct ParticleCullingUniformBufferContent {
+    lod_distance: f32,
+    frustum_culling: u32,
+    num_mirror_instances: u32,
+    padding: u32,
+}
+unsafe impl bytemuck::Pod for ParticleCullingUniformBufferContent {}
+unsafe impl bytemuck::Zeroable for ParticleCullingUniformBufferContent {}
+
+enum ReadbackState {
+    None,
+    Copied,
+    Mapping(Pin<Box<dyn Future<Output = Result<(), wgpu::BufferAsyncError>>>>),
+}
+
+struct VisibleParticles {
+    buffer: wgpu::Buffer,
+    capacity: u32,
+    bind_group_rendering: wgpu::BindGroup,
+}
 
 pub struct ParticleRenderer {
     render_pipeline: RenderPipelineHandle,
+    culling_pipeline: ComputePipelineHandle,
+    group_layout_culling: BindGroupLayoutWithDesc,
+    group_layout_rendering: BindGroupLayoutWithDesc,
+    culling_settings: UniformBuffer<ParticleCullingUniformBufferContent>,
+    draw_indirect_buffer: wgpu::Buffer,
+    visible_particles: Option<VisibleParticles>, // (re)created on cull for each max particle count
+
+    readback_buffer: wgpu::Buffer,
+    readback_state: ReadbackState,
+    rendered_fraction: Option<f32>,
+
+    pub lod_settings: ParticleLodSettings,
 }
 
 pub enum ParticleRendererMode {
     Velocity,
     Index,
+    Phase,
+    Age,
+    Dye,
 }
 
 impl ParticleRenderer {
+    const AUTOMATIC_LOD_MIN_NUM_PARTICLES: u32 = 4 * 1024 * 1024;
+    // Particle indices in the visible particle list share their bits with the lod level.
+    const MAX_NUM_PARTICLES: u32 = 1 << 28;
+
     pub fn new(
         device: &wgpu::Device,
         shader_dir: &ShaderDirectory,
@@ -23,16 +98,44 @@ impl ParticleRenderer {
         global_bind_group_layout: &wgpu::BindGroupLayout,
         fluid_renderer_group_layout: &wgpu::BindGroupLayout,
     ) -> ParticleRenderer {
+        let group_layout_culling = BindGroupLayoutBuilder::new()
+            .next_binding_compute(binding_glsl::buffer(true)) // particles, position llindex
+            .next_binding_compute(binding_glsl::buffer(false)) // visible particles
+            .next_binding_compute(binding_glsl::buffer(false)) // draw indirect args
+            .next_binding_compute(binding_glsl::uniform()) // culling settings
+            .next_binding_compute(binding_glsl::buffer(true)) // particle count
+            .create(device, "BindGroupLayout: Particle culling");
+        let group_layout_rendering = BindGroupLayoutBuilder::new()
+            .next_binding_vertex(binding_glsl::buffer(true)) // visible particles
+            .create(device, "BindGroupLayout: Particle rendering");
+
+        let culling_pipeline = pipeline_manager.create_compute_pipeline(
+            device,
+            shader_dir,
+            ComputePipelineCreationDesc::new(
+                "ParticleRenderer: Culling",
+                Arc::new(device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("ParticleRenderer Culling Pipeline Layout"),
+                    bind_group_layouts: &[&global_bind_group_layout, &group_layout_culling.layout],
+                    push_constant_ranges: &[],
+                })),
+                Path::new("particle_culling.comp"),
+            ),
+        );
+
         let mut desc = RenderPipelineCreationDesc::new(
             "ParticleRenderer: Render particles",
-            Rc::new(device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("ParticleRenderer Pipeline Layout"),
-                bind_group_layouts: &[&global_bind_group_layout, &fluid_renderer_group_layout],
-                push_constant_ranges: &[wgpu::PushConstantRange {
-                    stages: wgpu::ShaderStage::VERTEX,
-                    range: 0..4,
-                }],
-            })),
+            pipeline_manager.create_pipeline_layout(
+                device,
+                &wgpu::PipelineLayoutDescriptor {
+                    label: Some("ParticleRenderer Pipeline Layout"),
+                    bind_group_layouts: &[&global_bind_group_layout, &fluid_renderer_group_layout, &group_layout_rendering.layout],
+                    push_constant_ranges: &[wgpu::PushConstantRange {
+                        stages: wgpu::ShaderStage::VERTEX,
+                        range: 0..8,
+                    }],
+                },
+            ),
             Path::new("fluid_particles.vert"),
             Path::new("sphere_particles.frag"),
             HdrBackbuffer::FORMAT,
@@ -40,7 +143,129 @@ impl ParticleRenderer {
         );
         desc.primitive.topology = wgpu::PrimitiveTopology::TriangleStrip;
         let render_pipeline = pipeline_manager.create_render_pipeline(device, shader_dir, desc);
-        ParticleRenderer { render_pipeline }
+
+        // Instance count is filled in by the culling shader, the rest is constant.
+        let draw_indirect_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Buffer: Particle draw indirect"),
+            size: 4 * std::mem::size_of::<u32>() as u64,
+            usage: wgpu::BufferUsage::INDIRECT | wgpu::BufferUsage::STORAGE | wgpu::BufferUsage::COPY_DST | wgpu::BufferUsage::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Buffer: Rendered particle count readback"),
+            size: 2 * std::mem::size_of::<u32>() as u64, // rendered particles, total particles
+            usage: wgpu::BufferUsage::MAP_READ | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        ParticleRenderer {
+            render_pipeline,
+            culling_pipeline,
+            group_layout_culling,
+            group_layout_rendering,
+            culling_settings: UniformBuffer::new(device),
+            draw_indirect_buffer,
+            visible_particles: None,
+
+            readback_buffer,
+            readback_state: ReadbackState::None,
+            rendered_fraction: None,
+
+            lod_settings: Default::default(),
+        }
+    }
+
+    pub fn is_lod_active(&self, num_particles: u32) -> bool {
+        match self.lod_settings.mode {
+            ParticleLodMode::Off => false,
+            ParticleLodMode::On => true,
+            ParticleLodMode::Automatic => num_particles >= Self::AUTOMATIC_LOD_MIN_NUM_PARTICLES,
+        }
+    }
+
+    // Fraction of particles that was rendered in a recent frame (for each mirror instance).
+    pub fn rendered_fraction(&self) -> Option<f32> {
+        self.rendered_fraction
+    }
+
+    // Determines which particles to draw. Needs to run before draw.
+    pub fn cull(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline_manager: &PipelineManager,
+        global_bind_group: &wgpu::BindGroup,
+        fluid: &HybridFluid,
+        symmetry: FluidSymmetry,
+    ) {
+        // Particle count is only known on the gpu, so we need to have space for all particles the fluid may ever have.
+        let capacity = fluid.max_num_particles().min(Self::MAX_NUM_PARTICLES);
+        if self.visible_particles.as_ref().map_or(true, |visible| visible.capacity != capacity) {
+            let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Buffer: Visible particles"),
+                size: capacity.max(1) as u64 * std::mem::size_of::<u32>() as u64,
+                usage: wgpu::BufferUsage::STORAGE,
+                mapped_at_creation: false,
+            });
+            let bind_group_rendering = BindGroupBuilder::new(&self.group_layout_rendering)
+                .resource(buffer.as_entire_binding())
+                .create(device, "BindGroup: Particle rendering");
+            self.visible_particles = Some(VisibleParticles {
+                buffer,
+                capacity,
+                bind_group_rendering,
+            });
+        }
+        let visible_particles = self.visible_particles.as_ref().unwrap();
+
+        self.culling_settings.update_content(
+            queue,
+            ParticleCullingUniformBufferContent {
+                lod_distance: if self.is_lod_active(fluid.num_particles()) {
+                    self.lod_settings.lod_distance
+                } else {
+                    0.0
+                },
+                frustum_culling: self.lod_settings.frustum_culling as u32,
+                num_mirror_instances: symmetry.num_instances(),
+                padding: 0,
+            },
+        );
+        // vertex count, instance count, first vertex, first instance
+        queue.write_buffer(&self.draw_indirect_buffer, 0, bytemuck::cast_slice(&[4u32, 0, 0, 0]));
+
+        // Fluid (and with it the particle buffer) may be swapped out any time, so we can't keep this around.
+        let bind_group_culling = BindGroupBuilder::new(&self.group_layout_culling)
+            .resource(fluid.particles_position_buffer().as_entire_binding())
+            .resource(visible_particles.buffer.as_entire_binding())
+            .resource(self.draw_indirect_buffer.as_entire_binding())
+            .resource(self.culling_settings.binding_resource())
+            .resource(fluid.particle_count_buffer().as_entire_binding())
+            .create(device, "BindGroup: Particle culling");
+
+        {
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("particle culling"),
+            });
+            cpass.set_pipeline(pipeline_manager.get_compute(&self.culling_pipeline));
+            cpass.set_bind_group(0, global_bind_group, &[]);
+            cpass.set_bind_group(1, &bind_group_culling, &[]);
+            cpass.dispatch_indirect(fluid.particle_count_buffer(), HybridFluid::PARTICLE_COUNT_BUFFER_DISPATCH_OFFSET);
+        }
+
+        if let ReadbackState::None = self.readback_state {
+            let count_size = std::mem::size_of::<u32>() as u64;
+            encoder.copy_buffer_to_buffer(&self.draw_indirect_buffer, count_size, &self.readback_buffer, 0, count_size);
+            encoder.copy_buffer_to_buffer(
+                fluid.particle_count_buffer(),
+                HybridFluid::PARTICLE_COUNT_BUFFER_NUM_PARTICLES_OFFSET,
+                &self.readback_buffer,
+                count_size,
+                count_size,
+            );
+            self.readback_state = ReadbackState::Copied;
+        }
     }
 
     pub fn draw<'a>(
@@ -48,11 +273,64 @@ impl ParticleRenderer {
         rpass: &mut wgpu::RenderPass<'a>,
         pipeline_manager: &'a PipelineManager,
         fluid: &'a HybridFluid,
+        symmetry: FluidSymmetry,
         mode: ParticleRendererMode,
     ) {
+        let visible_particles = match &self.visible_particles {
+            Some(visible_particles) => visible_particles,
+            None => return, // never culled
+        };
+
         rpass.set_pipeline(pipeline_manager.get_render(&self.render_pipeline));
         rpass.set_bind_group(1, fluid.bind_group_renderer(), &[]);
-        rpass.set_push_constants(wgpu::ShaderStage::VERTEX, 0, bytemuck::cast_slice(&[mode as u32]));
-        rpass.draw(0..4, 0..fluid.num_particles());
+        rpass.set_bind_group(2, &visible_particles.bind_group_rendering, &[]);
+        pipeline_manager.set_push_constants_render(
+            rpass,
+            &self.render_pipeline,
+            wgpu::ShaderStage::VERTEX,
+            0,
+            bytemuck::cast_slice(&[mode as u32]),
+        );
+        for mirror_index in 0..symmetry.num_instances() {
+            pipeline_manager.set_push_constants_render(
+                rpass,
+                &self.render_pipeline,
+                wgpu::ShaderStage::VERTEX,
+                4,
+                bytemuck::cast_slice(&[mirror_index]),
+            );
+            rpass.draw_indirect(&self.draw_indirect_buffer, 0);
+        }
+    }
+
+    // Needs to be called after the command buffer of cull was submitted.
+    pub fn update_readback(&mut self, device: &wgpu::Device) {
+        if let ReadbackState::Copied = self.readback_state {
+            self.readback_state = ReadbackState::Mapping(self.readback_buffer.slice(..).map_async(wgpu::MapMode::Read).boxed());
+        }
+        let mapping_result = match &mut self.readback_state {
+            ReadbackState::Mapping(mapping) => {
+                device.poll(wgpu::Maintain::Poll);
+                match mapping.now_or_never() {
+                    Some(result) => result,
+                    None => return,
+                }
+            }
+            _ => return,
+        };
+        self.readback_state = ReadbackState::None;
+
+        if let Err(err) = mapping_result {
+            error!("Failed to read back rendered particle count: {}", err);
+            return;
+        }
+        let [num_rendered_particles, num_particles] = *bytemuck::from_bytes::<[u32; 2]>(&self.readback_buffer.slice(..).get_mapped_range());
+        self.readback_buffer.unmap();
+
+        self.rendered_fraction = if num_particles > 0 {
+            Some(num_rendered_particles as f32 / num_particles as f32)
+        } else {
+            None
+        };
     }
 }
@@ -1,20 +1,28 @@
 use super::{
     background::Background,
+    gizmos::{Gizmo, GizmoEdit, GizmoShape, GizmoTarget, Gizmos},
+    grid_cell_inspector::GridCellInspector,
     mesh_renderer::MeshRenderer,
+    object_picking::ObjectPicking,
     particle_renderer::ParticleRenderer,
     screenspace_fluid::ScreenSpaceFluid,
+    smoke_renderer::SmokeRenderer,
     static_line_renderer::{LineVertex, StaticLineRenderer},
     volume_renderer::{VolumeRenderer, VolumeVisualizationMode},
-    voxel_renderer::VoxelRenderer,
+    voxel_renderer::{VoxelRenderer, VoxelSliceSettings},
 };
 use crate::{
-    render_output::hdr_backbuffer::HdrBackbuffer,
+    camera::{Camera, Frustum},
+    render_output::{hdr_backbuffer::HdrBackbuffer, screen::Screen},
     renderer::particle_renderer::ParticleRendererMode,
-    scene::Scene,
-    simulation::HybridFluid,
-    wgpu_utils::{pipelines::PipelineManager, shader::ShaderDirectory},
+    scene::{
+        lights::{Light, LightConfig},
+        FluidConfig, Scene,
+    },
+    simulation::{HybridFluid, MAX_NUM_FLUID_PHASES},
+    wgpu_utils::{pipelines::PipelineManager, shader::ShaderDirectory, transient_texture_pool::TransientTexturePool},
 };
-use cgmath::EuclideanSpace;
+use cgmath::{EuclideanSpace, InnerSpace};
 use std::path::Path;
 use wgpu_profiler::{wgpu_profiler, GpuProfiler};
 
@@ -24,6 +32,9 @@ pub enum FluidRenderingMode {
     ScreenSpaceFluid,
     ParticlesVelocity,
     ParticlesIndex,
+    ParticlesPhase,
+    ParticlesAge,
+    ParticlesDye,
 }
 
 #[repr(C)]
@@ -35,18 +46,53 @@ pub struct GlobalRenderSettingsUniformBufferContent {
     velocity_visualization_scale: f32,
     fluid_grid_resolution: cgmath::Point3<u32>,
     fluid_particle_radius: f32,
+    fluid_symmetry: u32,
+    age_visualization_range: f32,
+    padding: cgmath::Vector2<u32>,
+    fluid_phase_absorption: [cgmath::Vector4<f32>; MAX_NUM_FLUID_PHASES],
+}
+
+impl GlobalRenderSettingsUniformBufferContent {
+    pub fn new(fluid_config: &FluidConfig, particle_radius_factor: f32, velocity_visualization_scale: f32, age_visualization_range: f32) -> Self {
+        let fluid_particle_radius =
+            fluid_config.grid_to_world_scale / (fluid_config.particles_per_cell.max(1) as f32).powf(1.0 / 3.0) * particle_radius_factor;
+
+        GlobalRenderSettingsUniformBufferContent {
+            fluid_min: fluid_config.world_position,
+            fluid_max: fluid_config.world_position + fluid_config.grid_dimension.cast::<f32>().unwrap().to_vec() * fluid_config.grid_to_world_scale,
+            fluid_grid_to_world_scale: fluid_config.grid_to_world_scale,
+            velocity_visualization_scale,
+            fluid_particle_radius,
+            fluid_grid_resolution: fluid_config.grid_dimension,
+            fluid_symmetry: fluid_config.symmetry as u32,
+            age_visualization_range,
+            padding: cgmath::vec2(0, 0),
+            fluid_phase_absorption: {
+                let mut absorption = [cgmath::vec4(0.0, 0.0, 0.0, 0.0); MAX_NUM_FLUID_PHASES];
+                for (absorption, phase) in absorption.iter_mut().zip(fluid_config.phases().iter()) {
+                    *absorption = phase.absorption.extend(0.0);
+                }
+                absorption
+            },
+        }
+    }
 }
 
 // What renders the scene (so everything except ui!)
 // Maintains both configuration and necessary data structures, but doesn't shut down when a scene is swapped out.
 pub struct SceneRenderer {
-    particle_renderer: ParticleRenderer,
-    screenspace_fluid: ScreenSpaceFluid,
+    pub particle_renderer: ParticleRenderer,
+    pub screenspace_fluid: ScreenSpaceFluid,
+    smoke_renderer: SmokeRenderer,
     volume_renderer: VolumeRenderer,
     voxel_renderer: VoxelRenderer,
     bounds_line_renderer: StaticLineRenderer,
     pub mesh_renderer: MeshRenderer,
+    pub object_picking: ObjectPicking,
+    pub grid_cell_inspector: GridCellInspector,
+    pub gizmos: Gizmos,
     background_and_lighting: Background,
+    transient_textures: TransientTexturePool,
 
     pub fluid_rendering_mode: FluidRenderingMode,
     pub volume_visualization: VolumeVisualizationMode,
@@ -54,19 +100,28 @@ pub struct SceneRenderer {
     pub enable_box_lines: bool,
     pub enable_mesh_rendering: bool,
     pub enable_voxel_visualization: bool,
+    pub enable_frustum_culling: bool, // for meshes & voxel visualization
+    pub voxel_slice: VoxelSliceSettings,
     pub velocity_visualization_scale: f32,
+    // Particle age in seconds that maps to the top of the colormap in FluidRenderingMode::ParticlesAge.
+    pub age_visualization_range: f32,
+    // Hdr backbuffer resolution relative to the window, the result is scaled to the window while tonemapping.
+    pub render_scale: f32,
 }
 
 impl SceneRenderer {
+    pub const MIN_RENDER_SCALE: f32 = 0.5;
+    pub const MAX_RENDER_SCALE: f32 = 2.0;
+
     pub fn new(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         shader_dir: &ShaderDirectory,
         pipeline_manager: &mut PipelineManager,
         global_bind_group_layout: &wgpu::BindGroupLayout,
-        backbuffer: &HdrBackbuffer,
     ) -> Self {
-        let fluid_renderer_group_layout = &HybridFluid::get_or_create_group_layout_renderer(device).layout;
+        let fluid_renderer_group_layout = HybridFluid::group_layout_renderer(device, pipeline_manager);
+        let fluid_renderer_group_layout = &fluid_renderer_group_layout.layout;
 
         let background_and_lighting = Background::new(
             Path::new("background"),
@@ -86,7 +141,6 @@ impl SceneRenderer {
                 global_bind_group_layout,
                 fluid_renderer_group_layout,
                 background_and_lighting.bind_group_layout(),
-                backbuffer,
             ),
             particle_renderer: ParticleRenderer::new(
                 device,
@@ -95,6 +149,7 @@ impl SceneRenderer {
                 global_bind_group_layout,
                 fluid_renderer_group_layout,
             ),
+            smoke_renderer: SmokeRenderer::new(device, shader_dir, pipeline_manager, global_bind_group_layout),
             volume_renderer: VolumeRenderer::new(
                 device,
                 shader_dir,
@@ -109,7 +164,14 @@ impl SceneRenderer {
                 global_bind_group_layout,
                 background_and_lighting.bind_group_layout(),
             ),
-            bounds_line_renderer: StaticLineRenderer::new(device, shader_dir, pipeline_manager, global_bind_group_layout, 128),
+            bounds_line_renderer: StaticLineRenderer::new(
+                device,
+                shader_dir,
+                pipeline_manager,
+                global_bind_group_layout,
+                128,
+                Some(Screen::FORMAT_DEPTH),
+            ),
             mesh_renderer: MeshRenderer::new(
                 device,
                 shader_dir,
@@ -117,7 +179,11 @@ impl SceneRenderer {
                 global_bind_group_layout,
                 background_and_lighting.bind_group_layout(),
             ),
+            object_picking: ObjectPicking::new(device, shader_dir, pipeline_manager, global_bind_group_layout),
+            grid_cell_inspector: GridCellInspector::new(device),
+            gizmos: Gizmos::new(device, shader_dir, pipeline_manager, global_bind_group_layout),
             background_and_lighting,
+            transient_textures: TransientTexturePool::new(),
 
             fluid_rendering_mode: FluidRenderingMode::ScreenSpaceFluid,
             volume_visualization: VolumeVisualizationMode::None,
@@ -125,7 +191,11 @@ impl SceneRenderer {
             enable_box_lines: true,
             enable_mesh_rendering: true,
             enable_voxel_visualization: false,
+            enable_frustum_culling: true,
+            voxel_slice: Default::default(),
             velocity_visualization_scale: 0.008,
+            age_visualization_range: 10.0,
+            render_scale: 1.0,
         }
     }
 
@@ -170,38 +240,165 @@ impl SceneRenderer {
             queue,
         );
         self.voxel_renderer.on_new_scene(device, scene);
+        self.object_picking.clear_selection();
     }
 
     pub fn fill_global_uniform_buffer(&self, scene: &Scene) -> GlobalRenderSettingsUniformBufferContent {
+        GlobalRenderSettingsUniformBufferContent::new(
+            &scene.config().fluid,
+            self.particle_radius_factor,
+            self.velocity_visualization_scale,
+            self.age_visualization_range,
+        )
+    }
+
+    pub fn default_sun(&self) -> LightConfig {
+        self.background_and_lighting.default_sun()
+    }
+
+    // Hit tests the gizmos against the cursor and applies the edits of a dragged gizmo to the scene & lighting.
+    pub fn update_gizmos(&mut self, camera: &Camera, resolution: winit::dpi::PhysicalSize<u32>, scene: &mut Scene, queue: &wgpu::Queue) {
         let fluid_config = &scene.config().fluid;
-        let fluid_particle_radius =
-            fluid_config.grid_to_world_scale / (HybridFluid::PARTICLES_PER_GRID_CELL as f32).powf(1.0 / 3.0) * self.particle_radius_factor;
+        let grid_to_world_scale = fluid_config.grid_to_world_scale;
+        let domain_min = fluid_config.world_position;
+        let domain_size = fluid_config.grid_dimension.cast::<f32>().unwrap().to_vec() * grid_to_world_scale;
+        let domain_center = domain_min + domain_size * 0.5;
+        let arrow_length = domain_size.magnitude() * 0.25;
 
-        GlobalRenderSettingsUniformBufferContent {
-            fluid_min: fluid_config.world_position,
-            fluid_max: fluid_config.world_position + fluid_config.grid_dimension.cast::<f32>().unwrap().to_vec() * fluid_config.grid_to_world_scale,
-            fluid_grid_to_world_scale: fluid_config.grid_to_world_scale,
-            velocity_visualization_scale: self.velocity_visualization_scale,
-            fluid_particle_radius,
-            fluid_grid_resolution: fluid_config.grid_dimension,
+        let gravity = scene.gravity();
+        let mut gizmos = vec![Gizmo {
+            target: GizmoTarget::Gravity,
+            shape: GizmoShape::Direction {
+                origin: domain_center,
+                direction: if gravity.magnitude2() > 0.0 {
+                    gravity.normalize()
+                } else {
+                    -cgmath::Vector3::unit_y()
+                },
+                length: arrow_length,
+            },
+            color: cgmath::vec3(0.1, 0.3, 1.0),
+        }];
+        // Without lights in the scene, the sun of the background is shown. It becomes a scene light once it is changed.
+        let default_sun = [self.background_and_lighting.default_sun()];
+        let lights = if scene.lights.is_empty() { &default_sun[..] } else { &scene.lights[..] };
+        let mut num_directional_lights = 0;
+        for (index, config) in lights.iter().enumerate() {
+            // Directional lights are arrows above the domain pointing in the direction the light travels, spot lights start at their position.
+            let (origin, direction) = match config.light {
+                Light::Directional { direction } => {
+                    let offset = cgmath::vec3(
+                        num_directional_lights as f32 * arrow_length * 0.5,
+                        domain_size.y * 0.5 + arrow_length,
+                        0.0,
+                    );
+                    num_directional_lights += 1;
+                    (domain_center + offset, direction)
+                }
+                Light::Spot { position, direction, .. } => (position, direction),
+                Light::Point { .. } => continue,
+            };
+            gizmos.push(Gizmo {
+                target: GizmoTarget::Light(index),
+                shape: GizmoShape::Direction {
+                    origin,
+                    direction: if direction.magnitude2() > 0.0 {
+                        direction.normalize()
+                    } else {
+                        -cgmath::Vector3::unit_y()
+                    },
+                    length: arrow_length,
+                },
+                color: cgmath::vec3(1.0, 0.8, 0.1),
+            });
+        }
+        if self.voxel_slice.enabled {
+            let axis = self.voxel_slice.axis.min(2);
+            let mut normal = cgmath::vec3(0.0, 0.0, 0.0);
+            normal[axis] = 1.0;
+            let mut half_extent_u = cgmath::vec3(0.0, 0.0, 0.0);
+            let mut half_extent_v = cgmath::vec3(0.0, 0.0, 0.0);
+            half_extent_u[(axis + 1) % 3] = domain_size[(axis + 1) % 3] * 0.5;
+            half_extent_v[(axis + 2) % 3] = domain_size[(axis + 2) % 3] * 0.5;
+            let mut center = domain_center;
+            center[axis] = domain_min[axis] + (self.voxel_slice.index as f32 + 0.5) * grid_to_world_scale;
+            gizmos.push(Gizmo {
+                target: GizmoTarget::VoxelSlice,
+                shape: GizmoShape::Plane {
+                    center,
+                    normal,
+                    half_extent_u,
+                    half_extent_v,
+                },
+                color: cgmath::vec3(0.1, 0.9, 0.3),
+            });
+        }
+
+        match self.gizmos.update(&gizmos, camera, resolution, queue) {
+            Some((GizmoTarget::Gravity, GizmoEdit::Direction(direction))) => {
+                let magnitude = gravity.magnitude();
+                scene.set_gravity(direction * if magnitude > 0.0 { magnitude } else { 9.81 });
+            }
+            Some((GizmoTarget::Light(index), GizmoEdit::Direction(direction))) => {
+                if scene.lights.is_empty() {
+                    scene.lights.push(self.background_and_lighting.default_sun());
+                }
+                if let Some(config) = scene.lights.get_mut(index) {
+                    config.light.set_direction(direction);
+                }
+            }
+            Some((GizmoTarget::VoxelSlice, GizmoEdit::PlaneCenter(center))) => {
+                let axis = self.voxel_slice.axis.min(2);
+                let grid_dimension = scene.config().fluid.grid_dimension;
+                let cell = ((center[axis] - domain_min[axis]) / grid_to_world_scale).floor();
+                self.voxel_slice.index = (cell.max(0.0) as u32).min(grid_dimension[axis].max(1) - 1);
+            }
+            _ => {}
         }
     }
 
-    pub fn on_window_resize(&mut self, device: &wgpu::Device, backbuffer: &HdrBackbuffer) {
-        self.screenspace_fluid.on_window_resize(device, backbuffer);
+    // Needs to be called after the command buffer of draw was submitted.
+    pub fn update_readback(&mut self, device: &wgpu::Device, scene: &Scene) {
+        self.particle_renderer.update_readback(device);
+        self.object_picking.update_readback(device, &scene.models);
+        self.grid_cell_inspector.update_readback(device);
     }
 
     pub fn draw(
-        &self,
+        &mut self,
         scene: &Scene,
         profiler: &mut GpuProfiler,
         device: &wgpu::Device,
+        queue: &wgpu::Queue,
         encoder: &mut wgpu::CommandEncoder,
         pipeline_manager: &PipelineManager,
         backbuffer: &HdrBackbuffer,
         depthbuffer: &wgpu::TextureView,
         global_bind_group: &wgpu::BindGroup,
+        camera_frustum: &Frustum,
     ) {
+        let frustum = if self.enable_frustum_culling { Some(camera_frustum) } else { None };
+        self.background_and_lighting.update_lights(queue, &scene.lights);
+
+        if let FluidRenderingMode::ParticlesIndex
+        | FluidRenderingMode::ParticlesVelocity
+        | FluidRenderingMode::ParticlesPhase
+        | FluidRenderingMode::ParticlesAge
+        | FluidRenderingMode::ParticlesDye = self.fluid_rendering_mode
+        {
+            wgpu_profiler!("particle culling", profiler, encoder, device, {
+                self.particle_renderer.cull(
+                    device,
+                    queue,
+                    encoder,
+                    pipeline_manager,
+                    global_bind_group,
+                    &scene.fluid(),
+                    scene.config().fluid.symmetry,
+                );
+            });
+        }
+
         // Opaque
         wgpu_profiler!("opaque", profiler, encoder, device, {
             let mut rpass_backbuffer = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
@@ -230,15 +427,23 @@ impl SceneRenderer {
                 FluidRenderingMode::ScreenSpaceFluid => {
                     // Handled earlier!
                 }
-                FluidRenderingMode::ParticlesIndex | FluidRenderingMode::ParticlesVelocity => {
+                FluidRenderingMode::ParticlesIndex
+                | FluidRenderingMode::ParticlesVelocity
+                | FluidRenderingMode::ParticlesPhase
+                | FluidRenderingMode::ParticlesAge
+                | FluidRenderingMode::ParticlesDye => {
                     wgpu_profiler!("particles", profiler, &mut rpass_backbuffer, device, {
                         self.particle_renderer.draw(
                             &mut rpass_backbuffer,
                             pipeline_manager,
                             &scene.fluid(),
+                            scene.config().fluid.symmetry,
                             match self.fluid_rendering_mode {
                                 FluidRenderingMode::ParticlesVelocity => ParticleRendererMode::Velocity,
                                 FluidRenderingMode::ParticlesIndex => ParticleRendererMode::Index,
+                                FluidRenderingMode::ParticlesPhase => ParticleRendererMode::Phase,
+                                FluidRenderingMode::ParticlesAge => ParticleRendererMode::Age,
+                                FluidRenderingMode::ParticlesDye => ParticleRendererMode::Dye,
                                 _ => unreachable!(),
                             },
                         );
@@ -253,6 +458,8 @@ impl SceneRenderer {
                         pipeline_manager,
                         self.background_and_lighting.bind_group(),
                         &scene.models,
+                        self.object_picking.selected_object(),
+                        frustum,
                     );
                 });
             }
@@ -274,7 +481,8 @@ impl SceneRenderer {
                         &mut rpass_backbuffer,
                         pipeline_manager,
                         self.background_and_lighting.bind_group(),
-                        &scene.config().fluid.grid_dimension,
+                        &scene.config().fluid,
+                        frustum,
                     );
                 });
             }
@@ -298,12 +506,49 @@ impl SceneRenderer {
                         pipeline_manager,
                         depthbuffer,
                         global_bind_group,
-                        self.background_and_lighting.bind_group(),
+                        &mut self.background_and_lighting,
                         &scene.fluid(),
+                        scene.config().fluid.symmetry,
                         backbuffer,
+                        &mut self.transient_textures,
                     );
                 });
+            } else {
+                self.background_and_lighting.clear_caustics(encoder);
+            }
+
+            if let Some(smoke) = scene.smoke() {
+                wgpu_profiler!("smoke", profiler, encoder, device, {
+                    self.smoke_renderer
+                        .draw(encoder, device, pipeline_manager, global_bind_group, backbuffer, depthbuffer, smoke);
+                });
             }
         });
+
+        // Overlays
+        if self.voxel_slice.enabled {
+            wgpu_profiler!("voxel slice", profiler, encoder, device, {
+                self.voxel_renderer.draw_slice(
+                    encoder,
+                    pipeline_manager,
+                    global_bind_group,
+                    backbuffer,
+                    &scene.config().fluid.grid_dimension,
+                    self.voxel_slice,
+                );
+            });
+            if self.voxel_slice.inspect {
+                self.grid_cell_inspector.copy(
+                    encoder,
+                    backbuffer.resolution(),
+                    &scene.config().fluid.grid_dimension,
+                    self.voxel_slice,
+                    &scene.fluid(),
+                );
+            }
+        }
+        self.gizmos.draw(encoder, pipeline_manager, global_bind_group, backbuffer);
+
+        self.transient_textures.end_frame();
     }
 }
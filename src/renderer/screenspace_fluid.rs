@@ -1,36 +1,142 @@
+use super::background::Background;
 use crate::render_output::hdr_backbuffer::HdrBackbuffer;
 use crate::render_output::screen::Screen;
 use crate::wgpu_utils::pipelines::*;
 use crate::{
+    scene::FluidSymmetry,
     simulation::HybridFluid,
     wgpu_utils::{
         self,
         binding_builder::{BindGroupBuilder, BindGroupLayoutBuilder, BindGroupLayoutWithDesc},
         binding_glsl,
         shader::*,
+        transient_texture_pool::TransientTexturePool,
     },
 };
 use std::path::{Path, PathBuf};
-use std::rc::Rc;
+use std::sync::Arc;
+use strum::IntoEnumIterator;
 use wgpu_profiler::{wgpu_profiler, GpuProfiler};
 
-struct ScreenDependentProperties {
-    texture_view_fluid_view: [wgpu::TextureView; 2],
-    texture_view_fluid_thickness: [wgpu::TextureView; 2],
-    backbuffer_copy: wgpu::Texture,
-    bind_group_narrow_range_filter: [wgpu::BindGroup; 2],
-    bind_group_thickness_filter: [wgpu::BindGroup; 2],
-    bind_group_compose: wgpu::BindGroup,
-    target_textures_resolution: wgpu::Extent3d,
+#[derive(Clone, Copy, Debug, EnumIter, PartialEq)]
+pub enum ScreenSpaceFluidResolution {
+    Full,
+    Half,
+    Quarter,
 }
 
-struct ScreenIndependentProperties {
+impl ScreenSpaceFluidResolution {
+    pub fn divisor(self) -> u32 {
+        match self {
+            ScreenSpaceFluidResolution::Full => 1,
+            ScreenSpaceFluidResolution::Half => 2,
+            ScreenSpaceFluidResolution::Quarter => 4,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, EnumIter, PartialEq)]
+pub enum ScreenSpaceFluidDepthFilter {
+    NarrowRange,
+    CurvatureFlow,
+}
+
+#[derive(Clone, Copy, Debug, EnumIter, PartialEq)]
+pub enum ScreenSpaceFluidQuality {
+    Low,
+    Medium,
+    High,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct ScreenSpaceFluidSettings {
+    // Resolution of the depth & thickness targets and their filters. The final compose always happens at full resolution.
+    pub resolution: ScreenSpaceFluidResolution,
+    pub depth_filter: ScreenSpaceFluidDepthFilter,
+    // 2D narrow range filter pass after the 1D ones. Gets rid of most line artifacts, but is by far the most expensive filter.
+    pub depth_filter_2d: bool,
+    // Each iteration is a full pass over the depth target. Needs more iterations the higher the resolution.
+    pub curvature_flow_iterations: u32,
+    // Splats light refracted by the visible fluid surface onto the floor. Also applied to meshes, projected along the light direction.
+    pub caustics: bool,
+    // How much screen space reflections replace environment reflections. Zero skips tracing them altogether.
+    pub reflection_intensity: f32,
+    // Blurs screen space reflections, more so for far away objects.
+    pub reflection_roughness: f32,
+    // Particles turn into foam where the magnitude of their velocity divergence exceeds the threshold. (in grid cells per second)
+    pub foam_threshold: f32,
+    // How quickly foam fades in above the threshold. Zero disables foam.
+    pub foam_amount: f32,
+}
+
+impl ScreenSpaceFluidSettings {
+    pub fn from_quality(quality: ScreenSpaceFluidQuality) -> Self {
+        match quality {
+            ScreenSpaceFluidQuality::Low => ScreenSpaceFluidSettings {
+                resolution: ScreenSpaceFluidResolution::Quarter,
+                depth_filter: ScreenSpaceFluidDepthFilter::NarrowRange,
+                depth_filter_2d: false,
+                curvature_flow_iterations: 20,
+                caustics: false,
+                reflection_intensity: 0.0,
+                reflection_roughness: 0.1,
+                foam_threshold: 1.0,
+                foam_amount: 0.5,
+            },
+            ScreenSpaceFluidQuality::Medium => ScreenSpaceFluidSettings {
+                resolution: ScreenSpaceFluidResolution::Half,
+                depth_filter: ScreenSpaceFluidDepthFilter::NarrowRange,
+                depth_filter_2d: true,
+                curvature_flow_iterations: 40,
+                caustics: true,
+                reflection_intensity: 1.0,
+                reflection_roughness: 0.1,
+                foam_threshold: 1.0,
+                foam_amount: 0.5,
+            },
+            ScreenSpaceFluidQuality::High => ScreenSpaceFluidSettings {
+                resolution: ScreenSpaceFluidResolution::Full,
+                depth_filter: ScreenSpaceFluidDepthFilter::NarrowRange,
+                depth_filter_2d: true,
+                curvature_flow_iterations: 80,
+                caustics: true,
+                reflection_intensity: 1.0,
+                reflection_roughness: 0.1,
+                foam_threshold: 1.0,
+                foam_amount: 0.5,
+            },
+        }
+    }
+
+    // The preset these settings correspond to, None if they were customized.
+    pub fn quality(&self) -> Option<ScreenSpaceFluidQuality> {
+        ScreenSpaceFluidQuality::iter().find(|quality| Self::from_quality(*quality) == *self)
+    }
+}
+
+impl Default for ScreenSpaceFluidSettings {
+    fn default() -> Self {
+        Self::from_quality(ScreenSpaceFluidQuality::High)
+    }
+}
+
+pub struct ScreenSpaceFluid {
+    pub settings: ScreenSpaceFluidSettings,
+
+    pipeline_downsample_depth: RenderPipelineHandle,
+    group_layout_downsample_depth: BindGroupLayoutWithDesc,
+
     pipeline_render_particles: RenderPipelineHandle,
 
     pipeline_narrow_range_filter_1d: ComputePipelineHandle,
     pipeline_narrow_range_filter_2d: ComputePipelineHandle,
     group_layout_narrow_range_filter: BindGroupLayoutWithDesc,
 
+    pipeline_curvature_flow: ComputePipelineHandle,
+
+    pipeline_caustics: RenderPipelineHandle,
+    group_layout_caustics: BindGroupLayoutWithDesc,
+
     pipeline_thickness_filter: ComputePipelineHandle,
     group_layout_thickness_filter: BindGroupLayoutWithDesc,
 
@@ -38,14 +144,11 @@ struct ScreenIndependentProperties {
     group_layout_compose: BindGroupLayoutWithDesc,
 }
 
-pub struct ScreenSpaceFluid {
-    screen_independent: ScreenIndependentProperties,
-    screen_dependent: ScreenDependentProperties,
-}
-
 impl ScreenSpaceFluid {
     const FORMAT_FLUID_DEPTH: wgpu::TextureFormat = wgpu::TextureFormat::R32Float;
     const FORMAT_FLUID_THICKNESS: wgpu::TextureFormat = wgpu::TextureFormat::R16Float; // TODO: Smaller?
+                                                                                       // Thickness weighted foam & secondary fluid phase.
+    const FORMAT_FLUID_FOAM_AND_PHASE: wgpu::TextureFormat = wgpu::TextureFormat::Rg16Float;
 
     pub fn new(
         device: &wgpu::Device,
@@ -70,18 +173,67 @@ impl ScreenSpaceFluid {
             .next_binding_compute(binding_glsl::texture2D()) // Fluid thickness
             .next_binding_compute(binding_glsl::texture2D()) // HdrBackbuffer copy for reading
             .next_binding_compute(binding_glsl::image2D(HdrBackbuffer::FORMAT, wgpu::StorageTextureAccess::ReadWrite)) // hdr backbuffer, target
+            .next_binding_compute(binding_glsl::texture2DDepth()) // Scene depth
+            .next_binding_compute(binding_glsl::texture2D()) // Fluid foam
             .create(device, "BindGroupLayout: SSFluid, Final fluid/Compose");
 
+        let group_layout_downsample_depth = BindGroupLayoutBuilder::new()
+            .next_binding_fragment(binding_glsl::texture2DDepth()) // Scene depth
+            .create(device, "BindGroupLayout: SSFluid, Downsample Depth");
+        let layout_downsample_depth = pipeline_manager.create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Downsample Depth Pipeline Layout"),
+                bind_group_layouts: &[&group_layout_downsample_depth.layout],
+                push_constant_ranges: &[wgpu::PushConstantRange {
+                    stages: wgpu::ShaderStage::FRAGMENT,
+                    range: 0..4,
+                }],
+            },
+        );
+        let pipeline_downsample_depth = pipeline_manager.create_render_pipeline(
+            device,
+            shader_dir,
+            RenderPipelineCreationDesc {
+                label: "ScreenspaceFluid: Downsample Depth",
+                layout: layout_downsample_depth,
+                vertex: VertexStateCreationDesc {
+                    shader_relative_path: PathBuf::from("screentri.vert"),
+                    buffers: Vec::new(),
+                },
+                primitive: Default::default(),
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: Screen::FORMAT_DEPTH,
+                    depth_write_enabled: true,
+                    depth_compare: wgpu::CompareFunction::Always,
+                    stencil: Default::default(),
+                    bias: Default::default(),
+                }),
+                multisample: Default::default(),
+                fragment: FragmentStateCreationDesc {
+                    shader_relative_path: PathBuf::from("screenspace_fluid/downsample_depth.frag"),
+                    targets: Vec::new(),
+                },
+            },
+        );
+
+        let layout_render_particles = pipeline_manager.create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Render Particles for SS Fluid Pipeline Layout"),
+                bind_group_layouts: &[&global_bind_group_layout, &fluid_renderer_group_layout],
+                push_constant_ranges: &[wgpu::PushConstantRange {
+                    stages: wgpu::ShaderStage::VERTEX,
+                    range: 0..8,
+                }],
+            },
+        );
         let pipeline_render_particles = pipeline_manager.create_render_pipeline(
             device,
             shader_dir,
             RenderPipelineCreationDesc {
                 label: "ScreenspaceFluid: Render Particles",
-                layout: Rc::new(device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                    label: Some("Render Particles for SS Fluid Pipeline Layout"),
-                    bind_group_layouts: &[&global_bind_group_layout, &fluid_renderer_group_layout],
-                    push_constant_ranges: &[],
-                })),
+                layout: layout_render_particles,
 
                 vertex: VertexStateCreationDesc {
                     shader_relative_path: PathBuf::from("screenspace_fluid/particles.vert"),
@@ -124,26 +276,87 @@ impl ScreenSpaceFluid {
                             }),
                             ..Self::FORMAT_FLUID_THICKNESS.into()
                         },
+                        wgpu::ColorTargetState {
+                            blend: Some(wgpu::BlendState {
+                                color: wgpu::BlendComponent {
+                                    src_factor: wgpu::BlendFactor::One,
+                                    dst_factor: wgpu::BlendFactor::One,
+                                    operation: wgpu::BlendOperation::Add,
+                                },
+                                alpha: wgpu::BlendComponent::REPLACE,
+                            }),
+                            ..Self::FORMAT_FLUID_FOAM_AND_PHASE.into()
+                        },
                     ],
                 },
             },
         );
 
+        let group_layout_caustics = BindGroupLayoutBuilder::new()
+            .next_binding_vertex(binding_glsl::texture2D()) // Fluid depth
+            .next_binding_vertex(binding_glsl::uniform()) // Lighting
+            .create(device, "BindGroupLayout: SSFluid, Caustics");
+        let pipeline_caustics = pipeline_manager.create_render_pipeline(
+            device,
+            shader_dir,
+            RenderPipelineCreationDesc {
+                label: "ScreenspaceFluid: Caustics",
+                layout: Arc::new(device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("Caustics Pipeline Layout"),
+                    bind_group_layouts: &[&global_bind_group_layout, &group_layout_caustics.layout],
+                    push_constant_ranges: &[],
+                })),
+                vertex: VertexStateCreationDesc {
+                    shader_relative_path: PathBuf::from("screenspace_fluid/caustics.vert"),
+                    buffers: Vec::new(),
+                },
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::PointList,
+                    ..Default::default()
+                },
+                depth_stencil: None,
+                multisample: Default::default(),
+                fragment: FragmentStateCreationDesc {
+                    shader_relative_path: PathBuf::from("screenspace_fluid/caustics.frag"),
+                    targets: vec![wgpu::ColorTargetState {
+                        blend: Some(wgpu::BlendState {
+                            // Light that ends up in the same spot adds up.
+                            color: wgpu::BlendComponent {
+                                src_factor: wgpu::BlendFactor::One,
+                                dst_factor: wgpu::BlendFactor::One,
+                                operation: wgpu::BlendOperation::Add,
+                            },
+                            // Height of the highest fluid surface above the floor.
+                            alpha: wgpu::BlendComponent {
+                                src_factor: wgpu::BlendFactor::One,
+                                dst_factor: wgpu::BlendFactor::One,
+                                operation: wgpu::BlendOperation::Max,
+                            },
+                        }),
+                        ..Background::FORMAT_CAUSTICS.into()
+                    }],
+                },
+            },
+        );
+
         // Use same push constant range for all compute pipelines to improve internal Vulkan pipeline compatibility.
         let push_constant_ranges = &[wgpu::PushConstantRange {
             stages: wgpu::ShaderStage::COMPUTE,
-            range: 0..4,
+            range: 0..8,
         }];
 
-        let layout_narrow_range_filter = Rc::new(device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("Narrow Range Filter Pipeline Layout"),
-            bind_group_layouts: &[
-                &global_bind_group_layout,
-                &fluid_renderer_group_layout,
-                &group_layout_narrow_range_filter.layout,
-            ],
-            push_constant_ranges,
-        }));
+        let layout_narrow_range_filter = pipeline_manager.create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Narrow Range Filter Pipeline Layout"),
+                bind_group_layouts: &[
+                    &global_bind_group_layout,
+                    &fluid_renderer_group_layout,
+                    &group_layout_narrow_range_filter.layout,
+                ],
+                push_constant_ranges,
+            },
+        );
         let pipeline_narrow_range_filter_1d = pipeline_manager.create_compute_pipeline(
             device,
             shader_dir,
@@ -162,16 +375,29 @@ impl ScreenSpaceFluid {
                 Path::new("screenspace_fluid/narrow_range_filter_2d.comp"),
             ),
         );
+        // Same bindings as the narrow range filter, so it can use the same bind groups.
+        let pipeline_curvature_flow = pipeline_manager.create_compute_pipeline(
+            device,
+            shader_dir,
+            ComputePipelineCreationDesc::new(
+                "ScreenspaceFluid: Curvature Flow",
+                layout_narrow_range_filter.clone(),
+                Path::new("screenspace_fluid/curvature_flow.comp"),
+            ),
+        );
 
-        let layout_thickness_filter = Rc::new(device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("Thickness Filter Pipeline Layout"),
-            bind_group_layouts: &[
-                &global_bind_group_layout,
-                &fluid_renderer_group_layout,
-                &group_layout_thickness_filter.layout,
-            ],
-            push_constant_ranges,
-        }));
+        let layout_thickness_filter = pipeline_manager.create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Thickness Filter Pipeline Layout"),
+                bind_group_layouts: &[
+                    &global_bind_group_layout,
+                    &fluid_renderer_group_layout,
+                    &group_layout_thickness_filter.layout,
+                ],
+                push_constant_ranges,
+            },
+        );
         let pipeline_thickness_filter = pipeline_manager.create_compute_pipeline(
             device,
             shader_dir,
@@ -182,182 +408,175 @@ impl ScreenSpaceFluid {
             ),
         );
 
+        let layout_fluid = pipeline_manager.create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Fluid Render Pipeline Layout"),
+                bind_group_layouts: &[
+                    &global_bind_group_layout,
+                    &background_and_lighting_group_layout,
+                    &group_layout_compose.layout,
+                ],
+                push_constant_ranges,
+            },
+        );
         let pipeline_fluid = pipeline_manager.create_compute_pipeline(
             device,
             shader_dir,
             ComputePipelineCreationDesc::new(
                 "ScreenspaceFluid: Fluid/compose",
-                Rc::new(device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                    label: Some("Fluid Render Pipeline Layout"),
-                    bind_group_layouts: &[
-                        &global_bind_group_layout,
-                        &background_and_lighting_group_layout,
-                        &group_layout_compose.layout,
-                    ],
-                    push_constant_ranges,
-                })),
+                layout_fluid,
                 Path::new("screenspace_fluid/fluid_render.comp"),
             ),
         );
 
-        let screen_independent = ScreenIndependentProperties {
+        ScreenSpaceFluid {
+            settings: Default::default(),
+
+            pipeline_downsample_depth,
+            group_layout_downsample_depth,
+
             pipeline_render_particles,
 
             pipeline_narrow_range_filter_1d,
             pipeline_narrow_range_filter_2d,
             group_layout_narrow_range_filter,
 
+            pipeline_curvature_flow,
+
+            pipeline_caustics,
+            group_layout_caustics,
+
             pipeline_thickness_filter,
             group_layout_thickness_filter,
 
             pipeline_fluid,
             group_layout_compose,
-        };
-
-        let screen_dependent = Self::create_screen_dependent_properties(&screen_independent, device, backbuffer);
-
-        ScreenSpaceFluid {
-            screen_dependent,
-            screen_independent,
         }
     }
 
-    fn create_screen_dependent_properties(
-        screen_independent: &ScreenIndependentProperties,
+    pub fn draw<'a>(
+        &'a self,
+        encoder: &mut wgpu::CommandEncoder,
         device: &wgpu::Device,
+        profiler: &mut GpuProfiler,
+        pipeline_manager: &'a PipelineManager,
+        depthbuffer: &wgpu::TextureView,
+        global_bind_group: &wgpu::BindGroup,
+        background_and_lighting: &mut Background,
+        fluid: &HybridFluid,
+        symmetry: FluidSymmetry,
         backbuffer: &HdrBackbuffer,
-    ) -> ScreenDependentProperties {
-        let target_textures_resolution = wgpu::Extent3d {
+        transient_textures: &mut TransientTexturePool,
+    ) {
+        // Set some depth value that is beyond the far plane. (could do infinity, but don't trust this is passed down correctly)
+        let depth_clear_color = wgpu::Color {
+            r: 999999.0,
+            g: 999999.0,
+            b: 999999.0,
+            a: 999999.0,
+        };
+
+        // All intermediate targets are only needed during this pass and go back to the pool afterwards.
+        let resolution_divisor = self.settings.resolution.divisor();
+        let backbuffer_resolution = wgpu::Extent3d {
             width: backbuffer.resolution().width,
             height: backbuffer.resolution().height,
             depth_or_array_layers: 1,
         };
-        let texture_fluid_depth = [
-            device.create_texture(&wgpu::TextureDescriptor {
-                label: Some("Texture: Fluid Depth 1 (render target)"),
-                size: target_textures_resolution,
-                mip_level_count: 1,
-                sample_count: 1,
-                dimension: wgpu::TextureDimension::D2,
-                format: Self::FORMAT_FLUID_DEPTH,
-                usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::STORAGE | wgpu::TextureUsage::SAMPLED,
-            }),
-            device.create_texture(&wgpu::TextureDescriptor {
-                label: Some("Texture: Fluid Depth 2 (blur target)"),
-                size: target_textures_resolution,
-                mip_level_count: 1,
-                sample_count: 1,
-                dimension: wgpu::TextureDimension::D2,
-                format: Self::FORMAT_FLUID_DEPTH,
-                usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::STORAGE | wgpu::TextureUsage::SAMPLED,
-            }),
-        ];
-        let backbuffer_copy = device.create_texture(&wgpu::TextureDescriptor {
-            label: Some("Texture: HdrBackbuffer Copy for Refraction"),
-            size: wgpu::Extent3d {
-                width: backbuffer.resolution().width,
-                height: backbuffer.resolution().height,
-                depth_or_array_layers: 1,
-            },
+        let target_textures_resolution = wgpu::Extent3d {
+            width: wgpu_utils::compute_group_size_1d(backbuffer_resolution.width, resolution_divisor),
+            height: wgpu_utils::compute_group_size_1d(backbuffer_resolution.height, resolution_divisor),
+            depth_or_array_layers: 1,
+        };
+        let target_desc = |format| wgpu::TextureDescriptor {
+            label: None,
+            size: target_textures_resolution,
             mip_level_count: 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: HdrBackbuffer::FORMAT,
-            usage: wgpu::TextureUsage::COPY_DST | wgpu::TextureUsage::SAMPLED,
-        });
-        let texture_view_backbuffer_copy = backbuffer_copy.create_view(&Default::default());
-        let texture_view_fluid_view = [
-            texture_fluid_depth[0].create_view(&Default::default()),
-            texture_fluid_depth[1].create_view(&Default::default()),
-        ];
-
-        let texture_fluid_thickness = [
-            device.create_texture(&wgpu::TextureDescriptor {
-                label: Some("Texture: Fluid Thickness 1 (render target)"),
-                size: target_textures_resolution,
-                mip_level_count: 1,
-                sample_count: 1,
-                dimension: wgpu::TextureDimension::D2,
-                format: Self::FORMAT_FLUID_THICKNESS,
-                usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::STORAGE | wgpu::TextureUsage::SAMPLED,
-            }),
-            device.create_texture(&wgpu::TextureDescriptor {
-                label: Some("Texture: Fluid Thickness 2 (blur target)"),
-                size: target_textures_resolution,
-                mip_level_count: 1,
-                sample_count: 1,
-                dimension: wgpu::TextureDimension::D2,
-                format: Self::FORMAT_FLUID_THICKNESS,
-                usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::STORAGE | wgpu::TextureUsage::SAMPLED,
-            }),
+            format,
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::STORAGE | wgpu::TextureUsage::SAMPLED,
+        };
+        let fluid_depth = [
+            transient_textures.acquire(device, "Texture: Fluid Depth 1 (render target)", &target_desc(Self::FORMAT_FLUID_DEPTH)),
+            transient_textures.acquire(device, "Texture: Fluid Depth 2 (blur target)", &target_desc(Self::FORMAT_FLUID_DEPTH)),
         ];
-        let texture_view_fluid_thickness = [
-            texture_fluid_thickness[0].create_view(&Default::default()),
-            texture_fluid_thickness[1].create_view(&Default::default()),
+        let fluid_thickness = [
+            transient_textures.acquire(
+                device,
+                "Texture: Fluid Thickness 1 (render target)",
+                &target_desc(Self::FORMAT_FLUID_THICKNESS),
+            ),
+            transient_textures.acquire(
+                device,
+                "Texture: Fluid Thickness 2 (blur target)",
+                &target_desc(Self::FORMAT_FLUID_THICKNESS),
+            ),
         ];
+        // Foam weighted by thickness, so it can be normalized by thickness later on.
+        let fluid_foam_and_phase = transient_textures.acquire(device, "Texture: Fluid Foam & Phase", &target_desc(Self::FORMAT_FLUID_FOAM_AND_PHASE));
+        let backbuffer_copy = transient_textures.acquire(
+            device,
+            "Texture: HdrBackbuffer Copy for Refraction",
+            &wgpu::TextureDescriptor {
+                size: backbuffer_resolution,
+                format: HdrBackbuffer::FORMAT,
+                usage: wgpu::TextureUsage::COPY_DST | wgpu::TextureUsage::SAMPLED,
+                ..target_desc(HdrBackbuffer::FORMAT)
+            },
+        );
+        // Particles can't be depth tested against the full resolution depthbuffer if the targets are smaller.
+        let downsampled_depthbuffer = if resolution_divisor > 1 {
+            Some(transient_textures.acquire(
+                device,
+                "Texture: Fluid Depthbuffer (downsampled)",
+                &wgpu::TextureDescriptor {
+                    format: Screen::FORMAT_DEPTH,
+                    usage: wgpu::TextureUsage::RENDER_ATTACHMENT,
+                    ..target_desc(Screen::FORMAT_DEPTH)
+                },
+            ))
+        } else {
+            None
+        };
 
+        // Which textures we get from the pool may change from frame to frame, so bind groups are recreated every time.
         let bind_group_narrow_range_filter = [
-            BindGroupBuilder::new(&screen_independent.group_layout_narrow_range_filter)
-                .texture(&texture_view_fluid_view[1])
-                .texture(&texture_view_fluid_view[0])
+            BindGroupBuilder::new(&self.group_layout_narrow_range_filter)
+                .texture(fluid_depth[1].view())
+                .texture(fluid_depth[0].view())
                 .create(device, "BindGroup: Narrow Range filter 1"),
-            BindGroupBuilder::new(&screen_independent.group_layout_narrow_range_filter)
-                .texture(&texture_view_fluid_view[0])
-                .texture(&texture_view_fluid_view[1])
+            BindGroupBuilder::new(&self.group_layout_narrow_range_filter)
+                .texture(fluid_depth[0].view())
+                .texture(fluid_depth[1].view())
                 .create(device, "BindGroup: Narrow Range filter 2"),
         ];
         let bind_group_thickness_filter = [
-            BindGroupBuilder::new(&screen_independent.group_layout_thickness_filter)
-                .texture(&texture_view_fluid_thickness[1])
-                .texture(&texture_view_fluid_thickness[0])
+            BindGroupBuilder::new(&self.group_layout_thickness_filter)
+                .texture(fluid_thickness[1].view())
+                .texture(fluid_thickness[0].view())
                 .create(device, "BindGroup: Thickness Filter 1"),
-            BindGroupBuilder::new(&screen_independent.group_layout_thickness_filter)
-                .texture(&texture_view_fluid_thickness[0])
-                .texture(&texture_view_fluid_thickness[1])
+            BindGroupBuilder::new(&self.group_layout_thickness_filter)
+                .texture(fluid_thickness[0].view())
+                .texture(fluid_thickness[1].view())
                 .create(device, "BindGroup: Thickness Filter 2"),
         ];
-        let bind_group_compose = BindGroupBuilder::new(&screen_independent.group_layout_compose)
-            .texture(&texture_view_fluid_view[1])
-            .texture(&texture_view_fluid_thickness[0])
-            .texture(&texture_view_backbuffer_copy)
+        // Filters ping-pong between the two depth targets, starting with the particles in fluid_depth[0].
+        // The 1D narrow range passes end up in fluid_depth[0], the 2D filter writes from there into fluid_depth[1].
+        let filtered_fluid_depth = match self.settings.depth_filter {
+            ScreenSpaceFluidDepthFilter::NarrowRange => &fluid_depth[self.settings.depth_filter_2d as usize],
+            ScreenSpaceFluidDepthFilter::CurvatureFlow => &fluid_depth[(self.settings.curvature_flow_iterations % 2) as usize],
+        };
+        let bind_group_compose = BindGroupBuilder::new(&self.group_layout_compose)
+            .texture(filtered_fluid_depth.view())
+            .texture(fluid_thickness[0].view())
+            .texture(backbuffer_copy.view())
             .texture(&backbuffer.texture_view())
+            .texture(depthbuffer)
+            .texture(fluid_foam_and_phase.view())
             .create(device, "BindGroup: SSFluid, Final Compose");
 
-        ScreenDependentProperties {
-            texture_view_fluid_view,
-            texture_view_fluid_thickness,
-            backbuffer_copy,
-            target_textures_resolution,
-            bind_group_narrow_range_filter,
-            bind_group_thickness_filter,
-            bind_group_compose,
-        }
-    }
-
-    pub fn on_window_resize(&mut self, device: &wgpu::Device, backbuffer: &HdrBackbuffer) {
-        self.screen_dependent = Self::create_screen_dependent_properties(&self.screen_independent, device, backbuffer);
-    }
-
-    pub fn draw<'a>(
-        &'a self,
-        encoder: &mut wgpu::CommandEncoder,
-        device: &wgpu::Device,
-        profiler: &mut GpuProfiler,
-        pipeline_manager: &'a PipelineManager,
-        depthbuffer: &wgpu::TextureView,
-        global_bind_group: &wgpu::BindGroup,
-        background_and_lighting_bind_group: &wgpu::BindGroup,
-        fluid: &HybridFluid,
-        backbuffer: &HdrBackbuffer,
-    ) {
-        // Set some depth value that is beyond the far plane. (could do infinity, but don't trust this is passed down correctly)
-        let depth_clear_color = wgpu::Color {
-            r: 999999.0,
-            g: 999999.0,
-            b: 999999.0,
-            a: 999999.0,
-        };
-
         encoder.copy_texture_to_texture(
             wgpu::ImageCopyTexture {
                 texture: backbuffer.texture(),
@@ -365,23 +584,53 @@ impl ScreenSpaceFluid {
                 origin: wgpu::Origin3d::ZERO,
             },
             wgpu::ImageCopyTexture {
-                texture: &self.screen_dependent.backbuffer_copy,
+                texture: backbuffer_copy.texture(),
                 mip_level: 0,
                 origin: wgpu::Origin3d::ZERO,
             },
-            wgpu::Extent3d {
-                width: backbuffer.resolution().width,
-                height: backbuffer.resolution().height,
-                depth_or_array_layers: 1,
-            },
+            backbuffer_resolution,
         );
 
+        if let Some(ref downsampled_depthbuffer) = downsampled_depthbuffer {
+            wgpu_profiler!("downsample depth", profiler, encoder, device, {
+                let bind_group_downsample_depth = BindGroupBuilder::new(&self.group_layout_downsample_depth)
+                    .texture(depthbuffer)
+                    .create(device, "BindGroup: SSFluid, Downsample Depth");
+                let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("downsample depth"),
+                    color_attachments: &[],
+                    depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                        view: downsampled_depthbuffer.view(),
+                        depth_ops: Some(wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(1.0),
+                            store: true,
+                        }),
+                        stencil_ops: None,
+                    }),
+                });
+                rpass.set_bind_group(0, &bind_group_downsample_depth, &[]);
+                rpass.set_pipeline(pipeline_manager.get_render(&self.pipeline_downsample_depth));
+                pipeline_manager.set_push_constants_render(
+                    &mut rpass,
+                    &self.pipeline_downsample_depth,
+                    wgpu::ShaderStage::FRAGMENT,
+                    0,
+                    bytemuck::bytes_of(&resolution_divisor),
+                );
+                rpass.draw(0..3, 0..1);
+            });
+        }
+        let particles_depthbuffer = match downsampled_depthbuffer {
+            Some(ref downsampled_depthbuffer) => downsampled_depthbuffer.view(),
+            None => depthbuffer,
+        };
+
         wgpu_profiler!("particles", profiler, encoder, device, {
             let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("particles"),
                 color_attachments: &[
                     wgpu::RenderPassColorAttachment {
-                        view: &self.screen_dependent.texture_view_fluid_view[0],
+                        view: fluid_depth[0].view(),
                         resolve_target: None,
                         ops: wgpu::Operations {
                             load: wgpu::LoadOp::Clear(depth_clear_color),
@@ -389,7 +638,15 @@ impl ScreenSpaceFluid {
                         },
                     },
                     wgpu::RenderPassColorAttachment {
-                        view: &self.screen_dependent.texture_view_fluid_thickness[0],
+                        view: fluid_thickness[0].view(),
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                            store: true,
+                        },
+                    },
+                    wgpu::RenderPassColorAttachment {
+                        view: fluid_foam_and_phase.view(),
                         resolve_target: None,
                         ops: wgpu::Operations {
                             load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
@@ -398,7 +655,7 @@ impl ScreenSpaceFluid {
                     },
                 ],
                 depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                    view: depthbuffer,
+                    view: particles_depthbuffer,
                     depth_ops: Some(wgpu::Operations {
                         load: wgpu::LoadOp::Load,
                         store: false,
@@ -408,8 +665,20 @@ impl ScreenSpaceFluid {
             });
             rpass.set_bind_group(0, &global_bind_group, &[]);
             rpass.set_bind_group(1, fluid.bind_group_renderer(), &[]);
-            rpass.set_pipeline(pipeline_manager.get_render(&self.screen_independent.pipeline_render_particles));
-            rpass.draw(0..4, 0..fluid.num_particles());
+            rpass.set_pipeline(pipeline_manager.get_render(&self.pipeline_render_particles));
+            // Mirrored copies end up in the same depth/thickness targets, so everything after this is unaffected by symmetry.
+            let foam_parameters = half::f16::from_f32(self.settings.foam_threshold).to_bits() as u32
+                | ((half::f16::from_f32(self.settings.foam_amount).to_bits() as u32) << 16);
+            for mirror_index in 0..symmetry.num_instances() {
+                pipeline_manager.set_push_constants_render(
+                    &mut rpass,
+                    &self.pipeline_render_particles,
+                    wgpu::ShaderStage::VERTEX,
+                    0,
+                    bytemuck::cast_slice(&[mirror_index, foam_parameters]),
+                );
+                rpass.draw_indirect(fluid.particle_count_buffer(), HybridFluid::PARTICLE_COUNT_BUFFER_DRAW_OFFSET);
+            }
         });
 
         wgpu_profiler!("clear intermediate blur targets", profiler, encoder, device, {
@@ -418,7 +687,7 @@ impl ScreenSpaceFluid {
                     .begin_render_pass(&wgpu::RenderPassDescriptor {
                         label: Some("clear secondary water depth texture"),
                         color_attachments: &[wgpu::RenderPassColorAttachment {
-                            view: &self.screen_dependent.texture_view_fluid_view[1],
+                            view: fluid_depth[1].view(),
                             resolve_target: None,
                             ops: wgpu::Operations {
                                 load: wgpu::LoadOp::Clear(depth_clear_color),
@@ -434,7 +703,7 @@ impl ScreenSpaceFluid {
                     .begin_render_pass(&wgpu::RenderPassDescriptor {
                         label: Some("clear secondary water thickness texture"),
                         color_attachments: &[wgpu::RenderPassColorAttachment {
-                            view: &self.screen_dependent.texture_view_fluid_thickness[1],
+                            view: fluid_thickness[1].view(),
                             resolve_target: None,
                             ops: wgpu::Operations {
                                 load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
@@ -447,9 +716,9 @@ impl ScreenSpaceFluid {
             }
         });
 
-        wgpu_profiler!("fluid filters & render", profiler, encoder, device, {
+        wgpu_profiler!("fluid filters", profiler, encoder, device, {
             let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-                label: Some("fluid filters & render"),
+                label: Some("fluid filters"),
             });
             cpass.set_bind_group(0, &global_bind_group, &[]);
             cpass.set_bind_group(1, fluid.bind_group_renderer(), &[]);
@@ -464,76 +733,160 @@ impl ScreenSpaceFluid {
                 height: 64,
                 depth_or_array_layers: 1,
             };
-            let work_group_filter_1d_x = wgpu_utils::compute_group_size(self.screen_dependent.target_textures_resolution, LOCAL_SIZE_FILTER_1D_X);
-            let work_group_filter_1d_y = wgpu_utils::compute_group_size(self.screen_dependent.target_textures_resolution, LOCAL_SIZE_FILTER_1D_Y);
+            let work_group_filter_1d_x = wgpu_utils::compute_group_size(target_textures_resolution, LOCAL_SIZE_FILTER_1D_X);
+            let work_group_filter_1d_y = wgpu_utils::compute_group_size(target_textures_resolution, LOCAL_SIZE_FILTER_1D_Y);
 
             wgpu_profiler!("depth filter", profiler, &mut cpass, device, {
-                wgpu_profiler!("filter 1D", profiler, &mut cpass, device, {
-                    cpass.set_pipeline(pipeline_manager.get_compute(&self.screen_independent.pipeline_narrow_range_filter_1d));
-
-                    // Filter Y
-                    cpass.set_bind_group(2, &self.screen_dependent.bind_group_narrow_range_filter[0], &[]);
-                    cpass.set_push_constants(0, &bytemuck::bytes_of(&[1 as u32]));
-                    cpass.dispatch(
-                        work_group_filter_1d_y.width,
-                        work_group_filter_1d_y.height,
-                        work_group_filter_1d_y.depth_or_array_layers,
-                    );
-                    // Filter X - note that since filter is not really separable, order makes a difference. Found this order visually more pleasing.
-                    cpass.set_bind_group(2, &self.screen_dependent.bind_group_narrow_range_filter[1], &[]);
-                    cpass.set_push_constants(0, &bytemuck::bytes_of(&[0 as u32]));
-                    cpass.dispatch(
-                        work_group_filter_1d_x.width,
-                        work_group_filter_1d_x.height,
-                        work_group_filter_1d_x.depth_or_array_layers,
-                    );
-                });
-                wgpu_profiler!("filter 2D", profiler, &mut cpass, device, {
-                    cpass.set_pipeline(pipeline_manager.get_compute(&self.screen_independent.pipeline_narrow_range_filter_2d));
-                    cpass.set_bind_group(2, &self.screen_dependent.bind_group_narrow_range_filter[0], &[]);
-                    const LOCAL_SIZE_FILTER_2D: wgpu::Extent3d = wgpu::Extent3d {
-                        width: 16,
-                        height: 16,
-                        depth_or_array_layers: 1,
-                    };
-                    let work_group = wgpu_utils::compute_group_size(self.screen_dependent.target_textures_resolution, LOCAL_SIZE_FILTER_2D);
-                    cpass.dispatch(work_group.width, work_group.height, work_group.depth_or_array_layers);
-                });
+                match self.settings.depth_filter {
+                    ScreenSpaceFluidDepthFilter::NarrowRange => {
+                        wgpu_profiler!("filter 1D", profiler, &mut cpass, device, {
+                            cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_narrow_range_filter_1d));
+
+                            // Filter Y
+                            cpass.set_bind_group(2, &bind_group_narrow_range_filter[0], &[]);
+                            pipeline_manager.set_push_constants_compute(
+                                &mut cpass,
+                                &self.pipeline_narrow_range_filter_1d,
+                                0,
+                                bytemuck::bytes_of(&[1 as u32]),
+                            );
+                            cpass.dispatch(
+                                work_group_filter_1d_y.width,
+                                work_group_filter_1d_y.height,
+                                work_group_filter_1d_y.depth_or_array_layers,
+                            );
+                            // Filter X - note that since filter is not really separable, order makes a difference. Found this order visually more pleasing.
+                            cpass.set_bind_group(2, &bind_group_narrow_range_filter[1], &[]);
+                            pipeline_manager.set_push_constants_compute(
+                                &mut cpass,
+                                &self.pipeline_narrow_range_filter_1d,
+                                0,
+                                bytemuck::bytes_of(&[0 as u32]),
+                            );
+                            cpass.dispatch(
+                                work_group_filter_1d_x.width,
+                                work_group_filter_1d_x.height,
+                                work_group_filter_1d_x.depth_or_array_layers,
+                            );
+                        });
+                        if self.settings.depth_filter_2d {
+                            wgpu_profiler!("filter 2D", profiler, &mut cpass, device, {
+                                cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_narrow_range_filter_2d));
+                                cpass.set_bind_group(2, &bind_group_narrow_range_filter[0], &[]);
+                                const LOCAL_SIZE_FILTER_2D: wgpu::Extent3d = wgpu::Extent3d {
+                                    width: 16,
+                                    height: 16,
+                                    depth_or_array_layers: 1,
+                                };
+                                let work_group = wgpu_utils::compute_group_size(target_textures_resolution, LOCAL_SIZE_FILTER_2D);
+                                cpass.dispatch(work_group.width, work_group.height, work_group.depth_or_array_layers);
+                            });
+                        }
+                    }
+                    ScreenSpaceFluidDepthFilter::CurvatureFlow => {
+                        cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_curvature_flow));
+                        pipeline_manager.bind_push_constants_compute(&mut cpass, &self.pipeline_curvature_flow);
+                        const LOCAL_SIZE_CURVATURE_FLOW: wgpu::Extent3d = wgpu::Extent3d {
+                            width: 16,
+                            height: 16,
+                            depth_or_array_layers: 1,
+                        };
+                        let work_group = wgpu_utils::compute_group_size(target_textures_resolution, LOCAL_SIZE_CURVATURE_FLOW);
+                        for i in 0..self.settings.curvature_flow_iterations {
+                            cpass.set_bind_group(2, &bind_group_narrow_range_filter[(i % 2) as usize], &[]);
+                            cpass.dispatch(work_group.width, work_group.height, work_group.depth_or_array_layers);
+                        }
+                    }
+                }
             });
             wgpu_profiler!("thickness filter", profiler, &mut cpass, device, {
-                cpass.set_pipeline(pipeline_manager.get_compute(&self.screen_independent.pipeline_thickness_filter));
+                cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_thickness_filter));
 
                 // Filter Y
-                cpass.set_bind_group(2, &self.screen_dependent.bind_group_thickness_filter[0], &[]);
-                cpass.set_push_constants(0, &bytemuck::bytes_of(&[1 as u32]));
+                cpass.set_bind_group(2, &bind_group_thickness_filter[0], &[]);
+                pipeline_manager.set_push_constants_compute(&mut cpass, &self.pipeline_thickness_filter, 0, bytemuck::bytes_of(&[1 as u32]));
                 cpass.dispatch(
                     work_group_filter_1d_y.width,
                     work_group_filter_1d_y.height,
                     work_group_filter_1d_y.depth_or_array_layers,
                 );
                 // Filter X
-                cpass.set_bind_group(2, &self.screen_dependent.bind_group_thickness_filter[1], &[]);
-                cpass.set_push_constants(0, &bytemuck::bytes_of(&[0 as u32]));
+                cpass.set_bind_group(2, &bind_group_thickness_filter[1], &[]);
+                pipeline_manager.set_push_constants_compute(&mut cpass, &self.pipeline_thickness_filter, 0, bytemuck::bytes_of(&[0 as u32]));
                 cpass.dispatch(
                     work_group_filter_1d_x.width,
                     work_group_filter_1d_x.height,
                     work_group_filter_1d_x.depth_or_array_layers,
                 );
             });
+        });
+
+        // Caustics need to be done before composing since refraction may look at the floor.
+        // Meshes already used last frame's caustics at this point, but the one frame delay isn't noticeable.
+        if self.settings.caustics {
+            wgpu_profiler!("caustics", profiler, encoder, device, {
+                let bind_group_caustics = BindGroupBuilder::new(&self.group_layout_caustics)
+                    .texture(filtered_fluid_depth.view())
+                    .resource(background_and_lighting.uniform_buffer_binding_resource())
+                    .create(device, "BindGroup: SSFluid, Caustics");
+                let caustics_target = background_and_lighting.caustics_render_target();
+                let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("caustics"),
+                    color_attachments: &[wgpu::RenderPassColorAttachment {
+                        view: caustics_target,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                            store: true,
+                        },
+                    }],
+                    depth_stencil_attachment: None,
+                });
+                rpass.set_bind_group(0, &global_bind_group, &[]);
+                rpass.set_bind_group(1, &bind_group_caustics, &[]);
+                rpass.set_pipeline(pipeline_manager.get_render(&self.pipeline_caustics));
+                rpass.draw(0..(target_textures_resolution.width * target_textures_resolution.height), 0..1);
+            });
+        } else {
+            background_and_lighting.clear_caustics(encoder);
+        }
 
-            wgpu_profiler!("compose & render", profiler, &mut cpass, device, {
-                const LOCAL_SIZE_COMPOSE: wgpu::Extent3d = wgpu::Extent3d {
-                    width: 32,
-                    height: 32,
-                    depth_or_array_layers: 1,
-                };
-
-                cpass.set_bind_group(1, background_and_lighting_bind_group, &[]);
-                cpass.set_bind_group(2, &self.screen_dependent.bind_group_compose, &[]);
-                cpass.set_pipeline(pipeline_manager.get_compute(&self.screen_independent.pipeline_fluid));
-                let work_group = wgpu_utils::compute_group_size(self.screen_dependent.target_textures_resolution, LOCAL_SIZE_COMPOSE);
-                cpass.dispatch(work_group.width, work_group.height, work_group.depth_or_array_layers);
+        wgpu_profiler!("compose & render", profiler, encoder, device, {
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("fluid compose & render"),
             });
+            const LOCAL_SIZE_COMPOSE: wgpu::Extent3d = wgpu::Extent3d {
+                width: 32,
+                height: 32,
+                depth_or_array_layers: 1,
+            };
+
+            cpass.set_bind_group(0, &global_bind_group, &[]);
+            cpass.set_bind_group(1, background_and_lighting.bind_group(), &[]);
+            cpass.set_bind_group(2, &bind_group_compose, &[]);
+            cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_fluid));
+            let pack_unorm16 = |v: f32| (v.max(0.0).min(1.0) * 65535.0).round() as u32;
+            let reflection_parameters = pack_unorm16(self.settings.reflection_intensity) | (pack_unorm16(self.settings.reflection_roughness) << 16);
+            pipeline_manager.set_push_constants_compute(
+                &mut cpass,
+                &self.pipeline_fluid,
+                0,
+                bytemuck::bytes_of(&[resolution_divisor, reflection_parameters]),
+            );
+            let work_group = wgpu_utils::compute_group_size(backbuffer_resolution, LOCAL_SIZE_COMPOSE);
+            cpass.dispatch(work_group.width, work_group.height, work_group.depth_or_array_layers);
         });
+
+        let [fluid_depth_0, fluid_depth_1] = fluid_depth;
+        let [fluid_thickness_0, fluid_thickness_1] = fluid_thickness;
+        transient_textures.release(fluid_depth_0);
+        transient_textures.release(fluid_depth_1);
+        transient_textures.release(fluid_thickness_0);
+        transient_textures.release(fluid_thickness_1);
+        transient_textures.release(fluid_foam_and_phase);
+        transient_textures.release(backbuffer_copy);
+        if let Some(downsampled_depthbuffer) = downsampled_depthbuffer {
+            transient_textures.release(downsampled_depthbuffer);
+        }
     }
 }
@@ -0,0 +1,92 @@
+use std::{path::Path, sync::Arc};
+
+use crate::{
+    render_output::hdr_backbuffer::HdrBackbuffer,
+    simulation::SmokeSimulation,
+    wgpu_utils::{binding_builder::*, binding_glsl, pipelines::*, shader::ShaderDirectory},
+};
+
+// Raymarches the smoke volume of a SmokeSimulation on top of the opaque scene.
+pub struct SmokeRenderer {
+    pipeline: RenderPipelineHandle,
+    group_layout: BindGroupLayoutWithDesc,
+}
+
+impl SmokeRenderer {
+    pub fn new(
+        device: &wgpu::Device,
+        shader_dir: &ShaderDirectory,
+        pipeline_manager: &mut PipelineManager,
+        global_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let group_layout = BindGroupLayoutBuilder::new()
+            .next_binding_fragment(binding_glsl::texture3D()) // Smoke
+            .next_binding_fragment(binding_glsl::texture2DDepth()) // Scene depth
+            .create(device, "BindGroupLayout: Smoke Renderer");
+
+        let mut desc = RenderPipelineCreationDesc::new(
+            "Smoke Renderer",
+            Arc::new(device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Smoke Renderer Pipeline Layout"),
+                bind_group_layouts: &[&global_bind_group_layout, &group_layout.layout],
+                push_constant_ranges: &[],
+            })),
+            Path::new("screentri.vert"),
+            Path::new("smoke_render.frag"),
+            HdrBackbuffer::FORMAT,
+            None,
+        );
+        // Premultiplied alpha, destination alpha is left untouched.
+        desc.fragment.targets = vec![wgpu::ColorTargetState {
+            blend: Some(wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::One,
+                    dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::Zero,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+            }),
+            ..HdrBackbuffer::FORMAT.into()
+        }];
+        let pipeline = pipeline_manager.create_render_pipeline(device, shader_dir, desc);
+
+        SmokeRenderer { pipeline, group_layout }
+    }
+
+    pub fn draw(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        device: &wgpu::Device,
+        pipeline_manager: &PipelineManager,
+        global_bind_group: &wgpu::BindGroup,
+        backbuffer: &HdrBackbuffer,
+        depthbuffer: &wgpu::TextureView,
+        smoke: &SmokeSimulation,
+    ) {
+        let bind_group = BindGroupBuilder::new(&self.group_layout)
+            .texture(smoke.smoke_view())
+            .texture(depthbuffer)
+            .create(device, "BindGroup: Smoke Renderer");
+
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("smoke"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: backbuffer.texture_view(),
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+        rpass.set_pipeline(pipeline_manager.get_render(&self.pipeline));
+        rpass.set_bind_group(0, global_bind_group, &[]);
+        rpass.set_bind_group(1, &bind_group, &[]);
+        rpass.draw(0..3, 0..1);
+    }
+}
@@ -1,9 +1,6 @@
 use crate::wgpu_utils::pipelines::*;
-use crate::{
-    render_output::{hdr_backbuffer::HdrBackbuffer, screen::Screen},
-    wgpu_utils::shader::*,
-};
-use std::{path::Path, rc::Rc};
+use crate::{render_output::hdr_backbuffer::HdrBackbuffer, wgpu_utils::shader::*};
+use std::{path::Path, sync::Arc};
 
 #[repr(C)]
 #[derive(Clone, Copy)]
@@ -37,10 +34,11 @@ impl StaticLineRenderer {
         pipeline_manager: &mut PipelineManager,
         global_bind_group_layout: &wgpu::BindGroupLayout,
         max_num_lines: usize,
+        depth_format: Option<wgpu::TextureFormat>,
     ) -> Self {
         let mut render_pipeline_desc = RenderPipelineCreationDesc::new(
             "Line Renderer",
-            Rc::new(device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            Arc::new(device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("Static Line Renderer Pipeline Layout"),
                 bind_group_layouts: &[&global_bind_group_layout],
                 push_constant_ranges: &[],
@@ -48,7 +46,7 @@ impl StaticLineRenderer {
             Path::new("lines.vert"),
             Path::new("vertex_color.frag"),
             HdrBackbuffer::FORMAT,
-            Some(Screen::FORMAT_DEPTH),
+            depth_format,
         );
         render_pipeline_desc.primitive.topology = wgpu::PrimitiveTopology::LineList;
         render_pipeline_desc.vertex.buffers = vec![wgpu::VertexBufferLayout {
@@ -90,22 +88,32 @@ impl StaticLineRenderer {
         self.num_lines = 0;
     }
 
+    // Appends lines to the ones already added, every pair of vertices is a line.
     pub fn add_lines(&mut self, lines: &[LineVertex], queue: &wgpu::Queue) {
-        if lines.len() + self.num_lines > self.max_num_lines {
+        let num_new_lines = lines.len() / 2;
+        if num_new_lines + self.num_lines > self.max_num_lines {
             error!(
                 "Buffer too small to add {} lines. Containing {} right now, maximum is {}",
-                lines.len(),
-                self.num_lines,
-                self.max_num_lines
+                num_new_lines, self.num_lines, self.max_num_lines
             );
             return;
         }
+        if num_new_lines == 0 {
+            return;
+        }
 
-        queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(lines));
-        self.num_lines += lines.len();
+        queue.write_buffer(
+            &self.vertex_buffer,
+            (self.num_lines * 2 * LINE_VERTEX_SIZE) as wgpu::BufferAddress,
+            bytemuck::cast_slice(&lines[..num_new_lines * 2]),
+        );
+        self.num_lines += num_new_lines;
     }
 
     pub fn draw<'a>(&'a self, rpass: &mut wgpu::RenderPass<'a>, pipeline_manager: &'a PipelineManager) {
+        if self.num_lines == 0 {
+            return;
+        }
         rpass.set_pipeline(pipeline_manager.get_render(&self.render_pipeline));
         let num_vertices = self.num_lines * 2;
         rpass.set_vertex_buffer(0, self.vertex_buffer.slice(0..(num_vertices as u64 * LINE_VERTEX_SIZE as u64)));
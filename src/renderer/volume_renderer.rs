@@ -4,7 +4,7 @@ use crate::{
     simulation::HybridFluid,
     wgpu_utils::pipelines::*,
 };
-use std::{path::Path, rc::Rc};
+use std::path::Path;
 
 #[derive(Clone, Copy, Debug, EnumIter, PartialEq)]
 pub enum VolumeVisualizationMode {
@@ -14,6 +14,10 @@ pub enum VolumeVisualizationMode {
     PressureFromVelocity,
     PressureFromDensity,
     Marker,
+    // Color codes cell types (domain border, scene solid, fluid) and highlights cells whose type changed since the previous step.
+    MarkerChanges,
+    // Heatmap of particle counts per cell as of the last counting sort binning, cells outside of the resampling bounds are highlighted.
+    ParticlesPerCell,
     #[cfg(debug_assertions)]
     Debug,
 }
@@ -31,14 +35,17 @@ impl VolumeRenderer {
         global_bind_group_layout: &wgpu::BindGroupLayout,
         fluid_renderer_group_layout: &wgpu::BindGroupLayout,
     ) -> Self {
-        let layout = Rc::new(device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("Volume Renderer Pipeline Layout"),
-            bind_group_layouts: &[&global_bind_group_layout, &fluid_renderer_group_layout],
-            push_constant_ranges: &[wgpu::PushConstantRange {
-                stages: wgpu::ShaderStage::VERTEX,
-                range: 0..4,
-            }],
-        }));
+        let layout = pipeline_manager.create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Volume Renderer Pipeline Layout"),
+                bind_group_layouts: &[&global_bind_group_layout, &fluid_renderer_group_layout],
+                push_constant_ranges: &[wgpu::PushConstantRange {
+                    stages: wgpu::ShaderStage::VERTEX,
+                    range: 0..8,
+                }],
+            },
+        );
 
         let mut velocity_render_pipeline_desc = RenderPipelineCreationDesc::new(
             "VolumeRender: Velocity",
@@ -85,27 +92,33 @@ impl VolumeRenderer {
             VolumeVisualizationMode::None => {}
             VolumeVisualizationMode::Velocity => {
                 rpass.set_pipeline(pipeline_manager.get_render(&self.velocity_render_pipeline));
+                pipeline_manager.bind_push_constants_render(rpass, &self.velocity_render_pipeline);
                 rpass.set_bind_group(1, fluid.bind_group_renderer(), &[]);
                 rpass.draw(0..2, 0..Self::num_grid_cells(fluid.grid_dimension()) * 3);
             }
             _ => {
                 rpass.set_pipeline(pipeline_manager.get_render(&self.volume_visualization_with_billboards_pipeline));
                 rpass.set_bind_group(1, fluid.bind_group_renderer(), &[]);
-                match mode {
-                    VolumeVisualizationMode::DivergenceError => {
-                        rpass.set_push_constants(wgpu::ShaderStage::VERTEX, 0, bytemuck::cast_slice(&[0 as u32]))
-                    }
-                    VolumeVisualizationMode::PressureFromVelocity => {
-                        rpass.set_push_constants(wgpu::ShaderStage::VERTEX, 0, bytemuck::cast_slice(&[1 as u32]))
-                    }
-                    VolumeVisualizationMode::PressureFromDensity => {
-                        rpass.set_push_constants(wgpu::ShaderStage::VERTEX, 0, bytemuck::cast_slice(&[2 as u32]))
-                    }
-                    VolumeVisualizationMode::Marker => rpass.set_push_constants(wgpu::ShaderStage::VERTEX, 0, bytemuck::cast_slice(&[3 as u32])),
+                let visualization_type = match mode {
+                    VolumeVisualizationMode::DivergenceError => Some(0 as u32),
+                    VolumeVisualizationMode::PressureFromVelocity => Some(1),
+                    VolumeVisualizationMode::PressureFromDensity => Some(2),
+                    VolumeVisualizationMode::Marker => Some(3),
+                    VolumeVisualizationMode::MarkerChanges => Some(5),
+                    VolumeVisualizationMode::ParticlesPerCell => Some(6),
                     #[cfg(debug_assertions)]
-                    VolumeVisualizationMode::Debug => rpass.set_push_constants(wgpu::ShaderStage::VERTEX, 0, bytemuck::cast_slice(&[4 as u32])),
-                    _ => {}
+                    VolumeVisualizationMode::Debug => Some(4),
+                    _ => None,
                 };
+                if let Some(visualization_type) = visualization_type {
+                    pipeline_manager.set_push_constants_render(
+                        rpass,
+                        &self.volume_visualization_with_billboards_pipeline,
+                        wgpu::ShaderStage::VERTEX,
+                        0,
+                        bytemuck::cast_slice(&[visualization_type, fluid.particles_per_cell()]),
+                    );
+                }
                 rpass.draw(0..6, 0..Self::num_grid_cells(fluid.grid_dimension()));
             }
         }
@@ -1,17 +1,89 @@
-use std::{path::Path, rc::Rc};
+use std::{path::Path, sync::Arc};
 
 use crate::wgpu_utils::{binding_builder::*, binding_glsl, pipelines::*, shader::ShaderDirectory};
 use crate::{
+    camera::Frustum,
     render_output::{hdr_backbuffer::HdrBackbuffer, screen::Screen},
-    scene::Scene,
+    scene::{FluidConfig, Scene},
 };
 
 pub struct VoxelRenderer {
     pipeline: RenderPipelineHandle,
+    slice_pipeline: RenderPipelineHandle,
     group_layout: BindGroupLayoutWithDesc,
     bind_group: Option<wgpu::BindGroup>,
 }
 
+// Shows a single slice through the voxelized scene as a 2d overlay.
+#[derive(Clone, Copy)]
+pub struct VoxelSliceSettings {
+    pub enabled: bool,
+    pub axis: usize, // 0=x, 1=y, 2=z, the slice is orthogonal to this axis
+    pub index: u32,
+    // Shows the simulation values of the cell under the cursor, see GridCellInspector.
+    pub inspect: bool,
+}
+
+impl Default for VoxelSliceSettings {
+    fn default() -> Self {
+        VoxelSliceSettings {
+            enabled: false,
+            axis: 2,
+            index: 0,
+            inspect: false,
+        }
+    }
+}
+
+impl VoxelSliceSettings {
+    fn plane_axes(&self) -> (usize, usize) {
+        match self.axis.min(2) {
+            0 => (1, 2),
+            1 => (0, 2),
+            _ => (0, 1),
+        }
+    }
+
+    // Where the slice is shown on a target of the given resolution: (x, y, width, height) in pixels.
+    pub fn viewport(&self, resolution: winit::dpi::PhysicalSize<u32>, grid_dimension: &cgmath::Point3<u32>) -> (f32, f32, f32, f32) {
+        let grid_dimension = [grid_dimension.x, grid_dimension.y, grid_dimension.z];
+        let (axis_u, axis_v) = self.plane_axes();
+        let plane_size = (grid_dimension[axis_u], grid_dimension[axis_v]);
+        // Integer scale keeps cells the same size on screen.
+        const MARGIN: u32 = 16;
+        let max_size = (resolution.width.min(resolution.height) * 2 / 5).max(1);
+        let scale = (max_size / plane_size.0.max(plane_size.1).max(1)).max(1) as f32;
+        let width = plane_size.0 as f32 * scale;
+        let height = plane_size.1 as f32 * scale;
+        let x = (resolution.width as f32 - width - MARGIN as f32).max(0.0);
+        let y = (resolution.height as f32 - height - MARGIN as f32).max(0.0);
+        (x, y, width.min(resolution.width as f32 - x), height.min(resolution.height as f32 - y))
+    }
+
+    // Grid cell shown at a pixel position, None if the position is outside of the slice.
+    pub fn cell_at(
+        &self,
+        pixel: cgmath::Point2<f32>,
+        resolution: winit::dpi::PhysicalSize<u32>,
+        grid_dimension: &cgmath::Point3<u32>,
+    ) -> Option<cgmath::Point3<u32>> {
+        let (x, y, width, height) = self.viewport(resolution, grid_dimension);
+        let u = (pixel.x - x) / width;
+        let v = (pixel.y - y) / height;
+        if !(0.0..1.0).contains(&u) || !(0.0..1.0).contains(&v) {
+            return None;
+        }
+
+        let grid_dimension = [grid_dimension.x, grid_dimension.y, grid_dimension.z];
+        let (axis_u, axis_v) = self.plane_axes();
+        let mut cell = [0; 3];
+        cell[self.axis.min(2)] = self.index.min(grid_dimension[self.axis.min(2)].max(1) - 1);
+        cell[axis_u] = ((u * grid_dimension[axis_u] as f32) as u32).min(grid_dimension[axis_u].max(1) - 1);
+        cell[axis_v] = ((v * grid_dimension[axis_v] as f32) as u32).min(grid_dimension[axis_v].max(1) - 1);
+        Some(cgmath::point3(cell[0], cell[1], cell[2]))
+    }
+}
+
 impl VoxelRenderer {
     pub fn new(
         device: &wgpu::Device,
@@ -26,7 +98,7 @@ impl VoxelRenderer {
 
         let mut desc = RenderPipelineCreationDesc::new(
             "Visualize Voxels",
-            Rc::new(device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            Arc::new(device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("Visualize Voxels Pipeline Layout"),
                 bind_group_layouts: &[&global_bind_group_layout, background_and_lighting_group_layout, &group_layout.layout],
                 push_constant_ranges: &[],
@@ -39,8 +111,33 @@ impl VoxelRenderer {
         desc.primitive.topology = wgpu::PrimitiveTopology::TriangleStrip;
         let pipeline = pipeline_manager.create_render_pipeline(device, shader_dir, desc);
 
+        let slice_pipeline_layout = pipeline_manager.create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Visualize Voxel Slice Pipeline Layout"),
+                bind_group_layouts: &[&global_bind_group_layout, &group_layout.layout],
+                push_constant_ranges: &[wgpu::PushConstantRange {
+                    stages: wgpu::ShaderStage::FRAGMENT,
+                    range: 0..8,
+                }],
+            },
+        );
+        let slice_pipeline = pipeline_manager.create_render_pipeline(
+            device,
+            shader_dir,
+            RenderPipelineCreationDesc::new(
+                "Visualize Voxel Slice",
+                slice_pipeline_layout,
+                Path::new("volume_visualization/voxel_slice.vert"),
+                Path::new("volume_visualization/voxel_slice.frag"),
+                HdrBackbuffer::FORMAT,
+                None,
+            ),
+        );
+
         VoxelRenderer {
             pipeline,
+            slice_pipeline,
             group_layout,
             bind_group: None,
         }
@@ -59,7 +156,8 @@ impl VoxelRenderer {
         rpass: &mut wgpu::RenderPass<'a>,
         pipeline_manager: &'a PipelineManager,
         background_and_lighting_bind_group: &'a wgpu::BindGroup,
-        grid_dimension: &cgmath::Point3<u32>,
+        fluid_config: &FluidConfig,
+        frustum: Option<&Frustum>,
     ) {
         let bind_group = match self.bind_group.as_ref() {
             Some(bind_group) => bind_group,
@@ -73,6 +171,74 @@ impl VoxelRenderer {
         rpass.set_bind_group(2, bind_group, &[]);
 
         // this is heavy, but fine for debug viz..
-        rpass.draw(0..14, 0..(grid_dimension.x * grid_dimension.y * grid_dimension.z));
+        // Instances are laid out slice by slice along z, so we can skip slices that are entirely outside of the frustum.
+        let grid_dimension = fluid_config.grid_dimension;
+        let instances_per_slice = grid_dimension.x * grid_dimension.y;
+        let slice_extent = cgmath::vec3(grid_dimension.x as f32, grid_dimension.y as f32, 1.0) * fluid_config.grid_to_world_scale;
+        let is_slice_visible = |z: u32| {
+            frustum.map_or(true, |frustum| {
+                let slice_min = fluid_config.world_position + cgmath::vec3(0.0, 0.0, z as f32 * fluid_config.grid_to_world_scale);
+                frustum.intersects_box(slice_min, slice_min + slice_extent)
+            })
+        };
+        let mut z = 0;
+        while z < grid_dimension.z {
+            if !is_slice_visible(z) {
+                z += 1;
+                continue;
+            }
+            let first_slice = z;
+            while z < grid_dimension.z && is_slice_visible(z) {
+                z += 1;
+            }
+            rpass.draw(0..14, (first_slice * instances_per_slice)..(z * instances_per_slice));
+        }
+    }
+
+    // Draws the slice into the lower right corner of the backbuffer.
+    pub fn draw_slice(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline_manager: &PipelineManager,
+        global_bind_group: &wgpu::BindGroup,
+        backbuffer: &HdrBackbuffer,
+        grid_dimension: &cgmath::Point3<u32>,
+        settings: VoxelSliceSettings,
+    ) {
+        let bind_group = match self.bind_group.as_ref() {
+            Some(bind_group) => bind_group,
+            None => {
+                return;
+            }
+        };
+
+        let axis = settings.axis.min(2);
+        let (x, y, width, height) = settings.viewport(backbuffer.resolution(), grid_dimension);
+        let grid_dimension = [grid_dimension.x, grid_dimension.y, grid_dimension.z];
+
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("voxel slice"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: backbuffer.texture_view(),
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+        rpass.set_viewport(x, y, width, height, 0.0, 1.0);
+        rpass.set_pipeline(pipeline_manager.get_render(&self.slice_pipeline));
+        rpass.set_bind_group(0, global_bind_group, &[]);
+        rpass.set_bind_group(1, bind_group, &[]);
+        pipeline_manager.set_push_constants_render(
+            &mut rpass,
+            &self.slice_pipeline,
+            wgpu::ShaderStage::FRAGMENT,
+            0,
+            bytemuck::cast_slice(&[axis as u32, settings.index.min(grid_dimension[axis].max(1) - 1)]),
+        );
+        rpass.draw(0..3, 0..1);
     }
 }
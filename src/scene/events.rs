@@ -0,0 +1,31 @@
+use serde::Deserialize;
+
+// Action scheduled at a point in simulated time, allows choreographing a scene beyond its initial state.
+#[derive(Deserialize)]
+pub struct SceneEventConfig {
+    // Simulated time in seconds at which the action is triggered (with the first simulation step at or after it).
+    pub time: f32,
+    pub action: SceneEventAction,
+}
+
+#[derive(Deserialize)]
+pub enum SceneEventAction {
+    // Spawns a box of fluid for liquids, adds an emitter for smoke.
+    AddFluid(super::Box),
+    // Removes all fluid particles within a box (initial velocity and phase are ignored). Not supported for smoke.
+    RemoveFluid(super::Box),
+    // New global gravity in world space.
+    SetGravity(cgmath::Vector3<f32>),
+    // Starts the animation of a static object (index into SceneConfig::static_objects).
+    // Objects referenced by this action rest at their initial pose until then.
+    StartAnimation(usize),
+}
+
+impl SceneEventAction {
+    pub fn animated_object(&self) -> Option<usize> {
+        match self {
+            SceneEventAction::StartAnimation(object_index) => Some(*object_index),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use super::FluidConfig;
+use crate::simulation::{ForceFieldUniformBufferContent, MAX_NUM_FORCE_FIELDS};
+use cgmath::{EuclideanSpace, InnerSpace};
+use serde::Deserialize;
+
+// External force acting on the fluid in addition to gravity. All values in world space.
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub enum ForceField {
+    // Constant acceleration everywhere in the domain.
+    Wind {
+        acceleration: cgmath::Vector3<f32>,
+    },
+    // Accelerates tangentially around an axis through center.
+    Vortex {
+        center: cgmath::Point3<f32>,
+        axis: cgmath::Vector3<f32>,
+        strength: f32,
+        radius: f32,
+    },
+    // Accelerates towards a point (negative strength pushes away).
+    Attractor {
+        position: cgmath::Point3<f32>,
+        strength: f32,
+        radius: f32,
+    },
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub struct ForceFieldConfig {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    #[serde(flatten)]
+    pub field: ForceField,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+impl ForceField {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ForceField::Wind { .. } => "Wind",
+            ForceField::Vortex { .. } => "Vortex",
+            ForceField::Attractor { .. } => "Attractor",
+        }
+    }
+}
+
+// Converts all enabled force fields to grid space for the simulation. Superfluous fields are dropped.
+pub fn force_fields_to_grid(force_fields: &[ForceFieldConfig], fluid_config: &FluidConfig) -> Vec<ForceFieldUniformBufferContent> {
+    let world_to_grid = 1.0 / fluid_config.grid_to_world_scale;
+    let to_grid = |p: cgmath::Point3<f32>| cgmath::Point3::from_vec((p - fluid_config.world_position) * world_to_grid);
+
+    force_fields
+        .iter()
+        .filter(|config| config.enabled)
+        .take(MAX_NUM_FORCE_FIELDS)
+        .map(|config| match config.field {
+            ForceField::Wind { acceleration } => ForceFieldUniformBufferContent::wind(acceleration * world_to_grid),
+            ForceField::Vortex {
+                center,
+                axis,
+                strength,
+                radius,
+            } => ForceFieldUniformBufferContent::vortex(
+                to_grid(center),
+                if axis.magnitude2() > 0.0 {
+                    axis.normalize()
+                } else {
+                    cgmath::Vector3::unit_y()
+                },
+                strength * world_to_grid,
+                radius * world_to_grid,
+            ),
+            ForceField::Attractor { position, strength, radius } => {
+                ForceFieldUniformBufferContent::attractor(to_grid(position), strength * world_to_grid, radius * world_to_grid)
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,95 @@
+use super::models::MeshVertex;
+use cgmath::InnerSpace;
+use serde::Deserialize;
+use std::{error::Error, path::Path, path::PathBuf};
+
+// Terrain obstacle generated from a grayscale image instead of loaded from an obj file.
+// The image is stretched over the x/z extent of the object (centered on the origin), black is at y=0 and white at y=height.
+// Every pixel becomes a vertex, so large images should be scaled down beforehand.
+#[derive(Deserialize, Clone)]
+pub struct HeightfieldConfig {
+    // Relative to the models directory, like obj models.
+    pub image: PathBuf,
+    // Object space size along x & z.
+    pub extent: cgmath::Vector2<f32>,
+    // Object space height of a white pixel.
+    pub height: f32,
+}
+
+impl HeightfieldConfig {
+    // Generates a closed mesh (surface, side skirts down to y=0 and a bottom) so that the hull voxelization sees a proper solid.
+    pub fn generate_mesh(&self) -> Result<(Vec<MeshVertex>, Vec<u32>), Box<dyn Error>> {
+        let path = Path::new("models").join(&self.image);
+        info!("Generating heightfield from {:?}", path);
+        let image = image::io::Reader::open(&path)?.decode()?.to_luma16();
+        let (width, depth) = (image.width(), image.height());
+        if width < 2 || depth < 2 {
+            return Err(format!("heightfield image {:?} needs to be at least 2x2 pixels", path).into());
+        }
+
+        let height_at = |x: u32, z: u32| image.get_pixel(x.min(width - 1), z.min(depth - 1))[0] as f32 / std::u16::MAX as f32 * self.height;
+        let position_at = |x: u32, z: u32, y: f32| {
+            cgmath::point3(
+                (x as f32 / (width - 1) as f32 - 0.5) * self.extent.x,
+                y,
+                (z as f32 / (depth - 1) as f32 - 0.5) * self.extent.y,
+            )
+        };
+        let uv_at = |x: u32, z: u32| cgmath::point2(x as f32 / (width - 1) as f32, z as f32 / (depth - 1) as f32);
+        let cell_size = cgmath::vec2(self.extent.x / (width - 1) as f32, self.extent.y / (depth - 1) as f32);
+
+        let mut vertices = Vec::with_capacity((width * depth) as usize);
+        let mut indices = Vec::with_capacity(((width - 1) * (depth - 1) * 6) as usize);
+
+        // Surface, normals from central differences.
+        for z in 0..depth {
+            for x in 0..width {
+                let dx = (height_at(x + 1, z) - height_at(x.saturating_sub(1), z)) / (cell_size.x * 2.0);
+                let dz = (height_at(x, z + 1) - height_at(x, z.saturating_sub(1))) / (cell_size.y * 2.0);
+                let normal = cgmath::vec3(-dx, 1.0, -dz).normalize();
+                vertices.push(MeshVertex::new(position_at(x, z, height_at(x, z)), normal, uv_at(x, z)));
+            }
+        }
+        for z in 0..depth - 1 {
+            for x in 0..width - 1 {
+                let i = z * width + x;
+                indices.extend_from_slice(&[i, i + width, i + 1, i + 1, i + width, i + width + 1]);
+            }
+        }
+
+        // Skirts along the four borders, walking around the heightfield in a consistent direction.
+        let borders: [(Vec<(u32, u32)>, cgmath::Vector3<f32>); 4] = [
+            ((0..width).map(|x| (x, 0)).collect(), -cgmath::Vector3::unit_z()),
+            ((0..depth).map(|z| (width - 1, z)).collect(), cgmath::Vector3::unit_x()),
+            ((0..width).rev().map(|x| (x, depth - 1)).collect(), cgmath::Vector3::unit_z()),
+            ((0..depth).rev().map(|z| (0, z)).collect(), -cgmath::Vector3::unit_x()),
+        ];
+        for (border, normal) in borders.iter() {
+            let first_vertex = vertices.len() as u32;
+            for &(x, z) in border.iter() {
+                vertices.push(MeshVertex::new(position_at(x, z, height_at(x, z)), *normal, uv_at(x, z)));
+                vertices.push(MeshVertex::new(position_at(x, z, 0.0), *normal, uv_at(x, z)));
+            }
+            for i in 0..border.len() as u32 - 1 {
+                let top = first_vertex + i * 2;
+                indices.extend_from_slice(&[top, top + 2, top + 1, top + 2, top + 3, top + 1]);
+            }
+        }
+
+        // Bottom
+        let first_vertex = vertices.len() as u32;
+        for &(x, z) in [(0, 0), (width - 1, 0), (0, depth - 1), (width - 1, depth - 1)].iter() {
+            vertices.push(MeshVertex::new(position_at(x, z, 0.0), -cgmath::Vector3::unit_y(), uv_at(x, z)));
+        }
+        indices.extend_from_slice(&[
+            first_vertex,
+            first_vertex + 1,
+            first_vertex + 2,
+            first_vertex + 2,
+            first_vertex + 1,
+            first_vertex + 3,
+        ]);
+
+        Ok((vertices, indices))
+    }
+}
@@ -0,0 +1,74 @@
+use serde::Deserialize;
+
+// Light source of a scene. All values in world space.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum Light {
+    // Infinitely far away light (sun), direction is the one the light travels in.
+    Directional {
+        direction: cgmath::Vector3<f32>,
+    },
+    // Shines in all directions, falls off with the squared distance.
+    Point {
+        position: cgmath::Point3<f32>,
+    },
+    // Point light restricted to a cone around direction.
+    // Angles are half angles of the cone in degrees, light fades out between inner & outer angle.
+    Spot {
+        position: cgmath::Point3<f32>,
+        direction: cgmath::Vector3<f32>,
+        outer_angle: f32,
+        #[serde(default)]
+        inner_angle: f32,
+    },
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct LightConfig {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    // Linear rgb, scaled by intensity.
+    #[serde(default = "default_color")]
+    pub color: cgmath::Vector3<f32>,
+    pub intensity: f32,
+    #[serde(flatten)]
+    pub light: Light,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_color() -> cgmath::Vector3<f32> {
+    cgmath::vec3(1.0, 1.0, 1.0)
+}
+
+impl Light {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Light::Directional { .. } => "Directional",
+            Light::Point { .. } => "Point",
+            Light::Spot { .. } => "Spot",
+        }
+    }
+
+    // Direction the light travels in, None for lights without one.
+    pub fn direction(&self) -> Option<cgmath::Vector3<f32>> {
+        match *self {
+            Light::Directional { direction } | Light::Spot { direction, .. } => Some(direction),
+            Light::Point { .. } => None,
+        }
+    }
+
+    pub fn set_direction(&mut self, new_direction: cgmath::Vector3<f32>) {
+        match self {
+            Light::Directional { direction } | Light::Spot { direction, .. } => *direction = new_direction,
+            Light::Point { .. } => {}
+        }
+    }
+}
+
+impl LightConfig {
+    pub fn radiance(&self) -> cgmath::Vector3<f32> {
+        self.color * self.intensity
+    }
+}
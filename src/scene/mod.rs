@@ -1,18 +1,29 @@
+pub mod events;
+pub mod force_fields;
+pub mod heightfield;
+pub mod lights;
 pub mod models;
+pub mod vdb_import;
 pub mod voxelization;
 
 use crate::{
-    simulation::HybridFluid,
+    settings_overrides::SettingsOverrides,
+    simulation::{HybridFluid, PlaybackCache, SimulationStage, SmokeSimulation, MAX_NUM_FLUID_PHASES},
     timer::Timer,
-    wgpu_utils::{pipelines::PipelineManager, shader::ShaderDirectory},
+    utils::merge_json,
+    wgpu_utils::{fence_profiler::FenceProfiler, pipelines::PipelineManager, profiling::ProfilingDetail, shader::ShaderDirectory},
 };
-use wgpu_profiler::{wgpu_profiler, GpuProfiler};
+use wgpu_profiler::GpuProfiler;
 
 use serde::Deserialize;
 use std::{error, fs::File, io::BufReader, path::Path, path::PathBuf};
 
 use self::{
-    models::{SceneModels, StaticObjectConfig},
+    events::{SceneEventAction, SceneEventConfig},
+    force_fields::{force_fields_to_grid, ForceFieldConfig},
+    lights::LightConfig,
+    models::{SceneModels, SceneModelsData, StaticObjectConfig},
+    vdb_import::VdbFluidSource,
     voxelization::SceneVoxelization,
 };
 
@@ -20,16 +31,173 @@ use self::{
 pub struct Box {
     pub min: cgmath::Point3<f32>,
     pub max: cgmath::Point3<f32>,
+    // Velocity all particles spawned in this box start out with (in world space)
+    #[serde(default = "zero_velocity")]
+    pub initial_velocity: cgmath::Vector3<f32>,
+    // Index into FluidConfig::phases
+    #[serde(default)]
+    pub phase: u32,
+}
+
+fn zero_velocity() -> cgmath::Vector3<f32> {
+    cgmath::vec3(0.0, 0.0, 0.0)
+}
+
+// Symmetry of the fluid domain.
+// Only the simulated part needs to be described in the scene, rendering mirrors it on the positive x (and z) domain boundary.
+// Since the domain boundary is a (free slip) wall, this is equivalent to simulating the full, symmetric domain.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum FluidSymmetry {
+    None = 0,
+    MirrorX = 1,
+    MirrorXZ = 3,
+}
+
+impl Default for FluidSymmetry {
+    fn default() -> Self {
+        FluidSymmetry::None
+    }
+}
+
+impl FluidSymmetry {
+    // Number of rendered copies of the simulated domain (including the original).
+    pub fn num_instances(&self) -> u32 {
+        match self {
+            FluidSymmetry::None => 1,
+            FluidSymmetry::MirrorX => 2,
+            FluidSymmetry::MirrorXZ => 4,
+        }
+    }
+}
+
+// One of several immiscible fluids in a scene (e.g. water & oil).
+#[derive(Deserialize, Clone, Copy)]
+pub struct FluidPhaseConfig {
+    // Only the ratio between the densities of all phases matters.
+    pub density: f32,
+    // Extinction coefficient per meter (rgb), determines the color of the fluid.
+    #[serde(default = "default_phase_absorption")]
+    pub absorption: cgmath::Vector3<f32>,
+}
+
+fn default_phase_absorption() -> cgmath::Vector3<f32> {
+    cgmath::vec3(0.46, 0.18, 0.06)
+}
+
+impl Default for FluidPhaseConfig {
+    fn default() -> Self {
+        FluidPhaseConfig {
+            density: 1000.0,
+            absorption: default_phase_absorption(),
+        }
+    }
+}
+
+// What kind of simulation runs in the fluid domain.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SimulationType {
+    // Particle based liquid (see HybridFluid)
+    Liquid,
+    // Purely grid based smoke/gas without any particles (see SmokeSimulation). fluid_cubes act as continuous emitters.
+    Smoke,
+}
+
+impl Default for SimulationType {
+    fn default() -> Self {
+        SimulationType::Liquid
+    }
+}
+
+// Parameters of the smoke simulation, only used if the simulation type is smoke.
+#[derive(Deserialize, Clone, Copy)]
+#[serde(default)]
+pub struct SmokeConfig {
+    // Upward acceleration per degree above ambient temperature, relative to gravity.
+    pub buoyancy: f32,
+    // Downward acceleration per unit of smoke density, relative to gravity.
+    pub density_weight: f32,
+    pub ambient_temperature: f32,
+    // Smoke density & temperature inside the emitters.
+    pub source_density: f32,
+    pub source_temperature: f32,
+    // Strength of the force that reintroduces small scale swirls lost to numerical dissipation.
+    pub vorticity_confinement: f32,
+    // Fraction of smoke density & temperature difference lost per second.
+    pub dissipation: f32,
+}
+
+impl Default for SmokeConfig {
+    fn default() -> Self {
+        SmokeConfig {
+            buoyancy: 1.0,
+            density_weight: 0.05,
+            ambient_temperature: 0.0,
+            source_density: 1.0,
+            source_temperature: 1.0,
+            vorticity_confinement: 0.3,
+            dissipation: 0.05,
+        }
+    }
 }
 
 // Data describing a fluid in the scene.
 #[derive(Deserialize)]
 pub struct FluidConfig {
+    #[serde(rename = "type", default)]
+    pub simulation_type: SimulationType,
     pub world_position: cgmath::Point3<f32>,
     pub grid_to_world_scale: f32,
     pub grid_dimension: cgmath::Point3<u32>,
     pub max_num_particles: u32,
     pub fluid_cubes: Vec<Box>,
+    // Fluid volumes imported from OpenVDB files
+    #[serde(default)]
+    pub vdb_sources: Vec<VdbFluidSource>,
+    #[serde(default)]
+    pub symmetry: FluidSymmetry,
+    // Fluid phases, referenced by fluid_cubes & vdb_sources. If empty, there is only water.
+    #[serde(default)]
+    pub phases: Vec<FluidPhaseConfig>,
+    // Number of particles a fluid cell is filled with at rest density. More particles give smoother results at higher cost.
+    #[serde(default = "default_particles_per_cell")]
+    pub particles_per_cell: u32,
+    // Surface tension coefficient divided by the density of the primary phase in m³/s² (water is about 7.3e-5). Zero disables it.
+    // Realistic values barely matter at typical grid resolutions, larger ones exaggerate the effect on droplets and thin streams.
+    #[serde(default)]
+    pub surface_tension: f32,
+    // Particles are removed after this many seconds, e.g. to keep scenes with continuously added fluid from filling up. Zero means no limit.
+    #[serde(default)]
+    pub particle_lifetime: f32,
+    #[serde(default)]
+    pub smoke: SmokeConfig,
+    // Axes (x, y, z) along which the domain wraps around instead of being bounded by walls.
+    // Useful for studying bulk behavior without wall effects. Only supported by the liquid simulation.
+    #[serde(default)]
+    pub periodic: [bool; 3],
+    // Seed for the jitter of initial particle positions. Fixed by default, so that runs (and changes to e.g. max_num_particles) are comparable.
+    #[serde(default)]
+    pub seed: u64,
+}
+
+fn default_particles_per_cell() -> u32 {
+    HybridFluid::DEFAULT_PARTICLES_PER_GRID_CELL
+}
+
+impl FluidConfig {
+    // Bit per periodic axis, as used by the simulation shaders.
+    pub fn periodic_axes(&self) -> u32 {
+        self.periodic.iter().enumerate().map(|(axis, &periodic)| (periodic as u32) << axis).sum()
+    }
+
+    // All phases the simulation supports, missing ones are filled up with the default (water).
+    pub fn phases(&self) -> [FluidPhaseConfig; MAX_NUM_FLUID_PHASES] {
+        let mut phases = [FluidPhaseConfig::default(); MAX_NUM_FLUID_PHASES];
+        for (phase, config) in phases.iter_mut().zip(self.phases.iter()) {
+            *phase = *config;
+        }
+        phases
+    }
 }
 
 // Data describing a scene.
@@ -40,30 +208,199 @@ pub struct SceneConfig {
     pub fluid: FluidConfig,
     #[serde(default)]
     pub static_objects: Vec<StaticObjectConfig>,
+    // External forces in addition to gravity (wind, vortices, attractors)
+    #[serde(default)]
+    pub force_fields: Vec<ForceFieldConfig>,
+    // Replaces the sun of the background if not empty. The first directional light casts caustics.
+    #[serde(default)]
+    pub lights: Vec<LightConfig>,
+    // Optional .cube file for color grading the final image (applied after tonemapping)
+    #[serde(default)]
+    pub color_grading_lut: Option<PathBuf>,
+    // Actions triggered at specific simulation times.
+    #[serde(default)]
+    pub events: Vec<SceneEventConfig>,
+}
+
+// Reads the json of a scene file, resolving "extends".
+// A scene with "extends" (path relative to the scene file) starts out with everything of the scene it names,
+// its own values are merged on top (json merge patch, i.e. objects are merged, arrays & values replaced, null removes).
+// Bases can extend other scenes in turn.
+pub fn load_scene_json(path: &Path) -> Result<serde_json::Value, std::boxed::Box<dyn error::Error>> {
+    load_scene_json_recursive(path, &mut Vec::new())
+}
+
+fn load_scene_json_recursive(path: &Path, visited: &mut Vec<PathBuf>) -> Result<serde_json::Value, std::boxed::Box<dyn error::Error>> {
+    let canonical_path = path.canonicalize()?;
+    if visited.contains(&canonical_path) {
+        return Err(format!("scene {:?} is part of an \"extends\" cycle ({:?})", path, visited).into());
+    }
+    visited.push(canonical_path);
+
+    let mut scene_json: serde_json::Value = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+    let base_path = match scene_json.as_object_mut().and_then(|scene| scene.remove("extends")) {
+        None => return Ok(scene_json),
+        Some(serde_json::Value::String(base)) => path.parent().unwrap_or_else(|| Path::new("")).join(base),
+        Some(other) => return Err(format!("\"extends\" in {:?} needs to be a path, got {}", path, other).into()),
+    };
+    let mut base_json =
+        load_scene_json_recursive(&base_path, visited).map_err(|err| format!("failed to load base scene {:?} of {:?}: {}", base_path, path, err))?;
+    merge_json(&mut base_json, &scene_json);
+    Ok(base_json)
+}
+
+// Everything of a scene that is loaded from disk, validated but without any gpu resources.
+// Doesn't need a device, so it can be loaded on a background thread. See Scene::from_data.
+pub struct SceneData {
+    path: PathBuf,
+    config: SceneConfig,
+    models: SceneModelsData,
+}
+
+// Below this, boundary cells make up most of the domain.
+const MIN_GRID_DIMENSION: u32 = 4;
+// Maximum 3d texture size of the device (we don't ask for more than the wgpu default).
+const MAX_GRID_DIMENSION: u32 = 2048;
+
+fn validate_grid_dimension(grid_dimension: cgmath::Point3<u32>) -> Result<(), String> {
+    let dimensions = [grid_dimension.x, grid_dimension.y, grid_dimension.z];
+    if dimensions.iter().any(|&size| size < MIN_GRID_DIMENSION || size > MAX_GRID_DIMENSION) {
+        return Err(format!(
+            "grid dimension {}x{}x{} is not supported, every axis needs to be between {} and {}",
+            grid_dimension.x, grid_dimension.y, grid_dimension.z, MIN_GRID_DIMENSION, MAX_GRID_DIMENSION
+        ));
+    }
+    // Cell indices (and the pressure solver's reduction) are 32 bit.
+    if dimensions.iter().try_fold(1u32, |num_cells, &size| num_cells.checked_mul(size)).is_none() {
+        return Err(format!(
+            "grid dimension {}x{}x{} has too many cells, there can be at most {}",
+            grid_dimension.x,
+            grid_dimension.y,
+            grid_dimension.z,
+            u32::MAX
+        ));
+    }
+    Ok(())
+}
+
+impl SceneData {
+    // Loads a scene file with the scene overrides applied to its json. Doesn't need the gpu, see SceneLoader.
+    // Overrides that don't end up in the config (unknown keys) are an error, other than unknown keys in the scene file itself.
+    pub fn load_file(path: &Path, settings_overrides: &SettingsOverrides) -> Result<Self, std::boxed::Box<dyn error::Error>> {
+        let mut scene_json = load_scene_json(path)?;
+        settings_overrides.apply_to_scene_json(&mut scene_json);
+        let mut ignored_paths = Vec::new();
+        let config: SceneConfig = serde_ignored::deserialize(scene_json, |ignored_path| {
+            // Options & newtypes show up as "?" segments.
+            let ignored_path = ignored_path.to_string();
+            ignored_paths.push(ignored_path.split('.').filter(|segment| *segment != "?").collect::<Vec<_>>().join("."));
+        })?;
+        settings_overrides.check_ignored_scene_paths(&ignored_paths)?;
+        Self::load(path, config)
+    }
+
+    // Path is only used for identification, the config is expected to be parsed already.
+    pub fn load(path: &Path, mut config: SceneConfig) -> Result<Self, std::boxed::Box<dyn error::Error>> {
+        validate_grid_dimension(config.fluid.grid_dimension)?;
+        config.events.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap());
+        let mirrored_axes = match config.fluid.symmetry {
+            FluidSymmetry::None => [false, false, false],
+            FluidSymmetry::MirrorX => [true, false, false],
+            FluidSymmetry::MirrorXZ => [true, false, true],
+        };
+        if mirrored_axes
+            .iter()
+            .zip(config.fluid.periodic.iter())
+            .any(|(&mirrored, &periodic)| mirrored && periodic)
+        {
+            return Err("fluid symmetry mirrors the domain on its boundary, mirrored axes can't be periodic".into());
+        }
+        if config.fluid.periodic_axes() != 0 && config.fluid.simulation_type == SimulationType::Smoke {
+            warn!("Periodic boundaries are not supported by the smoke simulation, ignoring them");
+        }
+        // Densities are only used relative to the first phase, anything non-positive would give inf/NaN.
+        if let Some(phase) = config.fluid.phases.iter().find(|phase| !(phase.density > 0.0)) {
+            return Err(format!("fluid phase densities need to be positive, got {}", phase.density).into());
+        }
+
+        let models = SceneModelsData::load(&config.static_objects)?;
+        Ok(SceneData {
+            path: path.to_path_buf(),
+            config,
+            models,
+        })
+    }
 }
 
 // Scene data & simulation.
 pub struct Scene {
     hybrid_fluid: HybridFluid,
+    // Only present if the simulation type is smoke, the hybrid fluid stays empty then.
+    smoke: Option<SmokeSimulation>,
     config: SceneConfig,
     pub models: SceneModels,
     pub voxelization: SceneVoxelization,
+    // Live copy of the configured force fields, editable at runtime.
+    pub force_fields: Vec<ForceFieldConfig>,
+    // Live copy of the configured lights, editable at runtime.
+    pub lights: Vec<LightConfig>,
+    // Gravity in world space, starts out as configured but may be changed by events.
+    gravity: cgmath::Vector3<f32>,
+    // Index of the next event in config.events that hasn't been triggered yet.
+    next_event_index: usize,
     distance_field_dirty: bool,
     path: PathBuf,
+    pub playback_cache: PlaybackCache,
 }
 
 impl Scene {
-    pub fn new(
+    // Loads a scene file with all settings overrides applied, see SceneData::load_file & Scene::from_data for loading in two steps.
+    pub fn load(
+        path: &Path,
+        settings_overrides: &SettingsOverrides,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        shader_dir: &ShaderDirectory,
+        pipeline_manager: &mut PipelineManager,
+        global_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Result<Self, std::boxed::Box<dyn error::Error>> {
+        let data = SceneData::load_file(path, settings_overrides)?;
+        let mut scene = Self::from_data(data, device, queue, shader_dir, pipeline_manager, global_bind_group_layout);
+        settings_overrides.apply(&mut scene);
+        Ok(scene)
+    }
+
+    // Creates a scene from an already parsed config (see Scene::load for loading from file). Path is only used for identification.
+    pub fn from_config(
         path: &Path,
+        config: SceneConfig,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         shader_dir: &ShaderDirectory,
         pipeline_manager: &mut PipelineManager,
         global_bind_group_layout: &wgpu::BindGroupLayout,
     ) -> Result<Self, std::boxed::Box<dyn error::Error>> {
-        let file = File::open(path)?;
-        let reader = BufReader::new(file);
-        let config: SceneConfig = serde_json::from_reader(reader)?;
+        let data = SceneData::load(path, config)?;
+        Ok(Self::from_data(
+            data,
+            device,
+            queue,
+            shader_dir,
+            pipeline_manager,
+            global_bind_group_layout,
+        ))
+    }
+
+    // Creates all gpu resources for a scene whose data was already loaded.
+    pub fn from_data(
+        data: SceneData,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        shader_dir: &ShaderDirectory,
+        pipeline_manager: &mut PipelineManager,
+        global_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let SceneData { path, config, models } = data;
 
         let voxelization = SceneVoxelization::new(
             device,
@@ -86,22 +423,45 @@ impl Scene {
             global_bind_group_layout,
             &voxelization,
         );
-        let models = SceneModels::from_config(&device, queue, &config.static_objects, &config.fluid)?;
+        let smoke = Self::create_smoke_from_config(&config, device, shader_dir, pipeline_manager, global_bind_group_layout, &voxelization);
+        let mut models = SceneModels::from_data(&device, queue, shader_dir, pipeline_manager, models, &config.fluid);
+        models.reset_animations(config.events.iter().filter_map(|event| event.action.animated_object()));
+        let playback_cache = PlaybackCache::new(hybrid_fluid.particles_position_buffer_size());
 
-        Ok(Scene {
+        Scene {
             hybrid_fluid,
+            smoke,
+            force_fields: config.force_fields.clone(),
+            lights: config.lights.clone(),
+            gravity: config.gravity,
+            next_event_index: 0,
             config,
             models,
             voxelization,
             distance_field_dirty: true,
-            path: path.to_path_buf(),
-        })
+            path,
+            playback_cache,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
     }
 
     pub fn config(&self) -> &SceneConfig {
         &self.config
     }
 
+    // Takes effect on the next reset, since fluid cells are only filled with particles initially.
+    pub fn set_particles_per_cell(&mut self, particles_per_cell: u32) {
+        self.config.fluid.particles_per_cell = particles_per_cell;
+    }
+
+    // Takes effect on the next reset, see FluidConfig::seed.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.config.fluid.seed = seed;
+    }
+
     pub fn num_active_particles(&self) -> u32 {
         self.hybrid_fluid.num_active_particles()
     }
@@ -123,26 +483,91 @@ impl Scene {
                 depth_or_array_layers: config.fluid.grid_dimension.z,
             },
             config.fluid.max_num_particles,
+            config.fluid.particles_per_cell,
             shader_dir,
             pipeline_manager,
             global_bind_group_layout,
             voxelization,
         );
+        hybrid_fluid.set_particle_seed(config.fluid.seed);
 
-        for cube in config.fluid.fluid_cubes.iter() {
+        // In smoke mode, fluid cubes are emitters of the smoke simulation instead.
+        let fluid_cubes: &[Box] = match config.fluid.simulation_type {
+            SimulationType::Liquid => &config.fluid.fluid_cubes,
+            SimulationType::Smoke => &[],
+        };
+        for cube in fluid_cubes.iter() {
             hybrid_fluid.add_fluid_cube(
                 queue,
                 cube.min / config.fluid.grid_to_world_scale,
                 cube.max / config.fluid.grid_to_world_scale,
+                cube.initial_velocity / config.fluid.grid_to_world_scale,
+                cube.phase,
             );
         }
+        for source in config.fluid.vdb_sources.iter() {
+            match source.load_fluid_cells(&config.fluid) {
+                Ok(cells) => hybrid_fluid.add_fluid_cells(queue, &cells, source.initial_velocity / config.fluid.grid_to_world_scale, source.phase),
+                Err(err) => error!("Failed to import fluid from vdb file {:?}: {}", source.path, err),
+            }
+        }
         hybrid_fluid.set_gravity_grid(config.gravity / config.fluid.grid_to_world_scale);
+        hybrid_fluid.set_surface_tension_grid(config.fluid.surface_tension / config.fluid.grid_to_world_scale.powi(3));
+        hybrid_fluid.set_periodic_axes(config.fluid.periodic_axes());
+        hybrid_fluid.set_max_particle_age(config.fluid.particle_lifetime);
+        if config.fluid.phases.len() > MAX_NUM_FLUID_PHASES {
+            warn!("Only {} fluid phases are supported, ignoring the remaining ones", MAX_NUM_FLUID_PHASES);
+        }
+        let mut phase_densities = [0.0; MAX_NUM_FLUID_PHASES];
+        for (density, phase) in phase_densities.iter_mut().zip(config.fluid.phases().iter()) {
+            *density = phase.density;
+        }
+        hybrid_fluid.set_phase_densities(phase_densities);
 
         // Creating the fluid is quite heavy, make sure we're done with all the buffer book-keeping before we move on.
         device.poll(wgpu::Maintain::Wait);
         hybrid_fluid
     }
 
+    fn create_smoke_from_config(
+        config: &SceneConfig,
+        device: &wgpu::Device,
+        shader_dir: &ShaderDirectory,
+        pipeline_manager: &mut PipelineManager,
+        global_bind_group_layout: &wgpu::BindGroupLayout,
+        voxelization: &SceneVoxelization,
+    ) -> Option<SmokeSimulation> {
+        if config.fluid.simulation_type != SimulationType::Smoke {
+            return None;
+        }
+
+        let mut smoke = SmokeSimulation::new(
+            device,
+            wgpu::Extent3d {
+                width: config.fluid.grid_dimension.x,
+                height: config.fluid.grid_dimension.y,
+                depth_or_array_layers: config.fluid.grid_dimension.z,
+            },
+            shader_dir,
+            pipeline_manager,
+            global_bind_group_layout,
+            voxelization,
+        );
+        for cube in config.fluid.fluid_cubes.iter() {
+            smoke.add_emitter(
+                cube.min / config.fluid.grid_to_world_scale,
+                cube.max / config.fluid.grid_to_world_scale,
+                cube.initial_velocity / config.fluid.grid_to_world_scale,
+            );
+        }
+        if !config.fluid.vdb_sources.is_empty() {
+            warn!("Fluid volumes from vdb files are ignored by the smoke simulation");
+        }
+        smoke.set_gravity_grid(config.gravity / config.fluid.grid_to_world_scale);
+        smoke.set_config(&config.fluid.smoke);
+        Some(smoke)
+    }
+
     pub fn reset(
         &mut self,
         device: &wgpu::Device,
@@ -160,14 +585,174 @@ impl Scene {
             global_bind_group_layout,
             &self.voxelization,
         );
+        self.smoke = Self::create_smoke_from_config(
+            &self.config,
+            device,
+            shader_dir,
+            pipeline_manager,
+            global_bind_group_layout,
+            &self.voxelization,
+        );
+        self.gravity = self.config.gravity;
+        self.next_event_index = 0;
+        self.models
+            .reset_animations(self.config.events.iter().filter_map(|event| event.action.animated_object()));
+        self.distance_field_dirty = true;
+        self.playback_cache.clear();
+    }
+
+    // Changes the grid resolution of the running simulation by a factor, keeping its world space extent.
+    // Particles stay where they are, the scene config is updated accordingly (i.e. resets use the new resolution as well).
+    pub fn resample_grid(
+        &mut self,
+        factor: f32,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        shader_dir: &ShaderDirectory,
+        pipeline_manager: &mut PipelineManager,
+        global_bind_group_layout: &wgpu::BindGroupLayout,
+        global_bind_group: &wgpu::BindGroup,
+    ) {
+        let old_dimension = self.config.fluid.grid_dimension;
+        let resample = |size: u32| ((size as f32 * factor).round() as u32).max(MIN_GRID_DIMENSION).min(MAX_GRID_DIMENSION);
+        let new_dimension = cgmath::point3(resample(old_dimension.x), resample(old_dimension.y), resample(old_dimension.z));
+        if new_dimension == old_dimension {
+            return;
+        }
+        if let Err(err) = validate_grid_dimension(new_dimension) {
+            error!("Can't resample grid: {}", err);
+            return;
+        }
+        info!(
+            "Resampling fluid grid from {}x{}x{} to {}x{}x{}",
+            old_dimension.x, old_dimension.y, old_dimension.z, new_dimension.x, new_dimension.y, new_dimension.z
+        );
+
+        // Rounding may make the scale slightly different per axis, x is authoritative.
+        let grid_scale = new_dimension.x as f32 / old_dimension.x as f32;
+        self.config.fluid.grid_dimension = new_dimension;
+        self.config.fluid.grid_to_world_scale /= grid_scale;
+
+        let grid_extent = wgpu::Extent3d {
+            width: new_dimension.x,
+            height: new_dimension.y,
+            depth_or_array_layers: new_dimension.z,
+        };
+        self.voxelization = SceneVoxelization::new(device, shader_dir, pipeline_manager, global_bind_group_layout, grid_extent);
+        let mut hybrid_fluid = HybridFluid::new(
+            device,
+            grid_extent,
+            self.config.fluid.max_num_particles,
+            self.config.fluid.particles_per_cell,
+            shader_dir,
+            pipeline_manager,
+            global_bind_group_layout,
+            &self.voxelization,
+        );
+        hybrid_fluid.take_over_resampled(&self.hybrid_fluid, device, queue, pipeline_manager, global_bind_group);
+        hybrid_fluid.set_gravity_grid(self.gravity / self.config.fluid.grid_to_world_scale);
+        hybrid_fluid.set_surface_tension_grid(self.config.fluid.surface_tension / self.config.fluid.grid_to_world_scale.powi(3));
+        hybrid_fluid.set_periodic_axes(self.config.fluid.periodic_axes());
+        hybrid_fluid.set_max_particle_age(self.config.fluid.particle_lifetime);
+        device.poll(wgpu::Maintain::Wait);
+
+        self.hybrid_fluid = hybrid_fluid;
+        // Smoke state isn't carried over, it builds up again quickly.
+        self.smoke = Self::create_smoke_from_config(
+            &self.config,
+            device,
+            shader_dir,
+            pipeline_manager,
+            global_bind_group_layout,
+            &self.voxelization,
+        );
+        if let Some(smoke) = self.smoke.as_mut() {
+            smoke.set_gravity_grid(self.gravity / self.config.fluid.grid_to_world_scale);
+            // Emitters are part of the smoke simulation, re-add those that were added by events so far.
+            for event in self.config.events[..self.next_event_index].iter() {
+                if let SceneEventAction::AddFluid(cube) = &event.action {
+                    smoke.add_emitter(
+                        cube.min / self.config.fluid.grid_to_world_scale,
+                        cube.max / self.config.fluid.grid_to_world_scale,
+                        cube.initial_velocity / self.config.fluid.grid_to_world_scale,
+                    );
+                }
+            }
+        }
         self.distance_field_dirty = true;
+        // Cached positions are in the old grid space.
+        self.playback_cache.clear();
+    }
+
+    // Gravity in world space.
+    pub fn gravity(&self) -> cgmath::Vector3<f32> {
+        self.gravity
+    }
+
+    // Gravity in world space.
+    pub fn set_gravity(&mut self, gravity: cgmath::Vector3<f32>) {
+        self.gravity = gravity;
+        let gravity_grid = gravity / self.config.fluid.grid_to_world_scale;
+        self.hybrid_fluid.set_gravity_grid(gravity_grid);
+        if let Some(smoke) = self.smoke.as_mut() {
+            smoke.set_gravity_grid(gravity_grid);
+        }
+    }
+
+    // Executes all events that are due at the current simulation time.
+    fn trigger_events(&mut self, timer: &Timer, queue: &wgpu::Queue) {
+        let total_simulated_time = timer.total_simulated_time();
+        while let Some(event) = self.config.events.get(self.next_event_index) {
+            if event.time > total_simulated_time.as_secs_f32() {
+                break;
+            }
+            self.next_event_index += 1;
+            info!("Triggering scene event at {}s", event.time);
+
+            let grid_to_world_scale = self.config.fluid.grid_to_world_scale;
+            match &event.action {
+                SceneEventAction::AddFluid(cube) => match self.smoke.as_mut() {
+                    Some(smoke) => smoke.add_emitter(
+                        cube.min / grid_to_world_scale,
+                        cube.max / grid_to_world_scale,
+                        cube.initial_velocity / grid_to_world_scale,
+                    ),
+                    None => self.hybrid_fluid.add_fluid_cube(
+                        queue,
+                        cube.min / grid_to_world_scale,
+                        cube.max / grid_to_world_scale,
+                        cube.initial_velocity / grid_to_world_scale,
+                        cube.phase,
+                    ),
+                },
+                SceneEventAction::RemoveFluid(cube) => match self.smoke {
+                    Some(_) => warn!("Removing fluid is not supported by the smoke simulation, ignoring the event"),
+                    None => self
+                        .hybrid_fluid
+                        .remove_fluid_in_box(cube.min / grid_to_world_scale, cube.max / grid_to_world_scale),
+                },
+                SceneEventAction::SetGravity(gravity) => {
+                    let gravity = *gravity;
+                    self.set_gravity(gravity);
+                }
+                SceneEventAction::StartAnimation(object_index) => {
+                    let object_index = *object_index;
+                    self.models.start_animation(object_index, total_simulated_time);
+                }
+            }
+        }
     }
 
+    // Executes the given stages of a simulation step.
+    // Model animation & voxelization happen along with the first stage.
     pub fn step(
         &mut self,
         timer: &Timer,
+        stages: &[SimulationStage],
         device: &wgpu::Device,
         profiler: &mut GpuProfiler,
+        fence_profiler: &mut FenceProfiler,
+        profiling_detail: ProfilingDetail,
         pipeline_manager: &PipelineManager,
         queue: &wgpu::Queue,
         global_bind_group: &wgpu::BindGroup,
@@ -188,29 +773,89 @@ impl Scene {
             label: Some("Encoder: Scene Step"),
         });
 
-        //wgpu_profiler!("Animate Models", profiler, &mut encoder, device, {
-        self.models.step(timer, queue, &self.config.fluid);
-        //});
+        // Never simulate on top of a cached frame.
+        self.playback_cache.request_frame(None);
+        self.playback_cache
+            .apply(device, &mut encoder, self.hybrid_fluid.particles_position_buffer());
 
-        wgpu_profiler!("Voxelize Scene", profiler, &mut encoder, device, {
-            self.voxelization.update(&mut encoder, pipeline_manager, global_bind_group, &self.models);
-        });
+        if stages.first() == Some(&SimulationStage::TransferToGrid) {
+            self.trigger_events(timer, queue);
 
-        wgpu_profiler!("HybridFluid step", profiler, &mut encoder, device, {
-            self.hybrid_fluid.step(
-                timer.simulation_delta(),
-                &mut encoder,
-                device,
-                queue,
-                global_bind_group,
-                pipeline_manager,
-                profiler,
-            );
-        });
+            //wgpu_profiler!("Animate Models", profiler, &mut encoder, device, {
+            self.models.step(timer, queue, &self.config.fluid);
+            //});
+
+            profiling_scope!(Coarse, profiling_detail, "Voxelize Scene", profiler, &mut encoder, device, {
+                self.voxelization.update(&mut encoder, pipeline_manager, global_bind_group, &self.models);
+            });
+            fence_profiler.submit_scope("Voxelize Scene", &mut encoder, device, queue, pipeline_manager);
+        }
+
+        if let Some(smoke) = self.smoke.as_mut() {
+            // The smoke simulation has no intermediate stages to stop at, the entire step runs with the first one.
+            if stages.first() == Some(&SimulationStage::TransferToGrid) {
+                profiling_scope!(Coarse, profiling_detail, "Smoke step", profiler, &mut encoder, device, {
+                    smoke.step(
+                        timer.simulation_delta(),
+                        &mut encoder,
+                        device,
+                        queue,
+                        global_bind_group,
+                        pipeline_manager,
+                        profiler,
+                        profiling_detail,
+                    );
+                });
+                fence_profiler.submit_scope("Smoke step", &mut encoder, device, queue, pipeline_manager);
+            }
+        } else {
+            self.hybrid_fluid
+                .set_force_fields(&force_fields_to_grid(&self.force_fields, &self.config.fluid));
+
+            profiling_scope!(Coarse, profiling_detail, "HybridFluid step", profiler, &mut encoder, device, {
+                for stage in stages {
+                    self.hybrid_fluid.step_stage(
+                        *stage,
+                        timer.simulation_delta(),
+                        &mut encoder,
+                        device,
+                        queue,
+                        global_bind_group,
+                        pipeline_manager,
+                        profiler,
+                        profiling_detail,
+                    );
+                    fence_profiler.submit_scope(&format!("{:?}", stage), &mut encoder, device, queue, pipeline_manager);
+                }
+            });
+        }
         profiler.resolve_queries(&mut encoder);
+        pipeline_manager.flush_push_constants(device, queue);
         queue.submit(Some(encoder.finish()));
         profiler.end_frame().unwrap();
-        self.hybrid_fluid.update_statistics();
+        fence_profiler.end_frame();
+        match self.smoke.as_mut() {
+            Some(smoke) => smoke.update_statistics(),
+            None => self.hybrid_fluid.update_statistics(),
+        }
+    }
+
+    // Captures the fluid state if simulation steps were performed since the last call, otherwise shows the frame requested from the playback cache.
+    pub fn update_playback_cache(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, timer: &Timer) {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Encoder: Playback Cache"),
+        });
+        if timer.num_simulation_steps_performed_for_current_frame() > 0 {
+            self.playback_cache.capture(
+                device,
+                &mut encoder,
+                self.hybrid_fluid.particles_position_buffer(),
+                timer.total_simulated_time(),
+            );
+        }
+        self.playback_cache
+            .apply(device, &mut encoder, self.hybrid_fluid.particles_position_buffer());
+        queue.submit(Some(encoder.finish()));
     }
 
     pub fn fluid(&self) -> &HybridFluid {
@@ -220,4 +865,12 @@ impl Scene {
     pub fn fluid_mut(&mut self) -> &mut HybridFluid {
         &mut self.hybrid_fluid
     }
+
+    pub fn smoke(&self) -> Option<&SmokeSimulation> {
+        self.smoke.as_ref()
+    }
+
+    pub fn smoke_mut(&mut self) -> Option<&mut SmokeSimulation> {
+        self.smoke.as_mut()
+    }
 }
@@ -1,21 +1,55 @@
 use cgmath::*;
+use lazy_static::lazy_static;
 use serde::Deserialize;
-use std::{error::Error, path::Path, path::PathBuf, time::Duration};
+use std::{
+    collections::HashMap,
+    error::Error,
+    path::Path,
+    path::PathBuf,
+    sync::atomic::{AtomicU64, Ordering},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
 use wgpu::util::DeviceExt;
 
-use crate::{timer::Timer, wgpu_utils::uniformbuffer::PaddedVector3};
+use crate::{
+    camera::Frustum,
+    global_bindings::GlobalBindings,
+    timer::Timer,
+    wgpu_utils::{mipmap::MipmapGenerator, pipelines::PipelineManager, shader::ShaderDirectory, uniformbuffer::PaddedVector3},
+};
 
-use super::FluidConfig;
+use super::{heightfield::HeightfieldConfig, FluidConfig};
 
 // Data describing a model in the scene.
 #[derive(Deserialize, Clone)]
 pub struct StaticObjectConfig {
+    // Either a model or a heightfield needs to be specified.
+    #[serde(default)]
     pub model: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub heightfield: Option<HeightfieldConfig>,
     pub world_position: cgmath::Point3<f32>,
     pub scale: f32,
     pub rotation_angles: cgmath::Euler<cgmath::Deg<f32>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub animation: Option<RigidAnimation>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vertex_animation: Option<VertexAnimationConfig>,
+    #[serde(default)]
+    pub boundary: BoundaryMaterial,
+}
+
+// How fluid interacts with the surface of an object.
+// Both values are quantized to 16 steps on the gpu, see encodeSolidBoundary in global_bindings.glsl
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(default)]
+pub struct BoundaryMaterial {
+    // Fraction of the fluid's tangential velocity (relative to the object) that is removed each step.
+    // 0 is free-slip (ice), 1 is no-slip (rubber).
+    pub friction: f32,
+    // Fraction of the velocity into the object that is reflected when particles hit it, 0 is fully inelastic.
+    pub restitution: f32,
 }
 
 #[derive(Deserialize, Clone)]
@@ -45,15 +79,119 @@ pub struct RigidAnimation {
     pub rotation: Option<RotationAnimation>,
 }
 
+// Per-vertex animation via a sequence of obj files that all need to have the same topology as the model itself.
+// Frames are linearly interpolated and the animation loops.
+#[derive(Deserialize, Clone)]
+pub struct VertexAnimationConfig {
+    pub frames: Vec<PathBuf>,
+    pub frames_per_second: f32,
+}
+
+// All frames of a vertex animation, kept on the CPU and uploaded every step.
+struct VertexAnimation {
+    object_index: usize,
+    vertex_offset: u32, // offset in number of vertices (not bytes!)
+    frames: Vec<Vec<MeshVertex>>,
+    frames_per_second: f32,
+}
+
+impl VertexAnimation {
+    fn vertices_at_time(&self, total_simulated_time: Duration) -> Vec<MeshVertex> {
+        let frame = total_simulated_time.as_secs_f32() * self.frames_per_second;
+        let frame_a = frame as usize % self.frames.len();
+        let frame_b = (frame_a + 1) % self.frames.len();
+        let t = frame.fract();
+
+        self.frames[frame_a]
+            .iter()
+            .zip(self.frames[frame_b].iter())
+            .map(|(a, b)| MeshVertex {
+                position: a.position + (b.position - a.position) * t,
+                normal: a.normal.lerp(b.normal, t),
+                uv: a.uv,
+            })
+            .collect()
+    }
+}
+
 pub struct StaticMeshData {
     pub config: StaticObjectConfig,
+    pub object_index: usize, // index of the static object in the scene config, an object may consist of several meshes
 
     pub vertex_buffer_range: core::ops::Range<u32>, // range in number of vertices (not bytes!)
     pub index_buffer_range: core::ops::Range<u32>,  // range in number of indices (not bytes!)
 
+    // Object space bounding box (including all frames of a vertex animation)
+    pub bounds_min: cgmath::Point3<f32>,
+    pub bounds_max: cgmath::Point3<f32>,
+
     // Material data. If we expected many materials would share a transform this would be a bad idea to put it together.
     // But per loaded mesh we typically only have one.
-    pub texture_index: i32,
+    pub material: MeshMaterial,
+}
+
+// PBR material (metallic/roughness workflow)
+// Texture indices are -1 if there is no texture, in which case the scalar values are used.
+#[derive(Clone, Copy)]
+pub struct MeshMaterial {
+    pub albedo_texture_index: i32,
+    pub normal_texture_index: i32,
+    pub roughness_texture_index: i32,
+    pub metallic_texture_index: i32,
+    pub roughness: f32,
+    pub metallic: f32,
+}
+
+impl Default for MeshMaterial {
+    fn default() -> Self {
+        MeshMaterial {
+            albedo_texture_index: -1,
+            normal_texture_index: -1,
+            roughness_texture_index: -1,
+            metallic_texture_index: -1,
+            roughness: 0.8,
+            metallic: 0.0,
+        }
+    }
+}
+
+// Texture files referenced by all materials in a scene.
+#[derive(Default)]
+struct MaterialTextures {
+    paths: Vec<(PathBuf, bool)>, // path, is srgb
+}
+
+impl MaterialTextures {
+    fn get_or_add(&mut self, directory: &Path, filename: &str, srgb: bool) -> i32 {
+        if filename.is_empty() {
+            return -1;
+        }
+        let path = directory.join(filename);
+        match self.paths.iter().position(|(p, _)| *p == path) {
+            Some(index) => index as i32,
+            None => {
+                self.paths.push((path, srgb));
+                self.paths.len() as i32 - 1
+            }
+        }
+    }
+
+    // Roughness and metalness aren't part of the original MTL spec, but there is a widespread extension for it.
+    // See http://exocortex.com/blog/extending_wavefront_mtl_to_support_pbr
+    fn material_from_mtl(&mut self, directory: &Path, mtl: &tobj::Material) -> MeshMaterial {
+        let scalar = |key: &str, default: f32| mtl.unknown_param.get(key).and_then(|v| v.trim().parse().ok()).unwrap_or(default);
+        let texture = |key: &str| mtl.unknown_param.get(key).map_or("", |v| v.trim());
+        let defaults = MeshMaterial::default();
+
+        MeshMaterial {
+            albedo_texture_index: self.get_or_add(directory, &mtl.diffuse_texture, true),
+            normal_texture_index: self.get_or_add(directory, &mtl.normal_texture, false),
+            roughness_texture_index: self.get_or_add(directory, texture("map_Pr"), false),
+            metallic_texture_index: self.get_or_add(directory, texture("map_Pm"), false),
+            roughness: scalar("Pr", defaults.roughness),
+            metallic: scalar("Pm", defaults.metallic),
+        }
+    }
 }
 #[repr(C)]
 #[derive(Clone, Copy)]
@@ -66,8 +204,15 @@ struct MeshDataGpu {
     vertex_buffer_range: cgmath::Vector2<u32>,
     index_buffer_range: cgmath::Vector2<u32>,
 
-    texture_index: i32,
-    padding1: cgmath::Vector3<i32>,
+    albedo_texture_index: i32,
+    normal_texture_index: i32,
+    roughness_texture_index: i32,
+    metallic_texture_index: i32,
+    roughness: f32,
+    metallic: f32,
+
+    boundary_friction: f32,
+    boundary_restitution: f32,
 }
 unsafe impl bytemuck::Pod for MeshDataGpu {}
 unsafe impl bytemuck::Zeroable for MeshDataGpu {}
@@ -92,6 +237,10 @@ impl Default for MeshVertex {
 }
 
 impl MeshVertex {
+    pub fn new(position: cgmath::Point3<f32>, normal: cgmath::Vector3<f32>, uv: cgmath::Point2<f32>) -> Self {
+        MeshVertex { position, normal, uv }
+    }
+
     pub const SIZE: wgpu::BufferAddress = std::mem::size_of::<MeshVertex>() as wgpu::BufferAddress;
 }
 
@@ -105,26 +254,193 @@ pub struct SceneModels {
     pub texture_views: Vec<wgpu::TextureView>,
 
     pub meshes: Vec<StaticMeshData>,
+    vertex_animations: Vec<VertexAnimation>,
+    // Simulated time at which the animations of each static object started, None if they haven't yet.
+    animation_start_times: Vec<Option<Duration>>,
+    // World space bounding box per mesh for the current time.
+    world_bounds: Vec<(cgmath::Point3<f32>, cgmath::Point3<f32>)>,
+}
+
+// Models & materials of an obj file, models sorted by material.
+type LoadedObj = (Vec<tobj::Model>, Vec<tobj::Material>);
+
+// Decoded files of recent scene loads, so that switching between scenes (or variants of the same scene) doesn't load them again.
+// Entries are keyed by path and ignored if the file was modified since.
+// Only the cpu side is cached: Uploading to the gpu is cheap in comparison and this way gpu resources stay with their scene.
+struct AssetCache<T> {
+    entries: HashMap<PathBuf, CachedAsset<T>>,
+}
+
+struct CachedAsset<T> {
+    modified: SystemTime,
+    // See LOAD_GENERATION
+    last_used_generation: u64,
+    asset: Arc<T>,
+}
+
+impl<T> AssetCache<T> {
+    fn new() -> Self {
+        AssetCache { entries: HashMap::new() }
+    }
+
+    fn get(&mut self, path: &Path, modified: SystemTime, generation: u64) -> Option<Arc<T>> {
+        let entry = self.entries.get_mut(path).filter(|entry| entry.modified == modified)?;
+        entry.last_used_generation = generation;
+        Some(entry.asset.clone())
+    }
+
+    fn insert(&mut self, path: &Path, modified: SystemTime, generation: u64, asset: Arc<T>) {
+        self.entries.insert(
+            path.to_path_buf(),
+            CachedAsset {
+                modified,
+                last_used_generation: generation,
+                asset,
+            },
+        );
+    }
+
+    // Keeps only what was used by the last two scene loads, enough for going back and forth between two scenes.
+    fn evict_unused(&mut self, generation: u64) {
+        self.entries.retain(|_, entry| entry.last_used_generation + 1 >= generation);
+    }
+}
+
+lazy_static! {
+    static ref OBJ_CACHE: Mutex<AssetCache<LoadedObj>> = Mutex::new(AssetCache::new());
+    static ref IMAGE_CACHE: Mutex<AssetCache<image::RgbaImage>> = Mutex::new(AssetCache::new());
+}
+// Incremented with every scene load, used to evict assets that are no longer in use.
+static LOAD_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+// Lock isn't held while loading, so several scenes can load at the same time. (e.g. a background load that is about to be superseded)
+fn load_cached<T>(
+    cache: &Mutex<AssetCache<T>>,
+    path: &Path,
+    load: impl FnOnce(&Path) -> Result<T, Box<dyn Error>>,
+) -> Result<Arc<T>, Box<dyn Error>> {
+    let modified = std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .map_err(|err| format!("Failed to access {:?}: {}", path, err))?;
+    let generation = LOAD_GENERATION.load(Ordering::SeqCst);
+    if let Some(asset) = cache.lock().unwrap().get(path, modified, generation) {
+        info!("Using cached {:?}", path);
+        return Ok(asset);
+    }
+    let asset = Arc::new(load(path)?);
+    cache.lock().unwrap().insert(path, modified, generation, asset.clone());
+    Ok(asset)
+}
+
+fn load_obj_sorted(file_name: &Path) -> Result<Arc<LoadedObj>, Box<dyn Error>> {
+    load_cached(&OBJ_CACHE, file_name, |file_name| {
+        let (mut loaded_models, loaded_materials) = tobj::load_obj(
+            file_name,
+            &tobj::LoadOptions {
+                single_index: true,
+                triangulate: true,
+                ignore_points: true,
+                ignore_lines: true,
+            },
+        )?;
+        loaded_models.sort_by_key(|m| m.mesh.material_id);
+        Ok((loaded_models, loaded_materials?))
+    })
+}
+
+fn append_vertices(vertices: &mut Vec<MeshVertex>, mesh: &tobj::Mesh) {
+    let prev_vertex_count = vertices.len();
+    vertices.resize_with(vertices.len() + mesh.positions.len(), || MeshVertex::default());
+
+    for (vertex, pos) in vertices.iter_mut().skip(prev_vertex_count).zip(mesh.positions.chunks(3)) {
+        vertex.position.x = pos[0];
+        vertex.position.y = pos[1];
+        vertex.position.z = pos[2];
+    }
+    for (vertex, norm) in vertices.iter_mut().skip(prev_vertex_count).zip(mesh.normals.chunks(3)) {
+        vertex.normal.x = norm[0];
+        vertex.normal.y = norm[1];
+        vertex.normal.z = norm[2];
+    }
+    for (vertex, uv) in vertices.iter_mut().skip(prev_vertex_count).zip(mesh.texcoords.chunks(2)) {
+        vertex.uv.x = uv[0];
+        vertex.uv.y = 1.0 - uv[1];
+    }
+}
+
+fn load_vertex_animation(
+    config: &VertexAnimationConfig,
+    object_index: usize,
+    vertex_offset: u32,
+    num_vertices: usize,
+) -> Result<VertexAnimation, Box<dyn Error>> {
+    if config.frames.is_empty() || config.frames_per_second <= 0.0 {
+        return Err("vertex animation needs at least one frame and a positive frame rate".into());
+    }
+
+    let mut frames = Vec::with_capacity(config.frames.len());
+    for frame_path in config.frames.iter() {
+        let file_name = Path::new("models").join(frame_path);
+        let loaded_obj = load_obj_sorted(&file_name)?;
+        let (loaded_models, _) = &*loaded_obj;
+        let mut vertices = Vec::with_capacity(num_vertices);
+        for m in loaded_models.iter() {
+            append_vertices(&mut vertices, &m.mesh);
+        }
+        if vertices.len() != num_vertices {
+            return Err(format!(
+                "animation frame {:?} has {} vertices, expected {}",
+                file_name,
+                vertices.len(),
+                num_vertices
+            )
+            .into());
+        }
+        frames.push(vertices);
+    }
+
+    Ok(VertexAnimation {
+        object_index,
+        vertex_offset,
+        frames,
+        frames_per_second: config.frames_per_second,
+    })
 }
 
-fn load_texture2d_from_path(device: &wgpu::Device, queue: &wgpu::Queue, path: &Path) -> wgpu::Texture {
-    info!("Loading 2d texture {:?}", path);
-    // TODO: Mipmaps
+fn load_image(path: &Path) -> Result<Arc<image::RgbaImage>, Box<dyn Error>> {
+    load_cached(&IMAGE_CACHE, path, |path| {
+        info!("Loading 2d texture {:?}", path);
+        Ok(image::io::Reader::open(path)?.decode()?.to_rgba8())
+    })
+}
 
-    let image = image::io::Reader::open(path).unwrap().decode().unwrap().to_rgba8();
+fn create_texture2d(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    pipeline_manager: &PipelineManager,
+    mipmap_generator: &MipmapGenerator,
+    path: &Path,
+    image: &image::RgbaImage,
+    srgb: bool,
+) -> wgpu::Texture {
     let image_data = image.as_raw();
 
+    let size = wgpu::Extent3d {
+        width: image.width(),
+        height: image.height(),
+        depth_or_array_layers: 1,
+    };
     let texture = device.create_texture(&wgpu::TextureDescriptor {
-        label: path.file_name().unwrap().to_str(),
-        size: wgpu::Extent3d {
-            width: image.width(),
-            height: image.height(),
-            depth_or_array_layers: 1,
-        },
+        label: Some("Texture: Mipmap Source"),
+        size,
         mip_level_count: 1,
         sample_count: 1,
         dimension: wgpu::TextureDimension::D2,
-        format: wgpu::TextureFormat::Rgba8UnormSrgb,
+        format: if srgb {
+            wgpu::TextureFormat::Rgba8UnormSrgb
+        } else {
+            wgpu::TextureFormat::Rgba8Unorm
+        },
         usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
     });
 
@@ -140,14 +456,23 @@ fn load_texture2d_from_path(device: &wgpu::Device, queue: &wgpu::Queue, path: &P
             bytes_per_row: std::num::NonZeroU32::new(4 * image.width()),
             rows_per_image: None,
         },
-        wgpu::Extent3d {
-            width: image.width(),
-            height: image.height(),
-            depth_or_array_layers: 1,
-        },
+        size,
+    );
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Encoder: Texture Mipmap Generation"),
+    });
+    let mipmapped_texture = mipmap_generator.create_mipmapped_texture(
+        device,
+        &mut encoder,
+        pipeline_manager,
+        &texture.create_view(&Default::default()),
+        size,
+        path.file_name().unwrap().to_str(),
     );
+    queue.submit(Some(encoder.finish()));
 
-    texture
+    mipmapped_texture
 }
 
 impl StaticMeshData {
@@ -181,20 +506,58 @@ impl StaticMeshData {
         }
     }
 
-    fn to_gpu(&self, total_simulated_time: Duration, simulation_delta: Duration, fluid_config: &FluidConfig) -> MeshDataGpu {
-        let world_position = self.world_position_at_time(total_simulated_time);
-        let rotation = self.rotation_at_time(total_simulated_time);
+    fn transform_world_at_time(&self, total_simulated_time: Duration) -> cgmath::Matrix4<f32> {
+        cgmath::Matrix4::from_translation(self.world_position_at_time(total_simulated_time).to_vec())
+            * cgmath::Matrix4::from_scale(self.config.scale)
+            * cgmath::Matrix4::from(self.rotation_at_time(total_simulated_time))
+    }
+
+    // Bounding box of the transformed object space bounding box.
+    fn world_bounds_at_time(&self, total_simulated_time: Duration) -> (cgmath::Point3<f32>, cgmath::Point3<f32>) {
+        let transform = self.transform_world_at_time(total_simulated_time);
+        let mut min = cgmath::point3(std::f32::MAX, std::f32::MAX, std::f32::MAX);
+        let mut max = cgmath::point3(std::f32::MIN, std::f32::MIN, std::f32::MIN);
+        for i in 0..8 {
+            let corner = cgmath::point3(
+                if i & 1 == 0 { self.bounds_min.x } else { self.bounds_max.x },
+                if i & 2 == 0 { self.bounds_min.y } else { self.bounds_max.y },
+                if i & 4 == 0 { self.bounds_min.z } else { self.bounds_max.z },
+            );
+            let corner = transform.transform_point(corner);
+            min = cgmath::point3(min.x.min(corner.x), min.y.min(corner.y), min.z.min(corner.z));
+            max = cgmath::point3(max.x.max(corner.x), max.y.max(corner.y), max.z.max(corner.z));
+        }
+        (min, max)
+    }
+
+    fn extend_bounds(&mut self, vertices: &[MeshVertex]) {
+        for vertex in vertices {
+            self.bounds_min = cgmath::point3(
+                self.bounds_min.x.min(vertex.position.x),
+                self.bounds_min.y.min(vertex.position.y),
+                self.bounds_min.z.min(vertex.position.z),
+            );
+            self.bounds_max = cgmath::point3(
+                self.bounds_max.x.max(vertex.position.x),
+                self.bounds_max.y.max(vertex.position.y),
+                self.bounds_max.z.max(vertex.position.z),
+            );
+        }
+    }
+
+    // Animation time is None if the object's animation hasn't started yet, i.e. it rests at its initial pose.
+    fn to_gpu(&self, animation_time: Option<Duration>, simulation_delta: Duration, fluid_config: &FluidConfig) -> MeshDataGpu {
+        let time = animation_time.unwrap_or_default();
+        let world_position = self.world_position_at_time(time);
 
         // Brute force way for getting a translation vector. Analytical derivative would be better.
-        let translation_velocity = if total_simulated_time > simulation_delta {
-            (world_position - self.world_position_at_time(total_simulated_time - simulation_delta)) / simulation_delta.as_secs_f32()
+        let translation_velocity = if time > simulation_delta {
+            (world_position - self.world_position_at_time(time - simulation_delta)) / simulation_delta.as_secs_f32()
         } else {
             cgmath::vec3(0.0, 0.0, 0.0)
         };
 
-        let transform_world = cgmath::Matrix4::from_translation(world_position.to_vec())
-            * cgmath::Matrix4::from_scale(self.config.scale)
-            * cgmath::Matrix4::from(rotation);
+        let transform_world = self.transform_world_at_time(time);
         let transform_voxel = cgmath::Matrix4::from_scale(1.0 / fluid_config.grid_to_world_scale)
             * cgmath::Matrix4::from_translation(-fluid_config.world_position.to_vec())
             * transform_world;
@@ -209,6 +572,7 @@ impl StaticMeshData {
                 .config
                 .animation
                 .as_ref()
+                .filter(|_| animation_time.is_some())
                 .map_or(cgmath::Vector3::zero(), |a| {
                     a.rotation
                         .as_ref()
@@ -218,62 +582,71 @@ impl StaticMeshData {
 
             vertex_buffer_range: cgmath::vec2(self.vertex_buffer_range.start, self.vertex_buffer_range.end),
             index_buffer_range: cgmath::vec2(self.index_buffer_range.start, self.index_buffer_range.end),
-            texture_index: self.texture_index,
-            padding1: cgmath::vec3(0, 0, 0),
+            albedo_texture_index: self.material.albedo_texture_index,
+            normal_texture_index: self.material.normal_texture_index,
+            roughness_texture_index: self.material.roughness_texture_index,
+            metallic_texture_index: self.material.metallic_texture_index,
+            roughness: self.material.roughness,
+            metallic: self.material.metallic,
+            boundary_friction: self.config.boundary.friction,
+            boundary_restitution: self.config.boundary.restitution,
         }
     }
 }
 
-impl SceneModels {
-    pub fn vertex_buffer_layout() -> wgpu::VertexBufferLayout<'static> {
-        wgpu::VertexBufferLayout {
-            array_stride: MeshVertex::SIZE,
-            step_mode: wgpu::InputStepMode::Vertex,
-            attributes: &[
-                wgpu::VertexAttribute {
-                    format: wgpu::VertexFormat::Float32x3,
-                    offset: 0,
-                    shader_location: 0,
-                },
-                wgpu::VertexAttribute {
-                    format: wgpu::VertexFormat::Float32x3,
-                    offset: 4 * 3,
-                    shader_location: 1,
-                },
-                wgpu::VertexAttribute {
-                    format: wgpu::VertexFormat::Float32x2,
-                    offset: 4 * 6,
-                    shader_location: 2,
-                },
-            ],
-        }
-    }
+// Cpu side data of all models in a scene, i.e. everything loaded from disk.
+// Doesn't need a device, so it can be loaded on a background thread, see SceneModels::from_data.
+pub struct SceneModelsData {
+    vertices: Vec<MeshVertex>,
+    indices: Vec<u32>,
+    meshes: Vec<StaticMeshData>,
+    vertex_animations: Vec<VertexAnimation>,
+    textures: Vec<(PathBuf, Arc<image::RgbaImage>, bool)>, // path, decoded image, is srgb
+    num_objects: usize,
+}
 
-    pub fn from_config(
-        device: &wgpu::Device,
-        queue: &wgpu::Queue,
-        configs: &Vec<StaticObjectConfig>,
-        fluid_config: &FluidConfig,
-    ) -> Result<Self, Box<dyn Error>> {
+impl SceneModelsData {
+    pub fn load(configs: &[StaticObjectConfig]) -> Result<Self, Box<dyn Error>> {
+        let generation = LOAD_GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
         let mut vertices = Vec::new();
         let mut indices = Vec::<u32>::new();
         let mut meshes = Vec::new();
-        let mut texture_paths = Vec::new();
+        let mut material_textures = MaterialTextures::default();
+        let mut vertex_animations = Vec::new();
+
+        for (object_index, static_object_config) in configs.iter().enumerate() {
+            if let Some(heightfield) = &static_object_config.heightfield {
+                if !static_object_config.model.as_os_str().is_empty() || static_object_config.vertex_animation.is_some() {
+                    return Err(format!(
+                        "static object {} has a heightfield, it can't have a model or vertex animation",
+                        object_index
+                    )
+                    .into());
+                }
+                let (heightfield_vertices, heightfield_indices) = heightfield.generate_mesh()?;
+                let vertex_offset = vertices.len() as u32;
+                let index_offset = indices.len() as u32;
+                indices.extend(heightfield_indices.iter().map(|i| i + vertex_offset));
+                vertices.extend_from_slice(&heightfield_vertices);
+
+                let mut mesh = StaticMeshData {
+                    config: static_object_config.clone(),
+                    object_index,
+                    vertex_buffer_range: vertex_offset..(vertices.len() as u32),
+                    index_buffer_range: index_offset..(indices.len() as u32),
+                    bounds_min: cgmath::point3(std::f32::MAX, std::f32::MAX, std::f32::MAX),
+                    bounds_max: cgmath::point3(std::f32::MIN, std::f32::MIN, std::f32::MIN),
+                    material: MeshMaterial::default(),
+                };
+                mesh.extend_bounds(&heightfield_vertices);
+                meshes.push(mesh);
+                continue;
+            }
 
-        for static_object_config in configs {
             let file_name = Path::new("models").join(&static_object_config.model);
-            let (mut loaded_models, loaded_materials) = tobj::load_obj(
-                &file_name,
-                &tobj::LoadOptions {
-                    single_index: true,
-                    triangulate: true,
-                    ignore_points: true,
-                    ignore_lines: true,
-                },
-            )?;
-            let loaded_materials = loaded_materials?;
-
-            loaded_models.sort_by_key(|m| m.mesh.material_id);
+            let loaded_obj = load_obj_sorted(&file_name)?;
+            let (loaded_models, loaded_materials) = &*loaded_obj;
+            let object_vertex_offset = vertices.len();
             let mut prev_material_id = std::usize::MAX;
 
             // if any mesh in the obj doesn't have a material, we need to add an artificial one and offset all others.
@@ -289,26 +662,19 @@ impl SceneModels {
                     m.mesh.material_id.unwrap()
                 };
                 if prev_material_id != material_id {
-                    let texture_index: i32 = if let Some(matid) = m.mesh.material_id {
-                        let texture_path = file_name.parent().unwrap().join(&loaded_materials[matid].diffuse_texture);
-
-                        let known_texture_index = texture_paths.iter().position(|p| *p == texture_path);
-                        match known_texture_index {
-                            Some(index) => index as i32,
-                            None => {
-                                texture_paths.push(texture_path);
-                                texture_paths.len() as i32 - 1
-                            }
-                        }
-                    } else {
-                        -1
+                    let material = match m.mesh.material_id {
+                        Some(matid) => material_textures.material_from_mtl(file_name.parent().unwrap(), &loaded_materials[matid]),
+                        None => MeshMaterial::default(),
                     };
 
                     meshes.push(StaticMeshData {
                         config: static_object_config.clone(),
+                        object_index,
                         vertex_buffer_range: (vertices.len() as u32)..(vertices.len() as u32),
                         index_buffer_range: (indices.len() as u32)..(indices.len() as u32),
-                        texture_index,
+                        bounds_min: cgmath::point3(std::f32::MAX, std::f32::MAX, std::f32::MAX),
+                        bounds_max: cgmath::point3(std::f32::MIN, std::f32::MIN, std::f32::MIN),
+                        material,
                     });
                 }
                 prev_material_id = material_id;
@@ -318,39 +684,115 @@ impl SceneModels {
                 mesh.index_buffer_range = mesh.index_buffer_range.start..(indices.len() as u32);
 
                 let prev_vertex_count = vertices.len();
-                vertices.resize_with(vertices.len() + m.mesh.positions.len(), || MeshVertex::default());
+                append_vertices(&mut vertices, &m.mesh);
                 mesh.vertex_buffer_range = mesh.vertex_buffer_range.start..(vertices.len() as u32);
+                mesh.extend_bounds(&vertices[prev_vertex_count..]);
+            }
 
-                for (vertex, pos) in vertices.iter_mut().skip(prev_vertex_count).zip(m.mesh.positions.chunks(3)) {
-                    vertex.position.x = pos[0];
-                    vertex.position.y = pos[1];
-                    vertex.position.z = pos[2];
-                }
-                for (vertex, norm) in vertices.iter_mut().skip(prev_vertex_count).zip(m.mesh.normals.chunks(3)) {
-                    vertex.normal.x = norm[0];
-                    vertex.normal.y = norm[1];
-                    vertex.normal.z = norm[2];
-                }
-                for (vertex, uv) in vertices.iter_mut().skip(prev_vertex_count).zip(m.mesh.texcoords.chunks(2)) {
-                    vertex.uv.x = uv[0];
-                    vertex.uv.y = 1.0 - uv[1];
+            if let Some(vertex_animation_config) = &static_object_config.vertex_animation {
+                let animation = load_vertex_animation(
+                    vertex_animation_config,
+                    object_index,
+                    object_vertex_offset as u32,
+                    vertices.len() - object_vertex_offset,
+                )?;
+                for mesh in meshes.iter_mut().filter(|mesh| mesh.object_index == object_index) {
+                    let range = (mesh.vertex_buffer_range.start - animation.vertex_offset) as usize
+                        ..(mesh.vertex_buffer_range.end - animation.vertex_offset) as usize;
+                    for frame in animation.frames.iter() {
+                        mesh.extend_bounds(&frame[range.clone()]);
+                    }
                 }
+                vertex_animations.push(animation);
             }
         }
 
+        if material_textures.paths.len() > GlobalBindings::NUM_MESH_TEXTURES as usize {
+            return Err(format!(
+                "Scene uses {} textures, but only up to {} are supported",
+                material_textures.paths.len(),
+                GlobalBindings::NUM_MESH_TEXTURES
+            )
+            .into());
+        }
+        let mut textures = Vec::with_capacity(material_textures.paths.len());
+        for (path, srgb) in material_textures.paths.into_iter() {
+            let image = load_image(&path).map_err(|err| format!("Failed to load texture {:?}: {}", path, err))?;
+            textures.push((path, image, srgb));
+        }
+        OBJ_CACHE.lock().unwrap().evict_unused(generation);
+        IMAGE_CACHE.lock().unwrap().evict_unused(generation);
+
+        Ok(SceneModelsData {
+            vertices,
+            indices,
+            meshes,
+            vertex_animations,
+            textures,
+            num_objects: configs.len(),
+        })
+    }
+}
+
+impl SceneModels {
+    pub fn vertex_buffer_layout() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: MeshVertex::SIZE,
+            step_mode: wgpu::InputStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x3,
+                    offset: 0,
+                    shader_location: 0,
+                },
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x3,
+                    offset: 4 * 3,
+                    shader_location: 1,
+                },
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x2,
+                    offset: 4 * 6,
+                    shader_location: 2,
+                },
+            ],
+        }
+    }
+
+    pub fn from_data(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        shader_dir: &ShaderDirectory,
+        pipeline_manager: &mut PipelineManager,
+        data: SceneModelsData,
+        fluid_config: &FluidConfig,
+    ) -> Self {
+        let SceneModelsData {
+            vertices,
+            indices,
+            meshes,
+            vertex_animations,
+            textures,
+            num_objects,
+        } = data;
+
         let meshes_gpu: Vec<MeshDataGpu> = meshes
             .iter()
-            .map(|mesh| mesh.to_gpu(Duration::from_secs(0), Duration::from_secs(0), fluid_config))
+            .map(|mesh| mesh.to_gpu(Some(Duration::from_secs(0)), Duration::from_secs(0), fluid_config))
             .collect();
+        let world_bounds = meshes.iter().map(|mesh| mesh.world_bounds_at_time(Duration::from_secs(0))).collect();
 
-        let texture_views = texture_paths
+        let mipmap_generator = MipmapGenerator::new(device, shader_dir, pipeline_manager);
+        let texture_views = textures
             .iter()
-            .map(|path| load_texture2d_from_path(device, queue, path).create_view(&Default::default()))
+            .map(|(path, image, srgb)| {
+                create_texture2d(device, queue, pipeline_manager, &mipmap_generator, path, image, *srgb).create_view(&Default::default())
+            })
             .collect();
 
         let dummy_content = [0, 0, 0, 0];
 
-        Ok(SceneModels {
+        SceneModels {
             vertex_buffer: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
                 label: Some("SceneModel VertexBuffer"),
                 contents: if meshes_gpu.is_empty() {
@@ -358,7 +800,7 @@ impl SceneModels {
                 } else {
                     bytemuck::cast_slice(&vertices)
                 },
-                usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::STORAGE,
+                usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::STORAGE | wgpu::BufferUsage::COPY_DST,
             }),
             index_buffer: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
                 label: Some("SceneModel IndexBuffer"),
@@ -380,16 +822,73 @@ impl SceneModels {
             }),
             meshes,
             texture_views,
-        })
+            vertex_animations,
+            animation_start_times: vec![Some(Duration::from_secs(0)); num_objects],
+            world_bounds,
+        }
+    }
+
+    pub fn is_mesh_visible(&self, mesh_index: usize, frustum: &Frustum) -> bool {
+        let (min, max) = self.world_bounds[mesh_index];
+        frustum.intersects_box(min, max)
+    }
+
+    // Animations of all objects start at the beginning of the simulation, except for those that are started explicitly.
+    pub fn reset_animations<'a>(&mut self, explicitly_started_objects: impl Iterator<Item = &'a usize>) {
+        for start_time in self.animation_start_times.iter_mut() {
+            *start_time = Some(Duration::from_secs(0));
+        }
+        for object_index in explicitly_started_objects {
+            match self.animation_start_times.get_mut(*object_index) {
+                Some(start_time) => *start_time = None,
+                None => warn!("There is no static object {} whose animation could be started", object_index),
+            }
+        }
+    }
+
+    // Does nothing if the animation is already running.
+    pub fn start_animation(&mut self, object_index: usize, total_simulated_time: Duration) {
+        if let Some(start_time) = self.animation_start_times.get_mut(object_index) {
+            start_time.get_or_insert(total_simulated_time);
+        }
+    }
+
+    fn animation_time(&self, object_index: usize, total_simulated_time: Duration) -> Option<Duration> {
+        self.animation_start_times[object_index].map(|start_time| total_simulated_time.saturating_sub(start_time))
     }
 
-    pub fn step(&self, timer: &Timer, queue: &wgpu::Queue, fluid_config: &FluidConfig) {
+    pub fn step(&mut self, timer: &Timer, queue: &wgpu::Queue, fluid_config: &FluidConfig) {
         // We typically don't have a lot of objects. So just overwrite the entire mesh desc.
         let meshes_gpu: Vec<MeshDataGpu> = self
             .meshes
             .iter()
-            .map(|mesh| mesh.to_gpu(timer.total_simulated_time(), timer.simulation_delta(), fluid_config))
+            .map(|mesh| {
+                mesh.to_gpu(
+                    self.animation_time(mesh.object_index, timer.total_simulated_time()),
+                    timer.simulation_delta(),
+                    fluid_config,
+                )
+            })
             .collect();
         queue.write_buffer(&self.mesh_desc_buffer, 0, bytemuck::cast_slice(&meshes_gpu));
+        self.world_bounds = self
+            .meshes
+            .iter()
+            .map(|mesh| mesh.world_bounds_at_time(self.animation_time(mesh.object_index, timer.total_simulated_time()).unwrap_or_default()))
+            .collect();
+
+        // Deforming meshes are simply re-uploaded. Voxelization happens every step anyways and picks up the new vertices.
+        // Note that the deformation itself isn't accounted for in the solid velocities, only the rigid animation is.
+        for animation in self.vertex_animations.iter() {
+            let animation_time = self
+                .animation_time(animation.object_index, timer.total_simulated_time())
+                .unwrap_or_default();
+            let vertices = animation.vertices_at_time(animation_time);
+            queue.write_buffer(
+                &self.vertex_buffer,
+                animation.vertex_offset as wgpu::BufferAddress * MeshVertex::SIZE,
+                bytemuck::cast_slice(&vertices),
+            );
+        }
     }
 }
@@ -0,0 +1,76 @@
+use super::FluidConfig;
+use serde::Deserialize;
+use std::{collections::HashSet, error::Error, fs::File, io::BufReader, path::PathBuf};
+use vdb_rs::{VdbLevel, VdbReader};
+
+// Initial fluid volume read from a float grid (typically "density") of an OpenVDB file, e.g. exported from Houdini.
+// Only leaf voxels are considered, constant tiles of the sparse tree are ignored.
+// Velocity grids are not imported, all particles of a source start with the same initial_velocity.
+#[derive(Deserialize)]
+pub struct VdbFluidSource {
+    pub path: PathBuf,
+    #[serde(default = "default_grid_name")]
+    pub grid: String,
+    // World space size of a single vdb voxel.
+    pub voxel_size: f32,
+    // World space position of the vdb voxel at index (0, 0, 0).
+    #[serde(default = "default_offset")]
+    pub offset: cgmath::Point3<f32>,
+    // Voxels with a value above the threshold are filled with fluid.
+    #[serde(default = "default_threshold")]
+    pub threshold: f32,
+    #[serde(default = "super::zero_velocity")]
+    pub initial_velocity: cgmath::Vector3<f32>,
+    // Index into FluidConfig::phases
+    #[serde(default)]
+    pub phase: u32,
+}
+
+fn default_grid_name() -> String {
+    "density".to_owned()
+}
+
+fn default_offset() -> cgmath::Point3<f32> {
+    cgmath::point3(0.0, 0.0, 0.0)
+}
+
+fn default_threshold() -> f32 {
+    0.5
+}
+
+impl VdbFluidSource {
+    // Reads the grid and returns all (deduplicated) fluid grid cells that contain a voxel above threshold.
+    pub fn load_fluid_cells(&self, fluid_config: &FluidConfig) -> Result<Vec<cgmath::Point3<u32>>, Box<dyn Error>> {
+        let file = File::open(&self.path)?;
+        let mut reader = VdbReader::new(BufReader::new(file))?;
+        let grid = reader.read_grid::<f32>(&self.grid)?;
+
+        let world_to_grid = 1.0 / fluid_config.grid_to_world_scale;
+        let mut cells = HashSet::new();
+        let mut num_ignored_tiles = 0;
+        for (index, value, level) in grid.iter() {
+            if level != VdbLevel::Voxel {
+                num_ignored_tiles += 1;
+                continue;
+            }
+            if value <= self.threshold {
+                continue;
+            }
+            // Same conversion as for fluid cubes.
+            let world = self.offset + cgmath::vec3(index.x + 0.5, index.y + 0.5, index.z + 0.5) * self.voxel_size;
+            let grid_position = world * world_to_grid;
+            if grid_position.x < 0.0 || grid_position.y < 0.0 || grid_position.z < 0.0 {
+                continue;
+            }
+            cells.insert(grid_position.cast::<u32>().unwrap());
+        }
+        if num_ignored_tiles > 0 {
+            warn!("Ignored {} tiles in vdb grid {:?} of {:?}", num_ignored_tiles, self.grid, self.path);
+        }
+
+        // Sort for a somewhat memory coherent initial particle order.
+        let mut cells: Vec<_> = cells.into_iter().collect();
+        cells.sort_by_key(|c| (c.z, c.y, c.x));
+        Ok(cells)
+    }
+}
@@ -1,5 +1,3 @@
-use std::rc::Rc;
-
 use crate::scene::SceneModels;
 use crate::wgpu_utils::{binding_builder::*, binding_glsl, pipelines::*, shader::ShaderDirectory};
 
@@ -45,19 +43,23 @@ impl SceneVoxelization {
             .texture(&volume_view)
             .create(device, "BindGroup: Voxelization");
 
+        let pipeline_layout = pipeline_manager.create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Voxelize Mesh Pipeline Layout"),
+                bind_group_layouts: &[&global_bind_group_layout, &group_layout.layout],
+                push_constant_ranges: &[wgpu::PushConstantRange {
+                    stages: wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
+                    range: 0..4,
+                }],
+            },
+        );
         let pipeline_conservative_hull = pipeline_manager.create_render_pipeline(
             device,
             shader_dir,
             RenderPipelineCreationDesc {
                 label: "Voxelize Mesh",
-                layout: Rc::new(device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                    label: Some("Voxelize Mesh Pipeline Layout"),
-                    bind_group_layouts: &[&global_bind_group_layout, &group_layout.layout],
-                    push_constant_ranges: &[wgpu::PushConstantRange {
-                        stages: wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
-                        range: 0..4,
-                    }],
-                })),
+                layout: pipeline_layout,
                 vertex: VertexStateCreationDesc {
                     shader_relative_path: "voxelize/conservative_hull.vert".into(),
                     buffers: Vec::new(),
@@ -147,7 +149,9 @@ impl SceneVoxelization {
         // (i.e. we may need to duplicate vertices that are otherwise shared with triangles)
 
         for (i, mesh) in scene_models.meshes.iter().enumerate() {
-            rpass.set_push_constants(
+            pipeline_manager.set_push_constants_render(
+                &mut rpass,
+                &self.pipeline_conservative_hull,
                 wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
                 0,
                 bytemuck::cast_slice(&[i as u32]),
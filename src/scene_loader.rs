@@ -0,0 +1,63 @@
+use crate::{scene::SceneData, settings_overrides::SettingsOverrides};
+use std::{
+    path::{Path, PathBuf},
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+// Loads scene files on a background thread (json, models, textures) so the current scene stays interactive meanwhile.
+// Gpu resources are only created once loading is done, all at once on the main thread (see Scene::from_data).
+pub struct SceneLoader {
+    pending: Option<PendingSceneLoad>,
+}
+
+struct PendingSceneLoad {
+    path: PathBuf,
+    start_time: Instant,
+    receiver: mpsc::Receiver<Result<SceneData, String>>,
+}
+
+impl SceneLoader {
+    pub fn new() -> Self {
+        SceneLoader { pending: None }
+    }
+
+    // Replaces any load that is still in progress, its result is discarded.
+    pub fn start(&mut self, path: &Path, settings_overrides: &SettingsOverrides) {
+        let (sender, receiver) = mpsc::channel();
+        let thread_path = path.to_path_buf();
+        let settings_overrides = settings_overrides.clone();
+        std::thread::spawn(move || {
+            // Errors aren't Send, so they're passed on as text.
+            let result = SceneData::load_file(&thread_path, &settings_overrides).map_err(|err| err.to_string());
+            // If the receiver is gone, this load was superseded by another one.
+            let _ = sender.send(result);
+        });
+
+        info!("Loading scene {:?} in the background", path);
+        self.pending = Some(PendingSceneLoad {
+            path: path.to_path_buf(),
+            start_time: Instant::now(),
+            receiver,
+        });
+    }
+
+    // Scene that is currently being loaded and for how long.
+    pub fn loading_scene(&self) -> Option<(&Path, Duration)> {
+        self.pending
+            .as_ref()
+            .map(|pending| (pending.path.as_path(), pending.start_time.elapsed()))
+    }
+
+    // Returns the loaded data once the background thread is done.
+    pub fn poll_finished(&mut self) -> Option<(PathBuf, Result<SceneData, String>)> {
+        let result = match self.pending.as_ref()?.receiver.try_recv() {
+            Ok(result) => result,
+            Err(mpsc::TryRecvError::Empty) => return None,
+            Err(mpsc::TryRecvError::Disconnected) => Err("scene loading thread panicked".to_string()),
+        };
+        let pending = self.pending.take().unwrap();
+        info!("Loading scene data of {:?} took {:?}", pending.path, pending.start_time.elapsed());
+        Some((pending.path, result))
+    }
+}
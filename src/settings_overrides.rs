@@ -0,0 +1,169 @@
+use crate::{scene::Scene, simulation::SolverConfig, utils::merge_json};
+use serde::Deserialize;
+
+// Scene & simulation settings applied on top of a scene file, used by parameter sweeps (per variant) and --set on the command line.
+#[derive(Deserialize, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SettingsOverrides {
+    // Merged into the scene json (json merge patch, i.e. objects are merged, everything else replaced, null removes).
+    #[serde(default)]
+    pub scene: serde_json::Value,
+    #[serde(default)]
+    pub solver_velocity: SolverOverrides,
+    #[serde(default)]
+    pub solver_density: SolverOverrides,
+    #[serde(default)]
+    pub particle_rebinning_step_frequency: Option<u32>,
+    #[serde(default)]
+    pub vorticity_confinement: Option<f32>,
+}
+
+#[derive(Deserialize, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SolverOverrides {
+    pub error_tolerance: Option<f32>,
+    pub max_num_iterations: Option<i32>,
+    pub error_check_frequency: Option<i32>,
+}
+
+impl SolverOverrides {
+    fn is_empty(&self) -> bool {
+        self.error_tolerance.is_none() && self.max_num_iterations.is_none() && self.error_check_frequency.is_none()
+    }
+
+    fn apply(&self, config: &mut SolverConfig) {
+        if let Some(error_tolerance) = self.error_tolerance {
+            config.error_tolerance = error_tolerance;
+        }
+        if let Some(max_num_iterations) = self.max_num_iterations {
+            config.max_num_iterations = max_num_iterations;
+        }
+        if let Some(error_check_frequency) = self.error_check_frequency {
+            config.error_check_frequency = error_check_frequency;
+        }
+    }
+}
+
+// Top level keys that aren't part of the scene json. "solver" addresses both solvers.
+const NON_SCENE_KEYS: [&str; 5] = [
+    "solver",
+    "solver_velocity",
+    "solver_density",
+    "particle_rebinning_step_frequency",
+    "vorticity_confinement",
+];
+
+impl SettingsOverrides {
+    // Parses assignments of the form "fluid.max_num_particles=4000000" or "solver.error_tolerance=0.05".
+    // Values are json if they parse as such, strings otherwise. Keys that aren't solver settings etc. are paths into the scene json.
+    pub fn from_assignments(assignments: &[String]) -> Result<Self, String> {
+        let mut overrides = serde_json::Map::new();
+        for assignment in assignments.iter() {
+            let (key, value) = match assignment.split_once('=') {
+                Some((key, value)) if !key.is_empty() => (key, value),
+                _ => return Err(format!("Expected <key>=<value>, got {:?}", assignment)),
+            };
+            let value = serde_json::from_str(value).unwrap_or_else(|_| serde_json::Value::String(value.to_owned()));
+
+            let path: Vec<&str> = key.split('.').collect();
+            if path.iter().any(|segment| segment.is_empty()) {
+                return Err(format!("Invalid key {:?}", key));
+            }
+            match path[0] {
+                "solver" => {
+                    for solver in ["solver_velocity", "solver_density"].iter() {
+                        let mut solver_path = vec![*solver];
+                        solver_path.extend_from_slice(&path[1..]);
+                        insert_at_path(&mut overrides, &solver_path, value.clone());
+                    }
+                }
+                key if NON_SCENE_KEYS.contains(&key) => insert_at_path(&mut overrides, &path, value),
+                _ => {
+                    let mut scene_path = vec!["scene"];
+                    scene_path.extend_from_slice(&path);
+                    insert_at_path(&mut overrides, &scene_path, value);
+                }
+            }
+        }
+        serde_json::from_value(serde_json::Value::Object(overrides)).map_err(|err| format!("Invalid settings override: {}", err))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scene.is_null()
+            && self.solver_velocity.is_empty()
+            && self.solver_density.is_empty()
+            && self.particle_rebinning_step_frequency.is_none()
+            && self.vorticity_confinement.is_none()
+    }
+
+    pub fn apply_to_scene_json(&self, scene: &mut serde_json::Value) {
+        if !self.scene.is_null() {
+            merge_json(scene, &self.scene);
+        }
+    }
+
+    // Applies all non-scene overrides to a freshly loaded or reset scene.
+    pub fn apply(&self, scene: &mut Scene) {
+        self.solver_velocity.apply(scene.fluid_mut().pressure_solver_config_velocity());
+        self.solver_density.apply(scene.fluid_mut().pressure_solver_config_density());
+        if let Some(frequency) = self.particle_rebinning_step_frequency {
+            scene.fluid_mut().dynamic_settings().particle_rebinning_step_frequency = frequency;
+        }
+        if let Some(strength) = self.vorticity_confinement {
+            scene.fluid_mut().dynamic_settings().vorticity_confinement = strength;
+        }
+    }
+
+    // Fails if any scene override ended up in a part of the scene json that wasn't used when parsing it, i.e. a misspelled or unknown key.
+    // Ignored paths are dot separated keys as reported by serde_ignored (e.g. "fluid.max_num_particle").
+    pub fn check_ignored_scene_paths(&self, ignored_paths: &[String]) -> Result<(), String> {
+        let mut override_paths = Vec::new();
+        collect_value_paths(&self.scene, "", &mut override_paths);
+        for override_path in override_paths.iter() {
+            if let Some(ignored_path) = ignored_paths
+                .iter()
+                .find(|ignored_path| is_path_within(ignored_path, override_path) || is_path_within(override_path, ignored_path))
+            {
+                return Err(format!("Unknown scene setting {:?} (overridden by {:?})", ignored_path, override_path));
+            }
+        }
+        Ok(())
+    }
+}
+
+// Dot separated paths of all non-object values in a json value.
+fn collect_value_paths(value: &serde_json::Value, prefix: &str, paths: &mut Vec<String>) {
+    match value.as_object() {
+        Some(object) => {
+            for (key, child) in object.iter() {
+                let path = if prefix.is_empty() { key.clone() } else { format!("{}.{}", prefix, key) };
+                collect_value_paths(child, &path, paths);
+            }
+        }
+        None if !prefix.is_empty() => paths.push(prefix.to_owned()),
+        None => {}
+    }
+}
+
+// Whether path is the same as parent or lies somewhere below it.
+fn is_path_within(path: &str, parent: &str) -> bool {
+    path == parent || (path.starts_with(parent) && path[parent.len()..].starts_with('.'))
+}
+
+fn insert_at_path(object: &mut serde_json::Map<String, serde_json::Value>, path: &[&str], value: serde_json::Value) {
+    match path {
+        [] => {}
+        [key] => {
+            object.insert(key.to_string(), value);
+        }
+        [key, rest @ ..] => {
+            let child = object
+                .entry(key.to_string())
+                .or_insert_with(|| serde_json::Value::Object(Default::default()));
+            if !child.is_object() {
+                *child = serde_json::Value::Object(Default::default());
+            }
+            insert_at_path(child.as_object_mut().unwrap(), rest, value);
+        }
+    }
+}
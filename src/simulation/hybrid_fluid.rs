@@ -1,41 +1,270 @@
-use super::pressure_solver::*;
+use super::{pressure_solver::*, radix_sort::RadixSort, workgroup_sizes::WorkgroupSizes};
 use crate::{
     scene::voxelization::SceneVoxelization,
-    wgpu_utils::{self, binding_builder::*, binding_glsl, pipelines::*, shader::*, uniformbuffer::*},
+    utils::round_to_multiple,
+    wgpu_utils::{self, binding_builder::*, binding_glsl, pipelines::*, profiling::ProfilingDetail, shader::*, uniformbuffer::*},
 };
+use futures::{Future, FutureExt};
 use rand::prelude::*;
-use std::{collections::VecDeque, path::Path, rc::Rc, time::Duration};
-use wgpu_profiler::{wgpu_profiler, GpuProfiler};
+use std::{collections::VecDeque, path::Path, pin::Pin, sync::Arc, time::Duration};
+use wgpu::util::DeviceExt;
+use wgpu_profiler::GpuProfiler;
 
 #[repr(C)]
 #[derive(Clone, Copy)]
 struct SimulationPropertiesUniformBufferContent {
     gravity_grid: cgmath::Vector3<f32>,
     num_particles: u32,
+    interaction_ray_origin: cgmath::Point3<f32>,
+    interaction_radius: f32,
+    interaction_ray_direction: cgmath::Vector3<f32>,
+    num_force_fields: u32,
+    interaction_velocity_change: cgmath::Vector3<f32>,
+    secondary_phase_relative_density: f32,
+    vorticity_confinement: f32,
+    surface_tension: f32,
+    max_num_particles: u32,
+    particles_per_cell: u32,
+    periodic_axes: u32,
+    max_particle_age: f32,
+    padding0: cgmath::Vector2<u32>,
+    erase_box_min: cgmath::Point3<f32>,
+    padding1: f32,
+    erase_box_max: cgmath::Point3<f32>,
+    padding2: f32,
+    force_fields: [ForceFieldUniformBufferContent; MAX_NUM_FORCE_FIELDS],
 }
 unsafe impl bytemuck::Pod for SimulationPropertiesUniformBufferContent {}
 unsafe impl bytemuck::Zeroable for SimulationPropertiesUniformBufferContent {}
 
+impl SimulationPropertiesUniformBufferContent {
+    // Offset of num_particles, which is updated on the gpu timeline from the particle count buffer (see ParticleCountBufferContent)
+    const NUM_PARTICLES_OFFSET: u64 = 12;
+}
+
+// Particle count on the gpu, laid out so that it can be used directly for indirect draws & dispatches over all particles.
+// Renderers should use this instead of num_particles, since the count may change on the gpu.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct ParticleCountBufferContent {
+    // Draw indirect arguments for one quad per particle (vertex count, instance count, first vertex, first instance)
+    draw_vertex_count: u32,
+    num_particles: u32,
+    draw_first_vertex: u32,
+    draw_first_instance: u32,
+    // Dispatch indirect arguments for WorkgroupSizes::particles
+    dispatch_particles: [u32; 3],
+    padding: u32,
+}
+unsafe impl bytemuck::Pod for ParticleCountBufferContent {}
+unsafe impl bytemuck::Zeroable for ParticleCountBufferContent {}
+
+// Needs to be kept in sync with MAX_NUM_FORCE_FIELDS in hybrid_fluid.glsl
+pub const MAX_NUM_FORCE_FIELDS: usize = 4;
+
+// Every particle belongs to one of several immiscible fluids (e.g. water & oil), called phases.
+// Phase 0 is the primary phase, all densities in the simulation are relative to it.
+// Needs to be kept in sync with MAX_NUM_FLUID_PHASES in hybrid_fluid.glsl
+pub const MAX_NUM_FLUID_PHASES: usize = 2;
+
+// Most storage buffers any compute pass of the fluid binds, the binning passes with 11 particle buffers plus 3 from the global bindings.
+// Above the default limit of 8, so the device needs to be created with at least this many.
+pub const MAX_STORAGE_BUFFERS_PER_SHADER_STAGE: u32 = 14;
+
+// External force field in grid space, evaluated when gathering velocities to the grid.
+// See ForceField struct in hybrid_fluid.glsl
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct ForceFieldUniformBufferContent {
+    position: cgmath::Point3<f32>,
+    field_type: u32,
+    vector: cgmath::Vector3<f32>,
+    strength: f32,
+    radius: f32,
+    padding: [f32; 3],
+}
+unsafe impl bytemuck::Pod for ForceFieldUniformBufferContent {}
+unsafe impl bytemuck::Zeroable for ForceFieldUniformBufferContent {}
+
+impl ForceFieldUniformBufferContent {
+    const TYPE_WIND: u32 = 1;
+    const TYPE_VORTEX: u32 = 2;
+    const TYPE_ATTRACTOR: u32 = 3;
+
+    pub fn wind(acceleration: cgmath::Vector3<f32>) -> Self {
+        ForceFieldUniformBufferContent {
+            position: cgmath::point3(0.0, 0.0, 0.0),
+            field_type: Self::TYPE_WIND,
+            vector: acceleration,
+            strength: 1.0,
+            radius: 0.0,
+            padding: [0.0; 3],
+        }
+    }
+
+    // Axis is expected to be normalized. A radius of zero means no falloff.
+    pub fn vortex(center: cgmath::Point3<f32>, axis: cgmath::Vector3<f32>, strength: f32, radius: f32) -> Self {
+        ForceFieldUniformBufferContent {
+            position: center,
+            field_type: Self::TYPE_VORTEX,
+            vector: axis,
+            strength,
+            radius,
+            padding: [0.0; 3],
+        }
+    }
+
+    // A radius of zero means no falloff.
+    pub fn attractor(position: cgmath::Point3<f32>, strength: f32, radius: f32) -> Self {
+        ForceFieldUniformBufferContent {
+            position,
+            field_type: Self::TYPE_ATTRACTOR,
+            vector: cgmath::vec3(0.0, 0.0, 0.0),
+            strength,
+            radius,
+            padding: [0.0; 3],
+        }
+    }
+}
+
+// Grid state read back from the gpu, see HybridFluid::read_grid
+pub struct GridReadback {
+    // Grid space velocity (cells/s)
+    pub velocity: Vec<cgmath::Vector3<f32>>,
+    // Pressure of the divergence projection in solver units.
+    pub pressure: Vec<f32>,
+    // 1 for fluid, 0 for solid, -1 for air cells.
+    pub marker: Vec<f32>,
+}
+
+// The stages a single simulation step is made of.
+// Usually all of them are executed at once, but for debugging it is possible to stop in between and inspect the intermediate grids.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SimulationStage {
+    TransferToGrid = 0,
+    SolvePressure = 1,
+    Advect = 2,
+    CorrectDensityError = 3,
+}
+
+impl SimulationStage {
+    pub const ALL: [SimulationStage; 4] = [
+        SimulationStage::TransferToGrid,
+        SimulationStage::SolvePressure,
+        SimulationStage::Advect,
+        SimulationStage::CorrectDensityError,
+    ];
+
+    pub fn next(self) -> Option<SimulationStage> {
+        Self::ALL.get(self as usize + 1).cloned()
+    }
+}
+
+// User interaction, particles close to a ray are accelerated. All in grid space.
+#[derive(Clone, Copy)]
+pub struct InteractionForce {
+    pub ray_origin: cgmath::Point3<f32>,
+    pub ray_direction: cgmath::Vector3<f32>,
+    pub radius: f32,
+    // Velocity change per second in the center of the ray.
+    pub acceleration: cgmath::Vector3<f32>,
+    // Removes all particles within the radius instead of accelerating them.
+    pub erase: bool,
+    // Colors all particles within the radius (sRGB) instead of accelerating them.
+    pub dye: Option<[u8; 3]>,
+}
+
+// Which particles the interaction pass removes, needs to be kept in sync with apply_interaction_force.comp
+#[derive(Clone, Copy, PartialEq)]
+enum EraseMode {
+    None = 0,
+    // Particles close to the interaction ray.
+    InteractionRay = 1,
+    // Particles inside the erase box of the simulation properties, see remove_fluid_in_box.
+    EraseBox = 2,
+}
+
+// Integration scheme used for moving particles through the grid velocity field.
+#[derive(Clone, Copy, Debug, EnumIter, PartialEq)]
+pub enum AdvectionScheme {
+    // Runge Kutta 4 confined to the particle's current cell, cheap since it reuses the corners that are sampled anyways.
+    // Large steps get clamped at the cell border.
+    CellRk4 = 0,
+    // Midpoint method on the full velocity grid.
+    Rk2 = 1,
+    // Ralston's third order method on the full velocity grid.
+    Rk3 = 2,
+}
+
+// How particles are reordered by cell during particle binning.
+#[derive(Clone, Copy, Debug, EnumIter, PartialEq)]
+pub enum ParticleBinningMethod {
+    // Counts particles per cell with atomics, prefix sum over the grid and rewrite. Needed for resampling.
+    CountingSort,
+    // Radix sort by the Morton code of the particle's cell, no global atomics.
+    RadixSort,
+}
+
+impl ParticleBinningMethod {
+    // Used to find the timings of each method in the profiler.
+    pub fn profiling_scope_label(self) -> &'static str {
+        match self {
+            ParticleBinningMethod::CountingSort => "Particle Binning (counting sort)",
+            ParticleBinningMethod::RadixSort => "Particle Binning (radix sort)",
+        }
+    }
+}
+
 pub struct DynamicSettings {
     // perform particle binning every n steps
     pub particle_rebinning_step_frequency: u32,
+    // Resampling steps always use ParticleBinningMethod::CountingSort.
+    pub particle_binning_method: ParticleBinningMethod,
+    // Strength of the vorticity confinement force that counteracts the loss of small swirls in the grid transfers. Zero disables it.
+    pub vorticity_confinement: f32,
+    // Split & merge particles during binning to keep the number of particles per cell close to the configured particles per cell.
+    pub particle_resampling: bool,
+    pub advection_scheme: AdvectionScheme,
+    // Projects particles that ended up inside solids back out after advection.
+    pub solid_push_out: bool,
 }
 
 pub struct HybridFluid {
     grid_dimension: wgpu::Extent3d,
+    workgroup_sizes: WorkgroupSizes,
 
     pressure_solver: PressureSolver,
     pressure_field_from_velocity: PressureField,
     pressure_field_from_density: PressureField,
 
+    volume_velocity: [wgpu::Texture; 3],
+    volume_density: wgpu::Texture,
     volume_linked_lists: wgpu::Texture,
     volume_marker: wgpu::Texture,
+    // Marker grid at the end of the previous step, only used for visualizing cells that changed their type.
+    volume_marker_previous: wgpu::Texture,
+    volume_particle_resampling: wgpu::Texture,
     volume_debug: Option<wgpu::Texture>,
 
     particles_position_llindex: wgpu::Buffer,
     particles_position_llindex_tmp: wgpu::Buffer,
+    particles_velocity: [wgpu::Buffer; 3],
+    particles_phase: wgpu::Buffer,
+    particles_phase_tmp: wgpu::Buffer,
+    // Seconds since a particle was added, kept through resampling. Split particles inherit the age of their origin.
+    particles_age: wgpu::Buffer,
+    particles_age_tmp: wgpu::Buffer,
+    // Passive dye color, packed rgba8 (sRGB). Alpha is how much dye a particle carries, undyed particles are zero.
+    particles_dye: wgpu::Buffer,
+    particles_dye_tmp: wgpu::Buffer,
     particle_binning_atomic_counter: wgpu::Buffer,
-    simulation_properties_uniformbuffer: UniformBuffer<SimulationPropertiesUniformBufferContent>,
+    particle_radix_sort: RadixSort,
+    particle_count_buffer: wgpu::Buffer,
+    // Resampling changes the particle count on the gpu, it is read back asynchronously.
+    particle_count_readback_buffer: wgpu::Buffer,
+    particle_count_readback_scheduled: bool,
+    particle_count_readback: Option<Pin<Box<dyn Future<Output = Result<(), wgpu::BufferAsyncError>> + Send>>>,
+    simulation_properties_uniformbuffer: UniformBufferRing<SimulationPropertiesUniformBufferContent>,
     simulation_properties: SimulationPropertiesUniformBufferContent,
 
     bind_group_general: wgpu::BindGroup,
@@ -44,9 +273,12 @@ pub struct HybridFluid {
     bind_group_divergence_projection_write_velocity: wgpu::BindGroup,
     bind_group_advect_particles: wgpu::BindGroup,
     bind_group_binning: wgpu::BindGroup,
+    bind_group_particle_count_update: wgpu::BindGroup,
     bind_group_density_projection_gather_error: wgpu::BindGroup,
     bind_group_density_projection_correct_particles: wgpu::BindGroup,
     bind_group_density_projection_write_velocity: wgpu::BindGroup,
+    bind_group_vorticity_compute: wgpu::BindGroup,
+    bind_group_surface_tension: wgpu::BindGroup,
 
     // The interface to any renderer of the fluid. Readonly access to relevant resources
     bind_group_renderer: wgpu::BindGroup,
@@ -55,24 +287,40 @@ pub struct HybridFluid {
     pipeline_transfer_build_linkedlist: ComputePipelineHandle,
     pipeline_transfer_set_boundary_marker: ComputePipelineHandle,
     pipeline_transfer_gather_velocity: ComputePipelineHandle,
+    pipeline_surface_tension_color_field: ComputePipelineHandle,
+    pipeline_surface_tension_curvature: ComputePipelineHandle,
     pipeline_divergence_compute: ComputePipelineHandle,
     pipeline_divergence_remove: ComputePipelineHandle,
     pipeline_extrapolate_velocity: ComputePipelineHandle,
+    pipeline_vorticity_compute: ComputePipelineHandle,
     pipeline_advect_particles: ComputePipelineHandle,
+    pipeline_apply_interaction_force: ComputePipelineHandle,
     pipeline_binning_count: ComputePipelineHandle,
     pipeline_binning_scan: ComputePipelineHandle,
     pipeline_binning_rewrite_particles: ComputePipelineHandle,
+    pipeline_binning_sort_keys: ComputePipelineHandle,
+    pipeline_binning_sort_gather: ComputePipelineHandle,
+    pipeline_resample_plan: ComputePipelineHandle,
+    pipeline_binning_update_count: ComputePipelineHandle,
     pipeline_density_projection_gather_error: ComputePipelineHandle,
     pipeline_density_projection_position_change: ComputePipelineHandle,
     pipeline_density_projection_correct_particles: ComputePipelineHandle,
+    pipeline_rescale_particles: ComputePipelineHandle,
+    pipeline_resample_pressure: ComputePipelineHandle,
+
+    group_layout_resample_pressure: BindGroupLayoutWithDesc,
+
+    interaction_force: Option<InteractionForce>,
+    // Set by remove_fluid_in_box, particles in the erase box of the simulation properties are removed with the next step.
+    erase_box_pending: bool,
 
     max_num_particles: u32,
+    // Jitters positions of newly added particles, see set_particle_seed.
+    particle_rng: rand::rngs::SmallRng,
     step_counter: u32,
     dynamic_settings: DynamicSettings,
 }
 
-static mut GROUP_LAYOUT_RENDERER: Option<BindGroupLayoutWithDesc> = None;
-
 #[repr(C)]
 #[derive(Clone, Copy)]
 struct ParticlePositionLl {
@@ -85,27 +333,40 @@ unsafe impl bytemuck::Pod for ParticlePositionLl {}
 unsafe impl bytemuck::Zeroable for ParticlePositionLl {}
 
 impl HybridFluid {
-    // particles are distributed 2x2x2 within a single gridcell
-    // (seems to be widely accepted as the default. Houdini seems to have this configurable from 4-16, maybe worth experimenting with it! Note however, that the density error computation assumes this constant as well!)
-    pub const PARTICLES_PER_GRID_CELL: u32 = 8;
+    // 8 particles, distributed 2x2x2 within a single gridcell, seems to be widely accepted as the default.
+    // Houdini has this configurable from 4-16.
+    pub const DEFAULT_PARTICLES_PER_GRID_CELL: u32 = 8;
+    // Linked list traversal in the transfer shaders scales with the particles per cell, beyond this it gets prohibitively slow.
+    pub const MAX_PARTICLES_PER_GRID_CELL: u32 = 32;
 
     pub fn new(
         device: &wgpu::Device,
         grid_dimension: wgpu::Extent3d,
         max_num_particles: u32,
+        particles_per_cell: u32,
         shader_dir: &ShaderDirectory,
         pipeline_manager: &mut PipelineManager,
         global_bind_group_layout: &wgpu::BindGroupLayout,
         voxelization: &SceneVoxelization,
     ) -> Self {
+        let workgroup_sizes = WorkgroupSizes::from_shader_dir(shader_dir);
+        if particles_per_cell == 0 || particles_per_cell > Self::MAX_PARTICLES_PER_GRID_CELL {
+            warn!(
+                "{} particles per cell are not supported, clamping to 1-{}",
+                particles_per_cell,
+                Self::MAX_PARTICLES_PER_GRID_CELL
+            );
+        }
+        let particles_per_cell = particles_per_cell.max(1).min(Self::MAX_PARTICLES_PER_GRID_CELL);
+
         // Resources
-        let simulation_properties_uniformbuffer = UniformBuffer::new(device);
+        let simulation_properties_uniformbuffer = UniformBufferRing::new(device);
 
         let create_particle_buffer = |label| {
             device.create_buffer(&wgpu::BufferDescriptor {
                 label: Some(label),
                 size: max_num_particles as u64 * std::mem::size_of::<ParticlePositionLl>() as u64,
-                usage: wgpu::BufferUsage::STORAGE | wgpu::BufferUsage::COPY_DST,
+                usage: wgpu::BufferUsage::STORAGE | wgpu::BufferUsage::COPY_DST | wgpu::BufferUsage::COPY_SRC,
                 mapped_at_creation: false,
             })
         };
@@ -120,12 +381,41 @@ impl HybridFluid {
         let particles_velocity_x = create_particle_buffer("Buffer: Particles velocity X");
         let particles_velocity_y = create_particle_buffer("Buffer: Particles velocity Y");
         let particles_velocity_z = create_particle_buffer("Buffer: Particles velocity Z");
+        let create_scalar_particle_buffer = |label| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size: max_num_particles as u64 * std::mem::size_of::<u32>() as u64,
+                usage: wgpu::BufferUsage::STORAGE | wgpu::BufferUsage::COPY_DST | wgpu::BufferUsage::COPY_SRC,
+                mapped_at_creation: false,
+            })
+        };
+        let particles_phase = create_scalar_particle_buffer("Buffer: Particles phase");
+        let particles_phase_tmp = create_scalar_particle_buffer("Buffer: Particles phase tmp");
+        let particles_age = create_scalar_particle_buffer("Buffer: Particles age");
+        let particles_age_tmp = create_scalar_particle_buffer("Buffer: Particles age tmp");
+        let particles_dye = create_scalar_particle_buffer("Buffer: Particles dye");
+        let particles_dye_tmp = create_scalar_particle_buffer("Buffer: Particles dye tmp");
         let particle_binning_atomic_counter = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Buffer: Atomic counter for particle binning"),
             size: wgpu::BIND_BUFFER_ALIGNMENT,
             usage: wgpu::BufferUsage::STORAGE | wgpu::BufferUsage::COPY_DST,
             mapped_at_creation: false,
         });
+        // Cell Morton codes interleave up to 10 bits per axis.
+        let max_grid_extent = grid_dimension.width.max(grid_dimension.height).max(grid_dimension.depth_or_array_layers);
+        let bits_per_axis = (32 - (max_grid_extent - 1).leading_zeros()).min(10);
+        let particle_radix_sort = RadixSort::new(device, shader_dir, pipeline_manager, max_num_particles, bits_per_axis * 3);
+        let particle_count_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Buffer: Particle count"),
+            contents: bytemuck::bytes_of(&Self::particle_count_buffer_content(0, workgroup_sizes.particles)),
+            usage: wgpu::BufferUsage::INDIRECT | wgpu::BufferUsage::STORAGE | wgpu::BufferUsage::COPY_DST | wgpu::BufferUsage::COPY_SRC,
+        });
+        let particle_count_readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Buffer: Particle count readback"),
+            size: 4,
+            usage: wgpu::BufferUsage::MAP_READ | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
 
         let create_volume_texture_desc = |label: &'static str, format: wgpu::TextureFormat| -> wgpu::TextureDescriptor {
             wgpu::TextureDescriptor {
@@ -135,7 +425,8 @@ impl HybridFluid {
                 sample_count: 1,
                 dimension: wgpu::TextureDimension::D3,
                 format,
-                usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::STORAGE | wgpu::TextureUsage::COPY_DST,
+                // Copy source for debug readbacks, see GridCellInspector.
+                usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::STORAGE | wgpu::TextureUsage::COPY_DST | wgpu::TextureUsage::COPY_SRC,
             }
         };
         // TODO: Reuse volumes to safe memory, not all are used simultaneously.
@@ -147,6 +438,14 @@ impl HybridFluid {
             wgpu::TextureFormat::R32Uint,
         ));
         let volume_marker = device.create_texture(&create_volume_texture_desc("Marker Grid", wgpu::TextureFormat::R8Snorm));
+        let volume_marker_previous = device.create_texture(&create_volume_texture_desc("Previous Marker Grid", wgpu::TextureFormat::R8Snorm));
+        let volume_particle_resampling =
+            device.create_texture(&create_volume_texture_desc("Particle Resampling Volume", wgpu::TextureFormat::R32Uint));
+        let volume_phase_count = device.create_texture(&create_volume_texture_desc("Phase Count Volume", wgpu::TextureFormat::R32Uint));
+        let volume_density = device.create_texture(&create_volume_texture_desc("Density Volume", wgpu::TextureFormat::R32Float));
+        let volume_curl = device.create_texture(&create_volume_texture_desc("Curl Volume", wgpu::TextureFormat::Rgba32Float));
+        let volume_color_field = device.create_texture(&create_volume_texture_desc("Color Field Volume", wgpu::TextureFormat::R32Float));
+        let volume_curvature = device.create_texture(&create_volume_texture_desc("Curvature Volume", wgpu::TextureFormat::R32Float));
         let volume_debug = if cfg!(debug_assertions) {
             Some(device.create_texture(&create_volume_texture_desc("Debug Volume", wgpu::TextureFormat::R32Float)))
         } else {
@@ -159,6 +458,13 @@ impl HybridFluid {
         let volume_velocity_view_z = volume_velocity_z.create_view(&Default::default());
         let volume_linked_lists_view = volume_linked_lists.create_view(&Default::default());
         let volume_marker_view = volume_marker.create_view(&Default::default());
+        let volume_marker_previous_view = volume_marker_previous.create_view(&Default::default());
+        let volume_particle_resampling_view = volume_particle_resampling.create_view(&Default::default());
+        let volume_phase_count_view = volume_phase_count.create_view(&Default::default());
+        let volume_density_view = volume_density.create_view(&Default::default());
+        let volume_curl_view = volume_curl.create_view(&Default::default());
+        let volume_color_field_view = volume_color_field.create_view(&Default::default());
+        let volume_curvature_view = volume_curvature.create_view(&Default::default());
         let volume_debug_view = match volume_debug {
             Some(ref volume) => Some(volume.create_view(&Default::default())),
             None => None,
@@ -167,7 +473,7 @@ impl HybridFluid {
         // Layouts
         let group_layout_general = {
             let base_desc = BindGroupLayoutBuilder::new()
-                .next_binding_compute(binding_glsl::uniform())
+                .next_binding_compute(binding_glsl::uniform_dynamic())
                 .next_binding_compute(binding_glsl::texture3D());
             if volume_debug_view.is_some() {
                 base_desc.next_binding_compute(binding_glsl::image3D(
@@ -188,6 +494,15 @@ impl HybridFluid {
                 wgpu::TextureFormat::R32Float,
                 wgpu::StorageTextureAccess::ReadWrite,
             )) // velocity component
+            .next_binding_compute(binding_glsl::buffer(true)) // particles, phase
+            .next_binding_compute(binding_glsl::image3D(wgpu::TextureFormat::R32Uint, wgpu::StorageTextureAccess::ReadWrite)) // phase count volume
+            .next_binding_compute(binding_glsl::image3D(
+                wgpu::TextureFormat::R32Float,
+                wgpu::StorageTextureAccess::ReadWrite,
+            )) // density volume
+            .next_binding_compute(binding_glsl::texture3D()) // curl volume
+            .next_binding_compute(binding_glsl::texture3D()) // color field volume
+            .next_binding_compute(binding_glsl::texture3D()) // curvature volume
             .create(device, "BindGroupLayout: Transfer velocity from Particles to Volume(s)");
         let group_layout_divergence_compute = BindGroupLayoutBuilder::new()
             .next_binding_compute(binding_glsl::texture3D()) // marker volume
@@ -199,6 +514,27 @@ impl HybridFluid {
                 wgpu::StorageTextureAccess::ReadWrite,
             )) // divergence / initial residual
             .create(device, "BindGroupLayout: Compute Divergence");
+        let group_layout_vorticity_compute = BindGroupLayoutBuilder::new()
+            .next_binding_compute(binding_glsl::texture3D()) // marker volume
+            .next_binding_compute(binding_glsl::texture3D()) // velocityX
+            .next_binding_compute(binding_glsl::texture3D()) // velocityY
+            .next_binding_compute(binding_glsl::texture3D()) // velocityZ
+            .next_binding_compute(binding_glsl::image3D(
+                wgpu::TextureFormat::Rgba32Float,
+                wgpu::StorageTextureAccess::ReadWrite,
+            )) // curl volume
+            .create(device, "BindGroupLayout: Compute Vorticity");
+        let group_layout_surface_tension = BindGroupLayoutBuilder::new()
+            .next_binding_compute(binding_glsl::texture3D()) // marker volume
+            .next_binding_compute(binding_glsl::image3D(
+                wgpu::TextureFormat::R32Float,
+                wgpu::StorageTextureAccess::ReadWrite,
+            )) // color field
+            .next_binding_compute(binding_glsl::image3D(
+                wgpu::TextureFormat::R32Float,
+                wgpu::StorageTextureAccess::ReadWrite,
+            )) // curvature
+            .create(device, "BindGroupLayout: Surface Tension");
         let group_layout_write_velocity_volume = BindGroupLayoutBuilder::new()
             .next_binding_compute(binding_glsl::texture3D()) // marker volume
             .next_binding_compute(binding_glsl::image3D(
@@ -214,6 +550,7 @@ impl HybridFluid {
                 wgpu::StorageTextureAccess::ReadWrite,
             )) // velocityZ
             .next_binding_compute(binding_glsl::texture3D()) // pressure
+            .next_binding_compute(binding_glsl::texture3D()) // density volume
             .create(device, "BindGroupLayout: Write to Velocity");
         let group_layout_advect_particles = BindGroupLayoutBuilder::new()
             .next_binding_compute(binding_glsl::texture3D()) // velocityX
@@ -225,14 +562,38 @@ impl HybridFluid {
             .next_binding_compute(binding_glsl::buffer(false)) // particles, velocityX
             .next_binding_compute(binding_glsl::buffer(false)) // particles, velocityY
             .next_binding_compute(binding_glsl::buffer(false)) // particles, velocityZ
+            .next_binding_compute(binding_glsl::buffer(false)) // particles, age
+            .next_binding_compute(binding_glsl::buffer(false)) // particles, dye
             .create(device, "BindGroupLayout: Advect to Particles");
 
+        let group_layout_resample_pressure = BindGroupLayoutBuilder::new()
+            .next_binding_compute(binding_glsl::texture3D()) // source pressure
+            .next_binding_compute(binding_glsl::image3D(
+                wgpu::TextureFormat::R32Float,
+                wgpu::StorageTextureAccess::WriteOnly,
+            )) // destination pressure
+            .create(device, "BindGroupLayout: Resample Pressure");
+
         let group_layout_binning = BindGroupLayoutBuilder::new()
             .next_binding_compute(binding_glsl::buffer(true)) // particles, position llindex
             .next_binding_compute(binding_glsl::buffer(true)) // particles, position llindex
             .next_binding_compute(binding_glsl::image3D(wgpu::TextureFormat::R32Uint, wgpu::StorageTextureAccess::ReadWrite)) // volume_particle_binning
             .next_binding_compute(binding_glsl::buffer(false)) // ParticleBinningAtomicCounter
+            .next_binding_compute(binding_glsl::buffer(true)) // particles, phase
+            .next_binding_compute(binding_glsl::buffer(false)) // particles, phase
+            .next_binding_compute(binding_glsl::image3D(wgpu::TextureFormat::R32Uint, wgpu::StorageTextureAccess::ReadWrite)) // volume_particle_resampling
+            .next_binding_compute(binding_glsl::buffer(false)) // radix sort keys
+            .next_binding_compute(binding_glsl::buffer(false)) // radix sort values
+            .next_binding_compute(binding_glsl::buffer(true)) // particles, age
+            .next_binding_compute(binding_glsl::buffer(false)) // particles, age
+            .next_binding_compute(binding_glsl::buffer(true)) // particles, dye
+            .next_binding_compute(binding_glsl::buffer(false)) // particles, dye
             .create(device, "BindGroupLayout: Binning");
+        // Separate from binning since the particle count buffer is used for indirect dispatches of the binning passes.
+        let group_layout_particle_count_update = BindGroupLayoutBuilder::new()
+            .next_binding_compute(binding_glsl::buffer(true)) // ParticleBinningAtomicCounter
+            .next_binding_compute(binding_glsl::buffer(false)) // particle count buffer
+            .create(device, "BindGroupLayout: Particle count update");
         let group_layout_density_projection_gather_error = BindGroupLayoutBuilder::new()
             .next_binding_compute(binding_glsl::buffer(false)) // particles, position llindex
             .next_binding_compute(binding_glsl::utexture3D()) // linkedlist_volume
@@ -255,7 +616,14 @@ impl HybridFluid {
             error_check_frequency: 4,
             max_num_iterations: 32,
         };
-        let pressure_solver = PressureSolver::new(device, grid_dimension, shader_dir, pipeline_manager, &volume_marker_view);
+        let pressure_solver = PressureSolver::new(
+            device,
+            grid_dimension,
+            shader_dir,
+            pipeline_manager,
+            &volume_marker_view,
+            &volume_density_view,
+        );
         let pressure_field_from_velocity = PressureField::new("from velocity", device, grid_dimension, &pressure_solver, solver_config);
         let pressure_field_from_density = PressureField::new("from density", device, grid_dimension, &pressure_solver, solver_config);
 
@@ -278,6 +646,12 @@ impl HybridFluid {
                 .texture(&volume_linked_lists_view)
                 .texture(&volume_marker_view)
                 .texture(&volume_velocity_view_x)
+                .resource(particles_phase.as_entire_binding())
+                .texture(&volume_phase_count_view)
+                .texture(&volume_density_view)
+                .texture(&volume_curl_view)
+                .texture(&volume_color_field_view)
+                .texture(&volume_curvature_view)
                 .create(device, "BindGroup: Transfer velocity to volume X, p-buffer"),
             BindGroupBuilder::new(&group_layout_transfer_velocity)
                 .resource(particles_position_llindex.as_entire_binding())
@@ -285,6 +659,12 @@ impl HybridFluid {
                 .texture(&volume_linked_lists_view)
                 .texture(&volume_marker_view)
                 .texture(&volume_velocity_view_y)
+                .resource(particles_phase.as_entire_binding())
+                .texture(&volume_phase_count_view)
+                .texture(&volume_density_view)
+                .texture(&volume_curl_view)
+                .texture(&volume_color_field_view)
+                .texture(&volume_curvature_view)
                 .create(device, "BindGroup: Transfer velocity to volume Y, p-buffer"),
             BindGroupBuilder::new(&group_layout_transfer_velocity)
                 .resource(particles_position_llindex.as_entire_binding())
@@ -292,6 +672,12 @@ impl HybridFluid {
                 .texture(&volume_linked_lists_view)
                 .texture(&volume_marker_view)
                 .texture(&volume_velocity_view_z)
+                .resource(particles_phase.as_entire_binding())
+                .texture(&volume_phase_count_view)
+                .texture(&volume_density_view)
+                .texture(&volume_curl_view)
+                .texture(&volume_color_field_view)
+                .texture(&volume_curvature_view)
                 .create(device, "BindGroup: Transfer velocity to volume Z, p-buffer"),
         ];
         let bind_group_divergence_compute = BindGroupBuilder::new(&group_layout_divergence_compute)
@@ -301,12 +687,25 @@ impl HybridFluid {
             .texture(&volume_velocity_view_z)
             .texture(pressure_solver.residual_view())
             .create(device, "BindGroup: Compute divergence");
+        let bind_group_vorticity_compute = BindGroupBuilder::new(&group_layout_vorticity_compute)
+            .texture(&volume_marker_view)
+            .texture(&volume_velocity_view_x)
+            .texture(&volume_velocity_view_y)
+            .texture(&volume_velocity_view_z)
+            .texture(&volume_curl_view)
+            .create(device, "BindGroup: Compute vorticity");
+        let bind_group_surface_tension = BindGroupBuilder::new(&group_layout_surface_tension)
+            .texture(&volume_marker_view)
+            .texture(&volume_color_field_view)
+            .texture(&volume_curvature_view)
+            .create(device, "BindGroup: Surface tension");
         let bind_group_divergence_projection_write_velocity = BindGroupBuilder::new(&group_layout_write_velocity_volume)
             .texture(&volume_marker_view)
             .texture(&volume_velocity_view_x)
             .texture(&volume_velocity_view_y)
             .texture(&volume_velocity_view_z)
             .texture(pressure_field_from_velocity.pressure_view())
+            .texture(&volume_density_view)
             .create(device, "BindGroup: Write to Velocity Grid - divergence projection");
         let bind_group_density_projection_write_velocity = BindGroupBuilder::new(&group_layout_write_velocity_volume)
             .texture(&volume_marker_view)
@@ -314,6 +713,7 @@ impl HybridFluid {
             .texture(&volume_velocity_view_y)
             .texture(&volume_velocity_view_z)
             .texture(pressure_field_from_density.pressure_view())
+            .texture(&volume_density_view)
             .create(device, "BindGroup: Write to Velocity Grid - density projection");
         // todo: Very ugly duplication
         let bind_group_advect_particles = BindGroupBuilder::new(&group_layout_advect_particles)
@@ -326,6 +726,8 @@ impl HybridFluid {
             .resource(particles_velocity_x.as_entire_binding())
             .resource(particles_velocity_y.as_entire_binding())
             .resource(particles_velocity_z.as_entire_binding())
+            .resource(particles_age.as_entire_binding())
+            .resource(particles_dye.as_entire_binding())
             .create(device, "BindGroup: Write to Particles");
 
         let bind_group_binning = BindGroupBuilder::new(&group_layout_binning)
@@ -333,7 +735,20 @@ impl HybridFluid {
             .resource(particles_position_llindex_tmp.as_entire_binding())
             .texture(&volume_linked_lists_view) // reused for binning counters
             .resource(particle_binning_atomic_counter.as_entire_binding())
+            .resource(particles_phase.as_entire_binding())
+            .resource(particles_phase_tmp.as_entire_binding())
+            .texture(&volume_particle_resampling_view)
+            .resource(particle_radix_sort.keys().as_entire_binding())
+            .resource(particle_radix_sort.values().as_entire_binding())
+            .resource(particles_age.as_entire_binding())
+            .resource(particles_age_tmp.as_entire_binding())
+            .resource(particles_dye.as_entire_binding())
+            .resource(particles_dye_tmp.as_entire_binding())
             .create(device, "BindGroup: Binning");
+        let bind_group_particle_count_update = BindGroupBuilder::new(&group_layout_particle_count_update)
+            .resource(particle_binning_atomic_counter.as_entire_binding())
+            .resource(particle_count_buffer.as_entire_binding())
+            .create(device, "BindGroup: Particle count update");
 
         let bind_group_density_projection_gather_error = BindGroupBuilder::new(&group_layout_density_projection_gather_error)
             .resource(particles_position_llindex.as_entire_binding())
@@ -348,8 +763,9 @@ impl HybridFluid {
             .texture(&volume_velocity_view_y)
             .texture(&volume_velocity_view_z)
             .create(device, "BindGroup: Density projection correct particles 0");
+        let group_layout_renderer = Self::group_layout_renderer(device, pipeline_manager);
         let bind_group_renderer = {
-            let bind_group_renderer_builder = BindGroupBuilder::new(&Self::get_or_create_group_layout_renderer(device))
+            let bind_group_renderer_builder = BindGroupBuilder::new(&group_layout_renderer)
                 .resource(particles_position_llindex.as_entire_binding())
                 .resource(particles_velocity_x.as_entire_binding())
                 .resource(particles_velocity_y.as_entire_binding())
@@ -359,7 +775,13 @@ impl HybridFluid {
                 .texture(&volume_velocity_view_z)
                 .texture(&volume_marker_view)
                 .texture(&pressure_field_from_velocity.pressure_view())
-                .texture(&pressure_field_from_density.pressure_view());
+                .texture(&pressure_field_from_density.pressure_view())
+                .resource(particles_phase.as_entire_binding())
+                .texture(&volume_marker_previous_view)
+                .texture(voxelization.texture_view())
+                .texture(&volume_particle_resampling_view)
+                .resource(particles_age.as_entire_binding())
+                .resource(particles_dye.as_entire_binding());
             if let Some(volume_debug_view) = volume_debug_view.as_ref() {
                 bind_group_renderer_builder.texture(volume_debug_view)
             } else {
@@ -375,86 +797,191 @@ impl HybridFluid {
             range: 0..8,
         }];
 
-        let layout_transfer_velocity = Rc::new(device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("PipelineLayout: HybridFluid, Transfer Velocity"),
-            bind_group_layouts: &[
-                global_bind_group_layout,
-                &group_layout_general.layout,
-                &group_layout_transfer_velocity.layout,
-            ],
-            push_constant_ranges,
-        }));
-        let layout_divergence_compute = Rc::new(device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("PipelineLayout: HybridFluid, Compute Divergence"),
-            bind_group_layouts: &[
-                global_bind_group_layout,
-                &group_layout_general.layout,
-                &group_layout_divergence_compute.layout,
-            ],
-            push_constant_ranges,
-        }));
-        let layout_write_velocity_volume = Rc::new(device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("PipelineLayout: HybridFluid, Write Volume"),
-            bind_group_layouts: &[
-                global_bind_group_layout,
-                &group_layout_general.layout,
-                &group_layout_write_velocity_volume.layout,
-            ],
-            push_constant_ranges,
-        }));
-        let layout_particles = Rc::new(device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("PipelineLayout: HybridFluid, Particles"),
-            bind_group_layouts: &[
-                global_bind_group_layout,
-                &group_layout_general.layout,
-                &group_layout_advect_particles.layout,
-            ],
-            push_constant_ranges,
-        }));
-
-        let layout_binning = Rc::new(device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("PipelineLayout: Binning"),
-            bind_group_layouts: &[global_bind_group_layout, &group_layout_general.layout, &group_layout_binning.layout],
-            push_constant_ranges,
-        }));
-
-        let layout_density_projection_gather_error = Rc::new(device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("PipelineLayout: HybridFluid, Density Projection Gather"),
-            bind_group_layouts: &[
-                global_bind_group_layout,
-                &group_layout_general.layout,
-                &group_layout_density_projection_gather_error.layout,
-            ],
-            push_constant_ranges,
-        }));
-        let layout_density_projection_correct_particles = Rc::new(device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("PipelineLayout: HybridFluid, Density Projection Gather"),
-            bind_group_layouts: &[
-                global_bind_group_layout,
-                &group_layout_general.layout,
-                &group_layout_density_projection_correct_particles.layout,
-            ],
-            push_constant_ranges,
-        }));
+        let layout_transfer_velocity = pipeline_manager.create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("PipelineLayout: HybridFluid, Transfer Velocity"),
+                bind_group_layouts: &[
+                    global_bind_group_layout,
+                    &group_layout_general.layout,
+                    &group_layout_transfer_velocity.layout,
+                ],
+                push_constant_ranges,
+            },
+        );
+        let layout_divergence_compute = pipeline_manager.create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("PipelineLayout: HybridFluid, Compute Divergence"),
+                bind_group_layouts: &[
+                    global_bind_group_layout,
+                    &group_layout_general.layout,
+                    &group_layout_divergence_compute.layout,
+                ],
+                push_constant_ranges,
+            },
+        );
+        let layout_vorticity_compute = pipeline_manager.create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("PipelineLayout: HybridFluid, Compute Vorticity"),
+                bind_group_layouts: &[
+                    global_bind_group_layout,
+                    &group_layout_general.layout,
+                    &group_layout_vorticity_compute.layout,
+                ],
+                push_constant_ranges,
+            },
+        );
+        let layout_surface_tension = pipeline_manager.create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("PipelineLayout: HybridFluid, Surface Tension"),
+                bind_group_layouts: &[
+                    global_bind_group_layout,
+                    &group_layout_general.layout,
+                    &group_layout_surface_tension.layout,
+                ],
+                push_constant_ranges,
+            },
+        );
+        let layout_write_velocity_volume = pipeline_manager.create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("PipelineLayout: HybridFluid, Write Volume"),
+                bind_group_layouts: &[
+                    global_bind_group_layout,
+                    &group_layout_general.layout,
+                    &group_layout_write_velocity_volume.layout,
+                ],
+                push_constant_ranges,
+            },
+        );
+        let layout_particles = pipeline_manager.create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("PipelineLayout: HybridFluid, Particles"),
+                bind_group_layouts: &[
+                    global_bind_group_layout,
+                    &group_layout_general.layout,
+                    &group_layout_advect_particles.layout,
+                ],
+                push_constant_ranges,
+            },
+        );
+
+        let layout_resample_pressure = pipeline_manager.create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("PipelineLayout: HybridFluid, Resample Pressure"),
+                bind_group_layouts: &[
+                    global_bind_group_layout,
+                    &group_layout_general.layout,
+                    &group_layout_resample_pressure.layout,
+                ],
+                push_constant_ranges,
+            },
+        );
+
+        let layout_binning = pipeline_manager.create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("PipelineLayout: Binning"),
+                bind_group_layouts: &[global_bind_group_layout, &group_layout_general.layout, &group_layout_binning.layout],
+                push_constant_ranges,
+            },
+        );
+        let layout_particle_count_update = pipeline_manager.create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("PipelineLayout: Particle count update"),
+                bind_group_layouts: &[
+                    global_bind_group_layout,
+                    &group_layout_general.layout,
+                    &group_layout_particle_count_update.layout,
+                ],
+                push_constant_ranges,
+            },
+        );
+
+        let layout_density_projection_gather_error = pipeline_manager.create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("PipelineLayout: HybridFluid, Density Projection Gather"),
+                bind_group_layouts: &[
+                    global_bind_group_layout,
+                    &group_layout_general.layout,
+                    &group_layout_density_projection_gather_error.layout,
+                ],
+                push_constant_ranges,
+            },
+        );
+        let layout_density_projection_correct_particles = pipeline_manager.create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("PipelineLayout: HybridFluid, Density Projection Gather"),
+                bind_group_layouts: &[
+                    global_bind_group_layout,
+                    &group_layout_general.layout,
+                    &group_layout_density_projection_correct_particles.layout,
+                ],
+                push_constant_ranges,
+            },
+        );
 
         HybridFluid {
             grid_dimension,
+            workgroup_sizes,
 
             pressure_solver,
             pressure_field_from_velocity,
             pressure_field_from_density,
 
+            volume_velocity: [volume_velocity_x, volume_velocity_y, volume_velocity_z],
+            volume_density,
             volume_marker,
+            volume_marker_previous,
+            volume_particle_resampling,
             volume_linked_lists,
             volume_debug,
 
             particles_position_llindex,
             particles_position_llindex_tmp,
+            particles_velocity: [particles_velocity_x, particles_velocity_y, particles_velocity_z],
+            particles_phase,
+            particles_phase_tmp,
+            particles_age,
+            particles_age_tmp,
+            particles_dye,
+            particles_dye_tmp,
             particle_binning_atomic_counter,
+            particle_radix_sort,
+            particle_count_buffer,
+            particle_count_readback_buffer,
+            particle_count_readback_scheduled: false,
+            particle_count_readback: None,
             simulation_properties_uniformbuffer,
             simulation_properties: SimulationPropertiesUniformBufferContent {
                 num_particles: 0,
                 gravity_grid: cgmath::vec3(0.0, -9.81, 0.0),
+                interaction_ray_origin: cgmath::point3(0.0, 0.0, 0.0),
+                interaction_radius: 0.0,
+                interaction_ray_direction: cgmath::vec3(0.0, 0.0, 1.0),
+                num_force_fields: 0,
+                interaction_velocity_change: cgmath::vec3(0.0, 0.0, 0.0),
+                secondary_phase_relative_density: 1.0,
+                vorticity_confinement: 0.0,
+                surface_tension: 0.0,
+                max_num_particles,
+                particles_per_cell,
+                periodic_axes: 0,
+                max_particle_age: 0.0,
+                padding0: cgmath::vec2(0, 0),
+                erase_box_min: cgmath::point3(0.0, 0.0, 0.0),
+                padding1: 0.0,
+                erase_box_max: cgmath::point3(0.0, 0.0, 0.0),
+                padding2: 0.0,
+                force_fields: bytemuck::Zeroable::zeroed(),
             },
 
             bind_group_general,
@@ -463,11 +990,14 @@ impl HybridFluid {
             bind_group_divergence_projection_write_velocity,
             bind_group_advect_particles,
             bind_group_binning,
+            bind_group_particle_count_update,
             bind_group_renderer,
 
             bind_group_density_projection_gather_error,
             bind_group_density_projection_correct_particles,
             bind_group_density_projection_write_velocity,
+            bind_group_vorticity_compute,
+            bind_group_surface_tension,
 
             pipeline_transfer_clear: pipeline_manager.create_compute_pipeline(
                 device,
@@ -505,6 +1035,24 @@ impl HybridFluid {
                     Path::new("simulation/transfer_set_boundary_marker.comp"),
                 ),
             ),
+            pipeline_surface_tension_color_field: pipeline_manager.create_compute_pipeline(
+                device,
+                shader_dir,
+                ComputePipelineCreationDesc::new(
+                    "Fluid: Surface tension, color field",
+                    layout_surface_tension.clone(),
+                    Path::new("simulation/surface_tension_color_field.comp"),
+                ),
+            ),
+            pipeline_surface_tension_curvature: pipeline_manager.create_compute_pipeline(
+                device,
+                shader_dir,
+                ComputePipelineCreationDesc::new(
+                    "Fluid: Surface tension, curvature",
+                    layout_surface_tension.clone(),
+                    Path::new("simulation/surface_tension_curvature.comp"),
+                ),
+            ),
             pipeline_divergence_compute: pipeline_manager.create_compute_pipeline(
                 device,
                 shader_dir,
@@ -532,6 +1080,15 @@ impl HybridFluid {
                     Path::new("simulation/extrapolate_velocity.comp"),
                 ),
             ),
+            pipeline_vorticity_compute: pipeline_manager.create_compute_pipeline(
+                device,
+                shader_dir,
+                ComputePipelineCreationDesc::new(
+                    "Fluid: Compute vorticity",
+                    layout_vorticity_compute.clone(),
+                    Path::new("simulation/vorticity_compute.comp"),
+                ),
+            ),
             pipeline_advect_particles: pipeline_manager.create_compute_pipeline(
                 device,
                 shader_dir,
@@ -541,6 +1098,15 @@ impl HybridFluid {
                     Path::new("simulation/advect_particles.comp"),
                 ),
             ),
+            pipeline_apply_interaction_force: pipeline_manager.create_compute_pipeline(
+                device,
+                shader_dir,
+                ComputePipelineCreationDesc::new(
+                    "Fluid: Interaction force",
+                    layout_particles.clone(),
+                    Path::new("simulation/apply_interaction_force.comp"),
+                ),
+            ),
 
             pipeline_binning_count: pipeline_manager.create_compute_pipeline(
                 device,
@@ -569,6 +1135,42 @@ impl HybridFluid {
                     Path::new("simulation/particle_binning_rewrite_particles.comp"),
                 ),
             ),
+            pipeline_binning_sort_keys: pipeline_manager.create_compute_pipeline(
+                device,
+                shader_dir,
+                ComputePipelineCreationDesc::new(
+                    "Particle Binning: Sort keys",
+                    layout_binning.clone(),
+                    Path::new("simulation/particle_binning_sort_keys.comp"),
+                ),
+            ),
+            pipeline_binning_sort_gather: pipeline_manager.create_compute_pipeline(
+                device,
+                shader_dir,
+                ComputePipelineCreationDesc::new(
+                    "Particle Binning: Sort gather",
+                    layout_binning.clone(),
+                    Path::new("simulation/particle_binning_sort_gather.comp"),
+                ),
+            ),
+            pipeline_resample_plan: pipeline_manager.create_compute_pipeline(
+                device,
+                shader_dir,
+                ComputePipelineCreationDesc::new(
+                    "Particle Resampling: Plan",
+                    layout_binning.clone(),
+                    Path::new("simulation/particle_resample_plan.comp"),
+                ),
+            ),
+            pipeline_binning_update_count: pipeline_manager.create_compute_pipeline(
+                device,
+                shader_dir,
+                ComputePipelineCreationDesc::new(
+                    "Particle Binning: Update count",
+                    layout_particle_count_update.clone(),
+                    Path::new("simulation/particle_binning_update_count.comp"),
+                ),
+            ),
 
             pipeline_density_projection_gather_error: pipeline_manager.create_compute_pipeline(
                 device,
@@ -597,11 +1199,40 @@ impl HybridFluid {
                     Path::new("simulation/density_projection_correct_particles.comp"),
                 ),
             ),
+            pipeline_rescale_particles: pipeline_manager.create_compute_pipeline(
+                device,
+                shader_dir,
+                ComputePipelineCreationDesc::new(
+                    "Fluid: Rescale particles",
+                    layout_particles.clone(),
+                    Path::new("simulation/rescale_particles.comp"),
+                ),
+            ),
+            pipeline_resample_pressure: pipeline_manager.create_compute_pipeline(
+                device,
+                shader_dir,
+                ComputePipelineCreationDesc::new(
+                    "Fluid: Resample pressure",
+                    layout_resample_pressure.clone(),
+                    Path::new("simulation/resample_pressure.comp"),
+                ),
+            ),
+
+            group_layout_resample_pressure,
+
+            interaction_force: None,
+            erase_box_pending: false,
 
             max_num_particles,
+            particle_rng: rand::SeedableRng::seed_from_u64(0),
             step_counter: 0,
             dynamic_settings: DynamicSettings {
                 particle_rebinning_step_frequency: 60,
+                particle_binning_method: ParticleBinningMethod::CountingSort,
+                vorticity_confinement: 0.0,
+                particle_resampling: false,
+                advection_scheme: AdvectionScheme::CellRk4,
+                solid_push_out: true,
             },
         }
     }
@@ -617,13 +1248,57 @@ impl HybridFluid {
     }
 
     // Adds a cube of fluid. Coordinates are in grid space! Very slow operation!
-    pub fn add_fluid_cube(&mut self, queue: &wgpu::Queue, min_grid: cgmath::Point3<f32>, max_grid: cgmath::Point3<f32>) {
+    pub fn add_fluid_cube(
+        &mut self,
+        queue: &wgpu::Queue,
+        min_grid: cgmath::Point3<f32>,
+        max_grid: cgmath::Point3<f32>,
+        initial_velocity_grid: cgmath::Vector3<f32>,
+        phase: u32,
+    ) {
         // align to whole cells for simplicity.
         let min_grid = self.clamp_to_grid(min_grid);
         let max_grid = self.clamp_to_grid(max_grid);
-        let extent_cell = max_grid - min_grid;
 
-        let mut num_new_particles = (extent_cell.x * extent_cell.y * extent_cell.z * Self::PARTICLES_PER_GRID_CELL) as u32;
+        let mut cells = Vec::new();
+        for z in min_grid.z..max_grid.z {
+            for y in min_grid.y..max_grid.y {
+                for x in min_grid.x..max_grid.x {
+                    cells.push(cgmath::point3(x, y, z));
+                }
+            }
+        }
+        self.add_fluid_cells(queue, &cells, initial_velocity_grid, phase);
+    }
+
+    // Removes all particles within a box with the next simulation step. Coordinates are in grid space!
+    // Particles are only marked dead on the gpu, they are dropped from the particle buffers with the next particle binning.
+    pub fn remove_fluid_in_box(&mut self, min_grid: cgmath::Point3<f32>, max_grid: cgmath::Point3<f32>) {
+        if self.erase_box_pending {
+            warn!("Only one box of fluid can be removed per simulation step, ignoring the previous one");
+        }
+        self.simulation_properties.erase_box_min = min_grid;
+        self.simulation_properties.erase_box_max = max_grid;
+        self.erase_box_pending = true;
+    }
+
+    // Fills a list of grid cells with fluid of the given phase. Cells outside of the fillable domain are skipped. Very slow operation!
+    // New particles are appended after the cpu side particle count, which lags behind after particle resampling.
+    pub fn add_fluid_cells(&mut self, queue: &wgpu::Queue, cells: &[cgmath::Point3<u32>], initial_velocity_grid: cgmath::Vector3<f32>, phase: u32) {
+        // Same reserved border cells as in clamp_to_grid.
+        let max_cell = cgmath::point3(
+            self.grid_dimension.width - 1,
+            self.grid_dimension.height - 1,
+            self.grid_dimension.depth_or_array_layers - 1,
+        );
+        let cells: Vec<cgmath::Point3<u32>> = cells
+            .iter()
+            .filter(|c| c.x >= 1 && c.y >= 1 && c.z >= 1 && c.x < max_cell.x && c.y < max_cell.y && c.z < max_cell.z)
+            .cloned()
+            .collect();
+
+        let particles_per_cell = self.simulation_properties.particles_per_cell;
+        let mut num_new_particles = cells.len() as u32 * particles_per_cell;
         if self.max_num_particles < num_new_particles + self.simulation_properties.num_particles {
             error!(
                 "Can't add {} particles, max is {}, current is {}",
@@ -634,7 +1309,9 @@ impl HybridFluid {
         info!("Adding {} new particles", num_new_particles);
 
         // Fill buffer with particle data
-        let mut rng: rand::rngs::SmallRng = rand::SeedableRng::seed_from_u64((self.simulation_properties.num_particles + num_new_particles) as u64);
+        // Samples are spread over a regular grid of strata per cell, e.g. 2x2x2 for 8 particles.
+        let strata_per_axis = (particles_per_cell as f32).cbrt().ceil() as u32;
+        let num_strata = strata_per_axis * strata_per_axis * strata_per_axis;
         let mut new_particles = Vec::new();
         new_particles.resize(
             num_new_particles as usize,
@@ -644,25 +1321,22 @@ impl HybridFluid {
             },
         );
         for (i, particle) in new_particles.iter_mut().enumerate() {
-            let cell = cgmath::point3(
-                (min_grid.x + i as u32 / Self::PARTICLES_PER_GRID_CELL % extent_cell.x) as f32,
-                (min_grid.y + i as u32 / Self::PARTICLES_PER_GRID_CELL / extent_cell.x % extent_cell.y) as f32,
-                (min_grid.z + i as u32 / Self::PARTICLES_PER_GRID_CELL / extent_cell.x / extent_cell.y) as f32,
-            );
+            let cell = cells[i / particles_per_cell as usize].cast::<f32>().unwrap();
 
-            let sample_idx = i as u32 % Self::PARTICLES_PER_GRID_CELL;
+            let sample_idx = i as u32 % particles_per_cell;
+            let stratum = sample_idx * num_strata / particles_per_cell;
 
             // pure random
-            // let offset = rng.gen::<cgmath::Vector3<f32>>();
+            // let offset = self.particle_rng.gen::<cgmath::Vector3<f32>>();
+            let stratum_coordinate = cgmath::vec3(
+                (stratum % strata_per_axis) as f32,
+                (stratum / strata_per_axis % strata_per_axis) as f32,
+                (stratum / strata_per_axis / strata_per_axis) as f32,
+            );
             // pure regular
-            // let offset = cgmath::vec3(
-            //     (sample_idx % 2) as f32 + 0.5,
-            //     (sample_idx / 2 % 2) as f32 + 0.5,
-            //     (sample_idx / 4 % 2) as f32 + 0.5,
-            // ) * 0.5;
+            // let offset = (stratum_coordinate + cgmath::vec3(0.5, 0.5, 0.5)) / strata_per_axis as f32;
             // stratified
-            let offset = cgmath::vec3((sample_idx % 2) as f32, (sample_idx / 2 % 2) as f32, (sample_idx / 4 % 2) as f32) * 0.5
-                + rng.gen::<cgmath::Vector3<f32>>() * 0.5;
+            let offset = (stratum_coordinate + self.particle_rng.gen::<cgmath::Vector3<f32>>()) / strata_per_axis as f32;
 
             particle.position = cell + offset;
         }
@@ -674,7 +1348,187 @@ impl HybridFluid {
             bytemuck::cast_slice(&new_particles),
         );
 
+        // Velocity is stored in the w component of each velocity component buffer (xyz is the row of the APIC affine matrix).
+        for (component, buffer) in self.particles_velocity.iter().enumerate() {
+            let velocities = vec![[0.0, 0.0, 0.0, initial_velocity_grid[component]]; num_new_particles as usize];
+            queue.write_buffer(
+                buffer,
+                self.simulation_properties.num_particles as u64 * particle_size,
+                bytemuck::cast_slice(&velocities),
+            );
+        }
+
+        let phases = vec![phase.min(MAX_NUM_FLUID_PHASES as u32 - 1); num_new_particles as usize];
+        queue.write_buffer(
+            &self.particles_phase,
+            self.simulation_properties.num_particles as u64 * std::mem::size_of::<u32>() as u64,
+            bytemuck::cast_slice(&phases),
+        );
+        let ages = vec![0.0f32; num_new_particles as usize];
+        queue.write_buffer(
+            &self.particles_age,
+            self.simulation_properties.num_particles as u64 * std::mem::size_of::<f32>() as u64,
+            bytemuck::cast_slice(&ages),
+        );
+        let dyes = vec![0u32; num_new_particles as usize];
+        queue.write_buffer(
+            &self.particles_dye,
+            self.simulation_properties.num_particles as u64 * std::mem::size_of::<u32>() as u64,
+            bytemuck::cast_slice(&dyes),
+        );
+
         self.simulation_properties.num_particles += num_new_particles;
+        self.update_particle_count_buffer(queue);
+    }
+
+    fn particle_count_buffer_content(num_particles: u32, workgroup_size_particles: u32) -> ParticleCountBufferContent {
+        ParticleCountBufferContent {
+            draw_vertex_count: 4,
+            num_particles,
+            draw_first_vertex: 0,
+            draw_first_instance: 0,
+            dispatch_particles: [wgpu_utils::compute_group_size_1d(num_particles, workgroup_size_particles), 1, 1],
+            padding: 0,
+        }
+    }
+
+    // The particle count buffer is authoritative, the cpu side count may lag behind (see particle_count_readback).
+    fn copy_particle_count_to_uniform(&self, encoder: &mut wgpu::CommandEncoder) {
+        encoder.copy_buffer_to_buffer(
+            &self.particle_count_buffer,
+            Self::PARTICLE_COUNT_BUFFER_NUM_PARTICLES_OFFSET,
+            self.simulation_properties_uniformbuffer.buffer(),
+            self.simulation_properties_uniformbuffer.current_buffer_offset() + SimulationPropertiesUniformBufferContent::NUM_PARTICLES_OFFSET,
+            4,
+        );
+    }
+
+    fn update_particle_count_buffer(&self, queue: &wgpu::Queue) {
+        queue.write_buffer(
+            &self.particle_count_buffer,
+            0,
+            bytemuck::bytes_of(&Self::particle_count_buffer_content(
+                self.simulation_properties.num_particles,
+                self.workgroup_sizes.particles,
+            )),
+        );
+    }
+
+    // Takes over the state of a fluid with a different grid resolution but the same world space extent.
+    // Particles are copied and rescaled to the new grid space, pressure volumes (initial guess for the solver) are resampled.
+    // Gravity and surface tension need to be set separately (both depend on the grid scale).
+    pub fn take_over_resampled(
+        &mut self,
+        source: &HybridFluid,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pipeline_manager: &PipelineManager,
+        global_bind_group: &wgpu::BindGroup,
+    ) {
+        let grid_scale = self.grid_dimension.width as f32 / source.grid_dimension.width as f32;
+        // The source's cpu side particle count may lag behind if it resamples particles, so everything is copied on the gpu.
+        let max_num_particles = source.max_num_particles.min(self.max_num_particles);
+
+        self.simulation_properties.num_particles = source.simulation_properties.num_particles.min(self.max_num_particles);
+        self.simulation_properties.secondary_phase_relative_density = source.simulation_properties.secondary_phase_relative_density;
+        self.simulation_properties_uniformbuffer.update_content(queue, self.simulation_properties);
+        self.pressure_field_from_velocity.config = source.pressure_field_from_velocity.config;
+        self.pressure_field_from_density.config = source.pressure_field_from_density.config;
+        self.dynamic_settings.particle_rebinning_step_frequency = source.dynamic_settings.particle_rebinning_step_frequency;
+        self.dynamic_settings.particle_binning_method = source.dynamic_settings.particle_binning_method;
+        self.dynamic_settings.vorticity_confinement = source.dynamic_settings.vorticity_confinement;
+        self.dynamic_settings.particle_resampling = source.dynamic_settings.particle_resampling;
+        self.dynamic_settings.advection_scheme = source.dynamic_settings.advection_scheme;
+        self.dynamic_settings.solid_push_out = source.dynamic_settings.solid_push_out;
+        // Fluid added later on (events) continues the same random sequence.
+        self.particle_rng = source.particle_rng.clone();
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Encoder: Resample fluid"),
+        });
+
+        encoder.copy_buffer_to_buffer(
+            &source.particle_count_buffer,
+            0,
+            &self.particle_count_buffer,
+            0,
+            std::mem::size_of::<ParticleCountBufferContent>() as u64,
+        );
+        self.copy_particle_count_to_uniform(&mut encoder);
+
+        let particle_buffer_size = max_num_particles as u64 * std::mem::size_of::<ParticlePositionLl>() as u64;
+        encoder.copy_buffer_to_buffer(
+            &source.particles_position_llindex,
+            0,
+            &self.particles_position_llindex,
+            0,
+            particle_buffer_size,
+        );
+        for (source_buffer, buffer) in source.particles_velocity.iter().zip(self.particles_velocity.iter()) {
+            encoder.copy_buffer_to_buffer(source_buffer, 0, buffer, 0, particle_buffer_size);
+        }
+        encoder.copy_buffer_to_buffer(
+            &source.particles_phase,
+            0,
+            &self.particles_phase,
+            0,
+            max_num_particles as u64 * std::mem::size_of::<u32>() as u64,
+        );
+        encoder.copy_buffer_to_buffer(
+            &source.particles_age,
+            0,
+            &self.particles_age,
+            0,
+            max_num_particles as u64 * std::mem::size_of::<f32>() as u64,
+        );
+        encoder.copy_buffer_to_buffer(
+            &source.particles_dye,
+            0,
+            &self.particles_dye,
+            0,
+            max_num_particles as u64 * std::mem::size_of::<u32>() as u64,
+        );
+
+        let pressure_bind_groups: Vec<wgpu::BindGroup> = [
+            (&source.pressure_field_from_velocity, &self.pressure_field_from_velocity),
+            (&source.pressure_field_from_density, &self.pressure_field_from_density),
+        ]
+        .iter()
+        .map(|(source_field, field)| {
+            BindGroupBuilder::new(&self.group_layout_resample_pressure)
+                .texture(source_field.pressure_view())
+                .texture(field.pressure_view())
+                .create(device, "BindGroup: Resample Pressure")
+        })
+        .collect();
+
+        {
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("resample fluid"),
+            });
+            cpass.set_bind_group(0, global_bind_group, &[]);
+            cpass.set_bind_group(1, &self.bind_group_general, &[self.simulation_properties_uniformbuffer.dynamic_offset()]);
+
+            cpass.set_bind_group(2, &self.bind_group_advect_particles, &[]);
+            cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_rescale_particles));
+            pipeline_manager.set_push_constants_compute(&mut cpass, &self.pipeline_rescale_particles, 0, bytemuck::bytes_of(&[grid_scale]));
+            cpass.dispatch_indirect(&self.particle_count_buffer, Self::PARTICLE_COUNT_BUFFER_DISPATCH_OFFSET);
+
+            let grid_work_groups = wgpu_utils::compute_group_size(self.grid_dimension, self.workgroup_sizes.volume_extent());
+            cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_resample_pressure));
+            pipeline_manager.set_push_constants_compute(
+                &mut cpass,
+                &self.pipeline_resample_pressure,
+                0,
+                bytemuck::bytes_of(&[grid_scale, grid_scale * grid_scale]),
+            );
+            for bind_group in pressure_bind_groups.iter() {
+                cpass.set_bind_group(2, bind_group, &[]);
+                cpass.dispatch(grid_work_groups.width, grid_work_groups.height, grid_work_groups.depth_or_array_layers);
+            }
+        }
+        pipeline_manager.flush_push_constants(device, queue);
+        queue.submit(Some(encoder.finish()));
     }
 
     pub fn update_signed_distance_field_for_static(
@@ -693,37 +1547,256 @@ impl HybridFluid {
         self.simulation_properties.gravity_grid = gravity;
     }
 
+    // Surface tension coefficient divided by the density of the primary phase, in grid space (cells³/s²). Zero disables surface tension.
+    pub fn set_surface_tension_grid(&mut self, surface_tension: f32) {
+        self.simulation_properties.surface_tension = surface_tension;
+    }
+
+    // Restarts the random sequence particle positions within a cell are jittered with.
+    // Adding the same fluid in the same order after setting the same seed gives identical particles.
+    pub fn set_particle_seed(&mut self, seed: u64) {
+        self.particle_rng = rand::SeedableRng::seed_from_u64(seed);
+    }
+
+    // Particles older than this (in seconds) are removed during advection. Zero means they live forever.
+    pub fn set_max_particle_age(&mut self, max_particle_age: f32) {
+        self.simulation_properties.max_particle_age = max_particle_age.max(0.0);
+    }
+
+    // Bit per axis along which the domain wraps around, see FluidConfig::periodic
+    pub fn set_periodic_axes(&mut self, periodic_axes: u32) {
+        self.simulation_properties.periodic_axes = periodic_axes;
+        self.pressure_field_from_velocity.periodic_axes = periodic_axes;
+        self.pressure_field_from_density.periodic_axes = periodic_axes;
+    }
+
+    // Fields beyond MAX_NUM_FORCE_FIELDS are ignored.
+    pub fn set_force_fields(&mut self, force_fields: &[ForceFieldUniformBufferContent]) {
+        let num_force_fields = force_fields.len().min(MAX_NUM_FORCE_FIELDS);
+        self.simulation_properties.force_fields[..num_force_fields].copy_from_slice(&force_fields[..num_force_fields]);
+        self.simulation_properties.num_force_fields = num_force_fields as u32;
+    }
+
+    // Densities of all fluid phases. Only the ratio between them matters, so any unit can be used.
+    pub fn set_phase_densities(&mut self, densities: [f32; MAX_NUM_FLUID_PHASES]) {
+        self.simulation_properties.secondary_phase_relative_density = densities[1] / densities[0];
+    }
+
+    pub fn set_interaction_force(&mut self, interaction_force: Option<InteractionForce>) {
+        self.interaction_force = interaction_force;
+        if let Some(force) = interaction_force {
+            self.simulation_properties.interaction_ray_origin = force.ray_origin;
+            self.simulation_properties.interaction_ray_direction = force.ray_direction;
+            self.simulation_properties.interaction_radius = force.radius;
+            self.simulation_properties.interaction_velocity_change = force.acceleration;
+        }
+    }
+
     pub fn num_particles(&self) -> u32 {
         self.simulation_properties.num_particles
     }
 
-    pub fn get_or_create_group_layout_renderer(device: &wgpu::Device) -> &BindGroupLayoutWithDesc {
-        unsafe {
-            GROUP_LAYOUT_RENDERER.get_or_insert_with(|| {
-                let mut builder = BindGroupLayoutBuilder::new()
-                    .next_binding_vertex(binding_glsl::buffer(true)) // particles, position llindex
-                    .next_binding_vertex(binding_glsl::buffer(true)) // particles, velocityX
-                    .next_binding_vertex(binding_glsl::buffer(true)) // particles, velocityY
-                    .next_binding_vertex(binding_glsl::buffer(true)) // particles, velocityZ
-                    .next_binding_vertex(binding_glsl::texture3D()) // velocityX
-                    .next_binding_vertex(binding_glsl::texture3D()) // velocityY
-                    .next_binding_vertex(binding_glsl::texture3D()) // velocityZ
-                    .next_binding_vertex(binding_glsl::texture3D()) // marker
-                    .next_binding_vertex(binding_glsl::texture3D()) // pressure
-                    .next_binding_vertex(binding_glsl::texture3D()); // density
-                if cfg!(debug_assertions) {
-                    builder = builder.next_binding_vertex(binding_glsl::texture3D());
-                }
+    pub fn max_num_particles(&self) -> u32 {
+        self.max_num_particles
+    }
 
-                builder.create(device, "BindGroupLayout: ParticleRenderer")
-            })
+    pub fn particles_per_cell(&self) -> u32 {
+        self.simulation_properties.particles_per_cell
+    }
+
+    // Layout of bind_group_renderer, shared by all renderers that visualize the fluid.
+    pub fn group_layout_renderer(device: &wgpu::Device, pipeline_manager: &mut PipelineManager) -> Arc<BindGroupLayoutWithDesc> {
+        let mut builder = BindGroupLayoutBuilder::new()
+            .next_binding_vertex(binding_glsl::buffer(true)) // particles, position llindex
+            .next_binding_vertex(binding_glsl::buffer(true)) // particles, velocityX
+            .next_binding_vertex(binding_glsl::buffer(true)) // particles, velocityY
+            .next_binding_vertex(binding_glsl::buffer(true)) // particles, velocityZ
+            .next_binding_vertex(binding_glsl::texture3D()) // velocityX
+            .next_binding_vertex(binding_glsl::texture3D()) // velocityY
+            .next_binding_vertex(binding_glsl::texture3D()) // velocityZ
+            .next_binding_vertex(binding_glsl::texture3D()) // marker
+            .next_binding_vertex(binding_glsl::texture3D()) // pressure
+            .next_binding_vertex(binding_glsl::texture3D()) // density
+            .next_binding_vertex(binding_glsl::buffer(true)) // particles, phase
+            .next_binding_vertex(binding_glsl::texture3D()) // marker of previous step
+            .next_binding_vertex(binding_glsl::texture3D()) // scene voxelization
+            .next_binding_vertex(binding_glsl::utexture3D()) // particle counts from binning
+            .next_binding_vertex(binding_glsl::buffer(true)) // particles, age
+            .next_binding_vertex(binding_glsl::buffer(true)); // particles, dye
+        if cfg!(debug_assertions) {
+            builder = builder.next_binding_vertex(binding_glsl::texture3D());
         }
+
+        builder.create_cached(device, pipeline_manager.bind_group_layout_cache(), "BindGroupLayout: ParticleRenderer")
     }
 
     pub fn bind_group_renderer(&self) -> &wgpu::BindGroup {
         &self.bind_group_renderer
     }
 
+    // See ParticleCountBufferContent
+    pub fn particle_count_buffer(&self) -> &wgpu::Buffer {
+        &self.particle_count_buffer
+    }
+
+    pub const PARTICLE_COUNT_BUFFER_DRAW_OFFSET: u64 = 0;
+    pub const PARTICLE_COUNT_BUFFER_NUM_PARTICLES_OFFSET: u64 = 4;
+    pub const PARTICLE_COUNT_BUFFER_DISPATCH_OFFSET: u64 = 16;
+
+    pub fn particles_position_buffer(&self) -> &wgpu::Buffer {
+        &self.particles_position_llindex
+    }
+
+    // Reads back all particle positions (in grid space). Stalls until the gpu is done!
+    pub fn read_particle_positions(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Vec<cgmath::Point3<f32>> {
+        let size = self.simulation_properties.num_particles as u64 * std::mem::size_of::<ParticlePositionLl>() as u64;
+        if size == 0 {
+            return Vec::new();
+        }
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Buffer: Particle position readback"),
+            size,
+            usage: wgpu::BufferUsage::MAP_READ | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Encoder: Particle position readback"),
+        });
+        encoder.copy_buffer_to_buffer(&self.particles_position_llindex, 0, &readback_buffer, 0, size);
+        queue.submit(Some(encoder.finish()));
+
+        let slice = readback_buffer.slice(..);
+        let mapping = slice.map_async(wgpu::MapMode::Read);
+        device.poll(wgpu::Maintain::Wait);
+        if let Err(err) = futures::executor::block_on(mapping) {
+            error!("Failed to read back particle positions: {}", err);
+            return Vec::new();
+        }
+        let positions = bytemuck::cast_slice::<u8, ParticlePositionLl>(&slice.get_mapped_range())
+            .iter()
+            .map(|particle| particle.position)
+            .collect();
+        readback_buffer.unmap();
+        positions
+    }
+
+    // Reads back the grid state for offline analysis, values are cell centered and ordered x fastest, then y, then z.
+    // Staggered velocity components are averaged from the faces of each cell. Stalls until the gpu is done!
+    pub fn read_grid(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Option<GridReadback> {
+        let velocity_faces = [
+            Self::read_volume_f32(&self.volume_velocity[0], self.grid_dimension, device, queue)?,
+            Self::read_volume_f32(&self.volume_velocity[1], self.grid_dimension, device, queue)?,
+            Self::read_volume_f32(&self.volume_velocity[2], self.grid_dimension, device, queue)?,
+        ];
+        let pressure = Self::read_volume_f32(self.pressure_field_from_velocity.pressure_volume(), self.grid_dimension, device, queue)?;
+        let marker = Self::read_volume(&self.volume_marker, 1, self.grid_dimension, device, queue)?
+            .iter()
+            .map(|&marker| (marker as i8).max(-127) as f32 / 127.0)
+            .collect();
+
+        // Velocity of a staggered component is stored on the positive face of each cell, the negative face belongs to the previous cell.
+        // Faces on the domain boundary are solid walls.
+        let (width, height) = (self.grid_dimension.width as usize, self.grid_dimension.height as usize);
+        let strides = [1, width, width * height];
+        let coordinates = [width, height, self.grid_dimension.depth_or_array_layers as usize];
+        let velocity = (0..pressure.len())
+            .map(|index| {
+                let mut velocity = [0.0; 3];
+                for (component, faces) in velocity_faces.iter().enumerate() {
+                    let coordinate = index / strides[component] % coordinates[component];
+                    let negative_face = if coordinate == 0 { 0.0 } else { faces[index - strides[component]] };
+                    velocity[component] = (negative_face + faces[index]) * 0.5;
+                }
+                cgmath::Vector3::from(velocity)
+            })
+            .collect();
+
+        Some(GridReadback { velocity, pressure, marker })
+    }
+
+    fn read_volume_f32(texture: &wgpu::Texture, extent: wgpu::Extent3d, device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Vec<f32>> {
+        Self::read_volume(texture, 4, extent, device, queue).map(|data| bytemuck::cast_slice(&data).to_vec())
+    }
+
+    // Tightly packed content of a volume texture.
+    fn read_volume(
+        texture: &wgpu::Texture,
+        bytes_per_texel: u32,
+        extent: wgpu::Extent3d,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Option<Vec<u8>> {
+        let bytes_per_row = extent.width * bytes_per_texel;
+        let padded_bytes_per_row = round_to_multiple(bytes_per_row as usize, wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as usize) as u32;
+        let num_rows = extent.height * extent.depth_or_array_layers;
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Buffer: Volume readback"),
+            size: padded_bytes_per_row as u64 * num_rows as u64,
+            usage: wgpu::BufferUsage::MAP_READ | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Encoder: Volume readback"),
+        });
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &readback_buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(padded_bytes_per_row),
+                    rows_per_image: std::num::NonZeroU32::new(extent.height),
+                },
+            },
+            extent,
+        );
+        queue.submit(Some(encoder.finish()));
+
+        let slice = readback_buffer.slice(..);
+        let mapping = slice.map_async(wgpu::MapMode::Read);
+        device.poll(wgpu::Maintain::Wait);
+        if let Err(err) = futures::executor::block_on(mapping) {
+            error!("Failed to read back volume: {}", err);
+            return None;
+        }
+        let data = slice
+            .get_mapped_range()
+            .chunks_exact(padded_bytes_per_row as usize)
+            .flat_map(|row| row[..bytes_per_row as usize].iter().cloned())
+            .collect();
+        readback_buffer.unmap();
+        Some(data)
+    }
+
+    pub fn particles_position_buffer_size(&self) -> u64 {
+        self.max_num_particles as u64 * std::mem::size_of::<ParticlePositionLl>() as u64
+    }
+
+    // Volumes worth looking at cell by cell when debugging.
+    pub fn inspectable_volumes(&self) -> [(&'static str, &wgpu::Texture, wgpu::TextureFormat); 7] {
+        [
+            ("velocity x (staggered)", &self.volume_velocity[0], wgpu::TextureFormat::R32Float),
+            ("velocity y (staggered)", &self.volume_velocity[1], wgpu::TextureFormat::R32Float),
+            ("velocity z (staggered)", &self.volume_velocity[2], wgpu::TextureFormat::R32Float),
+            (
+                "pressure (velocity)",
+                self.pressure_field_from_velocity.pressure_volume(),
+                wgpu::TextureFormat::R32Float,
+            ),
+            (
+                "pressure (density)",
+                self.pressure_field_from_density.pressure_volume(),
+                wgpu::TextureFormat::R32Float,
+            ),
+            ("density", &self.volume_density, wgpu::TextureFormat::R32Float),
+            ("marker", &self.volume_marker, wgpu::TextureFormat::R8Snorm),
+        ]
+    }
+
     pub fn grid_dimension(&self) -> wgpu::Extent3d {
         self.grid_dimension
     }
@@ -732,13 +1805,14 @@ impl HybridFluid {
         self.simulation_properties.num_particles
     }
 
-    const COMPUTE_LOCAL_SIZE_FLUID: wgpu::Extent3d = wgpu::Extent3d {
+    const COMPUTE_LOCAL_SIZE_SCAN: u32 = 1024;
+    // Cells covered by a single workgroup of the gather passes (transfer_gather_velocity & density_projection_gather_error).
+    // Those have a fixed size of 9x9x9 threads with one cell of overlap between groups, i.e. they don't follow the autotuned volume size.
+    const GATHER_WORKGROUP_CELLS: wgpu::Extent3d = wgpu::Extent3d {
         width: 8,
         height: 8,
         depth_or_array_layers: 8,
     };
-    const COMPUTE_LOCAL_SIZE_PARTICLES: u32 = 64;
-    const COMPUTE_LOCAL_SIZE_SCAN: u32 = 1024;
 
     pub fn pressure_solver_config_velocity(&mut self) -> &mut SolverConfig {
         &mut self.pressure_field_from_velocity.config
@@ -760,15 +1834,33 @@ impl HybridFluid {
         &self.pressure_field_from_density.stats
     }
 
-    // Necessary to call this to update solver statistics and config.
+    // Necessary to call this to update solver statistics, config and the cpu side particle count.
     // Do not call while building command buffer!
     pub fn update_statistics(&mut self) {
         self.pressure_field_from_density.start_error_buffer_readbacks();
         self.pressure_field_from_velocity.start_error_buffer_readbacks();
+
+        if let Some(readback) = self.particle_count_readback.as_mut() {
+            if let Some(result) = readback.now_or_never() {
+                self.particle_count_readback = None;
+                match result {
+                    Ok(()) => {
+                        let mapped = self.particle_count_readback_buffer.slice(..);
+                        self.simulation_properties.num_particles = *bytemuck::from_bytes::<u32>(&mapped.get_mapped_range());
+                    }
+                    Err(err) => error!("Failed to read back particle count: {}", err),
+                }
+                self.particle_count_readback_buffer.unmap();
+            }
+        } else if self.particle_count_readback_scheduled {
+            self.particle_count_readback_scheduled = false;
+            self.particle_count_readback = Some(self.particle_count_readback_buffer.slice(..).map_async(wgpu::MapMode::Read).boxed());
+        }
     }
 
-    pub fn step(
+    pub fn step_stage(
         &mut self,
+        stage: SimulationStage,
         simulation_delta: Duration,
         encoder: &mut wgpu::CommandEncoder,
         device: &wgpu::Device,
@@ -776,121 +1868,424 @@ impl HybridFluid {
         global_bind_group: &wgpu::BindGroup,
         pipeline_manager: &PipelineManager,
         profiler: &mut GpuProfiler,
+        profiling_detail: ProfilingDetail,
     ) {
-        wgpu_profiler!("update uniforms", profiler, encoder, device, {
-            self.pressure_field_from_density.update_uniforms(queue, simulation_delta);
-            self.pressure_field_from_velocity.update_uniforms(queue, simulation_delta);
-            self.simulation_properties_uniformbuffer.update_content(queue, self.simulation_properties);
-        });
+        match stage {
+            SimulationStage::TransferToGrid => {
+                profiling_scope!(Coarse, profiling_detail, "update uniforms", profiler, encoder, device, {
+                    self.simulation_properties.vorticity_confinement = self.dynamic_settings.vorticity_confinement;
+                    self.pressure_field_from_density.update_uniforms(queue, simulation_delta);
+                    self.pressure_field_from_velocity.update_uniforms(queue, simulation_delta);
+                    self.simulation_properties_uniformbuffer.update_content(queue, self.simulation_properties);
+                    self.copy_particle_count_to_uniform(encoder);
+                });
 
-        let grid_work_groups = wgpu_utils::compute_group_size(self.grid_dimension, Self::COMPUTE_LOCAL_SIZE_FLUID);
-        let particle_work_groups = wgpu_utils::compute_group_size_1d(self.simulation_properties.num_particles, Self::COMPUTE_LOCAL_SIZE_PARTICLES);
-        let scan_work_groups = wgpu_utils::compute_group_size_1d(
-            self.grid_dimension.width * self.grid_dimension.height * self.grid_dimension.depth_or_array_layers,
-            Self::COMPUTE_LOCAL_SIZE_SCAN,
-        );
+                if self.interaction_force.is_some() {
+                    let erase_mode = if self.interaction_force.map_or(false, |force| force.erase) {
+                        EraseMode::InteractionRay
+                    } else {
+                        EraseMode::None
+                    };
+                    self.apply_interaction_force(
+                        encoder,
+                        device,
+                        global_bind_group,
+                        pipeline_manager,
+                        profiler,
+                        profiling_detail,
+                        erase_mode,
+                    );
+                }
+                if self.erase_box_pending {
+                    self.apply_interaction_force(
+                        encoder,
+                        device,
+                        global_bind_group,
+                        pipeline_manager,
+                        profiler,
+                        profiling_detail,
+                        EraseMode::EraseBox,
+                    );
+                    self.erase_box_pending = false;
+                }
 
-        encoder.clear_buffer(&self.particle_binning_atomic_counter, 0, None);
-        if let Some(ref volume_debug) = self.volume_debug {
-            encoder.clear_texture(&volume_debug, &Default::default());
+                encoder.clear_buffer(&self.particle_binning_atomic_counter, 0, None);
+                if let Some(ref volume_debug) = self.volume_debug {
+                    encoder.clear_texture(&volume_debug, &Default::default());
+                }
+                // Marker is a byte per cell, cheap enough to keep a copy around all the time.
+                encoder.copy_texture_to_texture(
+                    wgpu::ImageCopyTexture {
+                        texture: &self.volume_marker,
+                        mip_level: 0,
+                        origin: wgpu::Origin3d::ZERO,
+                    },
+                    wgpu::ImageCopyTexture {
+                        texture: &self.volume_marker_previous,
+                        mip_level: 0,
+                        origin: wgpu::Origin3d::ZERO,
+                    },
+                    self.grid_dimension,
+                );
+
+                self.transfer_to_grid(encoder, device, global_bind_group, pipeline_manager, profiler, profiling_detail);
+            }
+            SimulationStage::SolvePressure => {
+                profiling_scope!(
+                    Coarse,
+                    profiling_detail,
+                    "primary pressure solver (divergence)",
+                    profiler,
+                    encoder,
+                    device,
+                    {
+                        self.pressure_solver.solve(
+                            simulation_delta,
+                            encoder,
+                            device,
+                            &mut self.pressure_field_from_velocity,
+                            pipeline_manager,
+                            profiler,
+                            profiling_detail,
+                        );
+                    }
+                );
+            }
+            SimulationStage::Advect => {
+                let binned = self.advect(encoder, device, global_bind_group, pipeline_manager, profiler, profiling_detail);
+                // The buffer may still be mapped or about to be mapped, in that case the next binning will schedule another readback.
+                if binned && self.particle_count_readback.is_none() && !self.particle_count_readback_scheduled {
+                    encoder.copy_buffer_to_buffer(
+                        &self.particle_count_buffer,
+                        Self::PARTICLE_COUNT_BUFFER_NUM_PARTICLES_OFFSET,
+                        &self.particle_count_readback_buffer,
+                        0,
+                        4,
+                    );
+                    self.particle_count_readback_scheduled = true;
+                }
+            }
+            SimulationStage::CorrectDensityError => {
+                profiling_scope!(
+                    Coarse,
+                    profiling_detail,
+                    "secondary pressure solver (density)",
+                    profiler,
+                    encoder,
+                    device,
+                    {
+                        self.pressure_solver.solve(
+                            simulation_delta,
+                            encoder,
+                            device,
+                            &mut self.pressure_field_from_density,
+                            pipeline_manager,
+                            profiler,
+                            profiling_detail,
+                        );
+                    }
+                );
+                self.correct_density_error(encoder, device, global_bind_group, pipeline_manager, profiler, profiling_detail);
+                self.step_counter += 1;
+            }
         }
+    }
 
-        wgpu_profiler!("transfer & divergence compute", profiler, encoder, device, {
+    fn apply_interaction_force(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        device: &wgpu::Device,
+        global_bind_group: &wgpu::BindGroup,
+        pipeline_manager: &PipelineManager,
+        profiler: &mut GpuProfiler,
+        profiling_detail: ProfilingDetail,
+        erase_mode: EraseMode,
+    ) {
+        profiling_scope!(Coarse, profiling_detail, "interaction force", profiler, encoder, device, {
             let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-                label: Some("transfer & divergence compute"),
+                label: Some("interaction force"),
             });
             cpass.set_bind_group(0, global_bind_group, &[]);
-            cpass.set_bind_group(1, &self.bind_group_general, &[]);
-
-            wgpu_profiler!("transfer particle velocity to grid", profiler, &mut cpass, device, {
-                for i in 0..3 {
-                    wgpu_profiler!(&format!("dimension {}", ["x", "y", "z"][i]), profiler, &mut cpass, device, {
-                        cpass.set_bind_group(2, &self.bind_group_transfer_velocity[i], &[]);
-                        let scope_label = &format!("clear linked list grid{}", if i == 0 { " & marker" } else { "" });
-                        wgpu_profiler!(scope_label, profiler, &mut cpass, device, {
-                            cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_transfer_clear));
-                            cpass.set_push_constants(0, bytemuck::bytes_of(&[i as u32]));
-                            cpass.dispatch(grid_work_groups.width, grid_work_groups.height, grid_work_groups.depth_or_array_layers);
-                        });
+            cpass.set_bind_group(1, &self.bind_group_general, &[self.simulation_properties_uniformbuffer.dynamic_offset()]);
+            cpass.set_bind_group(2, &self.bind_group_advect_particles, &[]);
+            cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_apply_interaction_force));
+            // Packed rgba8, zero alpha means no dye.
+            let dye = match erase_mode {
+                EraseMode::EraseBox => 0,
+                _ => self
+                    .interaction_force
+                    .and_then(|force| force.dye)
+                    .map_or(0, |[r, g, b]| r as u32 | (g as u32) << 8 | (b as u32) << 16 | 0xFF << 24),
+            };
+            pipeline_manager.set_push_constants_compute(
+                &mut cpass,
+                &self.pipeline_apply_interaction_force,
+                0,
+                bytemuck::bytes_of(&[erase_mode as u32, dye]),
+            );
+            cpass.dispatch_indirect(&self.particle_count_buffer, Self::PARTICLE_COUNT_BUFFER_DISPATCH_OFFSET);
+        });
+    }
 
-                        wgpu_profiler!("create particle linked lists", profiler, &mut cpass, device, {
-                            cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_transfer_build_linkedlist));
-                            cpass.dispatch(particle_work_groups, 1, 1);
-                        });
+    fn transfer_to_grid(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        device: &wgpu::Device,
+        global_bind_group: &wgpu::BindGroup,
+        pipeline_manager: &PipelineManager,
+        profiler: &mut GpuProfiler,
+        profiling_detail: ProfilingDetail,
+    ) {
+        let grid_work_groups = wgpu_utils::compute_group_size(self.grid_dimension, self.workgroup_sizes.volume_extent());
+        let gather_work_groups = wgpu_utils::compute_group_size(self.grid_dimension, Self::GATHER_WORKGROUP_CELLS);
 
-                        if i == 0 {
-                            wgpu_profiler!("set boundary marker", profiler, &mut cpass, device, {
-                                cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_transfer_set_boundary_marker));
-                                cpass.dispatch(grid_work_groups.width, grid_work_groups.height, grid_work_groups.depth_or_array_layers);
-                            });
-                        }
+        profiling_scope!(Coarse, profiling_detail, "transfer & divergence compute", profiler, encoder, device, {
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("transfer & divergence compute"),
+            });
+            cpass.set_bind_group(0, global_bind_group, &[]);
+            cpass.set_bind_group(1, &self.bind_group_general, &[self.simulation_properties_uniformbuffer.dynamic_offset()]);
 
-                        wgpu_profiler!("gather velocity & apply global forces", profiler, &mut cpass, device, {
-                            cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_transfer_gather_velocity));
-                            cpass.dispatch(grid_work_groups.width, grid_work_groups.height, grid_work_groups.depth_or_array_layers);
-                        });
-                    });
+            profiling_scope!(
+                Fine,
+                profiling_detail,
+                "transfer particle velocity to grid",
+                profiler,
+                &mut cpass,
+                device,
+                {
+                    for i in 0..3 {
+                        profiling_scope!(
+                            Fine,
+                            profiling_detail,
+                            &format!("dimension {}", ["x", "y", "z"][i]),
+                            profiler,
+                            &mut cpass,
+                            device,
+                            {
+                                cpass.set_bind_group(2, &self.bind_group_transfer_velocity[i], &[]);
+                                let scope_label = &format!("clear linked list grid{}", if i == 0 { " & marker" } else { "" });
+                                profiling_scope!(Fine, profiling_detail, scope_label, profiler, &mut cpass, device, {
+                                    cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_transfer_clear));
+                                    pipeline_manager.set_push_constants_compute(
+                                        &mut cpass,
+                                        &self.pipeline_transfer_clear,
+                                        0,
+                                        bytemuck::bytes_of(&[i as u32]),
+                                    );
+                                    cpass.dispatch(grid_work_groups.width, grid_work_groups.height, grid_work_groups.depth_or_array_layers);
+                                });
+
+                                profiling_scope!(Fine, profiling_detail, "create particle linked lists", profiler, &mut cpass, device, {
+                                    cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_transfer_build_linkedlist));
+                                    cpass.dispatch_indirect(&self.particle_count_buffer, Self::PARTICLE_COUNT_BUFFER_DISPATCH_OFFSET);
+                                });
+
+                                if i == 0 {
+                                    profiling_scope!(Fine, profiling_detail, "set boundary marker", profiler, &mut cpass, device, {
+                                        cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_transfer_set_boundary_marker));
+                                        cpass.dispatch(grid_work_groups.width, grid_work_groups.height, grid_work_groups.depth_or_array_layers);
+                                    });
+                                    // Needs the final marker grid, used by the gather of all components.
+                                    if self.simulation_properties.surface_tension > 0.0 {
+                                        profiling_scope!(Fine, profiling_detail, "surface tension curvature", profiler, &mut cpass, device, {
+                                            cpass.set_bind_group(2, &self.bind_group_surface_tension, &[]);
+                                            cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_surface_tension_color_field));
+                                            cpass.dispatch(grid_work_groups.width, grid_work_groups.height, grid_work_groups.depth_or_array_layers);
+                                            cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_surface_tension_curvature));
+                                            cpass.dispatch(grid_work_groups.width, grid_work_groups.height, grid_work_groups.depth_or_array_layers);
+                                            cpass.set_bind_group(2, &self.bind_group_transfer_velocity[i], &[]);
+                                        });
+                                    }
+                                }
+
+                                profiling_scope!(
+                                    Fine,
+                                    profiling_detail,
+                                    "gather velocity & apply global forces",
+                                    profiler,
+                                    &mut cpass,
+                                    device,
+                                    {
+                                        cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_transfer_gather_velocity));
+                                        cpass.dispatch(
+                                            gather_work_groups.width,
+                                            gather_work_groups.height,
+                                            gather_work_groups.depth_or_array_layers,
+                                        );
+                                    }
+                                );
+                            }
+                        );
+                    }
                 }
-            });
+            );
 
-            wgpu_profiler!("compute divergence", profiler, &mut cpass, device, {
+            profiling_scope!(Fine, profiling_detail, "compute divergence", profiler, &mut cpass, device, {
                 cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_divergence_compute));
                 cpass.set_bind_group(2, &self.bind_group_divergence_compute, &[]); // Writes directly into Residual of the pressure solver.
                 cpass.dispatch(grid_work_groups.width, grid_work_groups.height, grid_work_groups.depth_or_array_layers);
             });
         });
+    }
 
-        wgpu_profiler!("primary pressure solver (divergence)", profiler, encoder, device, {
-            self.pressure_solver.solve(
-                simulation_delta,
-                encoder,
-                device,
-                &mut self.pressure_field_from_velocity,
-                pipeline_manager,
-                profiler,
+    // Sorts particles by cell into the temporary particle buffers, alternative to the counting sort in advect.
+    fn bin_particles_radix_sort(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        device: &wgpu::Device,
+        global_bind_group: &wgpu::BindGroup,
+        pipeline_manager: &PipelineManager,
+        profiler: &mut GpuProfiler,
+        profiling_detail: ProfilingDetail,
+    ) {
+        let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Particle Binning (radix sort)"),
+        });
+        cpass.set_bind_group(0, global_bind_group, &[]);
+        cpass.set_bind_group(1, &self.bind_group_general, &[self.simulation_properties_uniformbuffer.dynamic_offset()]);
+        cpass.set_bind_group(2, &self.bind_group_binning, &[]);
+        profiling_scope!(Fine, profiling_detail, "compute keys", profiler, &mut cpass, device, {
+            cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_binning_sort_keys));
+            pipeline_manager.bind_push_constants_compute(&mut cpass, &self.pipeline_binning_sort_keys);
+            cpass.dispatch(
+                wgpu_utils::compute_group_size_1d(self.particle_radix_sort.num_padded_keys(), self.workgroup_sizes.particles),
+                1,
+                1,
             );
         });
+        profiling_scope!(Fine, profiling_detail, "sort", profiler, &mut cpass, device, {
+            self.particle_radix_sort.sort(&mut cpass, pipeline_manager);
+        });
+        // The radix sort uses its own bind group layout.
+        cpass.set_bind_group(0, global_bind_group, &[]);
+        cpass.set_bind_group(1, &self.bind_group_general, &[self.simulation_properties_uniformbuffer.dynamic_offset()]);
+        cpass.set_bind_group(2, &self.bind_group_binning, &[]);
+        profiling_scope!(Fine, profiling_detail, "gather particles", profiler, &mut cpass, device, {
+            cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_binning_sort_gather));
+            pipeline_manager.bind_push_constants_compute(&mut cpass, &self.pipeline_binning_sort_gather);
+            cpass.dispatch_indirect(&self.particle_count_buffer, Self::PARTICLE_COUNT_BUFFER_DISPATCH_OFFSET);
+        });
+        profiling_scope!(Fine, profiling_detail, "update particle count", profiler, &mut cpass, device, {
+            cpass.set_bind_group(2, &self.bind_group_particle_count_update, &[]);
+            cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_binning_update_count));
+            cpass.dispatch(1, 1, 1);
+        });
+    }
 
-        if self.dynamic_settings.particle_rebinning_step_frequency != 0
-            && self.step_counter % self.dynamic_settings.particle_rebinning_step_frequency == 0
-        {
-            wgpu_profiler!("Particle Binning", profiler, encoder, device, {
-                wgpu_profiler!("Clear counters", profiler, encoder, device, {
-                    encoder.clear_texture(&self.volume_linked_lists, &Default::default());
-                });
+    // Returns true if particles were binned, i.e. the particle count may have changed due to resampling or removal of dead particles.
+    fn advect(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        device: &wgpu::Device,
+        global_bind_group: &wgpu::BindGroup,
+        pipeline_manager: &PipelineManager,
+        profiler: &mut GpuProfiler,
+        profiling_detail: ProfilingDetail,
+    ) -> bool {
+        let grid_work_groups = wgpu_utils::compute_group_size(self.grid_dimension, self.workgroup_sizes.volume_extent());
+        let scan_work_groups = wgpu_utils::compute_group_size_1d(
+            self.grid_dimension.width * self.grid_dimension.height * self.grid_dimension.depth_or_array_layers,
+            Self::COMPUTE_LOCAL_SIZE_SCAN,
+        );
 
+        let rebinning = self.dynamic_settings.particle_rebinning_step_frequency != 0
+            && self.step_counter % self.dynamic_settings.particle_rebinning_step_frequency == 0;
+        let resampling = rebinning && self.dynamic_settings.particle_resampling;
+        if rebinning {
+            // Resampling needs the per cell particle counts of the counting sort.
+            let binning_method = if resampling {
+                ParticleBinningMethod::CountingSort
+            } else {
+                self.dynamic_settings.particle_binning_method
+            };
+            profiling_scope!(
+                Coarse,
+                profiling_detail,
+                binning_method.profiling_scope_label(),
+                profiler,
+                encoder,
+                device,
                 {
-                    let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-                        label: Some("Particle Binning"),
-                    });
-                    cpass.set_bind_group(0, global_bind_group, &[]);
-                    cpass.set_bind_group(1, &self.bind_group_general, &[]);
-                    cpass.set_bind_group(2, &self.bind_group_binning, &[]);
-                    wgpu_profiler!("count", profiler, &mut cpass, device, {
-                        cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_binning_count));
-                        cpass.dispatch(particle_work_groups, 1, 1);
-                    });
-                    wgpu_profiler!("scan", profiler, &mut cpass, device, {
-                        cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_binning_scan));
-                        cpass.dispatch(scan_work_groups, 1, 1);
-                    });
-                    wgpu_profiler!("rewrite particles", profiler, &mut cpass, device, {
-                        cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_binning_rewrite_particles));
-                        cpass.dispatch(particle_work_groups, 1, 1);
+                    if binning_method == ParticleBinningMethod::RadixSort {
+                        self.bin_particles_radix_sort(encoder, device, global_bind_group, pipeline_manager, profiler, profiling_detail);
+                    } else {
+                        profiling_scope!(Fine, profiling_detail, "Clear counters", profiler, encoder, device, {
+                            encoder.clear_texture(&self.volume_linked_lists, &Default::default());
+                        });
+
+                        {
+                            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                                label: Some("Particle Binning"),
+                            });
+                            cpass.set_bind_group(0, global_bind_group, &[]);
+                            cpass.set_bind_group(1, &self.bind_group_general, &[self.simulation_properties_uniformbuffer.dynamic_offset()]);
+                            cpass.set_bind_group(2, &self.bind_group_binning, &[]);
+                            profiling_scope!(Fine, profiling_detail, "count", profiler, &mut cpass, device, {
+                                cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_binning_count));
+                                pipeline_manager.set_push_constants_compute(
+                                    &mut cpass,
+                                    &self.pipeline_binning_count,
+                                    0,
+                                    bytemuck::bytes_of(&[resampling as u32]),
+                                );
+                                cpass.dispatch_indirect(&self.particle_count_buffer, Self::PARTICLE_COUNT_BUFFER_DISPATCH_OFFSET);
+                            });
+                            // Without resampling this only records the per cell particle counts (for visualization).
+                            profiling_scope!(Fine, profiling_detail, "plan resampling", profiler, &mut cpass, device, {
+                                cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_resample_plan));
+                                cpass.dispatch(grid_work_groups.width, grid_work_groups.height, grid_work_groups.depth_or_array_layers);
+                            });
+                            profiling_scope!(Fine, profiling_detail, "scan", profiler, &mut cpass, device, {
+                                cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_binning_scan));
+                                cpass.dispatch(scan_work_groups, 1, 1);
+                            });
+                            profiling_scope!(Fine, profiling_detail, "rewrite particles", profiler, &mut cpass, device, {
+                                cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_binning_rewrite_particles));
+                                cpass.dispatch_indirect(&self.particle_count_buffer, Self::PARTICLE_COUNT_BUFFER_DISPATCH_OFFSET);
+                            });
+                            profiling_scope!(Fine, profiling_detail, "update particle count", profiler, &mut cpass, device, {
+                                cpass.set_bind_group(2, &self.bind_group_particle_count_update, &[]);
+                                cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_binning_update_count));
+                                cpass.dispatch(1, 1, 1);
+                            });
+                        }
+                    }
+
+                    // Copy binned particles back to avoid having all descriptors twice
+                    profiling_scope!(Fine, profiling_detail, "Copy binned particles", profiler, encoder, device, {
+                        encoder.copy_buffer_to_buffer(
+                            &self.particles_position_llindex_tmp,
+                            0,
+                            &self.particles_position_llindex,
+                            0,
+                            self.max_num_particles as u64 * std::mem::size_of::<ParticlePositionLl>() as u64,
+                        );
+                        encoder.copy_buffer_to_buffer(
+                            &self.particles_phase_tmp,
+                            0,
+                            &self.particles_phase,
+                            0,
+                            self.max_num_particles as u64 * std::mem::size_of::<u32>() as u64,
+                        );
+                        encoder.copy_buffer_to_buffer(
+                            &self.particles_age_tmp,
+                            0,
+                            &self.particles_age,
+                            0,
+                            self.max_num_particles as u64 * std::mem::size_of::<f32>() as u64,
+                        );
+                        encoder.copy_buffer_to_buffer(
+                            &self.particles_dye_tmp,
+                            0,
+                            &self.particles_dye,
+                            0,
+                            self.max_num_particles as u64 * std::mem::size_of::<u32>() as u64,
+                        );
+                        self.copy_particle_count_to_uniform(encoder);
                     });
                 }
-
-                // Copy binned particles back to avoid having all descriptors twice
-                wgpu_profiler!("Copy binned particles", profiler, encoder, device, {
-                    encoder.copy_buffer_to_buffer(
-                        &self.particles_position_llindex_tmp,
-                        0,
-                        &self.particles_position_llindex,
-                        0,
-                        self.max_num_particles as u64 * std::mem::size_of::<ParticlePositionLl>() as u64,
-                    );
-                });
-            });
+            );
         }
 
         {
@@ -898,81 +2293,152 @@ impl HybridFluid {
                 label: Some("correct for divergence / advect, compute density"),
             });
             cpass.set_bind_group(0, global_bind_group, &[]);
-            cpass.set_bind_group(1, &self.bind_group_general, &[]);
+            cpass.set_bind_group(1, &self.bind_group_general, &[self.simulation_properties_uniformbuffer.dynamic_offset()]);
 
             {
                 cpass.set_bind_group(2, &self.bind_group_divergence_projection_write_velocity, &[]);
 
-                wgpu_profiler!("make velocity grid divergence free", profiler, &mut cpass, device, {
-                    cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_divergence_remove));
+                profiling_scope!(
+                    Coarse,
+                    profiling_detail,
+                    "make velocity grid divergence free",
+                    profiler,
+                    &mut cpass,
+                    device,
+                    {
+                        cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_divergence_remove));
+                        pipeline_manager.bind_push_constants_compute(&mut cpass, &self.pipeline_divergence_remove);
+                        cpass.dispatch(grid_work_groups.width, grid_work_groups.height, grid_work_groups.depth_or_array_layers);
+                    }
+                );
+
+                profiling_scope!(Coarse, profiling_detail, "extrapolate velocity grid", profiler, &mut cpass, device, {
+                    cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_extrapolate_velocity));
                     cpass.dispatch(grid_work_groups.width, grid_work_groups.height, grid_work_groups.depth_or_array_layers);
                 });
-
-                wgpu_profiler!("extrapolate velocity grid", profiler, &mut cpass, device, {
-                    cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_extrapolate_velocity));
+            }
+            // The velocity volumes are reused for position changes in the density projection, so the curl needed for the next transfer
+            // has to be computed while they still hold the divergence free velocity.
+            if self.dynamic_settings.vorticity_confinement > 0.0 {
+                profiling_scope!(Coarse, profiling_detail, "compute vorticity", profiler, &mut cpass, device, {
+                    cpass.set_bind_group(2, &self.bind_group_vorticity_compute, &[]);
+                    cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_vorticity_compute));
                     cpass.dispatch(grid_work_groups.width, grid_work_groups.height, grid_work_groups.depth_or_array_layers);
                 });
             }
-            wgpu_profiler!("clear marker & linked list grids", profiler, &mut cpass, device, {
-                cpass.set_bind_group(2, &self.bind_group_transfer_velocity[0], &[]);
-                cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_transfer_clear));
-                cpass.set_push_constants(0, &bytemuck::bytes_of(&[0 as u32]));
-                cpass.dispatch(grid_work_groups.width, grid_work_groups.height, grid_work_groups.depth_or_array_layers);
-            });
-            wgpu_profiler!("advect particles & write new linked list grid", profiler, &mut cpass, device, {
-                cpass.set_bind_group(2, &self.bind_group_advect_particles, &[]);
-                cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_advect_particles));
-                cpass.dispatch(particle_work_groups, 1, 1);
-            });
-
-            wgpu_profiler!("density projection: set boundary marker", profiler, &mut cpass, device, {
-                cpass.set_bind_group(2, &self.bind_group_transfer_velocity[0], &[]);
-                cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_transfer_set_boundary_marker));
-                cpass.dispatch(grid_work_groups.width, grid_work_groups.height, grid_work_groups.depth_or_array_layers);
-            });
-            wgpu_profiler!("density projection: compute density error via gather", profiler, &mut cpass, device, {
-                cpass.set_bind_group(2, &self.bind_group_density_projection_gather_error, &[]);
-                cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_density_projection_gather_error));
-                cpass.dispatch(grid_work_groups.width, grid_work_groups.height, grid_work_groups.depth_or_array_layers);
-            });
-        }
+            profiling_scope!(
+                Coarse,
+                profiling_detail,
+                "clear marker & linked list grids",
+                profiler,
+                &mut cpass,
+                device,
+                {
+                    cpass.set_bind_group(2, &self.bind_group_transfer_velocity[0], &[]);
+                    cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_transfer_clear));
+                    pipeline_manager.set_push_constants_compute(&mut cpass, &self.pipeline_transfer_clear, 0, bytemuck::bytes_of(&[0 as u32]));
+                    cpass.dispatch(grid_work_groups.width, grid_work_groups.height, grid_work_groups.depth_or_array_layers);
+                }
+            );
+            profiling_scope!(
+                Coarse,
+                profiling_detail,
+                "advect particles & write new linked list grid",
+                profiler,
+                &mut cpass,
+                device,
+                {
+                    cpass.set_bind_group(2, &self.bind_group_advect_particles, &[]);
+                    cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_advect_particles));
+                    pipeline_manager.set_push_constants_compute(
+                        &mut cpass,
+                        &self.pipeline_advect_particles,
+                        0,
+                        bytemuck::bytes_of(&[self.dynamic_settings.advection_scheme as u32, self.dynamic_settings.solid_push_out as u32]),
+                    );
+                    cpass.dispatch_indirect(&self.particle_count_buffer, Self::PARTICLE_COUNT_BUFFER_DISPATCH_OFFSET);
+                }
+            );
 
-        wgpu_profiler!("secondary pressure solver (density)", profiler, encoder, device, {
-            self.pressure_solver.solve(
-                simulation_delta,
-                encoder,
+            profiling_scope!(
+                Coarse,
+                profiling_detail,
+                "density projection: set boundary marker",
+                profiler,
+                &mut cpass,
                 device,
-                &mut self.pressure_field_from_density,
-                pipeline_manager,
+                {
+                    cpass.set_bind_group(2, &self.bind_group_transfer_velocity[0], &[]);
+                    cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_transfer_set_boundary_marker));
+                    cpass.dispatch(grid_work_groups.width, grid_work_groups.height, grid_work_groups.depth_or_array_layers);
+                }
+            );
+            profiling_scope!(
+                Coarse,
+                profiling_detail,
+                "density projection: compute density error via gather",
                 profiler,
+                &mut cpass,
+                device,
+                {
+                    cpass.set_bind_group(2, &self.bind_group_density_projection_gather_error, &[]);
+                    cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_density_projection_gather_error));
+                    let gather_work_groups = wgpu_utils::compute_group_size(self.grid_dimension, Self::GATHER_WORKGROUP_CELLS);
+                    cpass.dispatch(
+                        gather_work_groups.width,
+                        gather_work_groups.height,
+                        gather_work_groups.depth_or_array_layers,
+                    );
+                }
             );
-        });
+        }
+
+        rebinning
+    }
+
+    fn correct_density_error(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        device: &wgpu::Device,
+        global_bind_group: &wgpu::BindGroup,
+        pipeline_manager: &PipelineManager,
+        profiler: &mut GpuProfiler,
+        profiling_detail: ProfilingDetail,
+    ) {
+        let grid_work_groups = wgpu_utils::compute_group_size(self.grid_dimension, self.workgroup_sizes.volume_extent());
 
         {
             let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
                 label: Some("correct for density error"),
             });
-            cpass.set_bind_group(1, &self.bind_group_general, &[]);
+            cpass.set_bind_group(1, &self.bind_group_general, &[self.simulation_properties_uniformbuffer.dynamic_offset()]);
             cpass.set_bind_group(0, global_bind_group, &[]);
             {
                 cpass.set_bind_group(2, &self.bind_group_density_projection_write_velocity, &[]);
 
-                wgpu_profiler!("compute position change", profiler, &mut cpass, device, {
+                profiling_scope!(Coarse, profiling_detail, "compute position change", profiler, &mut cpass, device, {
                     cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_density_projection_position_change));
+                    pipeline_manager.bind_push_constants_compute(&mut cpass, &self.pipeline_density_projection_position_change);
                     cpass.dispatch(grid_work_groups.width, grid_work_groups.height, grid_work_groups.depth_or_array_layers);
                 });
-                wgpu_profiler!("extrapolate velocity grid", profiler, &mut cpass, device, {
+                profiling_scope!(Coarse, profiling_detail, "extrapolate velocity grid", profiler, &mut cpass, device, {
                     cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_extrapolate_velocity));
                     cpass.dispatch(grid_work_groups.width, grid_work_groups.height, grid_work_groups.depth_or_array_layers);
                 });
             }
-            wgpu_profiler!("correct particle density error", profiler, &mut cpass, device, {
-                cpass.set_bind_group(2, &self.bind_group_density_projection_correct_particles, &[]);
-                cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_density_projection_correct_particles));
-                cpass.dispatch(particle_work_groups, 1, 1);
-            });
+            profiling_scope!(
+                Coarse,
+                profiling_detail,
+                "correct particle density error",
+                profiler,
+                &mut cpass,
+                device,
+                {
+                    cpass.set_bind_group(2, &self.bind_group_density_projection_correct_particles, &[]);
+                    cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_density_projection_correct_particles));
+                    cpass.dispatch_indirect(&self.particle_count_buffer, Self::PARTICLE_COUNT_BUFFER_DISPATCH_OFFSET);
+                }
+            );
         }
-
-        self.step_counter += 1;
     }
 }
@@ -1,5 +1,15 @@
 mod hybrid_fluid;
+mod playback_cache;
 mod pressure_solver;
+mod radix_sort;
+mod smoke;
+mod workgroup_sizes;
 
-pub use hybrid_fluid::HybridFluid;
+pub use hybrid_fluid::{
+    AdvectionScheme, ForceFieldUniformBufferContent, GridReadback, HybridFluid, InteractionForce, ParticleBinningMethod, SimulationStage,
+    MAX_NUM_FLUID_PHASES, MAX_NUM_FORCE_FIELDS, MAX_STORAGE_BUFFERS_PER_SHADER_STAGE,
+};
+pub use playback_cache::PlaybackCache;
 pub use pressure_solver::{SolverConfig, SolverStatisticSample};
+pub use smoke::{SmokeSimulation, MAX_NUM_SMOKE_EMITTERS};
+pub use workgroup_sizes::WorkgroupSizes;
@@ -0,0 +1,151 @@
+use std::{collections::VecDeque, time::Duration};
+
+struct CachedFrame {
+    particle_positions: wgpu::Buffer,
+    simulation_time: Duration,
+}
+
+// Keeps the particle positions of the last n simulated frames around, so we can go back in time and inspect them.
+// Every frame is a full copy of the particle position buffer, so recording is off until enabled via set_recording.
+//
+// Only positions are stored, so this is purely for viewing, the simulation can't continue from a cached frame.
+// While a cached frame is displayed, it is copied into the live particle buffer (which makes all renderers just work)
+// and the live state is backed up. Once the simulation steps again, the live state is restored.
+pub struct PlaybackCache {
+    frames: VecDeque<CachedFrame>, // oldest first
+    unused_buffers: Vec<wgpu::Buffer>,
+    // Only allocated once a cached frame is displayed.
+    live_state_backup: Option<wgpu::Buffer>,
+    buffer_size: u64,
+    max_num_frames: usize,
+    recording: bool,
+
+    displayed_frame: Option<usize>, // None means that the particle buffer holds the live state.
+    requested_frame: Option<usize>,
+}
+
+impl PlaybackCache {
+    const DEFAULT_MAX_NUM_FRAMES: usize = 32;
+
+    pub fn new(buffer_size: u64) -> Self {
+        PlaybackCache {
+            frames: VecDeque::new(),
+            unused_buffers: Vec::new(),
+            live_state_backup: None,
+            buffer_size,
+            max_num_frames: Self::DEFAULT_MAX_NUM_FRAMES,
+            recording: false,
+            displayed_frame: None,
+            requested_frame: None,
+        }
+    }
+
+    fn create_buffer(device: &wgpu::Device, size: u64, label: &str) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size,
+            usage: wgpu::BufferUsage::COPY_SRC | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    pub fn recording(&self) -> bool {
+        self.recording
+    }
+
+    // Once recording stops, all cached frames are dropped with the next call to apply (after going back to the live state).
+    pub fn set_recording(&mut self, recording: bool) {
+        self.recording = recording;
+        if !recording {
+            self.requested_frame = None;
+        }
+    }
+
+    pub fn max_num_frames(&self) -> usize {
+        self.max_num_frames
+    }
+
+    pub fn set_max_num_frames(&mut self, max_num_frames: usize) {
+        self.max_num_frames = max_num_frames;
+        self.requested_frame = None;
+        while self.frames.len() > self.max_num_frames {
+            self.frames.pop_front();
+        }
+        // Don't hold on to memory we don't need anymore.
+        self.unused_buffers.clear();
+    }
+
+    pub fn num_frames(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn frame_simulation_time(&self, frame: usize) -> Duration {
+        self.frames[frame].simulation_time
+    }
+
+    pub fn memory_usage(&self) -> u64 {
+        (self.frames.len() + self.unused_buffers.len() + self.live_state_backup.is_some() as usize) as u64 * self.buffer_size
+    }
+
+    pub fn requested_frame(&self) -> Option<usize> {
+        self.requested_frame
+    }
+
+    // Requests a cached frame to be displayed (None to go back to the live state).
+    // Takes effect with the next call to apply.
+    pub fn request_frame(&mut self, frame: Option<usize>) {
+        self.requested_frame = frame.filter(|frame| *frame < self.frames.len());
+    }
+
+    // Forgets all cached frames. The live particle buffer is assumed to be reset as well.
+    pub fn clear(&mut self) {
+        self.unused_buffers.extend(self.frames.drain(..).map(|frame| frame.particle_positions));
+        self.displayed_frame = None;
+        self.requested_frame = None;
+    }
+
+    // Adds the current (live) state of the particle buffer to the cache.
+    pub fn capture(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, particles: &wgpu::Buffer, simulation_time: Duration) {
+        if !self.recording || self.max_num_frames == 0 || self.displayed_frame.is_some() {
+            return;
+        }
+
+        let particle_positions = if self.frames.len() >= self.max_num_frames {
+            self.frames.pop_front().unwrap().particle_positions
+        } else if let Some(buffer) = self.unused_buffers.pop() {
+            buffer
+        } else {
+            Self::create_buffer(device, self.buffer_size, "Buffer: Playback cache frame")
+        };
+        encoder.copy_buffer_to_buffer(particles, 0, &particle_positions, 0, self.buffer_size);
+        self.frames.push_back(CachedFrame {
+            particle_positions,
+            simulation_time,
+        });
+    }
+
+    // Makes sure the particle buffer holds the requested frame.
+    pub fn apply(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, particles: &wgpu::Buffer) {
+        if self.requested_frame != self.displayed_frame {
+            let buffer_size = self.buffer_size;
+            let live_state_backup = self
+                .live_state_backup
+                .get_or_insert_with(|| Self::create_buffer(device, buffer_size, "Buffer: Playback cache live state backup"));
+            if self.displayed_frame.is_none() {
+                encoder.copy_buffer_to_buffer(particles, 0, live_state_backup, 0, buffer_size);
+            }
+            let source = match self.requested_frame {
+                Some(frame) => &self.frames[frame].particle_positions,
+                None => &*live_state_backup,
+            };
+            encoder.copy_buffer_to_buffer(source, 0, particles, 0, buffer_size);
+            self.displayed_frame = self.requested_frame;
+        }
+
+        if !self.recording && self.displayed_frame.is_none() {
+            self.frames.clear();
+            self.unused_buffers.clear();
+            self.live_state_backup = None;
+        }
+    }
+}
@@ -1,11 +1,12 @@
-use crate::wgpu_utils::{self, binding_builder::*, binding_glsl, pipelines::*, shader::ShaderDirectory};
+use super::workgroup_sizes::WorkgroupSizes;
+use crate::wgpu_utils::{self, binding_builder::*, binding_glsl, pipelines::*, profiling::ProfilingDetail, shader::ShaderDirectory};
 use futures::Future;
 use futures::*;
+use serde::Serialize;
 use std::collections::VecDeque;
-use std::rc::Rc;
 use std::{path::Path, pin::Pin, time::Duration};
-use wgpu_profiler::{wgpu_profiler, GpuProfiler};
-use wgpu_utils::uniformbuffer::UniformBuffer;
+use wgpu_profiler::GpuProfiler;
+use wgpu_utils::uniformbuffer::UniformBufferRing;
 
 fn create_volume_texture_desc(label: &str, grid_dimension: wgpu::Extent3d, format: wgpu::TextureFormat) -> wgpu::TextureDescriptor {
     wgpu::TextureDescriptor {
@@ -15,12 +16,16 @@ fn create_volume_texture_desc(label: &str, grid_dimension: wgpu::Extent3d, forma
         sample_count: 1,
         dimension: wgpu::TextureDimension::D3,
         format,
-        usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::STORAGE | wgpu::TextureUsage::COPY_DST,
+        usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::STORAGE | wgpu::TextureUsage::COPY_DST | wgpu::TextureUsage::COPY_SRC,
     }
 }
 
 pub struct PressureSolver {
     grid_dimension: wgpu::Extent3d,
+    workgroup_sizes: WorkgroupSizes,
+    // Every thread of a pressure pass writes one entry for reduction, including those beyond the grid (they write zero).
+    // This is more than the number of cells if the grid isn't a multiple of the workgroup size.
+    num_reduce_entries: u32,
 
     bind_group_general: wgpu::BindGroup,
     bind_group_init: wgpu::BindGroup,
@@ -49,12 +54,12 @@ pub struct PressureSolver {
 const NUM_PRESSURE_ERROR_BUFFER: usize = 32;
 
 struct PendingErrorBuffer {
-    copy_operation: Option<Pin<Box<dyn Future<Output = std::result::Result<(), wgpu::BufferAsyncError>>>>>,
+    copy_operation: Option<Pin<Box<dyn Future<Output = std::result::Result<(), wgpu::BufferAsyncError>> + Send>>>,
     buffer: wgpu::Buffer,
     resulting_sample: SolverStatisticSample,
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Serialize)]
 pub struct SolverConfig {
     pub error_tolerance: f32,
     pub max_num_iterations: i32,
@@ -74,11 +79,13 @@ struct SolverConfigUniformBufferContent {
     // For easier handling with different timesteps the user facing parameter is about 'pressure * density'.
     error_tolerance: f32,
     max_num_iterations: u32,
+    periodic_axes: u32,
+    padding: u32,
 }
 unsafe impl bytemuck::Pod for SolverConfigUniformBufferContent {}
 unsafe impl bytemuck::Zeroable for SolverConfigUniformBufferContent {}
 
-type SolverConfigUniformBuffer = UniformBuffer<SolverConfigUniformBufferContent>;
+type SolverConfigUniformBuffer = UniformBufferRing<SolverConfigUniformBufferContent>;
 
 // Pressure solver instance keeps track of pressure result from last step/frame in order to speed up the solve.
 pub struct PressureField {
@@ -92,6 +99,8 @@ pub struct PressureField {
 
     config_ubo: SolverConfigUniformBuffer,
     pub config: SolverConfig,
+    // Bit per axis along which the domain wraps around, see FluidConfig::periodic
+    pub periodic_axes: u32,
     pub stats: VecDeque<SolverStatisticSample>,
 
     timestamp_last_iteration: Duration,
@@ -135,12 +144,17 @@ impl PressureField {
 
             config_ubo,
             config,
+            periodic_axes: 0,
             stats: VecDeque::new(),
 
             timestamp_last_iteration: Duration::new(0, 0),
         }
     }
 
+    pub fn pressure_volume(&self) -> &wgpu::Texture {
+        &self.volume_pressure
+    }
+
     pub fn pressure_view(&self) -> &wgpu::TextureView {
         &self.volume_pressure_view
     }
@@ -196,6 +210,8 @@ impl PressureField {
             SolverConfigUniformBufferContent {
                 error_tolerance: self.config.error_tolerance / simulation_delta.as_secs_f32(),
                 max_num_iterations: self.config.max_num_iterations as u32,
+                periodic_axes: self.periodic_axes,
+                padding: 0,
             },
         );
     }
@@ -216,11 +232,6 @@ impl PressureSolver {
     const REDUCE_RESULTMODE_BETA: u32 = 3;
     const REDUCE_RESULTMODE_MAX_ERROR: u32 = 4;
 
-    const COMPUTE_LOCAL_SIZE_VOLUME: wgpu::Extent3d = wgpu::Extent3d {
-        width: 8,
-        height: 8,
-        depth_or_array_layers: 1,
-    };
     const COMPUTE_LOCAL_SIZE_REDUCE: u32 = 1024;
     const REDUCE_READS_PER_THREAD: u32 = 16; // 32 was distinctively slower, 16 about same as than 8, 4 clearly slower (gtx1070 ti)
     const REDUCE_REDUCTION_PER_STEP: u32 = Self::COMPUTE_LOCAL_SIZE_REDUCE * Self::REDUCE_READS_PER_THREAD;
@@ -231,16 +242,18 @@ impl PressureSolver {
         shader_dir: &ShaderDirectory,
         pipeline_manager: &mut PipelineManager,
         volume_marker_view: &wgpu::TextureView,
+        volume_density_view: &wgpu::TextureView,
     ) -> Self {
         let group_layout_general = BindGroupLayoutBuilder::new()
-            .next_binding_compute(binding_glsl::texture3D())
+            .next_binding_compute(binding_glsl::texture3D()) // marker
+            .next_binding_compute(binding_glsl::texture3D()) // density
             .create(device, "BindGroupLayout: Pressure solver general");
         let group_layout_pressure_field = BindGroupLayoutBuilder::new()
             .next_binding_compute(binding_glsl::image3D(
                 wgpu::TextureFormat::R32Float,
                 wgpu::StorageTextureAccess::ReadWrite,
             ))
-            .next_binding_compute(binding_glsl::uniform())
+            .next_binding_compute(binding_glsl::uniform_dynamic())
             .create(device, "BindGroupLayout: Pressure solver Pressure");
         let group_layout_init = BindGroupLayoutBuilder::new()
             .next_binding_compute(binding_glsl::image3D(
@@ -282,52 +295,67 @@ impl PressureSolver {
             range: 0..8,
         }];
 
-        let layout_update_volume = Rc::new(device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("Update Volume Pipeline Layout"),
-            bind_group_layouts: &[
-                &group_layout_general.layout,
-                &group_layout_pressure_field.layout,
-                &group_layout_update_volume.layout,
-            ],
-            push_constant_ranges,
-        }));
-        let layout_preconditioner = Rc::new(device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("Pressure Solve Precondition Pipeline Layout"),
-            bind_group_layouts: &[
-                &group_layout_general.layout,
-                &group_layout_pressure_field.layout,
-                &group_layout_preconditioner.layout,
-            ],
-            push_constant_ranges,
-        }));
-        let layout_init = Rc::new(device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("Pressure Solve Init Pipeline Layout"),
-            bind_group_layouts: &[
-                &group_layout_general.layout,
-                &group_layout_pressure_field.layout,
-                &group_layout_init.layout,
-            ],
-            push_constant_ranges,
-        }));
-
-        let layout_apply_coeff = Rc::new(device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("Pressure Solve Apply Coeff Pipeline Layout"),
-            bind_group_layouts: &[
-                &group_layout_general.layout,
-                &group_layout_pressure_field.layout,
-                &group_layout_apply_coeff.layout,
-            ],
-            push_constant_ranges,
-        }));
-        let layout_reduce = Rc::new(device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("Pressure Solve Reduce Pipeline Layout"),
-            bind_group_layouts: &[
-                &group_layout_general.layout,
-                &group_layout_pressure_field.layout,
-                &group_layout_reduce.layout,
-            ],
-            push_constant_ranges,
-        }));
+        let layout_update_volume = pipeline_manager.create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Update Volume Pipeline Layout"),
+                bind_group_layouts: &[
+                    &group_layout_general.layout,
+                    &group_layout_pressure_field.layout,
+                    &group_layout_update_volume.layout,
+                ],
+                push_constant_ranges,
+            },
+        );
+        let layout_preconditioner = pipeline_manager.create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Pressure Solve Precondition Pipeline Layout"),
+                bind_group_layouts: &[
+                    &group_layout_general.layout,
+                    &group_layout_pressure_field.layout,
+                    &group_layout_preconditioner.layout,
+                ],
+                push_constant_ranges,
+            },
+        );
+        let layout_init = pipeline_manager.create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Pressure Solve Init Pipeline Layout"),
+                bind_group_layouts: &[
+                    &group_layout_general.layout,
+                    &group_layout_pressure_field.layout,
+                    &group_layout_init.layout,
+                ],
+                push_constant_ranges,
+            },
+        );
+
+        let layout_apply_coeff = pipeline_manager.create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Pressure Solve Apply Coeff Pipeline Layout"),
+                bind_group_layouts: &[
+                    &group_layout_general.layout,
+                    &group_layout_pressure_field.layout,
+                    &group_layout_apply_coeff.layout,
+                ],
+                push_constant_ranges,
+            },
+        );
+        let layout_reduce = pipeline_manager.create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Pressure Solve Reduce Pipeline Layout"),
+                bind_group_layouts: &[
+                    &group_layout_general.layout,
+                    &group_layout_pressure_field.layout,
+                    &group_layout_reduce.layout,
+                ],
+                push_constant_ranges,
+            },
+        );
 
         let volume_residual = device.create_texture(&create_volume_texture_desc(
             "Pressure Solve Residual",
@@ -350,17 +378,23 @@ impl PressureSolver {
             wgpu::TextureFormat::R32Float,
         ));
 
-        let num_cells = (grid_dimension.width * grid_dimension.height * grid_dimension.depth_or_array_layers) as u64;
+        let workgroup_sizes = WorkgroupSizes::from_shader_dir(shader_dir);
+        let num_reduce_entries = {
+            let workgroup_size = workgroup_sizes.pressure_extent();
+            let num_workgroups = wgpu_utils::compute_group_size(grid_dimension, workgroup_size);
+            num_workgroups.width * workgroup_size.width * num_workgroups.height * workgroup_size.height * num_workgroups.depth_or_array_layers
+        };
         let dotproduct_reduce_step_buffers = [
             device.create_buffer(&wgpu::BufferDescriptor {
                 label: Some("Buffer: DotProduct Reduce 0"),
-                size: num_cells * std::mem::size_of::<f32>() as u64,
+                size: num_reduce_entries as u64 * std::mem::size_of::<f32>() as u64,
                 usage: wgpu::BufferUsage::STORAGE,
                 mapped_at_creation: false,
             }),
             device.create_buffer(&wgpu::BufferDescriptor {
                 label: Some("Buffer: DotProduct Reduce 1"),
-                size: num_cells * std::mem::size_of::<f32>() as u64 / Self::REDUCE_REDUCTION_PER_STEP as u64,
+                // Small grids don't need any reduce steps before the final one, but a buffer needs to be there regardless.
+                size: Self::num_reduce_step_results(num_reduce_entries).max(1) as u64 * std::mem::size_of::<f32>() as u64,
                 usage: wgpu::BufferUsage::STORAGE,
                 mapped_at_creation: false,
             }),
@@ -379,6 +413,7 @@ impl PressureSolver {
 
         let bind_group_general = BindGroupBuilder::new(&group_layout_general)
             .texture(&volume_marker_view)
+            .texture(&volume_density_view)
             .create(device, "BindGroup: Pressure Solve general");
         let bind_group_init = BindGroupBuilder::new(&group_layout_init)
             .texture(&volume_residual_view)
@@ -446,6 +481,8 @@ impl PressureSolver {
 
         PressureSolver {
             grid_dimension: grid_dimension,
+            workgroup_sizes,
+            num_reduce_entries,
 
             bind_group_general,
             bind_group_init,
@@ -532,6 +569,11 @@ impl PressureSolver {
         &self.volume_residual_view
     }
 
+    // Number of values a reduce step leaves over from a given number of entries, i.e. the number of workgroups it needs.
+    fn num_reduce_step_results(num_entries: u32) -> u32 {
+        wgpu_utils::compute_group_size_1d(num_entries, Self::REDUCE_REDUCTION_PER_STEP)
+    }
+
     fn reduce_add<'a, 'b: 'a>(&'b self, cpass: &mut wgpu::ComputePass<'a>, pipeline_manager: &'a PipelineManager, result_mode: u32) {
         self.reduce(cpass, pipeline_manager, result_mode, &self.pipeline_reduce_sum);
     }
@@ -547,8 +589,8 @@ impl PressureSolver {
         result_mode: u32,
         pipeline: &ComputePipelineHandle,
     ) {
-        let mut num_entries_remaining = (self.grid_dimension.width * self.grid_dimension.height * self.grid_dimension.depth_or_array_layers) as u32;
-        assert!(num_entries_remaining > Self::REDUCE_REDUCTION_PER_STEP);
+        // If there are few enough entries (small grids), the final step takes care of everything.
+        let mut num_entries_remaining = self.num_reduce_entries;
         let mut source_buffer_index = 0;
 
         // the first few reduce steps are indirect dispatches so we can disable them if we reached some error threshold.
@@ -560,7 +602,12 @@ impl PressureSolver {
         let mut reduce_step_idx = 0;
         while num_entries_remaining > Self::REDUCE_REDUCTION_PER_STEP {
             cpass.set_bind_group(2, &self.bind_group_dotproduct_reduce[source_buffer_index], &[]);
-            cpass.set_push_constants(0, &bytemuck::bytes_of(&[Self::REDUCE_RESULTMODE_REDUCE, num_entries_remaining]));
+            pipeline_manager.set_push_constants_compute(
+                cpass,
+                pipeline,
+                0,
+                bytemuck::bytes_of(&[Self::REDUCE_RESULTMODE_REDUCE, num_entries_remaining]),
+            );
 
             if reduce_step_idx < DISPATCH_BUFFER_OFFSETS.len() {
                 cpass.dispatch_indirect(
@@ -568,14 +615,10 @@ impl PressureSolver {
                     DISPATCH_BUFFER_OFFSETS[reduce_step_idx],
                 )
             } else {
-                cpass.dispatch(
-                    wgpu_utils::compute_group_size_1d(num_entries_remaining / Self::REDUCE_READS_PER_THREAD, Self::COMPUTE_LOCAL_SIZE_REDUCE),
-                    1,
-                    1,
-                );
+                cpass.dispatch(Self::num_reduce_step_results(num_entries_remaining), 1, 1);
             }
             source_buffer_index = 1 - source_buffer_index;
-            num_entries_remaining /= Self::REDUCE_REDUCTION_PER_STEP;
+            num_entries_remaining = Self::num_reduce_step_results(num_entries_remaining);
 
             reduce_step_idx += 1;
         }
@@ -584,7 +627,7 @@ impl PressureSolver {
         // Right now not a dispatch_indirect, so we always run it even if we decided that it is no longer necessary.
         // It's simply a bit too tricky to turn it off - we can't write into a dispatch buffer that is in use
         cpass.set_bind_group(2, &self.bind_group_dotproduct_final[source_buffer_index], &[]);
-        cpass.set_push_constants(0, &bytemuck::bytes_of(&[result_mode, num_entries_remaining]));
+        pipeline_manager.set_push_constants_compute(cpass, pipeline, 0, bytemuck::bytes_of(&[result_mode, num_entries_remaining]));
         cpass.dispatch(1, 1, 1);
     }
 
@@ -596,6 +639,7 @@ impl PressureSolver {
         pressure_field: &'a mut PressureField,
         pipeline_manager: &'a PipelineManager,
         profiler: &mut GpuProfiler,
+        profiling_detail: ProfilingDetail,
     ) {
         // Clear pressures on first overall step of this pressure field.
         if pressure_field.timestamp_last_iteration == Duration::new(0, 0) {
@@ -611,84 +655,144 @@ impl PressureSolver {
 
         pressure_field.retrieve_new_error_samples(simulation_delta);
 
-        let reduce_pass_initial_group_size = wgpu_utils::compute_group_size_1d(
-            (self.grid_dimension.width * self.grid_dimension.height * self.grid_dimension.depth_or_array_layers) as u32
-                / Self::REDUCE_READS_PER_THREAD,
-            Self::COMPUTE_LOCAL_SIZE_REDUCE,
-        );
+        let reduce_pass_initial_group_size = Self::num_reduce_step_results(self.num_reduce_entries);
 
         cpass.set_bind_group(0, &self.bind_group_general, &[]);
-        cpass.set_bind_group(1, &pressure_field.bind_group_pressure_field, &[]);
+        cpass.set_bind_group(
+            1,
+            &pressure_field.bind_group_pressure_field,
+            &[pressure_field.config_ubo.dynamic_offset()],
+        );
 
         // For optimization various steps are collapsed as far as possible to avoid expensive buffer/texture read/writes
         // This makes the algorithm a lot faster but also a bit harder to read.
-        wgpu_profiler!("init", profiler, &mut cpass, device, {
-            let grid_work_groups = wgpu_utils::compute_group_size(self.grid_dimension, Self::COMPUTE_LOCAL_SIZE_VOLUME);
+        profiling_scope!(Coarse, profiling_detail, "init", profiler, &mut cpass, device, {
+            let grid_work_groups = wgpu_utils::compute_group_size(self.grid_dimension, self.workgroup_sizes.pressure_extent());
 
             // We use pressure from last frame, but set explicitly set all pressure values to zero wherever there is not fluid right now.
             // This is done in order to prevent having results from many frames ago influence results for upcoming frames.
             cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_init));
+            pipeline_manager.bind_push_constants_compute(&mut cpass, &self.pipeline_init);
             cpass.set_bind_group(2, &self.bind_group_init, &[]);
             cpass.dispatch(grid_work_groups.width, grid_work_groups.height, grid_work_groups.depth_or_array_layers);
 
             // Apply preconditioner on (r), store result to search vector (s) and start dotproduct of <s; r>
             // Note that we don't use the auxillary vector here as in-between storage!
-            wgpu_profiler!("preconditioner(r) ➡ s, start s·r", profiler, &mut cpass, device, {
-                cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_apply_preconditioner));
-                cpass.set_push_constants(0, &bytemuck::bytes_of(&[0 as u32]));
-                cpass.set_push_constants(0, &bytemuck::bytes_of(&[PRECONDITIONER_PASS0]));
-                cpass.set_bind_group(2, &self.bind_group_preconditioner[0], &[]);
-                cpass.dispatch(grid_work_groups.width, grid_work_groups.height, grid_work_groups.depth_or_array_layers);
-                cpass.set_push_constants(0, &bytemuck::bytes_of(&[PRECONDITIONER_PASS1, reduce_pass_initial_group_size]));
-                cpass.set_bind_group(2, &self.bind_group_preconditioner[2], &[]);
-                cpass.dispatch(grid_work_groups.width, grid_work_groups.height, grid_work_groups.depth_or_array_layers);
-            });
-            wgpu_profiler!("reduce_add: finish s·r ➡ sigma", profiler, &mut cpass, device, {
-                self.reduce_add(&mut cpass, pipeline_manager, Self::REDUCE_RESULTMODE_INIT);
-            });
+            profiling_scope!(
+                Fine,
+                profiling_detail,
+                "preconditioner(r) ➡ s, start s·r",
+                profiler,
+                &mut cpass,
+                device,
+                {
+                    cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_apply_preconditioner));
+                    pipeline_manager.set_push_constants_compute(&mut cpass, &self.pipeline_apply_preconditioner, 0, bytemuck::bytes_of(&[0 as u32]));
+                    pipeline_manager.set_push_constants_compute(
+                        &mut cpass,
+                        &self.pipeline_apply_preconditioner,
+                        0,
+                        bytemuck::bytes_of(&[PRECONDITIONER_PASS0]),
+                    );
+                    cpass.set_bind_group(2, &self.bind_group_preconditioner[0], &[]);
+                    cpass.dispatch(grid_work_groups.width, grid_work_groups.height, grid_work_groups.depth_or_array_layers);
+                    pipeline_manager.set_push_constants_compute(
+                        &mut cpass,
+                        &self.pipeline_apply_preconditioner,
+                        0,
+                        bytemuck::bytes_of(&[PRECONDITIONER_PASS1, reduce_pass_initial_group_size]),
+                    );
+                    cpass.set_bind_group(2, &self.bind_group_preconditioner[2], &[]);
+                    cpass.dispatch(grid_work_groups.width, grid_work_groups.height, grid_work_groups.depth_or_array_layers);
+                }
+            );
+            profiling_scope!(
+                Fine,
+                profiling_detail,
+                "reduce_add: finish s·r ➡ sigma",
+                profiler,
+                &mut cpass,
+                device,
+                {
+                    self.reduce_add(&mut cpass, pipeline_manager, Self::REDUCE_RESULTMODE_INIT);
+                }
+            );
         });
 
-        wgpu_profiler!("solver iterations", profiler, &mut cpass, device, {
+        profiling_scope!(Coarse, profiling_detail, "solver iterations", profiler, &mut cpass, device, {
             const DISPATCH_BUFFER_OFFSET: u64 = 4 * 4;
 
             let mut i = 0;
-            while wgpu_profiler!(
+            while profiling_scope!(
+                Fine,
+                profiling_detail,
                 &format!("iteration {}", i),
                 profiler,
                 &mut cpass,
                 device,
                 (|| {
-                    wgpu_profiler!("sA ➡ z, start s·z", profiler, &mut cpass, device, {
+                    profiling_scope!(Fine, profiling_detail, "sA ➡ z, start s·z", profiler, &mut cpass, device, {
                         // The dot product is applied to the result (denoted as z in Bridson's book) and the search vector (s), i.e. compute <s; As>
                         cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_apply_coeff));
                         cpass.set_bind_group(2, &self.bind_group_apply_coeff, &[]);
-                        cpass.set_push_constants(0, &bytemuck::bytes_of(&[0, reduce_pass_initial_group_size]));
+                        pipeline_manager.set_push_constants_compute(
+                            &mut cpass,
+                            &self.pipeline_apply_coeff,
+                            0,
+                            bytemuck::bytes_of(&[0, reduce_pass_initial_group_size]),
+                        );
                         cpass.dispatch_indirect(&self.dotproduct_reduce_result_and_dispatch_buffer, DISPATCH_BUFFER_OFFSET);
                     });
-                    wgpu_profiler!("reduce_add: finish s·z ➡ alpha", profiler, &mut cpass, device, {
-                        self.reduce_add(&mut cpass, pipeline_manager, Self::REDUCE_RESULTMODE_ALPHA);
-                    });
+                    profiling_scope!(
+                        Fine,
+                        profiling_detail,
+                        "reduce_add: finish s·z ➡ alpha",
+                        profiler,
+                        &mut cpass,
+                        device,
+                        {
+                            self.reduce_add(&mut cpass, pipeline_manager, Self::REDUCE_RESULTMODE_ALPHA);
+                        }
+                    );
 
                     let iteration_with_error_computation =
                         pressure_field.config.max_num_iterations == i || (i > 0 && i % pressure_field.config.error_check_frequency == 0);
 
-                    wgpu_profiler!("update pressure field (p) & residual field (r)", profiler, &mut cpass, device, {
-                        const PRUPDATE_COMPUTE_MAX_ERROR: u32 = 1;
-                        cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_update_pressure_and_residual));
-                        if iteration_with_error_computation {
-                            cpass.set_push_constants(0, &bytemuck::bytes_of(&[PRUPDATE_COMPUTE_MAX_ERROR, reduce_pass_initial_group_size]));
-                        } else {
-                            cpass.set_push_constants(0, &bytemuck::bytes_of(&[0]));
+                    profiling_scope!(
+                        Fine,
+                        profiling_detail,
+                        "update pressure field (p) & residual field (r)",
+                        profiler,
+                        &mut cpass,
+                        device,
+                        {
+                            const PRUPDATE_COMPUTE_MAX_ERROR: u32 = 1;
+                            cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_update_pressure_and_residual));
+                            if iteration_with_error_computation {
+                                pipeline_manager.set_push_constants_compute(
+                                    &mut cpass,
+                                    &self.pipeline_update_pressure_and_residual,
+                                    0,
+                                    bytemuck::bytes_of(&[PRUPDATE_COMPUTE_MAX_ERROR, reduce_pass_initial_group_size]),
+                                );
+                            } else {
+                                pipeline_manager.set_push_constants_compute(
+                                    &mut cpass,
+                                    &self.pipeline_update_pressure_and_residual,
+                                    0,
+                                    bytemuck::bytes_of(&[0]),
+                                );
+                            }
+                            cpass.set_bind_group(2, &self.bind_group_update_pressure_and_residual, &[]);
+                            cpass.dispatch_indirect(&self.dotproduct_reduce_result_and_dispatch_buffer, DISPATCH_BUFFER_OFFSET);
                         }
-                        cpass.set_bind_group(2, &self.bind_group_update_pressure_and_residual, &[]);
-                        cpass.dispatch_indirect(&self.dotproduct_reduce_result_and_dispatch_buffer, DISPATCH_BUFFER_OFFSET);
-                    });
+                    );
 
                     // Time to check on error?
                     if iteration_with_error_computation {
                         // Compute remaining error.
                         // Used for statistics. If below target, makes all upcoming dispatch_indirect no-ops.
-                        wgpu_profiler!("reduce: compute max error", profiler, &mut cpass, device, {
+                        profiling_scope!(Fine, profiling_detail, "reduce: compute max error", profiler, &mut cpass, device, {
                             self.reduce_max(&mut cpass, pipeline_manager, Self::REDUCE_RESULTMODE_MAX_ERROR + i as u32);
                         });
 
@@ -697,21 +801,39 @@ impl PressureSolver {
                         }
                     }
 
-                    wgpu_profiler!("preconditioner(r) ➡ (z), start z·r", profiler, &mut cpass, device, {
-                        cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_apply_preconditioner));
-                        cpass.set_push_constants(0, &bytemuck::bytes_of(&[PRECONDITIONER_PASS0]));
-                        cpass.set_bind_group(2, &self.bind_group_preconditioner[0], &[]);
-                        cpass.dispatch_indirect(&self.dotproduct_reduce_result_and_dispatch_buffer, DISPATCH_BUFFER_OFFSET);
-                        cpass.set_push_constants(0, &bytemuck::bytes_of(&[PRECONDITIONER_PASS1, reduce_pass_initial_group_size]));
-                        cpass.set_bind_group(2, &self.bind_group_preconditioner[1], &[]);
-                        cpass.dispatch_indirect(&self.dotproduct_reduce_result_and_dispatch_buffer, DISPATCH_BUFFER_OFFSET);
-                    });
+                    profiling_scope!(
+                        Fine,
+                        profiling_detail,
+                        "preconditioner(r) ➡ (z), start z·r",
+                        profiler,
+                        &mut cpass,
+                        device,
+                        {
+                            cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_apply_preconditioner));
+                            pipeline_manager.set_push_constants_compute(
+                                &mut cpass,
+                                &self.pipeline_apply_preconditioner,
+                                0,
+                                bytemuck::bytes_of(&[PRECONDITIONER_PASS0]),
+                            );
+                            cpass.set_bind_group(2, &self.bind_group_preconditioner[0], &[]);
+                            cpass.dispatch_indirect(&self.dotproduct_reduce_result_and_dispatch_buffer, DISPATCH_BUFFER_OFFSET);
+                            pipeline_manager.set_push_constants_compute(
+                                &mut cpass,
+                                &self.pipeline_apply_preconditioner,
+                                0,
+                                bytemuck::bytes_of(&[PRECONDITIONER_PASS1, reduce_pass_initial_group_size]),
+                            );
+                            cpass.set_bind_group(2, &self.bind_group_preconditioner[1], &[]);
+                            cpass.dispatch_indirect(&self.dotproduct_reduce_result_and_dispatch_buffer, DISPATCH_BUFFER_OFFSET);
+                        }
+                    );
 
-                    wgpu_profiler!("reduce_add: finish z·r ➡ beta", profiler, &mut cpass, device, {
+                    profiling_scope!(Fine, profiling_detail, "reduce_add: finish z·r ➡ beta", profiler, &mut cpass, device, {
                         self.reduce_add(&mut cpass, pipeline_manager, Self::REDUCE_RESULTMODE_BETA);
                     });
 
-                    wgpu_profiler!("Update search vector (s)", profiler, &mut cpass, device, {
+                    profiling_scope!(Fine, profiling_detail, "Update search vector (s)", profiler, &mut cpass, device, {
                         cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_update_search));
                         cpass.set_bind_group(2, &self.bind_group_update_search, &[]);
                         cpass.dispatch_indirect(&self.dotproduct_reduce_result_and_dispatch_buffer, DISPATCH_BUFFER_OFFSET);
@@ -0,0 +1,160 @@
+use crate::wgpu_utils::{self, binding_builder::*, binding_glsl, pipelines::*, shader::ShaderDirectory};
+use std::path::Path;
+
+// GPU radix sort of u32 key/value pairs, used for sort based particle binning.
+//
+// Sorts a fixed number of keys (padded to a multiple of the workgroup size), unused keys should be set to u32::MAX so they end up last.
+// Keys & values are written to keys() & values() and are sorted in place from the outside point of view.
+pub struct RadixSort {
+    num_workgroups: u32,
+    num_passes: u32,
+
+    keys: wgpu::Buffer,
+    values: wgpu::Buffer,
+    bind_groups: [wgpu::BindGroup; 2],
+
+    pipeline_histogram: ComputePipelineHandle,
+    pipeline_scan: ComputePipelineHandle,
+    pipeline_scatter: ComputePipelineHandle,
+}
+
+impl RadixSort {
+    // Needs to be kept in sync with LOCAL_SIZE_RADIX_SORT in radix_sort.glsl
+    pub const LOCAL_SIZE: u32 = 256;
+    const BITS_PER_PASS: u32 = 4;
+    const NUM_DIGITS: u32 = 1 << Self::BITS_PER_PASS;
+
+    pub fn new(
+        device: &wgpu::Device,
+        shader_dir: &ShaderDirectory,
+        pipeline_manager: &mut PipelineManager,
+        num_keys: u32,
+        num_key_bits: u32,
+    ) -> Self {
+        assert!(num_key_bits <= 32);
+        let num_workgroups = wgpu_utils::compute_group_size_1d(num_keys, Self::LOCAL_SIZE);
+        // Even number of passes, so the result ends up in the input buffers again.
+        let num_passes = (wgpu_utils::compute_group_size_1d(num_key_bits, Self::BITS_PER_PASS) + 1) / 2 * 2;
+
+        let create_buffer = |label: &str, size: u64| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size,
+                usage: wgpu::BufferUsage::STORAGE,
+                mapped_at_creation: false,
+            })
+        };
+        let padded_size = (num_workgroups * Self::LOCAL_SIZE) as u64 * std::mem::size_of::<u32>() as u64;
+        let keys = create_buffer("Buffer: Radix sort keys", padded_size);
+        let values = create_buffer("Buffer: Radix sort values", padded_size);
+        let keys_tmp = create_buffer("Buffer: Radix sort keys tmp", padded_size);
+        let values_tmp = create_buffer("Buffer: Radix sort values tmp", padded_size);
+        let histogram = create_buffer(
+            "Buffer: Radix sort histogram",
+            (num_workgroups * Self::NUM_DIGITS) as u64 * std::mem::size_of::<u32>() as u64,
+        );
+
+        let group_layout = BindGroupLayoutBuilder::new()
+            .next_binding_compute(binding_glsl::buffer(true)) // keys in
+            .next_binding_compute(binding_glsl::buffer(true)) // values in
+            .next_binding_compute(binding_glsl::buffer(false)) // keys out
+            .next_binding_compute(binding_glsl::buffer(false)) // values out
+            .next_binding_compute(binding_glsl::buffer(false)) // histogram
+            .create(device, "BindGroupLayout: Radix sort");
+        let bind_groups = [
+            BindGroupBuilder::new(&group_layout)
+                .resource(keys.as_entire_binding())
+                .resource(values.as_entire_binding())
+                .resource(keys_tmp.as_entire_binding())
+                .resource(values_tmp.as_entire_binding())
+                .resource(histogram.as_entire_binding())
+                .create(device, "BindGroup: Radix sort 0"),
+            BindGroupBuilder::new(&group_layout)
+                .resource(keys_tmp.as_entire_binding())
+                .resource(values_tmp.as_entire_binding())
+                .resource(keys.as_entire_binding())
+                .resource(values.as_entire_binding())
+                .resource(histogram.as_entire_binding())
+                .create(device, "BindGroup: Radix sort 1"),
+        ];
+
+        let layout = pipeline_manager.create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Radix Sort Pipeline Layout"),
+                bind_group_layouts: &[&group_layout.layout],
+                push_constant_ranges: &[wgpu::PushConstantRange {
+                    stages: wgpu::ShaderStage::COMPUTE,
+                    range: 0..8,
+                }],
+            },
+        );
+
+        let shader_path = Path::new("simulation/radix_sort");
+
+        RadixSort {
+            num_workgroups,
+            num_passes,
+
+            keys,
+            values,
+            bind_groups,
+
+            pipeline_histogram: pipeline_manager.create_compute_pipeline(
+                device,
+                shader_dir,
+                ComputePipelineCreationDesc::new(
+                    "RadixSort: Histogram",
+                    layout.clone(),
+                    &shader_path.join(Path::new("radix_sort_histogram.comp")),
+                ),
+            ),
+            pipeline_scan: pipeline_manager.create_compute_pipeline(
+                device,
+                shader_dir,
+                ComputePipelineCreationDesc::new("RadixSort: Scan", layout.clone(), &shader_path.join(Path::new("radix_sort_scan.comp"))),
+            ),
+            pipeline_scatter: pipeline_manager.create_compute_pipeline(
+                device,
+                shader_dir,
+                ComputePipelineCreationDesc::new(
+                    "RadixSort: Scatter",
+                    layout.clone(),
+                    &shader_path.join(Path::new("radix_sort_scatter.comp")),
+                ),
+            ),
+        }
+    }
+
+    // Number of keys including padding, everything in the key buffer needs to be filled before sorting.
+    pub fn num_padded_keys(&self) -> u32 {
+        self.num_workgroups * Self::LOCAL_SIZE
+    }
+
+    pub fn keys(&self) -> &wgpu::Buffer {
+        &self.keys
+    }
+
+    pub fn values(&self) -> &wgpu::Buffer {
+        &self.values
+    }
+
+    pub fn sort<'a>(&'a self, cpass: &mut wgpu::ComputePass<'a>, pipeline_manager: &'a PipelineManager) {
+        for pass in 0..self.num_passes {
+            cpass.set_bind_group(0, &self.bind_groups[(pass % 2) as usize], &[]);
+            let push_constants = [pass * Self::BITS_PER_PASS, self.num_workgroups];
+
+            cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_histogram));
+            pipeline_manager.set_push_constants_compute(cpass, &self.pipeline_histogram, 0, bytemuck::bytes_of(&push_constants));
+            cpass.dispatch(self.num_workgroups, 1, 1);
+
+            cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_scan));
+            pipeline_manager.set_push_constants_compute(cpass, &self.pipeline_scan, 0, bytemuck::bytes_of(&push_constants));
+            cpass.dispatch(1, 1, 1);
+
+            cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_scatter));
+            pipeline_manager.set_push_constants_compute(cpass, &self.pipeline_scatter, 0, bytemuck::bytes_of(&push_constants));
+            cpass.dispatch(self.num_workgroups, 1, 1);
+        }
+    }
+}
@@ -0,0 +1,448 @@
+use super::{pressure_solver::*, workgroup_sizes::WorkgroupSizes};
+use crate::{
+    scene::{voxelization::SceneVoxelization, SmokeConfig},
+    wgpu_utils::{self, binding_builder::*, binding_glsl, pipelines::*, profiling::ProfilingDetail, shader::*, uniformbuffer::*},
+};
+use std::{collections::VecDeque, path::Path, sync::Arc, time::Duration};
+use wgpu_profiler::GpuProfiler;
+
+// Needs to be kept in sync with MAX_NUM_SMOKE_EMITTERS in smoke.glsl
+pub const MAX_NUM_SMOKE_EMITTERS: usize = 4;
+
+// Box in grid space that continuously emits smoke. See SmokeEmitter struct in smoke.glsl
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct SmokeEmitterUniformBufferContent {
+    min: cgmath::Point3<f32>,
+    padding0: f32,
+    max: cgmath::Point3<f32>,
+    padding1: f32,
+    velocity: cgmath::Vector3<f32>,
+    padding2: f32,
+}
+unsafe impl bytemuck::Pod for SmokeEmitterUniformBufferContent {}
+unsafe impl bytemuck::Zeroable for SmokeEmitterUniformBufferContent {}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct SmokePropertiesUniformBufferContent {
+    gravity_grid: cgmath::Vector3<f32>,
+    num_emitters: u32,
+    buoyancy: f32,
+    density_weight: f32,
+    ambient_temperature: f32,
+    vorticity_confinement: f32,
+    source_density: f32,
+    source_temperature: f32,
+    dissipation: f32,
+    padding: f32,
+    emitters: [SmokeEmitterUniformBufferContent; MAX_NUM_SMOKE_EMITTERS],
+}
+unsafe impl bytemuck::Pod for SmokePropertiesUniformBufferContent {}
+unsafe impl bytemuck::Zeroable for SmokePropertiesUniformBufferContent {}
+
+// Purely grid based (Eulerian) smoke simulation.
+//
+// Smoke density & temperature are advected semi-lagrangian along with the (staggered) velocity grid.
+// Hot smoke rises, dense smoke sinks and vorticity confinement brings back some of the swirls that are lost to numerical dissipation.
+// Everything but the top layer of the domain is fluid, the top is left open (air) so the pressure solve is well defined and smoke can leave.
+pub struct SmokeSimulation {
+    grid_dimension: wgpu::Extent3d,
+    workgroup_sizes: WorkgroupSizes,
+
+    pressure_solver: PressureSolver,
+    pressure_field: PressureField,
+
+    volume_smoke_view: wgpu::TextureView,
+
+    properties_uniformbuffer: UniformBuffer<SmokePropertiesUniformBufferContent>,
+    properties: SmokePropertiesUniformBufferContent,
+
+    bind_group_general: wgpu::BindGroup,
+    bind_group_set_marker: wgpu::BindGroup,
+    bind_group_advect: wgpu::BindGroup,
+    bind_group_curl: wgpu::BindGroup,
+    bind_group_apply_forces: wgpu::BindGroup,
+    bind_group_divergence: wgpu::BindGroup,
+    bind_group_project: wgpu::BindGroup,
+
+    pipeline_set_marker: ComputePipelineHandle,
+    pipeline_advect: ComputePipelineHandle,
+    pipeline_curl: ComputePipelineHandle,
+    pipeline_apply_forces: ComputePipelineHandle,
+    pipeline_divergence: ComputePipelineHandle,
+    pipeline_project: ComputePipelineHandle,
+}
+
+impl SmokeSimulation {
+    // Format of the smoke volume, density in red, temperature in green.
+    // (half precision is plenty and unlike 32bit float formats can be filtered when rendering)
+    pub const FORMAT_SMOKE: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+    pub fn new(
+        device: &wgpu::Device,
+        grid_dimension: wgpu::Extent3d,
+        shader_dir: &ShaderDirectory,
+        pipeline_manager: &mut PipelineManager,
+        global_bind_group_layout: &wgpu::BindGroupLayout,
+        voxelization: &SceneVoxelization,
+    ) -> Self {
+        let create_volume = |label: &'static str, format: wgpu::TextureFormat| -> wgpu::Texture {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size: grid_dimension,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D3,
+                format,
+                usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::STORAGE | wgpu::TextureUsage::COPY_DST,
+            })
+        };
+
+        // Resources
+        let properties_uniformbuffer = UniformBuffer::new(device);
+
+        // Advection reads the velocity & smoke volumes and writes to the temporary ones, applying forces writes back.
+        let volume_velocity = [
+            create_volume("Smoke Velocity Volume X", wgpu::TextureFormat::R32Float),
+            create_volume("Smoke Velocity Volume Y", wgpu::TextureFormat::R32Float),
+            create_volume("Smoke Velocity Volume Z", wgpu::TextureFormat::R32Float),
+        ];
+        let volume_velocity_tmp = [
+            create_volume("Smoke Velocity Volume X tmp", wgpu::TextureFormat::R32Float),
+            create_volume("Smoke Velocity Volume Y tmp", wgpu::TextureFormat::R32Float),
+            create_volume("Smoke Velocity Volume Z tmp", wgpu::TextureFormat::R32Float),
+        ];
+        let volume_smoke = create_volume("Smoke Volume", Self::FORMAT_SMOKE);
+        let volume_smoke_tmp = create_volume("Smoke Volume tmp", Self::FORMAT_SMOKE);
+        let volume_curl = create_volume("Smoke Curl Volume", wgpu::TextureFormat::Rgba32Float);
+        let volume_marker = create_volume("Smoke Marker Grid", wgpu::TextureFormat::R8Snorm);
+        // Smoke has the same density everywhere, but the pressure solver supports varying densities.
+        let volume_density = create_volume("Smoke Density Volume", wgpu::TextureFormat::R32Float);
+
+        // Resource views
+        let volume_velocity_views: Vec<wgpu::TextureView> = volume_velocity.iter().map(|v| v.create_view(&Default::default())).collect();
+        let volume_velocity_tmp_views: Vec<wgpu::TextureView> = volume_velocity_tmp.iter().map(|v| v.create_view(&Default::default())).collect();
+        let volume_smoke_view = volume_smoke.create_view(&Default::default());
+        let volume_smoke_tmp_view = volume_smoke_tmp.create_view(&Default::default());
+        let volume_curl_view = volume_curl.create_view(&Default::default());
+        let volume_marker_view = volume_marker.create_view(&Default::default());
+        let volume_density_view = volume_density.create_view(&Default::default());
+
+        let image_r32f = || binding_glsl::image3D(wgpu::TextureFormat::R32Float, wgpu::StorageTextureAccess::ReadWrite);
+
+        // Layouts
+        let group_layout_general = BindGroupLayoutBuilder::new()
+            .next_binding_compute(binding_glsl::uniform())
+            .next_binding_compute(binding_glsl::texture3D()) // scene voxelization
+            .create(device, "BindGroupLayout: Smoke Uniform");
+        let group_layout_set_marker = BindGroupLayoutBuilder::new()
+            .next_binding_compute(binding_glsl::image3D(wgpu::TextureFormat::R8Snorm, wgpu::StorageTextureAccess::ReadWrite)) // marker volume
+            .next_binding_compute(image_r32f()) // density volume
+            .create(device, "BindGroupLayout: Smoke set marker");
+        let group_layout_advect = BindGroupLayoutBuilder::new()
+            .next_binding_compute(binding_glsl::texture3D()) // velocityX
+            .next_binding_compute(binding_glsl::texture3D()) // velocityY
+            .next_binding_compute(binding_glsl::texture3D()) // velocityZ
+            .next_binding_compute(binding_glsl::texture3D()) // smoke
+            .next_binding_compute(image_r32f()) // advected velocityX
+            .next_binding_compute(image_r32f()) // advected velocityY
+            .next_binding_compute(image_r32f()) // advected velocityZ
+            .next_binding_compute(binding_glsl::image3D(Self::FORMAT_SMOKE, wgpu::StorageTextureAccess::ReadWrite)) // advected smoke
+            .create(device, "BindGroupLayout: Smoke advect");
+        let group_layout_curl = BindGroupLayoutBuilder::new()
+            .next_binding_compute(binding_glsl::texture3D()) // velocityX
+            .next_binding_compute(binding_glsl::texture3D()) // velocityY
+            .next_binding_compute(binding_glsl::texture3D()) // velocityZ
+            .next_binding_compute(binding_glsl::image3D(
+                wgpu::TextureFormat::Rgba32Float,
+                wgpu::StorageTextureAccess::ReadWrite,
+            )) // curl
+            .create(device, "BindGroupLayout: Smoke curl");
+        let group_layout_apply_forces = BindGroupLayoutBuilder::new()
+            .next_binding_compute(binding_glsl::texture3D()) // marker volume
+            .next_binding_compute(binding_glsl::texture3D()) // advected velocityX
+            .next_binding_compute(binding_glsl::texture3D()) // advected velocityY
+            .next_binding_compute(binding_glsl::texture3D()) // advected velocityZ
+            .next_binding_compute(binding_glsl::texture3D()) // advected smoke
+            .next_binding_compute(binding_glsl::texture3D()) // curl
+            .next_binding_compute(image_r32f()) // velocityX
+            .next_binding_compute(image_r32f()) // velocityY
+            .next_binding_compute(image_r32f()) // velocityZ
+            .next_binding_compute(binding_glsl::image3D(Self::FORMAT_SMOKE, wgpu::StorageTextureAccess::ReadWrite)) // smoke
+            .create(device, "BindGroupLayout: Smoke apply forces");
+        let group_layout_divergence = BindGroupLayoutBuilder::new()
+            .next_binding_compute(binding_glsl::texture3D()) // marker volume
+            .next_binding_compute(binding_glsl::texture3D()) // velocityX
+            .next_binding_compute(binding_glsl::texture3D()) // velocityY
+            .next_binding_compute(binding_glsl::texture3D()) // velocityZ
+            .next_binding_compute(image_r32f()) // divergence / initial residual
+            .create(device, "BindGroupLayout: Smoke divergence");
+        let group_layout_project = BindGroupLayoutBuilder::new()
+            .next_binding_compute(binding_glsl::texture3D()) // marker volume
+            .next_binding_compute(image_r32f()) // velocityX
+            .next_binding_compute(image_r32f()) // velocityY
+            .next_binding_compute(image_r32f()) // velocityZ
+            .next_binding_compute(binding_glsl::texture3D()) // pressure
+            .create(device, "BindGroupLayout: Smoke project");
+
+        let pressure_solver = PressureSolver::new(
+            device,
+            grid_dimension,
+            shader_dir,
+            pipeline_manager,
+            &volume_marker_view,
+            &volume_density_view,
+        );
+        let pressure_field = PressureField::new(
+            "smoke",
+            device,
+            grid_dimension,
+            &pressure_solver,
+            SolverConfig {
+                error_tolerance: 0.1,
+                error_check_frequency: 4,
+                max_num_iterations: 32,
+            },
+        );
+
+        // Bind groups
+        let bind_group_general = BindGroupBuilder::new(&group_layout_general)
+            .resource(properties_uniformbuffer.binding_resource())
+            .texture(voxelization.texture_view())
+            .create(device, "BindGroup: Smoke Uniform");
+        let bind_group_set_marker = BindGroupBuilder::new(&group_layout_set_marker)
+            .texture(&volume_marker_view)
+            .texture(&volume_density_view)
+            .create(device, "BindGroup: Smoke set marker");
+        let bind_group_advect = BindGroupBuilder::new(&group_layout_advect)
+            .texture(&volume_velocity_views[0])
+            .texture(&volume_velocity_views[1])
+            .texture(&volume_velocity_views[2])
+            .texture(&volume_smoke_view)
+            .texture(&volume_velocity_tmp_views[0])
+            .texture(&volume_velocity_tmp_views[1])
+            .texture(&volume_velocity_tmp_views[2])
+            .texture(&volume_smoke_tmp_view)
+            .create(device, "BindGroup: Smoke advect");
+        let bind_group_curl = BindGroupBuilder::new(&group_layout_curl)
+            .texture(&volume_velocity_tmp_views[0])
+            .texture(&volume_velocity_tmp_views[1])
+            .texture(&volume_velocity_tmp_views[2])
+            .texture(&volume_curl_view)
+            .create(device, "BindGroup: Smoke curl");
+        let bind_group_apply_forces = BindGroupBuilder::new(&group_layout_apply_forces)
+            .texture(&volume_marker_view)
+            .texture(&volume_velocity_tmp_views[0])
+            .texture(&volume_velocity_tmp_views[1])
+            .texture(&volume_velocity_tmp_views[2])
+            .texture(&volume_smoke_tmp_view)
+            .texture(&volume_curl_view)
+            .texture(&volume_velocity_views[0])
+            .texture(&volume_velocity_views[1])
+            .texture(&volume_velocity_views[2])
+            .texture(&volume_smoke_view)
+            .create(device, "BindGroup: Smoke apply forces");
+        let bind_group_divergence = BindGroupBuilder::new(&group_layout_divergence)
+            .texture(&volume_marker_view)
+            .texture(&volume_velocity_views[0])
+            .texture(&volume_velocity_views[1])
+            .texture(&volume_velocity_views[2])
+            .texture(pressure_solver.residual_view())
+            .create(device, "BindGroup: Smoke divergence");
+        let bind_group_project = BindGroupBuilder::new(&group_layout_project)
+            .texture(&volume_marker_view)
+            .texture(&volume_velocity_views[0])
+            .texture(&volume_velocity_views[1])
+            .texture(&volume_velocity_views[2])
+            .texture(pressure_field.pressure_view())
+            .create(device, "BindGroup: Smoke project");
+
+        // None of the smoke shaders use push constants, so there is no need for them to be emulated on devices without support.
+        let create_pipeline_layout = |label, group_layout: &BindGroupLayoutWithDesc| {
+            Arc::new(device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some(label),
+                bind_group_layouts: &[global_bind_group_layout, &group_layout_general.layout, &group_layout.layout],
+                push_constant_ranges: &[],
+            }))
+        };
+
+        let shader_path = Path::new("simulation/smoke");
+        let mut create_pipeline = |label, group_layout: &BindGroupLayoutWithDesc, shader: &str| {
+            pipeline_manager.create_compute_pipeline(
+                device,
+                shader_dir,
+                ComputePipelineCreationDesc::new(label, create_pipeline_layout(label, group_layout), &shader_path.join(shader)),
+            )
+        };
+
+        let pipeline_set_marker = create_pipeline("Smoke: Set marker", &group_layout_set_marker, "set_marker.comp");
+        let pipeline_advect = create_pipeline("Smoke: Advect", &group_layout_advect, "advect.comp");
+        let pipeline_curl = create_pipeline("Smoke: Curl", &group_layout_curl, "curl.comp");
+        let pipeline_apply_forces = create_pipeline("Smoke: Apply forces", &group_layout_apply_forces, "apply_forces.comp");
+        let pipeline_divergence = create_pipeline("Smoke: Divergence", &group_layout_divergence, "divergence.comp");
+        let pipeline_project = create_pipeline("Smoke: Project", &group_layout_project, "project.comp");
+
+        let mut properties: SmokePropertiesUniformBufferContent = bytemuck::Zeroable::zeroed();
+        properties.gravity_grid = cgmath::vec3(0.0, -9.81, 0.0);
+
+        SmokeSimulation {
+            grid_dimension,
+            workgroup_sizes: WorkgroupSizes::from_shader_dir(shader_dir),
+
+            pressure_solver,
+            pressure_field,
+
+            volume_smoke_view,
+
+            properties_uniformbuffer,
+            properties,
+
+            bind_group_general,
+            bind_group_set_marker,
+            bind_group_advect,
+            bind_group_curl,
+            bind_group_apply_forces,
+            bind_group_divergence,
+            bind_group_project,
+
+            pipeline_set_marker,
+            pipeline_advect,
+            pipeline_curl,
+            pipeline_apply_forces,
+            pipeline_divergence,
+            pipeline_project,
+        }
+    }
+
+    pub fn set_gravity_grid(&mut self, gravity: cgmath::Vector3<f32>) {
+        self.properties.gravity_grid = gravity;
+    }
+
+    pub fn set_config(&mut self, config: &SmokeConfig) {
+        self.properties.buoyancy = config.buoyancy;
+        self.properties.density_weight = config.density_weight;
+        self.properties.ambient_temperature = config.ambient_temperature;
+        self.properties.source_density = config.source_density;
+        self.properties.source_temperature = config.source_temperature;
+        self.properties.vorticity_confinement = config.vorticity_confinement;
+        self.properties.dissipation = config.dissipation;
+    }
+
+    // Adds a box (in grid space) that continuously emits smoke with the given velocity.
+    pub fn add_emitter(&mut self, min_grid: cgmath::Point3<f32>, max_grid: cgmath::Point3<f32>, velocity_grid: cgmath::Vector3<f32>) {
+        let num_emitters = self.properties.num_emitters as usize;
+        if num_emitters == MAX_NUM_SMOKE_EMITTERS {
+            warn!(
+                "Only {} smoke emitters are supported, ignoring the remaining ones",
+                MAX_NUM_SMOKE_EMITTERS
+            );
+            return;
+        }
+        self.properties.emitters[num_emitters] = SmokeEmitterUniformBufferContent {
+            min: min_grid,
+            padding0: 0.0,
+            max: max_grid,
+            padding1: 0.0,
+            velocity: velocity_grid,
+            padding2: 0.0,
+        };
+        self.properties.num_emitters += 1;
+    }
+
+    pub fn grid_dimension(&self) -> wgpu::Extent3d {
+        self.grid_dimension
+    }
+
+    // Smoke density in the red channel, temperature in green.
+    pub fn smoke_view(&self) -> &wgpu::TextureView {
+        &self.volume_smoke_view
+    }
+
+    pub fn pressure_solver_config(&mut self) -> &mut SolverConfig {
+        &mut self.pressure_field.config
+    }
+
+    pub fn pressure_solver_stats(&self) -> &VecDeque<SolverStatisticSample> {
+        &self.pressure_field.stats
+    }
+
+    pub fn update_statistics(&mut self) {
+        self.pressure_field.start_error_buffer_readbacks();
+    }
+
+    // Performs an entire simulation step.
+    pub fn step(
+        &mut self,
+        simulation_delta: Duration,
+        encoder: &mut wgpu::CommandEncoder,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        global_bind_group: &wgpu::BindGroup,
+        pipeline_manager: &PipelineManager,
+        profiler: &mut GpuProfiler,
+        profiling_detail: ProfilingDetail,
+    ) {
+        profiling_scope!(Coarse, profiling_detail, "update uniforms", profiler, encoder, device, {
+            self.pressure_field.update_uniforms(queue, simulation_delta);
+            self.properties_uniformbuffer.update_content(queue, self.properties);
+        });
+
+        let grid_work_groups = wgpu_utils::compute_group_size(self.grid_dimension, self.workgroup_sizes.volume_extent());
+
+        profiling_scope!(Coarse, profiling_detail, "advect & apply forces", profiler, encoder, device, {
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("smoke advect & apply forces"),
+            });
+            cpass.set_bind_group(0, global_bind_group, &[]);
+            cpass.set_bind_group(1, &self.bind_group_general, &[]);
+
+            let passes = [
+                ("set marker", &self.pipeline_set_marker, &self.bind_group_set_marker),
+                ("advect velocity & smoke", &self.pipeline_advect, &self.bind_group_advect),
+                ("compute curl", &self.pipeline_curl, &self.bind_group_curl),
+                ("apply forces & emitters", &self.pipeline_apply_forces, &self.bind_group_apply_forces),
+                // Writes directly into Residual of the pressure solver.
+                ("compute divergence", &self.pipeline_divergence, &self.bind_group_divergence),
+            ];
+            for &(label, pipeline, bind_group) in passes.iter() {
+                profiling_scope!(Fine, profiling_detail, label, profiler, &mut cpass, device, {
+                    cpass.set_pipeline(pipeline_manager.get_compute(pipeline));
+                    cpass.set_bind_group(2, bind_group, &[]);
+                    cpass.dispatch(grid_work_groups.width, grid_work_groups.height, grid_work_groups.depth_or_array_layers);
+                });
+            }
+        });
+
+        profiling_scope!(Coarse, profiling_detail, "pressure solver", profiler, encoder, device, {
+            self.pressure_solver.solve(
+                simulation_delta,
+                encoder,
+                device,
+                &mut self.pressure_field,
+                pipeline_manager,
+                profiler,
+                profiling_detail,
+            );
+        });
+
+        profiling_scope!(
+            Coarse,
+            profiling_detail,
+            "make velocity grid divergence free",
+            profiler,
+            encoder,
+            device,
+            {
+                let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: Some("smoke project"),
+                });
+                cpass.set_bind_group(0, global_bind_group, &[]);
+                cpass.set_bind_group(1, &self.bind_group_general, &[]);
+                cpass.set_bind_group(2, &self.bind_group_project, &[]);
+                cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline_project));
+                cpass.dispatch(grid_work_groups.width, grid_work_groups.height, grid_work_groups.depth_or_array_layers);
+            }
+        );
+    }
+}
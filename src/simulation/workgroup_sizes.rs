@@ -0,0 +1,377 @@
+use crate::wgpu_utils::{
+    self,
+    binding_builder::{BindGroupBuilder, BindGroupLayoutBuilder},
+    binding_glsl,
+    shader::{ShaderDirectory, SHADER_ENTRY_POINT_NAME},
+};
+use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::BufReader,
+    path::Path,
+    time::{Duration, Instant},
+};
+use wgpu::util::DeviceExt;
+
+// Local sizes of the main simulation passes.
+// Passed to all shaders as defines (see simulation/workgroup_sizes.glsl), so they can be tuned for the adapter at hand.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WorkgroupSizes {
+    pub particles: u32,
+    pub volume: [u32; 3],
+    pub pressure: [u32; 2], // z is always 1, pressure solver passes run on slices
+}
+
+impl Default for WorkgroupSizes {
+    fn default() -> Self {
+        WorkgroupSizes {
+            particles: 64,
+            volume: [8, 8, 8],
+            pressure: [8, 8],
+        }
+    }
+}
+
+const DEFINE_PARTICLES: &str = "LOCAL_SIZE_PARTICLES";
+const DEFINES_VOLUME: [&str; 3] = ["LOCAL_SIZE_VOLUME_X", "LOCAL_SIZE_VOLUME_Y", "LOCAL_SIZE_VOLUME_Z"];
+const DEFINES_PRESSURE: [&str; 2] = ["LOCAL_SIZE_PRESSURE_X", "LOCAL_SIZE_PRESSURE_Y"];
+
+impl WorkgroupSizes {
+    // Sizes the shaders of a shader directory are compiled with.
+    pub fn from_shader_dir(shader_dir: &ShaderDirectory) -> Self {
+        let default = Self::default();
+        let get = |name: &str, default: u32| shader_dir.define(name).and_then(|value| value.parse().ok()).unwrap_or(default);
+        WorkgroupSizes {
+            particles: get(DEFINE_PARTICLES, default.particles),
+            volume: [
+                get(DEFINES_VOLUME[0], default.volume[0]),
+                get(DEFINES_VOLUME[1], default.volume[1]),
+                get(DEFINES_VOLUME[2], default.volume[2]),
+            ],
+            pressure: [
+                get(DEFINES_PRESSURE[0], default.pressure[0]),
+                get(DEFINES_PRESSURE[1], default.pressure[1]),
+            ],
+        }
+    }
+
+    // All shaders compiled from now on use these sizes.
+    pub fn apply_to(&self, shader_dir: &mut ShaderDirectory) {
+        for (name, value) in self.defines() {
+            shader_dir.set_define(name, value);
+        }
+    }
+
+    fn defines(&self) -> Vec<(&'static str, String)> {
+        let mut defines = vec![(DEFINE_PARTICLES, self.particles.to_string())];
+        defines.extend(
+            DEFINES_VOLUME
+                .iter()
+                .zip(self.volume.iter())
+                .map(|(name, size)| (*name, size.to_string())),
+        );
+        defines.extend(
+            DEFINES_PRESSURE
+                .iter()
+                .zip(self.pressure.iter())
+                .map(|(name, size)| (*name, size.to_string())),
+        );
+        defines
+    }
+
+    pub fn volume_extent(&self) -> wgpu::Extent3d {
+        wgpu::Extent3d {
+            width: self.volume[0],
+            height: self.volume[1],
+            depth_or_array_layers: self.volume[2],
+        }
+    }
+
+    pub fn pressure_extent(&self) -> wgpu::Extent3d {
+        wgpu::Extent3d {
+            width: self.pressure[0],
+            height: self.pressure[1],
+            depth_or_array_layers: 1,
+        }
+    }
+
+    // Returns the sizes previously determined for this adapter. If there are none yet (or if forced), runs the autotuning and stores the result.
+    pub fn load_or_autotune(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        shader_dir: &ShaderDirectory,
+        adapter_info: &wgpu::AdapterInfo,
+        results_path: &Path,
+        force_autotune: bool,
+    ) -> Self {
+        let mut results: HashMap<String, WorkgroupSizes> = File::open(results_path)
+            .ok()
+            .and_then(|file| serde_json::from_reader(BufReader::new(file)).ok())
+            .unwrap_or_default();
+        let adapter_key = format!(
+            "{} ({:04x}:{:04x}, {:?})",
+            adapter_info.name, adapter_info.vendor, adapter_info.device, adapter_info.backend
+        );
+        if !force_autotune {
+            if let Some(sizes) = results.get(&adapter_key) {
+                info!("Using workgroup sizes from previous autotuning: {:?}", sizes);
+                return *sizes;
+            }
+        }
+
+        info!("Autotuning workgroup sizes for {}", adapter_key);
+        let start_time = Instant::now();
+        let sizes = AutotuneBenchmark::new(device).run(device, queue, shader_dir);
+        info!("Autotuning took {:?}, picked {:?}", start_time.elapsed(), sizes);
+
+        results.insert(adapter_key, sizes);
+        match File::create(results_path) {
+            Ok(file) => {
+                if let Err(err) = serde_json::to_writer_pretty(file, &results) {
+                    error!("Failed to write workgroup autotuning results to {:?}: {}", results_path, err);
+                }
+            }
+            Err(err) => error!("Failed to create workgroup autotuning results file {:?}: {}", results_path, err),
+        }
+        sizes
+    }
+}
+
+// Synthetic stand-ins for the simulation passes, see workgroup_autotune.comp
+// Running the actual passes would require a full scene, but what matters most here is the access pattern & occupancy.
+struct AutotuneBenchmark {
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+}
+
+impl AutotuneBenchmark {
+    const GRID_SIZE: wgpu::Extent3d = wgpu::Extent3d {
+        width: 128,
+        height: 64,
+        depth_or_array_layers: 64,
+    };
+    const PARTICLES_PER_CELL: u32 = 4;
+    const NUM_DISPATCHES_PER_MEASUREMENT: u32 = 16;
+    const NUM_MEASUREMENTS: u32 = 5;
+
+    const CANDIDATES_PARTICLES: [u32; 4] = [32, 64, 128, 256];
+    const CANDIDATES_VOLUME: [[u32; 3]; 5] = [[8, 8, 8], [8, 8, 4], [16, 4, 4], [4, 4, 4], [32, 4, 2]];
+    const CANDIDATES_PRESSURE: [[u32; 2]; 5] = [[8, 8], [16, 8], [16, 16], [32, 4], [32, 8]];
+
+    fn num_particles() -> u32 {
+        Self::GRID_SIZE.width * Self::GRID_SIZE.height * Self::GRID_SIZE.depth_or_array_layers * Self::PARTICLES_PER_CELL
+    }
+
+    fn new(device: &wgpu::Device) -> Self {
+        let bind_group_layout = BindGroupLayoutBuilder::new()
+            .next_binding_compute(binding_glsl::texture3D())
+            .next_binding_compute(binding_glsl::image3D(
+                wgpu::TextureFormat::R32Float,
+                wgpu::StorageTextureAccess::WriteOnly,
+            ))
+            .next_binding_compute(binding_glsl::buffer(true))
+            .next_binding_compute(binding_glsl::buffer(false))
+            .create(device, "BindGroupLayout: Workgroup autotuning");
+
+        let create_volume = |label, usage| {
+            device
+                .create_texture(&wgpu::TextureDescriptor {
+                    label: Some(label),
+                    size: Self::GRID_SIZE,
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D3,
+                    format: wgpu::TextureFormat::R32Float,
+                    usage,
+                })
+                .create_view(&Default::default())
+        };
+        let source_volume = create_volume("Texture: Workgroup autotuning source", wgpu::TextureUsage::SAMPLED);
+        let dest_volume = create_volume("Texture: Workgroup autotuning dest", wgpu::TextureUsage::STORAGE);
+
+        // Particles are binned by cell in the simulation, so consecutive particles are close to each other.
+        let mut rng = rand::thread_rng();
+        let particles: Vec<[f32; 4]> = (0..Self::num_particles())
+            .map(|index| {
+                let cell = index / Self::PARTICLES_PER_CELL;
+                let x = cell % Self::GRID_SIZE.width;
+                let y = cell / Self::GRID_SIZE.width % Self::GRID_SIZE.height;
+                let z = cell / Self::GRID_SIZE.width / Self::GRID_SIZE.height;
+                [x as f32 + rng.gen::<f32>(), y as f32 + rng.gen::<f32>(), z as f32 + rng.gen::<f32>(), 0.0]
+            })
+            .collect();
+        let source_particles = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Buffer: Workgroup autotuning source particles"),
+            contents: bytemuck::cast_slice(&particles),
+            usage: wgpu::BufferUsage::STORAGE,
+        });
+        let dest_particles = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Buffer: Workgroup autotuning dest particles"),
+            size: (particles.len() * std::mem::size_of::<[f32; 4]>()) as u64,
+            usage: wgpu::BufferUsage::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        let bind_group = BindGroupBuilder::new(&bind_group_layout)
+            .texture(&source_volume)
+            .texture(&dest_volume)
+            .resource(source_particles.as_entire_binding())
+            .resource(dest_particles.as_entire_binding())
+            .create(device, "BindGroup: Workgroup autotuning");
+
+        AutotuneBenchmark {
+            bind_group_layout: bind_group_layout.layout,
+            bind_group,
+        }
+    }
+
+    fn run(&self, device: &wgpu::Device, queue: &wgpu::Queue, shader_dir: &ShaderDirectory) -> WorkgroupSizes {
+        let default = WorkgroupSizes::default();
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Workgroup autotuning pipeline layout"),
+            bind_group_layouts: &[&self.bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let particles = self.fastest(
+            device,
+            queue,
+            shader_dir,
+            &pipeline_layout,
+            "AUTOTUNE_PARTICLES",
+            &Self::CANDIDATES_PARTICLES,
+            default.particles,
+            |&size| vec![(DEFINE_PARTICLES, size.to_string())],
+            |&size| [wgpu_utils::compute_group_size_1d(Self::num_particles(), size), 1, 1],
+        );
+        let volume = self.fastest(
+            device,
+            queue,
+            shader_dir,
+            &pipeline_layout,
+            "AUTOTUNE_VOLUME",
+            &Self::CANDIDATES_VOLUME,
+            default.volume,
+            |size| {
+                DEFINES_VOLUME
+                    .iter()
+                    .zip(size.iter())
+                    .map(|(name, size)| (*name, size.to_string()))
+                    .collect()
+            },
+            |size| {
+                let work_groups = wgpu_utils::compute_group_size(
+                    Self::GRID_SIZE,
+                    wgpu::Extent3d {
+                        width: size[0],
+                        height: size[1],
+                        depth_or_array_layers: size[2],
+                    },
+                );
+                [work_groups.width, work_groups.height, work_groups.depth_or_array_layers]
+            },
+        );
+        let pressure = self.fastest(
+            device,
+            queue,
+            shader_dir,
+            &pipeline_layout,
+            "AUTOTUNE_PRESSURE",
+            &Self::CANDIDATES_PRESSURE,
+            default.pressure,
+            |size| {
+                DEFINES_PRESSURE
+                    .iter()
+                    .zip(size.iter())
+                    .map(|(name, size)| (*name, size.to_string()))
+                    .collect()
+            },
+            |size| {
+                let work_groups = wgpu_utils::compute_group_size(
+                    Self::GRID_SIZE,
+                    wgpu::Extent3d {
+                        width: size[0],
+                        height: size[1],
+                        depth_or_array_layers: 1,
+                    },
+                );
+                [work_groups.width, work_groups.height, work_groups.depth_or_array_layers]
+            },
+        );
+
+        WorkgroupSizes { particles, volume, pressure }
+    }
+
+    // Measures all candidates and returns the fastest. Candidates that fail to compile or create a pipeline are skipped.
+    fn fastest<T: Copy + std::fmt::Debug>(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        shader_dir: &ShaderDirectory,
+        pipeline_layout: &wgpu::PipelineLayout,
+        kernel: &'static str,
+        candidates: &[T],
+        default: T,
+        defines: impl Fn(&T) -> Vec<(&'static str, String)>,
+        work_groups: impl Fn(&T) -> [u32; 3],
+    ) -> T {
+        let mut fastest: Option<(T, Duration)> = None;
+        for candidate in candidates {
+            let mut candidate_defines = defines(candidate);
+            candidate_defines.push((kernel, "1".to_owned()));
+            let shader = match shader_dir.load_shader_module_with_defines(device, Path::new("workgroup_autotune.comp"), &candidate_defines) {
+                Ok(shader) => shader,
+                Err(()) => continue,
+            };
+
+            device.push_error_scope(wgpu::ErrorFilter::Validation);
+            let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Workgroup autotuning"),
+                layout: Some(pipeline_layout),
+                module: &shader.module,
+                entry_point: SHADER_ENTRY_POINT_NAME,
+            });
+            if let Some(err) = futures::executor::block_on(device.pop_error_scope()) {
+                warn!("Skipping workgroup size {:?} for {}: {}", candidate, kernel, err);
+                continue;
+            }
+
+            let work_groups = work_groups(candidate);
+            let duration = (0..Self::NUM_MEASUREMENTS)
+                .map(|_| {
+                    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                        label: Some("Encoder: Workgroup autotuning"),
+                    });
+                    {
+                        let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: Some(kernel) });
+                        cpass.set_pipeline(&pipeline);
+                        cpass.set_bind_group(0, &self.bind_group, &[]);
+                        for _ in 0..Self::NUM_DISPATCHES_PER_MEASUREMENT {
+                            cpass.dispatch(work_groups[0], work_groups[1], work_groups[2]);
+                        }
+                    }
+                    let start_time = Instant::now();
+                    queue.submit(Some(encoder.finish()));
+                    device.poll(wgpu::Maintain::Wait);
+                    start_time.elapsed()
+                })
+                .min()
+                .unwrap();
+            debug!("Workgroup size {:?} for {}: {:?}", candidate, kernel, duration);
+
+            if fastest.map_or(true, |(_, fastest_duration)| duration < fastest_duration) {
+                fastest = Some((*candidate, duration));
+            }
+        }
+
+        match fastest {
+            Some((candidate, _)) => candidate,
+            None => {
+                error!("No workgroup size candidate worked for {}, falling back to default", kernel);
+                default
+            }
+        }
+    }
+}
@@ -1,7 +1,10 @@
 use crate::scene::Scene;
 use crate::{
+    recording_schedule::RecordingSchedule,
+    renderdoc_capture::RenderDocCapture,
+    simulation::SimulationStage,
     timer::{SimulationStepResult, Timer},
-    wgpu_utils::pipelines::PipelineManager,
+    wgpu_utils::{fence_profiler::FenceProfiler, pipelines::PipelineManager, profiling::ProfilingDetail},
 };
 use std::time::{Duration, Instant};
 use wgpu_profiler::GpuProfiler;
@@ -16,13 +19,38 @@ pub enum SimulationControllerStatus {
     Paused,
 }
 
+// Bookkeeping of a fast forward that is in progress.
+struct FastForward {
+    start_time: Instant,
+    num_steps_total: u32,
+    num_steps_finished: u32,
+}
+
+pub struct FastForwardProgress {
+    pub num_steps_finished: u32,
+    pub num_steps_total: u32,
+    // None until the first chunk finished.
+    pub eta: Option<Duration>,
+}
+
 pub struct SimulationController {
     timer: Timer,
     computation_time_last_fast_forward: Duration,
+    fast_forward: Option<FastForward>,
     simulation_steps_per_second: u64,
     status: SimulationControllerStatus,
     pub simulation_stop_time: Duration,
     pub time_scale: f32,
+    // Time scale changes for the current recording, time_scale is ignored while recording.
+    recording_schedule: Option<RecordingSchedule>,
+    // Profiler scopes recorded within simulation steps.
+    pub profiling_detail: ProfilingDetail,
+
+    step_once_requested: bool,
+    // If enabled, stepping once while paused executes only a single stage of the simulation step.
+    pub sub_step_inspection: bool,
+    // Stage at which the current step is interrupted, None if we're in between steps.
+    next_sub_step_stage: Option<SimulationStage>,
 }
 
 // The maximum length of a single step we're willing to do in a single frame.
@@ -44,7 +72,13 @@ impl SimulationController {
             simulation_steps_per_second: DEFAULT_SIMULATION_STEPS_PER_SECOND,
             timer: Timer::new(delta_from_steps_per_second(DEFAULT_SIMULATION_STEPS_PER_SECOND)),
             computation_time_last_fast_forward: Default::default(),
+            fast_forward: None,
             time_scale: 1.0,
+            recording_schedule: None,
+            profiling_detail: ProfilingDetail::Fine,
+            step_once_requested: false,
+            sub_step_inspection: false,
+            next_sub_step_stage: None,
         }
     }
 
@@ -56,6 +90,11 @@ impl SimulationController {
         self.timer.on_frame_submitted(self.time_scale);
     }
 
+    // The application didn't render any frames for a while, don't try to catch up on that time.
+    pub fn on_resume_from_suspend(&mut self) {
+        self.timer.skip_time_since_last_frame();
+    }
+
     pub fn computation_time_last_fast_forward(&self) -> Duration {
         self.computation_time_last_fast_forward
     }
@@ -69,14 +108,51 @@ impl SimulationController {
     }
 
     pub fn pause_or_resume(&mut self) {
-        if self.status == SimulationControllerStatus::Paused {
+        if let SimulationControllerStatus::FastForward(..) = self.status {
+            self.cancel_fast_forward();
+        } else if self.status == SimulationControllerStatus::Paused {
             self.status = SimulationControllerStatus::Realtime;
         } else {
             self.status = SimulationControllerStatus::Paused;
         }
     }
 
-    pub fn start_recording_with_fixed_frame_length(&mut self, frames_per_second: f64) {
+    // Advances the simulation by a single step (or a single stage if sub_step_inspection is active) with the next frame.
+    // Only possible while paused.
+    pub fn step_once(&mut self) {
+        if self.status == SimulationControllerStatus::Paused {
+            self.step_once_requested = true;
+        }
+    }
+
+    // Time scale that applies to the current frame.
+    pub fn current_time_scale(&self) -> f32 {
+        match self.status {
+            SimulationControllerStatus::RecordingWithFixedFrameLength(..) => self.recording_time_scale(),
+            SimulationControllerStatus::FastForward(..) => 1.0,
+            SimulationControllerStatus::Realtime | SimulationControllerStatus::Paused => self.time_scale,
+        }
+    }
+
+    pub fn recording_schedule(&self) -> Option<&RecordingSchedule> {
+        self.recording_schedule.as_ref()
+    }
+
+    fn recording_time_scale(&self) -> f32 {
+        match &self.recording_schedule {
+            Some(schedule) => schedule.time_scale_at(self.timer.total_simulated_time()),
+            None => 1.0,
+        }
+    }
+
+    pub fn next_sub_step_stage(&self) -> Option<SimulationStage> {
+        self.next_sub_step_stage
+    }
+
+    // Without a schedule, every recorded frame advances the simulation by the same amount (i.e. time scale 1).
+    pub fn start_recording_with_fixed_frame_length(&mut self, frames_per_second: f64, schedule: Option<RecordingSchedule>) {
+        self.recording_schedule = schedule;
+        self.fast_forward = None;
         self.status = SimulationControllerStatus::RecordingWithFixedFrameLength(Duration::from_secs_f64(1.0 / frames_per_second));
     }
 
@@ -88,17 +164,79 @@ impl SimulationController {
 
     pub fn restart(&mut self) {
         self.timer = Timer::new(delta_from_steps_per_second(self.simulation_steps_per_second));
+        self.step_once_requested = false;
+        self.next_sub_step_stage = None;
+        self.cancel_fast_forward();
     }
 
     // A single fast forward operation is technically just a "very long frame".
     // However, since we need to give the GPU some breathing space it's handled in a different way (-> TDR).
     // Note that we assume that this never happens for realtime & recording, but it well could once a single simulation + render step takes longer than TDR time.
-    pub fn fast_forward_steps(
+    //
+    // The actual work is split up into chunks that the simulation thread processes in between frames (see SimulationThread),
+    // so the ui stays responsive, shows the progress and can cancel.
+    pub fn start_fast_forward(&mut self, simulation_jump_length: Duration) {
+        // jump at least one simulation step, makes for easier ui code
+        let num_steps_total =
+            (simulation_jump_length.max(self.timer.simulation_delta()).as_nanos() / self.timer.simulation_delta().as_nanos()) as u32;
+        // Like realtime stepping, fast forward doesn't go past the stop time.
+        let num_steps_total = num_steps_total.min(self.num_steps_until_stop_time()).max(1);
+
+        self.status = SimulationControllerStatus::FastForward(simulation_jump_length);
+        self.fast_forward = Some(FastForward {
+            start_time: Instant::now(),
+            num_steps_total,
+            num_steps_finished: 0,
+        });
+    }
+
+    pub fn cancel_fast_forward(&mut self) {
+        if let Some(fast_forward) = self.fast_forward.take() {
+            self.computation_time_last_fast_forward = fast_forward.start_time.elapsed();
+            info!(
+                "Fast forward canceled after {}/{} steps",
+                fast_forward.num_steps_finished, fast_forward.num_steps_total
+            );
+        }
+        if let SimulationControllerStatus::FastForward(..) = self.status {
+            self.status = SimulationControllerStatus::Paused;
+        }
+    }
+
+    pub fn fast_forward_progress(&self) -> Option<FastForwardProgress> {
+        match (self.status, &self.fast_forward) {
+            (SimulationControllerStatus::FastForward(..), Some(fast_forward)) => {
+                let eta = if fast_forward.num_steps_finished > 0 {
+                    let num_steps_remaining = fast_forward.num_steps_total - fast_forward.num_steps_finished;
+                    Some(fast_forward.start_time.elapsed() / fast_forward.num_steps_finished * num_steps_remaining)
+                } else {
+                    None
+                };
+                Some(FastForwardProgress {
+                    num_steps_finished: fast_forward.num_steps_finished,
+                    num_steps_total: fast_forward.num_steps_total,
+                    eta,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    // Number of steps that can be done before reaching simulation_stop_time (see single_step).
+    fn num_steps_until_stop_time(&self) -> u32 {
+        let remaining_time = self
+            .simulation_stop_time
+            .checked_sub(self.timer.total_simulated_time())
+            .unwrap_or_default();
+        (remaining_time.as_nanos() / self.timer.simulation_delta().as_nanos()).min(u32::MAX as u128) as u32
+    }
+
+    // Runs on the simulation thread, which is why there are no RenderDoc step captures.
+    pub fn fast_forward_chunk(
         &mut self,
-        simulation_jump_length: Duration,
+        scene: &mut Scene,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
-        scene: &mut Scene,
         pipeline_manager: &PipelineManager,
         global_bind_group: &wgpu::BindGroup,
     ) {
@@ -109,51 +247,81 @@ impl SimulationController {
         // Doing wait per step introduces too much stalling, by batching we're going a middle ground.
         //
         // Ideally we would like to never wait until the queue is flushed (i.e. have n steps in flight), but this is hard to do with wgpu!
-        const MAX_FAST_FORWARD_SIMULATION_BATCH_SIZE: usize = 16;
+        const MAX_FAST_FORWARD_SIMULATION_BATCH_SIZE: u32 = 16;
+        // Batches are processed until this much time has passed, then the frame is rendered.
+        const FAST_FORWARD_CHUNK_DURATION: Duration = Duration::from_millis(100);
 
-        self.status = SimulationControllerStatus::FastForward(simulation_jump_length);
-
-        // re-use stopping standard stopping mechanism to halt the simulation
-        let previous_simulation_end = self.simulation_stop_time;
-        // jump at least one simulation step, makes for easier ui code
-        self.simulation_stop_time = self.timer.total_simulated_time() + simulation_jump_length.max(self.timer.simulation_delta());
-        let num_expected_steps = simulation_jump_length.max(self.timer.simulation_delta()).as_nanos() / self.timer.simulation_delta().as_nanos();
+        let mut fast_forward = match self.fast_forward.take() {
+            Some(fast_forward) => fast_forward,
+            None => {
+                self.status = SimulationControllerStatus::Paused;
+                return;
+            }
+        };
 
+        // Profiling thousands of steps isn't meaningful and would just pile up queries.
         let mut dummy_profiler = GpuProfiler::new(1, 0.0);
         dummy_profiler.enable_timer = false;
         dummy_profiler.enable_debug_marker = false;
+        let mut dummy_fence_profiler = FenceProfiler::new(1);
 
-        self.start_simulation_frame();
-        {
-            let start_time = Instant::now();
-            let mut num_steps_finished = 0;
-            while let SimulationControllerStatus::FastForward(..) = self.status {
-                let mut batch_size = MAX_FAST_FORWARD_SIMULATION_BATCH_SIZE;
-                {
-                    for i in 0..MAX_FAST_FORWARD_SIMULATION_BATCH_SIZE {
-                        if !self.single_step(scene, device, queue, pipeline_manager, &mut dummy_profiler, global_bind_group) {
-                            batch_size = i;
-                            break;
-                        }
-                    }
-                }
-                device.poll(wgpu::Maintain::Wait);
-                num_steps_finished += batch_size;
-                info!(
-                    "simulation fast forwarding batch finished (progress {}/{})",
-                    num_steps_finished, num_expected_steps
+        self.finish_interrupted_step(
+            scene,
+            device,
+            queue,
+            pipeline_manager,
+            &mut dummy_profiler,
+            &mut dummy_fence_profiler,
+            global_bind_group,
+        );
+
+        let chunk_start_time = Instant::now();
+        while fast_forward.num_steps_finished < fast_forward.num_steps_total && chunk_start_time.elapsed() < FAST_FORWARD_CHUNK_DURATION {
+            // The stop time may have been changed since the fast forward started.
+            let num_steps_until_stop_time = self.num_steps_until_stop_time();
+            if num_steps_until_stop_time == 0 {
+                info!("Fast forward reached the simulation stop time");
+                fast_forward.num_steps_total = fast_forward.num_steps_finished;
+                break;
+            }
+            let batch_size = MAX_FAST_FORWARD_SIMULATION_BATCH_SIZE
+                .min(fast_forward.num_steps_total - fast_forward.num_steps_finished)
+                .min(num_steps_until_stop_time);
+            for _ in 0..batch_size {
+                // Pushes render time along with the simulation, i.e. render time jumps by the fast forward length as well.
+                self.timer.force_simulation_step();
+                scene.step(
+                    &self.timer,
+                    &SimulationStage::ALL,
+                    device,
+                    &mut dummy_profiler,
+                    &mut dummy_fence_profiler,
+                    self.profiling_detail,
+                    pipeline_manager,
+                    queue,
+                    global_bind_group,
                 );
             }
-            self.computation_time_last_fast_forward = start_time.elapsed();
+            device.poll(wgpu::Maintain::Wait);
+            fast_forward.num_steps_finished += batch_size;
         }
-        self.timer.on_frame_submitted(1.0);
-        self.timer.force_frame_delta(Duration::from_secs(0));
-        self.simulation_stop_time = previous_simulation_end;
-
         info!(
-            "Fast forward of {:?} took {:?} to compute",
-            simulation_jump_length, self.computation_time_last_fast_forward
+            "simulation fast forwarding chunk finished (progress {}/{})",
+            fast_forward.num_steps_finished, fast_forward.num_steps_total
         );
+
+        if fast_forward.num_steps_finished < fast_forward.num_steps_total {
+            self.fast_forward = Some(fast_forward);
+        } else {
+            self.computation_time_last_fast_forward = fast_forward.start_time.elapsed();
+            if let SimulationControllerStatus::FastForward(simulation_jump_length) = self.status {
+                info!(
+                    "Fast forward of {:?} took {:?} to compute",
+                    simulation_jump_length, self.computation_time_last_fast_forward
+                );
+            }
+            self.status = SimulationControllerStatus::Paused;
+        }
     }
 
     pub fn frame_steps(
@@ -163,23 +331,47 @@ impl SimulationController {
         queue: &wgpu::Queue,
         pipeline_manager: &PipelineManager,
         profiler: &mut GpuProfiler,
+        fence_profiler: &mut FenceProfiler,
         global_bind_group: &wgpu::BindGroup,
+        renderdoc: &mut RenderDocCapture,
     ) {
         if !self.start_simulation_frame() {
+            if self.step_once_requested {
+                self.step_once_requested = false;
+                renderdoc.begin_simulation_step();
+                self.perform_step_once(scene, device, queue, pipeline_manager, profiler, fence_profiler, global_bind_group);
+                renderdoc.end_simulation_step();
+            }
+            return;
+        }
+        // Fast forward chunks are done by the simulation thread instead.
+        if let SimulationControllerStatus::FastForward(..) = self.status {
             return;
         }
 
-        while self.single_step(scene, device, queue, pipeline_manager, profiler, global_bind_group) {}
+        self.finish_interrupted_step(scene, device, queue, pipeline_manager, profiler, fence_profiler, global_bind_group);
+        while self.single_step(
+            scene,
+            device,
+            queue,
+            pipeline_manager,
+            profiler,
+            fence_profiler,
+            global_bind_group,
+            renderdoc,
+        ) {}
     }
 
     fn start_simulation_frame(&mut self) -> bool {
         match self.status {
             SimulationControllerStatus::Realtime => {}
             SimulationControllerStatus::RecordingWithFixedFrameLength(frame_length) => {
-                self.timer.force_frame_delta(frame_length);
+                // The frame length of the video stays fixed, so slowing down means advancing less simulation time per frame.
+                self.timer.force_frame_delta(frame_length.mul_f32(self.recording_time_scale()));
             }
-            SimulationControllerStatus::FastForward(frame_length) => {
-                self.timer.force_frame_delta(frame_length);
+            SimulationControllerStatus::FastForward(..) => {
+                // Render time is pushed along with every step instead, see fast_forward_chunk.
+                self.timer.force_frame_delta(Duration::from_secs(0));
             }
             SimulationControllerStatus::Paused => {
                 self.timer.skip_simulation_frame();
@@ -189,6 +381,68 @@ impl SimulationController {
         return true;
     }
 
+    fn perform_step_once(
+        &mut self,
+        scene: &mut Scene,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pipeline_manager: &PipelineManager,
+        profiler: &mut GpuProfiler,
+        fence_profiler: &mut FenceProfiler,
+        global_bind_group: &wgpu::BindGroup,
+    ) {
+        let first_stage = match self.next_sub_step_stage {
+            Some(stage) => stage,
+            None => {
+                self.timer.force_simulation_step();
+                SimulationStage::TransferToGrid
+            }
+        };
+        let stages = if self.sub_step_inspection {
+            &SimulationStage::ALL[first_stage as usize..=first_stage as usize]
+        } else {
+            &SimulationStage::ALL[first_stage as usize..]
+        };
+        scene.step(
+            &self.timer,
+            stages,
+            device,
+            profiler,
+            fence_profiler,
+            self.profiling_detail,
+            pipeline_manager,
+            queue,
+            global_bind_group,
+        );
+        self.next_sub_step_stage = stages.last().unwrap().next();
+    }
+
+    // If a step was interrupted by sub-step inspection, execute all its remaining stages.
+    fn finish_interrupted_step(
+        &mut self,
+        scene: &mut Scene,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pipeline_manager: &PipelineManager,
+        profiler: &mut GpuProfiler,
+        fence_profiler: &mut FenceProfiler,
+        global_bind_group: &wgpu::BindGroup,
+    ) {
+        if let Some(stage) = self.next_sub_step_stage.take() {
+            scene.step(
+                &self.timer,
+                &SimulationStage::ALL[stage as usize..],
+                device,
+                profiler,
+                fence_profiler,
+                self.profiling_detail,
+                pipeline_manager,
+                queue,
+                global_bind_group,
+            );
+        }
+    }
+
     fn single_step<'a>(
         &mut self,
         scene: &'a mut Scene,
@@ -196,7 +450,9 @@ impl SimulationController {
         queue: &wgpu::Queue,
         pipeline_manager: &'a PipelineManager,
         profiler: &mut GpuProfiler,
+        fence_profiler: &mut FenceProfiler,
         global_bind_group: &wgpu::BindGroup,
+        renderdoc: &mut RenderDocCapture,
     ) -> bool {
         // frame drops are only relevant in realtime mode.
         let max_total_step_per_frame = if self.status == SimulationControllerStatus::Realtime {
@@ -211,7 +467,19 @@ impl SimulationController {
         }
 
         if self.timer.simulation_frame_loop(max_total_step_per_frame) == SimulationStepResult::PerformStepAndCallAgain {
-            scene.step(&self.timer, device, profiler, pipeline_manager, queue, global_bind_group);
+            renderdoc.begin_simulation_step();
+            scene.step(
+                &self.timer,
+                &SimulationStage::ALL,
+                device,
+                profiler,
+                fence_profiler,
+                self.profiling_detail,
+                pipeline_manager,
+                queue,
+                global_bind_group,
+            );
+            renderdoc.end_simulation_step();
             return true;
         }
         return false;
@@ -0,0 +1,153 @@
+use crate::{scene::Scene, simulation_controller::SimulationController, wgpu_utils::pipelines::PipelineManager};
+use std::{
+    ops::{Deref, DerefMut},
+    sync::{mpsc, Arc},
+    thread,
+    time::Duration,
+};
+
+// State that is owned by the event loop, but handed over to the simulation thread for the duration of a job.
+// Accessing it while it is away is a bug: the event loop leaves it alone as long as SimulationThread::is_busy.
+pub struct Lendable<T>(Option<T>);
+
+impl<T> Lendable<T> {
+    pub fn new(value: T) -> Self {
+        Lendable(Some(value))
+    }
+
+    fn lend(&mut self) -> T {
+        self.0.take().expect("Already lent to the simulation thread")
+    }
+
+    fn give_back(&mut self, value: T) {
+        assert!(self.0.is_none(), "Got back something that was never lent");
+        self.0 = Some(value);
+    }
+}
+
+impl<T> Deref for Lendable<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.0.as_ref().expect("Accessed while lent to the simulation thread")
+    }
+}
+
+impl<T> DerefMut for Lendable<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.0.as_mut().expect("Accessed while lent to the simulation thread")
+    }
+}
+
+// A chunk of a fast forward (see SimulationController::start_fast_forward).
+// Takes along everything it needs exclusive access to and is sent back once done.
+pub struct FastForwardJob {
+    simulation_controller: SimulationController,
+    scene: Scene,
+    pipeline_manager: PipelineManager,
+    device: Arc<wgpu::Device>,
+    queue: Arc<wgpu::Queue>,
+    global_bind_group: Arc<wgpu::BindGroup>,
+}
+
+impl FastForwardJob {
+    pub fn new(
+        simulation_controller: &mut Lendable<SimulationController>,
+        scene: &mut Lendable<Scene>,
+        pipeline_manager: &mut Lendable<PipelineManager>,
+        device: Arc<wgpu::Device>,
+        queue: Arc<wgpu::Queue>,
+        global_bind_group: Arc<wgpu::BindGroup>,
+    ) -> Self {
+        FastForwardJob {
+            simulation_controller: simulation_controller.lend(),
+            scene: scene.lend(),
+            pipeline_manager: pipeline_manager.lend(),
+            device,
+            queue,
+            global_bind_group,
+        }
+    }
+
+    pub fn give_back(
+        self,
+        simulation_controller: &mut Lendable<SimulationController>,
+        scene: &mut Lendable<Scene>,
+        pipeline_manager: &mut Lendable<PipelineManager>,
+    ) {
+        simulation_controller.give_back(self.simulation_controller);
+        scene.give_back(self.scene);
+        pipeline_manager.give_back(self.pipeline_manager);
+    }
+
+    fn run(&mut self) {
+        // Gpu errors end up in the uncaptured error handler, error scopes are per device and can't be shared with the event loop.
+        self.simulation_controller.fast_forward_chunk(
+            &mut self.scene,
+            &self.device,
+            &self.queue,
+            &self.pipeline_manager,
+            &self.global_bind_group,
+        );
+    }
+}
+
+// Records & submits simulation steps on a dedicated thread.
+// Long running simulation work would otherwise block the event loop, freezing the window for as long as it takes.
+pub struct SimulationThread {
+    job_sender: mpsc::Sender<FastForwardJob>,
+    finished_job_receiver: mpsc::Receiver<FastForwardJob>,
+    busy: bool,
+}
+
+impl SimulationThread {
+    pub fn new() -> Self {
+        let (job_sender, job_receiver) = mpsc::channel::<FastForwardJob>();
+        let (finished_job_sender, finished_job_receiver) = mpsc::channel();
+
+        // Ends once the event loop side hangs up.
+        thread::Builder::new()
+            .name("simulation".to_string())
+            .spawn(move || {
+                for mut job in job_receiver {
+                    job.run();
+                    if finished_job_sender.send(job).is_err() {
+                        break;
+                    }
+                }
+            })
+            .expect("Failed to spawn simulation thread");
+
+        SimulationThread {
+            job_sender,
+            finished_job_receiver,
+            busy: false,
+        }
+    }
+
+    pub fn is_busy(&self) -> bool {
+        self.busy
+    }
+
+    pub fn start(&mut self, job: FastForwardJob) {
+        assert!(!self.busy, "Simulation thread can only do one job at a time");
+        self.job_sender.send(job).expect("Simulation thread is gone");
+        self.busy = true;
+    }
+
+    // Waits at most timeout for the current job, returns it once it is done.
+    pub fn wait_for_finished_job(&mut self, timeout: Duration) -> Option<FastForwardJob> {
+        if !self.busy {
+            return None;
+        }
+        match self.finished_job_receiver.recv_timeout(timeout) {
+            Ok(job) => {
+                self.busy = false;
+                Some(job)
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => None,
+            // The job took the scene with it, there is nothing left to continue with.
+            Err(mpsc::RecvTimeoutError::Disconnected) => panic!("Simulation thread died during a job"),
+        }
+    }
+}
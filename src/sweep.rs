@@ -0,0 +1,112 @@
+use crate::{benchmark::SceneBenchmarkResult, scene::load_scene_json, settings_overrides::SettingsOverrides};
+use serde::Deserialize;
+use std::{
+    error::Error,
+    fs::File,
+    io::BufReader,
+    path::{Component, Path, PathBuf},
+};
+
+// Describes a parameter sweep: a base scene that is simulated once per variant, each with a set of overrides.
+#[derive(Deserialize)]
+pub struct SweepConfig {
+    pub base_scene: PathBuf,
+    #[serde(default = "default_simulation_seconds")]
+    pub simulation_seconds: f64,
+    #[serde(default = "default_simulation_steps_per_second")]
+    pub simulation_steps_per_second: u64,
+    // If set, screenshots are taken in this interval of simulated time. A screenshot of the last step is always taken.
+    #[serde(default)]
+    pub screenshot_interval_seconds: Option<f64>,
+    // Every variant writes to a subfolder named after it.
+    #[serde(default = "default_output")]
+    pub output: PathBuf,
+    pub variants: Vec<SweepVariant>,
+}
+
+fn default_simulation_seconds() -> f64 {
+    5.0
+}
+
+fn default_simulation_steps_per_second() -> u64 {
+    120
+}
+
+fn default_output() -> PathBuf {
+    PathBuf::from("sweep")
+}
+
+#[derive(Deserialize)]
+pub struct SweepVariant {
+    pub name: String,
+    #[serde(flatten)]
+    pub overrides: SettingsOverrides,
+}
+
+impl SweepConfig {
+    pub fn from_file(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let config: SweepConfig = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        if !config.base_scene.is_file() {
+            return Err(format!("Base scene {:?} not found", config.base_scene).into());
+        }
+        if config.simulation_steps_per_second == 0 {
+            return Err("simulation_steps_per_second needs to be greater than zero".into());
+        }
+        // Variant names become directories within the output directory, they must not point anywhere else.
+        for variant in config.variants.iter() {
+            let mut components = Path::new(&variant.name).components();
+            match (components.next(), components.next()) {
+                (Some(Component::Normal(_)), None) => {}
+                _ => return Err(format!("Variant name {:?} is not a valid directory name", variant.name).into()),
+            }
+        }
+        Ok(config)
+    }
+
+    pub fn num_steps(&self) -> u32 {
+        (self.simulation_seconds * self.simulation_steps_per_second as f64).ceil() as u32
+    }
+
+    // Steps after which a screenshot should be taken (1 based, i.e. number of steps done so far).
+    pub fn is_screenshot_step(&self, num_steps_done: u32) -> bool {
+        if num_steps_done == self.num_steps() {
+            return true;
+        }
+        match self.screenshot_interval_seconds {
+            Some(interval) if interval > 0.0 => {
+                let interval_steps = ((interval * self.simulation_steps_per_second as f64).round() as u32).max(1);
+                num_steps_done % interval_steps == 0
+            }
+            _ => false,
+        }
+    }
+
+    pub fn variant_directory(&self, variant: &SweepVariant) -> PathBuf {
+        self.output.join(&variant.name)
+    }
+
+    // Writes the scene file of a variant into its output directory and returns its path.
+    // Keeping the scene file there makes it easy to reproduce a variant later on.
+    pub fn write_variant_scene(&self, variant: &SweepVariant) -> Result<PathBuf, Box<dyn Error>> {
+        // Resolved here since a relative "extends" wouldn't point anywhere from the output directory.
+        let mut scene = load_scene_json(&self.base_scene)?;
+        variant.overrides.apply_to_scene_json(&mut scene);
+
+        let directory = self.variant_directory(variant);
+        std::fs::create_dir_all(&directory)?;
+        let scene_path = directory.join("scene.json");
+        serde_json::to_writer_pretty(File::create(&scene_path)?, &scene)?;
+        Ok(scene_path)
+    }
+}
+
+pub fn write_variant_statistics(directory: &Path, result: &SceneBenchmarkResult) {
+    let path = directory.join("statistics.json");
+    match File::create(&path) {
+        Ok(file) => match serde_json::to_writer_pretty(file, result) {
+            Ok(()) => info!("Wrote sweep statistics to {:?}", path),
+            Err(err) => error!("Failed to write sweep statistics {:?}: {}", path, err),
+        },
+        Err(err) => error!("Failed to create sweep statistics {:?}: {}", path, err),
+    }
+}
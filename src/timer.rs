@@ -87,6 +87,11 @@ impl Timer {
         self.num_frames_rendered += 1;
     }
 
+    // Ignores all real time that passed since the last frame, e.g. after the application was suspended.
+    pub fn skip_time_since_last_frame(&mut self) {
+        self.timestamp_last_frame = Instant::now();
+    }
+
     pub fn skip_simulation_frame(&mut self) {
         self.accepted_simulation_to_render_lag += self.current_frame_delta;
     }
@@ -125,6 +130,21 @@ impl Timer {
         SimulationStepResult::PerformStepAndCallAgain
     }
 
+    // Advances simulation time by a single step, regardless of render time.
+    // Used for stepping a paused simulation. Keeps simulation time behind render time by using up lag or, if there isn't enough, pushing render time.
+    pub fn force_simulation_step(&mut self) {
+        match self.accepted_simulation_to_render_lag.checked_sub(self.simulation_delta) {
+            Some(remaining_lag) => self.accepted_simulation_to_render_lag = remaining_lag,
+            None => {
+                self.total_rendered_time += self.simulation_delta - self.accepted_simulation_to_render_lag;
+                self.accepted_simulation_to_render_lag = Duration::from_secs(0);
+            }
+        }
+        self.num_simulation_steps_this_frame += 1;
+        self.num_simulation_steps += 1;
+        self.total_simulated_time += self.simulation_delta;
+    }
+
     pub fn simulation_delta(&self) -> Duration {
         self.simulation_delta
     }
@@ -1,3 +1,19 @@
 pub fn round_to_multiple(value: usize, multiple: usize) -> usize {
     (value + multiple - 1) / multiple * multiple
 }
+
+// Json merge patch (RFC 7386): objects are merged recursively, everything else is replaced, null removes a key.
+pub fn merge_json(base: &mut serde_json::Value, patch: &serde_json::Value) {
+    match (base, patch) {
+        (serde_json::Value::Object(base), serde_json::Value::Object(patch)) => {
+            for (key, value) in patch {
+                if value.is_null() {
+                    base.remove(key);
+                } else {
+                    merge_json(base.entry(key.clone()).or_insert(serde_json::Value::Null), value);
+                }
+            }
+        }
+        (base, patch) => *base = patch.clone(),
+    }
+}
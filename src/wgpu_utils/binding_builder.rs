@@ -1,3 +1,6 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
 pub struct BindGroupLayoutWithDesc {
     pub layout: wgpu::BindGroupLayout,
     pub entries: Vec<wgpu::BindGroupLayoutEntry>,
@@ -61,6 +64,31 @@ impl BindGroupLayoutBuilder {
             entries: self.entries,
         }
     }
+
+    // Like create, but returns an existing layout from the cache if one with identical entries was created before.
+    // Note that the label of a reused layout is the one it was first created with.
+    pub fn create_cached(self, device: &wgpu::Device, cache: &mut BindGroupLayoutCache, label: &str) -> Arc<BindGroupLayoutWithDesc> {
+        if let Some(layout) = cache.layouts.get(&self.entries) {
+            return layout.clone();
+        }
+        let layout = Arc::new(self.create(device, label));
+        cache.layouts.insert(layout.entries.clone(), layout.clone());
+        layout
+    }
+}
+
+// Deduplicates structurally identical bind group layouts.
+// Apart from having fewer objects around, pipelines created with the same layout are guaranteed to be compatible
+// which allows sharing bind groups across otherwise unrelated systems.
+// Layouts belong to a device, so a cache must not outlive it.
+pub struct BindGroupLayoutCache {
+    layouts: HashMap<Vec<wgpu::BindGroupLayoutEntry>, Arc<BindGroupLayoutWithDesc>>,
+}
+
+impl BindGroupLayoutCache {
+    pub fn new() -> Self {
+        BindGroupLayoutCache { layouts: HashMap::new() }
+    }
 }
 
 // Builder for wgpu::BindGroups following the exact layout from a wgpu::BindGroupLayout
@@ -16,6 +16,15 @@ pub fn uniform() -> wgpu::BindingType {
     }
 }
 
+// Uniform buffer bound with a dynamic offset, e.g. a slot of a UniformBufferRing.
+pub fn uniform_dynamic() -> wgpu::BindingType {
+    wgpu::BindingType::Buffer {
+        ty: wgpu::BufferBindingType::Uniform,
+        has_dynamic_offset: true,
+        min_binding_size: None,
+    }
+}
+
 pub fn sampler(filtering: bool) -> wgpu::BindingType {
     wgpu::BindingType::Sampler {
         filtering,
@@ -31,6 +40,15 @@ pub fn texture2D() -> wgpu::BindingType {
     }
 }
 
+// Depth textures can't be filtered, but reading them with texelFetch works just like any other float texture.
+pub fn texture2DDepth() -> wgpu::BindingType {
+    wgpu::BindingType::Texture {
+        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+        view_dimension: wgpu::TextureViewDimension::D2,
+        multisampled: false,
+    }
+}
+
 pub fn texture2DArray() -> wgpu::BindingType {
     wgpu::BindingType::Texture {
         sample_type: wgpu::TextureSampleType::Float { filterable: true },
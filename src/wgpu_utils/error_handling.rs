@@ -0,0 +1,80 @@
+use futures::{Future, FutureExt};
+use std::pin::Pin;
+
+// Keeps track of gpu errors instead of letting wgpu panic on them.
+//
+// Validation and out of memory errors are logged (and thus show up in the gui log console).
+// A lost device needs to be reported explicitly, the application may then try to recover from it by recreating the device.
+pub struct GpuErrorTracker {
+    device_lost: bool,
+    // Popped error scopes that haven't resolved yet, polled every frame instead of blocking on them.
+    pending_scopes: Vec<(&'static str, Pin<Box<dyn Future<Output = Option<wgpu::Error>>>>)>,
+}
+
+fn report_error(error: wgpu::Error, label: &str) {
+    match error {
+        wgpu::Error::OutOfMemoryError { .. } => {
+            error!("GPU out of memory during {}: {}", label, error);
+        }
+        wgpu::Error::ValidationError { .. } => {
+            error!("GPU validation error during {}: {}", label, error);
+        }
+    }
+}
+
+impl GpuErrorTracker {
+    pub fn new() -> Self {
+        GpuErrorTracker {
+            device_lost: false,
+            pending_scopes: Vec::new(),
+        }
+    }
+
+    // Catches all errors that are not handled by an error scope. (default behavior of wgpu is to panic)
+    pub fn install_uncaptured_error_handler(&self, device: &wgpu::Device) {
+        device.on_uncaptured_error(move |error| report_error(error, "uncaptured"));
+    }
+
+    pub fn report_device_lost(&mut self) {
+        self.device_lost = true;
+    }
+
+    // Returns true if the device was lost since the last call.
+    pub fn take_device_lost(&mut self) -> bool {
+        std::mem::replace(&mut self.device_lost, false)
+    }
+
+    pub fn push_scope(&self, device: &wgpu::Device) {
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        device.push_error_scope(wgpu::ErrorFilter::OutOfMemory);
+    }
+
+    // Pops a scope previously pushed with push_scope. Errors that happened within are reported once the scope resolved, see poll_scopes.
+    pub fn pop_scope(&mut self, device: &wgpu::Device, label: &'static str) {
+        for _ in 0..2 {
+            self.pending_scopes.push((label, Box::pin(device.pop_error_scope())));
+        }
+        self.poll_scopes();
+    }
+
+    // Reports errors of all popped scopes that resolved by now, never waits for the gpu.
+    fn poll_scopes(&mut self) {
+        let mut i = 0;
+        while i < self.pending_scopes.len() {
+            match (&mut self.pending_scopes[i].1).now_or_never() {
+                Some(error) => {
+                    let (label, _) = self.pending_scopes.swap_remove(i);
+                    if let Some(error) = error {
+                        report_error(error, label);
+                    }
+                }
+                None => i += 1,
+            }
+        }
+    }
+
+    // Scopes of an old device can't resolve anymore.
+    pub fn on_device_recreated(&mut self) {
+        self.pending_scopes.clear();
+    }
+}
@@ -0,0 +1,81 @@
+use super::pipelines::PipelineManager;
+use std::collections::VecDeque;
+use std::time::Instant;
+use wgpu_profiler::GpuTimerScopeResult;
+
+// Coarse profiling for adapters that don't support timestamp queries.
+//
+// Each scope is submitted on its own and waited for on the cpu right away, so the measured time includes submission overhead
+// and the gpu runs without any overlap between scopes.
+// This stalls the cpu, so it should only be enabled while someone looks at the results.
+// Results use the same structure as GpuProfiler, with one top level scope per submission.
+pub struct FenceProfiler {
+    pub enabled: bool,
+    time_base: Instant,
+    current_frame: Vec<GpuTimerScopeResult>,
+    finished_frames: VecDeque<Vec<GpuTimerScopeResult>>,
+    max_num_pending_frames: usize,
+}
+
+impl FenceProfiler {
+    pub fn new(max_num_pending_frames: usize) -> Self {
+        FenceProfiler {
+            enabled: false,
+            time_base: Instant::now(),
+            current_frame: Vec::new(),
+            finished_frames: VecDeque::new(),
+            max_num_pending_frames,
+        }
+    }
+
+    // Submits everything recorded so far on the encoder and measures how long it takes until the gpu is done with it.
+    // The encoder is replaced with a fresh one, so this must not be called within a debug group.
+    // Does nothing if the profiler is disabled.
+    pub fn submit_scope(
+        &mut self,
+        label: &str,
+        encoder: &mut wgpu::CommandEncoder,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pipeline_manager: &PipelineManager,
+    ) {
+        if !self.enabled {
+            return;
+        }
+
+        let recorded = std::mem::replace(
+            encoder,
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Encoder: FenceProfiler continuation"),
+            }),
+        );
+
+        // Make sure nothing submitted earlier is counted towards this scope.
+        device.poll(wgpu::Maintain::Wait);
+        pipeline_manager.flush_push_constants(device, queue);
+        let start = self.time_base.elapsed().as_secs_f64();
+        queue.submit(Some(recorded.finish()));
+        device.poll(wgpu::Maintain::Wait);
+        let end = self.time_base.elapsed().as_secs_f64();
+
+        self.current_frame.push(GpuTimerScopeResult {
+            label: label.to_owned(),
+            time: start..end,
+            nested_scopes: Vec::new(),
+        });
+    }
+
+    pub fn end_frame(&mut self) {
+        if self.current_frame.is_empty() {
+            return;
+        }
+        if self.finished_frames.len() == self.max_num_pending_frames {
+            self.finished_frames.pop_front();
+        }
+        self.finished_frames.push_back(std::mem::take(&mut self.current_frame));
+    }
+
+    pub fn process_finished_frame(&mut self) -> Option<Vec<GpuTimerScopeResult>> {
+        self.finished_frames.pop_front()
+    }
+}
@@ -0,0 +1,103 @@
+use super::{
+    binding_builder::{BindGroupBuilder, BindGroupLayoutBuilder, BindGroupLayoutWithDesc},
+    binding_glsl,
+    pipelines::*,
+    shader::ShaderDirectory,
+};
+use std::{path::Path, sync::Arc};
+
+// Generates full mip chains with a simple box filter in a compute shader.
+//
+// Storage textures can't be srgb, so all generated textures are Rgba16Float.
+// The source (mip 0) can be any filterable format though, typically an Rgba8UnormSrgb texture we just uploaded (conversion happens on the fly).
+pub struct MipmapGenerator {
+    pipeline: ComputePipelineHandle,
+    bind_group_layout: BindGroupLayoutWithDesc,
+}
+
+impl MipmapGenerator {
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+    const LOCAL_SIZE: wgpu::Extent3d = wgpu::Extent3d {
+        width: 8,
+        height: 8,
+        depth_or_array_layers: 1,
+    };
+
+    pub fn new(device: &wgpu::Device, shader_dir: &ShaderDirectory, pipeline_manager: &mut PipelineManager) -> Self {
+        let bind_group_layout = BindGroupLayoutBuilder::new()
+            .next_binding_compute(binding_glsl::texture2D())
+            .next_binding_compute(binding_glsl::image2D(Self::FORMAT, wgpu::StorageTextureAccess::WriteOnly))
+            .create(device, "BindGroupLayout: Mipmap Generation");
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Mipmap Generation Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout.layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = pipeline_manager.create_compute_pipeline(
+            device,
+            shader_dir,
+            ComputePipelineCreationDesc::new("Mipmap Generation", Arc::new(pipeline_layout), Path::new("generate_mip.comp")),
+        );
+
+        MipmapGenerator { pipeline, bind_group_layout }
+    }
+
+    pub fn num_mip_levels(size: wgpu::Extent3d) -> u32 {
+        32 - size.width.max(size.height).leading_zeros()
+    }
+
+    // Creates a texture with full mip chain from the given source texture (which has to have the same size)
+    pub fn create_mipmapped_texture(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline_manager: &PipelineManager,
+        source: &wgpu::TextureView,
+        size: wgpu::Extent3d,
+        label: Option<&str>,
+    ) -> wgpu::Texture {
+        let mip_level_count = Self::num_mip_levels(size);
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label,
+            size,
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::FORMAT,
+            usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::STORAGE,
+        });
+        let mip_views: Vec<wgpu::TextureView> = (0..mip_level_count)
+            .map(|mip_level| {
+                texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some("Mip Level View"),
+                    base_mip_level: mip_level,
+                    mip_level_count: std::num::NonZeroU32::new(1),
+                    ..Default::default()
+                })
+            })
+            .collect();
+
+        for mip_level in 0..mip_level_count {
+            let source_view = if mip_level == 0 { source } else { &mip_views[mip_level as usize - 1] };
+            let bind_group = BindGroupBuilder::new(&self.bind_group_layout)
+                .texture(source_view)
+                .texture(&mip_views[mip_level as usize])
+                .create(device, "BindGroup: Mipmap Generation");
+
+            let mip_size = wgpu::Extent3d {
+                width: (size.width >> mip_level).max(1),
+                height: (size.height >> mip_level).max(1),
+                depth_or_array_layers: 1,
+            };
+            let work_group = super::compute_group_size(mip_size, Self::LOCAL_SIZE);
+
+            // One pass per mip level since we read the previous level.
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: Some("generate mip") });
+            cpass.set_pipeline(pipeline_manager.get_compute(&self.pipeline));
+            cpass.set_bind_group(0, &bind_group, &[]);
+            cpass.dispatch(work_group.width, work_group.height, 1);
+        }
+
+        texture
+    }
+}
@@ -2,8 +2,15 @@ pub mod binding_builder;
 #[allow(dead_code)]
 #[allow(non_snake_case)]
 pub mod binding_glsl;
+pub mod error_handling;
+pub mod fence_profiler;
+pub mod mipmap;
 pub mod pipelines;
+#[macro_use]
+pub mod profiling;
 pub mod shader;
+pub mod texture_pool;
+pub mod transient_texture_pool;
 pub mod uniformbuffer;
 
 pub fn compute_group_size(resource_size: wgpu::Extent3d, group_local_size: wgpu::Extent3d) -> wgpu::Extent3d {
@@ -1,9 +1,194 @@
+use super::binding_builder::BindGroupLayoutCache;
 use super::shader::{ShaderDirectory, SHADER_ENTRY_POINT_NAME};
+use once_cell::unsync::OnceCell;
+use std::cell::RefCell;
 use std::path::{Path, PathBuf};
-use std::rc::{Rc, Weak};
+use std::sync::{Arc, Weak};
 
-pub type ComputePipelineHandle = Rc<usize>;
-pub type RenderPipelineHandle = Rc<usize>;
+// Atomically counted since the pipeline manager and scenes holding handles are handed to the simulation thread.
+pub type ComputePipelineHandle = Arc<usize>;
+pub type RenderPipelineHandle = Arc<usize>;
+
+// Push constant size all pipelines may rely on, no matter if push constants are native or emulated.
+pub const MAX_PUSH_CONSTANT_SIZE: u32 = 8;
+
+// Shader define that is used as layout qualifier of push constant blocks, i.e. `layout(PUSH_CONSTANT_LAYOUT) uniform PushConstants { ... }`
+pub const PUSH_CONSTANT_LAYOUT_DEFINE: &str = "PUSH_CONSTANT_LAYOUT";
+
+// Each emulated push constant update takes a slot of this size in the fallback uniform buffers, as dynamic offsets need to be aligned to it.
+const PUSH_CONSTANT_FALLBACK_SLOT_SIZE: usize = wgpu::BIND_BUFFER_ALIGNMENT as usize;
+// Number of slots in the first fallback block, every following block has twice as many as the previous one.
+const PUSH_CONSTANT_FALLBACK_FIRST_BLOCK_NUM_SLOTS: usize = 4096;
+// Limits the number of emulated push constant updates between two submits to 4096 * (2^6 - 1).
+const PUSH_CONSTANT_FALLBACK_MAX_NUM_BLOCKS: usize = 6;
+
+// Features and max_push_constant_size to request for a device on the given adapter.
+// If the adapter can't do push constants (of the size we need), they are emulated by the PipelineManager.
+pub fn push_constant_requirements(adapter: &wgpu::Adapter) -> (wgpu::Features, u32) {
+    if adapter.features().contains(wgpu::Features::PUSH_CONSTANTS) && adapter.limits().max_push_constant_size >= MAX_PUSH_CONSTANT_SIZE {
+        (wgpu::Features::PUSH_CONSTANTS, MAX_PUSH_CONSTANT_SIZE)
+    } else {
+        warn!("Adapter does not support push constants, falling back to dynamic offset uniform buffers.");
+        (wgpu::Features::empty(), 0)
+    }
+}
+
+// Emulates push constants on devices without wgpu::Features::PUSH_CONSTANTS.
+//
+// Pipeline layouts with push constant ranges get an additional bind group at the end with a single uniform buffer bound with a dynamic offset.
+// Every push constant update takes a new slot in that buffer. Slots are filled on the cpu and uploaded with flush_push_constants before submit.
+//
+// Passes hold on to the bind groups until they are done, so instead of resizing a single buffer, slots are spread over blocks that are only ever added.
+// Creating a block requires the device, so flush makes sure there is always an unused block after the last one that was filled.
+struct PushConstantFallback {
+    bind_group_layout: wgpu::BindGroupLayout,
+    blocks: [OnceCell<PushConstantFallbackBlock>; PUSH_CONSTANT_FALLBACK_MAX_NUM_BLOCKS],
+    slots: RefCell<PushConstantFallbackSlots>,
+}
+
+struct PushConstantFallbackBlock {
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+struct PushConstantFallbackSlots {
+    // Slots of each block, blocks are filled one after another.
+    data: [Vec<u8>; PUSH_CONSTANT_FALLBACK_MAX_NUM_BLOCKS],
+    // Block the last slot was added to.
+    block: usize,
+    // Like real push constants, partial updates keep the remaining values.
+    current: [u8; MAX_PUSH_CONSTANT_SIZE as usize],
+}
+
+impl PushConstantFallback {
+    fn new(device: &wgpu::Device) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("BindGroupLayout: Push constant fallback"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT | wgpu::ShaderStage::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: wgpu::BufferSize::new(MAX_PUSH_CONSTANT_SIZE as u64),
+                },
+                count: None,
+            }],
+        });
+
+        let fallback = PushConstantFallback {
+            bind_group_layout,
+            blocks: Default::default(),
+            slots: RefCell::new(PushConstantFallbackSlots {
+                data: Default::default(),
+                block: 0,
+                current: [0; MAX_PUSH_CONSTANT_SIZE as usize],
+            }),
+        };
+        fallback.ensure_block(device, 0);
+        fallback
+    }
+
+    fn block_num_slots(block: usize) -> usize {
+        PUSH_CONSTANT_FALLBACK_FIRST_BLOCK_NUM_SLOTS << block
+    }
+
+    fn ensure_block(&self, device: &wgpu::Device, block: usize) {
+        self.blocks[block].get_or_init(|| {
+            let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Buffer: Push constant fallback"),
+                size: (PUSH_CONSTANT_FALLBACK_SLOT_SIZE * Self::block_num_slots(block)) as u64,
+                usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+                mapped_at_creation: false,
+            });
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("BindGroup: Push constant fallback"),
+                layout: &self.bind_group_layout,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: &buffer,
+                        offset: 0,
+                        size: wgpu::BufferSize::new(MAX_PUSH_CONSTANT_SIZE as u64),
+                    }),
+                }],
+            });
+            PushConstantFallbackBlock { buffer, bind_group }
+        });
+    }
+
+    fn bind_group(&self, block: usize) -> &wgpu::BindGroup {
+        &self.blocks[block].get().unwrap().bind_group
+    }
+
+    // Updates the current push constant values and returns the bind group and dynamic offset of the slot holding them.
+    fn push(&self, offset: u32, data: &[u8]) -> (&wgpu::BindGroup, u32) {
+        let mut slots = self.slots.borrow_mut();
+        let offset = offset as usize;
+        assert!(
+            offset + data.len() <= MAX_PUSH_CONSTANT_SIZE as usize,
+            "push constants exceed MAX_PUSH_CONSTANT_SIZE"
+        );
+        slots.current[offset..offset + data.len()].copy_from_slice(data);
+        self.add_slot(&mut slots)
+    }
+
+    // Returns the bind group and dynamic offset of a slot with the current values, adding one if nothing was pushed since the last flush.
+    fn current_slot(&self) -> (&wgpu::BindGroup, u32) {
+        let mut slots = self.slots.borrow_mut();
+        let block = slots.block;
+        if slots.data[block].is_empty() {
+            self.add_slot(&mut slots)
+        } else {
+            (
+                self.bind_group(block),
+                (slots.data[block].len() - PUSH_CONSTANT_FALLBACK_SLOT_SIZE) as u32,
+            )
+        }
+    }
+
+    fn add_slot(&self, slots: &mut PushConstantFallbackSlots) -> (&wgpu::BindGroup, u32) {
+        if slots.data[slots.block].len() == PUSH_CONSTANT_FALLBACK_SLOT_SIZE * Self::block_num_slots(slots.block) {
+            match self.blocks.get(slots.block + 1).and_then(OnceCell::get) {
+                Some(_) => slots.block += 1,
+                None => {
+                    // Only happens if a single submit uses more slots than all blocks so far plus the spare one, the next flush adds another block.
+                    error!("Ran out of push constant fallback slots, overwriting the last one.");
+                    let block = slots.block;
+                    slots.data[block].truncate(slots.data[block].len() - PUSH_CONSTANT_FALLBACK_SLOT_SIZE);
+                }
+            }
+        }
+        let block = slots.block;
+        let current = slots.current;
+        let data = &mut slots.data[block];
+        let slot_start = data.len();
+        data.extend_from_slice(&current);
+        data.resize(slot_start + PUSH_CONSTANT_FALLBACK_SLOT_SIZE, 0);
+        (self.bind_group(block), slot_start as u32)
+    }
+
+    fn flush(&self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let mut slots = self.slots.borrow_mut();
+        for (block, data) in self.blocks.iter().zip(slots.data.iter_mut()) {
+            if !data.is_empty() {
+                queue.write_buffer(&block.get().unwrap().buffer, 0, data);
+                data.clear();
+            }
+        }
+        if slots.block + 1 < PUSH_CONSTANT_FALLBACK_MAX_NUM_BLOCKS {
+            self.ensure_block(device, slots.block + 1);
+        }
+        slots.block = 0;
+    }
+}
+
+fn push_constant_defines(push_constant_fallback_set: Option<u32>) -> Vec<(&'static str, String)> {
+    match push_constant_fallback_set {
+        Some(set) => vec![(PUSH_CONSTANT_LAYOUT_DEFINE, format!("set = {}, binding = 0", set))],
+        None => Vec::new(),
+    }
+}
 
 // This is essentially a copy of wgpu::ComputePipelineDescriptor that we can store.
 // This is needed since we want to be able to reload pipelines while the program is running.
@@ -11,12 +196,12 @@ pub struct ComputePipelineCreationDesc {
     /// Debug label of the pipeline. This will show up in graphics debuggers for easy identification.
     pub label: &'static str,
     /// The layout of bind groups for this pipeline.
-    pub layout: Rc<wgpu::PipelineLayout>,
+    pub layout: Arc<wgpu::PipelineLayout>,
     pub compute_shader_relative_path: PathBuf,
 }
 
 impl ComputePipelineCreationDesc {
-    pub fn new(label: &'static str, layout: Rc<wgpu::PipelineLayout>, compute_shader_relative_path: &Path) -> Self {
+    pub fn new(label: &'static str, layout: Arc<wgpu::PipelineLayout>, compute_shader_relative_path: &Path) -> Self {
         ComputePipelineCreationDesc {
             label,
             layout,
@@ -24,8 +209,14 @@ impl ComputePipelineCreationDesc {
         }
     }
 
-    fn try_create_pipeline(&self, device: &wgpu::Device, shader_dir: &ShaderDirectory) -> Result<PipelineAndSourceFiles<wgpu::ComputePipeline>, ()> {
-        let shader = shader_dir.load_shader_module(device, &self.compute_shader_relative_path)?;
+    fn try_create_pipeline(
+        &self,
+        device: &wgpu::Device,
+        shader_dir: &ShaderDirectory,
+        push_constant_fallback_set: Option<u32>,
+    ) -> Result<PipelineAndSourceFiles<wgpu::ComputePipeline>, ()> {
+        let defines = push_constant_defines(push_constant_fallback_set);
+        let shader = shader_dir.load_shader_module_with_defines(device, &self.compute_shader_relative_path, &defines)?;
         Ok(PipelineAndSourceFiles {
             pipeline: device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
                 label: Some(self.label),
@@ -60,7 +251,7 @@ pub struct RenderPipelineCreationDesc {
     /// Debug label of the pipeline. This will show up in graphics debuggers for easy identification.
     pub label: &'static str,
     /// The layout of bind groups for this pipeline.
-    pub layout: Rc<wgpu::PipelineLayout>,
+    pub layout: Arc<wgpu::PipelineLayout>,
     /// The vertex stage, its entry point, and the input buffers layout.
     pub vertex: VertexStateCreationDesc,
     /// The properties of the pipeline at the primitive assembly and rasterization level.
@@ -76,7 +267,7 @@ pub struct RenderPipelineCreationDesc {
 impl RenderPipelineCreationDesc {
     pub fn new(
         label: &'static str,
-        layout: Rc<wgpu::PipelineLayout>,
+        layout: Arc<wgpu::PipelineLayout>,
         vertex_shader_relative_path: &Path,
         fragment_shader_relative_path: &Path,
         output_format: wgpu::TextureFormat,
@@ -102,9 +293,15 @@ impl RenderPipelineCreationDesc {
         }
     }
 
-    fn try_create_pipeline(&self, device: &wgpu::Device, shader_dir: &ShaderDirectory) -> Result<PipelineAndSourceFiles<wgpu::RenderPipeline>, ()> {
-        let shader_vs = shader_dir.load_shader_module(device, &self.vertex.shader_relative_path)?;
-        let mut shader_fs = shader_dir.load_shader_module(device, &self.fragment.shader_relative_path)?;
+    fn try_create_pipeline(
+        &self,
+        device: &wgpu::Device,
+        shader_dir: &ShaderDirectory,
+        push_constant_fallback_set: Option<u32>,
+    ) -> Result<PipelineAndSourceFiles<wgpu::RenderPipeline>, ()> {
+        let defines = push_constant_defines(push_constant_fallback_set);
+        let shader_vs = shader_dir.load_shader_module_with_defines(device, &self.vertex.shader_relative_path, &defines)?;
+        let mut shader_fs = shader_dir.load_shader_module_with_defines(device, &self.fragment.shader_relative_path, &defines)?;
 
         let render_pipeline_descriptor = wgpu::RenderPipelineDescriptor {
             label: Some(self.label),
@@ -143,45 +340,98 @@ struct ReloadableComputePipeline {
     desc: ComputePipelineCreationDesc,
     handle: Weak<usize>,
     pipeline_and_sources: PipelineAndSourceFiles<wgpu::ComputePipeline>,
+    push_constant_fallback_set: Option<u32>,
 }
 
 struct ReloadableRenderPipeline {
     desc: RenderPipelineCreationDesc,
     handle: Weak<usize>,
     pipeline_and_sources: PipelineAndSourceFiles<wgpu::RenderPipeline>,
+    push_constant_fallback_set: Option<u32>,
 }
 
 pub struct PipelineManager {
     compute_pipelines: Vec<ReloadableComputePipeline>,
     render_pipelines: Vec<ReloadableRenderPipeline>,
+
+    // Only present if the device doesn't support push constants.
+    push_constant_fallback: Option<PushConstantFallback>,
+    // Pipeline layouts that got the push constant fallback bind group, with the index of that group.
+    push_constant_fallback_layouts: Vec<(Weak<wgpu::PipelineLayout>, u32)>,
+
+    // Bind group layouts belong to the device just like the pipelines, so they live and die with the PipelineManager.
+    bind_group_layout_cache: BindGroupLayoutCache,
 }
 
 impl PipelineManager {
-    pub fn new() -> Self {
+    pub fn new(device: &wgpu::Device) -> Self {
         PipelineManager {
             compute_pipelines: Vec::new(),
             render_pipelines: Vec::new(),
+            push_constant_fallback: if device.features().contains(wgpu::Features::PUSH_CONSTANTS) {
+                None
+            } else {
+                Some(PushConstantFallback::new(device))
+            },
+            push_constant_fallback_layouts: Vec::new(),
+            bind_group_layout_cache: BindGroupLayoutCache::new(),
         }
     }
 
+    // Cache for bind group layouts that are used by several systems, see BindGroupLayoutBuilder::create_cached.
+    pub fn bind_group_layout_cache(&mut self) -> &mut BindGroupLayoutCache {
+        &mut self.bind_group_layout_cache
+    }
+
+    // Creates a pipeline layout, use this instead of wgpu::Device::create_pipeline_layout for all layouts with push constants.
+    // If push constants are emulated, the push constant ranges are replaced by an additional bind group after all others.
+    pub fn create_pipeline_layout(&mut self, device: &wgpu::Device, desc: &wgpu::PipelineLayoutDescriptor) -> Arc<wgpu::PipelineLayout> {
+        let fallback = match &self.push_constant_fallback {
+            Some(fallback) if !desc.push_constant_ranges.is_empty() => fallback,
+            _ => return Arc::new(device.create_pipeline_layout(desc)),
+        };
+
+        let mut bind_group_layouts = desc.bind_group_layouts.to_vec();
+        bind_group_layouts.push(&fallback.bind_group_layout);
+        let layout = Arc::new(device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: desc.label,
+            bind_group_layouts: &bind_group_layouts,
+            push_constant_ranges: &[],
+        }));
+
+        self.push_constant_fallback_layouts.retain(|(layout, _)| layout.strong_count() > 0);
+        self.push_constant_fallback_layouts
+            .push((Arc::downgrade(&layout), desc.bind_group_layouts.len() as u32));
+        layout
+    }
+
+    fn push_constant_fallback_set(&self, layout: &Arc<wgpu::PipelineLayout>) -> Option<u32> {
+        self.push_constant_fallback_layouts
+            .iter()
+            .find(|(fallback_layout, _)| fallback_layout.ptr_eq(&Arc::downgrade(layout)))
+            .map(|(_, set)| *set)
+    }
+
     pub fn create_compute_pipeline(
         &mut self,
         device: &wgpu::Device,
         shader_dir: &ShaderDirectory,
         desc: ComputePipelineCreationDesc,
     ) -> ComputePipelineHandle {
-        let pipeline_and_sources = desc.try_create_pipeline(device, shader_dir).unwrap();
+        let push_constant_fallback_set = self.push_constant_fallback_set(&desc.layout);
+        let pipeline_and_sources = desc.try_create_pipeline(device, shader_dir, push_constant_fallback_set).unwrap();
 
         let mut first_free_slot = 0;
         while first_free_slot < self.compute_pipelines.len() && self.compute_pipelines[first_free_slot].handle.strong_count() > 0 {
             first_free_slot += 1;
         }
 
-        let handle = Rc::new(first_free_slot);
+        let handle = Arc::new(first_free_slot);
         let new_reloadable_pipeline = ReloadableComputePipeline {
             desc,
             pipeline_and_sources,
-            handle: Rc::downgrade(&handle),
+            handle: Arc::downgrade(&handle),
+            push_constant_fallback_set,
         };
         if first_free_slot == self.compute_pipelines.len() {
             self.compute_pipelines.push(new_reloadable_pipeline);
@@ -197,18 +447,20 @@ impl PipelineManager {
         shader_dir: &ShaderDirectory,
         desc: RenderPipelineCreationDesc,
     ) -> RenderPipelineHandle {
-        let pipeline_and_sources = desc.try_create_pipeline(device, shader_dir).unwrap();
+        let push_constant_fallback_set = self.push_constant_fallback_set(&desc.layout);
+        let pipeline_and_sources = desc.try_create_pipeline(device, shader_dir, push_constant_fallback_set).unwrap();
 
         let mut first_free_slot = 0;
         while first_free_slot < self.render_pipelines.len() && self.render_pipelines[first_free_slot].handle.strong_count() > 0 {
             first_free_slot += 1;
         }
 
-        let handle = Rc::new(first_free_slot);
+        let handle = Arc::new(first_free_slot);
         let new_reloadable_pipeline = ReloadableRenderPipeline {
             desc,
             pipeline_and_sources,
-            handle: Rc::downgrade(&handle),
+            handle: Arc::downgrade(&handle),
+            push_constant_fallback_set,
         };
         if first_free_slot == self.render_pipelines.len() {
             self.render_pipelines.push(new_reloadable_pipeline);
@@ -231,7 +483,11 @@ impl PipelineManager {
                     false
                 }
             }) {
-                if let Ok(pipeline_and_sources) = reloadable_pipeline.desc.try_create_pipeline(device, shader_dir) {
+                if let Ok(pipeline_and_sources) =
+                    reloadable_pipeline
+                        .desc
+                        .try_create_pipeline(device, shader_dir, reloadable_pipeline.push_constant_fallback_set)
+                {
                     reloadable_pipeline.pipeline_and_sources = pipeline_and_sources;
                 }
             }
@@ -245,24 +501,118 @@ impl PipelineManager {
                     false
                 }
             }) {
-                if let Ok(pipeline_and_sources) = reloadable_pipeline.desc.try_create_pipeline(device, shader_dir) {
+                if let Ok(pipeline_and_sources) =
+                    reloadable_pipeline
+                        .desc
+                        .try_create_pipeline(device, shader_dir, reloadable_pipeline.push_constant_fallback_set)
+                {
                     reloadable_pipeline.pipeline_and_sources = pipeline_and_sources;
                 }
             }
         }
     }
 
+    // Recompiles every pipeline, e.g. after the shader cache was cleared.
+    pub fn reload_all(&mut self, device: &wgpu::Device, shader_dir: &ShaderDirectory) {
+        for reloadable_pipeline in self.compute_pipelines.iter_mut().filter(|p| p.handle.strong_count() > 0) {
+            if let Ok(pipeline_and_sources) =
+                reloadable_pipeline
+                    .desc
+                    .try_create_pipeline(device, shader_dir, reloadable_pipeline.push_constant_fallback_set)
+            {
+                reloadable_pipeline.pipeline_and_sources = pipeline_and_sources;
+            }
+        }
+        for reloadable_pipeline in self.render_pipelines.iter_mut().filter(|p| p.handle.strong_count() > 0) {
+            if let Ok(pipeline_and_sources) =
+                reloadable_pipeline
+                    .desc
+                    .try_create_pipeline(device, shader_dir, reloadable_pipeline.push_constant_fallback_set)
+            {
+                reloadable_pipeline.pipeline_and_sources = pipeline_and_sources;
+            }
+        }
+    }
+
     pub fn get_compute(&self, handle: &ComputePipelineHandle) -> &wgpu::ComputePipeline {
         let i: usize = **handle;
-        assert!(self.compute_pipelines[i].handle.ptr_eq(&Rc::downgrade(handle)));
+        assert!(self.compute_pipelines[i].handle.ptr_eq(&Arc::downgrade(handle)));
         &self.compute_pipelines[i].pipeline_and_sources.pipeline
     }
 
     pub fn get_render(&self, handle: &RenderPipelineHandle) -> &wgpu::RenderPipeline {
         let i: usize = **handle;
-        assert!(self.render_pipelines[i].handle.ptr_eq(&Rc::downgrade(handle)));
+        assert!(self.render_pipelines[i].handle.ptr_eq(&Arc::downgrade(handle)));
         &self.render_pipelines[i].pipeline_and_sources.pipeline
     }
+
+    // Replacement for wgpu::ComputePass::set_push_constants that works with emulated push constants as well.
+    // The pipeline is needed to know where the emulated push constants are bound.
+    pub fn set_push_constants_compute<'a>(&'a self, cpass: &mut wgpu::ComputePass<'a>, pipeline: &ComputePipelineHandle, offset: u32, data: &[u8]) {
+        match &self.push_constant_fallback {
+            None => cpass.set_push_constants(offset, data),
+            Some(fallback) => match self.compute_pipelines[**pipeline].push_constant_fallback_set {
+                Some(set) => {
+                    let (bind_group, dynamic_offset) = fallback.push(offset, data);
+                    cpass.set_bind_group(set, bind_group, &[dynamic_offset]);
+                }
+                None => error!(
+                    "Compute pipeline \"{}\" has no push constants",
+                    self.compute_pipelines[**pipeline].desc.label
+                ),
+            },
+        }
+    }
+
+    // Replacement for wgpu::RenderPass::set_push_constants that works with emulated push constants as well.
+    pub fn set_push_constants_render<'a>(
+        &'a self,
+        rpass: &mut wgpu::RenderPass<'a>,
+        pipeline: &RenderPipelineHandle,
+        stages: wgpu::ShaderStage,
+        offset: u32,
+        data: &[u8],
+    ) {
+        match &self.push_constant_fallback {
+            None => rpass.set_push_constants(stages, offset, data),
+            Some(fallback) => match self.render_pipelines[**pipeline].push_constant_fallback_set {
+                Some(set) => {
+                    let (bind_group, dynamic_offset) = fallback.push(offset, data);
+                    rpass.set_bind_group(set, bind_group, &[dynamic_offset]);
+                }
+                None => error!(
+                    "Render pipeline \"{}\" has no push constants",
+                    self.render_pipelines[**pipeline].desc.label
+                ),
+            },
+        }
+    }
+
+    // Emulated push constants are a bind group that needs to be bound before dispatching a pipeline that has push constants in its layout.
+    // Passes that dispatch such a pipeline before setting any push constants need to call this first, it does nothing with native push constants.
+    pub fn bind_push_constants_compute<'a>(&'a self, cpass: &mut wgpu::ComputePass<'a>, pipeline: &ComputePipelineHandle) {
+        if let (Some(fallback), Some(set)) = (
+            &self.push_constant_fallback,
+            self.compute_pipelines[**pipeline].push_constant_fallback_set,
+        ) {
+            let (bind_group, dynamic_offset) = fallback.current_slot();
+            cpass.set_bind_group(set, bind_group, &[dynamic_offset]);
+        }
+    }
+
+    pub fn bind_push_constants_render<'a>(&'a self, rpass: &mut wgpu::RenderPass<'a>, pipeline: &RenderPipelineHandle) {
+        if let (Some(fallback), Some(set)) = (&self.push_constant_fallback, self.render_pipelines[**pipeline].push_constant_fallback_set) {
+            let (bind_group, dynamic_offset) = fallback.current_slot();
+            rpass.set_bind_group(set, bind_group, &[dynamic_offset]);
+        }
+    }
+
+    // Uploads emulated push constants, needs to be called before submitting any commands that set push constants.
+    pub fn flush_push_constants(&self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        if let Some(fallback) = &self.push_constant_fallback {
+            fallback.flush(device, queue);
+        }
+    }
 }
 
 pub mod depth_state {
@@ -0,0 +1,41 @@
+// How many profiler scopes are recorded.
+// Every scope adds debug markers (and timestamp queries if enabled), which adds up for deeply nested scopes like per solver iteration ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, EnumIter)]
+pub enum ProfilingDetail {
+    Off,
+    // Only top level scopes of each pass.
+    Coarse,
+    // Everything, down to single solver iterations.
+    Fine,
+}
+
+impl std::str::FromStr for ProfilingDetail {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "off" => Ok(ProfilingDetail::Off),
+            "coarse" => Ok(ProfilingDetail::Coarse),
+            "fine" => Ok(ProfilingDetail::Fine),
+            _ => Err(()),
+        }
+    }
+}
+
+// Same as wgpu_profiler!, but the scope is only opened if the given profiling detail is at least the required one, e.g.
+// profiling_scope!(Fine, profiling_detail, "label", profiler, encoder, device, { ... })
+// The code is executed either way.
+#[macro_export]
+macro_rules! profiling_scope {
+    ($required:ident, $detail:expr, $label:expr, $profiler:expr, $encoder_or_pass:expr, $device:expr, $code:expr) => {{
+        let scope_enabled = $detail >= $crate::wgpu_utils::profiling::ProfilingDetail::$required;
+        if scope_enabled {
+            $profiler.begin_scope($label, $encoder_or_pass, $device);
+        }
+        let ret = $code;
+        if scope_enabled {
+            $profiler.end_scope($encoder_or_pass);
+        }
+        ret
+    }};
+}
@@ -1,7 +1,11 @@
 use notify::Watcher;
 use std::{borrow::Cow::Borrowed, cell::RefCell};
 use std::{ffi::OsStr, hash::Hash};
-use std::{hash::Hasher, sync::Arc};
+use std::{
+    hash::Hasher,
+    sync::atomic::{AtomicUsize, Ordering},
+    sync::Arc,
+};
 use std::{
     path::{Path, PathBuf},
     sync::Mutex,
@@ -11,12 +15,64 @@ use std::{
 // (could make customizable, but forcing this has perks as well)
 pub const SHADER_ENTRY_POINT_NAME: &str = "main";
 
+const NUM_CACHE_WARMING_THREADS: usize = 8;
+
+// Bump whenever compile options or the cache layout change in a way that makes existing cache entries invalid.
+const SHADER_CACHE_VERSION: u32 = 2;
+const CONFIGURATION_NAME: &str = if cfg!(debug_assertions) { "debug" } else { "release" };
+// Cache entries that haven't been (re)written in this time are removed at startup.
+const SHADER_CACHE_MAX_ENTRY_AGE: std::time::Duration = std::time::Duration::from_secs(60 * 60 * 24 * 14);
+
 pub struct ShaderDirectory {
     #[allow(dead_code)]
     watcher: notify::RecommendedWatcher,
     changed_files: Arc<Mutex<Vec<PathBuf>>>,
     directory: PathBuf,
+    cache_root_dir: PathBuf,
     cache_dir: PathBuf,
+    defines: Vec<(String, String)>, // passed to all shaders
+    // Shaders whose last compile failed, with the errors. Entries are removed once the shader compiles again.
+    compile_errors: Mutex<Vec<(PathBuf, Vec<ShaderCompileError>)>>,
+}
+
+// Error of the last compile attempt of a shader, one per message in the compiler output.
+#[derive(Clone)]
+pub struct ShaderCompileError {
+    // File the error is in, may be an include of the compiled shader.
+    pub file: String,
+    pub line: Option<u32>,
+    pub message: String,
+}
+
+impl ShaderCompileError {
+    fn without_location(path: &Path, message: String) -> Vec<Self> {
+        vec![ShaderCompileError {
+            file: path.to_string_lossy().into_owned(),
+            line: None,
+            message,
+        }]
+    }
+
+    // shaderc reports errors as "file:line: error: message", one per line, followed by a summary line.
+    fn parse_shaderc_output(path: &Path, output: &str) -> Vec<Self> {
+        lazy_static::lazy_static! {
+            static ref ERROR_LINE: regex::Regex = regex::Regex::new(r"^(.+?):(\d+): (?:fatal )?error: (.*)$").unwrap();
+        }
+        let errors: Vec<Self> = output
+            .lines()
+            .filter_map(|line| ERROR_LINE.captures(line.trim()))
+            .map(|captures| ShaderCompileError {
+                file: captures[1].to_owned(),
+                line: captures[2].parse().ok(),
+                message: captures[3].to_owned(),
+            })
+            .collect();
+        if errors.is_empty() {
+            Self::without_location(path, output.trim().to_owned())
+        } else {
+            errors
+        }
+    }
 }
 
 pub struct ShaderModuleWithSourceFiles {
@@ -53,15 +109,82 @@ impl ShaderDirectory {
         .unwrap();
         watcher.watch(path, notify::RecursiveMode::Recursive).unwrap();
 
-        let cache_dir = PathBuf::from(cache_dir).join(if cfg!(debug_assertions) { "debug" } else { "release" });
+        // Entries of different configurations or compiler versions go to different directories, so they can't ever be mixed up.
+        // The spirv version is only the target version, the compiler itself can change without it (SHADERC_VERSION is set by build.rs).
+        let (spirv_version_major, spirv_version_minor) = shaderc::get_spirv_version();
+        let cache_root_dir = PathBuf::from(cache_dir);
+        let cache_dir = cache_root_dir.join(format!(
+            "{}-v{}-shaderc{}-spirv{}.{}",
+            CONFIGURATION_NAME,
+            SHADER_CACHE_VERSION,
+            env!("SHADERC_VERSION"),
+            spirv_version_major,
+            spirv_version_minor
+        ));
         let _ = std::fs::create_dir_all(&cache_dir);
 
-        ShaderDirectory {
+        let shader_dir = ShaderDirectory {
             watcher,
             changed_files,
             directory: PathBuf::from(path),
+            cache_root_dir,
             cache_dir,
+            // Native push constants unless the pipeline overrides it, see PipelineManager::create_pipeline_layout.
+            defines: vec![(super::pipelines::PUSH_CONSTANT_LAYOUT_DEFINE.to_owned(), "push_constant".to_owned())],
+            compile_errors: Mutex::new(Vec::new()),
+        };
+        shader_dir.evict_stale_cache_entries();
+        shader_dir
+    }
+
+    // Removes cache directories of other versions and cache entries that are too old.
+    // Editing shaders produces a lot of entries that are never used again, this keeps the cache from growing indefinitely.
+    fn evict_stale_cache_entries(&self) {
+        // Caches of the other build configuration are left alone, switching between debug and release is common.
+        let is_other_version_dir = |path: &Path| {
+            path.is_dir()
+                && path != self.cache_dir
+                && path
+                    .file_name()
+                    .and_then(OsStr::to_str)
+                    .map_or(false, |name| name.starts_with(CONFIGURATION_NAME))
+        };
+        if let Ok(entries) = std::fs::read_dir(&self.cache_root_dir) {
+            for path in entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()) {
+                if is_other_version_dir(&path) {
+                    info!("Removing outdated shader cache {:?}", path);
+                    if let Err(err) = std::fs::remove_dir_all(&path) {
+                        warn!("Failed to remove outdated shader cache {:?}: {}", path, err);
+                    }
+                }
+            }
+        }
+
+        let mut num_evicted = 0;
+        if let Ok(entries) = std::fs::read_dir(&self.cache_dir) {
+            for entry in entries.filter_map(|entry| entry.ok()) {
+                let age = entry
+                    .metadata()
+                    .and_then(|metadata| metadata.modified())
+                    .ok()
+                    .and_then(|modified| modified.elapsed().ok());
+                if age.map_or(false, |age| age > SHADER_CACHE_MAX_ENTRY_AGE) && std::fs::remove_file(entry.path()).is_ok() {
+                    num_evicted += 1;
+                }
+            }
+        }
+        if num_evicted > 0 {
+            info!("Evicted {} old shader cache files", num_evicted);
+        }
+    }
+
+    // Throws away all cached shaders of the current configuration.
+    pub fn clear_cache(&self) {
+        info!("Clearing shader cache {:?}", self.cache_dir);
+        if let Err(err) = std::fs::remove_dir_all(&self.cache_dir) {
+            error!("Failed to clear shader cache {:?}: {}", self.cache_dir, err);
         }
+        let _ = std::fs::create_dir_all(&self.cache_dir);
     }
 
     // Checks if any change was detected in the shader directory.
@@ -70,134 +193,329 @@ impl ShaderDirectory {
         self.changed_files.lock().unwrap().drain(..).collect()
     }
 
+    // Sets a macro definition for all shaders compiled from now on. Already created shader modules are not affected.
+    pub fn set_define(&mut self, name: &str, value: String) {
+        match self.defines.iter_mut().find(|(existing_name, _)| existing_name == name) {
+            Some((_, existing_value)) => *existing_value = value,
+            None => self.defines.push((name.to_owned(), value)),
+        }
+    }
+
+    pub fn define(&self, name: &str) -> Option<&str> {
+        self.defines
+            .iter()
+            .find(|(existing_name, _)| existing_name == name)
+            .map(|(_, value)| value.as_str())
+    }
+
     pub fn load_shader_module(&self, device: &wgpu::Device, relative_path: &Path) -> Result<ShaderModuleWithSourceFiles, ()> {
-        let path = self.directory.join(relative_path);
-        let source_files = RefCell::new(vec![path.canonicalize().unwrap()]);
+        self.load_shader_module_with_defines(device, relative_path, &[])
+    }
 
-        let glsl_code = match std::fs::read_to_string(&path) {
-            Ok(glsl_code) => glsl_code,
-            Err(err) => {
-                error!("Failed to read shader file \"{:?}\": {}", path, err);
-                return Err(());
+    // Like load_shader_module, but with additional macro definitions on top of the directory wide ones (replacing those of the same name).
+    pub fn load_shader_module_with_defines(
+        &self,
+        device: &wgpu::Device,
+        relative_path: &Path,
+        additional_defines: &[(&str, String)],
+    ) -> Result<ShaderModuleWithSourceFiles, ()> {
+        let path = self.directory.join(relative_path);
+        let defines: Vec<(String, String)> = self
+            .defines
+            .iter()
+            .filter(|(name, _)| !additional_defines.iter().any(|(additional_name, _)| additional_name == name))
+            .cloned()
+            .chain(additional_defines.iter().map(|(name, value)| (name.to_string(), value.clone())))
+            .collect();
+        let compile_result = compile_cached(&self.directory, &self.cache_dir, &path, &defines);
+        {
+            let mut compile_errors = self.compile_errors.lock().unwrap();
+            compile_errors.retain(|(failed_path, _)| *failed_path != path);
+            if let Err(errors) = &compile_result {
+                compile_errors.push((path.clone(), errors.clone()));
             }
-        };
+        }
+        let (spirv, source_files) = compile_result.map_err(|_| ())?;
+        Ok(ShaderModuleWithSourceFiles {
+            module: device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+                label: Some(path.file_name().unwrap().to_str().unwrap()),
+                source: wgpu::ShaderSource::SpirV(Borrowed(bytemuck::cast_slice(&spirv))),
+                flags: wgpu::ShaderFlags::empty(),
+            }),
+            source_files,
+        })
+    }
 
-        let kind = match path.extension().and_then(OsStr::to_str) {
-            Some("frag") => shaderc::ShaderKind::Fragment,
-            Some("vert") => shaderc::ShaderKind::Vertex,
-            Some("comp") => shaderc::ShaderKind::Compute,
-            _ => {
-                error!("Did not recognize file extension for shader file \"{:?}\"", path);
-                return Err(());
-            }
-        };
+    // Errors of all shaders that failed to compile the last time they were loaded.
+    pub fn compile_errors(&self) -> Vec<ShaderCompileError> {
+        self.compile_errors
+            .lock()
+            .unwrap()
+            .iter()
+            .flat_map(|(_, errors)| errors.iter().cloned())
+            .collect()
+    }
 
-        // Check for cache hit.
-        let mut hasher = std::collections::hash_map::DefaultHasher::new();
-        glsl_code.hash(&mut hasher);
+    // Compiles all shaders in the shader directory on a few background threads, so that the shader cache is hot by the time pipelines are created.
+    pub fn warm_cache(&self) -> ShaderCacheWarming {
+        let mut shader_files = Vec::new();
+        collect_shader_files(&self.directory, &mut shader_files);
+        let num_shaders = shader_files.len();
+        let shader_files = Arc::new(Mutex::new(shader_files));
+        let num_finished = Arc::new(AtomicUsize::new(0));
 
-        let cache_path = self.cache_dir.join(format!(
-            "{:X}.{}.cache",
-            hasher.finish(),
-            path.extension().and_then(OsStr::to_str).unwrap()
-        ));
-        let dependent_sources_cache_path = cache_path.with_extension("files.cache");
-        if let Ok(cached_shader) = std::fs::read(&cache_path) {
-            if let Ok(sources_string) = std::fs::read_to_string(&dependent_sources_cache_path) {
-                return Ok(ShaderModuleWithSourceFiles {
-                    module: device.create_shader_module(&wgpu::ShaderModuleDescriptor {
-                        label: Some(path.file_name().unwrap().to_str().unwrap()),
-                        source: wgpu::ShaderSource::SpirV(Borrowed(bytemuck::cast_slice(&cached_shader))),
-                        flags: wgpu::ShaderFlags::empty(),
-                    }),
-                    source_files: sources_string.lines().map(|line| PathBuf::from(line)).collect(),
-                });
-            }
+        let num_threads = NUM_CACHE_WARMING_THREADS.min(num_shaders.max(1));
+        let num_running_threads = Arc::new(AtomicUsize::new(num_threads));
+        let threads = (0..num_threads)
+            .map(|_| {
+                let directory = self.directory.clone();
+                let cache_dir = self.cache_dir.clone();
+                let defines = self.defines.clone();
+                let shader_files = shader_files.clone();
+                let num_finished = num_finished.clone();
+                let running_guard = RunningThreadGuard(num_running_threads.clone());
+                std::thread::spawn(move || {
+                    let _running_guard = running_guard;
+                    loop {
+                        let path = match shader_files.lock().unwrap().pop() {
+                            Some(path) => path,
+                            None => break,
+                        };
+                        // Errors are logged already, the shader will fail again once a pipeline needs it.
+                        let _ = compile_cached(&directory, &cache_dir, &path, &defines);
+                        num_finished.fetch_add(1, Ordering::SeqCst);
+                    }
+                })
+            })
+            .collect();
+
+        info!("Warming shader cache for {} shaders on {} threads", num_shaders, num_threads);
+        ShaderCacheWarming {
+            threads,
+            num_shaders,
+            num_finished,
+            num_running_threads,
+            start_time: std::time::Instant::now(),
         }
+    }
+}
 
-        let compilation_artifact = {
-            let mut compiler = shaderc::Compiler::new().unwrap();
-            let mut options = shaderc::CompileOptions::new().unwrap();
-            //options.set_hlsl_io_mapping(true);
-            options.set_warnings_as_errors();
-            options.set_target_env(shaderc::TargetEnv::Vulkan, 0);
-            //if cfg!(debug_assertions) {
-            //    options.set_optimization_level(shaderc::OptimizationLevel::Performance);
-            //} else {
-            options.set_optimization_level(shaderc::OptimizationLevel::Performance);
-            //}
-            // Helps a lot when inspecting in ShaderDoc (will show all original source files before processing) but doesn't seem to hurt performance at all :)
-            options.set_generate_debug_info();
-
-            options.add_macro_definition("FRAGMENT_SHADER", Some(if kind == shaderc::ShaderKind::Fragment { "1" } else { "0" }));
-            options.add_macro_definition("VERTEX_SHADER", Some(if kind == shaderc::ShaderKind::Vertex { "1" } else { "0" }));
-            options.add_macro_definition("COMPUTE_SHADER", Some(if kind == shaderc::ShaderKind::Compute { "1" } else { "0" }));
-
-            if cfg!(debug_assertions) {
-                options.add_macro_definition("DEBUG", Some("1"));
-            } else {
-                options.add_macro_definition("NDEBUG", Some("1"));
+// Counts a cache warming thread as done once it exits, also if it panicked.
+struct RunningThreadGuard(Arc<AtomicUsize>);
+
+impl Drop for RunningThreadGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+// Background compilation of all shaders, see ShaderDirectory::warm_cache
+pub struct ShaderCacheWarming {
+    threads: Vec<std::thread::JoinHandle<()>>,
+    num_shaders: usize,
+    num_finished: Arc<AtomicUsize>,
+    num_running_threads: Arc<AtomicUsize>,
+    start_time: std::time::Instant,
+}
+
+impl ShaderCacheWarming {
+    pub fn num_shaders(&self) -> usize {
+        self.num_shaders
+    }
+
+    pub fn num_finished(&self) -> usize {
+        self.num_finished.load(Ordering::SeqCst)
+    }
+
+    pub fn progress(&self) -> f32 {
+        if self.num_shaders == 0 {
+            1.0
+        } else {
+            self.num_finished() as f32 / self.num_shaders as f32
+        }
+    }
+
+    // All threads are done, either because all shaders were compiled or because they panicked (see join).
+    pub fn is_finished(&self) -> bool {
+        self.num_running_threads.load(Ordering::SeqCst) == 0
+    }
+
+    pub fn join(self) {
+        let num_panicked = self.threads.into_iter().filter(|thread| thread.join().is_err()).count();
+        if num_panicked > 0 {
+            error!(
+                "{} shader cache warming thread(s) panicked, {} of {} shaders were warmed",
+                num_panicked,
+                self.num_finished.load(Ordering::SeqCst),
+                self.num_shaders
+            );
+        }
+        info!("Warming shader cache took {:?}", self.start_time.elapsed());
+    }
+}
+
+fn collect_shader_files(directory: &Path, shader_files: &mut Vec<PathBuf>) {
+    let entries = match std::fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(err) => {
+            error!("Failed to list shader directory {:?}: {}", directory, err);
+            return;
+        }
+    };
+    for path in entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()) {
+        if path.is_dir() {
+            collect_shader_files(&path, shader_files);
+        } else if let Some("frag") | Some("vert") | Some("comp") = path.extension().and_then(OsStr::to_str) {
+            shader_files.push(path);
+        }
+    }
+}
+
+fn hash_source_files(source_files: &[PathBuf]) -> std::io::Result<u64> {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    for path in source_files {
+        std::fs::read(path)?.hash(&mut hasher);
+    }
+    Ok(hasher.finish())
+}
+
+// Compiles a shader to SPIR-V or grabs it from the cache.
+// Returns the SPIR-V binary and all source files that went into it.
+fn compile_cached(
+    directory: &Path,
+    cache_dir: &Path,
+    path: &Path,
+    defines: &[(String, String)],
+) -> Result<(Vec<u8>, Vec<PathBuf>), Vec<ShaderCompileError>> {
+    let source_files = RefCell::new(vec![path.canonicalize().unwrap()]);
+
+    let glsl_code = match std::fs::read_to_string(path) {
+        Ok(glsl_code) => glsl_code,
+        Err(err) => {
+            error!("Failed to read shader file \"{:?}\": {}", path, err);
+            return Err(ShaderCompileError::without_location(path, format!("Failed to read shader file: {}", err)));
+        }
+    };
+
+    let kind = match path.extension().and_then(OsStr::to_str) {
+        Some("frag") => shaderc::ShaderKind::Fragment,
+        Some("vert") => shaderc::ShaderKind::Vertex,
+        Some("comp") => shaderc::ShaderKind::Compute,
+        _ => {
+            error!("Did not recognize file extension for shader file \"{:?}\"", path);
+            return Err(ShaderCompileError::without_location(path, "Unknown shader file extension".to_owned()));
+        }
+    };
+
+    // Check for cache hit.
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    glsl_code.hash(&mut hasher);
+    defines.hash(&mut hasher);
+
+    let cache_path = cache_dir.join(format!(
+        "{:X}.{}.cache",
+        hasher.finish(),
+        path.extension().and_then(OsStr::to_str).unwrap()
+    ));
+    let dependent_sources_cache_path = cache_path.with_extension("files.cache");
+    // The key only covers the main source file, so check the includes that went into the cached shader as well.
+    // First line of the dependency file is the hash over all sources, followed by one path per line.
+    if let Ok(cached_shader) = std::fs::read(&cache_path) {
+        if let Ok(sources_string) = std::fs::read_to_string(&dependent_sources_cache_path) {
+            let mut lines = sources_string.lines();
+            let expected_sources_hash = lines.next().unwrap_or_default().to_owned();
+            let source_files: Vec<PathBuf> = lines.map(|line| PathBuf::from(line)).collect();
+            if hash_source_files(&source_files).map_or(false, |hash| format!("{:X}", hash) == expected_sources_hash) {
+                return Ok((cached_shader, source_files));
             }
+            info!("Includes of {:?} changed since it was cached, recompiling", path);
+        }
+    }
 
-            options.set_include_callback(|name, include_type, source_file, _depth| {
-                let path = if include_type == shaderc::IncludeType::Relative {
-                    Path::new(Path::new(source_file).parent().unwrap()).join(name)
-                } else {
-                    self.directory.join(name)
-                };
-                match std::fs::read_to_string(&path) {
-                    Ok(glsl_code) => {
-                        source_files.borrow_mut().push(path.canonicalize().unwrap());
-                        Ok(shaderc::ResolvedInclude {
-                            resolved_name: String::from(name),
-                            content: glsl_code,
-                        })
-                    }
-                    Err(err) => Err(format!(
-                        "Failed to resolve include to {} in {} (was looking for {:?}): {}",
-                        name, source_file, path, err
-                    )),
-                }
-            });
-            match compiler.compile_into_spirv(&glsl_code, kind, path.to_str().unwrap(), SHADER_ENTRY_POINT_NAME, Some(&options)) {
-                Ok(compile_result) => {
-                    if compile_result.get_num_warnings() > 0 {
-                        warn!("warnings when compiling {:?}:\n{}", path, compile_result.get_warning_messages());
-                    }
-                    compile_result
+    let compilation_artifact = {
+        let mut compiler = shaderc::Compiler::new().unwrap();
+        let mut options = shaderc::CompileOptions::new().unwrap();
+        //options.set_hlsl_io_mapping(true);
+        options.set_warnings_as_errors();
+        options.set_target_env(shaderc::TargetEnv::Vulkan, 0);
+        //if cfg!(debug_assertions) {
+        //    options.set_optimization_level(shaderc::OptimizationLevel::Performance);
+        //} else {
+        options.set_optimization_level(shaderc::OptimizationLevel::Performance);
+        //}
+        // Helps a lot when inspecting in ShaderDoc (will show all original source files before processing) but doesn't seem to hurt performance at all :)
+        options.set_generate_debug_info();
+
+        options.add_macro_definition("FRAGMENT_SHADER", Some(if kind == shaderc::ShaderKind::Fragment { "1" } else { "0" }));
+        options.add_macro_definition("VERTEX_SHADER", Some(if kind == shaderc::ShaderKind::Vertex { "1" } else { "0" }));
+        options.add_macro_definition("COMPUTE_SHADER", Some(if kind == shaderc::ShaderKind::Compute { "1" } else { "0" }));
+
+        for (name, value) in defines {
+            options.add_macro_definition(name, Some(value));
+        }
+
+        if cfg!(debug_assertions) {
+            options.add_macro_definition("DEBUG", Some("1"));
+        } else {
+            options.add_macro_definition("NDEBUG", Some("1"));
+        }
+
+        options.set_include_callback(|name, include_type, source_file, _depth| {
+            let path = if include_type == shaderc::IncludeType::Relative {
+                Path::new(Path::new(source_file).parent().unwrap()).join(name)
+            } else {
+                directory.join(name)
+            };
+            match std::fs::read_to_string(&path) {
+                Ok(glsl_code) => {
+                    source_files.borrow_mut().push(path.canonicalize().unwrap());
+                    Ok(shaderc::ResolvedInclude {
+                        resolved_name: String::from(name),
+                        content: glsl_code,
+                    })
                 }
-                Err(compile_error) => {
-                    error!("failed to compile shader {:?}: {}", path, compile_error);
-                    return Err(());
+                Err(err) => Err(format!(
+                    "Failed to resolve include to {} in {} (was looking for {:?}): {}",
+                    name, source_file, path, err
+                )),
+            }
+        });
+        match compiler.compile_into_spirv(&glsl_code, kind, path.to_str().unwrap(), SHADER_ENTRY_POINT_NAME, Some(&options)) {
+            Ok(compile_result) => {
+                if compile_result.get_num_warnings() > 0 {
+                    warn!("warnings when compiling {:?}:\n{}", path, compile_result.get_warning_messages());
                 }
+                compile_result
             }
-        };
+            Err(compile_error) => {
+                error!("failed to compile shader {:?}: {}", path, compile_error);
+                return Err(ShaderCompileError::parse_shaderc_output(path, &compile_error.to_string()));
+            }
+        }
+    };
 
-        std::fs::write(&cache_path, compilation_artifact.as_binary_u8()).or_else(|e| {
-            error!("failed to shader cache file {:?}: {}", cache_path, e);
-            Err(())
-        })?;
-        std::fs::write(
-            &dependent_sources_cache_path,
-            source_files
-                .borrow()
-                .iter()
-                .map(|path| path.to_str().unwrap())
-                .collect::<Vec<&str>>()
-                .join("\n"),
-        )
-        .or_else(|e| {
-            error!("failed to shader cache dependency file {:?}: {}", dependent_sources_cache_path, e);
-            Err(())
-        })?;
+    std::fs::write(&cache_path, compilation_artifact.as_binary_u8()).or_else(|e| {
+        error!("failed to shader cache file {:?}: {}", cache_path, e);
+        Err(ShaderCompileError::without_location(path, format!("Failed to write shader cache: {}", e)))
+    })?;
+    let sources_hash = hash_source_files(&source_files.borrow()).or_else(|e| {
+        error!("failed to read shader sources of {:?} for hashing: {}", path, e);
+        Err(ShaderCompileError::without_location(
+            path,
+            format!("Failed to read shader sources for hashing: {}", e),
+        ))
+    })?;
+    std::fs::write(
+        &dependent_sources_cache_path,
+        std::iter::once(format!("{:X}", sources_hash))
+            .chain(source_files.borrow().iter().map(|path| path.to_str().unwrap().to_owned()))
+            .collect::<Vec<String>>()
+            .join("\n"),
+    )
+    .or_else(|e| {
+        error!("failed to shader cache dependency file {:?}: {}", dependent_sources_cache_path, e);
+        Err(ShaderCompileError::without_location(path, format!("Failed to write shader cache: {}", e)))
+    })?;
 
-        Ok(ShaderModuleWithSourceFiles {
-            module: device.create_shader_module(&wgpu::ShaderModuleDescriptor {
-                label: Some(path.file_name().unwrap().to_str().unwrap()),
-                source: wgpu::ShaderSource::SpirV(Borrowed(&compilation_artifact.as_binary())),
-                flags: wgpu::ShaderFlags::empty(),
-            }),
-            source_files: source_files.into_inner(),
-        })
-    }
+    Ok((compilation_artifact.as_binary_u8().to_vec(), source_files.into_inner()))
 }
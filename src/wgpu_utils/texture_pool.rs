@@ -0,0 +1,57 @@
+// Keeps textures that are no longer in use around so that they can be handed out again for the same descriptor.
+//
+// Mostly useful for resolution dependent render targets: switching back and forth between window sizes
+// (e.g. maximize/restore) doesn't need to allocate anything after the first time.
+pub struct TexturePool {
+    free_textures: Vec<(TextureKey, wgpu::Texture)>, // least recently released first
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub(super) struct TextureKey {
+    size: wgpu::Extent3d,
+    mip_level_count: u32,
+    sample_count: u32,
+    dimension: wgpu::TextureDimension,
+    format: wgpu::TextureFormat,
+    usage: wgpu::TextureUsage,
+}
+
+impl TextureKey {
+    pub(super) fn new(desc: &wgpu::TextureDescriptor) -> Self {
+        TextureKey {
+            size: desc.size,
+            mip_level_count: desc.mip_level_count,
+            sample_count: desc.sample_count,
+            dimension: desc.dimension,
+            format: desc.format,
+            usage: desc.usage,
+        }
+    }
+}
+
+impl TexturePool {
+    // Render targets are big, don't hold on to too many of them.
+    const MAX_NUM_FREE_TEXTURES: usize = 16;
+
+    pub fn new() -> Self {
+        TexturePool { free_textures: Vec::new() }
+    }
+
+    // Returns a free texture matching the descriptor or creates a new one.
+    // Note that a reused texture keeps the label it was created with.
+    pub fn acquire(&mut self, device: &wgpu::Device, desc: &wgpu::TextureDescriptor) -> wgpu::Texture {
+        let key = TextureKey::new(desc);
+        match self.free_textures.iter().rposition(|(free_key, _)| *free_key == key) {
+            Some(index) => self.free_textures.remove(index).1,
+            None => device.create_texture(desc),
+        }
+    }
+
+    // Hands back a texture that was created with the given descriptor. The caller must not use it anymore.
+    pub fn release(&mut self, desc: &wgpu::TextureDescriptor, texture: wgpu::Texture) {
+        if self.free_textures.len() == Self::MAX_NUM_FREE_TEXTURES {
+            self.free_textures.remove(0);
+        }
+        self.free_textures.push((TextureKey::new(desc), texture));
+    }
+}
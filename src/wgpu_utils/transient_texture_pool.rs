@@ -0,0 +1,107 @@
+use super::texture_pool::TextureKey;
+
+// Pool for render targets that are only needed for the duration of a few passes.
+//
+// Passes acquire their intermediate targets right before using them and release them once they're done.
+// Any later pass asking for a target with the same descriptor gets the same texture, so memory is shared across passes within a frame
+// instead of every pass holding on to its own set of full resolution textures.
+// Textures that haven't been used for a few frames are dropped, which also takes care of everything that's left over after a resize.
+pub struct TransientTexturePool {
+    free_textures: Vec<PooledTexture>, // least recently released first
+    num_acquired_textures: usize,
+    frame_index: u64,
+}
+
+struct PooledTexture {
+    key: TextureKey,
+    tag: &'static str,
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    last_used_frame: u64,
+}
+
+// A texture handed out by TransientTexturePool. Needs to be given back via TransientTexturePool::release.
+pub struct TransientTexture {
+    pooled: PooledTexture,
+}
+
+impl TransientTexture {
+    pub fn texture(&self) -> &wgpu::Texture {
+        &self.pooled.texture
+    }
+
+    // View of the entire texture.
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.pooled.view
+    }
+}
+
+impl TransientTexturePool {
+    // Textures that weren't acquired for this many frames are freed.
+    const MAX_NUM_UNUSED_FRAMES: u64 = 3;
+
+    pub fn new() -> Self {
+        TransientTexturePool {
+            free_textures: Vec::new(),
+            num_acquired_textures: 0,
+            frame_index: 0,
+        }
+    }
+
+    // Returns a free texture matching the descriptor or creates a new one.
+    // The tag is used as label for newly created textures. Free textures that were created with the same tag are preferred,
+    // so a graphics debugger shows meaningful names as long as there is no actual sharing going on.
+    // (The label of the descriptor is ignored)
+    pub fn acquire(&mut self, device: &wgpu::Device, tag: &'static str, desc: &wgpu::TextureDescriptor) -> TransientTexture {
+        let key = TextureKey::new(desc);
+        self.num_acquired_textures += 1;
+
+        let index = self
+            .free_textures
+            .iter()
+            .rposition(|free| free.key == key && free.tag == tag)
+            .or_else(|| self.free_textures.iter().rposition(|free| free.key == key));
+        if let Some(index) = index {
+            return TransientTexture {
+                pooled: self.free_textures.remove(index),
+            };
+        }
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor { label: Some(tag), ..*desc });
+        let view = texture.create_view(&Default::default());
+        TransientTexture {
+            pooled: PooledTexture {
+                key,
+                tag,
+                texture,
+                view,
+                last_used_frame: self.frame_index,
+            },
+        }
+    }
+
+    // Hands back a texture for reuse by later passes.
+    // Since passes are executed in recording order, it is fine to release a texture that was used by commands that are still pending.
+    pub fn release(&mut self, texture: TransientTexture) {
+        let mut pooled = texture.pooled;
+        pooled.last_used_frame = self.frame_index;
+        self.free_textures.push(pooled);
+        self.num_acquired_textures -= 1;
+    }
+
+    // Needs to be called once per frame, after all passes of the frame were recorded.
+    pub fn end_frame(&mut self) {
+        if self.num_acquired_textures > 0 {
+            warn!(
+                "{} transient textures were not released at the end of the frame",
+                self.num_acquired_textures
+            );
+            self.num_acquired_textures = 0;
+        }
+
+        let frame_index = self.frame_index;
+        self.free_textures
+            .retain(|free| frame_index - free.last_used_frame < Self::MAX_NUM_UNUSED_FRAMES);
+        self.frame_index += 1;
+    }
+}
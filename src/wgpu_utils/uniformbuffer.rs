@@ -7,16 +7,16 @@ pub struct UniformBuffer<Content> {
     previous_content: Vec<u8>,
 }
 
-impl<Content: bytemuck::Pod> UniformBuffer<Content> {
-    fn name() -> &'static str {
-        let type_name = std::any::type_name::<Content>();
-        let pos = type_name.rfind(':').unwrap();
-        &type_name[(pos + 1)..]
-    }
+fn content_name<Content>() -> &'static str {
+    let type_name = std::any::type_name::<Content>();
+    let pos = type_name.rfind(':').unwrap();
+    &type_name[(pos + 1)..]
+}
 
+impl<Content: bytemuck::Pod> UniformBuffer<Content> {
     pub fn new(device: &wgpu::Device) -> Self {
         let buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some(&format!("UniformBuffer: {}", Self::name())),
+            label: Some(&format!("UniformBuffer: {}", content_name::<Content>())),
             size: std::mem::size_of::<Content>() as u64,
             usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
             mapped_at_creation: false,
@@ -31,7 +31,7 @@ impl<Content: bytemuck::Pod> UniformBuffer<Content> {
 
     pub fn new_with_data(device: &wgpu::Device, initial_content: &Content) -> Self {
         let buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some(&format!("UniformBuffer: {}", Self::name())),
+            label: Some(&format!("UniformBuffer: {}", content_name::<Content>())),
             size: std::mem::size_of::<Content>() as u64,
             usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
             mapped_at_creation: true,
@@ -61,6 +61,105 @@ impl<Content: bytemuck::Pod> UniformBuffer<Content> {
     pub fn binding_resource(&self) -> wgpu::BindingResource {
         self.buffer.as_entire_binding()
     }
+
+    // For partial updates on the gpu timeline, e.g. copying values computed on the gpu.
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+}
+
+// Number of slots in a UniformBufferRing, i.e. how many differing contents can be in flight at once.
+pub const NUM_RING_SLOTS: usize = 3;
+
+// Uniform buffer with several slots that are written round robin.
+//
+// A changed content goes to the next slot instead of overwriting the one that earlier recorded commands refer to.
+// This way content can change between passes (or frames) without all of them seeing the last written value,
+// as long as there are no more than NUM_RING_SLOTS updates per submit.
+// Slots are either addressed with a dynamic offset (binding_resource & dynamic_offset) or with one binding per slot (slot_binding_resource).
+pub struct UniformBufferRing<Content> {
+    buffer: wgpu::Buffer,
+    content_type: PhantomData<Content>,
+    current_slot: usize,
+    previous_content: Vec<u8>,
+}
+
+impl<Content: bytemuck::Pod> UniformBufferRing<Content> {
+    // Dynamic offsets need to be aligned, so each slot is padded.
+    const SLOT_SIZE: u64 =
+        (std::mem::size_of::<Content>() as u64 + wgpu::BIND_BUFFER_ALIGNMENT - 1) / wgpu::BIND_BUFFER_ALIGNMENT * wgpu::BIND_BUFFER_ALIGNMENT;
+
+    pub fn new(device: &wgpu::Device) -> Self {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(&format!("UniformBufferRing: {}", content_name::<Content>())),
+            size: Self::SLOT_SIZE * NUM_RING_SLOTS as u64,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        UniformBufferRing {
+            buffer,
+            content_type: PhantomData,
+            current_slot: 0,
+            previous_content: Vec::new(),
+        }
+    }
+
+    // Writes the content to the next slot. Does nothing if the content didn't change.
+    pub fn update_content(&mut self, queue: &wgpu::Queue, content: Content) {
+        let new_content = bytemuck::bytes_of(&content);
+        if self.previous_content == new_content {
+            return;
+        }
+        self.next_slot();
+        self.write_current_slot(queue, 0, new_content);
+        self.previous_content = new_content.to_vec();
+    }
+
+    // Moves on to the next slot without writing anything.
+    // For users that keep track of changes themselves, the new slot still holds whatever was written NUM_RING_SLOTS updates ago.
+    pub fn next_slot(&mut self) {
+        self.current_slot = (self.current_slot + 1) % NUM_RING_SLOTS;
+        self.previous_content.clear();
+    }
+
+    // Writes data at the given byte offset within the content of the current slot.
+    pub fn write_current_slot(&self, queue: &wgpu::Queue, offset: wgpu::BufferAddress, data: &[u8]) {
+        assert!(offset + data.len() as u64 <= std::mem::size_of::<Content>() as u64);
+        queue.write_buffer(&self.buffer, self.current_buffer_offset() + offset, data);
+    }
+
+    pub fn current_slot(&self) -> usize {
+        self.current_slot
+    }
+
+    // Offset of the current slot within buffer(), for partial updates on the gpu timeline.
+    pub fn current_buffer_offset(&self) -> wgpu::BufferAddress {
+        self.current_slot as u64 * Self::SLOT_SIZE
+    }
+
+    // Dynamic offset to use when binding binding_resource.
+    pub fn dynamic_offset(&self) -> wgpu::DynamicOffset {
+        self.current_buffer_offset() as wgpu::DynamicOffset
+    }
+
+    // Binding of a single slot, requires a dynamic offset binding type (see binding_glsl::uniform_dynamic).
+    pub fn binding_resource(&self) -> wgpu::BindingResource {
+        self.slot_binding_resource(0)
+    }
+
+    // Binding of a fixed slot, for bind groups that are created once per slot instead of using dynamic offsets.
+    pub fn slot_binding_resource(&self, slot: usize) -> wgpu::BindingResource {
+        wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+            buffer: &self.buffer,
+            offset: slot as u64 * Self::SLOT_SIZE,
+            size: wgpu::BufferSize::new(std::mem::size_of::<Content>() as u64),
+        })
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
 }
 
 #[derive(Clone, Copy)]